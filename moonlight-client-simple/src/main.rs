@@ -2,13 +2,14 @@ use std::time::Duration;
 
 use moonlight_common::{
     PairPin, PairStatus,
-    network::backend::reqwest::ReqwestMoonlightHost,
+    network::{backend::reqwest::ReqwestMoonlightHost, launch::AudioRouting},
     pair::{ClientAuth, generate_new_client},
     stream::{
         MoonlightInstance,
         bindings::{ActiveGamepads, ColorRange, Colorspace, EncryptionFlags},
         debug::DebugHandler,
     },
+    units::{Bytes, Fps, Kbps},
 };
 
 use simplelog::{ColorChoice, LevelFilter, TermLogger, TerminalMode};
@@ -19,9 +20,7 @@ use tokio::{
     time::sleep,
 };
 
-use crate::gstreamer::gstreamer_pipeline;
-
-mod gstreamer;
+use moonlight_gstreamer::{GStreamerConfig, gstreamer_decoders};
 
 #[tokio::main]
 async fn main() {
@@ -139,9 +138,9 @@ async fn main() {
     println!("Connecting to the first app: {app:?}");
 
     // Creating gstreamer stuff
-    gstreamer::init();
+    moonlight_gstreamer::init().expect("failed to init gstreamer");
 
-    let (video_decoder, audio_decoder) = gstreamer_pipeline().unwrap();
+    let (video_decoder, audio_decoder) = gstreamer_decoders(&GStreamerConfig::default()).unwrap();
 
     // Start the stream (only 1 stream per program is allowed)
     let stream = host
@@ -150,16 +149,16 @@ async fn main() {
             app_id,
             1920,
             1080,
-            60,
-            false,
+            Fps(60),
             false,
             false,
+            AudioRouting::Client,
             ActiveGamepads::empty(),
             false,
             Colorspace::Rec2020,
             ColorRange::Full,
-            4000,
-            1024,
+            Kbps(4000),
+            Bytes(1024),
             EncryptionFlags::all(),
             DebugHandler,
             video_decoder,
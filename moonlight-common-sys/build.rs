@@ -5,6 +5,9 @@ fn main() {
     #[cfg(feature = "generate-bindings")]
     generate_bindings();
 
+    compile_shim();
+
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_NO_VENDOR");
     let allow_vendored = var("MOONLIGHT_COMMON_NO_VENDOR").is_err();
 
     #[allow(unused)]
@@ -37,6 +40,15 @@ fn generate_bindings_with_name(header_name: &str, rust_name: &str) {
         .expect("Couldn't write bindings!");
 }
 
+fn compile_shim() {
+    println!("cargo::rerun-if-changed=shim");
+
+    cc::Build::new()
+        .file("shim/log.c")
+        .include("shim")
+        .compile("moonlight-common-shim");
+}
+
 #[cfg(feature = "vendored")]
 fn compile_moonlight(allow_vendored: bool) -> Option<(String, PathBuf)> {
     if !allow_vendored {
@@ -102,6 +114,9 @@ fn compile_moonlight(allow_vendored: bool) -> Option<(String, PathBuf)> {
     // Force the library used by openssl
     config.define("OPENSSL_USE_STATIC_LIBS", "TRUE");
 
+    // -- Cross compilation
+    configure_cross_compilation(&mut config);
+
     // If we're in cargo cross
     if let Ok("1") = var("MOONLIGHT_COMMON_CROSS").as_deref() {
         let target_os = var("CARGO_CFG_TARGET_OS").unwrap();
@@ -119,12 +134,97 @@ fn compile_moonlight(allow_vendored: bool) -> Option<(String, PathBuf)> {
     Some((profile, config.build()))
 }
 
+#[cfg(feature = "vendored")]
+fn configure_cross_compilation(config: &mut cmake::Config) {
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_CMAKE_TOOLCHAIN_FILE");
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_CFLAGS");
+
+    // A toolchain file knows best how to compile for the target
+    if let Ok(toolchain_file) = var("MOONLIGHT_COMMON_CMAKE_TOOLCHAIN_FILE") {
+        config.define("CMAKE_TOOLCHAIN_FILE", toolchain_file);
+    } else if var("TARGET").unwrap() != var("HOST").unwrap() {
+        // The compilers are already selected by cmake-rs using the cc crate (e.g. CC_aarch64_unknown_linux_gnu),
+        // but CMake also needs to know that it's cross compiling
+        let target_os = var("CARGO_CFG_TARGET_OS").unwrap();
+        let target_arch = var("CARGO_CFG_TARGET_ARCH").unwrap();
+
+        let system_name = match target_os.as_str() {
+            "linux" => "Linux",
+            "windows" => "Windows",
+            "macos" => "Darwin",
+            "android" => "Android",
+            "freebsd" => "FreeBSD",
+            other => other,
+        };
+
+        config.define("CMAKE_SYSTEM_NAME", system_name);
+        config.define("CMAKE_SYSTEM_PROCESSOR", target_arch);
+    }
+
+    if let Ok(flags) = var("MOONLIGHT_COMMON_CFLAGS") {
+        config.cflag(&flags);
+        config.cxxflag(&flags);
+    }
+}
+
+/// How the moonlight library should be linked when it's provided by the user
+fn link_kind() -> &'static str {
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_LINK_KIND");
+
+    match var("MOONLIGHT_COMMON_LINK_KIND").as_deref() {
+        Ok("dylib") => "dylib",
+        Ok("static") | Err(_) => "static",
+        Ok(other) => {
+            panic!("MOONLIGHT_COMMON_LINK_KIND must be \"static\" or \"dylib\", got \"{other}\"")
+        }
+    }
+}
+
+#[cfg(feature = "pkg-config")]
+fn probe_pkg_config() -> bool {
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_NO_PKG_CONFIG");
+    if var("MOONLIGHT_COMMON_NO_PKG_CONFIG").is_ok() {
+        return false;
+    }
+
+    let statik = link_kind() == "static";
+
+    // pkg-config will emit all required link instructions
+    match pkg_config::Config::new()
+        .statik(statik)
+        .probe("moonlight-common-c")
+    {
+        Ok(_) => {
+            // Some distributions ship enet as a separate library
+            let _ = pkg_config::Config::new().statik(statik).probe("libenet");
+
+            true
+        }
+        Err(err) => {
+            println!("cargo::warning=failed to find moonlight-common-c using pkg-config: {err}");
+
+            false
+        }
+    }
+}
+
+#[cfg(not(feature = "pkg-config"))]
+fn probe_pkg_config() -> bool {
+    false
+}
+
 fn link(compile_info: Option<(String, PathBuf)>, allow_vendored: bool) {
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_COMMON_LIB");
     let lib_path = var("MOONLIGHT_COMMON_LIB").ok();
 
+    // A system installation
+    if lib_path.is_none() && compile_info.is_none() && probe_pkg_config() {
+        return;
+    }
+
     if lib_path.is_none() && compile_info.is_none() {
         panic!(
-            "Failed to compile moonlight sys because a library couldn't be found. You can try:\n1. Enable the vendored flags\n2. Provide a moonlight library with the MOONLIGHT_COMMON_LIB environment variable"
+            "Failed to compile moonlight sys because a library couldn't be found. You can try:\n1. Enable the vendored flags\n2. Provide a moonlight library with the MOONLIGHT_COMMON_LIB environment variable\n3. Enable the pkg-config feature and install moonlight-common-c on your system"
         );
     }
     if !allow_vendored && lib_path.is_none() {
@@ -148,7 +248,12 @@ fn link(compile_info: Option<(String, PathBuf)>, allow_vendored: bool) {
     } else if let Some(lib_path) = lib_path.as_ref() {
         println!("cargo:rustc-link-search=native={}/enet", lib_path);
     }
-    println!("cargo:rustc-link-lib=static=enet");
+    let lib_kind = if compile_info.is_some() && allow_vendored {
+        "static"
+    } else {
+        link_kind()
+    };
+    println!("cargo:rustc-link-lib={lib_kind}=enet");
 
     // Moonlight
     if let Some((profile, path)) = &compile_info
@@ -162,7 +267,7 @@ fn link(compile_info: Option<(String, PathBuf)>, allow_vendored: bool) {
     } else if let Some(lib_path) = lib_path.as_ref() {
         println!("cargo:rustc-link-search=native={}", lib_path);
     }
-    println!("cargo:rustc-link-lib=static=moonlight-common-c");
+    println!("cargo:rustc-link-lib={lib_kind}=moonlight-common-c");
 
     // Windows Debug: msvcrtd.lib
     let target_os = var("CARGO_CFG_TARGET_OS").unwrap();
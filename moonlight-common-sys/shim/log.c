@@ -0,0 +1,32 @@
+// Moonlight logs using a printf style variadic callback.
+// Variadic functions can't be defined in stable rust, so the message is formatted here
+// and then forwarded to a plain callback.
+
+#include <stdarg.h>
+#include <stdio.h>
+
+#include "log.h"
+
+#define LOG_BUFFER_SIZE 1024
+
+static MoonlightLogCallback log_callback = NULL;
+
+void MoonlightSetLogCallback(MoonlightLogCallback callback) {
+    log_callback = callback;
+}
+
+void MoonlightLogMessage(const char* format, ...) {
+    MoonlightLogCallback callback = log_callback;
+    if (callback == NULL) {
+        return;
+    }
+
+    char buffer[LOG_BUFFER_SIZE];
+
+    va_list args;
+    va_start(args, format);
+    vsnprintf(buffer, LOG_BUFFER_SIZE, format, args);
+    va_end(args);
+
+    callback(buffer);
+}
@@ -0,0 +1,9 @@
+#pragma once
+
+typedef void (*MoonlightLogCallback)(const char* message);
+
+// Sets the callback which receives the already formatted log messages
+void MoonlightSetLogCallback(MoonlightLogCallback callback);
+
+// Can be used as the logMessage callback of moonlight
+void MoonlightLogMessage(const char* format, ...);
@@ -15,3 +15,15 @@ mod crypto {
 }
 #[cfg(all(not(feature = "generate-bindings"), feature = "crypto"))]
 pub mod crypto;
+
+/// Formats the variadic log messages of moonlight in C, see `shim/log.h`
+pub mod shim {
+    use std::os::raw::c_char;
+
+    pub type MoonlightLogCallback = Option<unsafe extern "C" fn(message: *const c_char)>;
+
+    unsafe extern "C" {
+        pub fn MoonlightSetLogCallback(callback: MoonlightLogCallback);
+        pub fn MoonlightLogMessage(format: *const c_char, ...);
+    }
+}
@@ -0,0 +1,134 @@
+//! Video formats and color settings which are shared between the network api and the stream.
+//! These mirror the values of moonlight-common-c so that they're also available without the `stream` feature.
+
+use std::fmt::{self, Display, Formatter};
+
+use bitflags::bitflags;
+use num_derive::FromPrimitive;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct ServerCodeModeSupport: u32 {
+        const H264            = 0x00001;
+        const HEVC            = 0x00100;
+        const HEVC_MAIN10     = 0x00200;
+        const AV1_MAIN8       = 0x10000;
+        const AV1_MAIN10      = 0x20000;
+        const H264_HIGH8_444  = 0x40000;
+        const HEVC_REXT8_444  = 0x80000;
+        const HEVC_REXT10_444 = 0x100000;
+        const AV1_HIGH8_444   = 0x200000;
+        const AV1_HIGH10_444  = 0x400000;
+    }
+}
+
+#[repr(u32)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, FromPrimitive)]
+pub enum Colorspace {
+    Rec601 = 0,
+    Rec709 = 1,
+    Rec2020 = 2,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, FromPrimitive)]
+pub enum ColorRange {
+    Limited = 0,
+    Full = 1,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SupportedVideoFormats(u32);
+
+bitflags! {
+    impl SupportedVideoFormats: u32 {
+        const H264 = 0x0001;            // H.264 High Profile
+        const H264_HIGH8_444 = 0x0004;  // H.264 High 4:4:4 8-bit Profile
+        const H265 = 0x0100;            // HEVC Main Profile
+        const H265_MAIN10 = 0x0200;     // HEVC Main10 Profile
+        const H265_REXT8_444 = 0x0400;  // HEVC RExt 4:4:4 8-bit Profile
+        const H265_REXT10_444 = 0x0800; // HEVC RExt 4:4:4 10-bit Profile
+        const AV1_MAIN8 = 0x1000;       // AV1 Main 8-bit profile
+        const AV1_MAIN10 = 0x2000;      // AV1 Main 10-bit profile
+        const AV1_HIGH8_444 = 0x4000;   // AV1 High 4:4:4 8-bit profile
+        const AV1_HIGH10_444 = 0x8000;  // AV1 High 4:4:4 10-bit profile
+
+        // Preconfigured
+        const MASK_H264 = 0x000F;
+        const MASK_H265 = 0x0F00;
+        const MASK_AV1 = 0xF000;
+        const MASK_10BIT = 0xAA00;
+        const MASK_YUV444 = 0xCC04;
+    }
+}
+
+impl SupportedVideoFormats {
+    /// The formats the host can encode. H264 is always included because older hosts don't report it.
+    pub fn from_server_codec_mode_support(support: ServerCodeModeSupport) -> Self {
+        const MAPPING: &[(ServerCodeModeSupport, SupportedVideoFormats)] = &[
+            (ServerCodeModeSupport::H264, SupportedVideoFormats::H264),
+            (ServerCodeModeSupport::HEVC, SupportedVideoFormats::H265),
+            (
+                ServerCodeModeSupport::HEVC_MAIN10,
+                SupportedVideoFormats::H265_MAIN10,
+            ),
+            (
+                ServerCodeModeSupport::AV1_MAIN8,
+                SupportedVideoFormats::AV1_MAIN8,
+            ),
+            (
+                ServerCodeModeSupport::AV1_MAIN10,
+                SupportedVideoFormats::AV1_MAIN10,
+            ),
+            (
+                ServerCodeModeSupport::H264_HIGH8_444,
+                SupportedVideoFormats::H264_HIGH8_444,
+            ),
+            (
+                ServerCodeModeSupport::HEVC_REXT8_444,
+                SupportedVideoFormats::H265_REXT8_444,
+            ),
+            (
+                ServerCodeModeSupport::HEVC_REXT10_444,
+                SupportedVideoFormats::H265_REXT10_444,
+            ),
+            (
+                ServerCodeModeSupport::AV1_HIGH8_444,
+                SupportedVideoFormats::AV1_HIGH8_444,
+            ),
+            (
+                ServerCodeModeSupport::AV1_HIGH10_444,
+                SupportedVideoFormats::AV1_HIGH10_444,
+            ),
+        ];
+
+        let mut formats = SupportedVideoFormats::H264;
+        for (server, format) in MAPPING {
+            if support.contains(*server) {
+                formats |= *format;
+            }
+        }
+
+        formats
+    }
+}
+
+impl Display for SupportedVideoFormats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+
+        let mut first = true;
+        for (name, _) in self.iter_names() {
+            if !first {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", name)?;
+
+            first = false;
+        }
+        write!(f, "]")?;
+        Ok(())
+    }
+}
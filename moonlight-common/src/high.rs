@@ -13,13 +13,18 @@ use uuid::Uuid;
 
 use crate::{
     Error, MoonlightError, PairPin, PairStatus, ServerState, ServerVersion,
+    formats::ServerCodeModeSupport,
     mac::MacAddress,
     network::{
         ApiError, App, ClientAppBoxArtRequest, ClientInfo, DEFAULT_UNIQUE_ID, HostInfo,
-        ServerAppListResponse, host_app_box_art, host_app_list, host_cancel, host_info,
-        pair::host_unpair, request_client::RequestClient,
+        ServerAppListResponse, XmlParseMode, host_app_box_art, host_app_list, host_cancel,
+        host_info,
+        launch::{AudioRouting, ClientStreamRequest, DEFAULT_LAUNCH_QUERY_PARAMETERS, host_launch},
+        pair::host_unpair,
+        request_client::{CancellationToken, RequestClient, RequestClientOptions},
     },
     pair::{ClientAuth, PairError, PairSuccess, host_pair},
+    units::Fps,
 };
 
 pub async fn broadcast_magic_packet(mac: MacAddress) -> Result<(), io::Error> {
@@ -56,6 +61,8 @@ pub enum HostError<RequestError> {
     StreamConfig(#[from] StreamConfigError),
     #[error("the host is likely offline")]
     LikelyOffline,
+    #[error("another app is already running on the host")]
+    AppAlreadyRunning,
 }
 
 #[derive(Debug, Error)]
@@ -77,6 +84,9 @@ pub struct MoonlightHost<Client> {
     http_port: u16,
     tried_connect: bool,
     cache_info: Option<HostInfo>,
+    cancellation_token: CancellationToken,
+    request_options: RequestClientOptions,
+    xml_parse_mode: XmlParseMode,
     // Paired
     paired: Option<Paired>,
 }
@@ -97,15 +107,33 @@ where
         address: String,
         http_port: u16,
         unique_id: Option<String>,
+    ) -> Result<Self, HostError<C::Error>> {
+        Self::with_request_options(
+            address,
+            http_port,
+            unique_id,
+            RequestClientOptions::default(),
+        )
+    }
+
+    /// All clients of this host are created with these options, e.g. to connect through a proxy
+    pub fn with_request_options(
+        address: String,
+        http_port: u16,
+        unique_id: Option<String>,
+        request_options: RequestClientOptions,
     ) -> Result<Self, HostError<C::Error>> {
         Ok(Self {
-            client: C::with_defaults()
+            client: C::with_defaults(&request_options)
                 .map_err(|err| HostError::Api(ApiError::RequestClient(err)))?,
             client_unique_id: unique_id.unwrap_or_else(|| DEFAULT_UNIQUE_ID.to_string()),
             address,
             http_port,
             tried_connect: false,
             cache_info: None,
+            cancellation_token: CancellationToken::new(),
+            request_options,
+            xml_parse_mode: XmlParseMode::default(),
             paired: None,
         })
     }
@@ -121,6 +149,24 @@ where
         format!("{}:{}", self.address, self.http_port)
     }
 
+    /// All requests to the host fail with a cancelled error once this token is cancelled.
+    /// Requests which are in flight while cancelling it are aborted.
+    pub fn set_cancellation_token(&mut self, cancellation_token: CancellationToken) {
+        self.cancellation_token = cancellation_token;
+    }
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Lenient parsing accepts the responses of hosts which deviate from Sunshine, the cached responses are cleared
+    pub fn set_xml_parse_mode(&mut self, mode: XmlParseMode) {
+        self.xml_parse_mode = mode;
+        self.clear_cache();
+    }
+    pub fn xml_parse_mode(&self) -> XmlParseMode {
+        self.xml_parse_mode
+    }
+
     async fn host_info(&mut self) -> Result<&HostInfo, HostError<C::Error>> {
         let has_cache = self.cache_info.is_some();
         let mut https_port = None;
@@ -138,7 +184,15 @@ where
                 uuid: Uuid::new_v4(),
             };
 
-            let info = host_info(&mut self.client, false, &http_address, Some(client_info)).await?;
+            let info = host_info(
+                &mut self.client,
+                &self.cancellation_token,
+                false,
+                &http_address,
+                Some(client_info),
+                self.xml_parse_mode,
+            )
+            .await?;
 
             https_port = Some(info.https_port);
 
@@ -154,8 +208,17 @@ where
                 unique_id: &self.client_unique_id,
                 uuid: Uuid::new_v4(),
             };
-            self.cache_info =
-                Some(host_info(&mut self.client, true, &https_address, Some(client_info)).await?);
+            self.cache_info = Some(
+                host_info(
+                    &mut self.client,
+                    &self.cancellation_token,
+                    true,
+                    &https_address,
+                    Some(client_info),
+                    self.xml_parse_mode,
+                )
+                .await?,
+            );
         }
 
         let Some(info) = &self.cache_info else {
@@ -167,6 +230,9 @@ where
     pub fn clear_cache(&mut self) {
         self.tried_connect = false;
         self.cache_info = None;
+        self.clear_app_list_cache();
+    }
+    pub fn clear_app_list_cache(&mut self) {
         if let Some(paired) = self.paired.as_mut() {
             paired.cache_app_list = None;
         }
@@ -226,12 +292,13 @@ where
         let info = self.host_info().await?;
         Ok((info.state_string.as_str(), info.state))
     }
+    pub async fn host_type(&mut self) -> Result<HostType, HostError<C::Error>> {
+        let info = self.host_info().await?;
+        Ok(info.host_type)
+    }
     pub async fn is_nvidia_software(&mut self) -> Result<bool, HostError<C::Error>> {
-        let (state_str, _) = self.state().await?;
-        // Real Nvidia host software (GeForce Experience and RTX Experience) both use the 'Mjolnir'
-        // codename in the state field and no version of Sunshine does. We can use this to bypass
-        // some assumptions about Nvidia hardware that don't apply to Sunshine hosts.
-        Ok(state_str.contains("Mjolnir"))
+        // This bypasses some assumptions about Nvidia hardware that don't apply to Sunshine hosts.
+        Ok(matches!(self.host_type().await?, HostType::Gfe))
     }
 
     pub async fn max_luma_pixels_hevc(&mut self) -> Result<u32, HostError<C::Error>> {
@@ -243,12 +310,9 @@ where
         Ok(info.server_codec_mode_support)
     }
 
-    #[cfg(feature = "stream")]
     pub async fn server_codec_mode_support(
         &mut self,
-    ) -> Result<crate::stream::bindings::ServerCodeModeSupport, HostError<C::Error>> {
-        use crate::stream::bindings::ServerCodeModeSupport;
-
+    ) -> Result<ServerCodeModeSupport, HostError<C::Error>> {
         let bits = self.server_codec_mode_support_raw().await?;
         Ok(ServerCodeModeSupport::from_bits(bits).expect("valid server code mode support"))
     }
@@ -259,6 +323,7 @@ where
         server_certificate: &Pem,
     ) -> Result<(), HostError<C::Error>> {
         self.client = C::with_certificates(
+            &self.request_options,
             &client_auth.private_key,
             &client_auth.certificate,
             server_certificate,
@@ -286,7 +351,15 @@ where
             uuid: Uuid::new_v4(),
         };
 
-        let info = host_info(&mut self.client, true, &https_address, Some(client_info)).await?;
+        let info = host_info(
+            &mut self.client,
+            &self.cancellation_token,
+            true,
+            &https_address,
+            Some(client_info),
+            self.xml_parse_mode,
+        )
+        .await?;
 
         let pair_status = info.pair_status;
         self.cache_info = Some(info);
@@ -295,7 +368,7 @@ where
     }
 
     pub fn clear_pairing_info(&mut self) -> Result<(), HostError<C::Error>> {
-        self.client = C::with_defaults().map_err(ApiError::RequestClient)?;
+        self.client = C::with_defaults(&self.request_options).map_err(ApiError::RequestClient)?;
         self.paired = None;
 
         Ok(())
@@ -334,13 +407,16 @@ where
             uuid: Uuid::new_v4(),
         };
 
-        let mut client = C::with_defaults_long_timeout().map_err(ApiError::RequestClient)?;
+        let mut client = C::with_defaults_long_timeout(&self.request_options)
+            .map_err(ApiError::RequestClient)?;
 
         let PairSuccess {
             server_certificate,
             client: new_client,
         } = host_pair(
             &mut client,
+            &self.request_options,
+            &self.cancellation_token,
             &http_address,
             &https_address,
             client_info,
@@ -388,7 +464,13 @@ where
             uuid: Uuid::new_v4(),
         };
 
-        host_unpair(&mut self.client, &http_address, client_info).await?;
+        host_unpair(
+            &mut self.client,
+            &self.cancellation_token,
+            &http_address,
+            client_info,
+        )
+        .await?;
 
         self.clear_pairing_info()?;
 
@@ -410,7 +492,14 @@ where
 
         // Recache
         if paired.cache_app_list.is_none() {
-            let response = host_app_list(&mut self.client, &https_address, client_info).await?;
+            let response = host_app_list(
+                &mut self.client,
+                &self.cancellation_token,
+                &https_address,
+                client_info,
+                self.xml_parse_mode,
+            )
+            .await?;
 
             paired.cache_app_list = Some(response);
         }
@@ -437,6 +526,7 @@ where
 
         let response = host_app_box_art(
             &mut self.client,
+            &self.cancellation_token,
             &https_address,
             client_info,
             ClientAppBoxArtRequest { app_id },
@@ -446,6 +536,59 @@ where
         Ok(response)
     }
 
+    /// Launches the app without starting a stream, e.g. to let a game load before connecting to it.
+    /// Starting a stream later on will resume the app and [Self::cancel] will quit it.
+    ///
+    /// Returns false if the app was already running.
+    pub async fn launch_app_only(
+        &mut self,
+        app_id: u32,
+        width: u32,
+        height: u32,
+        fps: Fps,
+        hdr: bool,
+        sops: bool,
+        audio_routing: AudioRouting,
+    ) -> Result<bool, HostError<C::Error>> {
+        self.check_paired()?;
+
+        // Clearing cache so we see the currently running game
+        self.clear_cache();
+
+        let current_game = self.current_game().await?;
+        if current_game == app_id {
+            return Ok(false);
+        } else if current_game != 0 {
+            return Err(HostError::AppAlreadyRunning);
+        }
+
+        let https_address = self.https_address().await?;
+
+        let request =
+            ClientStreamRequest::launch_only(app_id, width, height, fps, hdr, sops, audio_routing)
+                .map_err(PairError::from)?;
+
+        let client_info = ClientInfo {
+            unique_id: &self.client_unique_id,
+            uuid: Uuid::new_v4(),
+        };
+
+        host_launch(
+            DEFAULT_LAUNCH_QUERY_PARAMETERS,
+            &mut self.client,
+            &self.cancellation_token,
+            &https_address,
+            client_info,
+            request,
+        )
+        .await?;
+
+        // Clear cache because now there's an active app
+        self.clear_cache();
+
+        Ok(true)
+    }
+
     pub async fn cancel(&mut self) -> Result<bool, HostError<C::Error>> {
         self.check_paired()?;
 
@@ -456,7 +599,13 @@ where
             uuid: Uuid::new_v4(),
         };
 
-        let response = host_cancel(&mut self.client, &https_hostport, client_info).await?;
+        let response = host_cancel(
+            &mut self.client,
+            &self.cancellation_token,
+            &https_hostport,
+            client_info,
+        )
+        .await?;
 
         self.clear_cache();
 
@@ -480,7 +629,7 @@ mod stream {
         high::{HostError, MoonlightHost, StreamConfigError},
         network::{
             ClientInfo,
-            launch::{ClientStreamRequest, host_launch, host_resume},
+            launch::{AudioRouting, ClientStreamRequest, host_launch, host_resume},
             request_client::RequestClient,
         },
         pair::PairError,
@@ -494,6 +643,7 @@ mod stream {
             connection::ConnectionListener,
             video::VideoDecoder,
         },
+        units::{Bytes, Fps, Kbps},
     };
 
     impl<C> MoonlightHost<C>
@@ -502,12 +652,8 @@ mod stream {
     {
         // Stream config correction
         pub async fn is_hdr_supported(&mut self) -> Result<bool, HostError<C::Error>> {
-            let server_codec_mode_support = self.server_codec_mode_support().await?;
-
-            Ok(
-                server_codec_mode_support.contains(ServerCodeModeSupport::HEVC_MAIN10)
-                    || server_codec_mode_support.contains(ServerCodeModeSupport::AV1_MAIN10),
-            )
+            let info = self.host_info().await?;
+            Ok(info.supports_hdr())
         }
         pub async fn is_4k_supported(&mut self) -> Result<bool, HostError<C::Error>> {
             let is_nvidia = self.is_nvidia_software().await?;
@@ -569,16 +715,16 @@ mod stream {
             app_id: u32,
             width: u32,
             height: u32,
-            mut fps: u32,
+            mut fps: Fps,
             hdr: bool,
             mut sops: bool,
-            local_audio_play_mode: bool,
+            audio_routing: AudioRouting,
             gamepads_attached: ActiveGamepads,
             gamepads_persist_after_disconnect: bool,
             color_space: Colorspace,
             color_range: ColorRange,
-            bitrate: u32,
-            packet_size: u32,
+            bitrate: Kbps,
+            packet_size: Bytes,
             encryption_flags: EncryptionFlags,
             connection_listener: impl ConnectionListener + Send + Sync + 'static,
             video_decoder: impl VideoDecoder + Send + 'static,
@@ -602,8 +748,8 @@ mod stream {
                 // so force it to 0 to ensure the correct resolution is set. We
                 // used to use 60 here but that locked the frame rate to 60 FPS
                 // on GFE 3.20.3. We don't need this hack for Sunshine.
-                if fps > 60 {
-                    fps = 0;
+                if fps > Fps(60) {
+                    fps = Fps(0);
                 }
 
                 if self
@@ -636,7 +782,7 @@ mod stream {
                 mode_fps: fps,
                 hdr,
                 sops,
-                local_audio_play_mode,
+                audio_routing,
                 gamepads_attached_mask: gamepads_attached.bits() as i32,
                 gamepads_persist_after_disconnect,
                 ri_key: aes_key,
@@ -650,8 +796,9 @@ mod stream {
 
             let rtsp_session_url = if current_game == 0 {
                 let launch_response = host_launch(
-                    instance,
+                    instance.launch_url_query_parameters(),
                     &mut self.client,
+                    &self.cancellation_token,
                     &https_address,
                     client_info,
                     request,
@@ -661,8 +808,9 @@ mod stream {
                 launch_response.rtsp_session_url
             } else {
                 let resume_response = host_resume(
-                    instance,
+                    instance.launch_url_query_parameters(),
                     &mut self.client,
+                    &self.cancellation_token,
                     &https_address,
                     client_info,
                     request,
@@ -689,13 +837,13 @@ mod stream {
                 let stream_config = StreamConfiguration {
                     width: width as i32,
                     height: height as i32,
-                    fps: fps as i32,
-                    bitrate: bitrate as i32,
-                    packet_size: packet_size as i32,
+                    fps,
+                    bitrate,
+                    packet_size,
                     streaming_remotely: StreamingConfig::Auto,
                     audio_configuration: audio_decoder.config().raw() as i32,
                     supported_video_formats: video_decoder.supported_formats(),
-                    client_refresh_rate_x100: (fps * 100) as i32,
+                    client_refresh_rate_x100: fps.x100() as i32,
                     color_space,
                     color_range,
                     encryption_flags,
@@ -720,3 +868,83 @@ mod stream {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub enum AppListChange {
+    Added(App),
+    Removed(App),
+    /// The title or the hdr support of an app with the same id changed
+    Changed {
+        old: App,
+        new: App,
+    },
+}
+
+impl AppListChange {
+    pub fn app_id(&self) -> u32 {
+        match self {
+            Self::Added(app) | Self::Removed(app) => app.id,
+            Self::Changed { new, .. } => new.id,
+        }
+    }
+}
+
+/// Compares app lists of a host, e.g. to notice games which were installed on the host.
+#[derive(Debug, Default)]
+pub struct AppListWatcher {
+    last_apps: Option<Vec<App>>,
+}
+
+impl AppListWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last list, None before the first update
+    pub fn apps(&self) -> Option<&[App]> {
+        self.last_apps.as_deref()
+    }
+
+    /// Returns the changes since the last list.
+    /// The first list has nothing to compare with and returns no changes.
+    pub fn update(&mut self, apps: &[App]) -> Vec<AppListChange> {
+        let Some(last_apps) = self.last_apps.replace(apps.to_vec()) else {
+            return Vec::new();
+        };
+
+        let mut changes = Vec::new();
+
+        for app in apps {
+            match last_apps.iter().find(|last_app| last_app.id == app.id) {
+                None => changes.push(AppListChange::Added(app.clone())),
+                Some(last_app) if last_app != app => changes.push(AppListChange::Changed {
+                    old: last_app.clone(),
+                    new: app.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for last_app in last_apps {
+            if !apps.iter().any(|app| app.id == last_app.id) {
+                changes.push(AppListChange::Removed(last_app));
+            }
+        }
+
+        changes
+    }
+
+    /// Requests the app list from the host again and returns the changes since the last poll.
+    pub async fn poll<C>(
+        &mut self,
+        host: &mut MoonlightHost<C>,
+    ) -> Result<Vec<AppListChange>, HostError<C::Error>>
+    where
+        C: RequestClient,
+    {
+        host.clear_app_list_cache();
+
+        let apps = host.app_list().await?;
+
+        Ok(self.update(apps))
+    }
+}
@@ -0,0 +1,222 @@
+//! Where the private keys of clients are kept.
+//!
+//! A [KeyStore] returns a pem which is saved in place of the private key.
+//! Stores which keep the key elsewhere return a reference pem tagged with [KEY_REFERENCE_TAG].
+//! Loading a plain private key always returns it unchanged, so keys which were saved before a store was used keep working.
+//!
+//! The network backends need the key itself for the tls handshake,
+//! so keys which can't leave a hardware token (PKCS#11, TPM) can't be used with a [KeyStore].
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use pem::{Pem, PemError};
+use thiserror::Error;
+
+pub const KEY_REFERENCE_TAG: &str = "MOONLIGHT KEY REFERENCE";
+
+#[derive(Debug, Error)]
+pub enum KeyStoreError {
+    #[error("the key was not found in the key store")]
+    NotFound,
+    #[error("the key id is invalid: {0}")]
+    InvalidId(String),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("pem error: {0}")]
+    Pem(#[from] PemError),
+    #[cfg(feature = "key_store_keyring")]
+    #[error("keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+}
+
+pub trait KeyStore: Send + Sync {
+    /// Stores the key and returns the pem which should be saved instead of it
+    fn store(&self, id: &str, private_key: &Pem) -> Result<Pem, KeyStoreError>;
+
+    /// Returns the private key of a pem returned by [KeyStore::store]
+    fn load(&self, stored: &Pem) -> Result<Pem, KeyStoreError>;
+
+    /// Removes the key, plain private keys aren't stored anywhere so nothing happens
+    fn remove(&self, stored: &Pem) -> Result<(), KeyStoreError>;
+}
+
+pub fn is_key_reference(stored: &Pem) -> bool {
+    stored.tag() == KEY_REFERENCE_TAG
+}
+
+fn key_reference(id: &str) -> Pem {
+    Pem::new(KEY_REFERENCE_TAG, id.as_bytes().to_vec())
+}
+
+/// None if the pem is a plain private key
+fn key_reference_id(stored: &Pem) -> Result<Option<&str>, KeyStoreError> {
+    if !is_key_reference(stored) {
+        return Ok(None);
+    }
+
+    let id = std::str::from_utf8(stored.contents())
+        .map_err(|_| KeyStoreError::InvalidId(hex::encode(stored.contents())))?;
+    validate_id(id)?;
+
+    Ok(Some(id))
+}
+
+/// Ids are used as file names, so they must not contain anything else than alphanumeric characters, "-" and "_"
+fn validate_id(id: &str) -> Result<(), KeyStoreError> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(KeyStoreError::InvalidId(id.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Saves the key in plain text wherever the returned pem is saved
+#[derive(Debug, Default)]
+pub struct PemKeyStore;
+
+impl KeyStore for PemKeyStore {
+    fn store(&self, _id: &str, private_key: &Pem) -> Result<Pem, KeyStoreError> {
+        Ok(private_key.clone())
+    }
+
+    fn load(&self, stored: &Pem) -> Result<Pem, KeyStoreError> {
+        if is_key_reference(stored) {
+            return Err(KeyStoreError::NotFound);
+        }
+
+        Ok(stored.clone())
+    }
+
+    fn remove(&self, _stored: &Pem) -> Result<(), KeyStoreError> {
+        Ok(())
+    }
+}
+
+/// Writes every key into its own file in a directory, on unix only the current user can read them
+#[derive(Debug)]
+pub struct FileKeyStore {
+    directory: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, KeyStoreError> {
+        let directory = directory.into();
+
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{id}.pem"))
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn store(&self, id: &str, private_key: &Pem) -> Result<Pem, KeyStoreError> {
+        validate_id(id)?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(self.path(id))?;
+        file.write_all(pem::encode(private_key).as_bytes())?;
+        file.sync_all()?;
+
+        Ok(key_reference(id))
+    }
+
+    fn load(&self, stored: &Pem) -> Result<Pem, KeyStoreError> {
+        let Some(id) = key_reference_id(stored)? else {
+            return Ok(stored.clone());
+        };
+
+        let text = match fs::read_to_string(self.path(id)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(KeyStoreError::NotFound);
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(pem::parse(text)?)
+    }
+
+    fn remove(&self, stored: &Pem) -> Result<(), KeyStoreError> {
+        let Some(id) = key_reference_id(stored)? else {
+            return Ok(());
+        };
+
+        match fs::remove_file(self.path(id)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Keeps the keys in the keyring of the os, e.g. the Secret Service on linux or the Credential Manager on windows
+#[cfg(feature = "key_store_keyring")]
+#[derive(Debug)]
+pub struct KeyringKeyStore {
+    service: String,
+}
+
+#[cfg(feature = "key_store_keyring")]
+impl KeyringKeyStore {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+}
+
+#[cfg(feature = "key_store_keyring")]
+impl KeyStore for KeyringKeyStore {
+    fn store(&self, id: &str, private_key: &Pem) -> Result<Pem, KeyStoreError> {
+        validate_id(id)?;
+
+        keyring::Entry::new(&self.service, id)?.set_password(&pem::encode(private_key))?;
+
+        Ok(key_reference(id))
+    }
+
+    fn load(&self, stored: &Pem) -> Result<Pem, KeyStoreError> {
+        let Some(id) = key_reference_id(stored)? else {
+            return Ok(stored.clone());
+        };
+
+        let text = match keyring::Entry::new(&self.service, id)?.get_password() {
+            Ok(text) => text,
+            Err(keyring::Error::NoEntry) => return Err(KeyStoreError::NotFound),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(pem::parse(text)?)
+    }
+
+    fn remove(&self, stored: &Pem) -> Result<(), KeyStoreError> {
+        let Some(id) = key_reference_id(stored)? else {
+            return Ok(());
+        };
+
+        match keyring::Entry::new(&self.service, id)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
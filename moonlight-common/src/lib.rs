@@ -1,6 +1,3 @@
-// Sadly moonlight log message requires variadic args
-#![feature(c_variadic)]
-
 use std::{
     ffi::NulError,
     fmt::{Debug, Display},
@@ -10,6 +7,8 @@ use std::{
 
 use thiserror::Error;
 
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum MoonlightError {
@@ -43,7 +42,12 @@ pub mod high;
 #[cfg(feature = "pair")]
 pub mod pair;
 
+#[cfg(feature = "network")]
+pub mod key_store;
+
+pub mod formats;
 pub mod mac;
+pub mod units;
 
 #[derive(Debug, Error, Clone)]
 #[error("failed to parse the state of the server")]
@@ -82,7 +86,8 @@ pub enum ParseServerVersionError {
     InvalidPattern,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// The version of the host, the fields are compared in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ServerVersion {
     pub major: i32,
     pub minor: i32,
@@ -90,6 +95,25 @@ pub struct ServerVersion {
     pub mini_patch: i32,
 }
 
+/// Features which depend on the version of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerFeature {
+    /// Pairing hashes with sha256 instead of sha1
+    Sha256Pairing,
+    /// The host can stream with a high dynamic range, if it also supports a 10 bit codec
+    Hdr,
+}
+
+impl ServerFeature {
+    pub fn min_version(&self) -> ServerVersion {
+        // GeForce Experience reports -1 as the mini patch
+        match self {
+            Self::Sha256Pairing => ServerVersion::new(7, 0, 0, -1),
+            Self::Hdr => ServerVersion::new(7, 0, 0, -1),
+        }
+    }
+}
+
 impl ServerVersion {
     pub fn new(major: i32, minor: i32, patch: i32, mini_patch: i32) -> ServerVersion {
         Self {
@@ -99,6 +123,16 @@ impl ServerVersion {
             mini_patch,
         }
     }
+
+    pub fn supports(&self, feature: ServerFeature) -> bool {
+        *self >= feature.min_version()
+    }
+    pub fn supports_sha256_pairing(&self) -> bool {
+        self.supports(ServerFeature::Sha256Pairing)
+    }
+    pub fn supports_hdr(&self) -> bool {
+        self.supports(ServerFeature::Hdr)
+    }
 }
 
 impl Display for ServerVersion {
@@ -111,11 +145,12 @@ impl Display for ServerVersion {
     }
 }
 
+/// Parses "MAJOR.MINOR.PATCH.MINI_PATCH", Sunshine sometimes leaves out the mini patch which is 0 then
 impl FromStr for ServerVersion {
     type Err = ParseServerVersionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.splitn(4, ".");
+        let mut split = s.trim().splitn(4, ".");
 
         let major = split
             .next()
@@ -129,10 +164,10 @@ impl FromStr for ServerVersion {
             .next()
             .ok_or(ParseServerVersionError::InvalidPattern)?
             .parse()?;
-        let mini_patch = split
-            .next()
-            .ok_or(ParseServerVersionError::InvalidPattern)?
-            .parse()?;
+        let mini_patch = match split.next() {
+            Some(mini_patch) => mini_patch.parse()?,
+            None => 0,
+        };
 
         Ok(Self {
             major,
@@ -143,43 +178,121 @@ impl FromStr for ServerVersion {
     }
 }
 
-/// A pin which contains four values in the range 0..10
+/// The lengths of pins which a host accepts, always at least one length between 1 and [PairPin::MAX_LENGTH]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinPolicy {
+    min_length: usize,
+    max_length: usize,
+}
+
+impl PinPolicy {
+    /// Returns None if no pin can have a length in the range
+    pub fn new(min_length: usize, max_length: usize) -> Option<Self> {
+        if min_length == 0 || min_length > max_length || max_length > PairPin::MAX_LENGTH {
+            return None;
+        }
+
+        Some(Self {
+            min_length,
+            max_length,
+        })
+    }
+
+    /// Every host accepts four digits
+    pub const CLASSIC: Self = Self {
+        min_length: 4,
+        max_length: 4,
+    };
+    /// Newer Sunshine versions also accept longer pins
+    pub const EXTENDED: Self = Self {
+        min_length: 4,
+        max_length: PairPin::MAX_LENGTH,
+    };
+
+    pub fn min_length(&self) -> usize {
+        self.min_length
+    }
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub fn allows(&self, length: usize) -> bool {
+        (self.min_length..=self.max_length).contains(&length)
+    }
+}
+
+impl Default for PinPolicy {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+/// A pin which contains 4 to 8 values in the range 0..10
 #[derive(Clone, Copy)]
 pub struct PairPin {
-    numbers: [u8; 4],
+    numbers: [u8; PairPin::MAX_LENGTH],
+    length: usize,
 }
 
 impl PairPin {
+    pub const MAX_LENGTH: usize = 8;
+
+    /// Generates a pin with four digits
     #[cfg(feature = "pair")]
     pub fn generate() -> Result<Self, openssl::error::ErrorStack> {
-        let rand_num = || {
-            let mut num = [0u8];
-            openssl::rand::rand_bytes(&mut num)?;
-            Ok(num[0] % 10)
-        };
+        Self::generate_with(PinPolicy::CLASSIC, 4)
+    }
 
-        Ok(
-            Self::from_array([rand_num()?, rand_num()?, rand_num()?, rand_num()?])
-                .expect("generated invalid pair pin"),
-        )
+    /// The length is clamped to the lengths of the policy
+    #[cfg(feature = "pair")]
+    pub fn generate_with(
+        policy: PinPolicy,
+        length: usize,
+    ) -> Result<Self, openssl::error::ErrorStack> {
+        // The policy guarantees that min_length <= max_length <= MAX_LENGTH
+        let length = length.clamp(policy.min_length, policy.max_length);
+
+        let mut random = [0u8; PairPin::MAX_LENGTH];
+        openssl::rand::rand_bytes(&mut random)?;
+
+        let mut numbers = [0u8; PairPin::MAX_LENGTH];
+        for (number, random) in numbers.iter_mut().zip(random).take(length) {
+            *number = random % 10;
+        }
+
+        Ok(Self { numbers, length })
     }
 
     pub fn from_array(numbers: [u8; 4]) -> Option<Self> {
-        let range = 0..10;
+        Self::from_digits(&numbers, PinPolicy::CLASSIC)
+    }
 
-        if range.contains(&numbers[0])
-            && range.contains(&numbers[1])
-            && range.contains(&numbers[2])
-            && range.contains(&numbers[3])
+    pub fn from_digits(digits: &[u8], policy: PinPolicy) -> Option<Self> {
+        if !policy.allows(digits.len())
+            || digits.len() > Self::MAX_LENGTH
+            || digits.iter().any(|digit| *digit >= 10)
         {
-            return Some(Self { numbers });
+            return None;
         }
 
-        None
+        let mut numbers = [0u8; PairPin::MAX_LENGTH];
+        numbers[..digits.len()].copy_from_slice(digits);
+
+        Some(Self {
+            numbers,
+            length: digits.len(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
     }
 
     pub fn n(&self, index: usize) -> Option<u8> {
-        self.numbers.get(index).copied()
+        self.digits().get(index).copied()
     }
     pub fn n1(&self) -> u8 {
         self.numbers[0]
@@ -194,14 +307,28 @@ impl PairPin {
         self.numbers[3]
     }
 
+    pub fn digits(&self) -> &[u8] {
+        &self.numbers[..self.length]
+    }
+
+    /// The first four digits, longer pins are cut off
+    #[deprecated(note = "pins can have up to 8 digits, use digits or try_array instead")]
     pub fn array(&self) -> [u8; 4] {
-        self.numbers
+        [self.n1(), self.n2(), self.n3(), self.n4()]
+    }
+    /// Returns None for pins which don't have four digits
+    pub fn try_array(&self) -> Option<[u8; 4]> {
+        self.digits().try_into().ok()
     }
 }
 
 impl Display for PairPin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}{}{}", self.n1(), self.n2(), self.n3(), self.n4())
+        for digit in self.digits() {
+            write!(f, "{digit}")?;
+        }
+
+        Ok(())
     }
 }
 impl Debug for PairPin {
@@ -235,9 +362,115 @@ impl HashAlgorithm {
 }
 
 pub fn hash_algorithm_for_server(server_version: ServerVersion) -> HashAlgorithm {
-    if server_version.major >= 7 {
+    if server_version.supports_sha256_pairing() {
         HashAlgorithm::Sha256
     } else {
         HashAlgorithm::Sha1
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{PairPin, ParseServerVersionError, PinPolicy, ServerVersion};
+
+    #[test]
+    fn test_parse_server_version() -> Result<(), ParseServerVersionError> {
+        assert_eq!(
+            "7.1.431.-1".parse::<ServerVersion>()?,
+            ServerVersion::new(7, 1, 431, -1)
+        );
+        assert_eq!(
+            " 7.1.431.0\n".parse::<ServerVersion>()?,
+            ServerVersion::new(7, 1, 431, 0)
+        );
+        // Sunshine leaves out the mini patch
+        assert_eq!(
+            "2025.628.4510".parse::<ServerVersion>()?,
+            ServerVersion::new(2025, 628, 4510, 0)
+        );
+
+        assert!(matches!(
+            "7.1".parse::<ServerVersion>(),
+            Err(ParseServerVersionError::InvalidPattern)
+        ));
+        assert!(matches!(
+            "7.1.a".parse::<ServerVersion>(),
+            Err(ParseServerVersionError::ParseIntError(_))
+        ));
+        assert!(matches!(
+            "7.1.431.0.1".parse::<ServerVersion>(),
+            Err(ParseServerVersionError::ParseIntError(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_version_order() {
+        let threshold = ServerVersion::new(7, 0, 0, -1);
+
+        assert!(ServerVersion::new(6, 9, 9, 9) < threshold);
+        assert!(ServerVersion::new(7, 0, 0, -2) < threshold);
+        assert!(threshold < ServerVersion::new(7, 0, 0, 0));
+        assert!(ServerVersion::new(7, 0, 0, 0) < ServerVersion::new(7, 0, 1, -1));
+        assert!(ServerVersion::new(7, 1, 431, -1) < ServerVersion::new(2025, 628, 4510, 0));
+
+        assert!(threshold.supports_sha256_pairing());
+        assert!(threshold.supports_hdr());
+        assert!(ServerVersion::new(2025, 628, 4510, 0).supports_sha256_pairing());
+        assert!(!ServerVersion::new(6, 9, 9, 9).supports_sha256_pairing());
+        assert!(!ServerVersion::new(7, 0, 0, -2).supports_hdr());
+    }
+
+    #[test]
+    fn test_pin_policy() {
+        assert_eq!(PinPolicy::new(4, 4), Some(PinPolicy::CLASSIC));
+        assert_eq!(PinPolicy::new(4, 8), Some(PinPolicy::EXTENDED));
+        assert_eq!(PinPolicy::new(0, 4), None);
+        assert_eq!(PinPolicy::new(6, 5), None);
+        assert_eq!(PinPolicy::new(4, 9), None);
+
+        assert!(PinPolicy::CLASSIC.allows(4));
+        assert!(!PinPolicy::CLASSIC.allows(3));
+        assert!(!PinPolicy::CLASSIC.allows(5));
+        assert!((4..=8).all(|length| PinPolicy::EXTENDED.allows(length)));
+        assert!(!PinPolicy::EXTENDED.allows(9));
+    }
+
+    #[test]
+    fn test_from_digits() {
+        let pin = PairPin::from_digits(&[1, 2, 3, 4, 5, 6], PinPolicy::EXTENDED);
+        assert_eq!(pin.map(|pin| pin.to_string()), Some("123456".to_string()));
+
+        assert!(PairPin::from_digits(&[1, 2, 3, 4, 5, 6], PinPolicy::CLASSIC).is_none());
+        assert!(PairPin::from_digits(&[1, 2, 3], PinPolicy::EXTENDED).is_none());
+        assert!(PairPin::from_digits(&[1; 9], PinPolicy::EXTENDED).is_none());
+        assert!(PairPin::from_digits(&[1, 2, 3, 10], PinPolicy::CLASSIC).is_none());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_array() {
+        let pin = PairPin::from_array([1, 2, 3, 4]);
+        assert_eq!(pin.map(|pin| pin.array()), Some([1, 2, 3, 4]));
+        assert_eq!(pin.and_then(|pin| pin.try_array()), Some([1, 2, 3, 4]));
+
+        let pin = PairPin::from_digits(&[1, 2, 3, 4, 5], PinPolicy::EXTENDED);
+        assert_eq!(pin.map(|pin| pin.array()), Some([1, 2, 3, 4]));
+        assert_eq!(pin.and_then(|pin| pin.try_array()), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pair")]
+    fn test_generate_with() -> Result<(), openssl::error::ErrorStack> {
+        assert_eq!(PairPin::generate_with(PinPolicy::EXTENDED, 6)?.len(), 6);
+        assert_eq!(PairPin::generate_with(PinPolicy::EXTENDED, 2)?.len(), 4);
+        assert_eq!(PairPin::generate_with(PinPolicy::EXTENDED, 20)?.len(), 8);
+        assert_eq!(PairPin::generate_with(PinPolicy::CLASSIC, 8)?.len(), 4);
+
+        let pin = PairPin::generate_with(PinPolicy::EXTENDED, 8)?;
+        assert!(pin.digits().iter().all(|digit| *digit < 10));
+
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 use std::{
     mem::swap,
+    net::IpAddr,
     sync::atomic::{AtomicUsize, Ordering},
     time::Duration,
 };
 
-use curl::easy::{Easy2, Handler, InfoType, SslOpt, WriteError};
+use curl::easy::{Easy2, Handler, InfoType, List, SslOpt, WriteError};
 use log::{LevelFilter, debug};
 use pem::Pem;
 use thiserror::Error;
@@ -13,7 +14,9 @@ use url::Url;
 
 use crate::network::{
     backend::{DEFAULT_LONG_TIMEOUT, DEFAULT_TIMEOUT},
-    request_client::{QueryParamsRef, RequestClient, RequestError},
+    request_client::{
+        CancellationToken, QueryParamsRef, RequestClient, RequestClientOptions, RequestError,
+    },
 };
 
 #[derive(Debug, Error)]
@@ -26,6 +29,8 @@ pub enum CurlError {
     Tokio(#[from] JoinError),
     #[error("cannot make https requests without certificates")]
     NoCertificates,
+    #[error("the request was cancelled")]
+    Cancelled,
 }
 
 impl RequestError for CurlError {
@@ -35,10 +40,14 @@ impl RequestError for CurlError {
     fn is_encryption(&self) -> bool {
         matches!(self, Self::Curl(err) if err.is_peer_failed_verification())
     }
+    fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
 }
 
 pub struct CurlClient {
     timeout: Duration,
+    options: RequestClientOptions,
     certificates: Option<Certificates>,
 }
 
@@ -52,14 +61,16 @@ struct Certificates {
 struct CurlHandler {
     debug_number: usize,
     response: Vec<u8>,
+    cancel: CancellationToken,
 }
 impl CurlHandler {
-    fn new() -> Self {
+    fn new(cancel: CancellationToken) -> Self {
         static DEBUG_NUMBER: AtomicUsize = AtomicUsize::new(0);
 
         Self {
             debug_number: DEBUG_NUMBER.fetch_add(1, Ordering::Acquire),
             response: Default::default(),
+            cancel,
         }
     }
 }
@@ -69,6 +80,11 @@ impl Handler for CurlHandler {
         Ok(data.len())
     }
 
+    fn progress(&mut self, _dltotal: f64, _dlnow: f64, _ultotal: f64, _ulnow: f64) -> bool {
+        // Returning false aborts the transfer on the blocking thread
+        !self.cancel.is_cancelled()
+    }
+
     fn debug(&mut self, kind: InfoType, data: &[u8]) {
         let prefix = match kind {
             InfoType::Text => "*",
@@ -105,12 +121,14 @@ fn log_error<T>(error: Result<T, CurlError>) -> Result<T, CurlError> {
 
 async fn make_curl_request(
     certificates: Option<&Certificates>,
+    options: &RequestClientOptions,
     hostport: &str,
     path: &str,
     query_params: &QueryParamsRef<'_>,
     timeout: Duration,
+    cancel: &CancellationToken,
 ) -> Result<Vec<u8>, CurlError> {
-    let mut curl = Easy2::new(CurlHandler::new());
+    let mut curl = Easy2::new(CurlHandler::new(cancel.clone()));
 
     curl.verbose(log::max_level() >= LevelFilter::Debug)?;
 
@@ -119,6 +137,25 @@ async fn make_curl_request(
 
     curl.url(url.as_str())?;
     curl.timeout(timeout)?;
+    curl.progress(true)?;
+
+    if let Some(proxy) = &options.proxy {
+        curl.proxy(proxy)?;
+    }
+    // The system resolver is skipped by handing curl the address of the host name
+    let host = url.host_str().unwrap_or_default();
+    if let Some(address) = options.dns_override(host)
+        && let Some(port) = url.port_or_known_default()
+    {
+        let address = match address {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{address}]"),
+        };
+
+        let mut resolve = List::new();
+        resolve.append(&format!("{host}:{port}:{address}"))?;
+        curl.resolve(resolve)?;
+    }
 
     if let Some(certificates) = certificates {
         curl.ssl_cert_type("DER")?;
@@ -136,11 +173,14 @@ async fn make_curl_request(
         curl.ssl_options(SslOpt::new().no_revoke(true))?;
     }
 
-    let (result, mut curl) = spawn_blocking(move || {
+    let request = spawn_blocking(move || {
         let result = curl.perform();
         (result, curl)
-    })
-    .await?;
+    });
+    let Some(request) = cancel.run_until_cancelled(request).await else {
+        return Err(CurlError::Cancelled);
+    };
+    let (result, mut curl) = request?;
 
     result?;
 
@@ -156,19 +196,22 @@ impl RequestClient for CurlClient {
     type Bytes = Vec<u8>;
     type Text = String;
 
-    fn with_defaults() -> Result<Self, Self::Error> {
+    fn with_defaults(options: &RequestClientOptions) -> Result<Self, Self::Error> {
         Ok(CurlClient {
             certificates: None,
+            options: options.clone(),
             timeout: DEFAULT_TIMEOUT,
         })
     }
-    fn with_defaults_long_timeout() -> Result<Self, Self::Error> {
+    fn with_defaults_long_timeout(options: &RequestClientOptions) -> Result<Self, Self::Error> {
         Ok(CurlClient {
             certificates: None,
+            options: options.clone(),
             timeout: DEFAULT_LONG_TIMEOUT,
         })
     }
     fn with_certificates(
+        options: &RequestClientOptions,
         client_private_key: &Pem,
         client_certificate: &Pem,
         server_certificate: &Pem,
@@ -179,6 +222,7 @@ impl RequestClient for CurlClient {
                 client_certificate: client_certificate.contents().to_vec(),
                 server_certificate: server_certificate.contents().to_vec(),
             }),
+            options: options.clone(),
             timeout: DEFAULT_TIMEOUT,
         })
     }
@@ -188,9 +232,20 @@ impl RequestClient for CurlClient {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Text, Self::Error> {
-        let response =
-            log_error(make_curl_request(None, hostport, path, query_params, self.timeout).await)?;
+        let response = log_error(
+            make_curl_request(
+                None,
+                &self.options,
+                hostport,
+                path,
+                query_params,
+                self.timeout,
+                cancel,
+            )
+            .await,
+        )?;
 
         // TODO: convert to utf8 lossy owned when stable
         Ok(String::from_utf8_lossy(&response).into_owned())
@@ -200,6 +255,7 @@ impl RequestClient for CurlClient {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Text, Self::Error> {
         if self.certificates.is_none() {
             return Err(CurlError::NoCertificates);
@@ -208,10 +264,12 @@ impl RequestClient for CurlClient {
         let response = log_error(
             make_curl_request(
                 self.certificates.as_ref(),
+                &self.options,
                 hostport,
                 path,
                 query_params,
                 self.timeout,
+                cancel,
             )
             .await,
         )?;
@@ -223,6 +281,7 @@ impl RequestClient for CurlClient {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Bytes, Self::Error> {
         if self.certificates.is_none() {
             return Err(CurlError::NoCertificates);
@@ -231,10 +290,12 @@ impl RequestClient for CurlClient {
         let response = log_error(
             make_curl_request(
                 self.certificates.as_ref(),
+                &self.options,
                 hostport,
                 path,
                 query_params,
                 self.timeout,
+                cancel,
             )
             .await,
         )?;
@@ -1,6 +1,9 @@
 use std::{
+    collections::HashMap,
     io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr},
     str::Utf8Error,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -12,7 +15,7 @@ use hyper_util::rt::TokioIo;
 use log::debug;
 use openssl::{
     pkey::PKey,
-    ssl::{Ssl, SslContext, SslMethod, SslVerifyMode},
+    ssl::{Ssl, SslContext, SslMethod, SslRef, SslSession, SslSessionCacheMode, SslVerifyMode},
     x509::X509,
 };
 use pem::Pem;
@@ -22,7 +25,9 @@ use url::Url;
 
 use crate::network::{
     backend::{DEFAULT_LONG_TIMEOUT, DEFAULT_TIMEOUT},
-    request_client::{QueryParamsRef, RequestClient, RequestError},
+    request_client::{
+        CancellationToken, QueryParamsRef, RequestClient, RequestClientOptions, RequestError,
+    },
 };
 
 #[derive(Debug, Error)]
@@ -47,6 +52,10 @@ pub enum HyperOpenSSLError {
     NoCertificates,
     #[error("timeout")]
     Timeout,
+    #[error("this backend cannot connect through a proxy")]
+    ProxyUnsupported,
+    #[error("the request was cancelled")]
+    Cancelled,
 }
 
 impl RequestError for HyperOpenSSLError {
@@ -60,6 +69,9 @@ impl RequestError for HyperOpenSSLError {
     fn is_encryption(&self) -> bool {
         matches!(self, Self::NoCertificates)
     }
+    fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
 }
 
 fn build_url(
@@ -78,32 +90,53 @@ fn build_url(
 
 pub struct HyperOpenSSLClient {
     ssl_ctx: Option<SslContext>,
+    /// The newest tls session of the host, resumed by the next connection to skip the full handshake
+    ssl_session: Arc<Mutex<Option<SslSession>>>,
+    dns_overrides: HashMap<String, IpAddr>,
     timeout: Duration,
 }
 
+fn check_options(options: &RequestClientOptions) -> Result<(), HyperOpenSSLError> {
+    if options.proxy.is_some() {
+        return Err(HyperOpenSSLError::ProxyUnsupported);
+    }
+    Ok(())
+}
+
 impl RequestClient for HyperOpenSSLClient {
     type Error = HyperOpenSSLError;
 
     type Bytes = bytes::Bytes;
     type Text = String;
 
-    fn with_defaults() -> Result<Self, Self::Error> {
+    fn with_defaults(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        check_options(options)?;
+
         Ok(Self {
             ssl_ctx: None,
+            ssl_session: Default::default(),
+            dns_overrides: options.dns_overrides.clone(),
             timeout: DEFAULT_TIMEOUT,
         })
     }
-    fn with_defaults_long_timeout() -> Result<Self, Self::Error> {
+    fn with_defaults_long_timeout(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        check_options(options)?;
+
         Ok(Self {
             ssl_ctx: None,
+            ssl_session: Default::default(),
+            dns_overrides: options.dns_overrides.clone(),
             timeout: DEFAULT_LONG_TIMEOUT,
         })
     }
     fn with_certificates(
+        options: &RequestClientOptions,
         client_private_key: &Pem,
         client_certificate: &Pem,
         server_certificate: &Pem,
     ) -> Result<Self, Self::Error> {
+        check_options(options)?;
+
         let client_certificate = X509::from_der(client_certificate.contents())?;
         let client_private_key = PKey::private_key_from_der(client_private_key.contents())?;
 
@@ -123,8 +156,19 @@ impl RequestClient for HyperOpenSSLClient {
             }
         });
 
+        let ssl_session = Arc::new(Mutex::new(None));
+        let new_session = ssl_session.clone();
+        ssl.set_session_cache_mode(SslSessionCacheMode::CLIENT);
+        ssl.set_new_session_callback(move |_, session| {
+            if let Ok(mut ssl_session) = new_session.lock() {
+                *ssl_session = Some(session);
+            }
+        });
+
         Ok(Self {
             ssl_ctx: Some(ssl.build()),
+            ssl_session,
+            dns_overrides: options.dns_overrides.clone(),
             timeout: DEFAULT_TIMEOUT,
         })
     }
@@ -134,11 +178,76 @@ impl RequestClient for HyperOpenSSLClient {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Text, Self::Error> {
+        cancel
+            .run_until_cancelled(self.http_text_response(hostport, path, query_params))
+            .await
+            .unwrap_or(Err(HyperOpenSSLError::Cancelled))
+    }
+    async fn send_https_request_text_response(
+        &mut self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Self::Text, Self::Error> {
+        cancel
+            .run_until_cancelled(self.https_text_response(hostport, path, query_params))
+            .await
+            .unwrap_or(Err(HyperOpenSSLError::Cancelled))
+    }
+    async fn send_https_request_data_response(
+        &mut self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Self::Bytes, Self::Error> {
+        cancel
+            .run_until_cancelled(self.https_data_response(hostport, path, query_params))
+            .await
+            .unwrap_or(Err(HyperOpenSSLError::Cancelled))
+    }
+}
+
+impl HyperOpenSSLClient {
+    fn socket_addrs(&self, url: &Url) -> Result<Vec<SocketAddr>, HyperOpenSSLError> {
+        let overridden = url
+            .host_str()
+            .and_then(|host| self.dns_overrides.get(host))
+            .zip(url.port_or_known_default());
+
+        match overridden {
+            Some((address, port)) => Ok(vec![SocketAddr::new(*address, port)]),
+            None => Ok(url.socket_addrs(|| None)?),
+        }
+    }
+
+    fn resume_session(&self, ssl: &mut SslRef) -> Result<(), HyperOpenSSLError> {
+        let session = match self.ssl_session.lock() {
+            Ok(session) => session.clone(),
+            Err(_) => None,
+        };
+
+        if let Some(session) = session {
+            // Safety: all sessions were created by connections of our ssl context
+            unsafe { ssl.set_session(&session)? };
+        }
+
+        Ok(())
+    }
+
+    async fn http_text_response(
+        &self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+    ) -> Result<String, HyperOpenSSLError> {
         let url = build_url(false, hostport, path, query_params)?;
         debug!(target: "client_hyper_openssl", "Sending http request to \"{url}\"");
 
-        let address = url.socket_addrs(|| None)?;
+        let address = self.socket_addrs(&url)?;
         let stream = timeout(self.timeout, TcpStream::connect(&*address))
             .await
             .map_err(|_| HyperOpenSSLError::Timeout)??;
@@ -188,12 +297,12 @@ impl RequestClient for HyperOpenSSLClient {
         Ok(response_str)
     }
 
-    async fn send_https_request_text_response(
-        &mut self,
+    async fn https_text_response(
+        &self,
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
-    ) -> Result<Self::Text, Self::Error> {
+    ) -> Result<String, HyperOpenSSLError> {
         let Some(ssl_ctx) = self.ssl_ctx.as_ref() else {
             return Err(HyperOpenSSLError::NoCertificates);
         };
@@ -201,7 +310,7 @@ impl RequestClient for HyperOpenSSLClient {
         let url = build_url(false, hostport, path, query_params)?;
         debug!(target: "client_hyper_openssl", "Sending https request to \"{url}\"");
 
-        let address = url.socket_addrs(|| None)?;
+        let address = self.socket_addrs(&url)?;
         let stream = timeout(self.timeout, TcpStream::connect(&*address))
             .await
             .map_err(|_| HyperOpenSSLError::Timeout)??;
@@ -210,6 +319,7 @@ impl RequestClient for HyperOpenSSLClient {
 
         let mut ssl = Ssl::new(ssl_ctx)?;
         ssl.set_connect_state();
+        self.resume_session(&mut ssl)?;
 
         let mut ssl_stream = Box::pin(SslStream::new(ssl, io)?);
         timeout(self.timeout, ssl_stream.as_mut().do_handshake())
@@ -256,12 +366,12 @@ impl RequestClient for HyperOpenSSLClient {
 
         Ok(response_str)
     }
-    async fn send_https_request_data_response(
-        &mut self,
+    async fn https_data_response(
+        &self,
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
-    ) -> Result<Self::Bytes, Self::Error> {
+    ) -> Result<Bytes, HyperOpenSSLError> {
         let Some(ssl_ctx) = self.ssl_ctx.as_ref() else {
             return Err(HyperOpenSSLError::NoCertificates);
         };
@@ -269,7 +379,7 @@ impl RequestClient for HyperOpenSSLClient {
         let url = build_url(false, hostport, path, query_params)?;
         debug!(target: "client_hyper_openssl", "Sending https request to \"{url}\"");
 
-        let address = url.socket_addrs(|| None)?;
+        let address = self.socket_addrs(&url)?;
         let stream = timeout(self.timeout, TcpStream::connect(&*address))
             .await
             .map_err(|_| HyperOpenSSLError::Timeout)??;
@@ -278,6 +388,7 @@ impl RequestClient for HyperOpenSSLClient {
 
         let mut ssl = Ssl::new(ssl_ctx)?;
         ssl.set_connect_state();
+        self.resume_session(&mut ssl)?;
 
         let mut ssl_stream = Box::pin(SslStream::new(ssl, io)?);
         timeout(self.timeout, ssl_stream.as_mut().do_handshake())
@@ -0,0 +1,115 @@
+//! Responses in the formats of GeForce Experience and Sunshine for a [MockScript](super::MockScript).
+//!
+//! GFE sends more fields than the parsers read, they're kept so parsers are tested against the real shape.
+
+/// An unpaired Sunshine host on the default ports without a running app
+pub const SUNSHINE_SERVER_INFO: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <hostname>SunshineHost</hostname>
+    <appversion>7.1.431.-1</appversion>
+    <GfeVersion>3.23.0.74</GfeVersion>
+    <uniqueid>5b1f2e84-6c1a-4e0f-9d57-2c3a8b4e91d0</uniqueid>
+    <HttpsPort>47984</HttpsPort>
+    <ExternalPort>47989</ExternalPort>
+    <MaxLumaPixelsHEVC>1869449984</MaxLumaPixelsHEVC>
+    <mac>00:11:22:33:44:55</mac>
+    <LocalIP>192.168.1.20</LocalIP>
+    <ServerCodecModeSupport>3843</ServerCodecModeSupport>
+    <PairStatus>0</PairStatus>
+    <currentgame>0</currentgame>
+    <state>SUNSHINE_SERVER_FREE</state>
+</root>"#;
+
+/// A paired Sunshine host streaming the app with the id 1, requested over https
+pub const SUNSHINE_SERVER_INFO_BUSY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <hostname>SunshineHost</hostname>
+    <appversion>7.1.431.-1</appversion>
+    <GfeVersion>3.23.0.74</GfeVersion>
+    <uniqueid>5b1f2e84-6c1a-4e0f-9d57-2c3a8b4e91d0</uniqueid>
+    <HttpsPort>47984</HttpsPort>
+    <ExternalPort>47989</ExternalPort>
+    <MaxLumaPixelsHEVC>1869449984</MaxLumaPixelsHEVC>
+    <mac>00:11:22:33:44:55</mac>
+    <LocalIP>192.168.1.20</LocalIP>
+    <ServerCodecModeSupport>3843</ServerCodecModeSupport>
+    <PairStatus>1</PairStatus>
+    <currentgame>1</currentgame>
+    <state>SUNSHINE_SERVER_BUSY</state>
+</root>"#;
+
+pub const SUNSHINE_APP_LIST: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <App>
+        <IsHdrSupported>0</IsHdrSupported>
+        <AppTitle>Desktop</AppTitle>
+        <ID>1</ID>
+    </App>
+    <App>
+        <IsHdrSupported>1</IsHdrSupported>
+        <AppTitle>Steam Big Picture</AppTitle>
+        <ID>2</ID>
+    </App>
+</root>"#;
+
+/// An unpaired GeForce Experience host, GFE leaves the mini patch of the app version positive
+pub const GFE_SERVER_INFO: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <hostname>GFE-HOST</hostname>
+    <appversion>7.1.431.0</appversion>
+    <GfeVersion>3.23.0.74</GfeVersion>
+    <uniqueid>c7d3a915-0e42-4b8c-a6f1-7e59d2b8c034</uniqueid>
+    <HttpsPort>47984</HttpsPort>
+    <ExternalPort>47989</ExternalPort>
+    <MaxLumaPixelsHEVC>1869449984</MaxLumaPixelsHEVC>
+    <mac>66:77:88:99:AA:BB</mac>
+    <LocalIP>192.168.1.30</LocalIP>
+    <ServerCodecModeSupport>259</ServerCodecModeSupport>
+    <SupportedDisplayMode>
+        <DisplayMode>
+            <Width>1920</Width>
+            <Height>1080</Height>
+            <RefreshRate>60</RefreshRate>
+        </DisplayMode>
+    </SupportedDisplayMode>
+    <PairStatus>0</PairStatus>
+    <currentgame>0</currentgame>
+    <state>MJOLNIR_STATE_SERVER_FREE</state>
+    <ServerCapability>1</ServerCapability>
+    <gputype>NVIDIA GeForce RTX 3070</gputype>
+    <numofapps>1</numofapps>
+</root>"#;
+
+pub const GFE_APP_LIST: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <App>
+        <AppTitle>Steam</AppTitle>
+        <ID>123456789</ID>
+        <IsHdrSupported>1</IsHdrSupported>
+        <IsAppCollectorGame>0</IsAppCollectorGame>
+        <Distributor>Steam</Distributor>
+        <MaxControllersForSingleSession>4</MaxControllersForSingleSession>
+        <SupportsStandardPreview>0</SupportsStandardPreview>
+    </App>
+</root>"#;
+
+pub const LAUNCH: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <sessionUrl0>rtsp://192.168.1.20:48010</sessionUrl0>
+    <gamesession>1</gamesession>
+</root>"#;
+
+pub const RESUME: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <sessionUrl0>rtsp://192.168.1.20:48010</sessionUrl0>
+    <resume>1</resume>
+</root>"#;
+
+pub const CANCEL: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="200">
+    <cancel>1</cancel>
+</root>"#;
+
+/// Sent by hosts which don't know the certificate of the client anymore
+pub const UNAUTHORIZED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root status_code="401" status_message="The client is not authorized. Certificate verification failed."/>"#;
@@ -0,0 +1,341 @@
+//! A scriptable [RequestClient] for testing host logic without a network.
+//!
+//! The clients don't know their responses when they're created, because [MoonlightHost](crate::high::MoonlightHost) creates them itself.
+//! Instead every request looks up the [MockScript] registered for the host name of its `hostport`,
+//! so tests running in parallel must use different host names.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use moonlight_common::network::backend::mock::{
+//!     MockMoonlightHost, MockResponse, MockScript, fixtures,
+//! };
+//!
+//! let script = MockScript::new("sunshine-test-host");
+//! script.respond("serverinfo", MockResponse::text(fixtures::SUNSHINE_SERVER_INFO));
+//!
+//! let mut host = MockMoonlightHost::new(script.host_name().to_string(), 47989, None).unwrap();
+//! assert_eq!(host.host_name().await.unwrap(), "SunshineHost");
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, LazyLock, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+use pem::Pem;
+use thiserror::Error;
+
+use crate::network::{
+    ApiError,
+    request_client::{
+        CancellationToken, QueryParamsRef, RequestClient, RequestClientOptions, RequestError,
+    },
+};
+
+pub mod fixtures;
+
+#[cfg(feature = "high")]
+pub type MockMoonlightHost = crate::high::MoonlightHost<MockRequestClient>;
+
+/// The scripts by host name, a script is removed when all of its handles are dropped
+static SCRIPTS: LazyLock<Mutex<HashMap<String, Weak<Mutex<ScriptState>>>>> =
+    LazyLock::new(Default::default);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking test shouldn't fail the other tests
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MockError {
+    #[error("the host cannot be reached")]
+    Connect,
+    #[error("the host rejected the encryption")]
+    Encryption,
+    #[error("the request was cancelled")]
+    Cancelled,
+    #[error("no mock script is registered for the host \"{0}\"")]
+    UnknownHost(String),
+    #[error("the mock script has no response for \"{0}\"")]
+    NoResponse(String),
+    #[error("{0}")]
+    Other(String),
+}
+pub type MockApiError = ApiError<MockError>;
+
+impl RequestError for MockError {
+    fn is_connect(&self) -> bool {
+        matches!(self, MockError::Connect)
+    }
+    fn is_encryption(&self) -> bool {
+        matches!(self, MockError::Encryption)
+    }
+    fn is_cancelled(&self) -> bool {
+        matches!(self, MockError::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockResponse {
+    Data(Vec<u8>),
+    Error(MockError),
+}
+
+impl MockResponse {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Data(text.into().into_bytes())
+    }
+    /// Wraps the body into the `<root>` element like the hosts do
+    pub fn xml(status_code: u16, body: &str) -> Self {
+        Self::text(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><root status_code=\"{status_code}\">{body}</root>"
+        ))
+    }
+    pub fn error(err: MockError) -> Self {
+        Self::Error(err)
+    }
+}
+
+/// A request which was sent to a [MockScript]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub https: bool,
+    pub hostport: String,
+    pub path: String,
+    pub query_params: Vec<(String, String)>,
+    /// If the client was created with the client and server certificate
+    pub with_certificates: bool,
+}
+
+impl MockRequest {
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(param_key, _)| param_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Default)]
+struct ScriptState {
+    responses: HashMap<String, MockResponse>,
+    queued: HashMap<String, VecDeque<MockResponse>>,
+    latency: Duration,
+    requests: Vec<MockRequest>,
+}
+
+/// The responses of a host, cloned handles share the same script
+#[derive(Clone)]
+pub struct MockScript {
+    host_name: String,
+    state: Arc<Mutex<ScriptState>>,
+}
+
+impl MockScript {
+    /// Registers the script for the host name, replacing the previous script of it
+    pub fn new(host_name: impl Into<String>) -> Self {
+        let host_name = host_name.into();
+        let state = Arc::new(Mutex::new(ScriptState::default()));
+
+        let mut scripts = lock(&SCRIPTS);
+        scripts.retain(|_, script| script.strong_count() > 0);
+        scripts.insert(host_name.clone(), Arc::downgrade(&state));
+
+        Self { host_name, state }
+    }
+
+    pub fn host_name(&self) -> &str {
+        &self.host_name
+    }
+
+    /// Answers every request to the path with the response, e.g. `serverinfo` or `applist`
+    pub fn respond(&self, path: &str, response: MockResponse) -> &Self {
+        lock(&self.state)
+            .responses
+            .insert(normalize_path(path).to_string(), response);
+        self
+    }
+
+    /// Answers the next request to the path with the response before falling back to [Self::respond]
+    pub fn respond_once(&self, path: &str, response: MockResponse) -> &Self {
+        lock(&self.state)
+            .queued
+            .entry(normalize_path(path).to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    /// Fails every request to the path
+    pub fn fail(&self, path: &str, err: MockError) -> &Self {
+        self.respond(path, MockResponse::Error(err))
+    }
+
+    /// Delays every response, cancelled requests return early
+    pub fn set_latency(&self, latency: Duration) {
+        lock(&self.state).latency = latency;
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        lock(&self.state).requests.clone()
+    }
+
+    pub fn clear_requests(&self) {
+        lock(&self.state).requests.clear();
+    }
+}
+
+fn normalize_path(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+/// The host name without the port, ipv6 addresses keep their brackets
+fn host_name(hostport: &str) -> &str {
+    if hostport.starts_with('[') {
+        return match hostport.find(']') {
+            Some(end) => &hostport[..=end],
+            None => hostport,
+        };
+    }
+
+    match hostport.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => hostport,
+    }
+}
+
+/// A [RequestClient] answering with the [MockScript] of the requested host
+#[derive(Debug, Clone, Default)]
+pub struct MockRequestClient {
+    options: RequestClientOptions,
+    with_certificates: bool,
+}
+
+impl MockRequestClient {
+    pub fn options(&self) -> &RequestClientOptions {
+        &self.options
+    }
+
+    async fn send(
+        &self,
+        https: bool,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<u8>, MockError> {
+        let host_name = host_name(hostport);
+        let state = lock(&SCRIPTS)
+            .get(host_name)
+            .and_then(Weak::upgrade)
+            .ok_or_else(|| MockError::UnknownHost(host_name.to_string()))?;
+
+        let path = normalize_path(path);
+
+        let (latency, response) = {
+            let mut state = lock(&state);
+
+            state.requests.push(MockRequest {
+                https,
+                hostport: hostport.to_string(),
+                path: path.to_string(),
+                query_params: query_params
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                with_certificates: self.with_certificates,
+            });
+
+            let response = state
+                .queued
+                .get_mut(path)
+                .and_then(VecDeque::pop_front)
+                .or_else(|| state.responses.get(path).cloned());
+
+            (state.latency, response)
+        };
+
+        if cancel
+            .run_until_cancelled(tokio::time::sleep(latency))
+            .await
+            .is_none()
+        {
+            return Err(MockError::Cancelled);
+        }
+
+        match response {
+            Some(MockResponse::Data(data)) => Ok(data),
+            Some(MockResponse::Error(err)) => Err(err),
+            None => Err(MockError::NoResponse(path.to_string())),
+        }
+    }
+}
+
+impl RequestClient for MockRequestClient {
+    type Error = MockError;
+
+    type Text = String;
+    type Bytes = Vec<u8>;
+
+    fn with_defaults(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        Ok(Self {
+            options: options.clone(),
+            with_certificates: false,
+        })
+    }
+    fn with_defaults_long_timeout(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        Self::with_defaults(options)
+    }
+
+    fn with_certificates(
+        options: &RequestClientOptions,
+        _client_private_key: &Pem,
+        _client_certificate: &Pem,
+        _server_certificate: &Pem,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            options: options.clone(),
+            with_certificates: true,
+        })
+    }
+
+    async fn send_http_request_text_response(
+        &mut self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Self::Text, Self::Error> {
+        let data = self
+            .send(false, hostport, path, query_params, cancel)
+            .await?;
+
+        String::from_utf8(data).map_err(|err| MockError::Other(err.to_string()))
+    }
+
+    async fn send_https_request_text_response(
+        &mut self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Self::Text, Self::Error> {
+        let data = self
+            .send(true, hostport, path, query_params, cancel)
+            .await?;
+
+        String::from_utf8(data).map_err(|err| MockError::Other(err.to_string()))
+    }
+
+    async fn send_https_request_data_response(
+        &mut self,
+        hostport: &str,
+        path: &str,
+        query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<Self::Bytes, Self::Error> {
+        self.send(true, hostport, path, query_params, cancel).await
+    }
+}
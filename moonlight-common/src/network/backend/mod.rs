@@ -4,8 +4,12 @@ use std::time::Duration;
 pub mod curl;
 #[cfg(feature = "backend_hyper_openssl")]
 pub mod hyper_openssl;
+#[cfg(feature = "backend_mock")]
+pub mod mock;
 #[cfg(feature = "backend_reqwest")]
 pub mod reqwest;
 
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 pub const DEFAULT_LONG_TIMEOUT: Duration = Duration::from_secs(90);
+/// Hosts close idle keep-alive connections after a few seconds, a connection which the host already closed fails the request
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
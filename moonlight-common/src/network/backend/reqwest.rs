@@ -1,14 +1,18 @@
+use std::net::SocketAddr;
+
 use bytes::Bytes;
 use log::debug;
 use pem::Pem;
-use reqwest::{Certificate, Client, ClientBuilder, Identity};
+use reqwest::{Certificate, Client, ClientBuilder, Identity, Proxy};
 use thiserror::Error;
 use url::{ParseError, Url};
 
 use crate::network::{
     ApiError,
-    backend::{DEFAULT_LONG_TIMEOUT, DEFAULT_TIMEOUT},
-    request_client::{QueryParamsRef, RequestClient, RequestError},
+    backend::{DEFAULT_LONG_TIMEOUT, DEFAULT_POOL_IDLE_TIMEOUT, DEFAULT_TIMEOUT},
+    request_client::{
+        CancellationToken, QueryParamsRef, RequestClient, RequestClientOptions, RequestError,
+    },
 };
 
 pub type ReqwestClient = reqwest::Client;
@@ -21,6 +25,8 @@ pub enum ReqwestError {
     Reqwest(#[from] reqwest::Error),
     #[error("{0}")]
     UrlParse(#[from] ParseError),
+    #[error("the request was cancelled")]
+    Cancelled,
 }
 pub type ReqwestApiError = ApiError<ReqwestError>;
 
@@ -34,17 +40,30 @@ impl RequestError for ReqwestError {
             _ => false,
         }
     }
+    fn is_cancelled(&self) -> bool {
+        matches!(self, ReqwestError::Cancelled)
+    }
 }
 
-fn default_builder() -> ClientBuilder {
-    ClientBuilder::new()
+fn default_builder(options: &RequestClientOptions) -> Result<ClientBuilder, ReqwestError> {
+    let mut builder = ClientBuilder::new()
         .use_native_tls()
         .timeout(DEFAULT_LONG_TIMEOUT)
-        // https://github.com/seanmonstar/reqwest/issues/2021
-        .pool_max_idle_per_host(0)
+        // Only reuse connections shortly: https://github.com/seanmonstar/reqwest/issues/2021
+        .pool_idle_timeout(DEFAULT_POOL_IDLE_TIMEOUT);
+
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    for (host, address) in &options.dns_overrides {
+        // The port of the url is used instead of this one
+        builder = builder.resolve(host, SocketAddr::new(*address, 0));
+    }
+
+    Ok(builder)
 }
-fn timeout_builder() -> ClientBuilder {
-    default_builder().timeout(DEFAULT_TIMEOUT)
+fn timeout_builder(options: &RequestClientOptions) -> Result<ClientBuilder, ReqwestError> {
+    Ok(default_builder(options)?.timeout(DEFAULT_TIMEOUT))
 }
 
 fn build_url(
@@ -69,14 +88,15 @@ impl RequestClient for Client {
     type Text = String;
     type Bytes = Bytes;
 
-    fn with_defaults_long_timeout() -> Result<Self, Self::Error> {
-        Ok(default_builder().build()?)
+    fn with_defaults_long_timeout(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        Ok(default_builder(options)?.build()?)
     }
-    fn with_defaults() -> Result<Self, Self::Error> {
-        Ok(timeout_builder().build()?)
+    fn with_defaults(options: &RequestClientOptions) -> Result<Self, Self::Error> {
+        Ok(timeout_builder(options)?.build()?)
     }
 
     fn with_certificates(
+        options: &RequestClientOptions,
         client_private_key: &Pem,
         client_certificate: &Pem,
         server_certificate: &Pem,
@@ -93,7 +113,7 @@ impl RequestClient for Client {
             client_private_key.to_string().as_bytes(),
         )?;
 
-        Ok(timeout_builder()
+        Ok(timeout_builder(options)?
             .tls_built_in_root_certs(false)
             .add_root_certificate(server_cert)
             .identity(identity)
@@ -106,9 +126,15 @@ impl RequestClient for Client {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Text, Self::Error> {
         let url = build_url(false, hostport, path, query_params)?;
-        Ok(self.get(url).send().await?.text().await?)
+        let request = async { self.get(url).send().await?.text().await };
+
+        match cancel.run_until_cancelled(request).await {
+            Some(response) => Ok(response?),
+            None => Err(ReqwestError::Cancelled),
+        }
     }
 
     async fn send_https_request_text_response(
@@ -116,9 +142,15 @@ impl RequestClient for Client {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Text, Self::Error> {
         let url = build_url(true, hostport, path, query_params)?;
-        Ok(self.get(url).send().await?.text().await?)
+        let request = async { self.get(url).send().await?.text().await };
+
+        match cancel.run_until_cancelled(request).await {
+            Some(response) => Ok(response?),
+            None => Err(ReqwestError::Cancelled),
+        }
     }
 
     async fn send_https_request_data_response(
@@ -126,8 +158,14 @@ impl RequestClient for Client {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef<'_>,
+        cancel: &CancellationToken,
     ) -> Result<Self::Bytes, Self::Error> {
         let url = build_url(true, hostport, path, query_params)?;
-        Ok(self.get(url).send().await?.bytes().await?)
+        let request = async { self.get(url).send().await?.bytes().await };
+
+        match cancel.run_until_cancelled(request).await {
+            Some(response) => Ok(response?),
+            None => Err(ReqwestError::Cancelled),
+        }
     }
 }
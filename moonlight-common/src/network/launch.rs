@@ -1,33 +1,93 @@
 use std::fmt::Write as _;
 
+use openssl::{error::ErrorStack, rand::rand_bytes};
 use roxmltree::Document;
 use uuid::fmt::Hyphenated;
 
-use crate::network::{fmt_write_to_buffer, u32_to_str};
 use crate::{
     network::{
-        ApiError, ClientInfo,
-        request_client::{DynamicQueryParams, QueryBuilder, RequestClient, query_param},
-        xml_child_text, xml_root_node,
+        ApiError, ClientInfo, fmt_write_to_buffer,
+        request_client::{
+            CancellationToken, DynamicQueryParams, QueryBuilder, RequestClient, query_param,
+        },
+        u32_to_str, xml_child_text, xml_root_node,
     },
-    stream::MoonlightInstance,
+    units::Fps,
 };
 
+/// The launch query parameters of moonlight-common-c, used when launching an app without starting a stream.
+/// When streaming use the parameters of the `MoonlightInstance` instead.
+pub const DEFAULT_LAUNCH_QUERY_PARAMETERS: &str = "&corever=1";
+
+/// Where the audio of a stream is played.
+/// The host always sends the audio, so the client decides locally whether to play it.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioRouting {
+    #[default]
+    Client,
+    Host,
+    Both,
+}
+
+impl AudioRouting {
+    pub fn plays_on_host(self) -> bool {
+        matches!(self, Self::Host | Self::Both)
+    }
+    pub fn plays_on_client(self) -> bool {
+        matches!(self, Self::Client | Self::Both)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClientStreamRequest {
     pub app_id: u32,
     pub mode_width: u32,
     pub mode_height: u32,
-    pub mode_fps: u32,
+    pub mode_fps: Fps,
     pub sops: bool,
     pub hdr: bool,
-    pub local_audio_play_mode: bool,
+    pub audio_routing: AudioRouting,
     pub gamepads_attached_mask: i32,
     pub gamepads_persist_after_disconnect: bool,
     pub ri_key: [u8; 16usize],
     pub ri_key_id: u32,
 }
 
+impl ClientStreamRequest {
+    /// A request which launches the app without starting a stream.
+    /// The stream which resumes this app later on sends its own key, so a random one is used.
+    pub fn launch_only(
+        app_id: u32,
+        width: u32,
+        height: u32,
+        fps: Fps,
+        hdr: bool,
+        sops: bool,
+        audio_routing: AudioRouting,
+    ) -> Result<Self, ErrorStack> {
+        let mut ri_key = [0u8; 16];
+        rand_bytes(&mut ri_key)?;
+
+        let mut ri_key_id = [0u8; 4];
+        rand_bytes(&mut ri_key_id)?;
+
+        Ok(Self {
+            app_id,
+            mode_width: width,
+            mode_height: height,
+            mode_fps: fps,
+            hdr,
+            sops,
+            audio_routing,
+            gamepads_attached_mask: 0,
+            gamepads_persist_after_disconnect: false,
+            ri_key,
+            ri_key_id: u32::from_be_bytes(ri_key_id),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HostLaunchResponse {
     pub game_session: u32,
@@ -35,14 +95,23 @@ pub struct HostLaunchResponse {
 }
 
 pub async fn host_launch<C: RequestClient>(
-    instance: &MoonlightInstance,
+    launch_query_parameters: &str,
     client: &mut C,
+    cancel: &CancellationToken,
     https_address: &str,
     info: ClientInfo<'_>,
     request: ClientStreamRequest,
 ) -> Result<HostLaunchResponse, ApiError<C::Error>> {
-    let response =
-        inner_launch_host(instance, client, https_address, "launch", info, request).await?;
+    let response = inner_launch_host(
+        launch_query_parameters,
+        client,
+        cancel,
+        https_address,
+        "launch",
+        info,
+        request,
+    )
+    .await?;
 
     let doc = Document::parse(response.as_ref())?;
     let root = xml_root_node(&doc)?;
@@ -60,14 +129,23 @@ pub struct HostResumeResponse {
 }
 
 pub async fn host_resume<C: RequestClient>(
-    instance: &MoonlightInstance,
+    launch_query_parameters: &str,
     client: &mut C,
+    cancel: &CancellationToken,
     https_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientStreamRequest,
 ) -> Result<HostResumeResponse, ApiError<C::Error>> {
-    let response =
-        inner_launch_host(instance, client, https_hostport, "resume", info, request).await?;
+    let response = inner_launch_host(
+        launch_query_parameters,
+        client,
+        cancel,
+        https_hostport,
+        "resume",
+        info,
+        request,
+    )
+    .await?;
 
     let doc = Document::parse(response.as_ref())?;
     let root = doc
@@ -83,8 +161,9 @@ pub async fn host_resume<C: RequestClient>(
 }
 
 async fn inner_launch_host<C: RequestClient>(
-    instance: &MoonlightInstance,
+    launch_query_parameters: &str,
     client: &mut C,
+    cancel: &CancellationToken,
     https_hostport: &str,
     verb: &str,
     info: ClientInfo<'_>,
@@ -95,7 +174,7 @@ async fn inner_launch_host<C: RequestClient>(
     let mut uuid_bytes = [0; Hyphenated::LENGTH];
     info.add_query_params(&mut uuid_bytes, &mut query_params);
 
-    let launch_params = form_urlencoded::parse(instance.launch_url_query_parameters().as_bytes());
+    let launch_params = form_urlencoded::parse(launch_query_parameters.as_bytes());
     for (name, value) in launch_params {
         query_params.push((name, value));
     }
@@ -109,7 +188,9 @@ async fn inner_launch_host<C: RequestClient>(
         write!(
             writer,
             "{}x{}x{}",
-            request.mode_width, request.mode_height, request.mode_fps
+            request.mode_width,
+            request.mode_height,
+            request.mode_fps.get()
         )
         .expect("write mode")
     });
@@ -145,7 +226,7 @@ async fn inner_launch_host<C: RequestClient>(
 
     query_params.push(query_param(
         "localAudioPlayMode",
-        if request.local_audio_play_mode {
+        if request.audio_routing.plays_on_host() {
             "1"
         } else {
             "0"
@@ -174,7 +255,7 @@ async fn inner_launch_host<C: RequestClient>(
     ));
 
     let response = client
-        .send_https_request_text_response(https_hostport, verb, &query_params)
+        .send_https_request_text_response(https_hostport, verb, &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
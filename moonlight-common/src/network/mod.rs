@@ -2,15 +2,18 @@ use std::{
     borrow::Cow, fmt, fmt::Write as _, num::ParseIntError, str::FromStr, string::FromUtf8Error,
 };
 
-use log::warn;
-use roxmltree::{Document, Error, Node};
+use log::{debug, warn};
+use roxmltree::{Document, Error, Node, ParsingOptions};
 use thiserror::Error;
 use uuid::{Uuid, fmt::Hyphenated};
 
 use crate::{
     PairStatus, ParseServerStateError, ParseServerVersionError, ServerState, ServerVersion,
+    formats::ServerCodeModeSupport,
     mac::{MacAddress, ParseMacAddressError},
-    network::request_client::{LocalQueryParams, QueryBuilder, RequestClient, query_param},
+    network::request_client::{
+        CancellationToken, LocalQueryParams, QueryBuilder, RequestClient, query_param,
+    },
 };
 
 #[derive(Debug, Error)]
@@ -45,7 +48,6 @@ pub enum ApiError<RequestError> {
     Utf8Error(#[from] FromUtf8Error),
 }
 
-#[cfg(feature = "stream")]
 pub mod launch;
 pub mod pair;
 pub mod request_client;
@@ -54,6 +56,9 @@ pub mod backend;
 
 pub const DEFAULT_UNIQUE_ID: &str = "0123456789ABCDEF";
 
+pub const DEFAULT_HTTP_PORT: u16 = 47989;
+pub const DEFAULT_HTTPS_PORT: u16 = 47984;
+
 #[derive(Debug, Clone, Copy)]
 pub struct ClientInfo<'a> {
     /// It's recommended to use the same (default) UID for all Moonlight clients so we can quit games started by other Moonlight clients.
@@ -86,35 +91,187 @@ impl<'a> ClientInfo<'a> {
     }
 }
 
+/// How strictly the xml responses of hosts are parsed
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum XmlParseMode {
+    /// Requires every field which GeForce Experience and Sunshine send
+    #[default]
+    Strict,
+    /// Accepts the responses of Sunshine forks: tag names are matched case insensitively, values are trimmed,
+    /// missing or invalid fields fall back to their default and apps which can't be parsed are skipped
+    Lenient,
+}
+
+impl XmlParseMode {
+    fn matches(self, node: Node, name: &str) -> bool {
+        if !node.is_element() {
+            return false;
+        }
+
+        let tag_name = node.tag_name().name();
+        match self {
+            Self::Strict => tag_name == name,
+            Self::Lenient => tag_name.eq_ignore_ascii_case(name),
+        }
+    }
+
+    fn trim(self, text: &str) -> &str {
+        match self {
+            Self::Strict => text,
+            Self::Lenient => text.trim(),
+        }
+    }
+}
+
+/// The child elements of a response which the parser doesn't read, e.g. fields added by newer hosts or forks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnknownFields {
+    fields: Vec<(String, String)>,
+}
+
+impl UnknownFields {
+    fn capture(node: Node, known: &[&str], mode: XmlParseMode) -> Self {
+        let fields = node
+            .children()
+            .filter(|child| {
+                child.is_element() && !known.iter().any(|name| mode.matches(*child, name))
+            })
+            .map(|child| {
+                (
+                    child.tag_name().name().to_string(),
+                    child.text().unwrap_or_default().trim().to_string(),
+                )
+            })
+            .collect();
+
+        Self { fields }
+    }
+
+    /// The trimmed text of the field, fields with nested elements have an empty text
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+fn xml_document(text: &str, mode: XmlParseMode) -> Result<Document<'_>, Error> {
+    match mode {
+        XmlParseMode::Strict => Document::parse(text),
+        XmlParseMode::Lenient => {
+            // Some forks and proxies send a byte order mark, whitespace or a doctype before the root
+            let mut options = ParsingOptions::default();
+            options.allow_dtd = true;
+
+            Document::parse_with_options(text.trim_start_matches('\u{feff}').trim_start(), options)
+        }
+    }
+}
+
 fn xml_child_text<'doc, 'node, C: RequestClient>(
     list_node: Node<'node, 'doc>,
     name: &'static str,
 ) -> Result<&'node str, ApiError<C::Error>>
+where
+    'node: 'doc,
+{
+    xml_child_text_mode::<C>(list_node, name, XmlParseMode::Strict)
+}
+
+fn xml_child_text_mode<'doc, 'node, C: RequestClient>(
+    list_node: Node<'node, 'doc>,
+    name: &'static str,
+    mode: XmlParseMode,
+) -> Result<&'node str, ApiError<C::Error>>
 where
     'node: 'doc,
 {
     let node = list_node
         .children()
-        .find(|node| node.tag_name().name() == name)
+        .find(|node| mode.matches(*node, name))
         .ok_or(ApiError::<C::Error>::DetailNotFound(name))?;
     let content = node
         .text()
         .ok_or(ApiError::<C::Error>::XmlTextNotFound(name))?;
 
-    Ok(content)
+    Ok(mode.trim(content))
+}
+
+/// In lenient mode a missing or invalid field is replaced by the default
+fn xml_child_parse_or<C: RequestClient, T: FromStr>(
+    list_node: Node,
+    name: &'static str,
+    mode: XmlParseMode,
+    default: T,
+) -> Result<T, ApiError<C::Error>>
+where
+    ApiError<C::Error>: From<T::Err>,
+{
+    let result = xml_child_text_mode::<C>(list_node, name, mode)
+        .and_then(|text| text.parse::<T>().map_err(ApiError::from));
+
+    match result {
+        Err(_) if mode == XmlParseMode::Lenient => {
+            debug!("the host response has no valid {name}, using its default");
+            Ok(default)
+        }
+        result => result,
+    }
+}
+
+fn xml_child_text_or<'doc, 'node, C: RequestClient>(
+    list_node: Node<'node, 'doc>,
+    name: &'static str,
+    mode: XmlParseMode,
+    default: &'static str,
+) -> Result<&'node str, ApiError<C::Error>>
+where
+    'node: 'doc,
+{
+    match xml_child_text_mode::<C>(list_node, name, mode) {
+        Err(_) if mode == XmlParseMode::Lenient => {
+            debug!("the host response has no {name}, using its default");
+            Ok(default)
+        }
+        result => result,
+    }
 }
 
 fn xml_root_node<'doc, C>(doc: &'doc Document) -> Result<Node<'doc, 'doc>, ApiError<C>> {
+    xml_root_node_mode(doc, XmlParseMode::Strict)
+}
+
+fn xml_root_node_mode<'doc, C>(
+    doc: &'doc Document,
+    mode: XmlParseMode,
+) -> Result<Node<'doc, 'doc>, ApiError<C>> {
     let root = doc
         .root()
         .children()
-        .find(|node| node.tag_name().name() == "root")
+        .find(|node| mode.matches(*node, "root"))
         .ok_or(ApiError::XmlRootNotFound)?;
 
-    let status_code = root
-        .attribute("status_code")
-        .ok_or(ApiError::DetailNotFound("status_code"))?
-        .parse::<u32>()?;
+    let status_code = match root.attribute("status_code") {
+        Some(status_code) => mode.trim(status_code).parse::<u32>()?,
+        None if mode == XmlParseMode::Lenient => 200,
+        None => return Err(ApiError::DetailNotFound("status_code")),
+    };
 
     if status_code / 100 == 4 {
         return Err(ApiError::InvalidXmlStatusCode {
@@ -141,13 +298,87 @@ pub struct HostInfo {
     pub current_game: u32,
     pub state_string: String,
     pub state: ServerState,
+    pub host_type: HostType,
+    pub unknown_fields: UnknownFields,
+}
+
+impl HostInfo {
+    pub fn supports_hdr(&self) -> bool {
+        let codec_mode_support =
+            ServerCodeModeSupport::from_bits_truncate(self.server_codec_mode_support);
+
+        self.app_version.supports_hdr()
+            && (codec_mode_support.contains(ServerCodeModeSupport::HEVC_MAIN10)
+                || codec_mode_support.contains(ServerCodeModeSupport::AV1_MAIN10))
+    }
+}
+
+/// The software which runs on the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostType {
+    /// GeForce Experience or the Nvidia App
+    Gfe,
+    Sunshine,
+    /// A fork of Sunshine with virtual displays
+    Apollo,
+    Unknown,
+}
+
+impl HostType {
+    /// Nvidia calls its host software "Mjolnir" in the state, Sunshine and its forks use "SUNSHINE".
+    /// Older Sunshine versions can only be recognized by the -1 at the end of their version.
+    pub fn detect(state: &str, app_version: ServerVersion, virtual_display_capable: bool) -> Self {
+        let state = state.to_ascii_uppercase();
+
+        if state.contains("MJOLNIR") {
+            Self::Gfe
+        } else if virtual_display_capable {
+            Self::Apollo
+        } else if state.starts_with("SUNSHINE") || app_version.mini_patch < 0 {
+            Self::Sunshine
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn is_sunshine_based(&self) -> bool {
+        matches!(self, Self::Sunshine | Self::Apollo)
+    }
+
+    /// Controllers other than the Xbox layout, e.g. with a touchpad or motion sensors
+    pub fn supports_extended_gamepads(&self) -> bool {
+        self.is_sunshine_based()
+    }
+    pub fn supports_pen_touch(&self) -> bool {
+        self.is_sunshine_based()
+    }
 }
 
+/// The fields of the serverinfo response which [HostInfo] reads
+const HOST_INFO_FIELDS: &[&str] = &[
+    "hostname",
+    "appversion",
+    "GfeVersion",
+    "uniqueid",
+    "HttpsPort",
+    "ExternalPort",
+    "MaxLumaPixelsHEVC",
+    "mac",
+    "LocalIP",
+    "ServerCodecModeSupport",
+    "PairStatus",
+    "currentgame",
+    "state",
+    "VirtualDisplayCapable",
+];
+
 pub async fn host_info<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     use_https: bool,
     hostport: &str,
     info: Option<ClientInfo<'_>>,
+    mode: XmlParseMode,
 ) -> Result<HostInfo, ApiError<C::Error>> {
     let mut query_params = LocalQueryParams::<2>::default();
 
@@ -158,22 +389,22 @@ pub async fn host_info<C: RequestClient>(
 
     let response = if use_https {
         client
-            .send_https_request_text_response(hostport, "serverinfo", &query_params)
+            .send_https_request_text_response(hostport, "serverinfo", &query_params, cancel)
             .await
             .map_err(ApiError::RequestClient)?
     } else {
         client
-            .send_http_request_text_response(hostport, "serverinfo", &query_params)
+            .send_http_request_text_response(hostport, "serverinfo", &query_params, cancel)
             .await
             .map_err(ApiError::RequestClient)?
     };
 
-    let doc = Document::parse(response.as_ref())?;
-    let root = xml_root_node(&doc)?;
+    let doc = xml_document(response.as_ref(), mode)?;
+    let root = xml_root_node_mode(&doc, mode)?;
 
-    let state_string = xml_child_text::<C>(root, "state")?.to_string();
+    let state_string = xml_child_text_or::<C>(root, "state", mode, "")?.to_string();
 
-    let mac = match xml_child_text::<C>(root, "mac") {
+    let mac = match xml_child_text_mode::<C>(root, "mac", mode) {
         Ok(mac) => match mac.parse()? {
             mac if mac == MacAddress::from_bytes([0u8; 6]) => None,
             mac => Some(mac),
@@ -184,25 +415,48 @@ pub async fn host_info<C: RequestClient>(
         }
     };
 
+    let app_version: ServerVersion = xml_child_text_mode::<C>(root, "appversion", mode)?.parse()?;
+    let current_game = xml_child_parse_or::<C, _>(root, "currentgame", mode, 0)?;
+
+    let state = match ServerState::from_str(&state_string) {
+        Ok(state) => state,
+        // Forks with their own state names are only busy while streaming
+        Err(_) if mode == XmlParseMode::Lenient && current_game != 0 => ServerState::Busy,
+        Err(_) if mode == XmlParseMode::Lenient => ServerState::Free,
+        Err(err) => return Err(err.into()),
+    };
+
     Ok(HostInfo {
-        host_name: xml_child_text::<C>(root, "hostname")?.to_string(),
-        app_version: xml_child_text::<C>(root, "appversion")?.parse()?,
-        gfe_version: xml_child_text::<C>(root, "GfeVersion")?.to_string(),
-        unique_id: xml_child_text::<C>(root, "uniqueid")?.parse()?,
-        https_port: xml_child_text::<C>(root, "HttpsPort")?.parse()?,
-        external_port: xml_child_text::<C>(root, "ExternalPort")?.parse()?,
-        max_luma_pixels_hevc: xml_child_text::<C>(root, "MaxLumaPixelsHEVC")?.parse()?,
+        host_name: xml_child_text_mode::<C>(root, "hostname", mode)?.to_string(),
+        app_version,
+        gfe_version: xml_child_text_or::<C>(root, "GfeVersion", mode, "")?.to_string(),
+        unique_id: xml_child_text_mode::<C>(root, "uniqueid", mode)?.parse()?,
+        https_port: xml_child_parse_or::<C, _>(root, "HttpsPort", mode, DEFAULT_HTTPS_PORT)?,
+        external_port: xml_child_parse_or::<C, _>(root, "ExternalPort", mode, DEFAULT_HTTP_PORT)?,
+        max_luma_pixels_hevc: xml_child_parse_or::<C, _>(root, "MaxLumaPixelsHEVC", mode, 0)?,
         mac,
-        local_ip: xml_child_text::<C>(root, "LocalIP")?.to_string(),
-        server_codec_mode_support: xml_child_text::<C>(root, "ServerCodecModeSupport")?.parse()?,
-        pair_status: if xml_child_text::<C>(root, "PairStatus")?.parse::<u32>()? == 0 {
+        local_ip: xml_child_text_or::<C>(root, "LocalIP", mode, "")?.to_string(),
+        server_codec_mode_support: xml_child_parse_or::<C, _>(
+            root,
+            "ServerCodecModeSupport",
+            mode,
+            0,
+        )?,
+        pair_status: if xml_child_parse_or::<C, u32>(root, "PairStatus", mode, 0)? == 0 {
             PairStatus::NotPaired
         } else {
             PairStatus::Paired
         },
-        current_game: xml_child_text::<C>(root, "currentgame")?.parse()?,
-        state: ServerState::from_str(&state_string)?,
+        current_game,
+        state,
+        host_type: HostType::detect(
+            &state_string,
+            app_version,
+            root.children()
+                .any(|node| mode.matches(node, "VirtualDisplayCapable")),
+        ),
         state_string,
+        unknown_fields: UnknownFields::capture(root, HOST_INFO_FIELDS, mode),
     })
 }
 
@@ -224,13 +478,16 @@ where
     })
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct App {
     pub id: u32,
     pub title: String,
     pub is_hdr_supported: bool,
+    pub unknown_fields: UnknownFields,
 }
 
+const APP_FIELDS: &[&str] = &["AppTitle", "ID", "IsHdrSupported"];
+
 #[derive(Debug, Clone)]
 pub struct ServerAppListResponse {
     pub apps: Vec<App>,
@@ -238,8 +495,10 @@ pub struct ServerAppListResponse {
 
 pub async fn host_app_list<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     https_hostport: &str,
     info: ClientInfo<'_>,
+    mode: XmlParseMode,
 ) -> Result<ServerAppListResponse, ApiError<C::Error>> {
     let mut query_params = LocalQueryParams::<2>::default();
 
@@ -247,37 +506,55 @@ pub async fn host_app_list<C: RequestClient>(
     info.add_query_params(&mut uuid_bytes, &mut query_params);
 
     let response = client
-        .send_https_request_text_response(https_hostport, "applist", &query_params)
+        .send_https_request_text_response(https_hostport, "applist", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
-    let doc = Document::parse(response.as_ref())?;
-    let root = xml_root_node(&doc)?;
-
-    let apps = root
-        .children()
-        .filter(|node| node.tag_name().name() == "App")
-        .map(|app_node| {
-            let title = xml_child_text::<C>(app_node, "AppTitle")?.to_string();
-
-            let id = xml_child_text::<C>(app_node, "ID")?.parse()?;
-
-            let is_hdr_supported = xml_child_text::<C>(app_node, "IsHdrSupported")
-                .unwrap_or("0")
-                .parse::<u32>()?
-                == 1;
-
-            Ok(App {
-                id,
-                title,
-                is_hdr_supported,
-            })
-        })
-        .collect::<Result<Vec<_>, ApiError<_>>>()?;
+    let doc = xml_document(response.as_ref(), mode)?;
+    let root = xml_root_node_mode(&doc, mode)?;
+
+    let mut apps = Vec::new();
+    for app_node in root.children().filter(|node| mode.matches(*node, "App")) {
+        match parse_app::<C>(app_node, mode) {
+            Ok(app) => apps.push(app),
+            // One odd app shouldn't hide all the other apps
+            Err(_) if mode == XmlParseMode::Lenient => {
+                let title = xml_child_text_mode::<C>(app_node, "AppTitle", mode).unwrap_or("");
+                warn!("skipping the app \"{title}\" of the host because it failed to parse");
+            }
+            Err(err) => return Err(err),
+        }
+    }
 
     Ok(ServerAppListResponse { apps })
 }
 
+fn parse_app<C: RequestClient>(
+    app_node: Node,
+    mode: XmlParseMode,
+) -> Result<App, ApiError<C::Error>> {
+    let title = xml_child_text_mode::<C>(app_node, "AppTitle", mode)?.to_string();
+
+    let id = xml_child_text_mode::<C>(app_node, "ID", mode)?.parse()?;
+
+    // Some forks don't send it at all
+    let is_hdr_supported = xml_child_text_mode::<C>(app_node, "IsHdrSupported", mode)
+        .unwrap_or("0")
+        .parse::<u32>()
+        .or_else(|err| match mode {
+            XmlParseMode::Strict => Err(err),
+            XmlParseMode::Lenient => Ok(0),
+        })?
+        == 1;
+
+    Ok(App {
+        id,
+        title,
+        is_hdr_supported,
+        unknown_fields: UnknownFields::capture(app_node, APP_FIELDS, mode),
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct ClientAppBoxArtRequest {
     pub app_id: u32,
@@ -285,6 +562,7 @@ pub struct ClientAppBoxArtRequest {
 
 pub async fn host_app_box_art<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     https_address: &str,
     info: ClientInfo<'_>,
     request: ClientAppBoxArtRequest,
@@ -303,7 +581,7 @@ pub async fn host_app_box_art<C: RequestClient>(
     query_params.push(query_param("AssetIdx", "0"));
 
     let response = client
-        .send_https_request_data_response(https_address, "appasset", &query_params)
+        .send_https_request_data_response(https_address, "appasset", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -312,6 +590,7 @@ pub async fn host_app_box_art<C: RequestClient>(
 
 pub async fn host_cancel<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     https_hostport: &str,
     info: ClientInfo<'_>,
 ) -> Result<bool, ApiError<C::Error>> {
@@ -321,7 +600,7 @@ pub async fn host_cancel<C: RequestClient>(
     info.add_query_params(&mut uuid_bytes, &mut query_params);
 
     let response = client
-        .send_https_request_text_response(https_hostport, "cancel", &query_params)
+        .send_https_request_text_response(https_hostport, "cancel", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -8,7 +8,9 @@ use crate::{
     SALT_LENGTH,
     network::{
         ApiError, ClientInfo, PairStatus,
-        request_client::{LocalQueryParams, QueryBuilder, RequestClient, query_param},
+        request_client::{
+            CancellationToken, LocalQueryParams, QueryBuilder, RequestClient, query_param,
+        },
         xml_child_paired, xml_child_text, xml_root_node,
     },
 };
@@ -28,6 +30,7 @@ pub struct HostPairResponse1 {
 
 pub async fn host_pair1<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     http_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientPairRequest1<'_>,
@@ -50,7 +53,7 @@ pub async fn host_pair1<C: RequestClient>(
     query_params.push(query_param("clientcert", &client_cert_pem_str));
 
     let response = client
-        .send_http_request_text_response(http_hostport, "pair", &query_params)
+        .send_http_request_text_response(http_hostport, "pair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -86,6 +89,7 @@ pub struct HostPairResponse2 {
 
 pub async fn host_pair2<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     http_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientPairRequest2<'_>,
@@ -102,7 +106,7 @@ pub async fn host_pair2<C: RequestClient>(
     query_params.push(query_param("clientchallenge", &encrypted_challenge_str));
 
     let response = client
-        .send_http_request_text_response(http_hostport, "pair", &query_params)
+        .send_http_request_text_response(http_hostport, "pair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -132,6 +136,7 @@ pub struct HostPairResponse3 {
 
 pub async fn host_pair3<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     http_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientPairRequest3<'_>,
@@ -148,7 +153,7 @@ pub async fn host_pair3<C: RequestClient>(
     query_params.push(query_param("serverchallengeresp", &encrypted_challenge_str));
 
     let response = client
-        .send_http_request_text_response(http_hostport, "pair", &query_params)
+        .send_http_request_text_response(http_hostport, "pair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -177,6 +182,7 @@ pub struct HostPairResponse4 {
 
 pub async fn host_pair4<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     http_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientPairRequest4<'_>,
@@ -196,7 +202,7 @@ pub async fn host_pair4<C: RequestClient>(
     ));
 
     let response = client
-        .send_http_request_text_response(http_hostport, "pair", &query_params)
+        .send_http_request_text_response(http_hostport, "pair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -219,6 +225,7 @@ pub struct ServerPairResponse5 {
 /// Note: This requires an https client
 pub async fn host_pair5<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     https_hostport: &str,
     info: ClientInfo<'_>,
     request: ClientPairRequest5<'_>,
@@ -233,7 +240,7 @@ pub async fn host_pair5<C: RequestClient>(
     query_params.push(query_param("updateState", "1"));
 
     let response = client
-        .send_https_request_text_response(https_hostport, "pair", &query_params)
+        .send_https_request_text_response(https_hostport, "pair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -247,6 +254,7 @@ pub async fn host_pair5<C: RequestClient>(
 
 pub async fn host_unpair<C: RequestClient>(
     client: &mut C,
+    cancel: &CancellationToken,
     http_hostport: &str,
     info: ClientInfo<'_>,
 ) -> Result<(), ApiError<C::Error>> {
@@ -256,7 +264,7 @@ pub async fn host_unpair<C: RequestClient>(
     info.add_query_params(&mut uuid_bytes, &mut query_params);
 
     client
-        .send_http_request_text_response(http_hostport, "unpair", &query_params)
+        .send_http_request_text_response(http_hostport, "unpair", &query_params, cancel)
         .await
         .map_err(ApiError::RequestClient)?;
 
@@ -1,7 +1,10 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, collections::HashMap, net::IpAddr, ops::Deref};
 
 use pem::Pem;
 
+/// Aborts in-flight requests, e.g. when nobody is waiting for the response anymore
+pub use tokio_util::sync::CancellationToken;
+
 pub(crate) fn empty_query_param<'a>() -> (Cow<'a, str>, Cow<'a, str>) {
     query_param("", "")
 }
@@ -71,6 +74,23 @@ pub trait RequestError {
     fn is_connect(&self) -> bool;
     /// The sunshine encryption is invalid (e.g. the host removed our client -> we're unpaired)
     fn is_encryption(&self) -> bool;
+    /// The request was aborted by its [CancellationToken]
+    fn is_cancelled(&self) -> bool;
+}
+
+/// How a [RequestClient] connects to the host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestClientOptions {
+    /// Connect to the host through this proxy, e.g. `http://10.0.0.1:3128` or `socks5://10.0.0.1:1080`
+    pub proxy: Option<String>,
+    /// Resolves these lower case host names to the address instead of using the system resolver
+    pub dns_overrides: HashMap<String, IpAddr>,
+}
+
+impl RequestClientOptions {
+    pub fn dns_override(&self, host: &str) -> Option<IpAddr> {
+        self.dns_overrides.get(host).copied()
+    }
 }
 
 pub trait RequestClient: Sized {
@@ -79,10 +99,11 @@ pub trait RequestClient: Sized {
     type Text: AsRef<str>;
     type Bytes: AsRef<[u8]>;
 
-    fn with_defaults() -> Result<Self, Self::Error>;
-    fn with_defaults_long_timeout() -> Result<Self, Self::Error>;
+    fn with_defaults(options: &RequestClientOptions) -> Result<Self, Self::Error>;
+    fn with_defaults_long_timeout(options: &RequestClientOptions) -> Result<Self, Self::Error>;
 
     fn with_certificates(
+        options: &RequestClientOptions,
         client_private_key: &Pem,
         client_certificate: &Pem,
         server_certificate: &Pem,
@@ -93,6 +114,7 @@ pub trait RequestClient: Sized {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef,
+        cancel: &CancellationToken,
     ) -> impl std::future::Future<Output = Result<Self::Text, Self::Error>> + Send;
 
     fn send_https_request_text_response(
@@ -100,6 +122,7 @@ pub trait RequestClient: Sized {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef,
+        cancel: &CancellationToken,
     ) -> impl std::future::Future<Output = Result<Self::Text, Self::Error>> + Send;
 
     fn send_https_request_data_response(
@@ -107,5 +130,6 @@ pub trait RequestClient: Sized {
         hostport: &str,
         path: &str,
         query_params: &QueryParamsRef,
+        cancel: &CancellationToken,
     ) -> impl std::future::Future<Output = Result<Self::Bytes, Self::Error>> + Send;
 }
@@ -1,7 +1,10 @@
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use openssl::{
-    asn1::Asn1Time,
+    asn1::{Asn1Time, Asn1TimeRef},
     cipher::Cipher,
     cipher_ctx::CipherCtx,
     error::ErrorStack,
@@ -27,7 +30,7 @@ use crate::{
             ClientPairRequest5, host_pair1, host_pair2, host_pair3, host_pair4, host_pair5,
             host_unpair,
         },
-        request_client::RequestClient,
+        request_client::{CancellationToken, RequestClient, RequestClientOptions},
     },
 };
 
@@ -50,16 +53,16 @@ fn hash_size_uneq(algorithm: HashAlgorithm, data: &[u8], output: &mut [u8]) {
     output.copy_from_slice(&hash[0..output.len()]);
 }
 
-fn salt_pin(salt: [u8; SALT_LENGTH], pin: PairPin) -> [u8; SALT_LENGTH + 4] {
-    let mut out = [0u8; SALT_LENGTH + 4];
-
-    out[0..16].copy_from_slice(&salt);
+fn salt_pin(salt: [u8; SALT_LENGTH], pin: PairPin) -> Vec<u8> {
+    let mut out = Vec::with_capacity(SALT_LENGTH + pin.len());
 
-    let pin_utf8 = pin
-        .array()
-        .map(|value| char::from_digit(value as u32, 10).expect("a pin digit between 0-9") as u8);
+    out.extend_from_slice(&salt);
 
-    out[16..].copy_from_slice(&pin_utf8);
+    out.extend(
+        pin.digits().iter().map(|value| {
+            char::from_digit(*value as u32, 10).expect("a pin digit between 0-9") as u8
+        }),
+    );
 
     out
 }
@@ -137,7 +140,14 @@ pub struct ClientAuth {
     pub certificate: Pem,
 }
 
+/// How long the certificates of [generate_new_client] are valid
+pub const CLIENT_CERTIFICATE_VALIDITY_DAYS: u32 = 365;
+
 pub fn generate_new_client() -> Result<ClientAuth, ErrorStack> {
+    generate_new_client_valid_for(CLIENT_CERTIFICATE_VALIDITY_DAYS)
+}
+
+pub fn generate_new_client_valid_for(validity_days: u32) -> Result<ClientAuth, ErrorStack> {
     let rsa = Rsa::generate(2048)?;
     let key = PKey::from_rsa(rsa)?;
 
@@ -160,7 +170,7 @@ pub fn generate_new_client() -> Result<ClientAuth, ErrorStack> {
     builder.set_issuer_name(&name)?;
     builder.set_pubkey(&key)?;
     builder.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
-    builder.set_not_after(Asn1Time::days_from_now(365)?.as_ref())?;
+    builder.set_not_after(Asn1Time::days_from_now(validity_days)?.as_ref())?;
     builder.sign(&key, MessageDigest::sha256())?;
     let cert = builder.build();
 
@@ -172,6 +182,43 @@ pub fn generate_new_client() -> Result<ClientAuth, ErrorStack> {
     })
 }
 
+/// The time span in which a certificate is valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CertificateValidity {
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+}
+
+impl CertificateValidity {
+    pub fn read(certificate: &Pem) -> Result<Self, ErrorStack> {
+        let certificate = X509::from_der(certificate.contents())?;
+
+        Ok(Self {
+            not_before: asn1_to_system_time(certificate.not_before())?,
+            not_after: asn1_to_system_time(certificate.not_after())?,
+        })
+    }
+
+    /// Zero if the certificate isn't valid yet
+    pub fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.not_before).unwrap_or_default()
+    }
+    /// Zero if the certificate already expired
+    pub fn remaining(&self, now: SystemTime) -> Duration {
+        self.not_after.duration_since(now).unwrap_or_default()
+    }
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now >= self.not_after
+    }
+}
+
+fn asn1_to_system_time(time: &Asn1TimeRef) -> Result<SystemTime, ErrorStack> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    let secs = diff.days as i64 * 24 * 60 * 60 + diff.secs as i64;
+
+    Ok(UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64))
+}
+
 pub struct PairSuccess<C: RequestClient> {
     pub client: C,
     pub server_certificate: Pem,
@@ -198,8 +245,11 @@ pub enum PairError<RequestError> {
     Failed,
 }
 
+/// The paired client is created with the `options` of the given client
 pub async fn host_pair<C: RequestClient>(
     client: &mut C,
+    options: &RequestClientOptions,
+    cancel: &CancellationToken,
     http_address: &str,
     https_address: &str,
     client_info: ClientInfo<'_>,
@@ -227,6 +277,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let server_response1 = host_pair1(
         client,
+        cancel,
         http_address,
         client_info,
         ClientPairRequest1 {
@@ -255,6 +306,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let server_response2 = host_pair2(
         client,
+        cancel,
         http_address,
         client_info,
         ClientPairRequest2 {
@@ -265,7 +317,7 @@ pub async fn host_pair<C: RequestClient>(
     .await?;
 
     if !matches!(server_response2.paired, PairStatus::Paired) {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         return Err(PairError::Failed);
     }
@@ -298,6 +350,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let server_response3 = host_pair3(
         client,
+        cancel,
         http_address,
         client_info,
         ClientPairRequest3 {
@@ -308,7 +361,7 @@ pub async fn host_pair<C: RequestClient>(
     .await?;
 
     if !matches!(server_response3.paired, PairStatus::Paired) {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         return Err(PairError::Failed);
     }
@@ -320,7 +373,7 @@ pub async fn host_pair<C: RequestClient>(
     server_signature.extend_from_slice(&server_response3.server_pairing_secret[16..]);
 
     if !verify_signature(&server_secret, &server_signature, &server_cert)? {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         // MITM likely
         return Err(PairError::Failed);
@@ -340,7 +393,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let expected_response_hash = &expected_response_hash[0..hash_algorithm.hash_len()];
     if expected_response_hash != server_response_hash {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         // Probably wrong pin
         return Err(PairError::IncorrectPin);
@@ -353,6 +406,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let server_response4 = host_pair4(
         client,
+        cancel,
         http_address,
         client_info,
         ClientPairRequest4 {
@@ -363,13 +417,14 @@ pub async fn host_pair<C: RequestClient>(
     .await?;
 
     if !matches!(server_response4.paired, PairStatus::Paired) {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         return Err(PairError::Failed);
     }
 
     // Required for us to show as paired
     let mut new_client = C::with_certificates(
+        options,
         client_private_key_pem,
         client_certificate_pem,
         &server_cert_pem,
@@ -378,6 +433,7 @@ pub async fn host_pair<C: RequestClient>(
 
     let server_response5 = host_pair5(
         &mut new_client,
+        cancel,
         https_address,
         client_info,
         ClientPairRequest5 { device_name },
@@ -385,7 +441,7 @@ pub async fn host_pair<C: RequestClient>(
     .await?;
 
     if !matches!(server_response5.paired, PairStatus::Paired) {
-        host_unpair(client, http_address, client_info).await?;
+        host_unpair(client, cancel, http_address, client_info).await?;
 
         return Err(PairError::Failed);
     }
@@ -395,3 +451,44 @@ pub async fn host_pair<C: RequestClient>(
         server_certificate: server_cert_pem,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        HashAlgorithm, PairPin, PinPolicy, SALT_LENGTH,
+        pair::{generate_aes_key, salt_pin},
+    };
+
+    #[test]
+    fn test_salt_long_pins() {
+        let salt = [7u8; SALT_LENGTH];
+        let digits = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        for length in 5..=8 {
+            let Some(pin) = PairPin::from_digits(&digits[..length], PinPolicy::EXTENDED) else {
+                panic!("the pin with {length} digits is invalid");
+            };
+
+            let salted = salt_pin(salt, pin);
+            assert_eq!(salted.len(), SALT_LENGTH + length);
+            assert_eq!(&salted[..SALT_LENGTH], &salt);
+            assert_eq!(&salted[SALT_LENGTH..], &b"12345678"[..length]);
+        }
+    }
+
+    #[test]
+    fn test_aes_key_uses_every_digit() {
+        let salt = [7u8; SALT_LENGTH];
+        let (Some(short), Some(long)) = (
+            PairPin::from_digits(&[1, 2, 3, 4, 5], PinPolicy::EXTENDED),
+            PairPin::from_digits(&[1, 2, 3, 4, 5, 6], PinPolicy::EXTENDED),
+        ) else {
+            panic!("the pins are invalid");
+        };
+
+        assert_ne!(
+            generate_aes_key(HashAlgorithm::Sha256, salt, short),
+            generate_aes_key(HashAlgorithm::Sha256, salt, long)
+        );
+    }
+}
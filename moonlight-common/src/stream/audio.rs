@@ -5,9 +5,46 @@ use std::{
     sync::Mutex,
 };
 
+#[cfg(feature = "audio_pcm")]
+use log::warn;
 use moonlight_common_sys::limelight::{_AUDIO_RENDERER_CALLBACKS, POPUS_MULTISTREAM_CONFIGURATION};
 
 use crate::stream::bindings::{AudioConfig, Capabilities, OpusMultistreamConfig};
+#[cfg(feature = "audio_pcm")]
+use crate::stream::pcm::{GLOBAL_PCM_DECODER, OpusPcmDecoder};
+
+/// The type of a decoded sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmSampleType {
+    I16,
+    F32,
+}
+
+/// How the samples of the channels are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmLayout {
+    /// The samples of all channels follow each other: L R L R
+    Interleaved,
+    /// Every channel has its own buffer: L L, R R
+    Planar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_type: PcmSampleType,
+    pub layout: PcmLayout,
+}
+
+/// The decoded samples of one opus packet
+#[derive(Debug)]
+pub enum PcmSamples<'a> {
+    InterleavedI16(&'a [i16]),
+    InterleavedF32(&'a [f32]),
+    /// One buffer per channel
+    PlanarI16(&'a [Vec<i16>]),
+    /// One buffer per channel
+    PlanarF32(&'a [Vec<f32>]),
+}
 
 pub trait AudioDecoder {
     /// This callback initializes the audio renderer. The audio configuration parameter
@@ -29,6 +66,21 @@ pub trait AudioDecoder {
     /// This callback provides Opus audio data to be decoded and played. sampleLength is in bytes.
     fn decode_and_play_sample(&mut self, data: &[u8]);
 
+    /// The pcm formats which this decoder can play, the most preferred first.
+    /// If this is empty or the crate is built without the `audio_pcm` feature, the opus packets are passed to [AudioDecoder::decode_and_play_sample].
+    fn supported_pcm_formats(&self) -> Vec<PcmFormat> {
+        Vec::new()
+    }
+
+    /// This callback is called after [AudioDecoder::setup] with the format [AudioDecoder::play_pcm] receives.
+    /// Returns 0 on success, non-zero on failure.
+    fn setup_pcm(&mut self, _format: PcmFormat, _stream_config: &OpusMultistreamConfig) -> i32 {
+        0
+    }
+
+    /// This callback provides the decoded samples of a packet instead of [AudioDecoder::decode_and_play_sample] once a pcm format was set up.
+    fn play_pcm(&mut self, _samples: PcmSamples<'_>) {}
+
     fn config(&self) -> AudioConfig;
     fn capabilities(&self) -> Capabilities {
         Capabilities::empty()
@@ -80,9 +132,42 @@ unsafe extern "C" fn setup(
             mapping: raw_opus_config.mapping,
         };
 
-        decoder.setup(audio_config, opus_config, arFlags)
+        let result = decoder.setup(audio_config, opus_config.clone(), arFlags);
+
+        #[cfg(feature = "audio_pcm")]
+        if result == 0 {
+            return setup_pcm(decoder, &opus_config);
+        }
+
+        result
     })
 }
+
+/// The opus decoder can create every format, so the most preferred one is used
+#[cfg(feature = "audio_pcm")]
+fn setup_pcm(decoder: &mut dyn AudioDecoder, opus_config: &OpusMultistreamConfig) -> c_int {
+    let mut pcm_decoder = GLOBAL_PCM_DECODER.lock().expect("global pcm decoder");
+    *pcm_decoder = None;
+
+    let Some(format) = decoder.supported_pcm_formats().first().copied() else {
+        return 0;
+    };
+
+    let new_pcm_decoder = match OpusPcmDecoder::new(opus_config, format) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("failed to create the opus decoder for the pcm format {format:?}: {err}");
+            return -1;
+        }
+    };
+
+    let result = decoder.setup_pcm(format, opus_config);
+    if result == 0 {
+        *pcm_decoder = Some(new_pcm_decoder);
+    }
+
+    result
+}
 unsafe extern "C" fn start() {
     global_decoder(|decoder| {
         decoder.start();
@@ -91,7 +176,25 @@ unsafe extern "C" fn start() {
 
 unsafe extern "C" fn decode_and_play_sample(data: *mut c_char, len: c_int) {
     global_decoder(|decoder| unsafe {
-        let data = slice::from_raw_parts(data as *mut u8, len as usize);
+        // Lost packets might be signaled without data
+        let data = if data.is_null() || len <= 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(data as *mut u8, len as usize)
+        };
+
+        #[cfg(feature = "audio_pcm")]
+        if let Some(pcm_decoder) = GLOBAL_PCM_DECODER
+            .lock()
+            .expect("global pcm decoder")
+            .as_mut()
+        {
+            match pcm_decoder.decode(data) {
+                Ok(samples) => decoder.play_pcm(samples),
+                Err(err) => warn!("failed to decode audio sample: {err}"),
+            }
+            return;
+        }
 
         decoder.decode_and_play_sample(data);
     })
@@ -104,6 +207,11 @@ unsafe extern "C" fn stop() {
 }
 
 unsafe extern "C" fn cleanup() {
+    #[cfg(feature = "audio_pcm")]
+    {
+        *GLOBAL_PCM_DECODER.lock().expect("global pcm decoder") = None;
+    }
+
     clear_global();
 }
 
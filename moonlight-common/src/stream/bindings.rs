@@ -1,8 +1,4 @@
-use std::{
-    ffi::CStr,
-    fmt::{self, Debug, Display, Formatter},
-    time::Duration,
-};
+use std::{ffi::CStr, fmt::Debug, time::Duration};
 
 use bitflags::bitflags;
 use moonlight_common_sys::limelight::{
@@ -44,6 +40,8 @@ use moonlight_common_sys::limelight::{
 use num_derive::FromPrimitive;
 use thiserror::Error;
 
+use crate::units::{Bytes, Fps, Kbps};
+
 // --------------- Stream ---------------
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -62,15 +60,15 @@ pub struct StreamConfiguration {
     /// Dimensions in pixels of the desired video stream
     pub height: i32,
     /// FPS of the desired video stream
-    pub fps: i32,
+    pub fps: Fps,
     /// Bitrate of the desired video stream (audio adds another ~1 Mbps). This
     /// includes error correction data, so the actual encoder bitrate will be
     /// about 20% lower when using the standard 20% FEC configuration.
-    pub bitrate: i32,
+    pub bitrate: Kbps,
     /// Max video packet size in bytes (use 1024 if unsure). If STREAM_CFG_AUTO
     /// determines the stream is remote (see below), it will cap this value at
     /// 1024 to avoid MTU-related issues like packet loss and fragmentation.
-    pub packet_size: i32,
+    pub packet_size: Bytes,
     /// Determines whether to enable remote (over the Internet)
     /// streaming optimizations. If unsure, set to STREAM_CFG_AUTO.
     /// STREAM_CFG_AUTO uses a heuristic (whether the target address is
@@ -124,7 +122,7 @@ bitflags! {
 }
 
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 pub enum Stage {
     None = STAGE_NONE,
     PlatformInit = STAGE_PLATFORM_INIT,
@@ -168,7 +166,7 @@ bitflags! {
 }
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, FromPrimitive)]
 pub enum TerminationError {
     Graceful = ML_ERROR_GRACEFUL_TERMINATION as i32,
     NoVideoTraffic = ML_ERROR_NO_VIDEO_TRAFFIC,
@@ -180,21 +178,44 @@ pub enum TerminationError {
 
 // --------------- Video ---------------
 
-bitflags! {
-    #[derive(Debug, Clone, Copy)]
-    pub struct ServerCodeModeSupport: u32 {
-        const H264            = SCM_H264;
-        const HEVC            = SCM_HEVC;
-        const HEVC_MAIN10     = SCM_HEVC_MAIN10;
-        const AV1_MAIN8       = SCM_AV1_MAIN8;
-        const AV1_MAIN10      = SCM_AV1_MAIN10;
-        const H264_HIGH8_444  = SCM_H264_HIGH8_444;
-        const HEVC_REXT8_444  = SCM_HEVC_REXT8_444;
-        const HEVC_REXT10_444 = SCM_HEVC_REXT10_444;
-        const AV1_HIGH8_444   = SCM_AV1_HIGH8_444;
-        const AV1_HIGH10_444  = SCM_AV1_HIGH10_444;
-    }
-}
+pub use crate::formats::{ColorRange, Colorspace, ServerCodeModeSupport, SupportedVideoFormats};
+
+// The formats are mirrored without the c library, so make sure they still match it
+const _: () = {
+    assert!(ServerCodeModeSupport::H264.bits() == SCM_H264);
+    assert!(ServerCodeModeSupport::HEVC.bits() == SCM_HEVC);
+    assert!(ServerCodeModeSupport::HEVC_MAIN10.bits() == SCM_HEVC_MAIN10);
+    assert!(ServerCodeModeSupport::AV1_MAIN8.bits() == SCM_AV1_MAIN8);
+    assert!(ServerCodeModeSupport::AV1_MAIN10.bits() == SCM_AV1_MAIN10);
+    assert!(ServerCodeModeSupport::H264_HIGH8_444.bits() == SCM_H264_HIGH8_444);
+    assert!(ServerCodeModeSupport::HEVC_REXT8_444.bits() == SCM_HEVC_REXT8_444);
+    assert!(ServerCodeModeSupport::HEVC_REXT10_444.bits() == SCM_HEVC_REXT10_444);
+    assert!(ServerCodeModeSupport::AV1_HIGH8_444.bits() == SCM_AV1_HIGH8_444);
+    assert!(ServerCodeModeSupport::AV1_HIGH10_444.bits() == SCM_AV1_HIGH10_444);
+
+    assert!(Colorspace::Rec601 as u32 == COLORSPACE_REC_601);
+    assert!(Colorspace::Rec709 as u32 == COLORSPACE_REC_709);
+    assert!(Colorspace::Rec2020 as u32 == COLORSPACE_REC_2020);
+
+    assert!(ColorRange::Limited as u32 == COLOR_RANGE_LIMITED);
+    assert!(ColorRange::Full as u32 == COLOR_RANGE_FULL);
+
+    assert!(SupportedVideoFormats::H264.bits() == VIDEO_FORMAT_H264);
+    assert!(SupportedVideoFormats::H264_HIGH8_444.bits() == VIDEO_FORMAT_H264_HIGH8_444);
+    assert!(SupportedVideoFormats::H265.bits() == VIDEO_FORMAT_H265);
+    assert!(SupportedVideoFormats::H265_MAIN10.bits() == VIDEO_FORMAT_H265_MAIN10);
+    assert!(SupportedVideoFormats::H265_REXT8_444.bits() == VIDEO_FORMAT_H265_REXT8_444);
+    assert!(SupportedVideoFormats::H265_REXT10_444.bits() == VIDEO_FORMAT_H265_REXT10_444);
+    assert!(SupportedVideoFormats::AV1_MAIN8.bits() == VIDEO_FORMAT_AV1_MAIN8);
+    assert!(SupportedVideoFormats::AV1_MAIN10.bits() == VIDEO_FORMAT_AV1_MAIN10);
+    assert!(SupportedVideoFormats::AV1_HIGH8_444.bits() == VIDEO_FORMAT_AV1_HIGH8_444);
+    assert!(SupportedVideoFormats::AV1_HIGH10_444.bits() == VIDEO_FORMAT_AV1_HIGH10_444);
+    assert!(SupportedVideoFormats::MASK_H264.bits() == VIDEO_FORMAT_MASK_H264);
+    assert!(SupportedVideoFormats::MASK_H265.bits() == VIDEO_FORMAT_MASK_H265);
+    assert!(SupportedVideoFormats::MASK_AV1.bits() == VIDEO_FORMAT_MASK_AV1);
+    assert!(SupportedVideoFormats::MASK_10BIT.bits() == VIDEO_FORMAT_MASK_10BIT);
+    assert!(SupportedVideoFormats::MASK_YUV444.bits() == VIDEO_FORMAT_MASK_YUV444);
+};
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, FromPrimitive)]
@@ -204,66 +225,6 @@ pub enum StreamingConfig {
     Auto = STREAM_CFG_AUTO,
 }
 
-#[repr(u32)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone, Copy, FromPrimitive)]
-pub enum Colorspace {
-    Rec601 = COLORSPACE_REC_601,
-    Rec709 = COLORSPACE_REC_709,
-    Rec2020 = COLORSPACE_REC_2020,
-}
-
-#[repr(u32)]
-#[derive(Debug, Clone, Copy, FromPrimitive)]
-pub enum ColorRange {
-    Limited = COLOR_RANGE_LIMITED,
-    Full = COLOR_RANGE_FULL,
-}
-
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone, Copy, Default)]
-pub struct SupportedVideoFormats(u32);
-
-bitflags! {
-    impl SupportedVideoFormats: u32 {
-        const H264 = VIDEO_FORMAT_H264;          // H.264 High Profile
-        const H264_HIGH8_444 = VIDEO_FORMAT_H264_HIGH8_444;   // H.264 High 4:4:4 8-bit Profile
-        const H265 = VIDEO_FORMAT_H265;                       // HEVC Main Profile
-        const H265_MAIN10 = VIDEO_FORMAT_H265_MAIN10;         // HEVC Main10 Profile
-        const H265_REXT8_444 = VIDEO_FORMAT_H265_REXT8_444;   // HEVC RExt 4:4:4 8-bit Profile
-        const H265_REXT10_444 = VIDEO_FORMAT_H265_REXT10_444; // HEVC RExt 4:4:4 10-bit Profile
-        const AV1_MAIN8 = VIDEO_FORMAT_AV1_MAIN8;             // AV1 Main 8-bit profile
-        const AV1_MAIN10 = VIDEO_FORMAT_AV1_MAIN10;           // AV1 Main 10-bit profile
-        const AV1_HIGH8_444 = VIDEO_FORMAT_AV1_HIGH8_444;     // AV1 High 4:4:4 8-bit profile
-        const AV1_HIGH10_444 = VIDEO_FORMAT_AV1_HIGH10_444;   // AV1 High 4:4:4 10-bit profile
-
-        // Preconfigured
-        const MASK_H264 = VIDEO_FORMAT_MASK_H264;
-        const MASK_H265 = VIDEO_FORMAT_MASK_H265;
-        const MASK_AV1 = VIDEO_FORMAT_MASK_AV1;
-        const MASK_10BIT = VIDEO_FORMAT_MASK_10BIT;
-        const MASK_YUV444 = VIDEO_FORMAT_MASK_YUV444;
-    }
-}
-
-impl Display for SupportedVideoFormats {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "[")?;
-
-        let mut first = true;
-        for (name, _) in self.iter_names() {
-            if !first {
-                write!(f, ",")?;
-            }
-            write!(f, "{}", name)?;
-
-            first = false;
-        }
-        write!(f, "]")?;
-        Ok(())
-    }
-}
-
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, FromPrimitive)]
 pub enum VideoFormat {
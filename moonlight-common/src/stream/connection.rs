@@ -1,13 +1,17 @@
 use std::{
+    ffi::CStr,
     os::raw::{c_char, c_int, c_uchar, c_ushort},
     sync::Mutex,
+    time::{Duration, Instant},
 };
 
-use moonlight_common_sys::limelight::_CONNECTION_LISTENER_CALLBACKS;
+use moonlight_common_sys::{
+    limelight::_CONNECTION_LISTENER_CALLBACKS,
+    shim::{MoonlightLogMessage, MoonlightSetLogCallback},
+};
 use num::FromPrimitive;
-use printf_compat::{format, output};
 
-use crate::stream::bindings::{ConnectionStatus, Stage};
+use crate::stream::bindings::{ConnectionStatus, Stage, TerminationError};
 
 pub trait ConnectionListener {
     /// This callback is invoked to indicate that a stage of initialization is about to begin
@@ -21,6 +25,10 @@ pub trait ConnectionListener {
     /// result in this callback being invoked, but it is not guaranteed.
     fn stage_failed(&mut self, stage: Stage, error_code: i32);
 
+    /// This callback is invoked after a stage of initialization has completed or failed
+    /// with how long it took, see [StageTimer]
+    fn stage_timed(&mut self, _timing: StageTiming) {}
+
     /// This callback is invoked after the connection is successfully established
     fn connection_started(&mut self);
 
@@ -30,8 +38,7 @@ pub trait ConnectionListener {
     /// non-zero, it means the termination was probably unexpected (loss of network,
     /// crash, or similar conditions). This will not be invoked as a result of a call
     /// to LiStopConnection() or LiInterruptConnection().
-    /// HINT: Use TerminationError
-    fn connection_terminated(&mut self, error_code: i32);
+    fn connection_terminated(&mut self, reason: TerminationReason);
 
     /// This callback is invoked to log debug message
     fn log_message(&mut self, message: &str);
@@ -96,6 +103,164 @@ pub trait ConnectionListener {
     fn controller_set_led(&mut self, controller_number: u16, r: u8, g: u8, b: u8);
 }
 
+/// Why an established connection was terminated, see [ConnectionListener::connection_terminated]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// The host ended the stream intentionally, e.g. the user closed the game
+    Graceful,
+    NoVideoTraffic,
+    NoVideoFrame,
+    UnexpectedEarlyTermination,
+    ProtectedContent,
+    FrameConversion,
+    /// An error code which isn't known to this library
+    Other(i32),
+}
+
+impl TerminationReason {
+    pub fn from_code(error_code: i32) -> Self {
+        match TerminationError::from_i32(error_code) {
+            Some(error) => error.into(),
+            None => Self::Other(error_code),
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Graceful => TerminationError::Graceful as i32,
+            Self::NoVideoTraffic => TerminationError::NoVideoTraffic as i32,
+            Self::NoVideoFrame => TerminationError::NoVideoFrame as i32,
+            Self::UnexpectedEarlyTermination => TerminationError::UnexpectedEarlyTermination as i32,
+            Self::ProtectedContent => TerminationError::ProtectedContent as i32,
+            Self::FrameConversion => TerminationError::FrameConversion as i32,
+            Self::Other(error_code) => *error_code,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::Graceful)
+    }
+
+    /// A stable identifier which can be used by clients and in logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Graceful => "graceful",
+            Self::NoVideoTraffic => "no_video_traffic",
+            Self::NoVideoFrame => "no_video_frame",
+            Self::UnexpectedEarlyTermination => "unexpected_early_termination",
+            Self::ProtectedContent => "protected_content",
+            Self::FrameConversion => "frame_conversion",
+            Self::Other(_) => "other",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Graceful => "The host ended the stream",
+            Self::NoVideoTraffic => {
+                "No video was received from the host. Check the firewall and port forwarding rules of the host"
+            }
+            Self::NoVideoFrame => {
+                "The network connection isn't performing well. Reduce the bitrate or try a faster connection"
+            }
+            Self::UnexpectedEarlyTermination => {
+                "Something went wrong on the host while starting the stream"
+            }
+            Self::ProtectedContent => {
+                "The host is showing protected content which can't be streamed"
+            }
+            Self::FrameConversion => "The host failed to encode the video",
+            Self::Other(_) => "The connection to the host was terminated unexpectedly",
+        }
+    }
+}
+
+impl From<TerminationError> for TerminationReason {
+    fn from(value: TerminationError) -> Self {
+        match value {
+            TerminationError::Graceful => Self::Graceful,
+            TerminationError::NoVideoTraffic => Self::NoVideoTraffic,
+            TerminationError::NoVideoFrame => Self::NoVideoFrame,
+            TerminationError::UnexpectedEarlyTermination => Self::UnexpectedEarlyTermination,
+            TerminationError::ProtectedContent => Self::ProtectedContent,
+            TerminationError::FrameConversion => Self::FrameConversion,
+        }
+    }
+}
+
+/// How long a [Stage] of initialization took
+#[derive(Debug, Clone, Copy)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub duration: Duration,
+    /// The error code of [ConnectionListener::stage_failed], none if the stage completed
+    pub error_code: Option<i32>,
+}
+
+/// Records the durations of the initialization stages.
+/// The stages of a connection are timed by this library, see [MoonlightStream::stage_timings](crate::stream::MoonlightStream::stage_timings).
+#[derive(Debug, Default)]
+pub struct StageTimer {
+    current: Option<(Stage, Instant)>,
+    timings: Vec<StageTiming>,
+}
+
+impl StageTimer {
+    pub const fn new() -> Self {
+        Self {
+            current: None,
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn stage_starting(&mut self, stage: Stage) {
+        self.current = Some((stage, Instant::now()));
+    }
+    pub fn stage_complete(&mut self, stage: Stage) -> Option<StageTiming> {
+        self.finish(stage, None)
+    }
+    pub fn stage_failed(&mut self, stage: Stage, error_code: i32) -> Option<StageTiming> {
+        self.finish(stage, Some(error_code))
+    }
+
+    /// None if the stage wasn't started
+    fn finish(&mut self, stage: Stage, error_code: Option<i32>) -> Option<StageTiming> {
+        let (current, started) = self.current.take_if(|(current, _)| *current == stage)?;
+
+        let timing = StageTiming {
+            stage: current,
+            duration: started.elapsed(),
+            error_code,
+        };
+        self.timings.push(timing);
+
+        Some(timing)
+    }
+
+    /// The finished stages in the order they ran
+    pub fn timings(&self) -> &[StageTiming] {
+        &self.timings
+    }
+
+    /// The time spent in all finished stages
+    pub fn total(&self) -> Duration {
+        self.timings.iter().map(|timing| timing.duration).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.timings.clear();
+    }
+}
+
+static STAGE_TIMER: Mutex<StageTimer> = Mutex::new(StageTimer::new());
+
+pub(crate) fn stage_timings() -> Vec<StageTiming> {
+    let timer = STAGE_TIMER.lock().expect("stage timer");
+
+    timer.timings().to_vec()
+}
+
 static GLOBAL_CONNECTION_LISTENER: Mutex<Option<Box<dyn ConnectionListener + Send + 'static>>> =
     Mutex::new(None);
 
@@ -113,6 +278,8 @@ pub(crate) fn set_global(listener: impl ConnectionListener + Send + 'static) {
         .expect("global connection lock");
 
     *global_listener = Some(Box::new(listener));
+
+    STAGE_TIMER.lock().expect("stage timer").clear();
 }
 pub(crate) fn clear_global() {
     let mut decoder = GLOBAL_CONNECTION_LISTENER
@@ -123,18 +290,42 @@ pub(crate) fn clear_global() {
 }
 
 unsafe extern "C" fn stage_starting(stage: c_int) {
+    let stage = Stage::from_i32(stage).expect("valid stage");
+    STAGE_TIMER
+        .lock()
+        .expect("stage timer")
+        .stage_starting(stage);
+
     global_listener(|listener| {
-        listener.stage_starting(Stage::from_i32(stage).expect("valid stage"));
+        listener.stage_starting(stage);
     });
 }
 unsafe extern "C" fn stage_complete(stage: c_int) {
+    let stage = Stage::from_i32(stage).expect("valid stage");
+    let timing = STAGE_TIMER
+        .lock()
+        .expect("stage timer")
+        .stage_complete(stage);
+
     global_listener(|listener| {
-        listener.stage_complete(Stage::from_i32(stage).expect("valid stage"));
+        listener.stage_complete(stage);
+        if let Some(timing) = timing {
+            listener.stage_timed(timing);
+        }
     });
 }
 unsafe extern "C" fn stage_failed(stage: c_int, error_code: c_int) {
+    let stage = Stage::from_i32(stage).expect("valid stage");
+    let timing = STAGE_TIMER
+        .lock()
+        .expect("stage timer")
+        .stage_failed(stage, error_code);
+
     global_listener(|listener| {
-        listener.stage_failed(Stage::from_i32(stage).expect("valid stage"), error_code);
+        listener.stage_failed(stage, error_code);
+        if let Some(timing) = timing {
+            listener.stage_timed(timing);
+        }
     });
 }
 unsafe extern "C" fn connection_started() {
@@ -144,7 +335,7 @@ unsafe extern "C" fn connection_started() {
 }
 unsafe extern "C" fn connection_terminated(error_code: c_int) {
     global_listener(|listener| {
-        listener.connection_terminated(error_code);
+        listener.connection_terminated(TerminationReason::from_code(error_code));
     });
 }
 unsafe extern "C" fn connection_status_update(status: c_int) {
@@ -155,10 +346,10 @@ unsafe extern "C" fn connection_status_update(status: c_int) {
     });
 }
 
-unsafe extern "C" fn log_message(message: *const c_char, args: ...) {
-    global_listener(|listener| unsafe {
-        let mut text = String::new();
-        format(message, args, output::fmt_write(&mut text));
+/// Called by the C shim with the already formatted message
+unsafe extern "C" fn log_message(message: *const c_char) {
+    global_listener(|listener| {
+        let text = unsafe { CStr::from_ptr(message) }.to_string_lossy();
 
         listener.log_message(&text);
     });
@@ -229,13 +420,17 @@ unsafe extern "C" fn controller_set_adaptive_triggers(
 }
 
 pub(crate) unsafe fn raw_callbacks() -> _CONNECTION_LISTENER_CALLBACKS {
+    unsafe {
+        MoonlightSetLogCallback(Some(log_message));
+    }
+
     _CONNECTION_LISTENER_CALLBACKS {
         stageStarting: Some(stage_starting),
         stageComplete: Some(stage_complete),
         stageFailed: Some(stage_failed),
         connectionStarted: Some(connection_started),
         connectionTerminated: Some(connection_terminated),
-        logMessage: Some(log_message),
+        logMessage: Some(MoonlightLogMessage),
         rumble: Some(controller_rumble),
         connectionStatusUpdate: Some(connection_status_update),
         setHdrMode: Some(set_hdr_mode),
@@ -6,7 +6,7 @@ use crate::stream::{
         AudioConfig, Capabilities, ConnectionStatus, DecodeResult, OpusMultistreamConfig, Stage,
         SupportedVideoFormats, VideoDecodeUnit,
     },
-    connection::ConnectionListener,
+    connection::{ConnectionListener, TerminationReason},
     video::{VideoDecoder, VideoSetup},
 };
 
@@ -80,8 +80,8 @@ impl ConnectionListener for NullHandler {
     fn connection_status_update(&mut self, status: ConnectionStatus) {
         let _ = status;
     }
-    fn connection_terminated(&mut self, error_code: i32) {
-        let _ = error_code;
+    fn connection_terminated(&mut self, reason: TerminationReason) {
+        let _ = reason;
     }
 
     fn log_message(&mut self, message: &str) {
@@ -158,8 +158,8 @@ impl ConnectionListener for DebugHandler {
     fn connection_status_update(&mut self, status: ConnectionStatus) {
         info!(target: "moonlight", "Connection Status Update: {status:?}");
     }
-    fn connection_terminated(&mut self, error_code: i32) {
-        info!(target: "moonlight","Connection Terminated: {error_code}");
+    fn connection_terminated(&mut self, reason: TerminationReason) {
+        info!(target: "moonlight", "Connection Terminated: {reason:?} ({})", reason.code());
     }
 
     fn log_message(&mut self, message: &str) {
@@ -10,14 +10,14 @@ use std::{
 use moonlight_common_sys::limelight::{
     _SERVER_INFORMATION, _STREAM_CONFIGURATION, LI_BATTERY_PERCENTAGE_UNKNOWN, LI_ERR_UNSUPPORTED,
     LI_ROT_UNKNOWN, LiGetEstimatedRttInfo, LiGetHostFeatureFlags, LiGetLaunchUrlQueryParameters,
-    LiInterruptConnection, LiSendControllerArrivalEvent, LiSendControllerBatteryEvent,
-    LiSendControllerEvent, LiSendControllerMotionEvent, LiSendControllerTouchEvent,
-    LiSendHScrollEvent, LiSendHighResHScrollEvent, LiSendHighResScrollEvent, LiSendKeyboardEvent,
-    LiSendKeyboardEvent2, LiSendMouseButtonEvent, LiSendMouseMoveAsMousePositionEvent,
-    LiSendMouseMoveEvent, LiSendMousePositionEvent, LiSendMultiControllerEvent, LiSendScrollEvent,
-    LiSendTouchEvent, LiSendUtf8TextEvent, LiStartConnection, LiStopConnection,
-    PAUDIO_RENDERER_CALLBACKS, PCONNECTION_LISTENER_CALLBACKS, PDECODER_RENDERER_CALLBACKS,
-    PSERVER_INFORMATION, PSTREAM_CONFIGURATION,
+    LiInterruptConnection, LiRequestIdrFrame, LiSendControllerArrivalEvent,
+    LiSendControllerBatteryEvent, LiSendControllerEvent, LiSendControllerMotionEvent,
+    LiSendControllerTouchEvent, LiSendHScrollEvent, LiSendHighResHScrollEvent,
+    LiSendHighResScrollEvent, LiSendKeyboardEvent, LiSendKeyboardEvent2, LiSendMouseButtonEvent,
+    LiSendMouseMoveAsMousePositionEvent, LiSendMouseMoveEvent, LiSendMousePositionEvent,
+    LiSendMultiControllerEvent, LiSendScrollEvent, LiSendTouchEvent, LiSendUtf8TextEvent,
+    LiStartConnection, LiStopConnection, PAUDIO_RENDERER_CALLBACKS, PCONNECTION_LISTENER_CALLBACKS,
+    PDECODER_RENDERER_CALLBACKS, PSERVER_INFORMATION, PSTREAM_CONFIGURATION,
 };
 
 use crate::{
@@ -30,7 +30,7 @@ use crate::{
             MotionType, MouseButton, MouseButtonAction, ServerCodeModeSupport, StreamConfiguration,
             TouchEventType,
         },
-        connection::ConnectionListener,
+        connection::{ConnectionListener, StageTiming},
         video::VideoDecoder,
     },
 };
@@ -39,6 +39,8 @@ pub mod audio;
 pub mod bindings;
 pub mod connection;
 pub mod debug;
+#[cfg(feature = "audio_pcm")]
+mod pcm;
 pub mod video;
 
 static INSTANCE: LazyLock<Arc<Handle>> = LazyLock::new(|| {
@@ -163,9 +165,9 @@ impl MoonlightStream {
             let mut stream_config = _STREAM_CONFIGURATION {
                 width: stream_config.width,
                 height: stream_config.height,
-                fps: stream_config.fps,
-                bitrate: stream_config.bitrate,
-                packetSize: stream_config.packet_size,
+                fps: stream_config.fps.get() as i32,
+                bitrate: stream_config.bitrate.get() as i32,
+                packetSize: stream_config.packet_size.get() as i32,
                 streamingRemotely: stream_config.streaming_remotely as u32 as i32,
                 audioConfiguration: stream_config.audio_configuration,
                 supportedVideoFormats: stream_config.supported_video_formats.bits() as i32,
@@ -729,6 +731,24 @@ impl MoonlightStream {
         Ok(())
     }
 
+    /// How long each stage of establishing this connection took, in the order they ran.
+    pub fn stage_timings(&self) -> Vec<StageTiming> {
+        connection::stage_timings()
+    }
+
+    /// Requests an IDR frame from the host, e.g. when the video decoder lost frames.
+    pub fn request_idr_frame(&self) -> Result<(), MoonlightError> {
+        if !self.is_connected() {
+            return Err(MoonlightError::ConnectionFailed);
+        }
+
+        unsafe {
+            LiRequestIdrFrame();
+        }
+
+        Ok(())
+    }
+
     pub fn stop(self) {
         drop(self);
     }
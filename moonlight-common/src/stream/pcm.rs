@@ -0,0 +1,167 @@
+//! Decodes the opus packets of the host for [AudioDecoder](crate::stream::audio::AudioDecoder)s which play pcm samples.
+
+use std::{
+    os::raw::c_int,
+    ptr::{NonNull, null},
+    sync::Mutex,
+};
+
+use audiopus_sys::{
+    OpusMSDecoder, opus_multistream_decode, opus_multistream_decode_float,
+    opus_multistream_decoder_create, opus_multistream_decoder_destroy,
+};
+use thiserror::Error;
+
+use crate::stream::{
+    audio::{PcmFormat, PcmLayout, PcmSampleType, PcmSamples},
+    bindings::OpusMultistreamConfig,
+};
+
+/// Opus frames are at most 120ms long
+const MAX_FRAME_MS: usize = 120;
+const OPUS_OK: c_int = 0;
+
+#[derive(Debug, Error)]
+#[error("opus failed with error code {0}")]
+pub(crate) struct OpusError(c_int);
+
+pub(crate) static GLOBAL_PCM_DECODER: Mutex<Option<OpusPcmDecoder>> = Mutex::new(None);
+
+pub(crate) struct OpusPcmDecoder {
+    decoder: NonNull<OpusMSDecoder>,
+    format: PcmFormat,
+    channel_count: usize,
+    max_samples_per_channel: usize,
+    interleaved_i16: Vec<i16>,
+    interleaved_f32: Vec<f32>,
+    planar_i16: Vec<Vec<i16>>,
+    planar_f32: Vec<Vec<f32>>,
+}
+
+// The decoder has no thread local state and is only used behind a mutex
+unsafe impl Send for OpusPcmDecoder {}
+
+impl OpusPcmDecoder {
+    pub fn new(config: &OpusMultistreamConfig, format: PcmFormat) -> Result<Self, OpusError> {
+        let mut error = OPUS_OK;
+        let decoder = unsafe {
+            opus_multistream_decoder_create(
+                config.sample_rate as i32,
+                config.channel_count as c_int,
+                config.streams as c_int,
+                config.coupled_streams as c_int,
+                config.mapping.as_ptr(),
+                &mut error,
+            )
+        };
+
+        let Some(decoder) = NonNull::new(decoder) else {
+            return Err(OpusError(error));
+        };
+        if error != OPUS_OK {
+            unsafe { opus_multistream_decoder_destroy(decoder.as_ptr()) };
+            return Err(OpusError(error));
+        }
+
+        let channel_count = config.channel_count as usize;
+
+        Ok(Self {
+            decoder,
+            format,
+            channel_count,
+            max_samples_per_channel: config.sample_rate as usize * MAX_FRAME_MS / 1000,
+            interleaved_i16: Vec::new(),
+            interleaved_f32: Vec::new(),
+            planar_i16: vec![Vec::new(); channel_count],
+            planar_f32: vec![Vec::new(); channel_count],
+        })
+    }
+
+    /// An empty packet lets opus conceal a lost packet
+    pub fn decode(&mut self, data: &[u8]) -> Result<PcmSamples<'_>, OpusError> {
+        let (data_ptr, data_len) = if data.is_empty() {
+            (null(), 0)
+        } else {
+            (data.as_ptr(), data.len() as i32)
+        };
+        let buffer_len = self.max_samples_per_channel * self.channel_count;
+
+        match self.format.sample_type {
+            PcmSampleType::I16 => {
+                self.interleaved_i16.resize(buffer_len, 0);
+
+                let samples = unsafe {
+                    opus_multistream_decode(
+                        self.decoder.as_ptr(),
+                        data_ptr,
+                        data_len,
+                        self.interleaved_i16.as_mut_ptr(),
+                        self.max_samples_per_channel as c_int,
+                        0,
+                    )
+                };
+                if samples < 0 {
+                    return Err(OpusError(samples));
+                }
+                self.interleaved_i16
+                    .truncate(samples as usize * self.channel_count);
+
+                Ok(match self.format.layout {
+                    PcmLayout::Interleaved => PcmSamples::InterleavedI16(&self.interleaved_i16),
+                    PcmLayout::Planar => {
+                        deinterleave(&self.interleaved_i16, &mut self.planar_i16);
+                        PcmSamples::PlanarI16(&self.planar_i16)
+                    }
+                })
+            }
+            PcmSampleType::F32 => {
+                self.interleaved_f32.resize(buffer_len, 0.0);
+
+                let samples = unsafe {
+                    opus_multistream_decode_float(
+                        self.decoder.as_ptr(),
+                        data_ptr,
+                        data_len,
+                        self.interleaved_f32.as_mut_ptr(),
+                        self.max_samples_per_channel as c_int,
+                        0,
+                    )
+                };
+                if samples < 0 {
+                    return Err(OpusError(samples));
+                }
+                self.interleaved_f32
+                    .truncate(samples as usize * self.channel_count);
+
+                Ok(match self.format.layout {
+                    PcmLayout::Interleaved => PcmSamples::InterleavedF32(&self.interleaved_f32),
+                    PcmLayout::Planar => {
+                        deinterleave(&self.interleaved_f32, &mut self.planar_f32);
+                        PcmSamples::PlanarF32(&self.planar_f32)
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl Drop for OpusPcmDecoder {
+    fn drop(&mut self) {
+        unsafe { opus_multistream_decoder_destroy(self.decoder.as_ptr()) };
+    }
+}
+
+fn deinterleave<T: Copy>(interleaved: &[T], planar: &mut [Vec<T>]) {
+    let channel_count = planar.len();
+
+    for (channel, buffer) in planar.iter_mut().enumerate() {
+        buffer.clear();
+        buffer.extend(
+            interleaved
+                .iter()
+                .skip(channel)
+                .step_by(channel_count)
+                .copied(),
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//!
+//! Units of the stream settings, so a bitrate in kbps can't be passed where bps are expected
+//!
+
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+/// A bitrate in kilobits per second, the unit of moonlight-common-c
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Kbps(pub u32);
+
+impl Kbps {
+    pub const fn from_mbps(mbps: u32) -> Self {
+        Self(mbps.saturating_mul(1000))
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+    pub const fn bps(self) -> u64 {
+        self.0 as u64 * 1000
+    }
+}
+
+impl Display for Kbps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} kbps", self.0)
+    }
+}
+
+/// A size in bytes, e.g. of a video packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Bytes(pub u32);
+
+impl Bytes {
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.0)
+    }
+}
+
+/// Frames per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fps(pub u32);
+
+impl Fps {
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+    /// The refresh rate in hundredths of a hertz
+    pub const fn x100(self) -> u32 {
+        self.0.saturating_mul(100)
+    }
+}
+
+impl Display for Fps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} fps", self.0)
+    }
+}
+
+impl From<u32> for Kbps {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+impl From<u32> for Bytes {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+impl From<u32> for Fps {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ParseUnitError {
+    #[error("the value is not a number")]
+    InvalidNumber,
+    #[error("the unit \"{0}\" is unknown")]
+    UnknownUnit(String),
+    #[error("the value doesn't fit into 32 bits")]
+    OutOfRange,
+}
+
+/// Parses a number with an optional unit, the factor converts the unit into the base unit.
+/// A number without unit is already in the base unit.
+fn parse_with_unit(text: &str, units: &[(&str, f64)]) -> Result<u32, ParseUnitError> {
+    let text = text.trim();
+
+    let number_end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(number_end);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| ParseUnitError::InvalidNumber)?;
+
+    let unit = unit.trim();
+    let factor = if unit.is_empty() {
+        1.0
+    } else {
+        units
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, factor)| *factor)
+            .ok_or_else(|| ParseUnitError::UnknownUnit(unit.to_string()))?
+    };
+
+    let value = (number * factor).round();
+    if !(0.0..=u32::MAX as f64).contains(&value) {
+        return Err(ParseUnitError::OutOfRange);
+    }
+
+    Ok(value as u32)
+}
+
+impl FromStr for Kbps {
+    type Err = ParseUnitError;
+
+    /// e.g. `20000`, `20000 kbps` or `20 Mbps`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_with_unit(
+            s,
+            &[
+                ("bps", 0.001),
+                ("kbps", 1.0),
+                ("mbps", 1000.0),
+                ("gbps", 1_000_000.0),
+            ],
+        )
+        .map(Self)
+    }
+}
+
+impl FromStr for Bytes {
+    type Err = ParseUnitError;
+
+    /// e.g. `1392`, `1392 B` or `1 KiB`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_with_unit(
+            s,
+            &[("b", 1.0), ("bytes", 1.0), ("kb", 1000.0), ("kib", 1024.0)],
+        )
+        .map(Self)
+    }
+}
+
+impl FromStr for Fps {
+    type Err = ParseUnitError;
+
+    /// e.g. `60` or `60 fps`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_with_unit(s, &[("fps", 1.0), ("hz", 1.0)]).map(Self)
+    }
+}
+
+/// The units serialize as plain numbers in their base unit.
+/// Deserializing also accepts strings with a unit, so configs can't be off by a factor of 1000 unnoticed.
+#[cfg(feature = "serde")]
+mod serde {
+    use std::{fmt, marker::PhantomData, str::FromStr};
+
+    use serde::de::{self, Unexpected, Visitor};
+
+    use crate::units::{Bytes, Fps, Kbps};
+
+    struct UnitVisitor<T> {
+        expecting: &'static str,
+        unit: PhantomData<T>,
+    }
+
+    impl<'a, T> Visitor<'a> for UnitVisitor<T>
+    where
+        T: FromStr<Err: fmt::Display> + From<u32>,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str(self.expecting)
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            u32::try_from(v)
+                .map(T::from)
+                .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            u32::try_from(v)
+                .map(T::from)
+                .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            T::from_str(v).map_err(E::custom)
+        }
+    }
+
+    macro_rules! unit_serde {
+        ($unit:ident, $expecting:literal) => {
+            impl serde::Serialize for $unit {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer,
+                {
+                    serializer.serialize_u32(self.0)
+                }
+            }
+
+            impl<'a> serde::Deserialize<'a> for $unit {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: serde::Deserializer<'a>,
+                {
+                    deserializer.deserialize_any(UnitVisitor {
+                        expecting: $expecting,
+                        unit: PhantomData,
+                    })
+                }
+            }
+        };
+    }
+
+    unit_serde!(Kbps, "a bitrate in kbps or a string like \"20 Mbps\"");
+    unit_serde!(Bytes, "a size in bytes or a string like \"1 KiB\"");
+    unit_serde!(Fps, "frames per second or a string like \"60 fps\"");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::units::{Bytes, Fps, Kbps, ParseUnitError, parse_with_unit};
+
+    #[test]
+    fn test_parse_with_unit() {
+        let units = [("k", 1000.0), ("half", 0.5)];
+
+        assert_eq!(parse_with_unit("42", &units), Ok(42));
+        assert_eq!(parse_with_unit(" 2 k ", &units), Ok(2000));
+        assert_eq!(parse_with_unit("2K", &units), Ok(2000));
+        assert_eq!(parse_with_unit("1.5k", &units), Ok(1500));
+        // Rounded to the nearest base unit
+        assert_eq!(parse_with_unit("3 half", &units), Ok(2));
+        assert_eq!(parse_with_unit("0.4", &units), Ok(0));
+    }
+
+    #[test]
+    fn test_parse_with_unit_errors() {
+        let units = [("k", 1000.0)];
+
+        assert_eq!(
+            parse_with_unit("", &units),
+            Err(ParseUnitError::InvalidNumber)
+        );
+        assert_eq!(
+            parse_with_unit("fast", &units),
+            Err(ParseUnitError::InvalidNumber)
+        );
+        assert_eq!(
+            parse_with_unit("-5", &units),
+            Err(ParseUnitError::InvalidNumber)
+        );
+        assert_eq!(
+            parse_with_unit("5 furlongs", &units),
+            Err(ParseUnitError::UnknownUnit("furlongs".to_string()))
+        );
+        assert_eq!(parse_with_unit("4294967295", &units), Ok(u32::MAX));
+        assert_eq!(
+            parse_with_unit("4294967296", &units),
+            Err(ParseUnitError::OutOfRange)
+        );
+        assert_eq!(
+            parse_with_unit("5000000 k", &units),
+            Err(ParseUnitError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_parse_kbps() {
+        assert_eq!("20 Mbps".parse(), Ok(Kbps(20000)));
+        assert_eq!("20mbps".parse(), Ok(Kbps(20000)));
+        assert_eq!("20000".parse(), Ok(Kbps(20000)));
+        assert_eq!("20000 KBPS".parse(), Ok(Kbps(20000)));
+        assert_eq!("1 Gbps".parse(), Ok(Kbps(1_000_000)));
+        // Bits per second are rounded to kilobits
+        assert_eq!("1500 bps".parse(), Ok(Kbps(2)));
+        assert_eq!("499 bps".parse(), Ok(Kbps(0)));
+    }
+
+    #[test]
+    fn test_parse_bytes_and_fps() {
+        assert_eq!("1392".parse(), Ok(Bytes(1392)));
+        assert_eq!("1392 B".parse(), Ok(Bytes(1392)));
+        assert_eq!("1 KiB".parse(), Ok(Bytes(1024)));
+        assert_eq!("1 kb".parse(), Ok(Bytes(1000)));
+
+        assert_eq!("60".parse(), Ok(Fps(60)));
+        assert_eq!("120 fps".parse(), Ok(Fps(120)));
+        assert_eq!("144 Hz".parse(), Ok(Fps(144)));
+        assert_eq!(
+            "60 Mbps".parse::<Fps>(),
+            Err(ParseUnitError::UnknownUnit("Mbps".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_unit_serde() -> Result<(), serde::de::value::Error> {
+        use serde::{Deserialize, de::IntoDeserializer};
+
+        type Error = serde::de::value::Error;
+
+        assert_eq!(
+            Kbps::deserialize(IntoDeserializer::<Error>::into_deserializer("20 Mbps"))?,
+            Kbps(20000)
+        );
+        assert_eq!(
+            Kbps::deserialize(IntoDeserializer::<Error>::into_deserializer(20000u64))?,
+            Kbps(20000)
+        );
+        assert_eq!(
+            Fps::deserialize(IntoDeserializer::<Error>::into_deserializer(60i64))?,
+            Fps(60)
+        );
+
+        assert!(Kbps::deserialize(IntoDeserializer::<Error>::into_deserializer(-1i64)).is_err());
+        assert!(
+            Kbps::deserialize(IntoDeserializer::<Error>::into_deserializer(
+                5_000_000_000u64
+            ))
+            .is_err()
+        );
+        assert!(
+            Bytes::deserialize(IntoDeserializer::<Error>::into_deserializer("1 lightyear"))
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::ptr::copy_nonoverlapping;
+
+use ffmpeg_next::{Error, Packet, codec, decoder, ffi, frame};
+use log::{info, warn};
+use moonlight_common::stream::{
+    audio::AudioDecoder,
+    bindings::{AudioConfig, Capabilities, OpusMultistreamConfig},
+};
+
+/// Decodes the opus audio and calls the callback for every decoded frame
+pub struct FfmpegAudioDecoder {
+    audio_config: AudioConfig,
+    decoder: Option<decoder::Audio>,
+    decoded: frame::Audio,
+    on_frame: Box<dyn FnMut(&frame::Audio) + Send>,
+}
+
+impl FfmpegAudioDecoder {
+    pub fn new(
+        audio_config: AudioConfig,
+        on_frame: impl FnMut(&frame::Audio) + Send + 'static,
+    ) -> Self {
+        Self {
+            audio_config,
+            decoder: None,
+            decoded: frame::Audio::empty(),
+            on_frame: Box::new(on_frame),
+        }
+    }
+
+    fn receive_frames(&mut self) -> Result<(), Error> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Ok(());
+        };
+
+        loop {
+            match decoder.receive_frame(&mut self.decoded) {
+                Ok(()) => (self.on_frame)(&self.decoded),
+                Err(Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                    return Ok(());
+                }
+                Err(Error::Eof) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Opens the opus decoder with the channel mapping of the host
+fn open_audio_decoder(config: &OpusMultistreamConfig) -> Result<decoder::Audio, Error> {
+    let codec = decoder::find(codec::Id::OPUS).ok_or(Error::DecoderNotFound)?;
+
+    let mut context = codec::Context::new_with_codec(codec);
+
+    let head = opus_head(config);
+    unsafe {
+        let context = context.as_mut_ptr();
+
+        let extradata =
+            ffi::av_mallocz(head.len() + ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+        if extradata.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg_next::error::ENOMEM,
+            });
+        }
+        copy_nonoverlapping(head.as_ptr(), extradata, head.len());
+
+        // Freed by FFmpeg together with the context
+        (*context).extradata = extradata;
+        (*context).extradata_size = head.len() as i32;
+        (*context).sample_rate = config.sample_rate as i32;
+    }
+
+    context.decoder().audio()
+}
+
+/// The "OpusHead" header of RFC 7845 which tells FFmpeg how the streams map to the channels
+fn opus_head(config: &OpusMultistreamConfig) -> Vec<u8> {
+    let channel_count = config.channel_count as usize;
+    // Family 0 only allows mono and stereo with a single stream
+    let mapping_family = if channel_count <= 2 && config.streams == 1 {
+        0u8
+    } else {
+        1u8
+    };
+
+    let mut head = Vec::with_capacity(21 + channel_count);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1); // version
+    head.push(channel_count as u8);
+    head.extend_from_slice(&0u16.to_le_bytes()); // pre skip
+    head.extend_from_slice(&config.sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(mapping_family);
+
+    if mapping_family != 0 {
+        head.push(config.streams as u8);
+        head.push(config.coupled_streams as u8);
+        head.extend_from_slice(&config.mapping[..channel_count]);
+    }
+
+    head
+}
+
+impl AudioDecoder for FfmpegAudioDecoder {
+    fn setup(
+        &mut self,
+        audio_config: AudioConfig,
+        stream_config: OpusMultistreamConfig,
+        _ar_flags: i32,
+    ) -> i32 {
+        info!("[FFmpeg]: starting audio {audio_config:?} with {stream_config:?}");
+
+        match open_audio_decoder(&stream_config) {
+            Ok(decoder) => {
+                self.decoder = Some(decoder);
+                0
+            }
+            Err(err) => {
+                warn!("[FFmpeg]: failed to open the audio decoder: {err}");
+                -1
+            }
+        }
+    }
+
+    fn start(&mut self) {}
+
+    fn stop(&mut self) {
+        self.decoder = None;
+    }
+
+    fn decode_and_play_sample(&mut self, data: &[u8]) {
+        // Lost packets are skipped, FFmpeg can't conceal them without the next packet
+        if data.is_empty() {
+            return;
+        }
+
+        let Some(decoder) = self.decoder.as_mut() else {
+            return;
+        };
+
+        if let Err(err) = decoder.send_packet(&Packet::copy(data)) {
+            warn!("[FFmpeg]: failed to decode an audio sample: {err}");
+            return;
+        }
+
+        if let Err(err) = self.receive_frames() {
+            warn!("[FFmpeg]: failed to receive an audio frame: {err}");
+        }
+    }
+
+    fn config(&self) -> AudioConfig {
+        self.audio_config
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+}
@@ -0,0 +1,17 @@
+//! Decodes a Moonlight stream with FFmpeg in software.
+//!
+//! The [VideoDecoder](moonlight_common::stream::video::VideoDecoder) and [AudioDecoder](moonlight_common::stream::audio::AudioDecoder)
+//! of this crate don't display anything, they pass the decoded frames to a callback.
+//! This is useful for programs without a window, e.g. to take screenshots or to analyze the frames.
+
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "video")]
+pub mod video;
+
+pub use ffmpeg_next as ffmpeg;
+
+/// Must be called once before any decoder is created
+pub fn init() -> Result<(), ffmpeg::Error> {
+    ffmpeg::init()
+}
@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use ffmpeg_next::{
+    Error, Packet, codec, decoder,
+    format::Pixel,
+    frame,
+    software::scaling::{self, Flags},
+};
+use log::{info, warn};
+use moonlight_common::stream::{
+    bindings::{
+        Capabilities, DecodeResult, FrameType, SupportedVideoFormats, VideoDecodeUnit, VideoFormat,
+    },
+    video::{VideoDecoder, VideoSetup},
+};
+
+#[derive(Debug, Clone)]
+pub struct FfmpegVideoConfig {
+    /// The frames are converted into this format before they're passed to the callback, None keeps the format of the decoder
+    pub output_format: Option<Pixel>,
+    pub supported_formats: SupportedVideoFormats,
+}
+
+impl Default for FfmpegVideoConfig {
+    fn default() -> Self {
+        Self {
+            output_format: Some(Pixel::RGBA),
+            supported_formats: SupportedVideoFormats::H264
+                | SupportedVideoFormats::H265
+                | SupportedVideoFormats::H265_MAIN10
+                | SupportedVideoFormats::AV1_MAIN8
+                | SupportedVideoFormats::AV1_MAIN10,
+        }
+    }
+}
+
+/// A decoded frame together with the information of the decode unit it was decoded from
+#[derive(Debug)]
+pub struct DecodedVideoFrame<'a> {
+    pub frame: &'a frame::Video,
+    pub frame_number: i32,
+    pub frame_type: FrameType,
+    pub presentation_time: Duration,
+}
+
+/// The FFmpeg codec which decodes the format
+pub fn codec_id(format: VideoFormat) -> codec::Id {
+    match format {
+        VideoFormat::H264 | VideoFormat::H264High8_444 => codec::Id::H264,
+        VideoFormat::H265
+        | VideoFormat::H265Main10
+        | VideoFormat::H265Rext8_444
+        | VideoFormat::H265Rext10_444 => codec::Id::HEVC,
+        VideoFormat::Av1Main8
+        | VideoFormat::Av1Main10
+        | VideoFormat::Av1High8_444
+        | VideoFormat::Av1High10_444 => codec::Id::AV1,
+    }
+}
+
+/// Opens a software decoder which outputs a frame for every packet
+pub fn open_video_decoder(format: VideoFormat) -> Result<decoder::Video, Error> {
+    let codec = decoder::find(codec_id(format)).ok_or(Error::DecoderNotFound)?;
+
+    let mut context = codec::Context::new_with_codec(codec);
+    context.set_flags(codec::Flags::LOW_DELAY);
+
+    context.decoder().video()
+}
+
+/// Decodes the video and calls the callback for every decoded frame
+pub struct FfmpegVideoDecoder {
+    config: FfmpegVideoConfig,
+    decoder: Option<decoder::Video>,
+    scaler: Option<Scaler>,
+    decoded: frame::Video,
+    converted: frame::Video,
+    packet: Vec<u8>,
+    on_frame: Box<dyn FnMut(DecodedVideoFrame<'_>) + Send>,
+}
+
+impl FfmpegVideoDecoder {
+    pub fn new(
+        config: FfmpegVideoConfig,
+        on_frame: impl FnMut(DecodedVideoFrame<'_>) + Send + 'static,
+    ) -> Self {
+        Self {
+            config,
+            decoder: None,
+            scaler: None,
+            decoded: frame::Video::empty(),
+            converted: frame::Video::empty(),
+            packet: Vec::new(),
+            on_frame: Box::new(on_frame),
+        }
+    }
+
+    fn receive_frames(&mut self, unit: &VideoDecodeUnit<'_>) -> Result<(), Error> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Ok(());
+        };
+
+        loop {
+            match decoder.receive_frame(&mut self.decoded) {
+                Ok(()) => {}
+                Err(Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                    return Ok(());
+                }
+                Err(Error::Eof) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+
+            let frame = match self.config.output_format {
+                Some(output_format) if output_format != self.decoded.format() => {
+                    let scaler = Scaler::get(&mut self.scaler, &self.decoded, output_format)?;
+                    scaler.run(&self.decoded, &mut self.converted)?;
+
+                    &self.converted
+                }
+                _ => &self.decoded,
+            };
+
+            (self.on_frame)(DecodedVideoFrame {
+                frame,
+                frame_number: unit.frame_number,
+                frame_type: unit.frame_type,
+                presentation_time: unit.presentation_time,
+            });
+        }
+    }
+}
+
+impl VideoDecoder for FfmpegVideoDecoder {
+    fn setup(
+        &mut self,
+        VideoSetup {
+            format,
+            width,
+            height,
+            redraw_rate,
+            flags: _,
+        }: VideoSetup,
+    ) -> i32 {
+        info!("[FFmpeg]: starting video {format:?} with {width}x{height}x{redraw_rate}");
+
+        match open_video_decoder(format) {
+            Ok(decoder) => {
+                self.decoder = Some(decoder);
+                self.scaler = None;
+                0
+            }
+            Err(err) => {
+                warn!("[FFmpeg]: failed to open the video decoder for {format:?}: {err}");
+                -1
+            }
+        }
+    }
+
+    fn start(&mut self) {}
+    fn stop(&mut self) {
+        self.decoder = None;
+        self.scaler = None;
+    }
+
+    fn submit_decode_unit(&mut self, unit: VideoDecodeUnit<'_>) -> DecodeResult {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return DecodeResult::Ok;
+        };
+
+        self.packet.clear();
+        for buffer in unit.buffers {
+            self.packet.extend_from_slice(buffer.data);
+        }
+
+        if let Err(err) = decoder.send_packet(&Packet::copy(&self.packet)) {
+            warn!("[FFmpeg]: failed to decode a video frame: {err}");
+            return DecodeResult::NeedIdr;
+        }
+
+        if let Err(err) = self.receive_frames(&unit) {
+            warn!("[FFmpeg]: failed to receive a video frame: {err}");
+            return DecodeResult::NeedIdr;
+        }
+
+        DecodeResult::Ok
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+    fn supported_formats(&self) -> SupportedVideoFormats {
+        self.config.supported_formats
+    }
+}
+
+/// Converts the decoded frames, it's recreated when the size or format of the frames changes
+struct Scaler {
+    context: scaling::Context,
+    input: (Pixel, u32, u32),
+}
+
+// The context is only used by the decoder which owns it
+unsafe impl Send for Scaler {}
+
+impl Scaler {
+    fn get<'a>(
+        scaler: &'a mut Option<Scaler>,
+        frame: &frame::Video,
+        output_format: Pixel,
+    ) -> Result<&'a mut scaling::Context, Error> {
+        let input = (frame.format(), frame.width(), frame.height());
+
+        if scaler.as_ref().is_none_or(|scaler| scaler.input != input) {
+            let context = scaling::Context::get(
+                input.0,
+                input.1,
+                input.2,
+                output_format,
+                input.1,
+                input.2,
+                Flags::BILINEAR,
+            )?;
+
+            *scaler = Some(Scaler { context, input });
+        }
+
+        Ok(&mut scaler.as_mut().expect("scaler was just created").context)
+    }
+}
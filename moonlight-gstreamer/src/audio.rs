@@ -0,0 +1,101 @@
+use gstreamer::{
+    Buffer, Caps, Element, ElementFactory, Format, Pipeline, State,
+    event::Eos,
+    glib,
+    prelude::{ElementExt, ElementExtManual, GstBinExtManual},
+};
+use gstreamer_app::AppSrc;
+use log::{info, warn};
+use moonlight_common::stream::{
+    audio::AudioDecoder,
+    bindings::{AudioConfig, Capabilities, OpusMultistreamConfig},
+};
+
+use crate::SinkConfig;
+
+/// Decodes the opus audio with GStreamer and plays it on the configured sink
+pub struct GStreamerAudioDecoder {
+    pipeline: Pipeline,
+    app_src: AppSrc,
+}
+
+impl GStreamerAudioDecoder {
+    pub fn new(pipeline: Pipeline, sink: &SinkConfig) -> Result<Self, glib::BoolError> {
+        let app_src = AppSrc::builder().name("audio input").build();
+        app_src.set_is_live(true);
+        app_src.set_format(Format::Time);
+        app_src.set_block(false);
+        app_src.set_do_timestamp(true);
+        app_src.set_caps(Some(
+            &Caps::builder("audio/x-opus")
+                .field("channel-mapping-family", 0)
+                .field("channels", 2)
+                .field("rate", 48000)
+                .build(),
+        ));
+
+        let parse = ElementFactory::make_with_name("opusparse", Some("parse audio"))?;
+        let decode = ElementFactory::make_with_name("opusdec", Some("decode audio"))?;
+        let convert = ElementFactory::make_with_name("audioconvert", Some("convert audio"))?;
+        let resample = ElementFactory::make_with_name("audioresample", Some("resample audio"))?;
+        let sink = sink.make("play audio")?;
+
+        let elements = [
+            app_src.as_ref(),
+            &parse,
+            &decode,
+            &convert,
+            &resample,
+            &sink,
+        ];
+        pipeline.add_many(elements)?;
+        Element::link_many(elements)?;
+
+        Ok(Self { pipeline, app_src })
+    }
+}
+
+impl AudioDecoder for GStreamerAudioDecoder {
+    fn setup(
+        &mut self,
+        audio_config: AudioConfig,
+        stream_config: OpusMultistreamConfig,
+        _ar_flags: i32,
+    ) -> i32 {
+        info!("[GStreamer]: starting audio {audio_config:?} with {stream_config:?}");
+
+        0
+    }
+
+    fn start(&mut self) {
+        if let Err(err) = self.pipeline.set_state(State::Playing) {
+            warn!("[GStreamer]: failed to start the audio: {err}");
+        }
+    }
+
+    fn stop(&mut self) {
+        self.pipeline.send_event(Eos::new());
+        if let Err(err) = self.pipeline.set_state(State::Null) {
+            warn!("[GStreamer]: failed to stop the audio: {err}");
+        }
+    }
+
+    fn decode_and_play_sample(&mut self, data: &[u8]) {
+        // opusdec conceals lost packets itself
+        if data.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.app_src.push_buffer(Buffer::from_slice(data.to_vec())) {
+            warn!("[GStreamer]: failed to push an audio sample: {err}");
+        }
+    }
+
+    fn config(&self) -> AudioConfig {
+        AudioConfig::STEREO
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+}
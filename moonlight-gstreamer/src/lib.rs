@@ -0,0 +1,123 @@
+//! Plays a Moonlight stream with GStreamer.
+//!
+//! The [VideoDecoder](moonlight_common::stream::video::VideoDecoder) and [AudioDecoder](moonlight_common::stream::audio::AudioDecoder)
+//! of this crate push the received data into a GStreamer pipeline which decodes it and passes it to the configured sinks.
+
+use gstreamer::{
+    Element, ElementFactory, Pipeline, State, glib,
+    prelude::{ElementExt, GstBinExtManual, ObjectExt},
+};
+
+#[cfg(feature = "audio")]
+use crate::audio::GStreamerAudioDecoder;
+#[cfg(feature = "video")]
+use crate::video::GStreamerVideoDecoder;
+#[cfg(feature = "video")]
+use moonlight_common::stream::bindings::SupportedVideoFormats;
+
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "video")]
+pub mod video;
+
+pub use gstreamer;
+
+/// Must be called once before any pipeline is created
+pub fn init() -> Result<(), glib::Error> {
+    gstreamer::init()
+}
+
+/// A GStreamer element which receives the decoded data
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    /// The name of the element factory, e.g. "autovideosink" or "wasapisink"
+    pub factory: String,
+    /// Properties which are set on the element, e.g. the "device" of an audio sink
+    pub properties: Vec<(String, String)>,
+}
+
+impl SinkConfig {
+    pub fn new(factory: impl Into<String>) -> Self {
+        Self {
+            factory: factory.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn property(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((name.into(), value.into()));
+        self
+    }
+
+    pub(crate) fn make(&self, name: &str) -> Result<Element, glib::BoolError> {
+        let sink = ElementFactory::make_with_name(&self.factory, Some(name))?;
+
+        for (name, value) in &self.properties {
+            sink.set_property_from_str(name, value);
+        }
+
+        Ok(sink)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GStreamerConfig {
+    #[cfg(feature = "video")]
+    pub video_sink: SinkConfig,
+    /// The formats which the installed GStreamer plugins can decode
+    #[cfg(feature = "video")]
+    pub video_formats: SupportedVideoFormats,
+    #[cfg(feature = "audio")]
+    pub audio_sink: SinkConfig,
+}
+
+impl Default for GStreamerConfig {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "video")]
+            video_sink: SinkConfig::new("autovideosink")
+                .property("sync", "false")
+                .property("async-handling", "true"),
+            #[cfg(feature = "video")]
+            video_formats: SupportedVideoFormats::H264
+                | SupportedVideoFormats::H265
+                | SupportedVideoFormats::H265_MAIN10
+                | SupportedVideoFormats::AV1_MAIN8
+                | SupportedVideoFormats::AV1_MAIN10,
+            #[cfg(feature = "audio")]
+            audio_sink: SinkConfig::new("autoaudiosink"),
+        }
+    }
+}
+
+/// Creates the decoders which play the stream in one pipeline
+#[cfg(all(feature = "video", feature = "audio"))]
+pub fn gstreamer_decoders(
+    config: &GStreamerConfig,
+) -> Result<(GStreamerVideoDecoder, GStreamerAudioDecoder), glib::BoolError> {
+    let pipeline = Pipeline::new();
+
+    let video_decoder =
+        GStreamerVideoDecoder::new(pipeline.clone(), &config.video_sink, config.video_formats)?;
+    let audio_decoder = GStreamerAudioDecoder::new(pipeline, &config.audio_sink)?;
+
+    Ok((video_decoder, audio_decoder))
+}
+
+/// Removes elements which were created for a previous setup
+pub(crate) fn remove_elements(
+    pipeline: &Pipeline,
+    elements: &mut Vec<Element>,
+) -> Result<(), glib::BoolError> {
+    if elements.is_empty() {
+        return Ok(());
+    }
+
+    for element in elements.iter() {
+        let _ = element.set_state(State::Null);
+    }
+    pipeline.remove_many(elements.iter())?;
+    elements.clear();
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use gstreamer::{
+    Buffer, BufferFlags, Caps, ClockTime, Element, ElementFactory, Format, Pipeline, State,
+    event::Eos,
+    glib,
+    prelude::{Cast, ElementExt, ElementExtManual, GstBinExtManual, ObjectExt},
+};
+use gstreamer_app::AppSrc;
+use log::{info, warn};
+use moonlight_common::stream::{
+    bindings::{
+        Capabilities, DecodeResult, FrameType, SupportedVideoFormats, VideoDecodeUnit, VideoFormat,
+    },
+    video::{VideoDecoder, VideoSetup},
+};
+
+use crate::{SinkConfig, remove_elements};
+
+/// Decodes the video with the installed GStreamer plugins and passes the frames to the configured sink
+pub struct GStreamerVideoDecoder {
+    pipeline: Pipeline,
+    app_src: AppSrc,
+    sink: Element,
+    supported_formats: SupportedVideoFormats,
+    /// The parse and decode elements which depend on the negotiated format
+    codec_elements: Vec<Element>,
+}
+
+impl GStreamerVideoDecoder {
+    pub fn new(
+        pipeline: Pipeline,
+        sink: &SinkConfig,
+        supported_formats: SupportedVideoFormats,
+    ) -> Result<Self, glib::BoolError> {
+        let app_src = AppSrc::builder().name("video input").build();
+        app_src.set_is_live(true);
+        app_src.set_format(Format::Time);
+        app_src.set_block(false);
+        app_src.set_min_latency(-1);
+
+        let sink = sink.make("play video")?;
+
+        pipeline.add_many([app_src.as_ref(), &sink])?;
+
+        Ok(Self {
+            pipeline,
+            app_src,
+            sink,
+            supported_formats,
+            codec_elements: Vec::new(),
+        })
+    }
+
+    fn link_codec(&mut self, format: VideoFormat) -> Result<(), glib::BoolError> {
+        remove_elements(&self.pipeline, &mut self.codec_elements)?;
+
+        let (caps, parse, decode) = match format {
+            VideoFormat::H264 | VideoFormat::H264High8_444 => (
+                Caps::builder("video/x-h264")
+                    .field("stream-format", "byte-stream")
+                    .field("alignment", "au")
+                    .build(),
+                "h264parse",
+                "avdec_h264",
+            ),
+            VideoFormat::H265
+            | VideoFormat::H265Main10
+            | VideoFormat::H265Rext8_444
+            | VideoFormat::H265Rext10_444 => (
+                Caps::builder("video/x-h265")
+                    .field("stream-format", "byte-stream")
+                    .field("alignment", "au")
+                    .build(),
+                "h265parse",
+                "avdec_h265",
+            ),
+            VideoFormat::Av1Main8
+            | VideoFormat::Av1Main10
+            | VideoFormat::Av1High8_444
+            | VideoFormat::Av1High10_444 => (
+                Caps::builder("video/x-av1")
+                    .field("stream-format", "obu-stream")
+                    .field("alignment", "tu")
+                    .build(),
+                "av1parse",
+                "dav1ddec",
+            ),
+        };
+        self.app_src.set_caps(Some(&caps));
+
+        let parse = ElementFactory::make_with_name(parse, Some("parse video"))?;
+        if parse.has_property("config-interval") {
+            parse.set_property("config-interval", -1);
+        }
+        let decode = ElementFactory::make_with_name(decode, Some("decode video"))?;
+        let convert = ElementFactory::make_with_name("videoconvert", Some("convert video"))?;
+
+        self.codec_elements = vec![parse, decode, convert];
+
+        self.pipeline.add_many(self.codec_elements.iter())?;
+
+        let mut chain = vec![self.app_src.upcast_ref::<Element>().clone()];
+        chain.extend(self.codec_elements.iter().cloned());
+        chain.push(self.sink.clone());
+
+        Element::link_many(chain.iter())?;
+
+        Ok(())
+    }
+}
+
+impl VideoDecoder for GStreamerVideoDecoder {
+    fn setup(
+        &mut self,
+        VideoSetup {
+            format,
+            width,
+            height,
+            redraw_rate,
+            flags: _,
+        }: VideoSetup,
+    ) -> i32 {
+        info!("[GStreamer]: starting video {format:?} with {width}x{height}x{redraw_rate}");
+
+        if let Err(err) = self.link_codec(format) {
+            warn!("[GStreamer]: failed to create the video decoder for {format:?}: {err}");
+            return -1;
+        }
+
+        0
+    }
+
+    fn start(&mut self) {
+        if let Err(err) = self.pipeline.set_state(State::Playing) {
+            warn!("[GStreamer]: failed to start the video: {err}");
+        }
+    }
+    fn stop(&mut self) {
+        self.pipeline.send_event(Eos::new());
+        if let Err(err) = self.pipeline.set_state(State::Null) {
+            warn!("[GStreamer]: failed to stop the video: {err}");
+        }
+    }
+
+    fn submit_decode_unit(&mut self, unit: VideoDecodeUnit<'_>) -> DecodeResult {
+        if matches!(self.pipeline.current_state(), State::Null) {
+            return DecodeResult::Ok;
+        }
+
+        // The parser expects a whole access unit per buffer
+        let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+        let mut frame = Vec::with_capacity(frame_len);
+        for buffer in unit.buffers {
+            frame.extend_from_slice(buffer.data);
+        }
+
+        let mut gst_buffer = Buffer::from_mut_slice(frame);
+        {
+            let Some(buffer_mut) = gst_buffer.get_mut() else {
+                return DecodeResult::Ok;
+            };
+
+            let pts = ClockTime::from_nseconds(unit.presentation_time.as_nanos() as u64);
+            buffer_mut.set_pts(pts);
+            buffer_mut.set_dts(pts);
+
+            if matches!(unit.frame_type, FrameType::PFrame) {
+                buffer_mut.set_flags(BufferFlags::DELTA_UNIT);
+            }
+        }
+
+        if let Err(err) = self.app_src.push_buffer(gst_buffer) {
+            warn!("[GStreamer]: failed to push a video frame: {err}");
+            return DecodeResult::NeedIdr;
+        }
+
+        DecodeResult::Ok
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+    fn supported_formats(&self) -> SupportedVideoFormats {
+        self.supported_formats
+    }
+}
@@ -0,0 +1,150 @@
+//! Decoders which record everything moonlight-common-c or a [FakeVideoSource](crate::FakeVideoSource) submits.
+
+use std::sync::{Arc, Mutex};
+
+use moonlight_common::stream::{
+    audio::AudioDecoder,
+    bindings::{
+        AudioConfig, DecodeResult, FrameType, OpusMultistreamConfig, SupportedVideoFormats,
+        VideoDecodeUnit,
+    },
+    video::{VideoDecoder, VideoSetup},
+};
+
+#[derive(Debug, Clone)]
+pub struct RecordedVideoFrame {
+    pub frame_number: i32,
+    pub frame_type: FrameType,
+    /// All buffers of the decode unit concatenated
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct VideoRecording {
+    pub setup: Option<VideoSetup>,
+    pub started: bool,
+    pub stopped: bool,
+    pub frames: Vec<RecordedVideoFrame>,
+}
+
+/// The recording stays accessible after the decoder was moved into a stream, see [RecordingVideoDecoder::recording].
+pub struct RecordingVideoDecoder {
+    supported_formats: SupportedVideoFormats,
+    recording: Arc<Mutex<VideoRecording>>,
+}
+
+impl RecordingVideoDecoder {
+    pub fn new(supported_formats: SupportedVideoFormats) -> Self {
+        Self {
+            supported_formats,
+            recording: Default::default(),
+        }
+    }
+
+    pub fn recording(&self) -> Arc<Mutex<VideoRecording>> {
+        self.recording.clone()
+    }
+
+    fn record(&self, f: impl FnOnce(&mut VideoRecording)) {
+        let mut recording = self.recording.lock().expect("video recording");
+        f(&mut recording);
+    }
+}
+
+impl VideoDecoder for RecordingVideoDecoder {
+    fn setup(&mut self, setup: VideoSetup) -> i32 {
+        self.record(|recording| recording.setup = Some(setup));
+        0
+    }
+
+    fn start(&mut self) {
+        self.record(|recording| recording.started = true);
+    }
+
+    fn submit_decode_unit(&mut self, unit: VideoDecodeUnit<'_>) -> DecodeResult {
+        let data = unit
+            .buffers
+            .iter()
+            .flat_map(|buffer| buffer.data)
+            .copied()
+            .collect();
+
+        self.record(|recording| {
+            recording.frames.push(RecordedVideoFrame {
+                frame_number: unit.frame_number,
+                frame_type: unit.frame_type,
+                data,
+            })
+        });
+
+        DecodeResult::Ok
+    }
+
+    fn stop(&mut self) {
+        self.record(|recording| recording.stopped = true);
+    }
+
+    fn supported_formats(&self) -> SupportedVideoFormats {
+        self.supported_formats
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AudioRecording {
+    pub setup: Option<(AudioConfig, OpusMultistreamConfig)>,
+    pub started: bool,
+    pub stopped: bool,
+    pub samples: Vec<Vec<u8>>,
+}
+
+/// The recording stays accessible after the decoder was moved into a stream, see [RecordingAudioDecoder::recording].
+pub struct RecordingAudioDecoder {
+    config: AudioConfig,
+    recording: Arc<Mutex<AudioRecording>>,
+}
+
+impl RecordingAudioDecoder {
+    pub fn new(config: AudioConfig) -> Self {
+        Self {
+            config,
+            recording: Default::default(),
+        }
+    }
+
+    pub fn recording(&self) -> Arc<Mutex<AudioRecording>> {
+        self.recording.clone()
+    }
+
+    fn record(&self, f: impl FnOnce(&mut AudioRecording)) {
+        let mut recording = self.recording.lock().expect("audio recording");
+        f(&mut recording);
+    }
+}
+
+impl AudioDecoder for RecordingAudioDecoder {
+    fn setup(
+        &mut self,
+        audio_config: AudioConfig,
+        stream_config: OpusMultistreamConfig,
+        _ar_flags: i32,
+    ) -> i32 {
+        self.record(|recording| recording.setup = Some((audio_config, stream_config)));
+        0
+    }
+
+    fn start(&mut self) {
+        self.record(|recording| recording.started = true);
+    }
+
+    fn stop(&mut self) {
+        self.record(|recording| recording.stopped = true);
+    }
+
+    fn decode_and_play_sample(&mut self, data: &[u8]) {
+        self.record(|recording| recording.samples.push(data.to_vec()));
+    }
+
+    fn config(&self) -> AudioConfig {
+        self.config
+    }
+}
@@ -0,0 +1,742 @@
+//! A mock Sunshine host which speaks the Moonlight http / https api.
+//! - https://games-on-whales.github.io/wolf/stable/protocols/http-pairing.html
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    Request, Response, StatusCode,
+    body::Incoming,
+    header::{CONTENT_TYPE, HeaderValue},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use log::{debug, warn};
+use moonlight_common::{
+    HashAlgorithm, PairPin, ServerVersion, formats::ServerCodeModeSupport,
+    hash_algorithm_for_server, pair::generate_new_client,
+};
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    sha::{sha1, sha256},
+    sign::{Signer, Verifier},
+    ssl::{Ssl, SslAcceptor, SslMethod, SslVerifyMode},
+    symm::{Cipher, Crypter, Mode},
+    x509::X509,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    spawn,
+    task::JoinHandle,
+};
+use tokio_openssl::SslStream;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum MockHostError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    OpenSsl(#[from] ErrorStack),
+    #[error("{0}")]
+    Ssl(#[from] openssl::ssl::Error),
+    #[error("{0}")]
+    Hex(#[from] hex::FromHexError),
+    #[error("invalid pairing request: {0}")]
+    InvalidPairing(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub struct MockApp {
+    pub id: u32,
+    pub title: String,
+    pub is_hdr_supported: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockHostConfig {
+    pub host_name: String,
+    pub unique_id: Uuid,
+    pub app_version: ServerVersion,
+    pub gfe_version: String,
+    pub mac: String,
+    pub max_luma_pixels_hevc: u32,
+    pub server_codec_mode_support: ServerCodeModeSupport,
+    /// The pin which the user would enter on the host
+    pub pin: PairPin,
+    pub apps: Vec<MockApp>,
+    /// Returned as the box art of every app
+    pub box_art: Vec<u8>,
+}
+
+impl Default for MockHostConfig {
+    fn default() -> Self {
+        Self {
+            host_name: "MockHost".to_string(),
+            unique_id: Uuid::new_v4(),
+            app_version: ServerVersion::new(7, 1, 431, -1),
+            gfe_version: "3.23.0.74".to_string(),
+            mac: "00:11:22:33:44:55".to_string(),
+            max_luma_pixels_hevc: 1869449984,
+            server_codec_mode_support: ServerCodeModeSupport::H264
+                | ServerCodeModeSupport::HEVC
+                | ServerCodeModeSupport::HEVC_MAIN10,
+            pin: PairPin::from_array([1, 2, 3, 4]).expect("valid pin"),
+            apps: vec![
+                MockApp {
+                    id: 1,
+                    title: "Desktop".to_string(),
+                    is_hdr_supported: false,
+                },
+                MockApp {
+                    id: 2,
+                    title: "Steam Big Picture".to_string(),
+                    is_hdr_supported: true,
+                },
+            ],
+            box_art: b"\x89PNG\r\n\x1a\n".to_vec(),
+        }
+    }
+}
+
+/// Serves the http and https api on localhost until it's dropped.
+pub struct MockHost {
+    http_address: SocketAddr,
+    https_address: SocketAddr,
+    state: Arc<Mutex<MockHostState>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MockHost {
+    pub async fn start(config: MockHostConfig) -> Result<Self, MockHostError> {
+        let auth = generate_new_client()?;
+        let server_key = PKey::private_key_from_der(auth.private_key.contents())?;
+        let server_certificate = X509::from_der(auth.certificate.contents())?;
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+        acceptor.set_private_key(&server_key)?;
+        acceptor.set_certificate(&server_certificate)?;
+        acceptor.check_private_key()?;
+        // Clients use self signed certificates which are only compared against the paired ones
+        acceptor.set_verify_callback(SslVerifyMode::PEER, |_, _| true);
+        let acceptor = Arc::new(acceptor.build());
+
+        let http_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let https_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let http_address = http_listener.local_addr()?;
+        let https_address = https_listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(MockHostState {
+            config,
+            http_port: http_address.port(),
+            https_port: https_address.port(),
+            server_key,
+            server_certificate,
+            paired_clients: Vec::new(),
+            pairing: None,
+            current_game: 0,
+            requests: Vec::new(),
+        }));
+
+        let tasks = vec![
+            spawn(serve_http(http_listener, state.clone())),
+            spawn(serve_https(https_listener, acceptor, state.clone())),
+        ];
+
+        Ok(Self {
+            http_address,
+            https_address,
+            state,
+            tasks,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        self.http_address.ip().to_string()
+    }
+    pub fn http_port(&self) -> u16 {
+        self.http_address.port()
+    }
+    pub fn https_port(&self) -> u16 {
+        self.https_address.port()
+    }
+
+    pub fn is_paired(&self) -> bool {
+        !self.state().paired_clients.is_empty()
+    }
+
+    pub fn current_game(&self) -> u32 {
+        self.state().current_game
+    }
+    /// Simulates an app which was started by another client
+    pub fn set_current_game(&self, app_id: u32) {
+        self.state().current_game = app_id;
+    }
+
+    /// The paths of all received requests in order, e.g. `/serverinfo`
+    pub fn requests(&self) -> Vec<String> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockHostState> {
+        lock_state(&self.state)
+    }
+}
+
+impl Drop for MockHost {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn lock_state(state: &Mutex<MockHostState>) -> MutexGuard<'_, MockHostState> {
+    // A failed assertion in a test shouldn't make all following requests fail too
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// -- Server
+
+async fn serve_http(listener: TcpListener, state: Arc<Mutex<MockHostState>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("[MockHost]: failed to accept http connection: {err}");
+                continue;
+            }
+        };
+
+        spawn(serve_connection(stream, state.clone(), None));
+    }
+}
+
+async fn serve_https(
+    listener: TcpListener,
+    acceptor: Arc<SslAcceptor>,
+    state: Arc<Mutex<MockHostState>>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("[MockHost]: failed to accept https connection: {err}");
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+        spawn(async move {
+            let stream = match accept_tls(&acceptor, stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("[MockHost]: tls handshake failed: {err}");
+                    return;
+                }
+            };
+
+            let peer_certificate = stream.ssl().peer_certificate();
+            serve_connection(stream, state, Some(peer_certificate)).await;
+        });
+    }
+}
+
+async fn accept_tls(
+    acceptor: &SslAcceptor,
+    stream: TcpStream,
+) -> Result<SslStream<TcpStream>, MockHostError> {
+    let ssl = Ssl::new(acceptor.context())?;
+
+    let mut stream = SslStream::new(ssl, stream)?;
+    Pin::new(&mut stream).accept().await?;
+
+    Ok(stream)
+}
+
+/// `https` contains the certificate of the client if this is an https connection
+async fn serve_connection<S>(
+    stream: S,
+    state: Arc<Mutex<MockHostState>>,
+    https: Option<Option<X509>>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(|request: Request<Incoming>| {
+        let response = handle_request(&state, https.as_ref(), &request);
+
+        async move { Ok::<_, Infallible>(response) }
+    });
+
+    if let Err(err) = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+    {
+        debug!("[MockHost]: connection failed: {err}");
+    }
+}
+
+type Query = HashMap<String, String>;
+
+fn handle_request(
+    state: &Mutex<MockHostState>,
+    https: Option<&Option<X509>>,
+    request: &Request<Incoming>,
+) -> Response<Full<Bytes>> {
+    let path = request.uri().path();
+    let query = form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect::<Query>();
+
+    let mut state = lock_state(state);
+    state.requests.push(path.to_string());
+
+    let peer_certificate = https.and_then(Option::as_ref);
+    let paired = state.is_paired_certificate(peer_certificate);
+
+    match path {
+        "/serverinfo" => state.server_info(paired),
+        "/pair" if https.is_some() => xml_response(200, &paired_xml(paired)),
+        "/pair" => state.pair(&query),
+        "/unpair" => state.unpair(&query),
+        _ if !paired => xml_error(
+            401,
+            "The client is not authorized. Certificate verification failed.",
+        ),
+        "/applist" => state.app_list(),
+        "/appasset" => state.app_asset(),
+        "/launch" => state.launch(&query),
+        "/resume" => state.resume(),
+        "/cancel" => {
+            state.current_game = 0;
+            xml_response(200, "<cancel>1</cancel>")
+        }
+        _ => xml_error(404, "Not Found"),
+    }
+}
+
+fn xml_response(status_code: u16, body: &str) -> Response<Full<Bytes>> {
+    response(
+        status_code,
+        "application/xml",
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><root status_code=\"{status_code}\">{body}</root>"
+        ),
+    )
+}
+fn xml_error(status_code: u16, message: &str) -> Response<Full<Bytes>> {
+    response(
+        status_code,
+        "application/xml",
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><root status_code=\"{status_code}\" status_message=\"{}\"/>",
+            escape_xml(message)
+        ),
+    )
+}
+
+fn response(
+    status_code: u16,
+    content_type: &'static str,
+    body: impl Into<Bytes>,
+) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(body.into()));
+    *response.status_mut() = StatusCode::from_u16(status_code).unwrap_or(StatusCode::OK);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    response
+}
+
+fn paired_xml(paired: bool) -> String {
+    format!("<paired>{}</paired>", paired as u8)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// -- State
+
+struct PairedClient {
+    unique_id: String,
+    certificate: X509,
+}
+
+/// A pairing procedure which is currently in progress
+struct PairingSession {
+    unique_id: String,
+    client_certificate: X509,
+    aes_key: [u8; 16],
+    server_secret: [u8; 16],
+    server_challenge: [u8; 16],
+    client_hash: Option<Vec<u8>>,
+}
+
+struct MockHostState {
+    config: MockHostConfig,
+    http_port: u16,
+    https_port: u16,
+    server_key: PKey<Private>,
+    server_certificate: X509,
+    paired_clients: Vec<PairedClient>,
+    pairing: Option<PairingSession>,
+    current_game: u32,
+    requests: Vec<String>,
+}
+
+impl MockHostState {
+    fn is_paired_certificate(&self, certificate: Option<&X509>) -> bool {
+        let Some(certificate) = certificate else {
+            return false;
+        };
+
+        self.paired_clients.iter().any(|client| {
+            client.certificate.signature().as_slice() == certificate.signature().as_slice()
+        })
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        hash_algorithm_for_server(self.config.app_version)
+    }
+
+    fn server_info(&self, paired: bool) -> Response<Full<Bytes>> {
+        let config = &self.config;
+
+        let state = if self.current_game == 0 {
+            "SUNSHINE_SERVER_FREE"
+        } else {
+            "SUNSHINE_SERVER_BUSY"
+        };
+
+        xml_response(
+            200,
+            &format!(
+                "<hostname>{}</hostname>\
+                <appversion>{}</appversion>\
+                <GfeVersion>{}</GfeVersion>\
+                <uniqueid>{}</uniqueid>\
+                <HttpsPort>{}</HttpsPort>\
+                <ExternalPort>{}</ExternalPort>\
+                <MaxLumaPixelsHEVC>{}</MaxLumaPixelsHEVC>\
+                <mac>{}</mac>\
+                <LocalIP>127.0.0.1</LocalIP>\
+                <ServerCodecModeSupport>{}</ServerCodecModeSupport>\
+                <PairStatus>{}</PairStatus>\
+                <currentgame>{}</currentgame>\
+                <state>{state}</state>",
+                escape_xml(&config.host_name),
+                config.app_version,
+                escape_xml(&config.gfe_version),
+                config.unique_id,
+                self.https_port,
+                self.http_port,
+                config.max_luma_pixels_hevc,
+                config.mac,
+                config.server_codec_mode_support.bits(),
+                paired as u8,
+                self.current_game,
+            ),
+        )
+    }
+
+    fn app_list(&self) -> Response<Full<Bytes>> {
+        let apps = self
+            .config
+            .apps
+            .iter()
+            .map(|app| {
+                format!(
+                    "<App><IsHdrSupported>{}</IsHdrSupported><AppTitle>{}</AppTitle><ID>{}</ID></App>",
+                    app.is_hdr_supported as u8,
+                    escape_xml(&app.title),
+                    app.id
+                )
+            })
+            .collect::<String>();
+
+        xml_response(200, &apps)
+    }
+
+    fn app_asset(&self) -> Response<Full<Bytes>> {
+        response(200, "image/png", self.config.box_art.clone())
+    }
+
+    fn launch(&mut self, query: &Query) -> Response<Full<Bytes>> {
+        let app_id = query.get("appid").and_then(|id| id.parse::<u32>().ok());
+        let Some(app_id) = app_id.filter(|id| self.config.apps.iter().any(|app| app.id == *id))
+        else {
+            return xml_error(404, "Cannot find requested application");
+        };
+
+        if self.current_game != 0 {
+            return xml_error(400, "An app is already running on this host");
+        }
+        self.current_game = app_id;
+
+        xml_response(
+            200,
+            "<gamesession>1</gamesession><sessionUrl0>rtsp://127.0.0.1:48010</sessionUrl0>",
+        )
+    }
+
+    fn resume(&mut self) -> Response<Full<Bytes>> {
+        if self.current_game == 0 {
+            return xml_error(503, "No running app to resume");
+        }
+
+        xml_response(
+            200,
+            "<resume>1</resume><sessionUrl0>rtsp://127.0.0.1:48010</sessionUrl0>",
+        )
+    }
+
+    // -- Pairing
+
+    fn pair(&mut self, query: &Query) -> Response<Full<Bytes>> {
+        let Some(unique_id) = query.get("uniqueid") else {
+            return xml_error(400, "Missing uniqueid parameter");
+        };
+
+        let result = if query
+            .get("phrase")
+            .is_some_and(|phrase| phrase == "getservercert")
+        {
+            self.pair_get_server_cert(unique_id, query)
+        } else if let Some(challenge) = query.get("clientchallenge") {
+            self.pair_client_challenge(challenge)
+        } else if let Some(response) = query.get("serverchallengeresp") {
+            self.pair_server_challenge_response(response)
+        } else if let Some(secret) = query.get("clientpairingsecret") {
+            self.pair_client_pairing_secret(secret)
+        } else {
+            return xml_error(404, "Invalid pairing request");
+        };
+
+        match result {
+            Ok(body) => xml_response(200, &body),
+            Err(err) => {
+                warn!("[MockHost]: pairing failed: {err}");
+                self.pairing = None;
+
+                xml_response(200, &paired_xml(false))
+            }
+        }
+    }
+
+    fn pair_get_server_cert(
+        &mut self,
+        unique_id: &str,
+        query: &Query,
+    ) -> Result<String, MockHostError> {
+        let salt = hex::decode(query_value(query, "salt")?)?;
+        if salt.len() != 16 {
+            return Err(MockHostError::InvalidPairing("the salt must be 16 bytes"));
+        }
+
+        let client_certificate = X509::from_pem(&hex::decode(query_value(query, "clientcert")?)?)?;
+
+        let mut salted_pin = salt;
+        salted_pin.extend_from_slice(self.config.pin.to_string().as_bytes());
+
+        let mut aes_key = [0u8; 16];
+        aes_key.copy_from_slice(&hash(self.hash_algorithm(), &salted_pin)[..16]);
+
+        self.pairing = Some(PairingSession {
+            unique_id: unique_id.to_string(),
+            client_certificate,
+            aes_key,
+            server_secret: [0; 16],
+            server_challenge: [0; 16],
+            client_hash: None,
+        });
+
+        let server_certificate = self.server_certificate.to_pem()?;
+
+        Ok(format!(
+            "{}<plaincert>{}</plaincert>",
+            paired_xml(true),
+            hex::encode_upper(server_certificate)
+        ))
+    }
+
+    fn pair_client_challenge(
+        &mut self,
+        encrypted_challenge: &str,
+    ) -> Result<String, MockHostError> {
+        let hash_algorithm = self.hash_algorithm();
+        let session = pairing_session(&mut self.pairing)?;
+
+        let challenge = aes_ecb(
+            Mode::Decrypt,
+            &session.aes_key,
+            &hex::decode(encrypted_challenge)?,
+        )?;
+
+        rand_bytes(&mut session.server_secret)?;
+        rand_bytes(&mut session.server_challenge)?;
+
+        let mut response = hash(
+            hash_algorithm,
+            &[
+                challenge.as_slice(),
+                self.server_certificate.signature().as_slice(),
+                &session.server_secret,
+            ]
+            .concat(),
+        );
+        response.extend_from_slice(&session.server_challenge);
+        // The response is encrypted without padding
+        response.resize(response.len().next_multiple_of(16), 0);
+
+        let encrypted_response = aes_ecb(Mode::Encrypt, &session.aes_key, &response)?;
+
+        Ok(format!(
+            "{}<challengeresponse>{}</challengeresponse>",
+            paired_xml(true),
+            hex::encode_upper(encrypted_response)
+        ))
+    }
+
+    fn pair_server_challenge_response(
+        &mut self,
+        encrypted_hash: &str,
+    ) -> Result<String, MockHostError> {
+        let session = pairing_session(&mut self.pairing)?;
+
+        session.client_hash = Some(aes_ecb(
+            Mode::Decrypt,
+            &session.aes_key,
+            &hex::decode(encrypted_hash)?,
+        )?);
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.server_key)?;
+        signer.update(&session.server_secret)?;
+
+        let mut pairing_secret = session.server_secret.to_vec();
+        pairing_secret.extend(signer.sign_to_vec()?);
+
+        Ok(format!(
+            "{}<pairingsecret>{}</pairingsecret>",
+            paired_xml(true),
+            hex::encode_upper(pairing_secret)
+        ))
+    }
+
+    fn pair_client_pairing_secret(&mut self, secret: &str) -> Result<String, MockHostError> {
+        let hash_algorithm = self.hash_algorithm();
+        let Some(session) = self.pairing.take() else {
+            return Err(MockHostError::InvalidPairing("no pairing in progress"));
+        };
+
+        let secret = hex::decode(secret)?;
+        if secret.len() <= 16 {
+            return Err(MockHostError::InvalidPairing(
+                "the client pairing secret is too short",
+            ));
+        }
+        let (client_secret, client_signature) = secret.split_at(16);
+
+        let public_key = session.client_certificate.public_key()?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+        verifier.update(client_secret)?;
+        if !verifier.verify(client_signature)? {
+            return Err(MockHostError::InvalidPairing(
+                "the client pairing secret has an invalid signature",
+            ));
+        }
+
+        let expected_hash = hash(
+            hash_algorithm,
+            &[
+                &session.server_challenge,
+                session.client_certificate.signature().as_slice(),
+                client_secret,
+            ]
+            .concat(),
+        );
+        // The decrypted hash might be padded
+        if !session
+            .client_hash
+            .as_deref()
+            .is_some_and(|client_hash| client_hash.starts_with(&expected_hash))
+        {
+            return Err(MockHostError::InvalidPairing(
+                "the challenge response doesn't match, probably a wrong pin",
+            ));
+        }
+
+        self.paired_clients.push(PairedClient {
+            unique_id: session.unique_id,
+            certificate: session.client_certificate,
+        });
+
+        Ok(paired_xml(true))
+    }
+
+    fn unpair(&mut self, query: &Query) -> Response<Full<Bytes>> {
+        self.pairing = None;
+
+        if let Some(unique_id) = query.get("uniqueid") {
+            self.paired_clients
+                .retain(|client| &client.unique_id != unique_id);
+        }
+
+        xml_response(200, "")
+    }
+}
+
+fn pairing_session(
+    pairing: &mut Option<PairingSession>,
+) -> Result<&mut PairingSession, MockHostError> {
+    pairing
+        .as_mut()
+        .ok_or(MockHostError::InvalidPairing("no pairing in progress"))
+}
+
+fn query_value<'a>(query: &'a Query, key: &'static str) -> Result<&'a str, MockHostError> {
+    query
+        .get(key)
+        .map(String::as_str)
+        .ok_or(MockHostError::InvalidPairing(key))
+}
+
+fn hash(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+    match algorithm {
+        HashAlgorithm::Sha1 => sha1(data).to_vec(),
+        HashAlgorithm::Sha256 => sha256(data).to_vec(),
+    }
+}
+
+fn aes_ecb(mode: Mode, key: &[u8], data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let cipher = Cipher::aes_128_ecb();
+
+    let mut crypter = Crypter::new(cipher, mode, key, None)?;
+    crypter.pad(false);
+
+    let mut output = vec![0; data.len() + cipher.block_size()];
+    let mut len = crypter.update(data, &mut output)?;
+    len += crypter.finalize(&mut output[len..])?;
+    output.truncate(len);
+
+    Ok(output)
+}
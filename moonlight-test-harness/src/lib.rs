@@ -0,0 +1,16 @@
+//! Helpers to test the moonlight crates without a real host.
+//!
+//! - [MockHost]: a Sunshine like http / https server which answers serverinfo, applist, launch and the pairing procedure
+//! - [FakeVideoSource] and [FakeAudioSource]: create decode units like moonlight-common-c would submit them
+//! - [RecordingVideoDecoder] and [RecordingAudioDecoder]: decoders which record everything they receive
+
+mod decoder;
+mod host;
+mod source;
+
+pub use decoder::{
+    AudioRecording, RecordedVideoFrame, RecordingAudioDecoder, RecordingVideoDecoder,
+    VideoRecording,
+};
+pub use host::{MockApp, MockHost, MockHostConfig, MockHostError};
+pub use source::{FakeAudioSource, FakeVideoFrame, FakeVideoSource};
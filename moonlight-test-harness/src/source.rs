@@ -0,0 +1,238 @@
+//! Sources which submit decode units to a decoder like moonlight-common-c does.
+//! The data is only shaped like H264 / Opus and can't be decoded.
+
+use std::time::Duration;
+
+use moonlight_common::stream::{
+    audio::AudioDecoder,
+    bindings::{
+        AudioConfig, BufferType, Colorspace, DecodeResult, FrameType, OpusMultistreamConfig,
+        VideoDataBuffer, VideoDecodeUnit, VideoFormat,
+    },
+    video::{VideoDecoder, VideoSetup},
+};
+
+const SPS: &[u8] = &[
+    0, 0, 0, 1, 0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0x84,
+];
+const PPS: &[u8] = &[0, 0, 0, 1, 0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+const IDR_SLICE_HEADER: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84];
+const SLICE_HEADER: &[u8] = &[0, 0, 0, 1, 0x41, 0x9a];
+
+/// CELT only, fullband, 20ms, stereo
+const OPUS_TOC: u8 = 0xfc;
+
+#[derive(Debug, Clone)]
+pub struct FakeVideoFrame {
+    pub frame_number: i32,
+    pub frame_type: FrameType,
+    pub presentation_time: Duration,
+    pub nal_units: Vec<(BufferType, Vec<u8>)>,
+}
+
+impl FakeVideoFrame {
+    pub fn buffers(&self) -> Vec<VideoDataBuffer<'_>> {
+        self.nal_units
+            .iter()
+            .map(|(ty, data)| VideoDataBuffer { ty: *ty, data })
+            .collect()
+    }
+
+    /// The decode unit borrows the buffers which are created by [FakeVideoFrame::buffers]
+    pub fn unit<'a>(&self, buffers: &'a [VideoDataBuffer<'a>]) -> VideoDecodeUnit<'a> {
+        VideoDecodeUnit {
+            frame_number: self.frame_number,
+            frame_type: self.frame_type,
+            frame_processing_latency: Some(Duration::from_millis(1)),
+            receive_time: self.presentation_time,
+            enqueue_time: self.presentation_time,
+            presentation_time: self.presentation_time,
+            hdr_active: false,
+            color_space: Colorspace::Rec709,
+            buffers,
+        }
+    }
+
+    /// All nal units concatenated like they're sent in an Annex B stream
+    pub fn data(&self) -> Vec<u8> {
+        self.nal_units
+            .iter()
+            .flat_map(|(_, data)| data)
+            .copied()
+            .collect()
+    }
+}
+
+/// Creates H264 frames, idr frames start with their sps and pps in separate buffers.
+pub struct FakeVideoSource {
+    width: u32,
+    height: u32,
+    fps: u32,
+    idr_interval: u32,
+    slice_size: usize,
+    frame_number: i32,
+    idr_requested: bool,
+}
+
+impl FakeVideoSource {
+    /// Sends an idr frame every second
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        Self {
+            width,
+            height,
+            fps,
+            idr_interval: fps,
+            slice_size: 512,
+            frame_number: 0,
+            idr_requested: false,
+        }
+    }
+
+    /// Zero only sends the first frame as an idr frame
+    pub fn with_idr_interval(mut self, frames: u32) -> Self {
+        self.idr_interval = frames;
+        self
+    }
+    pub fn with_slice_size(mut self, bytes: usize) -> Self {
+        self.slice_size = bytes;
+        self
+    }
+
+    pub fn setup(&self) -> VideoSetup {
+        VideoSetup {
+            format: VideoFormat::H264,
+            width: self.width,
+            height: self.height,
+            redraw_rate: self.fps,
+            flags: 0,
+        }
+    }
+
+    /// The next frame will be an idr frame
+    pub fn request_idr(&mut self) {
+        self.idr_requested = true;
+    }
+
+    pub fn next_frame(&mut self) -> FakeVideoFrame {
+        self.frame_number += 1;
+        let index = (self.frame_number - 1) as u32;
+
+        let is_idr = index == 0
+            || self.idr_requested
+            || (self.idr_interval != 0 && index.is_multiple_of(self.idr_interval));
+        self.idr_requested = false;
+
+        let mut nal_units = Vec::new();
+        let slice_header = if is_idr {
+            nal_units.push((BufferType::Sps, SPS.to_vec()));
+            nal_units.push((BufferType::Pps, PPS.to_vec()));
+
+            IDR_SLICE_HEADER
+        } else {
+            SLICE_HEADER
+        };
+
+        // Consecutive bytes never contain a start code
+        let mut slice = slice_header.to_vec();
+        slice.extend(
+            (0..self.slice_size).map(|offset| (self.frame_number as u8).wrapping_add(offset as u8)),
+        );
+        nal_units.push((BufferType::PicData, slice));
+
+        FakeVideoFrame {
+            frame_number: self.frame_number,
+            frame_type: if is_idr {
+                FrameType::Idr
+            } else {
+                FrameType::PFrame
+            },
+            presentation_time: Duration::from_secs(index as u64) / self.fps.max(1),
+            nal_units,
+        }
+    }
+
+    /// Requests an idr frame if the decoder returns [DecodeResult::NeedIdr]
+    pub fn submit_next(&mut self, decoder: &mut impl VideoDecoder) -> DecodeResult {
+        let frame = self.next_frame();
+        let buffers = frame.buffers();
+
+        let result = decoder.submit_decode_unit(frame.unit(&buffers));
+        if matches!(result, DecodeResult::NeedIdr) {
+            self.request_idr();
+        }
+
+        result
+    }
+
+    /// Sets up, starts and stops the decoder around the frames.
+    /// Returns the error of the setup if it failed.
+    pub fn run(&mut self, decoder: &mut impl VideoDecoder, frame_count: usize) -> i32 {
+        let result = decoder.setup(self.setup());
+        if result != 0 {
+            return result;
+        }
+
+        decoder.start();
+        for _ in 0..frame_count {
+            self.submit_next(decoder);
+        }
+        decoder.stop();
+
+        0
+    }
+}
+
+pub struct FakeAudioSource {
+    audio_config: AudioConfig,
+    stream_config: OpusMultistreamConfig,
+    sample_number: u32,
+}
+
+impl Default for FakeAudioSource {
+    fn default() -> Self {
+        Self::new(AudioConfig::STEREO, OpusMultistreamConfig::STEREO)
+    }
+}
+
+impl FakeAudioSource {
+    pub fn new(audio_config: AudioConfig, stream_config: OpusMultistreamConfig) -> Self {
+        Self {
+            audio_config,
+            stream_config,
+            sample_number: 0,
+        }
+    }
+
+    pub fn audio_config(&self) -> AudioConfig {
+        self.audio_config
+    }
+    pub fn stream_config(&self) -> &OpusMultistreamConfig {
+        &self.stream_config
+    }
+
+    /// An opus packet whose payload contains the number of the sample
+    pub fn next_sample(&mut self) -> Vec<u8> {
+        self.sample_number += 1;
+
+        let mut sample = vec![OPUS_TOC];
+        sample.extend_from_slice(&self.sample_number.to_be_bytes());
+        sample
+    }
+
+    /// Sets up, starts and stops the decoder around the samples.
+    /// Returns the error of the setup if it failed.
+    pub fn run(&mut self, decoder: &mut impl AudioDecoder, sample_count: usize) -> i32 {
+        let result = decoder.setup(self.audio_config, self.stream_config.clone(), 0);
+        if result != 0 {
+            return result;
+        }
+
+        decoder.start();
+        for _ in 0..sample_count {
+            decoder.decode_and_play_sample(&self.next_sample());
+        }
+        decoder.stop();
+
+        0
+    }
+}
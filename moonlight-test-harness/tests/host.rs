@@ -0,0 +1,161 @@
+use moonlight_common::{
+    PairPin, PairStatus,
+    high::HostError,
+    network::{
+        ApiError, HostType,
+        backend::reqwest::{ReqwestError, ReqwestMoonlightHost},
+        launch::AudioRouting,
+        request_client::CancellationToken,
+    },
+    pair::{PairError, generate_new_client},
+    units::Fps,
+};
+use moonlight_test_harness::{MockHost, MockHostConfig};
+
+async fn start_host() -> (MockHost, ReqwestMoonlightHost) {
+    let mock = MockHost::start(MockHostConfig::default())
+        .await
+        .expect("failed to start mock host");
+
+    let host = ReqwestMoonlightHost::new(mock.address(), mock.http_port(), None)
+        .expect("failed to create host");
+
+    (mock, host)
+}
+
+async fn pair(
+    host: &mut ReqwestMoonlightHost,
+    pin: [u8; 4],
+) -> Result<(), HostError<ReqwestError>> {
+    let auth = generate_new_client().expect("failed to generate client");
+    let pin = PairPin::from_array(pin).expect("valid pin");
+
+    host.pair(&auth, "test".to_string(), pin).await
+}
+
+#[tokio::test]
+async fn test_server_info() {
+    let (mock, mut host) = start_host().await;
+
+    assert_eq!(host.host_name().await.expect("host name"), "MockHost");
+    assert_eq!(
+        host.https_port().await.expect("https port"),
+        mock.https_port()
+    );
+    assert_eq!(host.current_game().await.expect("current game"), 0);
+    assert_eq!(host.is_paired(), PairStatus::NotPaired);
+    assert_eq!(
+        host.host_type().await.expect("host type"),
+        HostType::Sunshine
+    );
+}
+
+#[tokio::test]
+async fn test_pair_and_app_list() {
+    let (mock, mut host) = start_host().await;
+
+    pair(&mut host, [1, 2, 3, 4]).await.expect("pairing failed");
+    assert!(mock.is_paired());
+    assert_eq!(host.is_paired(), PairStatus::Paired);
+    assert_eq!(
+        host.verify_paired().await.expect("verify paired"),
+        PairStatus::Paired
+    );
+
+    let apps = host.app_list().await.expect("app list");
+    let titles = apps
+        .iter()
+        .map(|app| app.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Desktop", "Steam Big Picture"]);
+    assert!(apps[1].is_hdr_supported);
+
+    let box_art = host.request_app_image(1).await.expect("box art");
+    assert!(box_art.starts_with(b"\x89PNG"));
+}
+
+#[tokio::test]
+async fn test_cancelled_requests() {
+    let (mock, mut host) = start_host().await;
+
+    let cancellation_token = CancellationToken::new();
+    host.set_cancellation_token(cancellation_token.clone());
+    cancellation_token.cancel();
+
+    let err = host
+        .host_name()
+        .await
+        .expect_err("request succeeded after cancelling");
+    assert!(
+        matches!(
+            err,
+            HostError::Api(ApiError::RequestClient(ReqwestError::Cancelled))
+        ),
+        "unexpected error: {err:?}"
+    );
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_pair_wrong_pin() {
+    let (mock, mut host) = start_host().await;
+
+    let err = pair(&mut host, [4, 3, 2, 1])
+        .await
+        .expect_err("pairing with a wrong pin succeeded");
+
+    assert!(
+        matches!(err, HostError::Pair(PairError::IncorrectPin)),
+        "unexpected error: {err:?}"
+    );
+    assert!(!mock.is_paired());
+    assert_eq!(host.is_paired(), PairStatus::NotPaired);
+}
+
+#[tokio::test]
+async fn test_unpair() {
+    let (mock, mut host) = start_host().await;
+
+    pair(&mut host, [1, 2, 3, 4]).await.expect("pairing failed");
+    host.unpair().await.expect("unpair");
+
+    assert!(!mock.is_paired());
+    assert!(matches!(host.app_list().await, Err(HostError::NotPaired)));
+}
+
+#[tokio::test]
+async fn test_launch_and_cancel() {
+    let (mock, mut host) = start_host().await;
+    pair(&mut host, [1, 2, 3, 4]).await.expect("pairing failed");
+
+    let launched = host
+        .launch_app_only(2, 1920, 1080, Fps(60), false, false, AudioRouting::Client)
+        .await
+        .expect("launch");
+    assert!(launched);
+    assert_eq!(mock.current_game(), 2);
+
+    // Launching the running app again only resumes it
+    let launched = host
+        .launch_app_only(2, 1920, 1080, Fps(60), false, false, AudioRouting::Client)
+        .await
+        .expect("launch");
+    assert!(!launched);
+
+    assert!(host.cancel().await.expect("cancel"));
+    assert_eq!(mock.current_game(), 0);
+    assert!(mock.requests().iter().any(|path| path == "/launch"));
+}
+
+#[tokio::test]
+async fn test_launch_while_other_app_running() {
+    let (mock, mut host) = start_host().await;
+    pair(&mut host, [1, 2, 3, 4]).await.expect("pairing failed");
+
+    mock.set_current_game(1);
+
+    let result = host
+        .launch_app_only(2, 1920, 1080, Fps(60), false, false, AudioRouting::Client)
+        .await;
+    assert!(matches!(result, Err(HostError::AppAlreadyRunning)));
+}
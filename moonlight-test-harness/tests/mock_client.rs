@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use moonlight_common::{
+    PairStatus, ServerState,
+    high::HostError,
+    network::{
+        ApiError, ClientInfo, HostType, XmlParseMode,
+        backend::mock::{
+            MockError, MockMoonlightHost, MockRequestClient, MockResponse, MockScript, fixtures,
+        },
+        host_app_list,
+        request_client::{CancellationToken, RequestClient, RequestClientOptions, RequestError},
+    },
+};
+
+/// A fork which leaves out fields, changes the case of tags and adds its own namespaced fields
+const FORK_SERVER_INFO: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns:fork="urn:fork">
+    <HostName> ForkHost </HostName>
+    <appversion>7.1.431.-1</appversion>
+    <uniqueid>0f6b2c1d-8e4a-4b7f-9c3e-5a1d7e2f8b64</uniqueid>
+    <currentgame>3</currentgame>
+    <state>FORK_STREAMING</state>
+    <fork:Uptime>42</fork:Uptime>
+</root>"#;
+
+const FORK_APP_LIST: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns:fork="urn:fork" status_code="200">
+    <App><AppTitle> Desktop </AppTitle><ID>1</ID><fork:Category>desktop</fork:Category></App>
+    <App><AppTitle>Broken</AppTitle><ID>not a number</ID></App>
+    <app><apptitle>Steam</apptitle><id>2</id><IsHdrSupported>1</IsHdrSupported></app>
+</root>"#;
+
+fn host(script: &MockScript) -> MockMoonlightHost {
+    MockMoonlightHost::new(script.host_name().to_string(), 47989, None)
+        .expect("failed to create host")
+}
+
+#[tokio::test]
+async fn test_sunshine_fixture() {
+    let script = MockScript::new("mock-client-sunshine");
+    script.respond(
+        "serverinfo",
+        MockResponse::text(fixtures::SUNSHINE_SERVER_INFO),
+    );
+
+    let mut host = host(&script);
+
+    assert_eq!(host.host_name().await.expect("host name"), "SunshineHost");
+    assert_eq!(
+        host.host_type().await.expect("host type"),
+        HostType::Sunshine
+    );
+    assert_eq!(host.is_paired(), PairStatus::NotPaired);
+
+    let requests = script.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].hostport, "mock-client-sunshine:47989");
+    assert!(!requests[0].https);
+    assert!(requests[0].query_param("uniqueid").is_some());
+}
+
+#[tokio::test]
+async fn test_gfe_fixture() {
+    let script = MockScript::new("mock-client-gfe");
+    script.respond("serverinfo", MockResponse::text(fixtures::GFE_SERVER_INFO));
+
+    let mut host = host(&script);
+
+    assert_eq!(host.host_name().await.expect("host name"), "GFE-HOST");
+    assert_eq!(host.host_type().await.expect("host type"), HostType::Gfe);
+}
+
+#[tokio::test]
+async fn test_error_injection() {
+    let script = MockScript::new("mock-client-errors");
+    script
+        .respond_once("serverinfo", MockResponse::error(MockError::Connect))
+        .respond("serverinfo", MockResponse::text(fixtures::UNAUTHORIZED));
+
+    let err = host(&script)
+        .host_name()
+        .await
+        .expect_err("request succeeded with an injected error");
+    assert!(
+        matches!(&err, HostError::Api(ApiError::RequestClient(err)) if err.is_connect()),
+        "unexpected error: {err:?}"
+    );
+
+    let err = host(&script)
+        .host_name()
+        .await
+        .expect_err("request succeeded with an unauthorized response");
+    assert!(
+        matches!(err, HostError::Api(ApiError::InvalidXmlStatusCode { .. })),
+        "unexpected error: {err:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_latency_cancelled() {
+    let script = MockScript::new("mock-client-latency");
+    script.respond(
+        "serverinfo",
+        MockResponse::text(fixtures::SUNSHINE_SERVER_INFO),
+    );
+    script.set_latency(Duration::from_secs(60));
+
+    let mut host = host(&script);
+
+    let cancellation_token = CancellationToken::new();
+    host.set_cancellation_token(cancellation_token.clone());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancellation_token.cancel();
+    });
+
+    let err = host
+        .host_name()
+        .await
+        .expect_err("request succeeded after cancelling");
+    assert!(
+        matches!(
+            err,
+            HostError::Api(ApiError::RequestClient(MockError::Cancelled))
+        ),
+        "unexpected error: {err:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_lenient_server_info() {
+    let script = MockScript::new("mock-client-fork-info");
+    script.respond("serverinfo", MockResponse::text(FORK_SERVER_INFO));
+
+    assert!(host(&script).host_name().await.is_err());
+
+    let mut host = host(&script);
+    host.set_xml_parse_mode(XmlParseMode::Lenient);
+
+    assert_eq!(host.host_name().await.expect("host name"), "ForkHost");
+    assert_eq!(host.https_port().await.expect("https port"), 47984);
+    assert_eq!(host.current_game().await.expect("current game"), 3);
+    assert!(matches!(
+        host.state().await.expect("state").1,
+        ServerState::Busy
+    ));
+}
+
+#[tokio::test]
+async fn test_lenient_app_list() {
+    let script = MockScript::new("mock-client-fork-apps");
+    script.respond("applist", MockResponse::text(FORK_APP_LIST));
+
+    let mut client =
+        MockRequestClient::with_defaults(&RequestClientOptions::default()).expect("client");
+    let cancel = CancellationToken::new();
+
+    let strict = host_app_list(
+        &mut client,
+        &cancel,
+        "mock-client-fork-apps:47984",
+        ClientInfo::default(),
+        XmlParseMode::Strict,
+    )
+    .await;
+    assert!(strict.is_err(), "the invalid app id was accepted");
+
+    let apps = host_app_list(
+        &mut client,
+        &cancel,
+        "mock-client-fork-apps:47984",
+        ClientInfo::default(),
+        XmlParseMode::Lenient,
+    )
+    .await
+    .expect("lenient app list")
+    .apps;
+
+    assert_eq!(apps.len(), 2);
+    assert_eq!(apps[0].title, "Desktop");
+    assert!(!apps[0].is_hdr_supported);
+    assert_eq!(apps[0].unknown_fields.get("Category"), Some("desktop"));
+    assert_eq!(apps[1].title, "Steam");
+    assert!(apps[1].is_hdr_supported);
+}
@@ -0,0 +1,97 @@
+use moonlight_common::stream::{
+    bindings::{AudioConfig, DecodeResult, FrameType, SupportedVideoFormats, VideoDecodeUnit},
+    video::{VideoDecoder, VideoSetup},
+};
+use moonlight_test_harness::{
+    FakeAudioSource, FakeVideoSource, RecordingAudioDecoder, RecordingVideoDecoder,
+};
+
+#[test]
+fn test_video_frames() {
+    let mut source = FakeVideoSource::new(1280, 720, 30).with_idr_interval(10);
+    let mut decoder = RecordingVideoDecoder::new(SupportedVideoFormats::H264);
+    let recording = decoder.recording();
+
+    assert_eq!(source.run(&mut decoder, 25), 0);
+
+    let recording = recording.lock().expect("recording");
+    assert!(recording.started && recording.stopped);
+
+    let setup = recording.setup.expect("video setup");
+    assert_eq!(
+        (setup.width, setup.height, setup.redraw_rate),
+        (1280, 720, 30)
+    );
+
+    let idr_frames = recording
+        .frames
+        .iter()
+        .filter(|frame| matches!(frame.frame_type, FrameType::Idr))
+        .map(|frame| frame.frame_number)
+        .collect::<Vec<_>>();
+    assert_eq!(idr_frames, [1, 11, 21]);
+
+    // Idr frames start with the sps
+    assert!(recording.frames[0].data.starts_with(&[0, 0, 0, 1, 0x67]));
+    assert!(recording.frames[1].data.starts_with(&[0, 0, 0, 1, 0x41]));
+}
+
+/// Requests an idr frame for the first frame after a "packet loss"
+struct LossyDecoder {
+    lost_frame: i32,
+    idr_frames: Vec<i32>,
+}
+
+impl VideoDecoder for LossyDecoder {
+    fn setup(&mut self, _setup: VideoSetup) -> i32 {
+        0
+    }
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+
+    fn submit_decode_unit(&mut self, unit: VideoDecodeUnit<'_>) -> DecodeResult {
+        if matches!(unit.frame_type, FrameType::Idr) {
+            self.idr_frames.push(unit.frame_number);
+        }
+
+        if unit.frame_number == self.lost_frame {
+            DecodeResult::NeedIdr
+        } else {
+            DecodeResult::Ok
+        }
+    }
+
+    fn supported_formats(&self) -> SupportedVideoFormats {
+        SupportedVideoFormats::H264
+    }
+}
+
+#[test]
+fn test_video_need_idr() {
+    let mut source = FakeVideoSource::new(1280, 720, 60).with_idr_interval(0);
+    let mut decoder = LossyDecoder {
+        lost_frame: 5,
+        idr_frames: Vec::new(),
+    };
+
+    source.run(&mut decoder, 10);
+
+    assert_eq!(decoder.idr_frames, [1, 6]);
+}
+
+#[test]
+fn test_audio_samples() {
+    let mut source = FakeAudioSource::default();
+    let mut decoder = RecordingAudioDecoder::new(AudioConfig::STEREO);
+    let recording = decoder.recording();
+
+    assert_eq!(source.run(&mut decoder, 3), 0);
+
+    let recording = recording.lock().expect("recording");
+    let (audio_config, stream_config) = recording.setup.clone().expect("audio setup");
+    assert_eq!(audio_config, AudioConfig::STEREO);
+    assert_eq!(stream_config.channel_count, 2);
+
+    assert_eq!(recording.samples.len(), 3);
+    assert_eq!(recording.samples[2], [0xfc, 0, 0, 0, 3]);
+}
@@ -0,0 +1,55 @@
+//! Embeds the git commits of this repository and of moonlight-common-c, see `build_info.rs`.
+//! Builds without a git checkout can set `MOONLIGHT_WEB_GIT_HASH` instead.
+
+use std::{env::var, path::Path, process::Command};
+
+fn main() {
+    println!("cargo::rerun-if-env-changed=MOONLIGHT_WEB_GIT_HASH");
+
+    let Ok(manifest_dir) = var("CARGO_MANIFEST_DIR") else {
+        return;
+    };
+    let root = Path::new(&manifest_dir).join("../..");
+
+    // Rebuild when a commit is checked out or the current branch moves
+    if let Some(git_dir) = git(&root, &["rev-parse", "--absolute-git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        println!("cargo::rerun-if-changed={}", git_dir.join("HEAD").display());
+
+        if let Some(reference) = git(&root, &["symbolic-ref", "-q", "HEAD"]) {
+            println!(
+                "cargo::rerun-if-changed={}",
+                git_dir.join(reference).display()
+            );
+        }
+    }
+
+    let git_hash = var("MOONLIGHT_WEB_GIT_HASH")
+        .ok()
+        .or_else(|| git(&root, &["rev-parse", "--short=12", "HEAD"]));
+    if let Some(git_hash) = git_hash {
+        println!("cargo::rustc-env=MOONLIGHT_WEB_GIT_HASH={git_hash}");
+    }
+
+    // The commit of the submodule which is recorded in this repository
+    if let Some(commit) = git(
+        &root,
+        &["rev-parse", "HEAD:moonlight-common-sys/moonlight-common-c"],
+    ) {
+        println!("cargo::rustc-env=MOONLIGHT_COMMON_C_COMMIT={commit}");
+    }
+}
+
+fn git(directory: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
@@ -1,11 +1,16 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+};
 
 use moonlight_common::{
     ServerState,
+    network::{self, launch::AudioRouting},
     stream::bindings::{
-        Colorspace, ControllerButtons, ControllerCapabilities, KeyModifiers, MouseButton,
-        SupportedVideoFormats,
+        Colorspace, ControllerButtons, ControllerCapabilities, ControllerType, KeyModifiers,
+        MouseButton, SupportedVideoFormats,
     },
+    units::{Bytes, Fps, Kbps},
 };
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
@@ -43,6 +48,36 @@ impl From<ServerState> for HostState {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum HostType {
+    Gfe,
+    Sunshine,
+    Apollo,
+    Unknown,
+}
+
+impl From<network::HostType> for HostType {
+    fn from(value: network::HostType) -> Self {
+        match value {
+            network::HostType::Gfe => Self::Gfe,
+            network::HostType::Sunshine => Self::Sunshine,
+            network::HostType::Apollo => Self::Apollo,
+            network::HostType::Unknown => Self::Unknown,
+        }
+    }
+}
+
+/// The features the host supports, the web server won't use the others
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostCapabilities {
+    pub hdr: bool,
+    /// Controllers with touchpads or motion sensors
+    pub extended_gamepads: bool,
+    pub pen_touch: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum PairStatus {
@@ -96,8 +131,28 @@ pub struct DetailedHost {
     pub mac: Option<String>,
     pub local_ip: String,
     pub current_game: u32,
+    pub current_session: Option<HostSession>,
     pub max_luma_pixels_hevc: u32,
     pub server_codec_mode_support: u32,
+    /// Unknown if the host is offline
+    pub host_type: HostType,
+    /// Nothing is supported if the host is offline
+    pub capabilities: HostCapabilities,
+    /// Free-form notes written by the users of this host
+    pub notes: String,
+    pub labels: BTreeMap<String, String>,
+    /// The address of the host is inside a Tailscale network, so it's reachable without port forwarding
+    pub tunnel_reachable: bool,
+}
+
+/// The app which is currently running on the host.
+/// Sunshine doesn't report the display mode or hdr state of the running session, so only the app is known.
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostSession {
+    pub app_id: u32,
+    /// Only present if the app list of the host could be fetched
+    pub app_title: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -118,10 +173,37 @@ impl From<moonlight_common::network::App> for App {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+#[serde(rename_all = "lowercase")]
+pub enum ListSortBy {
+    #[default]
+    Id,
+    Name,
+}
+
+/// Without a limit all hosts are returned
+#[derive(Serialize, Deserialize, Debug, TS, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostsQuery {
+    /// The next_cursor of the previous page
+    pub cursor: Option<String>,
+    /// All remaining entries if none, zero is rejected
+    pub limit: Option<u32>,
+    pub sort_by: Option<ListSortBy>,
+    pub descending: Option<bool>,
+    pub owner: Option<HostOwner>,
+    pub paired: Option<PairStatus>,
+    /// Case insensitive part of the host name
+    pub name: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetHostsResponse {
     pub hosts: Vec<UndetailedHost>,
+    /// Some if there are more hosts
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -157,6 +239,10 @@ pub struct PatchHostRequest {
     /// Option<Option<u32>> are not supported
     pub change_owner: bool,
     pub owner: Option<u32>,
+    /// If none the notes aren't changed
+    pub notes: Option<String>,
+    /// If none the labels aren't changed, otherwise all labels are replaced
+    pub labels: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -176,7 +262,22 @@ pub struct PostPairRequest {
 pub enum PostPairResponse1 {
     InternalServerError,
     PairError,
-    Pin(String),
+    Pin(PairPinInfo),
+}
+
+/// The pin which has to be entered on the host and other representations of it
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PairPinInfo {
+    pub pin: String,
+    /// The digits separated by spaces so that screen readers read them one by one
+    pub spoken_pin: String,
+    /// The pin page of the Sunshine web ui with the pin in the fragment, which can be shown as a qr code.
+    /// The fragment isn't sent to the host.
+    pub otp_url: String,
+    /// The web server submits the pin to Sunshine itself, entering it isn't required.
+    /// Always false for hosts which were never paired.
+    pub auto_submit: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -192,6 +293,82 @@ pub struct PostWakeUpRequest {
     pub host_id: u32,
 }
 
+/// The client certificate which this web server uses for a host
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostCertificate {
+    pub host_id: u32,
+    pub name: String,
+    /// Unix timestamps in seconds
+    pub not_before: u64,
+    pub not_after: u64,
+    pub age_days: u32,
+    /// Zero if the certificate already expired
+    pub expires_in_days: u32,
+    pub rotation_due: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostCertificatesResponse {
+    /// Only contains paired hosts
+    pub certificates: Vec<HostCertificate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostHostCertificateRotateRequest {
+    pub host_id: u32,
+    /// Also rotates the certificate if it doesn't expire soon
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostHostCertificateRotateResponse {
+    pub rotated: bool,
+}
+
+/// The client which the web server keeps for a host, requests through it reuse its connections
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostClientStats {
+    pub host_id: u32,
+    /// A new client is only created when the certificates of the host changed
+    pub clients_created: u64,
+    pub requests: u64,
+}
+
+/// Only for admins
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostClientsResponse {
+    pub clients: Vec<HostClientStats>,
+}
+
+/// A machine in the Tailscale network of the web server
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct TailscalePeer {
+    pub host_name: String,
+    /// The MagicDNS name, e.g. "gamingpc.tailnet-1234.ts.net"
+    pub dns_name: String,
+    /// The address to add the host with, the first tailscale ipv4 if there is one
+    pub address: String,
+    pub addresses: Vec<String>,
+    pub os: String,
+    pub online: bool,
+    /// A host of the user with the same name which isn't added with its tailscale address yet
+    pub suggested_host_id: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetTailscalePeersResponse {
+    pub peers: Vec<TailscalePeer>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetAppsQuery {
@@ -204,6 +381,27 @@ pub struct GetAppsResponse {
     pub apps: Vec<App>,
 }
 
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostDisplaysQuery {
+    pub host_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostDisplaysResponse {
+    pub displays: Vec<HostDisplay>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HostDisplay {
+    pub display_id: u32,
+    pub name: String,
+    /// The apps which can be streamed on this display
+    pub app_ids: Vec<u32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetAppImageQuery {
@@ -213,6 +411,102 @@ pub struct GetAppImageQuery {
     pub force_refresh: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppImagesPrefetchRequest {
+    pub host_id: u32,
+    /// Also fetches images which are already cached
+    #[serde(default)]
+    pub force_refresh: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppImagesPrefetchResponse1 {
+    /// The amount of images which will be fetched
+    pub total: u32,
+}
+
+/// Sent after every fetched image
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppImagesPrefetchResponse2 {
+    pub app_id: u32,
+    pub success: bool,
+    /// The amount of images which are finished, including this one
+    pub finished: u32,
+    pub total: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppLaunchRequest {
+    pub host_id: u32,
+    pub app_id: u32,
+    pub width: u32,
+    pub height: u32,
+    #[ts(as = "u32")]
+    pub fps: Fps,
+    #[serde(default)]
+    pub hdr: bool,
+    #[serde(default)]
+    pub play_audio_local: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppLaunchResponse {
+    /// False if the app was already running
+    pub launched: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppQuitRequest {
+    pub host_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostAppQuitResponse {
+    pub success: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetAppStreamDefaultsQuery {
+    pub host_id: u32,
+    pub app_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetAppStreamDefaultsResponse {
+    /// The settings of the last successful stream of this app, if any
+    pub defaults: Option<StreamDefaults>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamDefaults {
+    #[ts(as = "u32")]
+    pub bitrate: Kbps,
+    #[ts(as = "u32")]
+    pub fps: Fps,
+    pub width: u32,
+    pub height: u32,
+    /// Use VideoSupportedCodec to figure this out
+    pub video_format: u32,
+}
+
+/// Without resume a new stream is started
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetHostStreamQuery {
+    /// The token of [StreamServerMessage::ResumeToken]
+    pub resume: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostCancelRequest {
@@ -222,7 +516,66 @@ pub struct PostCancelRequest {
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostCancelResponse {
+    /// If the host quit the app
     pub success: bool,
+    /// If a stream of this web server was running on the host and got stopped
+    pub streamer_terminated: bool,
+}
+
+/// A stream which currently runs through this web server
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSession {
+    /// Admins can download the diagnostics of the session at `/api/session/{session_id}/diagnostics`
+    pub session_id: u32,
+    pub host_id: u32,
+    pub user_id: u32,
+    pub user_name: String,
+    /// See [GeneralServerMessage::QualityScore], none until the streamer rated the connection
+    pub quality_score: Option<u8>,
+    /// Devices in the local network can watch the session at `rtsp://<web server>:<rtsp_port>/`, none without the rtsp output
+    pub rtsp_port: Option<u16>,
+}
+
+/// Admins get all streams, other users only their own
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSessionsResponse {
+    pub sessions: Vec<StreamSession>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenshotFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostScreenshotQuery {
+    /// Png if not set
+    pub format: Option<ScreenshotFormat>,
+}
+
+/// The playlist of the hls output of a session, which cast receivers can load without credentials
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostSessionHlsResponse {
+    /// Includes the url path prefix of the web server
+    pub playlist_path: String,
+}
+
+/// The query of a blocking playlist reload of low latency hls players
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSessionHlsQuery {
+    #[serde(rename = "_HLS_msn")]
+    pub msn: Option<u64>,
+    #[serde(rename = "_HLS_part")]
+    pub part: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -247,6 +600,21 @@ pub struct DetailedUser {
     pub name: String,
     pub role: UserRole,
     pub client_unique_id: String,
+    pub stream_limits: UserStreamLimits,
+    /// Streams of the user have no thumbnails or preview and their diagnostics aren't kept
+    pub privacy_mode: bool,
+}
+
+/// The highest settings the user can stream with, higher settings are lowered to them before the stream starts.
+/// Unset values aren't limited.
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct UserStreamLimits {
+    /// In kbps
+    #[ts(as = "Option<u32>")]
+    pub max_bitrate: Option<Kbps>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -266,6 +634,12 @@ pub struct PatchUserRequest {
     pub password: Option<String>,
     pub role: Option<UserRole>,
     pub client_unique_id: Option<String>,
+    /// Only admins can change the limits
+    #[serde(default)]
+    pub stream_limits: Option<UserStreamLimits>,
+    /// Users can change their own privacy mode
+    #[serde(default)]
+    pub privacy_mode: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -274,10 +648,217 @@ pub struct DeleteUserRequest {
     pub id: u32,
 }
 
+/// Without a limit all users are returned
+#[derive(Serialize, Deserialize, Debug, TS, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetUsersQuery {
+    /// The next_cursor of the previous page
+    pub cursor: Option<String>,
+    /// All remaining entries if none, zero is rejected
+    pub limit: Option<u32>,
+    pub sort_by: Option<ListSortBy>,
+    pub descending: Option<bool>,
+    pub role: Option<UserRole>,
+    /// Case insensitive part of the user name
+    pub name: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetUsersResponse {
     pub users: Vec<DetailedUser>,
+    /// Some if there are more users
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct BuildInfo {
+    pub version: String,
+    /// The commit of moonlight-web, none if it wasn't built in a git checkout
+    pub git_hash: Option<String>,
+    /// The enabled cargo features
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetVersionResponse {
+    pub web_server: BuildInfo,
+    /// None if the streamer couldn't be started
+    pub streamer: Option<BuildInfo>,
+    pub moonlight_common: String,
+    pub moonlight_common_c_commit: Option<String>,
+}
+
+/// What the installed streamer supports, it's sent when the streamer starts.
+/// Streamers which don't know a newer subsystem don't send it, so every field defaults to unsupported.
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, Default, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamerCapabilities {
+    /// Needed for screenshots, thumbnails and the session preview, requires the "screenshot" feature
+    #[serde(default)]
+    pub video_decoding: bool,
+    #[serde(default)]
+    pub rtsp_output: bool,
+    #[serde(default)]
+    pub hls_output: bool,
+    #[serde(default)]
+    pub hls_recording: bool,
+}
+
+/// What clients of this web server can use, a subsystem must be supported by the streamer and enabled in the config
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetCapabilitiesResponse {
+    pub streamer: StreamerCapabilities,
+    pub screenshots: bool,
+    pub session_preview: bool,
+    pub session_thumbnails: bool,
+    pub rtsp_output: bool,
+    pub hls_output: bool,
+    pub hls_recording: bool,
+}
+
+// -- Sunshine
+
+/// Used by all Sunshine endpoints which only need the host
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSunshineQuery {
+    pub host_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct SunshineApp {
+    pub name: String,
+    pub cmd: String,
+    pub working_dir: String,
+    pub image_path: String,
+    /// Commands which are started in the background
+    pub detached: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSunshineAppsResponse {
+    pub apps: Vec<SunshineApp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostSunshineAppRequest {
+    pub host_id: u32,
+    pub app: SunshineApp,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetSunshineEncoderResponse {
+    /// Only the settings which were changed from the Sunshine defaults
+    pub settings: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PatchSunshineEncoderRequest {
+    pub host_id: u32,
+    /// An empty value resets the setting to the Sunshine default
+    pub settings: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct SunshineCredentials {
+    pub username: String,
+    pub password: String,
+    /// Defaults to the http port of the host + 1
+    pub web_ui_port: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PutSunshineCredentialsRequest {
+    pub host_id: u32,
+    /// None removes the stored credentials
+    pub credentials: Option<SunshineCredentials>,
+}
+
+// -- Input Macros
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct InputMacroInfo {
+    pub id: u32,
+    pub name: String,
+    pub event_count: u32,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetInputMacrosResponse {
+    pub macros: Vec<InputMacroInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeleteInputMacroQuery {
+    pub id: u32,
+}
+
+// -- Web Push
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetWebPushKeyResponse {
+    /// The `applicationServerKey` for `PushManager.subscribe`, base64url encoded
+    pub public_key: String,
+}
+
+/// The json of a `PushSubscription` of the browser
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PostPushSubscriptionRequest {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PushSubscriptionKeys {
+    /// Base64url encoded
+    pub p256dh: String,
+    /// Base64url encoded
+    pub auth: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeletePushSubscriptionQuery {
+    pub endpoint: String,
+}
+
+/// The payload of a push message, the service worker shows it as a notification
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct PushNotification {
+    pub kind: PushNotificationKind,
+    pub title: String,
+    pub body: String,
+    pub host_id: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum PushNotificationKind {
+    /// A host which the user woke is reachable
+    HostOnline,
+    /// The stream which kept the host busy for the user ended
+    HostAvailable,
+    /// Something an admin should look at, e.g. a crashed streamer
+    AdminAttention,
 }
 
 // -- Stream
@@ -319,6 +900,23 @@ ts_consts!(
     pub const CONTROLLER13: u8 = 23;
     pub const CONTROLLER14: u8 = 24;
     pub const CONTROLLER15: u8 = 25;
+    pub const FILE: u8 = 26;
+    /// Like [TransportChannelId::GENERAL] but for messages which are outdated by the next one
+    pub const GENERAL_UNRELIABLE: u8 = 27;
+    /// The cursor position which the streamer forwarded to the host, see [GeneralClientMessage::SetCursorPrediction]
+    pub const CURSOR: u8 = 28;
+);
+
+// Reasons why a file transfer on the file channel failed
+ts_consts!(
+    pub FileTransferErrorCode(export_bindings_file_transfer_error_code: EXPORT_PATH) as u8:
+
+    pub const DISABLED: u8 = 0;
+    pub const TOO_LARGE: u8 = 1;
+    pub const INVALID_NAME: u8 = 2;
+    pub const UNKNOWN_TRANSFER: u8 = 3;
+    pub const INVALID_OFFSET: u8 = 4;
+    pub const IO: u8 = 5;
 );
 
 #[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
@@ -374,15 +972,46 @@ pub enum StreamClientMessage {
     WebRtc(StreamSignalingMessage),
     SetTransport(TransportType),
     StartStream {
-        bitrate: u32,
-        packet_size: u32,
-        fps: u32,
+        /// In kbps
+        #[ts(as = "u32")]
+        bitrate: Kbps,
+        /// In bytes
+        #[ts(as = "u32")]
+        packet_size: Bytes,
+        #[ts(as = "u32")]
+        fps: Fps,
         width: u32,
         height: u32,
-        play_audio_local: bool,
+        audio_routing: StreamAudioRouting,
+        /// The formats the client can decode, the web server picks the codec from these
         video_supported_formats: u32,
         video_colorspace: StreamColorspace,
         video_color_range_full: bool,
+        /// See [GetHostDisplaysResponse], none streams the default display of the app
+        display_id: Option<u32>,
+        /// The rotation the browser applies to the video, used to stream vertical apps on a phone in portrait
+        video_rotation: StreamVideoRotation,
+        /// The name of a [StreamPreset], the web server replaces the settings above with the ones of the preset
+        #[serde(default)]
+        preset: Option<String>,
+    },
+    /// Stops the stream which is running on the host and retries starting this stream, see [StreamServerMessage::HostBusy]
+    Takeover,
+    /// Changes where the audio is played while streaming.
+    /// Playing it on the host or not requires restarting the stream on the host, the video will freeze shortly.
+    SetAudioRouting {
+        audio_routing: StreamAudioRouting,
+    },
+    /// Changes the quality while streaming, the web server checks it against the same limits as [StreamClientMessage::StartStream].
+    /// The host only applies encoder settings when launching, so the stream is restarted at the next keyframe and the video will freeze shortly.
+    SetQuality {
+        /// In kbps
+        #[ts(as = "u32")]
+        bitrate: Kbps,
+        #[ts(as = "u32")]
+        fps: Fps,
+        width: u32,
+        height: u32,
     },
 }
 
@@ -426,9 +1055,68 @@ pub enum LogMessageType {
     Recover,
 }
 
+/// The english texts are in [crate::messages]
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StreamMessageCode {
+    LaunchStreamer,
+    LaunchStreamerCompleted,
+    WaitingForTransport,
+    MoonlightStream,
+    StageStarting {
+        stage: String,
+    },
+    StageCompleted {
+        stage: String,
+    },
+    StageFailed {
+        stage: String,
+        error_code: i32,
+    },
+    HostNotFound,
+    AppNotFound,
+    HostNotPaired,
+    ServerError,
+    NoCommonVideoCodec,
+    UnsupportedVideoFormat {
+        format: String,
+        supported_formats: String,
+    },
+    VideoTrackFailed {
+        format: String,
+    },
+    TakeoverFailed,
+    /// The host didn't send video for a while, an IDR frame was requested
+    VideoStalled,
+    /// The host still didn't send video, the connection to it is restarted
+    VideoStallRestart,
+    /// The host sends video again after a [StreamMessageCode::VideoStalled]
+    VideoRecovered,
+    /// The web socket couldn't be resumed because the stream already ended or the resume timed out
+    ResumeFailed,
+    /// The host is offline, Wake-on-LAN is sent before the stream starts
+    WakingHost,
+    WaitingForHostBoot,
+    HostBooted,
+    /// The host didn't come online in time
+    HostOffline,
+    /// The preset of [StreamClientMessage::StartStream] isn't configured, the stream is stopped
+    UnknownStreamPreset {
+        name: String,
+    },
+    /// The connection to the host is restarted with the app of [GeneralClientMessage::SwitchApp]
+    SwitchingApp,
+    /// The app of [GeneralClientMessage::SwitchApp] doesn't exist or the user may not use it, the current app keeps running
+    SwitchAppDenied,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum StreamServerMessage {
+    /// The first message, a dropped web socket can be reconnected with `/host/stream?resume=<token>`
+    ResumeToken {
+        token: String,
+    },
     Setup {
         ice_servers: Vec<RtcIceServer>,
     },
@@ -440,6 +1128,9 @@ pub enum StreamServerMessage {
     DebugLog {
         message: String,
         ty: Option<LogMessageType>,
+        /// Identifies the message so that clients can show their own translation instead
+        #[serde(default)]
+        code: Option<StreamMessageCode>,
     },
     ConnectionComplete {
         capabilities: StreamCapabilities,
@@ -447,24 +1138,81 @@ pub enum StreamServerMessage {
         format: u32,
         width: u32,
         height: u32,
-        fps: u32,
+        #[ts(as = "u32")]
+        fps: Fps,
         audio_channels: u32,
         audio_sample_rate: u32,
+        /// How long the stages of connecting to the host took
+        stage_timings: Vec<StatsStageTiming>,
     },
     ConnectionTerminated {
         error_code: i32,
+        reason: TerminationReason,
+        /// A human readable explanation of the reason
+        description: String,
+    },
+    /// The stream couldn't start because the host is already streaming.
+    /// The client can answer with [StreamClientMessage::Takeover] if it's allowed to.
+    HostBusy {
+        /// The user of this web server which is streaming, none if unknown
+        current_user: Option<String>,
+        can_takeover: bool,
+    },
+    /// A setting of [StreamClientMessage::StartStream] is outside of what the web server allows for the host, the stream is stopped
+    SettingsRejected {
+        field: StreamSettingsField,
+        allowed_range: StreamSettingRange,
+    },
+    /// A setting of [StreamClientMessage::SetQuality] is outside of what the web server allows for the host, the stream keeps its quality
+    QualityRejected {
+        field: StreamSettingsField,
+        allowed_range: StreamSettingRange,
     },
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum GeneralServerMessage {
-    ConnectionStatusUpdate { status: ConnectionStatus },
+    ConnectionStatusUpdate {
+        status: ConnectionStatus,
+    },
+    /// The recorded macro was stored and can be run with [GeneralClientMessage::RunMacro]
+    MacroSaved {
+        id: u32,
+        name: String,
+    },
+    /// The quality of the connection from 1 (bad) to 5 (excellent),
+    /// rated from the rtt, jitter, lost packets and dropped frames
+    QualityScore {
+        score: u8,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
-pub enum GeneralClientMessage {}
+pub enum GeneralClientMessage {
+    /// Records the input of this client until [GeneralClientMessage::StopMacroRecording]
+    StartMacroRecording,
+    /// Saves the recorded input, an empty name discards it
+    StopMacroRecording {
+        name: String,
+    },
+    /// Replays a macro of the user with the recorded timing
+    RunMacro {
+        id: u32,
+    },
+    CancelMacro,
+    /// Echoes the forwarded absolute cursor position on [TransportChannelId::CURSOR],
+    /// so the client can correct the cursor it draws locally
+    SetCursorPrediction {
+        enabled: bool,
+    },
+    /// Quits the running app and launches another app of the host,
+    /// the WebRTC peer with its tracks and data channels stays connected
+    SwitchApp {
+        app_id: u32,
+    },
+}
 
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
@@ -483,6 +1231,57 @@ impl From<moonlight_common::stream::bindings::ConnectionStatus> for ConnectionSt
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum TerminationReason {
+    Graceful,
+    NoVideoTraffic,
+    NoVideoFrame,
+    UnexpectedEarlyTermination,
+    ProtectedContent,
+    FrameConversion,
+    /// Look at the error code
+    Unknown,
+}
+
+impl From<moonlight_common::stream::connection::TerminationReason> for TerminationReason {
+    fn from(value: moonlight_common::stream::connection::TerminationReason) -> Self {
+        use moonlight_common::stream::connection::TerminationReason;
+        match value {
+            TerminationReason::Graceful => Self::Graceful,
+            TerminationReason::NoVideoTraffic => Self::NoVideoTraffic,
+            TerminationReason::NoVideoFrame => Self::NoVideoFrame,
+            TerminationReason::UnexpectedEarlyTermination => Self::UnexpectedEarlyTermination,
+            TerminationReason::ProtectedContent => Self::ProtectedContent,
+            TerminationReason::FrameConversion => Self::FrameConversion,
+            TerminationReason::Other(_) => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StatsStageTiming {
+    /// The name of the moonlight stage
+    pub stage: String,
+    pub duration_ms: f64,
+    /// Set if the stage failed
+    pub error_code: Option<i32>,
+}
+
+/// The input packets of one channel since the stream started
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StatsInputChannel {
+    /// Look at [TransportChannelId]
+    pub channel: u8,
+    pub packets: u32,
+    /// Packets which were malformed
+    pub parse_failures: u32,
+    /// Packets which didn't reach the host, e.g. because the stream wasn't running or the controller wasn't connected
+    pub forwarding_errors: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct StatsHostProcessingLatency {
@@ -504,6 +1303,49 @@ pub enum StreamerStatsUpdate {
         max_streamer_processing_time_ms: f64,
         avg_streamer_processing_time_ms: f64,
     },
+    AudioJitterBuffer {
+        delay_ms: f64,
+        target_delay_ms: f64,
+        jitter_ms: f64,
+        /// Samples which were missing when they should've been played since the last update
+        underruns: u32,
+        /// Samples which were dropped because the buffer was too full since the last update
+        dropped: u32,
+    },
+    /// Results of the latency test since the last update.
+    /// The estimate adds the host processing latency, half of the host rtt, the streamer processing time
+    /// and the time until the client echoed the frame. It includes the way back of the echo, so it's an upper bound.
+    Latency {
+        min_estimated_latency_ms: f64,
+        max_estimated_latency_ms: f64,
+        avg_estimated_latency_ms: f64,
+        /// From sending a frame to the client until its echo was received
+        avg_display_round_trip_ms: f64,
+        echoed_frames: u32,
+    },
+    /// Sent once the stream started, the same as in [StreamServerMessage::ConnectionComplete]
+    StageTimings {
+        stages: Vec<StatsStageTiming>,
+    },
+    /// Sent once a second while input is received, only contains the channels which received packets
+    Input {
+        channels: Vec<StatsInputChannel>,
+    },
+}
+
+/// Sent by the client on the stats channel
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StatsClientMessage {
+    SetLatencyTest {
+        enabled: bool,
+    },
+    /// A frame was displayed while the latency test is enabled.
+    /// The presentation time is the timestamp of the web socket video unit
+    /// or the rtp timestamp converted to microseconds, both wrap around at u32::MAX.
+    LatencyEcho {
+        presentation_time_us: u32,
+    },
 }
 
 // Virtual-Key Codes
@@ -1021,6 +1863,16 @@ ts_consts!(
     pub const CAPABILITY_TRIGGER_RUMBLE: u16 = ControllerCapabilities::TRIGGER_RUMBLE.bits();
 );
 
+// Controller Types
+ts_consts!(
+    pub StreamControllerType(export_bindings_controller_types: EXPORT_PATH):
+
+    pub const UNKNOWN: u8 = ControllerType::Unknown as u8;
+    pub const XBOX: u8 = ControllerType::Xbox as u8;
+    pub const PLAYSTATION: u8 = ControllerType::PlayStation as u8;
+    pub const NINTENDO: u8 = ControllerType::Nintendo as u8;
+);
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum StreamColorspace {
@@ -1039,6 +1891,92 @@ impl From<StreamColorspace> for Colorspace {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StreamAudioRouting {
+    /// Only the browser plays the audio
+    Client,
+    /// Only the host plays the audio
+    Host,
+    Both,
+}
+
+impl From<StreamAudioRouting> for AudioRouting {
+    fn from(value: StreamAudioRouting) -> Self {
+        match value {
+            StreamAudioRouting::Client => AudioRouting::Client,
+            StreamAudioRouting::Host => AudioRouting::Host,
+            StreamAudioRouting::Both => AudioRouting::Both,
+        }
+    }
+}
+
+/// Clockwise rotation of the video.
+/// Hosts can't rotate the stream themselves, so the rotation is applied by the browser.
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StreamVideoRotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// The settings of [StreamClientMessage::StartStream] which are limited by the web server
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StreamSettingsField {
+    Width,
+    Height,
+    Fps,
+    Bitrate,
+}
+
+/// Named stream settings configured by the admin, selected with the preset of [StreamClientMessage::StartStream]
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamPreset {
+    /// e.g. "Battery saver" or "Max quality"
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    #[ts(as = "u32")]
+    pub fps: Fps,
+    /// In kbps, also accepts strings like "20 Mbps" in the config
+    #[ts(as = "u32")]
+    pub bitrate: Kbps,
+    /// The packet size of the client is kept if this isn't set
+    #[serde(default)]
+    #[ts(as = "Option<u32>")]
+    pub packet_size: Option<Bytes>,
+    /// The color range of the client is kept if this isn't set
+    #[serde(default)]
+    pub video_color_range_full: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetStreamPresetsResponse {
+    pub presets: Vec<StreamPreset>,
+}
+
+/// An inclusive range of the values a setting can have
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamSettingRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl StreamSettingRange {
+    pub fn contains(&self, value: u32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
 // Video Supported Codec
 ts_consts!(
     pub StreamSupportedVideoCodecs(export_bindings_supported_video_codecs: EXPORT_PATH):
@@ -0,0 +1,30 @@
+//! Versions and git commits which are embedded by `build.rs`, see [GetVersionResponse].
+
+use crate::api_bindings::{BuildInfo, GetVersionResponse};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// None if the build didn't happen in a git checkout
+pub const GIT_HASH: Option<&str> = option_env!("MOONLIGHT_WEB_GIT_HASH");
+pub const MOONLIGHT_COMMON_C_COMMIT: Option<&str> = option_env!("MOONLIGHT_COMMON_C_COMMIT");
+
+impl BuildInfo {
+    /// The features must be the enabled cargo features of the calling binary
+    pub fn current(features: &[&str]) -> Self {
+        Self {
+            version: VERSION.to_string(),
+            git_hash: GIT_HASH.map(str::to_string),
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+}
+
+impl GetVersionResponse {
+    pub fn new(web_server: BuildInfo, streamer: Option<BuildInfo>) -> Self {
+        Self {
+            web_server,
+            streamer,
+            moonlight_common: moonlight_common::VERSION.to_string(),
+            moonlight_common_c_commit: MOONLIGHT_COMMON_C_COMMIT.map(str::to_string),
+        }
+    }
+}
@@ -1,22 +1,34 @@
 use std::{
+    collections::HashMap,
     fmt::Display,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
     num::ParseIntError,
     str::FromStr,
     time::Duration,
 };
 
+use chrono::{NaiveTime, Weekday};
+use ipnet::IpNet;
 use log::LevelFilter;
+use moonlight_common::{
+    network::{XmlParseMode, request_client::RequestClientOptions},
+    units::Fps,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::api_bindings::RtcIceServer;
+use crate::{
+    api_bindings::{RtcIceServer, StreamPreset, StreamSettingRange},
+    messages::MessageCatalog,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub data_storage: StorageConfig,
     #[serde(default)]
+    pub client_key_store: KeyStoreConfig,
+    #[serde(default)]
     pub webrtc: WebRtcConfig,
     #[serde(default)]
     pub web_server: WebServerConfig,
@@ -25,41 +37,935 @@ pub struct Config {
     #[serde(default = "default_streamer_path")]
     pub streamer_path: String,
     #[serde(default)]
+    pub streamer_ipc: StreamerIpcMethod,
+    #[serde(default)]
+    pub streamer_sandbox: StreamerSandboxConfig,
+    #[serde(default)]
+    pub streamer_pool: StreamerPoolConfig,
+    #[serde(default)]
+    pub streamer_memory: StreamerMemoryConfig,
+    #[serde(default)]
+    pub remote_streamers: RemoteStreamersConfig,
+    #[serde(default)]
     pub log: LogConfig,
+    #[serde(default)]
+    pub file_transfer: FileTransferConfig,
+    #[serde(default)]
+    pub controller_rumble: ControllerRumbleConfig,
+    #[serde(default)]
+    pub video_watchdog: VideoWatchdogConfig,
+    #[serde(default)]
+    pub peer_watchdog: PeerWatchdogConfig,
+    #[serde(default)]
+    pub cursor_prediction: CursorPredictionConfig,
+    #[serde(default)]
+    pub media_priority: MediaPriorityConfig,
+    #[serde(default)]
+    pub stream_encryption: StreamEncryptionConfig,
+    #[serde(default)]
+    pub session_preview: SessionPreviewConfig,
+    #[serde(default)]
+    pub session_thumbnail: SessionThumbnailConfig,
+    #[serde(default)]
+    pub rtsp_output: RtspOutputConfig,
+    #[serde(default)]
+    pub hls_output: HlsOutputConfig,
+    #[serde(default)]
+    pub tailscale: TailscaleConfig,
+    #[serde(default)]
+    pub web_push: WebPushConfig,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    /// Overwrites the english texts of messages which are sent to clients
+    #[serde(default)]
+    pub messages: MessageCatalog,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_storage: Default::default(),
+            client_key_store: Default::default(),
+            streamer_path: default_streamer_path(),
+            streamer_ipc: Default::default(),
+            streamer_sandbox: Default::default(),
+            streamer_pool: Default::default(),
+            streamer_memory: Default::default(),
+            remote_streamers: Default::default(),
+            web_server: Default::default(),
+            moonlight: Default::default(),
+            webrtc: Default::default(),
+            log: Default::default(),
+            file_transfer: Default::default(),
+            controller_rumble: Default::default(),
+            video_watchdog: Default::default(),
+            peer_watchdog: Default::default(),
+            cursor_prediction: Default::default(),
+            media_priority: Default::default(),
+            stream_encryption: Default::default(),
+            session_preview: Default::default(),
+            session_thumbnail: Default::default(),
+            rtsp_output: Default::default(),
+            hls_output: Default::default(),
+            tailscale: Default::default(),
+            web_push: Default::default(),
+            tenants: Default::default(),
+            messages: Default::default(),
+        }
+    }
+}
+
+// -- Log
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    pub level_filter: LevelFilter,
+    pub file_path: Option<String>,
+    #[serde(default)]
+    pub anonymize_ips: LogIpAnonymization,
+    #[serde(default)]
+    pub file_rotation: LogFileRotationConfig,
+    #[serde(default)]
+    pub streamer_forwarding: StreamerLogForwardingConfig,
+    #[serde(default)]
+    pub error_reporting: ErrorReportingConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level_filter: default_level_filter(),
+            file_path: None,
+            anonymize_ips: Default::default(),
+            file_rotation: Default::default(),
+            streamer_forwarding: Default::default(),
+            error_reporting: Default::default(),
+        }
+    }
+}
+
+fn default_level_filter() -> LevelFilter {
+    LevelFilter::Info
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogIpAnonymization {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    /// Only keeps the /24 network of ipv4 and the /48 network of ipv6 addresses
+    #[serde(rename = "subnet")]
+    Subnet,
+    #[serde(rename = "full")]
+    Full,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogFileRotationConfig {
+    /// The log file is rotated once it's bigger than this in bytes
+    #[serde(default = "default_log_max_file_size")]
+    pub max_file_size: u64,
+    /// The amount of rotated log files which are kept
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+    /// Rotated log files older than this are deleted
+    #[serde(default)]
+    pub max_age: Option<Duration>,
+    /// Compresses rotated log files with gzip
+    #[serde(default = "default_log_compress")]
+    pub compress: bool,
+}
+
+impl Default for LogFileRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_log_max_file_size(),
+            max_files: default_log_max_files(),
+            max_age: None,
+            compress: default_log_compress(),
+        }
+    }
+}
+
+fn default_log_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+fn default_log_max_files() -> usize {
+    5
+}
+fn default_log_compress() -> bool {
+    true
+}
+
+/// Sends the logs of the streamers to the web server instead of writing them to their stderr
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StreamerLogForwardingConfig {
+    #[serde(default = "default_streamer_log_forwarding_enabled")]
+    pub enabled: bool,
+    /// Messages below this level aren't sent to the web server
+    #[serde(default = "default_level_filter")]
+    pub level_filter: LevelFilter,
+}
+
+impl Default for StreamerLogForwardingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_streamer_log_forwarding_enabled(),
+            level_filter: default_level_filter(),
+        }
+    }
+}
+
+fn default_streamer_log_forwarding_enabled() -> bool {
+    true
+}
+
+/// Reports panics and error logs of the web server and the streamers.
+/// Errors of the streamers are only seen by the web server if they're forwarded, see [StreamerLogForwardingConfig].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorReportingConfig {
+    /// Nothing is reported if this is none
+    #[serde(default)]
+    pub target: Option<ErrorReportingTarget>,
+    /// Reported with every error, e.g. "production"
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Reports above this are dropped, so a failing loop doesn't flood the target
+    #[serde(default = "default_error_reporting_max_reports_per_minute")]
+    pub max_reports_per_minute: u32,
+}
+
+impl Default for ErrorReportingConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            environment: None,
+            max_reports_per_minute: default_error_reporting_max_reports_per_minute(),
+        }
+    }
+}
+
+fn default_error_reporting_max_reports_per_minute() -> u32 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorReportingTarget {
+    /// The dsn of a Sentry project, also works with Sentry compatible services like GlitchTip
+    Sentry { dsn: String },
+    /// Posts every report as json to the url
+    Webhook { url: String },
+}
+
+// -- Streamer Ipc
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum StreamerIpcMethod {
+    #[default]
+    #[serde(rename = "stdio")]
+    Stdio,
+    /// Windows only: useful if the stdio of the streamer is not usable, e.g. when running as a service
+    #[serde(rename = "named_pipe")]
+    NamedPipe,
+}
+
+// -- Streamer Pool
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamerPoolConfig {
+    /// The amount of idle streamers which are spawned ahead of time to start streams faster, 0 disables the pool
+    #[serde(default)]
+    pub size: usize,
+}
+
+// -- Streamer Sandbox
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamerSandboxConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The working directory of the streamer.
+    /// Apart from the file transfer directory this is the only directory the streamer can write to.
+    #[serde(default = "default_streamer_sandbox_working_directory")]
+    pub working_directory: String,
+    /// Linux only: restricts file system access using landlock
+    #[serde(default = "default_streamer_sandbox_landlock")]
+    pub landlock: bool,
+    /// Linux only: denies syscalls which the streamer never needs using a seccomp filter
+    #[serde(default = "default_streamer_sandbox_seccomp")]
+    pub seccomp: bool,
+}
+
+impl Default for StreamerSandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            working_directory: default_streamer_sandbox_working_directory(),
+            landlock: default_streamer_sandbox_landlock(),
+            seccomp: default_streamer_sandbox_seccomp(),
+        }
+    }
+}
+
+fn default_streamer_sandbox_working_directory() -> String {
+    "server/streamer".to_string()
+}
+fn default_streamer_sandbox_landlock() -> bool {
+    true
+}
+fn default_streamer_sandbox_seccomp() -> bool {
+    true
+}
+
+// -- Streamer Memory
+
+/// Limits the memory of the video and audio samples which wait to be sent to the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamerMemoryConfig {
+    /// Samples are dropped once the buffered samples would use more bytes than this
+    #[serde(default = "default_streamer_memory_max_buffered_bytes")]
+    pub max_buffered_bytes: usize,
+    /// The amount of freed buffers which are kept to be reused
+    #[serde(default = "default_streamer_memory_max_pooled_buffers")]
+    pub max_pooled_buffers: usize,
+}
+
+impl Default for StreamerMemoryConfig {
+    fn default() -> Self {
+        Self {
+            max_buffered_bytes: default_streamer_memory_max_buffered_bytes(),
+            max_pooled_buffers: default_streamer_memory_max_pooled_buffers(),
+        }
+    }
+}
+
+fn default_streamer_memory_max_buffered_bytes() -> usize {
+    64 * 1024 * 1024
+}
+fn default_streamer_memory_max_pooled_buffers() -> usize {
+    64
+}
+
+// -- File Transfer
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTransferConfig {
+    /// The directory in which uploaded files are stored.
+    /// File transfers are disabled if this is not set.
+    ///
+    /// Sunshine can't receive files, so the host only sees them if it can access this directory.
+    #[serde(default)]
+    pub directory: Option<String>,
+    #[serde(default = "default_file_transfer_max_file_size")]
+    pub max_file_size: u32,
+}
+
+impl Default for FileTransferConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            max_file_size: default_file_transfer_max_file_size(),
+        }
+    }
+}
+
+fn default_file_transfer_max_file_size() -> u32 {
+    // 512 MiB
+    512 * 1024 * 1024
+}
+
+// -- Controller Rumble
+
+/// Browsers only support trigger rumble for a few gamepads,
+/// the rumble of the trigger motors can be played on the two main motors instead.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ControllerRumbleConfig {
+    #[serde(default)]
+    pub trigger_remap: TriggerRumbleRemap,
+    /// Overwrites the `trigger_remap` for a controller type
+    #[serde(default)]
+    pub controller_type_trigger_remap: HashMap<RumbleControllerType, TriggerRumbleRemap>,
+}
+
+impl ControllerRumbleConfig {
+    pub fn trigger_remap(&self, controller_type: RumbleControllerType) -> TriggerRumbleRemap {
+        self.controller_type_trigger_remap
+            .get(&controller_type)
+            .copied()
+            .unwrap_or(self.trigger_remap)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerRumbleRemap {
+    /// Trigger rumble is only sent to controllers with trigger motors
+    #[default]
+    Disabled,
+    /// Trigger rumble is played on the main motors of controllers without trigger motors
+    MissingTriggerMotors,
+    /// Trigger rumble is always played on the main motors, additionally to the trigger motors
+    Always,
+}
+
+/// The type of a controller as detected by the browser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RumbleControllerType {
+    Unknown,
+    Xbox,
+    PlayStation,
+    Nintendo,
+}
+
+// -- Video Watchdog
+
+/// Recovers streams which stopped receiving video, e.g. because the host hangs or all packets are lost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoWatchdogConfig {
+    #[serde(default = "default_video_watchdog_enabled")]
+    pub enabled: bool,
+    /// Requests an IDR frame once no frame was received for this long
+    #[serde(default = "default_video_watchdog_idr_timeout")]
+    pub idr_timeout: Duration,
+    /// Restarts the connection to the host once no frame was received for this long
+    #[serde(default = "default_video_watchdog_restart_timeout")]
+    pub restart_timeout: Duration,
+}
+
+impl Default for VideoWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_video_watchdog_enabled(),
+            idr_timeout: default_video_watchdog_idr_timeout(),
+            restart_timeout: default_video_watchdog_restart_timeout(),
+        }
+    }
+}
+
+fn default_video_watchdog_enabled() -> bool {
+    true
+}
+fn default_video_watchdog_idr_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+fn default_video_watchdog_restart_timeout() -> Duration {
+    Duration::from_secs(15)
+}
+
+// -- Peer Watchdog
+
+/// Cleans up streams whose WebRTC peer never connected, e.g. because the client closed the tab while the app launched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerWatchdogConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// The stream is stopped if no peer connected this long after the app was launched
+    #[serde(default = "default_peer_watchdog_connect_timeout")]
+    pub connect_timeout: Duration,
+    /// Also quits the app on the host instead of leaving it running
+    #[serde(default = "default_true")]
+    pub quit_app: bool,
+}
+
+impl Default for PeerWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            connect_timeout: default_peer_watchdog_connect_timeout(),
+            quit_app: true,
+        }
+    }
+}
+
+fn default_peer_watchdog_connect_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+// -- Cursor Prediction
+
+/// Clients can draw a predicted local cursor, which they correct with the cursor position the streamer echoes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorPredictionConfig {
+    /// Allows clients to turn on the echo
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// The echo is sent this often while the client has it turned on
+    #[serde(default = "default_cursor_echo_interval")]
+    pub echo_interval: Duration,
+}
+
+impl Default for CursorPredictionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            echo_interval: default_cursor_echo_interval(),
+        }
+    }
+}
+
+fn default_cursor_echo_interval() -> Duration {
+    Duration::from_millis(16)
+}
+
+// -- Media Priority
+
+/// Keeps the audio continuous when the bandwidth collapses, only used by the WebRTC transport
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaPriorityConfig {
+    /// Video frames wait until the queued audio samples were written
+    #[serde(default = "default_media_priority_audio_first")]
+    pub audio_first: bool,
+    /// Once more audio samples are queued than this, P-frames are dropped and an IDR frame is requested until the audio queue is empty again
+    #[serde(default = "default_media_priority_audio_backlog_threshold")]
+    pub audio_backlog_threshold: usize,
+}
+
+impl Default for MediaPriorityConfig {
+    fn default() -> Self {
+        Self {
+            audio_first: default_media_priority_audio_first(),
+            audio_backlog_threshold: default_media_priority_audio_backlog_threshold(),
+        }
+    }
+}
+
+fn default_media_priority_audio_first() -> bool {
+    true
+}
+fn default_media_priority_audio_backlog_threshold() -> usize {
+    4
+}
+
+// -- Stream Encryption
+
+/// What the host encrypts when it's in a private network, e.g. to save cpu on weak hosts.
+/// Hosts outside of a private network always encrypt everything and remote input is always encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamEncryptionConfig {
+    #[serde(default = "default_true")]
+    pub video: bool,
+    #[serde(default = "default_true")]
+    pub audio: bool,
+}
+
+impl Default for StreamEncryptionConfig {
+    fn default() -> Self {
+        Self {
+            video: true,
+            audio: true,
+        }
+    }
+}
+
+// -- Session Preview
+
+/// The low resolution preview track of a session, which admins can watch without attaching a client.
+/// The streamer only decodes the stream while somebody watches the preview.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPreviewConfig {
+    /// The frames are scaled down to this width, the height keeps the aspect ratio
+    #[serde(default = "default_session_preview_width")]
+    pub width: u32,
+    #[serde(default = "default_session_preview_fps")]
+    pub fps: Fps,
+    /// From 1 to 100
+    #[serde(default = "default_session_preview_jpeg_quality")]
+    pub jpeg_quality: u8,
+}
+
+impl Default for SessionPreviewConfig {
+    fn default() -> Self {
+        Self {
+            width: default_session_preview_width(),
+            fps: default_session_preview_fps(),
+            jpeg_quality: default_session_preview_jpeg_quality(),
+        }
+    }
+}
+
+fn default_session_preview_width() -> u32 {
+    320
+}
+fn default_session_preview_fps() -> Fps {
+    Fps(5)
+}
+fn default_session_preview_jpeg_quality() -> u8 {
+    70
+}
+
+// -- Session Thumbnail
+
+/// The thumbnails of the active sessions for the session list of admins, only taken by streamers built with the `screenshot` feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionThumbnailConfig {
+    /// A new thumbnail is taken after this long, zero disables thumbnails.
+    /// Hosts only send keyframes on request, so the stream gets a keyframe this often.
+    #[serde(default = "default_session_thumbnail_interval")]
+    pub interval: Duration,
+    /// The height keeps the aspect ratio
+    #[serde(default = "default_session_thumbnail_width")]
+    pub width: u32,
+    #[serde(default = "default_session_preview_jpeg_quality")]
+    pub jpeg_quality: u8,
+}
+
+impl Default for SessionThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_session_thumbnail_interval(),
+            width: default_session_thumbnail_width(),
+            jpeg_quality: default_session_preview_jpeg_quality(),
+        }
+    }
+}
+
+fn default_session_thumbnail_interval() -> Duration {
+    Duration::from_secs(30)
+}
+fn default_session_thumbnail_width() -> u32 {
+    240
+}
+
+// -- RTSP Output
+
+/// Republishes the video and audio of every stream over rtsp, so devices in the local network can watch without a browser.
+/// Clients aren't authenticated, so the port must not be reachable from outside of the local network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RtspOutputConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Streams which run at the same time listen on the next free ports
+    #[serde(default = "default_rtsp_output_bind_address")]
+    pub bind_address: SocketAddr,
+    #[serde(default = "default_rtsp_output_max_clients")]
+    pub max_clients: usize,
+}
+
+impl Default for RtspOutputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: default_rtsp_output_bind_address(),
+            max_clients: default_rtsp_output_max_clients(),
+        }
+    }
+}
+
+fn default_rtsp_output_bind_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8554))
+}
+fn default_rtsp_output_max_clients() -> usize {
+    4
+}
+
+// -- HLS Output
+
+/// Packages the video of a session as low latency hls, so it can be cast to a Chromecast or AirPlay device.
+/// The streamer only packages the stream while the playlist is requested.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsOutputConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Segments start with a keyframe, so the stream gets a keyframe this often
+    #[serde(default = "default_hls_output_segment_duration")]
+    pub segment_duration: Duration,
+    /// The parts of a segment which low latency players load before the segment is complete
+    #[serde(default = "default_hls_output_part_duration")]
+    pub part_duration: Duration,
+    /// The segments in the playlist, older segments are dropped
+    #[serde(default = "default_hls_output_playlist_segments")]
+    pub playlist_segments: usize,
+    /// The streamer stops packaging once nobody requested the playlist for this long
+    #[serde(default = "default_hls_output_idle_timeout")]
+    pub idle_timeout: Duration,
+    /// Every segment is also written into a folder per session in this directory, so the session can be reviewed later
+    #[serde(default)]
+    pub recording_directory: Option<String>,
+}
+
+impl Default for HlsOutputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            segment_duration: default_hls_output_segment_duration(),
+            part_duration: default_hls_output_part_duration(),
+            playlist_segments: default_hls_output_playlist_segments(),
+            idle_timeout: default_hls_output_idle_timeout(),
+            recording_directory: None,
+        }
+    }
+}
+
+fn default_hls_output_segment_duration() -> Duration {
+    Duration::from_secs(2)
+}
+fn default_hls_output_part_duration() -> Duration {
+    Duration::from_millis(500)
+}
+fn default_hls_output_playlist_segments() -> usize {
+    6
+}
+fn default_hls_output_idle_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+// -- Tailscale
+
+/// Lists the peers of the Tailscale network of the web server through the local api of tailscaled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TailscaleConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The unix socket of the local api
+    #[serde(default = "default_tailscale_socket")]
+    pub socket: String,
+}
+
+impl Default for TailscaleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: default_tailscale_socket(),
+        }
+    }
+}
+
+fn default_tailscale_socket() -> String {
+    "/var/run/tailscale/tailscaled.sock".to_string()
+}
+
+// -- Web Push
+
+/// Notifies subscribed browsers when something the user waits for happened, e.g. a woken host booted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebPushConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// A "mailto:" or "https:" url with which push services can contact the operator
+    #[serde(default = "default_web_push_subject")]
+    pub subject: String,
+    /// The pem of the vapid private key, it's generated if the file doesn't exist
+    #[serde(default = "default_web_push_vapid_key_path")]
+    pub vapid_key_path: String,
+    /// How long push services keep a notification for a device which is offline
+    #[serde(default = "default_web_push_ttl")]
+    pub ttl: Duration,
+    /// How long a woken host is polled until it's reachable
+    #[serde(default = "default_web_push_host_online_timeout")]
+    pub host_online_timeout: Duration,
+    /// Subscriptions are only accepted for these domains and their subdomains,
+    /// the web server sends requests to the endpoint of every subscription
+    #[serde(default = "default_web_push_services")]
+    pub push_services: Vec<String>,
+}
+
+impl Default for WebPushConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            subject: default_web_push_subject(),
+            vapid_key_path: default_web_push_vapid_key_path(),
+            ttl: default_web_push_ttl(),
+            host_online_timeout: default_web_push_host_online_timeout(),
+            push_services: default_web_push_services(),
+        }
+    }
+}
+
+fn default_web_push_subject() -> String {
+    "mailto:admin@localhost".to_string()
+}
+fn default_web_push_vapid_key_path() -> String {
+    "server/vapid_key.pem".to_string()
+}
+fn default_web_push_ttl() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+fn default_web_push_host_online_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+fn default_web_push_services() -> Vec<String> {
+    vec![
+        // Chrome, Edge, Opera
+        "fcm.googleapis.com".to_string(),
+        // Firefox
+        "push.services.mozilla.com".to_string(),
+        // Safari
+        "push.apple.com".to_string(),
+        // Legacy Edge
+        "notify.windows.com".to_string(),
+    ]
+}
+
+// -- Standalone Streamer
+
+/// The config of `streamer --standalone <path>`, which runs without a web server.
+/// Clients signal over its own http endpoint with the messages of the stream web socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandaloneStreamerConfig {
+    /// The address of the signaling endpoint
+    #[serde(default = "default_standalone_bind_address")]
+    pub bind_address: SocketAddr,
+    /// Clients authorize with `Authorization: Bearer <access_token>`, it must not be empty
+    pub access_token: String,
+    pub host_address: String,
+    #[serde(default = "default_moonlight_http_port")]
+    pub host_http_port: u16,
+    #[serde(default)]
+    pub host_xml_parse_mode: XmlParseMode,
+    /// The unique id with which the client certificate was paired
+    #[serde(default)]
+    pub client_unique_id: Option<String>,
+    /// The pem files of a paired client
+    pub client_private_key_path: String,
+    pub client_certificate_path: String,
+    pub server_certificate_path: String,
+    pub app_id: u32,
+    #[serde(default = "default_standalone_video_frame_queue_size")]
+    pub video_frame_queue_size: usize,
+    #[serde(default = "default_standalone_audio_sample_queue_size")]
+    pub audio_sample_queue_size: usize,
+    #[serde(default)]
+    pub webrtc: WebRtcConfig,
+    #[serde(default)]
+    pub sandbox: StreamerSandboxConfig,
+    #[serde(default = "default_level_filter")]
+    pub log_level: LevelFilter,
+}
+
+fn default_standalone_bind_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8090))
+}
+fn default_standalone_video_frame_queue_size() -> usize {
+    3
+}
+fn default_standalone_audio_sample_queue_size() -> usize {
+    20
+}
+
+// -- Remote Streamers
+
+/// Streamer nodes on other machines connect to this endpoint, sessions are started on the node closest to the host.
+/// Both sides authenticate with certificates signed by the same certificate authority.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteStreamersConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_remote_streamers_bind_address")]
+    pub bind_address: SocketAddr,
+    /// The pem of the certificate authority which signed the certificates of the nodes
+    #[serde(default = "default_remote_streamers_ca_certificate_path")]
+    pub ca_certificate_path: String,
+    #[serde(default = "default_remote_streamers_certificate_path")]
+    pub certificate_path: String,
+    #[serde(default = "default_remote_streamers_private_key_path")]
+    pub private_key_path: String,
+    /// A node is dropped if it didn't send a heartbeat for this long
+    #[serde(default = "default_remote_streamers_heartbeat_timeout")]
+    pub heartbeat_timeout: Duration,
+    /// Spawns a local streamer if no node can stream from the host
+    #[serde(default = "default_true")]
+    pub local_fallback: bool,
+}
+
+impl Default for RemoteStreamersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: default_remote_streamers_bind_address(),
+            ca_certificate_path: default_remote_streamers_ca_certificate_path(),
+            certificate_path: default_remote_streamers_certificate_path(),
+            private_key_path: default_remote_streamers_private_key_path(),
+            heartbeat_timeout: default_remote_streamers_heartbeat_timeout(),
+            local_fallback: true,
+        }
+    }
+}
+
+fn default_remote_streamers_bind_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8091))
+}
+fn default_remote_streamers_ca_certificate_path() -> String {
+    "server/remote_streamers/ca.pem".to_string()
+}
+fn default_remote_streamers_certificate_path() -> String {
+    "server/remote_streamers/cert.pem".to_string()
+}
+fn default_remote_streamers_private_key_path() -> String {
+    "server/remote_streamers/key.pem".to_string()
+}
+fn default_remote_streamers_heartbeat_timeout() -> Duration {
+    Duration::from_secs(15)
+}
+
+// -- Streamer Node
+
+/// The config of `streamer --node <path>`, which runs the streamers of a web server on this machine, see [RemoteStreamersConfig]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamerNodeConfig {
+    /// The address of the remote streamers endpoint of the web server
+    pub server_address: String,
+    /// The name in the certificate of the web server
+    pub server_name: String,
+    /// Shown in the logs of the web server
+    pub name: String,
+    /// The networks of the hosts this node can reach, the node with the most specific network of a host streams from it
+    pub networks: Vec<IpNet>,
+    #[serde(default = "default_streamer_node_max_sessions")]
+    pub max_sessions: usize,
+    pub ca_certificate_path: String,
+    pub certificate_path: String,
+    pub private_key_path: String,
+    #[serde(default = "default_streamer_node_heartbeat_interval")]
+    pub heartbeat_interval: Duration,
+    /// How long the node waits before connecting again after the connection to the web server was lost
+    #[serde(default = "default_streamer_node_reconnect_interval")]
+    pub reconnect_interval: Duration,
+    #[serde(default = "default_level_filter")]
+    pub log_level: LevelFilter,
+}
+
+fn default_streamer_node_max_sessions() -> usize {
+    4
+}
+fn default_streamer_node_heartbeat_interval() -> Duration {
+    Duration::from_secs(5)
 }
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            data_storage: Default::default(),
-            streamer_path: default_streamer_path(),
-            web_server: Default::default(),
-            moonlight: Default::default(),
-            webrtc: Default::default(),
-            log: Default::default(),
-        }
-    }
+fn default_streamer_node_reconnect_interval() -> Duration {
+    Duration::from_secs(5)
 }
 
-// -- Log
+// -- Tenants
 
+/// Another instance with its own users and hosts, which is selected by the host header of requests.
+/// Everything that isn't configured here is shared with the main instance.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LogConfig {
-    pub level_filter: LevelFilter,
-    pub file_path: Option<String>,
+pub struct TenantConfig {
+    /// The host names without a port, e.g. "family.example.com"
+    pub hosts: Vec<String>,
+    pub data_storage: StorageConfig,
+    #[serde(default)]
+    pub default_user_id: Option<u32>,
+    /// Overwrites [WebServerConfig::first_login_create_admin] if set
+    #[serde(default)]
+    pub first_login_create_admin: Option<bool>,
 }
 
-impl Default for LogConfig {
-    fn default() -> Self {
-        Self {
-            level_filter: default_level_filter(),
-            file_path: None,
+impl Config {
+    /// The config of the instance which serves the tenant
+    pub fn for_tenant(&self, tenant: &TenantConfig) -> Config {
+        let mut config = self.clone();
+
+        config.data_storage = tenant.data_storage.clone();
+        config.web_server.default_user_id = tenant.default_user_id;
+        if let Some(first_login_create_admin) = tenant.first_login_create_admin {
+            config.web_server.first_login_create_admin = first_login_create_admin;
         }
-    }
-}
+        config.tenants = Vec::new();
 
-fn default_level_filter() -> LevelFilter {
-    LevelFilter::Info
+        config
+    }
 }
 
 // -- Data Storage
@@ -82,10 +988,54 @@ impl Default for StorageConfig {
     }
 }
 
+/// Parses a storage of format "TYPE:LOCATION", e.g. "json:server/data.json"
+impl FromStr for StorageConfig {
+    type Err = StorageConfigFromStrError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ty, location) = s.split_once(":").ok_or(StorageConfigFromStrError::Split)?;
+        match ty {
+            "json" => Ok(StorageConfig::Json {
+                path: location.to_string(),
+                session_expiration_check_interval: default_session_expiration_check_interval(),
+            }),
+            _ => Err(StorageConfigFromStrError::UnknownType(ty.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StorageConfigFromStrError {
+    #[error("the storage must be of format \"TYPE:LOCATION\"")]
+    Split,
+    #[error("unknown storage type \"{0}\"")]
+    UnknownType(String),
+}
+
 fn default_session_expiration_check_interval() -> Duration {
     Duration::from_mins(5)
 }
 
+/// Where the private keys of paired hosts are kept
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum KeyStoreConfig {
+    /// Inside of the data storage next to the certificates
+    #[default]
+    Pem,
+    /// Every key in its own file inside of the directory
+    File { directory: String },
+    /// The keyring of the os, the web server must be built with the "keyring" feature
+    Keyring {
+        #[serde(default = "default_keyring_service")]
+        service: String,
+    },
+}
+
+fn default_keyring_service() -> String {
+    "moonlight-web".to_string()
+}
+
 // -- WebRTC Config
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,6 +1050,16 @@ pub struct WebRtcConfig {
     pub network_types: Vec<WebRtcNetworkType>,
     #[serde(default = "default_include_loopback_candidates")]
     pub include_loopback_candidates: bool,
+    #[serde(default)]
+    pub audio_jitter_buffer: AudioJitterBufferConfig,
+    #[serde(default)]
+    pub opus: OpusConfig,
+    #[serde(default)]
+    pub data_channel_backpressure: DataChannelBackpressureConfig,
+    #[serde(default)]
+    pub video_mtu: VideoMtuConfig,
+    #[serde(default)]
+    pub interceptors: WebRtcInterceptorConfig,
 }
 
 impl Default for WebRtcConfig {
@@ -110,6 +1070,11 @@ impl Default for WebRtcConfig {
             nat_1to1: None,
             network_types: default_network_types(),
             include_loopback_candidates: default_include_loopback_candidates(),
+            audio_jitter_buffer: Default::default(),
+            opus: Default::default(),
+            data_channel_backpressure: Default::default(),
+            video_mtu: Default::default(),
+            interceptors: Default::default(),
         }
     }
 }
@@ -220,6 +1185,174 @@ fn default_include_loopback_candidates() -> bool {
     true
 }
 
+/// Audio samples are held back shortly so that they're written to the track in a steady pace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioJitterBufferConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The delay which is used when the network has no jitter
+    #[serde(default = "default_audio_jitter_target_delay")]
+    pub target_delay: Duration,
+    /// The delay grows with the measured jitter but never above this
+    #[serde(default = "default_audio_jitter_max_delay")]
+    pub max_delay: Duration,
+}
+
+impl Default for AudioJitterBufferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_delay: default_audio_jitter_target_delay(),
+            max_delay: default_audio_jitter_max_delay(),
+        }
+    }
+}
+
+/// The Opus parameters which are announced to the browser in the audio SDP.
+/// The host decides if it actually encodes with these, neither Sunshine nor GeForce Experience can be configured by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpusConfig {
+    /// Lets the browser recover lost packets from the redundancy in the following packet
+    #[serde(default = "default_true")]
+    pub in_band_fec: bool,
+    /// Lets the browser accept discontinuous transmission during silence
+    #[serde(default)]
+    pub dtx: bool,
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            in_band_fec: true,
+            dtx: false,
+        }
+    }
+}
+
+impl OpusConfig {
+    pub fn sdp_fmtp_line(&self) -> String {
+        let mut fmtp = "minptime=10".to_string();
+        if self.in_band_fec {
+            fmtp.push_str(";useinbandfec=1");
+        }
+        if self.dtx {
+            fmtp.push_str(";usedtx=1");
+        }
+
+        fmtp
+    }
+}
+
+fn default_audio_jitter_target_delay() -> Duration {
+    Duration::from_millis(20)
+}
+fn default_audio_jitter_max_delay() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// Limits the data which the data channels buffer for slow clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataChannelBackpressureConfig {
+    /// Once a channel buffers more bytes than this, outdated packets are dropped and the others wait
+    #[serde(default = "default_data_channel_high_threshold")]
+    pub high_threshold: usize,
+    /// Waiting packets are sent again once the channel buffers less bytes than this
+    #[serde(default = "default_data_channel_low_threshold")]
+    pub low_threshold: usize,
+}
+
+impl Default for DataChannelBackpressureConfig {
+    fn default() -> Self {
+        Self {
+            high_threshold: default_data_channel_high_threshold(),
+            low_threshold: default_data_channel_low_threshold(),
+        }
+    }
+}
+
+fn default_data_channel_high_threshold() -> usize {
+    1024 * 1024
+}
+fn default_data_channel_low_threshold() -> usize {
+    256 * 1024
+}
+
+/// The size of the video rtp packets, larger units are fragmented and smaller ones are aggregated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoMtuConfig {
+    /// The mtu of a direct ipv4 path, including the rtp header
+    #[serde(default = "default_video_mtu")]
+    pub mtu: usize,
+    /// Lowers the mtu for paths with larger headers, e.g. ipv6 or TURN relays, every time the selected path changes
+    #[serde(default = "default_true")]
+    pub auto_adjust: bool,
+}
+
+impl Default for VideoMtuConfig {
+    fn default() -> Self {
+        Self {
+            mtu: default_video_mtu(),
+            auto_adjust: true,
+        }
+    }
+}
+
+fn default_video_mtu() -> usize {
+    1200
+}
+
+/// The rtp interceptors of the peer connection, larger buffers recover more losses on high latency links
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebRtcInterceptorConfig {
+    /// The sent packets which are kept to answer NACKs of the client.
+    /// Rounded to a power of two from 64 to 32768.
+    #[serde(default = "default_nack_responder_size")]
+    pub nack_responder_size: u16,
+    /// The received packets which are tracked to send NACKs for lost ones.
+    /// Rounded to a power of two from 64 to 32768.
+    #[serde(default = "default_nack_generator_size")]
+    pub nack_generator_size: u16,
+    #[serde(default = "default_nack_generator_interval")]
+    pub nack_generator_interval: Duration,
+    /// How often sender and receiver reports are sent
+    #[serde(default = "default_rtcp_report_interval")]
+    pub rtcp_report_interval: Duration,
+    /// Sends transport wide congestion control feedback for received packets
+    #[serde(default = "default_true")]
+    pub twcc: bool,
+    #[serde(default = "default_twcc_interval")]
+    pub twcc_interval: Duration,
+}
+
+impl Default for WebRtcInterceptorConfig {
+    fn default() -> Self {
+        Self {
+            nack_responder_size: default_nack_responder_size(),
+            nack_generator_size: default_nack_generator_size(),
+            nack_generator_interval: default_nack_generator_interval(),
+            rtcp_report_interval: default_rtcp_report_interval(),
+            twcc: true,
+            twcc_interval: default_twcc_interval(),
+        }
+    }
+}
+
+fn default_nack_responder_size() -> u16 {
+    1024
+}
+fn default_nack_generator_size() -> u16 {
+    512
+}
+fn default_nack_generator_interval() -> Duration {
+    Duration::from_millis(100)
+}
+fn default_rtcp_report_interval() -> Duration {
+    Duration::from_secs(1)
+}
+fn default_twcc_interval() -> Duration {
+    Duration::from_millis(100)
+}
+
 // -- Web Server Config
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -237,7 +1370,33 @@ pub struct WebServerConfig {
     pub first_login_create_admin: bool,
     pub first_login_assign_global_hosts: bool,
     pub default_user_id: Option<u32>,
+    #[serde(default)]
+    pub default_user: DefaultUserConfig,
     pub forwarded_header: Option<ForwardedHeaders>,
+    /// Proxies whose `trusted_proxy_header` is used to find the ip of the client
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    /// The header which the trusted proxies write, the other one is ignored because clients could set it
+    #[serde(default)]
+    pub trusted_proxy_header: TrustedProxyHeader,
+    /// How many trusted proxies can be between the client and this server
+    #[serde(default = "default_trusted_proxy_hops")]
+    pub trusted_proxy_hops: usize,
+    #[serde(default)]
+    pub cache_control: CacheControlConfig,
+    /// How long a stream waits for its client to reconnect a dropped web socket, zero disables resuming
+    #[serde(default = "default_stream_resume_timeout")]
+    pub stream_resume_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrustedProxyHeader {
+    #[default]
+    #[serde(rename = "x_forwarded_for")]
+    XForwardedFor,
+    /// The standardized header of RFC 7239
+    #[serde(rename = "forwarded")]
+    Forwarded,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -257,7 +1416,13 @@ impl Default for WebServerConfig {
             first_login_create_admin: true,
             first_login_assign_global_hosts: true,
             default_user_id: None,
+            default_user: Default::default(),
             forwarded_header: None,
+            trusted_proxies: Vec::new(),
+            trusted_proxy_header: Default::default(),
+            trusted_proxy_hops: default_trusted_proxy_hops(),
+            cache_control: Default::default(),
+            stream_resume_timeout: default_stream_resume_timeout(),
         }
     }
 }
@@ -265,15 +1430,77 @@ impl Default for WebServerConfig {
 fn default_bind_address() -> SocketAddr {
     SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080))
 }
+fn default_trusted_proxy_hops() -> usize {
+    1
+}
 fn default_session_cookie_secure() -> bool {
     false
 }
+fn default_stream_resume_timeout() -> Duration {
+    Duration::from_secs(30)
+}
 fn default_session_cookie_expiration() -> Duration {
     const DAY_SECONDS: u64 = 24 * 60 * 60;
 
     Duration::from_secs(DAY_SECONDS)
 }
 
+/// The `Cache-Control` header values of the different kinds of responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheControlConfig {
+    /// Responses of the api, they always depend on the current state
+    #[serde(default = "default_cache_control_api")]
+    pub api: String,
+    /// App images, they are validated with their ETag
+    #[serde(default = "default_cache_control_images")]
+    pub images: String,
+    /// Files of the web interface, they are validated with their ETag
+    #[serde(default = "default_cache_control_static_files")]
+    pub static_files: String,
+    /// Files of the web interface with a content hash in their name, e.g. `stream.3f2a9c1b.js`
+    #[serde(default = "default_cache_control_fingerprinted_files")]
+    pub fingerprinted_files: String,
+}
+
+impl Default for CacheControlConfig {
+    fn default() -> Self {
+        Self {
+            api: default_cache_control_api(),
+            images: default_cache_control_images(),
+            static_files: default_cache_control_static_files(),
+            fingerprinted_files: default_cache_control_fingerprinted_files(),
+        }
+    }
+}
+
+fn default_cache_control_api() -> String {
+    "no-store".to_string()
+}
+fn default_cache_control_images() -> String {
+    "private, no-cache".to_string()
+}
+fn default_cache_control_static_files() -> String {
+    "no-cache".to_string()
+}
+fn default_cache_control_fingerprinted_files() -> String {
+    "public, max-age=31536000, immutable".to_string()
+}
+
+/// Restrictions for requests without credentials which are logged in as the `default_user_id`.
+/// Users logging in with the credentials of the default user are not restricted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefaultUserConfig {
+    /// The hosts that can be used, all hosts of the default user if None
+    #[serde(default)]
+    pub allowed_hosts: Option<Vec<u32>>,
+    /// The apps that can be listed and started, all apps if None
+    #[serde(default)]
+    pub allowed_apps: Option<Vec<u32>>,
+    /// Only requests from these networks are logged in as the default user, e.g. "192.168.1.0/24"
+    #[serde(default)]
+    pub allowed_networks: Option<Vec<IpNet>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForwardedHeaders {
     pub username_header: String,
@@ -302,6 +1529,58 @@ pub struct MoonlightConfig {
     pub default_http_port: u16,
     #[serde(default = "default_pair_device_name")]
     pub pair_device_name: String,
+    /// The digits of the generated pin, older hosts only accept 4 digits
+    #[serde(default = "default_pair_pin_length")]
+    pub pair_pin_length: usize,
+    #[serde(default)]
+    pub video_codec_policy: VideoCodecPolicy,
+    /// Overwrites the `video_codec_policy` for the host id
+    #[serde(default)]
+    pub host_video_codec_policies: HashMap<u32, VideoCodecPolicy>,
+    /// Streams with settings outside of these limits are rejected before they start
+    #[serde(default)]
+    pub stream_limits: StreamLimits,
+    /// Further limits the settings of streams for the host id, the `stream_limits` still apply
+    #[serde(default)]
+    pub host_stream_limits: HashMap<u32, StreamLimits>,
+    /// Clients can start streams with these settings by their name, the `stream_limits` still apply
+    #[serde(default)]
+    pub stream_presets: Vec<StreamPreset>,
+    /// The displays of a host by its host id
+    #[serde(default)]
+    pub host_displays: HashMap<u32, Vec<HostDisplayConfig>>,
+    /// Admin credentials of the Sunshine web ui by host id, they're stored in plaintext
+    #[serde(default)]
+    pub host_sunshine_credentials: HashMap<u32, SunshineCredentials>,
+    /// Offline hosts are woken when a stream starts and it waits this long for them to boot, zero disables it
+    #[serde(default = "default_host_boot_timeout")]
+    pub host_boot_timeout: Duration,
+    /// Wakes the host id at these times, e.g. before it's usually streamed from
+    #[serde(default)]
+    pub host_wake_schedules: HashMap<u32, Vec<HostWakeSchedule>>,
+    /// The http and socks proxy through which hosts are reached, e.g. `socks5://10.0.0.1:1080`.
+    /// Only the http requests use it, the stream itself still connects directly to the host.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Overwrites the `proxy` for the host id
+    #[serde(default)]
+    pub host_proxies: HashMap<u32, String>,
+    /// Resolves the host names of host addresses like a hosts file, e.g. when the dns inside a container
+    /// doesn't know the names of the lan
+    #[serde(default)]
+    pub dns_overrides: HashMap<String, IpAddr>,
+    /// `lenient` accepts the responses of Sunshine forks which deviate from Sunshine, e.g. by leaving out fields
+    #[serde(default)]
+    pub xml_parse_mode: XmlParseMode,
+    /// Overwrites the `xml_parse_mode` for the host id
+    #[serde(default)]
+    pub host_xml_parse_modes: HashMap<u32, XmlParseMode>,
+    /// Submits the pin to Sunshine while pairing if the host has credentials in `host_sunshine_credentials`.
+    /// The first pairing of a host is never submitted because its web ui can't be verified yet.
+    #[serde(default)]
+    pub auto_submit_pair_pin: bool,
+    #[serde(default)]
+    pub client_certificate: ClientCertificateConfig,
 }
 
 impl Default for MoonlightConfig {
@@ -309,10 +1588,287 @@ impl Default for MoonlightConfig {
         Self {
             default_http_port: default_moonlight_http_port(),
             pair_device_name: default_pair_device_name(),
+            pair_pin_length: default_pair_pin_length(),
+            video_codec_policy: Default::default(),
+            host_video_codec_policies: Default::default(),
+            stream_limits: Default::default(),
+            host_stream_limits: Default::default(),
+            stream_presets: Default::default(),
+            host_displays: Default::default(),
+            host_sunshine_credentials: Default::default(),
+            host_boot_timeout: default_host_boot_timeout(),
+            host_wake_schedules: Default::default(),
+            proxy: None,
+            host_proxies: Default::default(),
+            dns_overrides: Default::default(),
+            xml_parse_mode: Default::default(),
+            host_xml_parse_modes: Default::default(),
+            auto_submit_pair_pin: false,
+            client_certificate: Default::default(),
+        }
+    }
+}
+
+impl MoonlightConfig {
+    pub fn video_codec_policy(&self, host_id: u32) -> &VideoCodecPolicy {
+        self.host_video_codec_policies
+            .get(&host_id)
+            .unwrap_or(&self.video_codec_policy)
+    }
+
+    /// The `stream_limits` narrowed down by the limits of the host
+    pub fn stream_limits(&self, host_id: u32) -> StreamLimits {
+        match self.host_stream_limits.get(&host_id) {
+            Some(host_limits) => self.stream_limits.intersect(host_limits),
+            None => self.stream_limits.clone(),
+        }
+    }
+
+    /// The options of the clients which make requests to the host, hosts which aren't added yet have no host id
+    pub fn request_client_options(&self, host_id: Option<u32>) -> RequestClientOptions {
+        let proxy = host_id
+            .and_then(|host_id| self.host_proxies.get(&host_id))
+            .or(self.proxy.as_ref());
+
+        RequestClientOptions {
+            proxy: proxy.cloned(),
+            dns_overrides: self
+                .dns_overrides
+                .iter()
+                .map(|(host, address)| (host.to_ascii_lowercase(), *address))
+                .collect(),
+        }
+    }
+
+    /// The overridden address of the host name or the address itself
+    pub fn resolve_host_address(&self, address: &str) -> String {
+        self.dns_overrides
+            .iter()
+            .find(|(host, _)| host.eq_ignore_ascii_case(address))
+            .map(|(_, address)| address.to_string())
+            .unwrap_or_else(|| address.to_string())
+    }
+
+    /// Hosts which aren't added yet have no host id
+    pub fn xml_parse_mode(&self, host_id: Option<u32>) -> XmlParseMode {
+        host_id
+            .and_then(|host_id| self.host_xml_parse_modes.get(&host_id))
+            .copied()
+            .unwrap_or(self.xml_parse_mode)
+    }
+
+    pub fn stream_preset(&self, name: &str) -> Option<&StreamPreset> {
+        self.stream_presets
+            .iter()
+            .find(|preset| preset.name == name)
+    }
+
+    /// The index of a display is used as its id
+    pub fn host_displays(&self, host_id: u32) -> &[HostDisplayConfig] {
+        self.host_displays
+            .get(&host_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn sunshine_credentials(&self, host_id: u32) -> Option<&SunshineCredentials> {
+        self.host_sunshine_credentials.get(&host_id)
+    }
+}
+
+/// The certificates which are generated when pairing a host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientCertificateConfig {
+    #[serde(default = "default_client_certificate_validity_days")]
+    pub validity_days: u32,
+    /// Rotating the certificate is due this many days before it expires
+    #[serde(default = "default_client_certificate_rotate_days_before_expiry")]
+    pub rotate_days_before_expiry: u32,
+    /// Rotates the certificates in the background once rotating is due, this needs the Sunshine credentials of the hosts
+    #[serde(default)]
+    pub auto_rotate: bool,
+}
+
+impl Default for ClientCertificateConfig {
+    fn default() -> Self {
+        Self {
+            validity_days: default_client_certificate_validity_days(),
+            rotate_days_before_expiry: default_client_certificate_rotate_days_before_expiry(),
+            auto_rotate: false,
+        }
+    }
+}
+
+fn default_client_certificate_validity_days() -> u32 {
+    365
+}
+fn default_client_certificate_rotate_days_before_expiry() -> u32 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SunshineCredentials {
+    pub username: String,
+    pub password: String,
+    /// The port of the web ui, defaults to the http port of the host + 1 like Sunshine does
+    #[serde(default)]
+    pub web_ui_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostWakeSchedule {
+    /// In the local time of the web server, e.g. "18:30"
+    pub time: NaiveTime,
+    /// Every day if empty, e.g. ["Sat", "Sun"]
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    /// The host is woken as this user, it must be allowed to use the host and app
+    pub user_id: u32,
+    /// Launched without streaming it once the host is online, the first stream resumes it
+    #[serde(default)]
+    pub launch_app: Option<ScheduledAppLaunch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledAppLaunch {
+    pub app_id: u32,
+    #[serde(default = "default_scheduled_app_launch_width")]
+    pub width: u32,
+    #[serde(default = "default_scheduled_app_launch_height")]
+    pub height: u32,
+    #[serde(default = "default_scheduled_app_launch_fps")]
+    pub fps: Fps,
+    #[serde(default)]
+    pub hdr: bool,
+    /// How long the host may take to boot before the launch is given up
+    #[serde(default = "default_scheduled_app_launch_boot_timeout")]
+    pub boot_timeout: Duration,
+}
+
+fn default_scheduled_app_launch_width() -> u32 {
+    1920
+}
+fn default_scheduled_app_launch_height() -> u32 {
+    1080
+}
+fn default_scheduled_app_launch_fps() -> Fps {
+    Fps(60)
+}
+fn default_scheduled_app_launch_boot_timeout() -> Duration {
+    Duration::from_secs(180)
+}
+
+/// Hosts can't be told which display to stream when launching an app.
+/// Instead a variant of the app is launched which switches to the display, e.g. in its prep commands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostDisplayConfig {
+    pub name: String,
+    /// Maps an app id to the app id of its variant which streams this display
+    #[serde(default)]
+    pub app_variants: HashMap<u32, u32>,
+}
+
+/// Which codec is streamed is decided by the web server using the formats that both the browser and host support
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoCodecPolicy {
+    /// The first codec which is supported is used, codecs which are not in this list are never used
+    #[serde(default = "default_video_codec_preference")]
+    pub preference: Vec<VideoCodec>,
+    #[serde(default = "default_true")]
+    pub allow_hdr: bool,
+    #[serde(default = "default_true")]
+    pub allow_yuv444: bool,
+}
+
+impl Default for VideoCodecPolicy {
+    fn default() -> Self {
+        Self {
+            preference: default_video_codec_preference(),
+            allow_hdr: true,
+            allow_yuv444: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoCodec {
+    #[serde(rename = "h264")]
+    H264,
+    #[serde(rename = "h265")]
+    H265,
+    #[serde(rename = "av1")]
+    Av1,
+}
+
+/// The allowed values of the stream settings, the bitrate is in kbps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamLimits {
+    #[serde(default = "default_stream_limits_width")]
+    pub width: StreamSettingRange,
+    #[serde(default = "default_stream_limits_height")]
+    pub height: StreamSettingRange,
+    #[serde(default = "default_stream_limits_fps")]
+    pub fps: StreamSettingRange,
+    #[serde(default = "default_stream_limits_bitrate")]
+    pub bitrate: StreamSettingRange,
+}
+
+impl Default for StreamLimits {
+    fn default() -> Self {
+        Self {
+            width: default_stream_limits_width(),
+            height: default_stream_limits_height(),
+            fps: default_stream_limits_fps(),
+            bitrate: default_stream_limits_bitrate(),
+        }
+    }
+}
+
+impl StreamLimits {
+    fn intersect(&self, other: &StreamLimits) -> StreamLimits {
+        fn intersect(a: StreamSettingRange, b: StreamSettingRange) -> StreamSettingRange {
+            StreamSettingRange {
+                min: a.min.max(b.min),
+                max: a.max.min(b.max),
+            }
         }
+
+        StreamLimits {
+            width: intersect(self.width, other.width),
+            height: intersect(self.height, other.height),
+            fps: intersect(self.fps, other.fps),
+            bitrate: intersect(self.bitrate, other.bitrate),
+        }
+    }
+}
+
+fn default_stream_limits_width() -> StreamSettingRange {
+    StreamSettingRange { min: 1, max: 8192 }
+}
+fn default_stream_limits_height() -> StreamSettingRange {
+    StreamSettingRange { min: 1, max: 8192 }
+}
+fn default_stream_limits_fps() -> StreamSettingRange {
+    StreamSettingRange { min: 1, max: 480 }
+}
+fn default_stream_limits_bitrate() -> StreamSettingRange {
+    StreamSettingRange {
+        min: 100,
+        max: 500_000,
     }
 }
 
+fn default_video_codec_preference() -> Vec<VideoCodec> {
+    vec![VideoCodec::H265, VideoCodec::Av1, VideoCodec::H264]
+}
+fn default_true() -> bool {
+    true
+}
+
+fn default_host_boot_timeout() -> Duration {
+    Duration::from_secs(120)
+}
+
 fn default_moonlight_http_port() -> u16 {
     47989
 }
@@ -321,6 +1877,10 @@ fn default_pair_device_name() -> String {
     "roth".to_string()
 }
 
+fn default_pair_pin_length() -> usize {
+    4
+}
+
 fn default_streamer_path() -> String {
     "./streamer".to_string()
 }
@@ -1,30 +1,75 @@
 use std::{
+    collections::HashMap,
     marker::PhantomData,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
 use bytes::Bytes;
-use log::{LevelFilter, info, trace, warn};
+use ipnet::IpNet;
+use log::{Level, LevelFilter, info, trace, warn};
+use moonlight_common::network::XmlParseMode;
 use pem::Pem;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tokio::{
-    io::{
-        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, Lines, Stdin, Stdout,
-    },
-    process::{ChildStderr, ChildStdin, ChildStdout},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
+    process::ChildStderr,
     spawn,
     sync::mpsc::{Receiver, Sender, channel},
 };
 
 use crate::{
-    api_bindings::{StreamClientMessage, StreamServerMessage},
-    config::WebRtcConfig,
+    api_bindings::{
+        ScreenshotFormat, StreamClientMessage, StreamServerMessage, StreamerCapabilities,
+    },
+    config::{
+        ControllerRumbleConfig, CursorPredictionConfig, FileTransferConfig, HlsOutputConfig,
+        MediaPriorityConfig, PeerWatchdogConfig, RtspOutputConfig, SessionPreviewConfig,
+        SessionThumbnailConfig, StreamEncryptionConfig, StreamerLogForwardingConfig,
+        StreamerMemoryConfig, StreamerSandboxConfig, VideoWatchdogConfig, WebRtcConfig,
+    },
 };
 
+/// Input which a user recorded while streaming
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputMacro {
+    pub id: u32,
+    pub name: String,
+    pub events: Vec<InputMacroEvent>,
+}
+
+/// A packet received on an input channel, see [crate::api_bindings::TransportChannelId]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputMacroEvent {
+    /// The time since the previous event
+    pub delay_ms: u32,
+    pub channel: u8,
+    pub data: Bytes,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamerConfig {
     pub webrtc: WebRtcConfig,
+    pub file_transfer: FileTransferConfig,
+    pub controller_rumble: ControllerRumbleConfig,
+    pub video_watchdog: VideoWatchdogConfig,
+    pub peer_watchdog: PeerWatchdogConfig,
+    pub cursor_prediction: CursorPredictionConfig,
+    pub media_priority: MediaPriorityConfig,
+    pub encryption: StreamEncryptionConfig,
+    pub thumbnail: SessionThumbnailConfig,
+    pub rtsp_output: RtspOutputConfig,
+    pub hls_output: HlsOutputConfig,
+    pub sandbox: StreamerSandboxConfig,
+    pub memory: StreamerMemoryConfig,
     pub log_level: LevelFilter,
+    pub log_forwarding: StreamerLogForwardingConfig,
+}
+
+/// A playlist or segment of the hls output, see [HlsOutputConfig]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsFile {
+    pub content_type: String,
+    pub data: Bytes,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -34,37 +79,146 @@ pub enum ServerIpcMessage {
         config: StreamerConfig,
         host_address: String,
         host_http_port: u16,
+        /// The http requests to the host go through this proxy
+        host_proxy: Option<String>,
+        host_xml_parse_mode: XmlParseMode,
         client_unique_id: Option<String>,
         client_private_key: Pem,
         client_certificate: Pem,
         server_certificate: Pem,
         app_id: u32,
+        /// The variants of the app which stream another display by display id
+        display_app_ids: HashMap<u32, u32>,
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
+        input_macros: Vec<InputMacro>,
     },
     WebSocket(StreamClientMessage),
     WebSocketTransport(Bytes),
+    /// Answer to [StreamerIpcMessage::SaveInputMacro]
+    InputMacroSaved(InputMacro),
+    /// Decodes the next IDR frame, answered with [StreamerIpcMessage::Screenshot]
+    TakeScreenshot {
+        format: ScreenshotFormat,
+    },
+    /// Decodes the stream into [StreamerIpcMessage::Preview] frames until [ServerIpcMessage::StopPreview]
+    StartPreview(SessionPreviewConfig),
+    StopPreview,
+    /// Requests a file of the hls output, answered with [StreamerIpcMessage::HlsFile]
+    HlsRequest {
+        request_id: u32,
+        /// Relative to the playlist, e.g. `index.m3u8`
+        path: String,
+        /// The `_HLS_msn` and `_HLS_part` of a blocking playlist reload
+        msn: Option<u64>,
+        part: Option<u32>,
+    },
+    /// Answer to [StreamerIpcMessage::SwitchApp] if the user may use the app
+    SwitchApp {
+        app_id: u32,
+        /// The variants of the app which stream another display by display id
+        display_app_ids: HashMap<u32, u32>,
+    },
     Stop,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum StreamerIpcMessage {
+    /// The first message of every streamer, before it waits for [ServerIpcMessage::Init]
+    Capabilities(StreamerCapabilities),
     WebSocket(StreamServerMessage),
     WebSocketTransport(Bytes),
+    /// Stores a recorded macro for the user of this stream
+    SaveInputMacro {
+        name: String,
+        events: Vec<InputMacroEvent>,
+    },
+    /// Sent when the [crate::api_bindings::GeneralServerMessage::QualityScore] changed
+    QualityScore {
+        score: u8,
+    },
+    /// Answer to [ServerIpcMessage::TakeScreenshot], none if the frame couldn't be decoded
+    Screenshot {
+        image: Option<Bytes>,
+    },
+    /// A small jpeg of the stream, taken periodically
+    Thumbnail {
+        image: Bytes,
+    },
+    /// A jpeg frame of the preview, none if the preview failed and was stopped
+    Preview {
+        image: Option<Bytes>,
+    },
+    /// The rtsp output of the stream listens on this port, see [RtspOutputConfig]
+    RtspOutput {
+        port: u16,
+    },
+    /// Answer to [ServerIpcMessage::HlsRequest], none if the file doesn't exist
+    HlsFile {
+        request_id: u32,
+        file: Option<HlsFile>,
+    },
+    /// A log message of the streamer, see [StreamerLogForwardingConfig]
+    Log {
+        level: Level,
+        target: String,
+        message: String,
+    },
+    /// The client sent [crate::api_bindings::GeneralClientMessage::SwitchApp], the web server checks if the user may use the app
+    SwitchApp {
+        app_id: u32,
+    },
+    /// No peer connected in time after the app was launched, the streamer stops after this, see [PeerWatchdogConfig]
+    PeerWatchdog {
+        /// The app was quit on the host
+        app_quit: bool,
+    },
+    /// The streamer panicked and exits, sent even if the logs aren't forwarded
+    Panic {
+        message: String,
+    },
     Stop,
 }
 
+/// The first message of every connection from a streamer node, see [crate::config::RemoteStreamersConfig]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum NodeHelloMessage {
+    /// Opens the control connection, which continues with [NodeIpcMessage] and [ServerNodeMessage]
+    Register {
+        name: String,
+        networks: Vec<IpNet>,
+        max_sessions: usize,
+    },
+    /// Answer to [ServerNodeMessage::StartSession], the connection continues with the ipc of the spawned streamer
+    Session { session_id: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum NodeIpcMessage {
+    Heartbeat,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerNodeMessage {
+    /// Answer to [NodeIpcMessage::Heartbeat], so the node notices a lost web server
+    Heartbeat,
+    /// Spawns a streamer which connects back with [NodeHelloMessage::Session]
+    StartSession { session_id: u64 },
+    /// Kills the streamer of the session
+    StopSession { session_id: u64 },
+}
+
 // We're using the:
-// Stdin: message passing
-// Stdout: message passing
+// Stdin: message passing (or a named pipe on windows)
+// Stdout: message passing (or a named pipe on windows)
 // Stderr: logging
 
 static CHILD_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 pub async fn create_child_ipc<Message, ChildMessage>(
     log_target: &str,
-    stdin: ChildStdin,
-    stdout: ChildStdout,
+    stdin: impl AsyncWrite + Send + Unpin + 'static,
+    stdout: impl AsyncRead + Send + Unpin + 'static,
     stderr: Option<ChildStderr>,
 ) -> (IpcSender<Message>, IpcReceiver<ChildMessage>)
 where
@@ -110,8 +264,8 @@ where
 }
 
 pub async fn create_process_ipc<ParentMessage, Message>(
-    stdin: Stdin,
-    stdout: Stdout,
+    stdin: impl AsyncRead + Send + Unpin + 'static,
+    stdout: impl AsyncWrite + Send + Unpin + 'static,
 ) -> (IpcSender<Message>, IpcReceiver<ParentMessage>)
 where
     ParentMessage: DeserializeOwned,
@@ -203,6 +357,10 @@ where
             warn!("{}[Ipc] failed to send message", self.log_target);
         }
     }
+    /// Drops the message if the channel is full or closed, returns if it was sent
+    pub fn try_send(&self, message: Message) -> bool {
+        self.sender.try_send(message).is_ok()
+    }
 }
 
 pub struct IpcReceiver<Message> {
@@ -1,25 +1,35 @@
 use std::fmt::{self, Display, Formatter};
 
 use log::warn;
-use moonlight_common::stream::bindings::{Colorspace, SupportedVideoFormats};
+use moonlight_common::{
+    network::launch::AudioRouting,
+    stream::bindings::{Colorspace, SupportedVideoFormats},
+    units::{Bytes, Fps, Kbps},
+};
 use serde::{Deserialize, Serialize};
 
+use crate::api_bindings::StreamVideoRotation;
+
 pub mod api_bindings;
 pub mod api_bindings_consts;
+pub mod build_info;
 pub mod config;
 pub mod ipc;
+pub mod messages;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamSettings {
-    pub bitrate: u32,
-    pub packet_size: u32,
-    pub fps: u32,
+    pub bitrate: Kbps,
+    pub packet_size: Bytes,
+    pub fps: Fps,
     pub width: u32,
     pub height: u32,
-    pub play_audio_local: bool,
+    pub audio_routing: AudioRouting,
     pub video_supported_formats: SupportedVideoFormats,
     pub video_colorspace: Colorspace,
     pub video_color_range_full: bool,
+    pub video_rotation: StreamVideoRotation,
+    pub display_id: Option<u32>,
 }
 
 impl Display for StreamSettings {
@@ -27,7 +37,10 @@ impl Display for StreamSettings {
         write!(
             f,
             "{} with {}x{}x{}",
-            self.video_supported_formats, self.width, self.height, self.fps
+            self.video_supported_formats,
+            self.width,
+            self.height,
+            self.fps.get()
         )
     }
 }
@@ -0,0 +1,159 @@
+//! Texts of the messages which are shown to the user.
+//!
+//! Every message has a [StreamMessageCode] so that clients can show their own translation.
+//! The english texts can be overwritten by the `messages` of the config.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api_bindings::{LogMessageType, StreamMessageCode, StreamServerMessage};
+
+/// Templates by the name of the code, arguments are inserted at `{argument}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageCatalog {
+    templates: HashMap<String, String>,
+}
+
+impl MessageCatalog {
+    pub fn text(&self, code: &StreamMessageCode) -> String {
+        let template = self
+            .templates
+            .get(code.name())
+            .map(String::as_str)
+            .unwrap_or_else(|| code.english_template());
+
+        let mut text = template.to_string();
+        for (name, value) in code.arguments() {
+            text = text.replace(&format!("{{{name}}}"), &value);
+        }
+
+        text
+    }
+
+    pub fn debug_log(
+        &self,
+        code: StreamMessageCode,
+        ty: Option<LogMessageType>,
+    ) -> StreamServerMessage {
+        StreamServerMessage::DebugLog {
+            message: self.text(&code),
+            ty,
+            code: Some(code),
+        }
+    }
+
+    /// Replaces the text of a message which was created by another catalog, e.g. in the streamer
+    pub fn localize(&self, message: &mut StreamServerMessage) {
+        if let StreamServerMessage::DebugLog {
+            message,
+            code: Some(code),
+            ..
+        } = message
+        {
+            *message = self.text(code);
+        }
+    }
+}
+
+impl StreamMessageCode {
+    /// Creates the message with the english text
+    pub fn debug_log(self, ty: Option<LogMessageType>) -> StreamServerMessage {
+        MessageCatalog::default().debug_log(self, ty)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LaunchStreamer => "LaunchStreamer",
+            Self::LaunchStreamerCompleted => "LaunchStreamerCompleted",
+            Self::WaitingForTransport => "WaitingForTransport",
+            Self::MoonlightStream => "MoonlightStream",
+            Self::StageStarting { .. } => "StageStarting",
+            Self::StageCompleted { .. } => "StageCompleted",
+            Self::StageFailed { .. } => "StageFailed",
+            Self::HostNotFound => "HostNotFound",
+            Self::AppNotFound => "AppNotFound",
+            Self::HostNotPaired => "HostNotPaired",
+            Self::ServerError => "ServerError",
+            Self::NoCommonVideoCodec => "NoCommonVideoCodec",
+            Self::UnsupportedVideoFormat { .. } => "UnsupportedVideoFormat",
+            Self::VideoTrackFailed { .. } => "VideoTrackFailed",
+            Self::TakeoverFailed => "TakeoverFailed",
+            Self::VideoStalled => "VideoStalled",
+            Self::VideoStallRestart => "VideoStallRestart",
+            Self::VideoRecovered => "VideoRecovered",
+            Self::ResumeFailed => "ResumeFailed",
+            Self::WakingHost => "WakingHost",
+            Self::WaitingForHostBoot => "WaitingForHostBoot",
+            Self::HostBooted => "HostBooted",
+            Self::HostOffline => "HostOffline",
+            Self::UnknownStreamPreset { .. } => "UnknownStreamPreset",
+            Self::SwitchingApp => "SwitchingApp",
+            Self::SwitchAppDenied => "SwitchAppDenied",
+        }
+    }
+
+    fn english_template(&self) -> &'static str {
+        match self {
+            Self::LaunchStreamer => "Launching streamer",
+            Self::LaunchStreamerCompleted => "Completed Stage: Launch Streamer",
+            Self::WaitingForTransport => "Waiting for Transport to negotiate",
+            Self::MoonlightStream => "Moonlight Stream",
+            Self::StageStarting { .. } => "Starting Stage: {stage}",
+            Self::StageCompleted { .. } => "Completed Stage: {stage}",
+            Self::StageFailed { .. } => "Failed Stage: {stage} with error code {error_code}",
+            Self::HostNotFound => "Failed to start stream because the host was not found",
+            Self::AppNotFound => "Failed to start stream because the app was not found",
+            Self::HostNotPaired => "Failed to start stream because the host is not paired",
+            Self::ServerError => "Failed to start stream because of a server error",
+            Self::NoCommonVideoCodec => {
+                "Failed to start stream because the browser and the host don't support a common video codec"
+            }
+            Self::UnsupportedVideoFormat { .. } => {
+                "The host tried to setup a video stream with a non supported video format: {format}, supported formats: {supported_formats}"
+            }
+            Self::VideoTrackFailed { .. } => "Failed to create video track with format {format}",
+            Self::TakeoverFailed => {
+                "The host didn't stop the current stream, it was likely started by another device"
+            }
+            Self::VideoStalled => "The host stopped sending video, requesting a new frame",
+            Self::VideoStallRestart => {
+                "The host still doesn't send video, restarting the connection to the host"
+            }
+            Self::VideoRecovered => "The host sends video again",
+            Self::ResumeFailed => "Failed to reconnect to the stream because it already ended",
+            Self::WakingHost => "The host is offline, waking it up",
+            Self::WaitingForHostBoot => "Waiting for the host to boot",
+            Self::HostBooted => "The host is online",
+            Self::HostOffline => "Failed to start stream because the host is offline",
+            Self::UnknownStreamPreset { .. } => {
+                "Failed to start stream because the stream preset \"{name}\" doesn't exist"
+            }
+            Self::SwitchingApp => "Switching the app",
+            Self::SwitchAppDenied => "Failed to switch the app because it was not found",
+        }
+    }
+
+    fn arguments(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::StageStarting { stage } | Self::StageCompleted { stage } => {
+                vec![("stage", stage.clone())]
+            }
+            Self::StageFailed { stage, error_code } => vec![
+                ("stage", stage.clone()),
+                ("error_code", error_code.to_string()),
+            ],
+            Self::UnsupportedVideoFormat {
+                format,
+                supported_formats,
+            } => vec![
+                ("format", format.clone()),
+                ("supported_formats", supported_formats.clone()),
+            ],
+            Self::VideoTrackFailed { format } => vec![("format", format.clone())],
+            Self::UnknownStreamPreset { name } => vec![("name", name.clone())],
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Benchmarks of the packet hot paths: data channel packets, nal parsing and rtp payloading.
+//!
+//! The streamer is only a binary so the modules are included by their path.
+//! Run with `cargo bench -p streamer`, add `--features profiling` to also print the allocations per frame.
+
+#![allow(dead_code)]
+
+use std::{hint::black_box, io::Cursor};
+
+use bytes::Bytes;
+use common::api_bindings::{
+    ConnectionStatus, GeneralServerMessage, StatsClientMessage, StreamerStatsUpdate,
+    TransportChannelId,
+};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use webrtc::rtp::{
+    codecs::av1::Av1Payloader, codecs::h265::RTP_OUTBOUND_MTU, packetizer::Payloader,
+};
+
+use crate::transport::{
+    InboundPacket, OutboundPacket, TransportChannel,
+    webrtc::video::{
+        annexb::AnnexBSplitter,
+        h264::{payloader::H264Payloader, reader::H264Reader},
+        h265::{payloader::H265Payloader, reader::H265Reader},
+    },
+};
+
+#[path = "../src/buffer.rs"]
+mod buffer;
+#[path = "../src/convert.rs"]
+mod convert;
+#[path = "../src/memory.rs"]
+mod memory;
+#[cfg(feature = "profiling")]
+#[path = "../src/profiling.rs"]
+mod profiling;
+#[path = "../src/quality.rs"]
+mod quality;
+#[path = "../src/transport/mod.rs"]
+mod transport;
+
+#[cfg(feature = "profiling")]
+#[global_allocator]
+static ALLOCATOR: profiling::CountingAllocator = profiling::CountingAllocator;
+
+/// Size of the slice nal unit in the synthetic frames, roughly a 1080p delta frame
+const SLICE_SIZE: usize = 64 * 1024;
+
+fn json_packet(message: &impl serde::Serialize) -> Vec<u8> {
+    let text = serde_json::to_string(message).expect("failed to serialize json packet");
+
+    let mut packet = (text.len() as u16).to_be_bytes().to_vec();
+    packet.extend_from_slice(text.as_bytes());
+    packet
+}
+
+fn inbound_packets() -> Vec<(&'static str, TransportChannel, Vec<u8>)> {
+    vec![
+        (
+            "mouse_move",
+            TransportChannel(TransportChannelId::MOUSE_RELATIVE),
+            vec![0, 0, 12, 255, 250],
+        ),
+        (
+            "key",
+            TransportChannel(TransportChannelId::KEYBOARD),
+            vec![0, 1, 0, 0, 0x41],
+        ),
+        (
+            "controller_state",
+            TransportChannel(InboundPacket::CONTROLLER_CHANNELS[0]),
+            vec![
+                0, 0, 0, 0x10, 0x00, 255, 0, 0x7F, 0xFF, 0x80, 0x00, 0, 0, 0, 0,
+            ],
+        ),
+        (
+            "stats_echo",
+            TransportChannel(TransportChannelId::STATS),
+            json_packet(&StatsClientMessage::LatencyEcho {
+                presentation_time_us: 16_000,
+            }),
+        ),
+    ]
+}
+
+fn outbound_packets() -> Vec<(&'static str, OutboundPacket)> {
+    vec![
+        (
+            "general",
+            OutboundPacket::General {
+                message: GeneralServerMessage::ConnectionStatusUpdate {
+                    status: ConnectionStatus::Ok,
+                },
+            },
+        ),
+        (
+            "stats_rtt",
+            OutboundPacket::Stats(StreamerStatsUpdate::Rtt {
+                rtt_ms: 4.2,
+                rtt_variance_ms: 0.8,
+            }),
+        ),
+        (
+            "controller_rumble",
+            OutboundPacket::ControllerRumble {
+                controller_number: 0,
+                low_frequency_motor: 0x4000,
+                high_frequency_motor: 0x8000,
+            },
+        ),
+    ]
+}
+
+fn slice(header: &[u8]) -> Vec<u8> {
+    let mut unit = header.to_vec();
+    unit.extend((0..SLICE_SIZE).map(|i| (i % 251) as u8 | 1));
+    unit
+}
+
+fn annex_b_frame(units: &[Vec<u8>]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    for unit in units {
+        frame.extend_from_slice(&[0, 0, 0, 1]);
+        frame.extend_from_slice(unit);
+    }
+    frame
+}
+
+/// Sps, Pps, Idr
+fn h264_units() -> Vec<Vec<u8>> {
+    vec![
+        vec![0x67, 0x42, 0xE0, 0x1F, 0x8C, 0x8D],
+        vec![0x68, 0xCE, 0x3C, 0x80],
+        slice(&[0x65, 0x88]),
+    ]
+}
+
+/// Vps, Sps, Pps, Idr
+fn h265_units() -> Vec<Vec<u8>> {
+    vec![
+        vec![0x40, 0x01, 0x0C, 0x01],
+        vec![0x42, 0x01, 0x01, 0x01],
+        vec![0x44, 0x01, 0xC1, 0x72],
+        slice(&[0x26, 0x01, 0xAF]),
+    ]
+}
+
+/// Temporal delimiter, Frame
+fn av1_units() -> Vec<Vec<u8>> {
+    let mut frame_header = vec![0x32];
+    let mut size = SLICE_SIZE;
+    while size >= 0x80 {
+        frame_header.push((size as u8 & 0x7F) | 0x80);
+        size >>= 7;
+    }
+    frame_header.push(size as u8);
+
+    vec![vec![0x12, 0x00], slice(&frame_header)]
+}
+
+fn bench_inbound(c: &mut Criterion) {
+    let mut group = c.benchmark_group("inbound_deserialize");
+
+    for (name, channel, packet) in inbound_packets() {
+        group.throughput(Throughput::Bytes(packet.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| InboundPacket::deserialize(black_box(channel), black_box(&packet)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_outbound(c: &mut Criterion) {
+    let mut group = c.benchmark_group("outbound_serialize");
+
+    for (name, packet) in outbound_packets() {
+        let mut buffer = Vec::new();
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(&packet).serialize(&mut buffer))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_nal_readers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nal_reader");
+
+    let h264 = annex_b_frame(&h264_units());
+    group.throughput(Throughput::Bytes(h264.len() as u64));
+    group.bench_function("h264", |b| {
+        let mut reader = H264Reader::new(Cursor::new(&[] as &[u8]), 0);
+        b.iter(|| {
+            reader.reset(Cursor::new(black_box(h264.as_slice())));
+            while let Some(nal) = reader.next_nal().expect("failed to read h264 nal") {
+                black_box(nal);
+            }
+        })
+    });
+
+    let h265 = annex_b_frame(&h265_units());
+    group.throughput(Throughput::Bytes(h265.len() as u64));
+    group.bench_function("h265", |b| {
+        let mut reader = H265Reader::new(Cursor::new(&[] as &[u8]), 0);
+        b.iter(|| {
+            reader.reset(Cursor::new(black_box(h265.as_slice())));
+            while let Some(nal) = reader.next_nal().expect("failed to read h265 nal") {
+                black_box(nal);
+            }
+        })
+    });
+
+    let av1 = annex_b_frame(&av1_units());
+    group.throughput(Throughput::Bytes(av1.len() as u64));
+    group.bench_function("annex_b", |b| {
+        let mut splitter = AnnexBSplitter::new(Cursor::new(&[] as &[u8]), 0);
+        b.iter(|| {
+            splitter.reset(Cursor::new(black_box(av1.as_slice())));
+            while let Some(data) = splitter.next().expect("failed to split annex b") {
+                black_box(data);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn payload_frame(payloader: &mut dyn Payloader, units: &[Bytes]) -> usize {
+    let mut payload_count = 0;
+    for unit in units {
+        let payloads = payloader
+            .payload(RTP_OUTBOUND_MTU - 12, unit)
+            .expect("failed to payload unit");
+        payload_count += payloads.len();
+        black_box(payloads);
+    }
+    payload_count
+}
+
+fn bench_payloaders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("payloader");
+
+    let frames: [(&str, Box<dyn Fn() -> Box<dyn Payloader>>, Vec<Vec<u8>>); 3] = [
+        (
+            "h264",
+            Box::new(|| Box::new(H264Payloader::default())),
+            h264_units(),
+        ),
+        (
+            "h265",
+            Box::new(|| Box::new(H265Payloader::default())),
+            h265_units(),
+        ),
+        (
+            "av1",
+            Box::new(|| Box::new(Av1Payloader::default())),
+            av1_units(),
+        ),
+    ];
+
+    for (name, new_payloader, units) in frames {
+        let units = units.into_iter().map(Bytes::from).collect::<Vec<_>>();
+        let frame_size = units.iter().map(|unit| unit.len()).sum::<usize>();
+
+        #[cfg(feature = "profiling")]
+        {
+            let mut payloader = new_payloader();
+            let before = profiling::thread_allocation_count();
+            payload_frame(payloader.as_mut(), &units);
+            println!(
+                "payloader/{name}: {} allocations per frame",
+                profiling::thread_allocation_count() - before
+            );
+        }
+
+        group.throughput(Throughput::Bytes(frame_size as u64));
+        group.bench_function(name, |b| {
+            let mut payloader = new_payloader();
+            b.iter(|| payload_frame(payloader.as_mut(), black_box(&units)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_inbound,
+    bench_outbound,
+    bench_nal_readers,
+    bench_payloaders
+);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use std::sync::Weak;
+use std::sync::{Weak, atomic::Ordering};
 
 use log::{debug, error, warn};
 use moonlight_common::stream::{
@@ -6,7 +6,7 @@ use moonlight_common::stream::{
     bindings::{AudioConfig, Capabilities, OpusMultistreamConfig},
 };
 
-use crate::StreamConnection;
+use crate::{StreamConnection, rtsp::push_rtsp_audio};
 
 pub(crate) struct StreamAudioDecoder {
     pub(crate) stream: Weak<StreamConnection>,
@@ -49,11 +49,18 @@ impl AudioDecoder for StreamAudioDecoder {
             return;
         };
 
+        // The audio is only played on the host
+        if !stream.audio_to_client.load(Ordering::Acquire) {
+            return;
+        }
+
         stream.runtime.clone().block_on(async move {
-            let mut stream = stream.transport_sender.lock().await;
+            push_rtsp_audio(&stream, data).await;
 
-            if let Some(stream) = stream.as_mut() {
-                if let Err(err) = stream.send_audio_sample(data).await {
+            let mut sender = stream.transport_sender.lock().await;
+
+            if let Some(sender) = sender.as_mut() {
+                if let Err(err) = sender.send_audio_sample(data).await {
                     warn!("Failed to send audio sample: {err}");
                 }
             } else {
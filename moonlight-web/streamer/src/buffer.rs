@@ -161,6 +161,15 @@ where
 
         self.put_u8_array(&bytes)
     }
+    pub fn put_i16(&mut self, data: i16) -> bool {
+        let bytes: [u8; 2] = if self.little_endian {
+            i16::to_le_bytes(data)
+        } else {
+            i16::to_be_bytes(data)
+        };
+
+        self.put_u8_array(&bytes)
+    }
     pub fn put_u32(&mut self, data: u32) -> bool {
         let bytes: [u8; 4] = if self.little_endian {
             u32::to_le_bytes(data)
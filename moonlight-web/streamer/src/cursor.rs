@@ -0,0 +1,85 @@
+//! Echoes the absolute cursor position which was forwarded to the host, see [GeneralClientMessage::SetCursorPrediction](common::api_bindings::GeneralClientMessage::SetCursorPrediction).
+//!
+//! Clients which draw a predicted cursor correct it with the echo, because the host only applies the positions which reached it.
+
+use crate::transport::OutboundPacket;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CursorPosition {
+    pub x: i16,
+    pub y: i16,
+    pub reference_width: i16,
+    pub reference_height: i16,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CursorEcho {
+    enabled: bool,
+    /// The last position which was forwarded to the host
+    position: Option<CursorPosition>,
+}
+
+impl CursorEcho {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn on_forwarded(&mut self, position: CursorPosition) {
+        self.position = Some(position);
+    }
+
+    /// Sent every echo interval, the packets are unreliable so an unchanged position is sent again
+    pub fn echo(&self) -> Option<OutboundPacket> {
+        if !self.enabled {
+            return None;
+        }
+
+        let position = self.position?;
+        Some(OutboundPacket::CursorPosition {
+            x: position.x,
+            y: position.y,
+            reference_width: position.reference_width,
+            reference_height: position.reference_height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cursor::{CursorEcho, CursorPosition},
+        transport::OutboundPacket,
+    };
+
+    const POSITION: CursorPosition = CursorPosition {
+        x: 100,
+        y: 200,
+        reference_width: 1920,
+        reference_height: 1080,
+    };
+
+    #[test]
+    fn test_echoes_only_when_enabled() {
+        let mut echo = CursorEcho::default();
+
+        echo.on_forwarded(POSITION);
+        assert!(echo.echo().is_none());
+
+        echo.set_enabled(true);
+        assert!(matches!(
+            echo.echo(),
+            Some(OutboundPacket::CursorPosition { x: 100, y: 200, .. })
+        ));
+
+        echo.set_enabled(false);
+        assert!(echo.echo().is_none());
+    }
+
+    #[test]
+    fn test_nothing_forwarded_yet() {
+        let mut echo = CursorEcho::default();
+        echo.set_enabled(true);
+
+        assert!(echo.echo().is_none());
+    }
+}
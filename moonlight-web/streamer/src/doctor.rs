@@ -0,0 +1,117 @@
+//! `--doctor`: checks if the streamer can run on this machine without starting a stream.
+//!
+//! Usage: `streamer --doctor [--port-range MIN:MAX]`
+
+use std::{
+    fmt::Display,
+    net::{Ipv4Addr, UdpSocket},
+};
+
+use anyhow::{Context, bail};
+use common::config::{PortRange, WebRtcConfig};
+use log::LevelFilter;
+use moonlight_common::stream::MoonlightInstance;
+use simplelog::{ColorChoice, TermLogger, TerminalMode};
+
+use crate::{flag_value, transport::webrtc};
+
+/// Returns true if all checks passed
+pub async fn run() -> bool {
+    TermLogger::init(
+        LevelFilter::Warn,
+        simplelog::ConfigBuilder::new()
+            .add_filter_ignore_str("webrtc_sctp")
+            .set_time_level(LevelFilter::Off)
+            .build(),
+        TerminalMode::Stderr,
+        ColorChoice::Never,
+    )
+    .expect("failed to init logger");
+
+    let mut config = WebRtcConfig::default();
+    match port_range_arg() {
+        Ok(port_range) => config.port_range = port_range,
+        Err(err) => {
+            report("Arguments", Err::<&str, _>(err));
+            return false;
+        }
+    }
+
+    let mut success = true;
+
+    success &= report("Moonlight", check_moonlight());
+    success &= report("UDP Ports", check_udp_ports(config.port_range.as_ref()));
+    success &= report(
+        "WebRTC Loopback",
+        webrtc::loopback_test(&config)
+            .await
+            .map(|elapsed| format!("connected in {}ms", elapsed.as_millis())),
+    );
+
+    for (codec, result) in webrtc::payloader_self_test() {
+        success &= report(
+            &format!("{codec} Payloader"),
+            result.map(|packet_count| format!("created {packet_count} packets")),
+        );
+    }
+
+    success
+}
+
+fn report(name: &str, result: Result<impl Display, anyhow::Error>) -> bool {
+    match result {
+        Ok(message) => {
+            println!("[OK]   {name}: {message}");
+            true
+        }
+        Err(err) => {
+            println!("[FAIL] {name}: {err:#}");
+            false
+        }
+    }
+}
+
+fn port_range_arg() -> Result<Option<PortRange>, anyhow::Error> {
+    let Some(value) = flag_value("--port-range")? else {
+        return Ok(None);
+    };
+
+    Ok(Some(value.parse()?))
+}
+
+fn check_moonlight() -> Result<String, anyhow::Error> {
+    let instance = MoonlightInstance::global().context("failed to initialize moonlight")?;
+
+    Ok(format!(
+        "linked, launch parameters \"{}\"",
+        instance.launch_url_query_parameters()
+    ))
+}
+
+fn check_udp_ports(port_range: Option<&PortRange>) -> Result<String, anyhow::Error> {
+    let Some(PortRange { min, max }) = port_range.cloned() else {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+
+        return Ok(format!(
+            "no port range configured, bound ephemeral port {}",
+            socket.local_addr()?.port()
+        ));
+    };
+
+    if min > max {
+        bail!("the port range {min}:{max} is empty");
+    }
+
+    let total = (max - min) as usize + 1;
+    let available = (min..=max)
+        .filter(|port| UdpSocket::bind((Ipv4Addr::UNSPECIFIED, *port)).is_ok())
+        .count();
+
+    if available == 0 {
+        bail!("none of the {total} ports in {min}:{max} are available");
+    }
+
+    Ok(format!(
+        "{available} of {total} ports in {min}:{max} are available"
+    ))
+}
@@ -0,0 +1,109 @@
+//! Picks the [EncryptionFlags] of a stream with the [StreamEncryptionConfig].
+
+use std::net::IpAddr;
+
+use common::config::StreamEncryptionConfig;
+use log::{info, warn};
+use moonlight_common::stream::bindings::EncryptionFlags;
+use tokio::net::lookup_host;
+
+/// Hosts outside of a private network always encrypt everything, no matter what's configured
+pub async fn encryption_flags(
+    config: &StreamEncryptionConfig,
+    host_address: &str,
+    host_port: u16,
+) -> EncryptionFlags {
+    if config.video && config.audio {
+        return EncryptionFlags::ALL;
+    }
+
+    let addresses = match lookup_host((host_address, host_port)).await {
+        Ok(addresses) => addresses.map(|address| address.ip()).collect::<Vec<_>>(),
+        Err(err) => {
+            warn!(
+                "[Stream]: failed to resolve the host address {host_address} to check if it's in a private network, encrypting everything: {err}"
+            );
+            return EncryptionFlags::ALL;
+        }
+    };
+
+    if addresses.is_empty() || !addresses.iter().all(|ip| is_private_address(*ip)) {
+        info!("[Stream]: the host isn't in a private network, encrypting everything");
+        return EncryptionFlags::ALL;
+    }
+
+    configured_flags(config)
+}
+
+fn configured_flags(config: &StreamEncryptionConfig) -> EncryptionFlags {
+    let mut flags = EncryptionFlags::NONE;
+    if config.video {
+        flags |= EncryptionFlags::VIDEO;
+    }
+    if config.audio {
+        flags |= EncryptionFlags::AUDIO;
+    }
+
+    flags
+}
+
+fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_address(IpAddr::V4(ip)),
+            None => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use common::config::StreamEncryptionConfig;
+    use moonlight_common::stream::bindings::EncryptionFlags;
+
+    use crate::encryption::{encryption_flags, is_private_address};
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn private_addresses() {
+        assert!(is_private_address(ip("192.168.1.20")));
+        assert!(is_private_address(ip("10.0.0.1")));
+        assert!(is_private_address(ip("127.0.0.1")));
+        assert!(is_private_address(ip("fd12:3456::1")));
+        assert!(is_private_address(ip("fe80::1")));
+        assert!(is_private_address(ip("::ffff:172.16.0.5")));
+
+        assert!(!is_private_address(ip("8.8.8.8")));
+        assert!(!is_private_address(ip("100.64.0.1")));
+        assert!(!is_private_address(ip("2001:db8::1")));
+        assert!(!is_private_address(ip("::ffff:1.1.1.1")));
+    }
+
+    #[tokio::test]
+    async fn local_hosts_use_the_config() {
+        let config = StreamEncryptionConfig {
+            video: false,
+            audio: true,
+        };
+
+        let flags = encryption_flags(&config, "192.168.1.20", 47989).await;
+        assert_eq!(flags.bits(), EncryptionFlags::AUDIO.bits());
+    }
+
+    #[tokio::test]
+    async fn remote_hosts_encrypt_everything() {
+        let config = StreamEncryptionConfig {
+            video: false,
+            audio: false,
+        };
+
+        let flags = encryption_flags(&config, "8.8.8.8", 47989).await;
+        assert_eq!(flags.bits(), EncryptionFlags::ALL.bits());
+    }
+}
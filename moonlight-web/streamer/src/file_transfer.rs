@@ -0,0 +1,318 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+};
+
+use common::{api_bindings::FileTransferErrorCode, config::FileTransferConfig};
+use log::{debug, info, warn};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::transport::{FileTransferPacket, FileTransferStatus};
+
+/// Existing files are never overwritten, the received file is renamed to `name (1).ext` and so on
+const MAX_NUMBERED_FILE_NAMES: u32 = 1000;
+
+struct ActiveFileTransfer {
+    file: File,
+    part_path: PathBuf,
+    directory: PathBuf,
+    file_name: String,
+    size: u32,
+    received: u32,
+}
+
+/// Reassembles the chunks received on the file channel into files inside the configured directory.
+/// Files are written into a `.part` file first and only renamed once all bytes have been received.
+/// The `.part` file contains the process and transfer id, so concurrent transfers of the same name don't share it.
+pub struct FileTransfers {
+    config: FileTransferConfig,
+    transfers: HashMap<u32, ActiveFileTransfer>,
+}
+
+impl FileTransfers {
+    pub fn new(config: FileTransferConfig) -> Self {
+        Self {
+            config,
+            transfers: HashMap::new(),
+        }
+    }
+
+    /// Returns the status which should be sent back to the client
+    pub async fn on_packet(
+        &mut self,
+        packet: FileTransferPacket,
+    ) -> Option<(u32, FileTransferStatus)> {
+        match packet {
+            FileTransferPacket::Start { id, size, name } => {
+                let status = match self.start(id, size, &name).await {
+                    Ok(()) => FileTransferStatus::Progress { received: 0 },
+                    Err(code) => FileTransferStatus::Failed { code },
+                };
+
+                Some((id, status))
+            }
+            FileTransferPacket::Chunk { id, offset, data } => {
+                let status = match self.write_chunk(id, offset, &data).await {
+                    Ok(status) => status,
+                    Err(code) => {
+                        self.abort(id).await;
+                        FileTransferStatus::Failed { code }
+                    }
+                };
+
+                Some((id, status))
+            }
+            FileTransferPacket::Cancel { id } => {
+                debug!("[FileTransfer]: transfer {id} was cancelled by the client");
+                self.abort(id).await;
+
+                None
+            }
+        }
+    }
+
+    async fn start(&mut self, id: u32, size: u32, name: &str) -> Result<(), u8> {
+        let Some(directory) = self.config.directory.as_ref() else {
+            return Err(FileTransferErrorCode::DISABLED);
+        };
+
+        if size > self.config.max_file_size {
+            info!(
+                "[FileTransfer]: rejecting file \"{name}\" with {size} bytes because it exceeds the limit of {} bytes",
+                self.config.max_file_size
+            );
+            return Err(FileTransferErrorCode::TOO_LARGE);
+        }
+
+        let Some(file_name) = sanitize_file_name(name) else {
+            info!("[FileTransfer]: rejecting file because of invalid name \"{name}\"");
+            return Err(FileTransferErrorCode::INVALID_NAME);
+        };
+
+        // A client might reuse the id of a previous transfer
+        self.abort(id).await;
+
+        let directory = Path::new(directory);
+        if let Err(err) = fs::create_dir_all(directory).await {
+            warn!("[FileTransfer]: failed to create directory {directory:?}: {err}");
+            return Err(FileTransferErrorCode::IO);
+        }
+
+        let part_path = directory.join(format!("{file_name}.{}-{id}.part", process::id()));
+
+        let file = match File::create(&part_path).await {
+            Ok(file) => file,
+            Err(err) => {
+                warn!("[FileTransfer]: failed to create file {part_path:?}: {err}");
+                return Err(FileTransferErrorCode::IO);
+            }
+        };
+
+        info!("[FileTransfer]: receiving file \"{file_name}\" in {directory:?} with {size} bytes");
+
+        self.transfers.insert(
+            id,
+            ActiveFileTransfer {
+                file,
+                part_path,
+                directory: directory.to_path_buf(),
+                file_name: file_name.to_string(),
+                size,
+                received: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn write_chunk(
+        &mut self,
+        id: u32,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<FileTransferStatus, u8> {
+        let Some(transfer) = self.transfers.get_mut(&id) else {
+            return Err(FileTransferErrorCode::UNKNOWN_TRANSFER);
+        };
+
+        // The file channel is reliable and ordered so chunks must arrive in sequence
+        if offset != transfer.received
+            || (transfer.received as u64 + data.len() as u64) > transfer.size as u64
+        {
+            warn!(
+                "[FileTransfer]: received chunk at offset {offset} with {} bytes for transfer {id}, expected offset {}",
+                data.len(),
+                transfer.received
+            );
+            return Err(FileTransferErrorCode::INVALID_OFFSET);
+        }
+
+        if let Err(err) = transfer.file.write_all(data).await {
+            warn!(
+                "[FileTransfer]: failed to write to {:?}: {err}",
+                transfer.part_path
+            );
+            return Err(FileTransferErrorCode::IO);
+        }
+        transfer.received += data.len() as u32;
+
+        if transfer.received < transfer.size {
+            return Ok(FileTransferStatus::Progress {
+                received: transfer.received,
+            });
+        }
+
+        #[allow(clippy::unwrap_used)]
+        let mut transfer = self.transfers.remove(&id).unwrap();
+
+        if let Err(err) = transfer.file.flush().await {
+            warn!(
+                "[FileTransfer]: failed to flush {:?}: {err}",
+                transfer.part_path
+            );
+            return Err(FileTransferErrorCode::IO);
+        }
+        drop(transfer.file);
+
+        let path = match reserve_path(&transfer.directory, &transfer.file_name).await {
+            Ok(path) => path,
+            Err(err) => {
+                warn!(
+                    "[FileTransfer]: failed to find a free name for \"{}\": {err}",
+                    transfer.file_name
+                );
+                let _ = fs::remove_file(&transfer.part_path).await;
+                return Err(FileTransferErrorCode::IO);
+            }
+        };
+
+        // Replaces the empty file which reserved the name
+        if let Err(err) = fs::rename(&transfer.part_path, &path).await {
+            warn!(
+                "[FileTransfer]: failed to move {:?} to {path:?}: {err}",
+                transfer.part_path
+            );
+            let _ = fs::remove_file(&transfer.part_path).await;
+            let _ = fs::remove_file(&path).await;
+            return Err(FileTransferErrorCode::IO);
+        }
+
+        info!("[FileTransfer]: received file {path:?}");
+
+        Ok(FileTransferStatus::Complete)
+    }
+
+    async fn abort(&mut self, id: u32) {
+        let Some(transfer) = self.transfers.remove(&id) else {
+            return;
+        };
+        drop(transfer.file);
+
+        if let Err(err) = fs::remove_file(&transfer.part_path).await {
+            warn!(
+                "[FileTransfer]: failed to remove partial file {:?}: {err}",
+                transfer.part_path
+            );
+        }
+    }
+
+    /// Removes all partial files of transfers which didn't complete
+    pub async fn clear(&mut self) {
+        let ids = self.transfers.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.abort(id).await;
+        }
+    }
+}
+
+/// Creates an empty file with the first name that doesn't exist yet,
+/// creating it fails if another transfer took the name in between
+async fn reserve_path(directory: &Path, file_name: &str) -> Result<PathBuf, std::io::Error> {
+    for number in 0..MAX_NUMBERED_FILE_NAMES {
+        let path = directory.join(numbered_file_name(file_name, number));
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(std::io::Error::new(
+        ErrorKind::AlreadyExists,
+        "all numbered names are taken",
+    ))
+}
+
+/// `name.ext`, `name (1).ext`, `name (2).ext`, ...
+fn numbered_file_name(file_name: &str, number: u32) -> String {
+    if number == 0 {
+        return file_name.to_string();
+    }
+
+    // A leading dot belongs to the name, e.g. ".config"
+    match file_name.rfind('.').filter(|index| *index > 0) {
+        Some(index) => format!("{} ({number}){}", &file_name[..index], &file_name[index..]),
+        None => format!("{file_name} ({number})"),
+    }
+}
+
+/// Only allow plain file names so that a client cannot escape the configured directory
+fn sanitize_file_name(name: &str) -> Option<&str> {
+    let name = name.trim();
+
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(['/', '\\', ':', '\0'])
+        || name.ends_with(".part")
+    {
+        return None;
+    }
+
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::file_transfer::{numbered_file_name, sanitize_file_name};
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("save.dat"), Some("save.dat"));
+        assert_eq!(sanitize_file_name("  save.dat "), Some("save.dat"));
+        assert_eq!(sanitize_file_name("..save"), Some("..save"));
+
+        assert_eq!(sanitize_file_name(""), None);
+        assert_eq!(sanitize_file_name("."), None);
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name("../save.dat"), None);
+        assert_eq!(sanitize_file_name("..\\save.dat"), None);
+        assert_eq!(sanitize_file_name("/etc/passwd"), None);
+        assert_eq!(sanitize_file_name("C:\\Windows\\save.dat"), None);
+        assert_eq!(sanitize_file_name("C:save.dat"), None);
+        assert_eq!(sanitize_file_name("saves/save.dat"), None);
+        assert_eq!(sanitize_file_name("save\0.dat"), None);
+        assert_eq!(sanitize_file_name("save.dat.part"), None);
+        assert_eq!(sanitize_file_name(".part"), None);
+    }
+
+    #[test]
+    fn test_numbered_file_name() {
+        assert_eq!(numbered_file_name("save.dat", 0), "save.dat");
+        assert_eq!(numbered_file_name("save.dat", 1), "save (1).dat");
+        assert_eq!(numbered_file_name("save.tar.gz", 2), "save.tar (2).gz");
+        assert_eq!(numbered_file_name("save", 1), "save (1)");
+        assert_eq!(numbered_file_name(".config", 1), ".config (1)");
+    }
+}
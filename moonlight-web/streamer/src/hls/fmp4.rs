@@ -0,0 +1,523 @@
+//! A minimal fragmented mp4 muxer for a single video track.
+//!
+//! The init segment describes the track with its parameter sets and every fragment is a `moof` and `mdat` box pair.
+
+use bytes::Bytes;
+
+/// The timescale of the video track, the same as rtp uses for video
+pub(super) const TIMESCALE: u32 = 90000;
+
+const TRACK_ID: u32 = 1;
+
+/// Sample flags of `trun` entries
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Fmp4Codec {
+    H264,
+    H265 {
+        chroma_format_idc: u8,
+        bit_depth_minus8: u8,
+    },
+}
+
+/// The parameter sets of a keyframe, without start codes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct ParameterSets {
+    /// Only used by h265
+    pub vps: Vec<Bytes>,
+    pub sps: Vec<Bytes>,
+    pub pps: Vec<Bytes>,
+}
+
+impl ParameterSets {
+    pub fn is_complete(&self, codec: Fmp4Codec) -> bool {
+        let vps = matches!(codec, Fmp4Codec::H264) || !self.vps.is_empty();
+
+        vps && !self.sps.is_empty() && !self.pps.is_empty()
+    }
+}
+
+/// A frame with length prefixed nal units
+#[derive(Debug, Clone)]
+pub(super) struct Sample {
+    pub data: Bytes,
+    pub decode_time: u64,
+    pub duration: u32,
+    pub keyframe: bool,
+}
+
+/// Writes nested boxes, the size of a box is filled in once it's closed
+#[derive(Default)]
+struct BoxWriter {
+    data: Vec<u8>,
+    open: Vec<usize>,
+}
+
+impl BoxWriter {
+    fn start(&mut self, kind: &[u8; 4]) {
+        self.open.push(self.data.len());
+        self.u32(0);
+        self.bytes(kind);
+    }
+    fn start_full(&mut self, kind: &[u8; 4], version: u8, flags: u32) {
+        self.start(kind);
+        self.u32(((version as u32) << 24) | (flags & 0x00ff_ffff));
+    }
+    fn end(&mut self) {
+        let Some(start) = self.open.pop() else {
+            return;
+        };
+
+        let size = (self.data.len() - start) as u32;
+        self.data[start..start + 4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+    fn zeros(&mut self, count: usize) {
+        self.data.resize(self.data.len() + count, 0);
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+    fn matrix(&mut self) {
+        for value in UNITY_MATRIX {
+            self.u32(value);
+        }
+    }
+
+    fn finish(self) -> Bytes {
+        debug_assert!(self.open.is_empty());
+        Bytes::from(self.data)
+    }
+}
+
+/// The `ftyp` and `moov` boxes, which every player loads before the first fragment
+pub(super) fn init_segment(
+    codec: Fmp4Codec,
+    width: u32,
+    height: u32,
+    parameter_sets: &ParameterSets,
+) -> Bytes {
+    let mut writer = BoxWriter::default();
+
+    writer.start(b"ftyp");
+    writer.bytes(b"iso5");
+    writer.u32(512);
+    for brand in [b"iso5", b"iso6", b"mp41"] {
+        writer.bytes(brand);
+    }
+    writer.end();
+
+    writer.start(b"moov");
+    {
+        writer.start_full(b"mvhd", 0, 0);
+        writer.u32(0); // creation time
+        writer.u32(0); // modification time
+        writer.u32(1000);
+        writer.u32(0); // duration
+        writer.u32(0x0001_0000); // rate
+        writer.u16(0x0100); // volume
+        writer.zeros(10);
+        writer.matrix();
+        writer.zeros(24);
+        writer.u32(TRACK_ID + 1);
+        writer.end();
+
+        writer.start(b"trak");
+        {
+            writer.start_full(b"tkhd", 0, 0x000003);
+            writer.u32(0); // creation time
+            writer.u32(0); // modification time
+            writer.u32(TRACK_ID);
+            writer.zeros(4);
+            writer.u32(0); // duration
+            writer.zeros(8);
+            writer.u16(0); // layer
+            writer.u16(0); // alternate group
+            writer.u16(0); // volume
+            writer.zeros(2);
+            writer.matrix();
+            writer.u32(width << 16);
+            writer.u32(height << 16);
+            writer.end();
+
+            writer.start(b"mdia");
+            {
+                writer.start_full(b"mdhd", 0, 0);
+                writer.u32(0); // creation time
+                writer.u32(0); // modification time
+                writer.u32(TIMESCALE);
+                writer.u32(0); // duration
+                writer.u16(0x55c4); // "und"
+                writer.u16(0);
+                writer.end();
+
+                writer.start_full(b"hdlr", 0, 0);
+                writer.u32(0);
+                writer.bytes(b"vide");
+                writer.zeros(12);
+                writer.bytes(b"VideoHandler\0");
+                writer.end();
+
+                writer.start(b"minf");
+                {
+                    writer.start_full(b"vmhd", 0, 0x000001);
+                    writer.zeros(8);
+                    writer.end();
+
+                    writer.start(b"dinf");
+                    writer.start_full(b"dref", 0, 0);
+                    writer.u32(1);
+                    // The samples are in the same file
+                    writer.start_full(b"url ", 0, 0x000001);
+                    writer.end();
+                    writer.end();
+                    writer.end();
+
+                    writer.start(b"stbl");
+                    {
+                        writer.start_full(b"stsd", 0, 0);
+                        writer.u32(1);
+                        write_sample_entry(&mut writer, codec, width, height, parameter_sets);
+                        writer.end();
+
+                        // The samples are described by the fragments
+                        for kind in [b"stts", b"stsc", b"stco"] {
+                            writer.start_full(kind, 0, 0);
+                            writer.u32(0);
+                            writer.end();
+                        }
+                        writer.start_full(b"stsz", 0, 0);
+                        writer.u32(0);
+                        writer.u32(0);
+                        writer.end();
+                    }
+                    writer.end();
+                }
+                writer.end();
+            }
+            writer.end();
+        }
+        writer.end();
+
+        writer.start(b"mvex");
+        writer.start_full(b"trex", 0, 0);
+        writer.u32(TRACK_ID);
+        writer.u32(1); // sample description index
+        writer.u32(0); // duration
+        writer.u32(0); // size
+        writer.u32(0); // flags
+        writer.end();
+        writer.end();
+    }
+    writer.end();
+
+    writer.finish()
+}
+
+fn write_sample_entry(
+    writer: &mut BoxWriter,
+    codec: Fmp4Codec,
+    width: u32,
+    height: u32,
+    parameter_sets: &ParameterSets,
+) {
+    // Apple devices only play h265 with the parameter sets in the hvc1 box
+    writer.start(match codec {
+        Fmp4Codec::H264 => b"avc1",
+        Fmp4Codec::H265 { .. } => b"hvc1",
+    });
+    writer.zeros(6);
+    writer.u16(1); // data reference index
+    writer.zeros(16);
+    writer.u16(width as u16);
+    writer.u16(height as u16);
+    writer.u32(0x0048_0000); // 72 dpi
+    writer.u32(0x0048_0000);
+    writer.zeros(4);
+    writer.u16(1); // frame count
+    writer.zeros(32); // compressor name
+    writer.u16(0x0018); // depth
+    writer.u16(0xffff);
+
+    match codec {
+        Fmp4Codec::H264 => write_avcc(writer, parameter_sets),
+        Fmp4Codec::H265 {
+            chroma_format_idc,
+            bit_depth_minus8,
+        } => write_hvcc(writer, parameter_sets, chroma_format_idc, bit_depth_minus8),
+    }
+
+    writer.end();
+}
+
+fn write_avcc(writer: &mut BoxWriter, parameter_sets: &ParameterSets) {
+    let profile = parameter_sets
+        .sps
+        .first()
+        .and_then(|sps| sps.get(1..4))
+        .unwrap_or(&[0x42, 0, 0x1f]);
+
+    writer.start(b"avcC");
+    writer.u8(1);
+    writer.bytes(profile);
+    writer.u8(0xfc | 3); // 4 byte nal lengths
+    writer.u8(0xe0 | parameter_sets.sps.len() as u8);
+    for sps in &parameter_sets.sps {
+        writer.u16(sps.len() as u16);
+        writer.bytes(sps);
+    }
+    writer.u8(parameter_sets.pps.len() as u8);
+    for pps in &parameter_sets.pps {
+        writer.u16(pps.len() as u16);
+        writer.bytes(pps);
+    }
+    writer.end();
+}
+
+/// The general profile, tier and level of an h265 sps starts at a fixed offset
+fn write_hvcc(
+    writer: &mut BoxWriter,
+    parameter_sets: &ParameterSets,
+    chroma_format_idc: u8,
+    bit_depth_minus8: u8,
+) {
+    const SPS_HEADER_SIZE: usize = 3;
+    const PROFILE_TIER_LEVEL_SIZE: usize = 12;
+
+    let sps = parameter_sets
+        .sps
+        .first()
+        .map(|sps| remove_emulation_prevention(sps, SPS_HEADER_SIZE + PROFILE_TIER_LEVEL_SIZE))
+        .unwrap_or_default();
+    let (max_sub_layers, temporal_id_nested, profile_tier_level) =
+        match sps.get(..SPS_HEADER_SIZE + PROFILE_TIER_LEVEL_SIZE) {
+            Some(sps) => (
+                ((sps[2] >> 1) & 0x07) + 1,
+                sps[2] & 0x01,
+                &sps[SPS_HEADER_SIZE..],
+            ),
+            // Main profile, level 5.1
+            None => (1, 1, &[1, 0x60, 0, 0, 0, 0, 0, 0, 0, 0, 0, 153][..]),
+        };
+
+    writer.start(b"hvcC");
+    writer.u8(1);
+    writer.bytes(profile_tier_level);
+    writer.u16(0xf000); // min spatial segmentation
+    writer.u8(0xfc); // parallelism type
+    writer.u8(0xfc | chroma_format_idc);
+    writer.u8(0xf8 | bit_depth_minus8);
+    writer.u8(0xf8 | bit_depth_minus8);
+    writer.u16(0); // average frame rate
+    writer.u8((max_sub_layers << 3) | (temporal_id_nested << 2) | 3);
+
+    let arrays = [
+        (32, &parameter_sets.vps),
+        (33, &parameter_sets.sps),
+        (34, &parameter_sets.pps),
+    ];
+    writer.u8(arrays.len() as u8);
+    for (nal_unit_type, nals) in arrays {
+        writer.u8(0x80 | nal_unit_type);
+        writer.u16(nals.len() as u16);
+        for nal in nals {
+            writer.u16(nal.len() as u16);
+            writer.bytes(nal);
+        }
+    }
+    writer.end();
+}
+
+/// Removes the emulation prevention bytes of the first bytes of a nal unit
+fn remove_emulation_prevention(nal: &[u8], len: usize) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(len);
+    let mut zeros = 0;
+
+    for &byte in nal {
+        if rbsp.len() >= len {
+            break;
+        }
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+/// A `moof` and `mdat` box with the samples, which must all have their duration
+pub(super) fn fragment(sequence_number: u32, samples: &[Sample]) -> Bytes {
+    let Some(first) = samples.first() else {
+        return Bytes::new();
+    };
+
+    let mut writer = BoxWriter::default();
+
+    writer.start(b"moof");
+    writer.start_full(b"mfhd", 0, 0);
+    writer.u32(sequence_number);
+    writer.end();
+
+    writer.start(b"traf");
+    // The sample offsets are relative to the moof box
+    writer.start_full(b"tfhd", 0, 0x020000);
+    writer.u32(TRACK_ID);
+    writer.end();
+
+    writer.start_full(b"tfdt", 1, 0);
+    writer.u64(first.decode_time);
+    writer.end();
+
+    // Data offset, duration, size and flags of every sample
+    writer.start_full(b"trun", 0, 0x000001 | 0x000100 | 0x000200 | 0x000400);
+    writer.u32(samples.len() as u32);
+    let data_offset = writer.data.len();
+    writer.u32(0);
+    for sample in samples {
+        writer.u32(sample.duration);
+        writer.u32(sample.data.len() as u32);
+        writer.u32(if sample.keyframe {
+            SAMPLE_FLAGS_SYNC
+        } else {
+            SAMPLE_FLAGS_NON_SYNC
+        });
+    }
+    writer.end();
+    writer.end();
+    writer.end();
+
+    // The mdat header comes before the first sample
+    let moof_size = writer.data.len() as u32;
+    writer.data[data_offset..data_offset + 4].copy_from_slice(&(moof_size + 8).to_be_bytes());
+
+    writer.start(b"mdat");
+    for sample in samples {
+        writer.bytes(&sample.data);
+    }
+    writer.end();
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::hls::fmp4::{
+        Fmp4Codec, ParameterSets, Sample, fragment, init_segment, remove_emulation_prevention,
+    };
+
+    /// The type and size of the top level boxes
+    fn boxes(data: &[u8]) -> Vec<(String, usize)> {
+        let mut boxes = Vec::new();
+        let mut offset = 0;
+
+        while offset + 8 <= data.len() {
+            let size = u32::from_be_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize;
+            let kind = String::from_utf8_lossy(&data[offset + 4..offset + 8]).to_string();
+
+            boxes.push((kind, size));
+            offset += size.max(8);
+        }
+        assert_eq!(offset, data.len());
+
+        boxes
+    }
+
+    #[test]
+    fn test_init_segment_boxes() {
+        let parameter_sets = ParameterSets {
+            vps: Vec::new(),
+            sps: vec![Bytes::from_static(&[0x67, 0x64, 0x00, 0x28, 0xac])],
+            pps: vec![Bytes::from_static(&[0x68, 0xee, 0x3c, 0x80])],
+        };
+
+        let init = init_segment(Fmp4Codec::H264, 1920, 1080, &parameter_sets);
+        let kinds = boxes(&init)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(kinds, ["ftyp", "moov"]);
+        // The profile of the sps is copied into the avcC box
+        let avcc = init
+            .windows(4)
+            .position(|window| window == b"avcC")
+            .expect("missing avcC box");
+        assert_eq!(&init[avcc + 4..avcc + 8], &[1, 0x64, 0x00, 0x28]);
+    }
+
+    #[test]
+    fn test_fragment_data_offset() {
+        let samples = [
+            Sample {
+                data: Bytes::from_static(&[0, 0, 0, 2, 0x65, 0x88]),
+                decode_time: 0,
+                duration: 1500,
+                keyframe: true,
+            },
+            Sample {
+                data: Bytes::from_static(&[0, 0, 0, 1, 0x41]),
+                decode_time: 1500,
+                duration: 1500,
+                keyframe: false,
+            },
+        ];
+
+        let fragment = fragment(7, &samples);
+        let boxes = boxes(&fragment);
+
+        assert_eq!(boxes[0].0, "moof");
+        assert_eq!(boxes[1], ("mdat".to_string(), 8 + 11));
+
+        // The data offset of the trun box points right behind the mdat header
+        let trun = fragment
+            .windows(4)
+            .position(|window| window == b"trun")
+            .expect("missing trun box");
+        let data_offset = u32::from_be_bytes([
+            fragment[trun + 12],
+            fragment[trun + 13],
+            fragment[trun + 14],
+            fragment[trun + 15],
+        ]) as usize;
+        assert_eq!(
+            &fragment[data_offset..data_offset + 6],
+            &samples[0].data[..]
+        );
+    }
+
+    #[test]
+    fn test_remove_emulation_prevention() {
+        assert_eq!(
+            remove_emulation_prevention(&[0x42, 0, 0, 3, 1, 0, 0, 3, 0, 5], 16),
+            [0x42, 0, 0, 1, 0, 0, 0, 5]
+        );
+        assert_eq!(remove_emulation_prevention(&[1, 2, 3, 4], 2), [1, 2]);
+    }
+}
@@ -0,0 +1,878 @@
+//! Packages the video of the stream as low latency hls for casting, see [HlsOutputConfig].
+//!
+//! Segments start with a keyframe and are split into parts, which players load while the segment is still being written.
+//! The web server forwards every request as [ServerIpcMessage::HlsRequest](common::ipc::ServerIpcMessage::HlsRequest),
+//! requests for parts or playlists which don't exist yet wait until the packager produced them.
+
+use std::{
+    collections::VecDeque,
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{
+    config::HlsOutputConfig,
+    ipc::{HlsFile, StreamerIpcMessage},
+};
+use log::{debug, info, warn};
+use moonlight_common::stream::{
+    bindings::{FrameType, VideoDecodeUnit, VideoFormat},
+    video::VideoSetup,
+};
+use tokio::{sync::watch, time::timeout_at};
+
+use crate::{
+    StreamConnection,
+    hls::{
+        fmp4::{Fmp4Codec, ParameterSets, Sample, TIMESCALE, fragment, init_segment},
+        recording::HlsRecording,
+    },
+    transport::webrtc::video::{
+        annexb::AnnexBSplitter,
+        h264::{NalHeader as H264NalHeader, NalUnitType as H264NalUnitType},
+        h265::reader::{NalHeader as H265NalHeader, NalUnitType as H265NalUnitType},
+        trim_bytes_to_range,
+    },
+};
+
+mod fmp4;
+mod recording;
+
+/// The web server gives up on a request a bit later
+const MAX_BLOCKING_WAIT: Duration = Duration::from_secs(10);
+/// Segments which are too long request a keyframe, but the host might take a while to send it
+const IDR_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Only the newest segments list their parts, players which fell further behind load whole segments
+const PART_SEGMENTS: usize = 2;
+
+const PLAYLIST_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+const MEDIA_CONTENT_TYPE: &str = "video/mp4";
+
+/// The files of the hls output, relative to the playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HlsPath {
+    Playlist,
+    Init(u32),
+    Segment(u64),
+    Part { sequence: u64, index: usize },
+}
+
+impl HlsPath {
+    fn parse(path: &str) -> Option<Self> {
+        if path == "index.m3u8" {
+            return Some(Self::Playlist);
+        }
+        if let Some(id) = path
+            .strip_prefix("init-")
+            .and_then(|path| path.strip_suffix(".mp4"))
+        {
+            return id.parse().ok().map(Self::Init);
+        }
+
+        let name = path.strip_suffix(".m4s")?;
+        if let Some(sequence) = name.strip_prefix("segment-") {
+            return sequence.parse().ok().map(Self::Segment);
+        }
+
+        let (sequence, index) = name.strip_prefix("part-")?.split_once('-')?;
+        Some(Self::Part {
+            sequence: sequence.parse().ok()?,
+            index: index.parse().ok()?,
+        })
+    }
+}
+
+enum HlsLookup {
+    Found(HlsFile),
+    /// The packager didn't produce the file yet
+    Pending,
+    Missing,
+}
+
+struct HlsPart {
+    duration: Duration,
+    /// Starts with a keyframe
+    independent: bool,
+    data: Bytes,
+}
+
+struct HlsSegment {
+    sequence: u64,
+    init: u32,
+    /// The parameter sets changed, e.g. because the stream restarted
+    discontinuity: bool,
+    start: u64,
+    parts: Vec<HlsPart>,
+    complete: bool,
+}
+
+impl HlsSegment {
+    fn duration(&self) -> Duration {
+        self.parts.iter().map(|part| part.duration).sum()
+    }
+
+    fn data(&self) -> Bytes {
+        let mut data = BytesMut::with_capacity(self.parts.iter().map(|part| part.data.len()).sum());
+        for part in &self.parts {
+            data.put_slice(&part.data);
+        }
+        data.freeze()
+    }
+}
+
+pub(crate) struct HlsOutput {
+    config: HlsOutputConfig,
+    codec: Fmp4Codec,
+    width: u32,
+    height: u32,
+    annex_b: AnnexBSplitter<Cursor<Vec<u8>>>,
+    parameter_sets: ParameterSets,
+    /// The init segments which are referenced by the segments in the playlist
+    inits: VecDeque<(u32, Bytes)>,
+    next_init: u32,
+    segments: VecDeque<HlsSegment>,
+    next_sequence: u64,
+    discontinuity_sequence: u64,
+    /// In seconds, must never shrink while players load the playlist
+    target_duration: u64,
+    /// The frames of the next part, the duration of the last one is only known with the next frame
+    pending: Vec<Sample>,
+    part_start: u64,
+    fragment_sequence: u32,
+    /// The presentation time restarts with the stream, but the timestamps of the segments must keep increasing
+    last_time: Option<u64>,
+    time_offset: u64,
+    frame_duration: u64,
+    last_idr_request: Option<Instant>,
+    last_request: Instant,
+    /// Changes whenever a part was added, for requests which wait for it
+    updates: watch::Sender<()>,
+    recording: Option<HlsRecording>,
+}
+
+impl HlsOutput {
+    fn new(config: HlsOutputConfig, setup: &VideoSetup) -> Option<Self> {
+        let codec = match setup.format {
+            VideoFormat::H264 | VideoFormat::H264High8_444 => Fmp4Codec::H264,
+            VideoFormat::H265 => Fmp4Codec::H265 {
+                chroma_format_idc: 1,
+                bit_depth_minus8: 0,
+            },
+            VideoFormat::H265Main10 => Fmp4Codec::H265 {
+                chroma_format_idc: 1,
+                bit_depth_minus8: 2,
+            },
+            VideoFormat::H265Rext8_444 => Fmp4Codec::H265 {
+                chroma_format_idc: 3,
+                bit_depth_minus8: 0,
+            },
+            VideoFormat::H265Rext10_444 => Fmp4Codec::H265 {
+                chroma_format_idc: 3,
+                bit_depth_minus8: 2,
+            },
+            // Cast receivers hardly play av1
+            VideoFormat::Av1Main8
+            | VideoFormat::Av1Main10
+            | VideoFormat::Av1High8_444
+            | VideoFormat::Av1High10_444 => return None,
+        };
+
+        Some(Self {
+            target_duration: config.segment_duration.as_secs_f64().ceil().max(1.0) as u64,
+            config,
+            codec,
+            width: setup.width,
+            height: setup.height,
+            annex_b: AnnexBSplitter::new(Cursor::new(Vec::new()), 0),
+            parameter_sets: ParameterSets::default(),
+            inits: VecDeque::new(),
+            next_init: 0,
+            segments: VecDeque::new(),
+            next_sequence: 0,
+            discontinuity_sequence: 0,
+            pending: Vec::new(),
+            part_start: 0,
+            fragment_sequence: 1,
+            last_time: None,
+            time_offset: 0,
+            frame_duration: 1,
+            last_idr_request: None,
+            last_request: Instant::now(),
+            updates: watch::Sender::new(()),
+            recording: None,
+        })
+    }
+
+    fn is_idle(&self) -> bool {
+        self.recording.is_none() && self.last_request.elapsed() > self.config.idle_timeout
+    }
+
+    fn should_request_idr_frame(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .last_idr_request
+            .is_some_and(|last_idr_request| now - last_idr_request < IDR_REQUEST_INTERVAL)
+        {
+            return false;
+        }
+        self.last_idr_request = Some(now);
+
+        true
+    }
+
+    /// Converts the presentation time into the timescale of the track
+    fn timestamp(&mut self, presentation_time: Duration) -> u64 {
+        let mut time =
+            presentation_time.as_micros() as u64 * TIMESCALE as u64 / 1_000_000 + self.time_offset;
+
+        if let Some(last_time) = self.last_time
+            && time <= last_time
+        {
+            self.time_offset += last_time + self.frame_duration - time;
+            time = last_time + self.frame_duration;
+        }
+        self.last_time = Some(time);
+
+        time
+    }
+
+    /// Splits the annex b frame into its parameter sets and a sample with length prefixed nal units
+    fn split_frame(&mut self, frame: Vec<u8>) -> (ParameterSets, Bytes) {
+        self.annex_b.reset(Cursor::new(frame));
+
+        let mut parameter_sets = ParameterSets::default();
+        let mut sample = BytesMut::new();
+
+        while let Ok(Some(nal)) = self.annex_b.next() {
+            let nal = trim_bytes_to_range(nal.full, nal.payload_range).freeze();
+
+            let target = match self.codec {
+                Fmp4Codec::H264 => {
+                    let Some(&header) = nal.first() else {
+                        continue;
+                    };
+                    match H264NalHeader::parse([header]).nal_unit_type {
+                        H264NalUnitType::Sps => Some(&mut parameter_sets.sps),
+                        H264NalUnitType::Pps => Some(&mut parameter_sets.pps),
+                        H264NalUnitType::AccessUnitDelimiter => continue,
+                        _ => None,
+                    }
+                }
+                Fmp4Codec::H265 { .. } => {
+                    let [first, second, ..] = nal[..] else {
+                        continue;
+                    };
+                    match H265NalHeader::parse([first, second]).nal_unit_type {
+                        H265NalUnitType::VpsNut => Some(&mut parameter_sets.vps),
+                        H265NalUnitType::SpsNut => Some(&mut parameter_sets.sps),
+                        H265NalUnitType::PpsNut => Some(&mut parameter_sets.pps),
+                        H265NalUnitType::AudNut => continue,
+                        _ => None,
+                    }
+                }
+            };
+
+            match target {
+                Some(parameter_sets) => parameter_sets.push(nal),
+                None => {
+                    sample.put_u32(nal.len() as u32);
+                    sample.put_slice(&nal);
+                }
+            }
+        }
+
+        (parameter_sets, sample.freeze())
+    }
+
+    /// Returns true if the packager needs a keyframe
+    fn push_frame(&mut self, frame: Vec<u8>, presentation_time: Duration, keyframe: bool) -> bool {
+        let (parameter_sets, data) = self.split_frame(frame);
+
+        let new_init = keyframe
+            && parameter_sets.is_complete(self.codec)
+            && parameter_sets != self.parameter_sets;
+        if new_init {
+            self.parameter_sets = parameter_sets;
+        }
+        if !self.parameter_sets.is_complete(self.codec) || data.is_empty() {
+            return !self.parameter_sets.is_complete(self.codec);
+        }
+
+        let time = self.timestamp(presentation_time);
+
+        if let Some(last) = self.pending.last_mut() {
+            last.duration = (time - last.decode_time) as u32;
+            self.frame_duration = (last.duration as u64).max(1);
+        }
+
+        let segment_duration = duration_to_ticks(self.config.segment_duration);
+        let part_duration = duration_to_ticks(self.config.part_duration);
+
+        let mut needs_idr_frame = false;
+        let segment_start = self
+            .segments
+            .back()
+            .filter(|segment| !segment.complete)
+            .map(|segment| segment.start);
+        match segment_start {
+            None if !keyframe => return true,
+            None => self.start_segment(time, new_init),
+            Some(segment_start) => {
+                let elapsed = time - segment_start;
+
+                if keyframe && (new_init || elapsed >= segment_duration) {
+                    self.finish_part();
+                    self.finish_segment();
+                    self.start_segment(time, new_init);
+                } else {
+                    // Parts must not be longer than the part target, so cut before this frame would exceed it
+                    if !self.pending.is_empty()
+                        && time + self.frame_duration - self.part_start > part_duration
+                    {
+                        self.finish_part();
+                    }
+
+                    needs_idr_frame = !keyframe && elapsed >= segment_duration;
+                }
+            }
+        }
+
+        if self.pending.is_empty() {
+            self.part_start = time;
+        }
+        self.pending.push(Sample {
+            data,
+            decode_time: time,
+            duration: 0,
+            keyframe,
+        });
+
+        needs_idr_frame
+    }
+
+    fn start_segment(&mut self, time: u64, new_init: bool) {
+        if new_init || self.inits.is_empty() {
+            let init = init_segment(self.codec, self.width, self.height, &self.parameter_sets);
+
+            if let Some(recording) = self.recording.as_ref() {
+                recording.write_init(self.next_init, &init);
+            }
+
+            self.inits.push_back((self.next_init, init));
+            self.next_init += 1;
+        }
+
+        let init = self.next_init - 1;
+        let discontinuity = new_init && !self.segments.is_empty();
+
+        self.segments.push_back(HlsSegment {
+            sequence: self.next_sequence,
+            init,
+            discontinuity,
+            start: time,
+            parts: Vec::new(),
+            complete: false,
+        });
+        self.next_sequence += 1;
+    }
+
+    fn finish_part(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let Some(segment) = self.segments.back_mut() else {
+            return;
+        };
+
+        let data = fragment(self.fragment_sequence, &self.pending);
+        self.fragment_sequence = self.fragment_sequence.wrapping_add(1);
+
+        let ticks = self
+            .pending
+            .iter()
+            .map(|sample| sample.duration as u64)
+            .sum::<u64>();
+        segment.parts.push(HlsPart {
+            duration: ticks_to_duration(ticks),
+            independent: self.pending[0].keyframe,
+            data,
+        });
+        self.pending.clear();
+
+        self.updates.send_replace(());
+    }
+
+    fn finish_segment(&mut self) {
+        let Some(segment) = self.segments.back_mut() else {
+            return;
+        };
+        segment.complete = true;
+
+        let duration = segment.duration();
+        self.target_duration = self
+            .target_duration
+            .max(duration.as_secs_f64().ceil() as u64);
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.write_segment(segment, self.target_duration);
+        }
+
+        while self.segments.len() > self.config.playlist_segments.max(1) {
+            if let Some(segment) = self.segments.pop_front()
+                && segment.discontinuity
+            {
+                self.discontinuity_sequence += 1;
+            }
+        }
+        if let Some(oldest_init) = self.segments.front().map(|segment| segment.init) {
+            self.inits.retain(|(id, _)| *id >= oldest_init);
+        }
+
+        self.updates.send_replace(());
+    }
+
+    fn segment(&self, sequence: u64) -> Option<&HlsSegment> {
+        let first = self.segments.front()?.sequence;
+        self.segments.get(sequence.checked_sub(first)? as usize)
+    }
+
+    /// If the playlist contains the segment or the part of it, used for blocking playlist reloads
+    fn contains(&self, sequence: u64, part: Option<u32>) -> bool {
+        if sequence + 1 < self.next_sequence {
+            return true;
+        }
+
+        self.segment(sequence).is_some_and(|segment| {
+            segment.complete || part.is_some_and(|part| segment.parts.len() > part as usize)
+        })
+    }
+
+    fn lookup(&self, path: HlsPath, msn: Option<u64>, part: Option<u32>) -> HlsLookup {
+        let media = |data: Bytes| {
+            HlsLookup::Found(HlsFile {
+                content_type: MEDIA_CONTENT_TYPE.to_string(),
+                data,
+            })
+        };
+
+        match path {
+            HlsPath::Playlist => {
+                let ready = match msn {
+                    // Players may only ask for the next two segments
+                    Some(msn) if msn > self.next_sequence + 1 => return HlsLookup::Missing,
+                    Some(msn) => self.contains(msn, part),
+                    None => self
+                        .segments
+                        .front()
+                        .is_some_and(|segment| segment.complete || !segment.parts.is_empty()),
+                };
+
+                if ready {
+                    HlsLookup::Found(HlsFile {
+                        content_type: PLAYLIST_CONTENT_TYPE.to_string(),
+                        data: Bytes::from(self.playlist()),
+                    })
+                } else {
+                    HlsLookup::Pending
+                }
+            }
+            HlsPath::Init(id) => match self.inits.iter().find(|(init, _)| *init == id) {
+                Some((_, init)) => media(init.clone()),
+                None => HlsLookup::Missing,
+            },
+            HlsPath::Segment(sequence) => match self.segment(sequence) {
+                Some(segment) if segment.complete => media(segment.data()),
+                Some(_) => HlsLookup::Pending,
+                None if sequence == self.next_sequence => HlsLookup::Pending,
+                None => HlsLookup::Missing,
+            },
+            HlsPath::Part { sequence, index } => match self.segment(sequence) {
+                Some(segment) if index < segment.parts.len() => {
+                    media(segment.parts[index].data.clone())
+                }
+                // The preload hint of the playlist
+                Some(segment) if !segment.complete && index == segment.parts.len() => {
+                    HlsLookup::Pending
+                }
+                None if sequence == self.next_sequence && index == 0 => HlsLookup::Pending,
+                _ => HlsLookup::Missing,
+            },
+        }
+    }
+
+    fn playlist(&self) -> String {
+        let part_target = self.config.part_duration.as_secs_f64();
+
+        let mut playlist = format!(
+            "#EXTM3U\n\
+            #EXT-X-VERSION:9\n\
+            #EXT-X-TARGETDURATION:{}\n\
+            #EXT-X-PART-INF:PART-TARGET={part_target:.3}\n\
+            #EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}\n\
+            #EXT-X-MEDIA-SEQUENCE:{}\n\
+            #EXT-X-DISCONTINUITY-SEQUENCE:{}\n",
+            self.target_duration,
+            part_target * 3.0,
+            self.segments
+                .front()
+                .map(|segment| segment.sequence)
+                .unwrap_or(self.next_sequence),
+            self.discontinuity_sequence,
+        );
+
+        let parts_from = self.segments.len().saturating_sub(PART_SEGMENTS);
+        let mut init = None;
+        for (index, segment) in self.segments.iter().enumerate() {
+            if segment.discontinuity {
+                playlist.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            if init != Some(segment.init) {
+                init = Some(segment.init);
+                playlist.push_str(&format!("#EXT-X-MAP:URI=\"init-{}.mp4\"\n", segment.init));
+            }
+
+            if index >= parts_from {
+                for (part_index, part) in segment.parts.iter().enumerate() {
+                    playlist.push_str(&format!(
+                        "#EXT-X-PART:DURATION={:.5},URI=\"part-{}-{part_index}.m4s\"{}\n",
+                        part.duration.as_secs_f64(),
+                        segment.sequence,
+                        if part.independent {
+                            ",INDEPENDENT=YES"
+                        } else {
+                            ""
+                        }
+                    ));
+                }
+            }
+
+            if segment.complete {
+                playlist.push_str(&format!(
+                    "#EXTINF:{:.5},\nsegment-{}.m4s\n",
+                    segment.duration().as_secs_f64(),
+                    segment.sequence
+                ));
+            }
+        }
+
+        let (sequence, part_index) = match self.segments.back() {
+            Some(segment) if !segment.complete => (segment.sequence, segment.parts.len()),
+            _ => (self.next_sequence, 0),
+        };
+        playlist.push_str(&format!(
+            "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part-{sequence}-{part_index}.m4s\"\n"
+        ));
+
+        playlist
+    }
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+    duration.as_micros() as u64 * TIMESCALE as u64 / 1_000_000
+}
+fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_micros(ticks * 1_000_000 / TIMESCALE as u64)
+}
+
+async fn request_idr_frame(stream: &StreamConnection) {
+    let moonlight_stream = stream.stream.read().await;
+    if let Some(moonlight_stream) = moonlight_stream.as_ref()
+        && let Err(err) = moonlight_stream.request_idr_frame()
+    {
+        warn!("[HLS]: failed to request an IDR frame: {err}");
+    }
+}
+
+/// Creates the packager if it doesn't run yet, none if the video can't be packaged
+async fn start_hls_output<'a>(
+    stream: &StreamConnection,
+    output: &'a mut Option<HlsOutput>,
+) -> Option<&'a mut HlsOutput> {
+    if output.is_none() {
+        let config = &stream.config.hls_output;
+        if !config.enabled {
+            return None;
+        }
+
+        let setup = stream.stream_setup.lock().await.video?;
+        let Some(new_output) = HlsOutput::new(config.clone(), &setup) else {
+            warn!(
+                "[HLS]: the video format {:?} can't be packaged as hls",
+                setup.format
+            );
+            return None;
+        };
+
+        info!("[HLS]: started packaging the stream");
+        *output = Some(new_output);
+
+        request_idr_frame(stream).await;
+    }
+
+    output.as_mut()
+}
+
+/// Packages the whole stream if a recording directory is configured, called once the stream started
+pub(crate) async fn start_hls_recording(stream: &StreamConnection) {
+    let Some(directory) = stream.config.hls_output.recording_directory.as_ref() else {
+        return;
+    };
+
+    let mut output = stream.hls_output.lock().await;
+    let Some(output) = start_hls_output(stream, &mut output).await else {
+        return;
+    };
+    if output.recording.is_some() {
+        return;
+    }
+
+    match HlsRecording::create(directory) {
+        Ok(recording) => {
+            info!(
+                "[HLS]: recording the stream into {:?}",
+                recording.directory()
+            );
+            output.recording = Some(recording);
+        }
+        Err(err) => warn!("[HLS]: failed to create the recording in {directory}: {err}"),
+    }
+}
+
+pub(crate) async fn stop_hls_output(stream: &StreamConnection) {
+    if stream.hls_output.lock().await.take().is_some() {
+        info!("[HLS]: stopped packaging the stream");
+    }
+}
+
+/// Must be called with every frame
+pub(crate) async fn push_hls_video(stream: &StreamConnection, unit: &VideoDecodeUnit<'_>) {
+    let needs_idr_frame = {
+        let mut output = stream.hls_output.lock().await;
+        let Some(hls) = output.as_mut() else {
+            return;
+        };
+
+        if hls.is_idle() {
+            info!(
+                "[HLS]: nobody requested the stream for {:?}, stopped packaging",
+                hls.config.idle_timeout
+            );
+            *output = None;
+            return;
+        }
+
+        let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+        let mut frame = Vec::with_capacity(frame_len);
+        for buffer in unit.buffers {
+            frame.extend_from_slice(buffer.data);
+        }
+
+        let keyframe = matches!(unit.frame_type, FrameType::Idr);
+        hls.push_frame(frame, unit.presentation_time, keyframe) && hls.should_request_idr_frame()
+    };
+
+    if needs_idr_frame {
+        debug!("[HLS]: requesting an IDR frame for the next segment");
+        request_idr_frame(stream).await;
+    }
+}
+
+/// Answers a request of the web server, waits for files which the packager didn't produce yet
+pub(crate) async fn handle_hls_request(
+    stream: Arc<StreamConnection>,
+    request_id: u32,
+    path: String,
+    msn: Option<u64>,
+    part: Option<u32>,
+) {
+    let file = match HlsPath::parse(&path) {
+        Some(path) => hls_file(&stream, path, msn, part).await,
+        None => None,
+    };
+
+    stream
+        .ipc_sender
+        .clone()
+        .send(StreamerIpcMessage::HlsFile { request_id, file })
+        .await;
+}
+
+async fn hls_file(
+    stream: &StreamConnection,
+    path: HlsPath,
+    mut msn: Option<u64>,
+    part: Option<u32>,
+) -> Option<HlsFile> {
+    let deadline = tokio::time::Instant::now() + MAX_BLOCKING_WAIT;
+
+    loop {
+        let mut updates = {
+            let mut output = stream.hls_output.lock().await;
+            let output = start_hls_output(stream, &mut output).await?;
+            output.last_request = Instant::now();
+
+            match output.lookup(path, msn, part) {
+                HlsLookup::Found(file) => return Some(file),
+                HlsLookup::Missing => return None,
+                HlsLookup::Pending => output.updates.subscribe(),
+            }
+        };
+
+        match timeout_at(deadline, updates.changed()).await {
+            Ok(Ok(())) => {}
+            // The packager stopped
+            Ok(Err(_)) => return None,
+            // A blocking reload which takes too long gets the current playlist
+            Err(_) if path == HlsPath::Playlist && msn.is_some() => msn = None,
+            Err(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use common::config::HlsOutputConfig;
+    use moonlight_common::stream::{bindings::VideoFormat, video::VideoSetup};
+
+    use crate::hls::{HlsLookup, HlsOutput, HlsPath};
+
+    const SPS: &[u8] = &[0, 0, 0, 1, 0x67, 0x64, 0x00, 0x28, 0xac];
+    const PPS: &[u8] = &[0, 0, 0, 1, 0x68, 0xee, 0x3c, 0x80];
+    const IDR_SLICE: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84];
+    const SLICE: &[u8] = &[0, 0, 0, 1, 0x41, 0x9a, 0x02];
+
+    fn output() -> HlsOutput {
+        let config = HlsOutputConfig {
+            enabled: true,
+            segment_duration: Duration::from_secs(1),
+            part_duration: Duration::from_millis(250),
+            playlist_segments: 2,
+            ..Default::default()
+        };
+        let setup = VideoSetup {
+            format: VideoFormat::H264,
+            width: 1280,
+            height: 720,
+            redraw_rate: 60,
+            flags: 0,
+        };
+
+        HlsOutput::new(config, &setup).expect("h264 is supported")
+    }
+
+    /// Pushes frames at 10 fps, every tenth frame is a keyframe
+    fn push_frames(output: &mut HlsOutput, count: u64) {
+        for frame in 0..count {
+            let keyframe = frame % 10 == 0;
+            let data = if keyframe {
+                [SPS, PPS, IDR_SLICE].concat()
+            } else {
+                SLICE.to_vec()
+            };
+
+            output.push_frame(data, Duration::from_millis(frame * 100), keyframe);
+        }
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(HlsPath::parse("index.m3u8"), Some(HlsPath::Playlist));
+        assert_eq!(HlsPath::parse("init-2.mp4"), Some(HlsPath::Init(2)));
+        assert_eq!(HlsPath::parse("segment-17.m4s"), Some(HlsPath::Segment(17)));
+        assert_eq!(
+            HlsPath::parse("part-17-3.m4s"),
+            Some(HlsPath::Part {
+                sequence: 17,
+                index: 3
+            })
+        );
+        assert_eq!(HlsPath::parse("../config.json"), None);
+        assert_eq!(HlsPath::parse("part-17.m4s"), None);
+    }
+
+    #[test]
+    fn test_waits_for_keyframe() {
+        let mut output = output();
+
+        assert!(output.push_frame(SLICE.to_vec(), Duration::ZERO, false));
+        assert!(output.segments.is_empty());
+        assert!(matches!(
+            output.lookup(HlsPath::Playlist, None, None),
+            HlsLookup::Pending
+        ));
+    }
+
+    #[test]
+    fn test_segments_and_parts() {
+        let mut output = output();
+        push_frames(&mut output, 25);
+
+        // Two complete segments of one second and the third one with its first parts
+        assert_eq!(output.segments.len(), 3);
+        assert!(output.segments[0].complete && output.segments[1].complete);
+        assert_eq!(output.segments[0].duration(), Duration::from_secs(1));
+        // The parts are cut before they exceed the part target
+        assert!(
+            output.segments[0]
+                .parts
+                .iter()
+                .all(|part| part.duration <= Duration::from_millis(250))
+        );
+        assert!(output.segments[0].parts[0].independent);
+        assert!(!output.segments[0].parts[1].independent);
+
+        let playlist = output.playlist();
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:1\n"));
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:0\n"));
+        assert!(playlist.contains("#EXT-X-MAP:URI=\"init-0.mp4\"\n"));
+        assert!(playlist.contains("#EXTINF:1.00000,\nsegment-1.m4s\n"));
+        assert!(playlist.contains("URI=\"part-2-0.m4s\",INDEPENDENT=YES\n"));
+        assert!(playlist.ends_with("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part-2-2.m4s\"\n"));
+
+        assert!(matches!(
+            output.lookup(
+                HlsPath::Part {
+                    sequence: 2,
+                    index: 2
+                },
+                None,
+                None
+            ),
+            HlsLookup::Pending
+        ));
+        assert!(matches!(
+            output.lookup(HlsPath::Segment(2), None, None),
+            HlsLookup::Pending
+        ));
+        assert!(matches!(
+            output.lookup(HlsPath::Segment(1), None, None),
+            HlsLookup::Found(_)
+        ));
+        assert!(matches!(
+            output.lookup(HlsPath::Playlist, Some(2), Some(3)),
+            HlsLookup::Pending
+        ));
+        assert!(matches!(
+            output.lookup(HlsPath::Playlist, Some(2), Some(1)),
+            HlsLookup::Found(_)
+        ));
+    }
+
+    #[test]
+    fn test_old_segments_are_dropped() {
+        let mut output = output();
+        push_frames(&mut output, 45);
+
+        let playlist = output.playlist();
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:2\n"));
+        assert!(!playlist.contains("segment-1.m4s"));
+        assert!(matches!(
+            output.lookup(HlsPath::Segment(1), None, None),
+            HlsLookup::Missing
+        ));
+    }
+}
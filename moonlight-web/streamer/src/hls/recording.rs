@@ -0,0 +1,106 @@
+//! Writes the segments of the hls output into a directory, which any hls player can open once the session ended.
+//!
+//! The frames are pushed from the decoder threads of moonlight-common-c, so the files are written right away.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::hls::HlsSegment;
+
+pub(super) struct HlsRecording {
+    directory: PathBuf,
+    /// The playlist entries of the written segments
+    entries: String,
+    media_sequence: Option<u64>,
+    last_init: Option<u32>,
+    target_duration: u64,
+}
+
+impl HlsRecording {
+    pub fn create(base_directory: &str) -> Result<Self, io::Error> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let directory = Path::new(base_directory).join(format!("stream-{started}"));
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            entries: String::new(),
+            media_sequence: None,
+            last_init: None,
+            target_duration: 1,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn write_init(&self, id: u32, init: &Bytes) {
+        if let Err(err) = fs::write(self.directory.join(format!("init-{id}.mp4")), init) {
+            warn!("[HLS]: failed to write the init segment of the recording: {err}");
+        }
+    }
+
+    pub fn write_segment(&mut self, segment: &HlsSegment, target_duration: u64) {
+        let name = format!("segment-{}.m4s", segment.sequence);
+        if let Err(err) = fs::write(self.directory.join(&name), segment.data()) {
+            warn!("[HLS]: failed to write a segment of the recording: {err}");
+            return;
+        }
+
+        self.media_sequence.get_or_insert(segment.sequence);
+        if self.last_init != Some(segment.init) {
+            if self.last_init.is_some() {
+                self.entries.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            self.entries
+                .push_str(&format!("#EXT-X-MAP:URI=\"init-{}.mp4\"\n", segment.init));
+            self.last_init = Some(segment.init);
+        }
+        self.entries.push_str(&format!(
+            "#EXTINF:{:.5},\n{name}\n",
+            segment.duration().as_secs_f64()
+        ));
+        self.target_duration = self.target_duration.max(target_duration);
+
+        self.write_playlist(false);
+    }
+
+    /// The playlist is rewritten with every segment, because the target duration might grow
+    fn write_playlist(&self, ended: bool) {
+        let playlist = format!(
+            "#EXTM3U\n\
+            #EXT-X-VERSION:7\n\
+            #EXT-X-PLAYLIST-TYPE:EVENT\n\
+            #EXT-X-TARGETDURATION:{}\n\
+            #EXT-X-MEDIA-SEQUENCE:{}\n\
+            {}{}",
+            self.target_duration,
+            self.media_sequence.unwrap_or(0),
+            self.entries,
+            if ended { "#EXT-X-ENDLIST\n" } else { "" }
+        );
+
+        if let Err(err) = fs::write(self.directory.join("index.m3u8"), playlist) {
+            warn!("[HLS]: failed to write the playlist of the recording: {err}");
+        }
+    }
+}
+
+impl Drop for HlsRecording {
+    fn drop(&mut self) {
+        if !self.entries.is_empty() {
+            self.write_playlist(true);
+        }
+    }
+}
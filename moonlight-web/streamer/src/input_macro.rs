@@ -0,0 +1,151 @@
+//! Records the input of a client with its timing so it can be replayed later,
+//! e.g. to navigate through the menus of a launcher.
+
+use std::{collections::HashMap, time::Instant};
+
+use bytes::Bytes;
+use common::{
+    api_bindings::TransportChannelId,
+    ipc::{InputMacro, InputMacroEvent},
+};
+use log::warn;
+use tokio::task::JoinHandle;
+
+use crate::transport::TransportChannel;
+
+/// A recording this long was most likely forgotten
+const MAX_RECORDED_EVENTS: usize = 20_000;
+
+/// Only input is recorded, e.g. file transfers or stats would make no sense to replay
+pub fn is_input_channel(channel: TransportChannel) -> bool {
+    (TransportChannelId::MOUSE_RELIABLE..=TransportChannelId::TOUCH).contains(&channel.0)
+        || (TransportChannelId::CONTROLLER0..=TransportChannelId::CONTROLLER15).contains(&channel.0)
+}
+
+struct Recording {
+    last_event: Instant,
+    events: Vec<InputMacroEvent>,
+}
+
+pub struct InputMacros {
+    macros: HashMap<u32, InputMacro>,
+    recording: Option<Recording>,
+    running: Option<JoinHandle<()>>,
+}
+
+impl InputMacros {
+    pub fn new(macros: Vec<InputMacro>) -> Self {
+        Self {
+            macros: macros
+                .into_iter()
+                .map(|input_macro| (input_macro.id, input_macro))
+                .collect(),
+            recording: None,
+            running: None,
+        }
+    }
+
+    /// Discards the previous recording if it wasn't stopped
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording {
+            last_event: Instant::now(),
+            events: Vec::new(),
+        });
+    }
+
+    pub fn record(&mut self, channel: TransportChannel, data: &Bytes) {
+        let Some(recording) = self.recording.as_mut() else {
+            return;
+        };
+        if !is_input_channel(channel) {
+            return;
+        }
+
+        if recording.events.len() >= MAX_RECORDED_EVENTS {
+            warn!("Stopped recording the input macro because it has too many events");
+            self.recording = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let delay_ms = (now - recording.last_event).as_millis();
+        recording.last_event = now;
+
+        recording.events.push(InputMacroEvent {
+            delay_ms: delay_ms.try_into().unwrap_or(u32::MAX),
+            channel: channel.0,
+            data: data.clone(),
+        });
+    }
+
+    /// Returns None if nothing was recorded
+    pub fn stop_recording(&mut self) -> Option<Vec<InputMacroEvent>> {
+        self.recording
+            .take()
+            .map(|recording| recording.events)
+            .filter(|events| !events.is_empty())
+    }
+
+    pub fn insert(&mut self, input_macro: InputMacro) {
+        self.macros.insert(input_macro.id, input_macro);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&InputMacro> {
+        self.macros.get(&id)
+    }
+
+    /// Only one macro runs at a time, the previous one is cancelled
+    pub fn set_running(&mut self, task: JoinHandle<()>) {
+        self.cancel();
+        self.running = Some(task);
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(task) = self.running.take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use common::api_bindings::TransportChannelId;
+
+    use crate::{input_macro::InputMacros, transport::TransportChannel};
+
+    #[test]
+    fn test_only_records_input() {
+        let mut macros = InputMacros::new(Vec::new());
+
+        // Not recording
+        macros.record(
+            TransportChannel(TransportChannelId::KEYBOARD),
+            &Bytes::from_static(&[1]),
+        );
+        macros.start_recording();
+        assert!(macros.stop_recording().is_none());
+
+        macros.start_recording();
+        for channel in [
+            TransportChannelId::GENERAL,
+            TransportChannelId::KEYBOARD,
+            TransportChannelId::CONTROLLERS,
+            TransportChannelId::CONTROLLER3,
+            TransportChannelId::FILE,
+        ] {
+            macros.record(TransportChannel(channel), &Bytes::from_static(&[channel]));
+        }
+
+        let events = macros.stop_recording().expect("no events recorded");
+        let channels = events.iter().map(|event| event.channel).collect::<Vec<_>>();
+        assert_eq!(
+            channels,
+            [
+                TransportChannelId::KEYBOARD,
+                TransportChannelId::CONTROLLER3
+            ]
+        );
+        assert!(macros.stop_recording().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+//! Counts the packets of every input channel and the ones which didn't reach the host,
+//! so reports like a controller which stopped working can be traced to a channel.
+
+use std::fmt::Write;
+
+use common::api_bindings::{StatsInputChannel, TransportChannelId};
+
+use crate::transport::{InboundPacket, TransportChannel};
+
+const FIRST_CHANNEL: u8 = TransportChannelId::MOUSE_RELIABLE;
+const LAST_CHANNEL: u8 = TransportChannelId::CONTROLLER15;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ChannelStats {
+    packets: u32,
+    parse_failures: u32,
+    forwarding_errors: u32,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct InputStats {
+    channels: [ChannelStats; (LAST_CHANNEL - FIRST_CHANNEL + 1) as usize],
+    /// Something changed since the last stats update
+    changed: bool,
+}
+
+impl InputStats {
+    fn channel_mut(&mut self, channel: TransportChannel) -> Option<&mut ChannelStats> {
+        if !(FIRST_CHANNEL..=LAST_CHANNEL).contains(&channel.0) {
+            return None;
+        }
+
+        self.changed = true;
+        self.channels.get_mut((channel.0 - FIRST_CHANNEL) as usize)
+    }
+
+    /// Every received packet, also the ones which couldn't be parsed
+    pub fn on_packet(&mut self, channel: TransportChannel, parsed: bool) {
+        if let Some(stats) = self.channel_mut(channel) {
+            stats.packets += 1;
+            if !parsed {
+                stats.parse_failures += 1;
+            }
+        }
+    }
+
+    pub fn on_forwarding_error(&mut self, channel: TransportChannel) {
+        if let Some(stats) = self.channel_mut(channel) {
+            stats.forwarding_errors += 1;
+        }
+    }
+
+    fn used_channels(&self) -> impl Iterator<Item = (u8, ChannelStats)> + '_ {
+        self.channels
+            .iter()
+            .zip(FIRST_CHANNEL..=LAST_CHANNEL)
+            .filter(|(stats, _)| stats.packets > 0)
+            .map(|(stats, id)| (id, *stats))
+    }
+
+    /// The totals of the session, none if nothing changed since the last update
+    pub fn update(&mut self) -> Option<Vec<StatsInputChannel>> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+
+        Some(
+            self.used_channels()
+                .map(|(id, stats)| StatsInputChannel {
+                    channel: id,
+                    packets: stats.packets,
+                    parse_failures: stats.parse_failures,
+                    forwarding_errors: stats.forwarding_errors,
+                })
+                .collect(),
+        )
+    }
+
+    /// A line for the log at the end of the session, none if no input was received
+    pub fn summary(&self) -> Option<String> {
+        let mut summary = String::new();
+
+        for (id, stats) in self.used_channels() {
+            if !summary.is_empty() {
+                summary.push_str(", ");
+            }
+
+            let _ = write!(summary, "{}: {} packets", channel_name(id), stats.packets);
+            if stats.parse_failures > 0 || stats.forwarding_errors > 0 {
+                let _ = write!(
+                    summary,
+                    " ({} parse failures, {} forwarding errors)",
+                    stats.parse_failures, stats.forwarding_errors
+                );
+            }
+        }
+
+        (!summary.is_empty()).then_some(summary)
+    }
+}
+
+/// The label of the data channel
+fn channel_name(id: u8) -> String {
+    match id {
+        TransportChannelId::MOUSE_RELIABLE => "mouse_reliable".to_string(),
+        TransportChannelId::MOUSE_ABSOLUTE => "mouse_absolute".to_string(),
+        TransportChannelId::MOUSE_RELATIVE => "mouse_relative".to_string(),
+        TransportChannelId::KEYBOARD => "keyboard".to_string(),
+        TransportChannelId::TOUCH => "touch".to_string(),
+        TransportChannelId::CONTROLLERS => "controllers".to_string(),
+        _ => match InboundPacket::CONTROLLER_CHANNELS
+            .iter()
+            .position(|channel| *channel == id)
+        {
+            Some(number) => format!("controller{number}"),
+            None => format!("channel {id}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use common::api_bindings::TransportChannelId;
+
+    use crate::{input_stats::InputStats, transport::TransportChannel};
+
+    #[test]
+    fn test_counts_input_channels() {
+        let mut stats = InputStats::default();
+
+        stats.on_packet(TransportChannel(TransportChannelId::KEYBOARD), true);
+        stats.on_packet(TransportChannel(TransportChannelId::CONTROLLER1), true);
+        stats.on_packet(TransportChannel(TransportChannelId::CONTROLLER1), false);
+        stats.on_forwarding_error(TransportChannel(TransportChannelId::CONTROLLER1));
+        // Not an input channel
+        stats.on_packet(TransportChannel(TransportChannelId::GENERAL), false);
+
+        let update = stats.update().unwrap();
+        assert_eq!(update.len(), 2);
+        assert_eq!(update[1].channel, TransportChannelId::CONTROLLER1);
+        assert_eq!(update[1].packets, 2);
+        assert_eq!(update[1].parse_failures, 1);
+        assert_eq!(update[1].forwarding_errors, 1);
+
+        // Nothing changed since
+        assert!(stats.update().is_none());
+
+        assert_eq!(
+            stats.summary().unwrap(),
+            "keyboard: 1 packets, controller1: 2 packets (1 parse failures, 1 forwarding errors)"
+        );
+    }
+
+    #[test]
+    fn test_no_summary_without_input() {
+        let stats = InputStats::default();
+
+        assert!(stats.summary().is_none());
+    }
+}
@@ -0,0 +1,247 @@
+//! Glass to glass latency estimation.
+//!
+//! The presentation time of every frame is already part of the video stream:
+//! web sockets send it in the video header and WebRTC sends it as the rtp timestamp.
+//! While the test is enabled the client echoes the presentation time of every displayed frame
+//! back on the stats channel, so we can match it to the time we sent the frame.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use common::api_bindings::StreamerStatsUpdate;
+
+/// Frames which weren't echoed after this many newer frames are forgotten
+const MAX_PENDING_FRAMES: usize = 512;
+/// The client might round the presentation time when converting it from the rtp timestamp
+const ECHO_TOLERANCE_US: u32 = 500;
+
+#[derive(Debug)]
+struct SentFrame {
+    presentation_time_us: u32,
+    sent_at: Instant,
+    /// Host processing latency and the time the streamer needed to send the frame
+    processing_time: Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct LatencyTest {
+    pending_frames: VecDeque<SentFrame>,
+    last_send: Option<Instant>,
+    min_latency: Duration,
+    max_latency: Duration,
+    total_latency: Duration,
+    total_display_round_trip: Duration,
+    echoed_frames: u32,
+}
+
+impl LatencyTest {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending_frames: VecDeque::new(),
+            last_send: None,
+            min_latency: Duration::MAX,
+            max_latency: Duration::ZERO,
+            total_latency: Duration::ZERO,
+            total_display_round_trip: Duration::ZERO,
+            echoed_frames: 0,
+        }
+    }
+
+    pub(crate) fn on_frame_sent(
+        &mut self,
+        presentation_time: Duration,
+        sent_at: Instant,
+        processing_time: Duration,
+    ) {
+        if self.pending_frames.len() >= MAX_PENDING_FRAMES {
+            self.pending_frames.pop_front();
+        }
+
+        self.pending_frames.push_back(SentFrame {
+            presentation_time_us: presentation_time.as_micros() as u32,
+            sent_at,
+            processing_time,
+        });
+    }
+
+    /// Returns the stats update if one is due.
+    /// The host rtt is added halved because the frame only travels from the host to us.
+    pub(crate) fn on_echo(
+        &mut self,
+        presentation_time_us: u32,
+        received_at: Instant,
+        host_rtt: Option<Duration>,
+    ) -> Option<StreamerStatsUpdate> {
+        let index = self.pending_frames.iter().position(|frame| {
+            let difference = frame
+                .presentation_time_us
+                .wrapping_sub(presentation_time_us)
+                .min(presentation_time_us.wrapping_sub(frame.presentation_time_us));
+
+            difference <= ECHO_TOLERANCE_US
+        });
+
+        if let Some(index) = index {
+            // Older frames were skipped by the client and won't be echoed anymore
+            let frame = self.pending_frames.drain(..=index).last()?;
+
+            let display_round_trip = received_at.saturating_duration_since(frame.sent_at);
+            let latency = frame.processing_time
+                + host_rtt.map(|rtt| rtt / 2).unwrap_or(Duration::ZERO)
+                + display_round_trip;
+
+            self.min_latency = self.min_latency.min(latency);
+            self.max_latency = self.max_latency.max(latency);
+            self.total_latency += latency;
+            self.total_display_round_trip += display_round_trip;
+            self.echoed_frames += 1;
+        }
+
+        // Send in 1 sec intervall
+        if self.echoed_frames == 0
+            || self
+                .last_send
+                .is_some_and(|last_send| last_send + Duration::from_secs(1) > received_at)
+        {
+            return None;
+        }
+
+        let update = StreamerStatsUpdate::Latency {
+            min_estimated_latency_ms: self.min_latency.as_secs_f64() * 1000.0,
+            max_estimated_latency_ms: self.max_latency.as_secs_f64() * 1000.0,
+            avg_estimated_latency_ms: (self.total_latency / self.echoed_frames).as_secs_f64()
+                * 1000.0,
+            avg_display_round_trip_ms: (self.total_display_round_trip / self.echoed_frames)
+                .as_secs_f64()
+                * 1000.0,
+            echoed_frames: self.echoed_frames,
+        };
+
+        // Clear data
+        self.min_latency = Duration::MAX;
+        self.max_latency = Duration::ZERO;
+        self.total_latency = Duration::ZERO;
+        self.total_display_round_trip = Duration::ZERO;
+        self.echoed_frames = 0;
+
+        self.last_send = Some(received_at);
+
+        Some(update)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use common::api_bindings::StreamerStatsUpdate;
+
+    use crate::latency::LatencyTest;
+
+    fn latency_ms(update: Option<StreamerStatsUpdate>) -> (f64, f64, f64, u32) {
+        match update {
+            Some(StreamerStatsUpdate::Latency {
+                min_estimated_latency_ms,
+                max_estimated_latency_ms,
+                avg_estimated_latency_ms,
+                echoed_frames,
+                ..
+            }) => (
+                min_estimated_latency_ms,
+                max_estimated_latency_ms,
+                avg_estimated_latency_ms,
+                echoed_frames,
+            ),
+            other => panic!("expected a latency update, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_echo_is_matched() {
+        let start = Instant::now();
+        let mut test = LatencyTest::new();
+
+        test.on_frame_sent(Duration::from_millis(16), start, Duration::from_millis(2));
+
+        let update = test.on_echo(
+            16_000,
+            start + Duration::from_millis(30),
+            Some(Duration::from_millis(10)),
+        );
+        let (min, max, avg, echoed_frames) = latency_ms(update);
+
+        assert_eq!(echoed_frames, 1);
+        assert!((min - 37.0).abs() < 0.001);
+        assert!((max - 37.0).abs() < 0.001);
+        assert!((avg - 37.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_updates_are_sent_once_per_second() {
+        let start = Instant::now();
+        let mut test = LatencyTest::new();
+
+        for frame in 0..3u64 {
+            test.on_frame_sent(
+                Duration::from_millis(frame * 16),
+                start,
+                Duration::from_millis(1),
+            );
+        }
+
+        assert!(
+            test.on_echo(0, start + Duration::from_millis(5), None)
+                .is_some()
+        );
+        assert!(
+            test.on_echo(16_000, start + Duration::from_millis(10), None)
+                .is_none()
+        );
+
+        let (min, max, _, echoed_frames) =
+            latency_ms(test.on_echo(32_000, start + Duration::from_millis(1100), None));
+        assert_eq!(echoed_frames, 2);
+        assert!((min - 11.0).abs() < 0.001);
+        assert!((max - 1101.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_skipped_frames_are_dropped() {
+        let start = Instant::now();
+        let mut test = LatencyTest::new();
+
+        for frame in 0..3u64 {
+            test.on_frame_sent(Duration::from_millis(frame * 16), start, Duration::ZERO);
+        }
+
+        // The client only displayed the last frame
+        assert!(
+            test.on_echo(32_000, start + Duration::from_millis(1), None)
+                .is_some()
+        );
+        assert!(test.pending_frames.is_empty());
+
+        // Unknown frames are ignored
+        assert!(
+            test.on_echo(0, start + Duration::from_secs(5), None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_wrapping_presentation_time() {
+        let start = Instant::now();
+        let mut test = LatencyTest::new();
+
+        let presentation_time = Duration::from_micros(u32::MAX as u64 + 1 + 16_000);
+        test.on_frame_sent(presentation_time, start, Duration::ZERO);
+
+        // Rounded by the client
+        assert!(
+            test.on_echo(15_999, start + Duration::from_millis(1), None)
+                .is_some()
+        );
+    }
+}
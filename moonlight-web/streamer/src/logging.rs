@@ -0,0 +1,92 @@
+//! Logger setup: the logs are either sent to the web server or written to stderr.
+//! Panics are always sent to the web server, so it can report them with the session.
+
+use std::{panic::PanicHookInfo, sync::OnceLock, thread, time::Duration};
+
+use common::ipc::{IpcSender, StreamerConfig, StreamerIpcMessage};
+use log::{LevelFilter, Log, Metadata, Record, warn};
+use simplelog::{ColorChoice, TermLogger, TerminalMode};
+
+/// Messages of these targets are never forwarded
+const IGNORED_TARGETS: [&str; 2] = [
+    "webrtc_sctp",
+    // Sending a message logs it, which would be forwarded again
+    "common::ipc",
+];
+
+/// The ipc messages are written by another task, the panicking thread waits this long before the process exits
+const PANIC_FORWARD_DELAY: Duration = Duration::from_millis(200);
+
+static PANIC_SENDER: OnceLock<IpcSender<StreamerIpcMessage>> = OnceLock::new();
+
+pub fn forward_panics(ipc_sender: IpcSender<StreamerIpcMessage>) {
+    if PANIC_SENDER.set(ipc_sender).is_err() {
+        warn!("Panics are already forwarded");
+    }
+}
+
+/// Must be called by the panic hook before exiting
+pub fn forward_panic(info: &PanicHookInfo) {
+    let Some(ipc_sender) = PANIC_SENDER.get() else {
+        return;
+    };
+
+    if ipc_sender.try_send(StreamerIpcMessage::Panic {
+        message: info.to_string(),
+    }) {
+        thread::sleep(PANIC_FORWARD_DELAY);
+    }
+}
+
+pub fn init_logger(config: &StreamerConfig, ipc_sender: IpcSender<StreamerIpcMessage>) {
+    if config.log_forwarding.enabled {
+        let level_filter = config.log_forwarding.level_filter;
+
+        log::set_boxed_logger(Box::new(IpcLogger {
+            level_filter,
+            ipc_sender,
+        }))
+        .expect("failed to init logger");
+        log::set_max_level(level_filter);
+    } else {
+        TermLogger::init(
+            config.log_level,
+            simplelog::ConfigBuilder::new()
+                .add_filter_ignore_str("webrtc_sctp")
+                .set_time_level(LevelFilter::Off)
+                .build(),
+            TerminalMode::Stderr,
+            ColorChoice::Never,
+        )
+        .expect("failed to init logger");
+    }
+}
+
+struct IpcLogger {
+    level_filter: LevelFilter,
+    ipc_sender: IpcSender<StreamerIpcMessage>,
+}
+
+impl Log for IpcLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_filter
+            && !IGNORED_TARGETS
+                .iter()
+                .any(|target| metadata.target().starts_with(target))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Waiting for the channel could deadlock the runtime, so the message is dropped instead
+        self.ipc_sender.try_send(StreamerIpcMessage::Log {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
@@ -1,57 +1,85 @@
-#![feature(if_let_guard)]
-#![feature(async_fn_traits)]
-
 use std::{
-    panic,
+    collections::HashMap,
+    env, panic,
     process::exit,
     sync::{
         Arc, Weak,
         atomic::{AtomicBool, Ordering},
     },
+    time::{Duration, Instant},
 };
 
+use anyhow::bail;
+use bytes::Bytes;
 use common::{
     StreamSettings,
-    api_bindings::{GeneralServerMessage, LogMessageType, StreamClientMessage, TransportType},
+    api_bindings::{
+        BuildInfo, GeneralClientMessage, GeneralServerMessage, LogMessageType, ScreenshotFormat,
+        StatsClientMessage, StatsStageTiming, StreamClientMessage, StreamMessageCode,
+        StreamerCapabilities, StreamerStatsUpdate, TransportType,
+    },
     ipc::{
-        IpcReceiver, IpcSender, ServerIpcMessage, StreamerConfig, StreamerIpcMessage,
+        InputMacro, IpcReceiver, IpcSender, ServerIpcMessage, StreamerConfig, StreamerIpcMessage,
         create_process_ipc,
     },
 };
-use log::{LevelFilter, debug, error, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 use moonlight_common::{
     MoonlightError,
     high::{HostError, MoonlightHost},
-    network::backend::reqwest::ReqwestClient,
+    network::{
+        backend::reqwest::ReqwestClient,
+        launch::AudioRouting,
+        request_client::{CancellationToken, RequestClientOptions},
+    },
     pair::ClientAuth,
     stream::{
         MoonlightInstance, MoonlightStream,
         bindings::{
-            ActiveGamepads, ColorRange, ConnectionStatus, ControllerButtons, EncryptionFlags,
-            HostFeatures, OpusMultistreamConfig, Stage, VideoFormat,
+            ActiveGamepads, ColorRange, ConnectionStatus, ControllerButtons, HostFeatures,
+            OpusMultistreamConfig, Stage, VideoFormat,
         },
-        connection::ConnectionListener,
+        connection::{ConnectionListener, TerminationReason},
         video::VideoSetup,
     },
+    units::Fps,
 };
-use simplelog::{ColorChoice, TermLogger, TerminalMode};
 use tokio::{
     io::{stdin, stdout},
     runtime::Handle,
     spawn,
     sync::{Mutex, Notify, RwLock},
     task::spawn_blocking,
+    time::{interval, sleep},
 };
 
 use common::api_bindings::{StreamCapabilities, StreamServerMessage};
 
 use crate::{
     audio::StreamAudioDecoder,
+    cursor::{CursorEcho, CursorPosition},
+    encryption::encryption_flags,
+    file_transfer::FileTransfers,
+    hls::{HlsOutput, handle_hls_request, start_hls_recording, stop_hls_output},
+    input_macro::{InputMacros, is_input_channel},
+    input_stats::InputStats,
+    latency::LatencyTest,
+    logging::{forward_panic, forward_panics, init_logger},
+    memory::{init_buffer_pool, log_buffer_pool_stats},
+    peer_watchdog::PeerWatchdog,
+    preview::{PreviewTrack, start_preview, stop_preview},
+    quality::QualityMonitor,
+    quality_switch::{QualitySwitch, StreamQuality, request_quality_change},
+    rtsp::{RtspOutput, start_rtsp_output, stop_rtsp_output},
+    rumble::RumbleRemapper,
+    screenshot::request_screenshot,
+    thumbnail::{Thumbnails, request_thumbnail},
     transport::{
-        InboundPacket, OutboundPacket, TransportError, TransportEvent, TransportEvents,
-        TransportSender, web_socket, webrtc,
+        InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
+        TransportEvents, TransportSender, web_socket, webrtc,
     },
     video::StreamVideoDecoder,
+    watchdog::{VideoWatchdog, WatchdogAction},
 };
 
 pub type RequestClient = ReqwestClient;
@@ -59,97 +87,193 @@ pub type RequestClient = ReqwestClient;
 mod audio;
 mod buffer;
 mod convert;
+mod cursor;
+mod doctor;
+mod encryption;
+mod file_transfer;
+mod hls;
+mod input_macro;
+mod input_stats;
+mod latency;
+mod logging;
+mod memory;
+mod node;
+mod peer_watchdog;
+mod preview;
+#[cfg(feature = "profiling")]
+mod profiling;
+mod quality;
+mod quality_switch;
+mod rtsp;
+mod rumble;
+mod sandbox;
+mod screenshot;
+mod standalone;
+mod thumbnail;
 mod transport;
 mod video;
+mod watchdog;
+
+/// The cargo features this streamer was built with
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "profiling")]
+    "profiling",
+    #[cfg(feature = "screenshot")]
+    "screenshot",
+];
+
+/// Sent to the web server, so it only offers what this streamer supports
+const CAPABILITIES: StreamerCapabilities = StreamerCapabilities {
+    video_decoding: cfg!(feature = "screenshot"),
+    rtsp_output: true,
+    hls_output: true,
+    hls_recording: true,
+};
+
+#[cfg(feature = "profiling")]
+#[global_allocator]
+static ALLOCATOR: profiling::CountingAllocator = profiling::CountingAllocator;
 
 #[tokio::main]
 async fn main() {
+    if env::args().any(|arg| arg == "--doctor") {
+        let success = doctor::run().await;
+        exit(if success { 0 } else { 1 });
+    }
+    // Queried by the web server for GET /api/version
+    if env::args().any(|arg| arg == "--build-info") {
+        let build_info = BuildInfo::current(FEATURES);
+        println!(
+            "{}",
+            serde_json::to_string(&build_info).expect("failed to serialize build info")
+        );
+        return;
+    }
+
+    // `--node PATH` runs the streamers of a web server on this machine, see [node]
+    if let Some(config_path) = flag_value_or_exit("--node") {
+        if let Err(err) = node::run(&config_path).await {
+            eprintln!("Failed to run the streamer node: {err:?}");
+        }
+        exit(1);
+    }
+
     let default_panic = panic::take_hook();
     panic::set_hook(Box::new(move |info| {
         default_panic(info);
+        forward_panic(info);
         exit(0);
     }));
 
     // At this point we're authenticated
-    let (mut ipc_sender, mut ipc_receiver) =
-        create_process_ipc::<ServerIpcMessage, StreamerIpcMessage>(stdin(), stdout()).await;
+    // `--standalone PATH` runs the streamer without a web server, see [standalone]
+    let (mut ipc_sender, mut ipc_receiver) = match flag_value_or_exit("--standalone") {
+        Some(config_path) => match standalone::create_ipc(&config_path).await {
+            Ok(ipc) => ipc,
+            Err(err) => {
+                eprintln!("Failed to start the standalone streamer: {err:?}");
+                exit(1);
+            }
+        },
+        None => create_ipc().await,
+    };
+    forward_panics(ipc_sender.clone());
+
+    ipc_sender
+        .send(StreamerIpcMessage::Capabilities(CAPABILITIES))
+        .await;
 
     // Send stage
     ipc_sender
         .send(StreamerIpcMessage::WebSocket(
-            StreamServerMessage::DebugLog {
-                message: "Completed Stage: Launch Streamer".to_string(),
-                ty: None,
-            },
+            StreamMessageCode::LaunchStreamerCompleted.debug_log(None),
         ))
         .await;
 
+    // Idle streamers of the pool wait here for their Init, so do everything that doesn't depend on it before
+    let moonlight = MoonlightInstance::global().expect("failed to find moonlight");
+
     let (
         config,
         host_address,
         host_http_port,
+        host_proxy,
+        host_xml_parse_mode,
         client_unique_id,
         client_private_key,
         client_certificate,
         server_certificate,
         app_id,
+        display_app_ids,
         video_frame_queue_size,
         audio_sample_queue_size,
+        input_macros,
     ) = loop {
         match ipc_receiver.recv().await {
             Some(ServerIpcMessage::Init {
                 config,
                 host_address,
                 host_http_port,
+                host_proxy,
+                host_xml_parse_mode,
                 client_unique_id,
                 client_private_key,
                 client_certificate,
                 server_certificate,
                 app_id,
+                display_app_ids,
                 video_frame_queue_size,
                 audio_sample_queue_size,
+                input_macros,
             }) => {
                 break (
                     config,
                     host_address,
                     host_http_port,
+                    host_proxy,
+                    host_xml_parse_mode,
                     client_unique_id,
                     client_private_key,
                     client_certificate,
                     server_certificate,
                     app_id,
+                    display_app_ids,
                     video_frame_queue_size,
                     audio_sample_queue_size,
+                    input_macros,
                 );
             }
             _ => continue,
         }
     };
 
-    TermLogger::init(
-        config.log_level,
-        simplelog::ConfigBuilder::new()
-            .add_filter_ignore_str("webrtc_sctp")
-            .set_time_level(LevelFilter::Off)
-            .build(),
-        TerminalMode::Stderr,
-        ColorChoice::Never,
-    )
-    .expect("failed to init logger");
+    init_logger(&config, ipc_sender.clone());
+    init_buffer_pool(&config.memory);
+
+    sandbox::apply(&config).expect("failed to apply sandbox");
 
     // Send stage
     ipc_sender
         .send(StreamerIpcMessage::WebSocket(
-            StreamServerMessage::DebugLog {
-                message: "Waiting for Transport to negotiate".to_string(),
-                ty: None,
-            },
+            StreamMessageCode::WaitingForTransport.debug_log(None),
         ))
         .await;
 
     // -- Create the host and pair it
-    let mut host = MoonlightHost::new(host_address, host_http_port, client_unique_id)
-        .expect("failed to create host");
+    // Only the http requests use the proxy, moonlight-common-c opens the rtsp, control and media
+    // connections itself and always connects directly to the host
+    let mut host = MoonlightHost::with_request_options(
+        host_address,
+        host_http_port,
+        client_unique_id,
+        RequestClientOptions {
+            proxy: host_proxy,
+            // The web server already resolved the host address
+            ..Default::default()
+        },
+    )
+    .expect("failed to create host");
+    host.set_xml_parse_mode(host_xml_parse_mode);
 
     host.set_pairing_info(
         &ClientAuth {
@@ -160,21 +284,26 @@ async fn main() {
     )
     .expect("failed to set pairing info");
 
-    // -- Configure moonlight
-    let moonlight = MoonlightInstance::global().expect("failed to find moonlight");
+    let host_requests = CancellationToken::new();
+    host.set_cancellation_token(host_requests.clone());
 
     // -- Create and Configure Peer
     let connection = StreamConnection::new(
         moonlight,
         StreamInfo {
             host: Mutex::new(host),
-            app_id,
+            host_requests,
+            app: Mutex::new(StreamApp {
+                app_id,
+                display_app_ids,
+            }),
         },
         ipc_sender.clone(),
         ipc_receiver,
         config,
         video_frame_queue_size,
         audio_sample_queue_size,
+        input_macros,
     )
     .await
     .expect("failed to create connection");
@@ -193,9 +322,78 @@ async fn main() {
     exit(0);
 }
 
+async fn create_ipc() -> (IpcSender<StreamerIpcMessage>, IpcReceiver<ServerIpcMessage>) {
+    // The web server passes `--ipc-pipe NAME` when the ipc should use a named pipe instead of stdio
+    #[cfg(windows)]
+    if let Some(pipe_name) = flag_value_or_exit("--ipc-pipe") {
+        use tokio::{io::split, net::windows::named_pipe::ClientOptions};
+
+        let pipe = ClientOptions::new()
+            .open(&pipe_name)
+            .expect("failed to open ipc pipe");
+        let (read, write) = split(pipe);
+
+        return create_process_ipc(read, write).await;
+    }
+
+    create_process_ipc(stdin(), stdout()).await
+}
+
+/// The value of a flag like `--node PATH`, None if the flag wasn't passed
+pub fn flag_value(name: &str) -> Result<Option<String>, anyhow::Error> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return match args.next() {
+                Some(value) => Ok(Some(value)),
+                None => bail!("{name} requires a value"),
+            };
+        }
+    }
+
+    Ok(None)
+}
+
+fn flag_value_or_exit(name: &str) -> Option<String> {
+    match flag_value(name) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Invalid arguments: {err}");
+            exit(1);
+        }
+    }
+}
+
 struct StreamInfo {
     host: Mutex<MoonlightHost<RequestClient>>,
+    /// Cancelled when stopping so a launch which is still waiting for the host releases the lock
+    host_requests: CancellationToken,
+    /// Changed by [GeneralClientMessage::SwitchApp]
+    app: Mutex<StreamApp>,
+}
+
+struct StreamApp {
     app_id: u32,
+    /// The variants of the app which stream another display
+    display_app_ids: HashMap<u32, u32>,
+}
+
+impl StreamApp {
+    fn app_id(&self, display_id: Option<u32>) -> u32 {
+        let Some(display_id) = display_id else {
+            return self.app_id;
+        };
+
+        match self.display_app_ids.get(&display_id) {
+            Some(app_id) => *app_id,
+            None => {
+                warn!(
+                    "The app has no variant for display {display_id}, streaming the default display"
+                );
+                self.app_id
+            }
+        }
+    }
 }
 
 struct StreamSetup {
@@ -213,10 +411,36 @@ struct StreamConnection {
     pub video_frame_queue_size: usize,
     pub audio_sample_queue_size: usize,
     pub stream_setup: Mutex<StreamSetup>,
+    /// The settings of a stream which couldn't start because the host is busy, used for a takeover
+    pub busy_settings: Mutex<Option<StreamSettings>>,
+    /// The settings of the running stream, used to restart it with another audio routing
+    pub stream_settings: Mutex<Option<StreamSettings>>,
+    /// False if the audio is only played on the host
+    pub audio_to_client: AtomicBool,
     // Stream
     pub stream: RwLock<Option<MoonlightStream>>,
     pub active_gamepads: RwLock<ActiveGamepads>,
+    pub rumble: Mutex<RumbleRemapper>,
     pub transport_sender: Mutex<Option<Box<dyn TransportSender + Send + Sync + 'static>>>,
+    pub file_transfers: Mutex<FileTransfers>,
+    /// Only set while the client runs the latency test
+    pub latency_test: Mutex<Option<LatencyTest>>,
+    pub input_macros: Mutex<InputMacros>,
+    pub input_stats: Mutex<InputStats>,
+    pub video_watchdog: Mutex<VideoWatchdog>,
+    pub peer_watchdog: Mutex<PeerWatchdog>,
+    pub quality: Mutex<QualityMonitor>,
+    /// The formats of the screenshots which wait for the next IDR frame
+    pub screenshot_requests: Mutex<Vec<ScreenshotFormat>>,
+    /// Only set while the web server wants a preview of the stream
+    pub preview: Mutex<Option<PreviewTrack>>,
+    pub thumbnails: Mutex<Thumbnails>,
+    /// Only set if the rtsp output is enabled and the stream started
+    pub rtsp_output: Mutex<Option<RtspOutput>>,
+    /// Only set while the hls output is requested or recorded
+    pub hls_output: Mutex<Option<HlsOutput>>,
+    pub quality_switch: Mutex<QualitySwitch>,
+    pub cursor_echo: Mutex<CursorEcho>,
     pub terminate: Notify,
     is_terminating: AtomicBool,
 }
@@ -230,7 +454,20 @@ impl StreamConnection {
         config: StreamerConfig,
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
+        input_macros: Vec<InputMacro>,
     ) -> Result<Arc<Self>, anyhow::Error> {
+        let file_transfers = FileTransfers::new(config.file_transfer.clone());
+        let rumble = RumbleRemapper::new(config.controller_rumble.clone());
+        let video_watchdog = VideoWatchdog::new(config.video_watchdog.clone());
+        let watchdog_enabled = video_watchdog.enabled();
+        let peer_watchdog = PeerWatchdog::new(config.peer_watchdog.clone());
+        let thumbnails = Thumbnails::new(config.thumbnail.clone());
+        let thumbnail_interval = thumbnails.enabled().then(|| thumbnails.interval());
+        let cursor_echo_interval = config
+            .cursor_prediction
+            .enabled
+            .then_some(config.cursor_prediction.echo_interval);
+
         let this = Arc::new(Self {
             runtime: Handle::current(),
             moonlight,
@@ -241,11 +478,29 @@ impl StreamConnection {
                 video: None,
                 audio: None,
             }),
+            busy_settings: Mutex::new(None),
+            stream_settings: Mutex::new(None),
+            audio_to_client: AtomicBool::new(true),
             video_frame_queue_size,
             audio_sample_queue_size,
             stream: RwLock::new(None),
             active_gamepads: RwLock::new(ActiveGamepads::empty()),
+            rumble: Mutex::new(rumble),
             transport_sender: Mutex::new(None),
+            file_transfers: Mutex::new(file_transfers),
+            latency_test: Mutex::new(None),
+            input_macros: Mutex::new(InputMacros::new(input_macros)),
+            input_stats: Mutex::new(InputStats::default()),
+            video_watchdog: Mutex::new(video_watchdog),
+            peer_watchdog: Mutex::new(peer_watchdog),
+            quality: Mutex::new(QualityMonitor::default()),
+            screenshot_requests: Mutex::new(Vec::new()),
+            preview: Mutex::new(None),
+            thumbnails: Mutex::new(thumbnails),
+            rtsp_output: Mutex::new(None),
+            hls_output: Mutex::new(None),
+            quality_switch: Mutex::new(QualitySwitch::default()),
+            cursor_echo: Mutex::new(CursorEcho::default()),
             terminate: Notify::default(),
             is_terminating: AtomicBool::new(false),
         });
@@ -270,6 +525,107 @@ impl StreamConnection {
             }
         });
 
+        if watchdog_enabled {
+            spawn({
+                let this = Arc::downgrade(&this);
+
+                async move {
+                    let mut interval = interval(Duration::from_secs(1));
+
+                    loop {
+                        interval.tick().await;
+
+                        let Some(this) = this.upgrade() else {
+                            return;
+                        };
+                        if this.is_terminating.load(Ordering::Acquire) {
+                            return;
+                        }
+
+                        this.check_video_watchdog().await;
+                    }
+                }
+            });
+        }
+
+        if let Some(thumbnail_interval) = thumbnail_interval {
+            spawn({
+                let this = Arc::downgrade(&this);
+
+                async move {
+                    let mut interval = interval(thumbnail_interval);
+
+                    loop {
+                        interval.tick().await;
+
+                        let Some(this) = this.upgrade() else {
+                            return;
+                        };
+                        if this.is_terminating.load(Ordering::Acquire) {
+                            return;
+                        }
+
+                        request_thumbnail(&this).await;
+                    }
+                }
+            });
+        }
+
+        spawn({
+            let this = Arc::downgrade(&this);
+
+            async move {
+                let mut interval = interval(Duration::from_secs(1));
+
+                loop {
+                    interval.tick().await;
+
+                    let Some(this) = this.upgrade() else {
+                        return;
+                    };
+                    if this.is_terminating.load(Ordering::Acquire) {
+                        return;
+                    }
+
+                    let channels = this.input_stats.lock().await.update();
+                    if let Some(channels) = channels {
+                        this.try_send_packet(
+                            OutboundPacket::Stats(StreamerStatsUpdate::Input { channels }),
+                            "input stats",
+                            false,
+                        )
+                        .await;
+                    }
+                }
+            }
+        });
+
+        if let Some(cursor_echo_interval) = cursor_echo_interval {
+            spawn({
+                let this = Arc::downgrade(&this);
+
+                async move {
+                    let mut interval = interval(cursor_echo_interval);
+
+                    loop {
+                        interval.tick().await;
+
+                        let Some(this) = this.upgrade() else {
+                            return;
+                        };
+                        if this.is_terminating.load(Ordering::Acquire) {
+                            return;
+                        }
+
+                        let packet = this.cursor_echo.lock().await.echo();
+                        if let Some(packet) = packet {
+                            this.try_send_packet(packet, "cursor position", false).await;
+                        }
+                    }
+                }
+            });
+        }
+
         Ok(this)
     }
 
@@ -316,7 +672,17 @@ impl StreamConnection {
                                 }
                             });
                         }
-                        Ok(TransportEvent::RecvPacket(packet)) => {
+                        Ok(TransportEvent::RecvPacket { channel, data }) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            this.on_raw_packet(channel, data).await;
+                        }
+                        Ok(TransportEvent::PeerConnected) => {
                             let Some(this) = this.upgrade() else {
                                 warn!(
                                     "Failed to get stream connection, stopping listening to events"
@@ -324,7 +690,8 @@ impl StreamConnection {
                                 return;
                             };
 
-                            this.on_packet(packet).await;
+                            debug!("[Stream]: the peer connected");
+                            this.peer_watchdog.lock().await.on_peer_connected();
                         }
                         Err(TransportError::Closed) | Ok(TransportEvent::Closed) => {
                             break;
@@ -375,26 +742,83 @@ impl StreamConnection {
         }
     }
 
-    async fn on_packet(&self, packet: InboundPacket) {
+    async fn on_raw_packet(self: &Arc<Self>, channel: TransportChannel, data: Bytes) {
+        let packet = InboundPacket::deserialize(channel, &data);
+        self.input_stats
+            .lock()
+            .await
+            .on_packet(channel, packet.is_some());
+
+        let Some(packet) = packet else {
+            warn!("Failed to receive packet on channel {}", channel.0);
+            return;
+        };
+
+        self.input_macros.lock().await.record(channel, &data);
+
+        if !self.on_packet(packet).await {
+            self.input_stats.lock().await.on_forwarding_error(channel);
+        }
+    }
+
+    /// Returns false if the packet was input which didn't reach the host
+    async fn on_packet(self: &Arc<Self>, packet: InboundPacket) -> bool {
+        // File transfers don't need the moonlight stream
+        if let InboundPacket::File(packet) = packet {
+            let mut file_transfers = self.file_transfers.lock().await;
+            let status = file_transfers.on_packet(packet).await;
+            drop(file_transfers);
+
+            if let Some((id, status)) = status {
+                self.try_send_packet(
+                    OutboundPacket::FileTransfer { id, status },
+                    "file transfer status",
+                    true,
+                )
+                .await;
+            }
+            return true;
+        }
+        if let InboundPacket::Stats { message } = packet {
+            self.on_stats_message(message).await;
+            return true;
+        }
+        if let InboundPacket::General { message } = packet {
+            self.on_general_message(message).await;
+            return true;
+        }
+
+        self.send_input(packet).await
+    }
+
+    /// Returns false if the input didn't reach the host
+    async fn send_input(&self, packet: InboundPacket) -> bool {
         let stream = self.stream.read().await;
         let Some(stream) = stream.as_ref() else {
             warn!("Failed to send packet {packet:?} because of missing stream");
-            return;
+            return false;
         };
 
         let err = match packet {
-            InboundPacket::General { message } => {
-                // currently there are no packets associated with that
-                match message {}
-            }
             InboundPacket::MousePosition {
                 x,
                 y,
                 reference_width,
                 reference_height,
-            } => stream
-                .send_mouse_position(x, y, reference_width, reference_height)
-                .err(),
+            } => {
+                let err = stream
+                    .send_mouse_position(x, y, reference_width, reference_height)
+                    .err();
+                if err.is_none() {
+                    self.cursor_echo.lock().await.on_forwarded(CursorPosition {
+                        x,
+                        y,
+                        reference_width,
+                        reference_height,
+                    });
+                }
+                err
+            }
             InboundPacket::MouseButton { action, button } => {
                 stream.send_mouse_button(action, button).err()
             }
@@ -459,12 +883,16 @@ impl StreamConnection {
             } => {
                 let Some(gamepad) = ActiveGamepads::from_id(id) else {
                     warn!("Failed to add gamepad because it is out of range: {id}");
-                    return;
+                    return false;
                 };
 
                 let mut active_gamepads = self.active_gamepads.write().await;
 
                 active_gamepads.insert(gamepad);
+                self.rumble
+                    .lock()
+                    .await
+                    .controller_connected(id, ty, capabilities);
 
                 stream
                     .send_controller_arrival(
@@ -479,11 +907,12 @@ impl StreamConnection {
             InboundPacket::ControllerDisconnected { id } => {
                 let Some(gamepad) = ActiveGamepads::from_id(id) else {
                     warn!("Failed to remove gamepad because it is out of range: {id}");
-                    return;
+                    return false;
                 };
 
                 let mut active_gamepads = self.active_gamepads.write().await;
                 active_gamepads.remove(gamepad);
+                self.rumble.lock().await.controller_disconnected(id);
 
                 stream
                     .send_multi_controller(
@@ -511,7 +940,7 @@ impl StreamConnection {
             } => {
                 let Some(gamepad) = ActiveGamepads::from_id(id) else {
                     warn!("Failed to update gamepad state because it is out of range: {id}");
-                    return;
+                    return false;
                 };
 
                 let active_gamepads = self.active_gamepads.read().await;
@@ -520,7 +949,7 @@ impl StreamConnection {
                         "Failed to send gamepad event for not registered gamepad, gamepad: {id}, currently active: {:?}",
                         *active_gamepads
                     );
-                    return;
+                    return false;
                 }
 
                 stream
@@ -537,14 +966,259 @@ impl StreamConnection {
                     )
                     .err()
             }
+            InboundPacket::File(_)
+            | InboundPacket::Stats { .. }
+            | InboundPacket::General { .. } => {
+                warn!("Ignoring a packet which isn't input");
+                None
+            }
         };
 
         if let Some(err) = err {
             warn!("Failed to handle packet: {err:?}");
+            return false;
+        }
+
+        true
+    }
+
+    async fn on_general_message(self: &Arc<Self>, message: GeneralClientMessage) {
+        match message {
+            GeneralClientMessage::StartMacroRecording => {
+                info!("Started recording an input macro");
+
+                self.input_macros.lock().await.start_recording();
+            }
+            GeneralClientMessage::StopMacroRecording { name } => {
+                let events = self.input_macros.lock().await.stop_recording();
+
+                let name = name.trim();
+                let Some(events) = events.filter(|_| !name.is_empty()) else {
+                    info!("Discarded the recorded input macro");
+                    return;
+                };
+
+                info!(
+                    "Recorded input macro \"{name}\" with {} events",
+                    events.len()
+                );
+
+                let mut ipc_sender = self.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::SaveInputMacro {
+                        name: name.to_string(),
+                        events,
+                    })
+                    .await;
+            }
+            GeneralClientMessage::RunMacro { id } => {
+                self.run_input_macro(id).await;
+            }
+            GeneralClientMessage::CancelMacro => {
+                self.input_macros.lock().await.cancel();
+            }
+            GeneralClientMessage::SetCursorPrediction { enabled } => {
+                if enabled && !self.config.cursor_prediction.enabled {
+                    warn!("The client tried to enable the cursor prediction, but it's disabled");
+                    return;
+                }
+
+                info!("Set the cursor prediction to {enabled}");
+                self.cursor_echo.lock().await.set_enabled(enabled);
+            }
+            GeneralClientMessage::SwitchApp { app_id } => {
+                if self.stream_settings.lock().await.is_none() {
+                    warn!("Received an app switch but the stream wasn't started");
+                    return;
+                }
+
+                // Answered with a ServerIpcMessage::SwitchApp if the user may use the app
+                self.ipc_sender
+                    .clone()
+                    .send(StreamerIpcMessage::SwitchApp { app_id })
+                    .await;
+            }
+        }
+    }
+
+    async fn run_input_macro(self: &Arc<Self>, id: u32) {
+        let mut input_macros = self.input_macros.lock().await;
+
+        let Some(input_macro) = input_macros.get(id).cloned() else {
+            warn!("Failed to run input macro {id} because it doesn't exist");
+            return;
+        };
+
+        info!("Running input macro \"{}\"", input_macro.name);
+
+        let this = Arc::downgrade(self);
+        input_macros.set_running(spawn(async move {
+            for event in input_macro.events {
+                sleep(Duration::from_millis(u64::from(event.delay_ms))).await;
+
+                let channel = TransportChannel(event.channel);
+                if !is_input_channel(channel) {
+                    continue;
+                }
+                let Some(packet) = InboundPacket::deserialize(channel, &event.data) else {
+                    continue;
+                };
+
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+                this.send_input(packet).await;
+            }
+
+            debug!("Finished running input macro \"{}\"", input_macro.name);
+        }));
+    }
+
+    async fn on_stats_message(&self, message: StatsClientMessage) {
+        match message {
+            StatsClientMessage::SetLatencyTest { enabled } => {
+                info!("Latency test enabled: {enabled}");
+
+                let mut latency_test = self.latency_test.lock().await;
+                *latency_test = enabled.then(LatencyTest::new);
+            }
+            StatsClientMessage::LatencyEcho {
+                presentation_time_us,
+            } => {
+                let received_at = Instant::now();
+
+                let host_rtt = {
+                    let stream = self.stream.read().await;
+                    stream
+                        .as_ref()
+                        .and_then(|stream| stream.estimated_rtt_info().ok())
+                        .map(|info| info.rtt)
+                };
+
+                let update = {
+                    let mut latency_test = self.latency_test.lock().await;
+                    latency_test.as_mut().and_then(|latency_test| {
+                        latency_test.on_echo(presentation_time_us, received_at, host_rtt)
+                    })
+                };
+
+                if let Some(update) = update {
+                    self.try_send_packet(OutboundPacket::Stats(update), "latency test", false)
+                        .await;
+                }
+            }
         }
     }
 
     async fn on_ipc_message(self: &Arc<StreamConnection>, message: ServerIpcMessage) {
+        if let ServerIpcMessage::InputMacroSaved(input_macro) = message {
+            let id = input_macro.id;
+            let name = input_macro.name.clone();
+
+            self.input_macros.lock().await.insert(input_macro);
+
+            self.try_send_packet(
+                OutboundPacket::General {
+                    message: GeneralServerMessage::MacroSaved { id, name },
+                },
+                "macro saved",
+                true,
+            )
+            .await;
+            return;
+        }
+
+        if let ServerIpcMessage::TakeScreenshot { format } = message {
+            request_screenshot(self, format).await;
+            return;
+        }
+        if let ServerIpcMessage::StartPreview(config) = message {
+            start_preview(self, config).await;
+            return;
+        }
+        if let ServerIpcMessage::StopPreview = message {
+            stop_preview(self).await;
+            return;
+        }
+        if let ServerIpcMessage::HlsRequest {
+            request_id,
+            path,
+            msn,
+            part,
+        } = message
+        {
+            // Requests for parts which don't exist yet wait for them
+            spawn(handle_hls_request(
+                self.clone(),
+                request_id,
+                path,
+                msn,
+                part,
+            ));
+            return;
+        }
+
+        if let ServerIpcMessage::SwitchApp {
+            app_id,
+            display_app_ids,
+        } = message
+        {
+            let this = self.clone();
+            spawn(async move {
+                this.switch_app(app_id, display_app_ids).await;
+            });
+            return;
+        }
+
+        if let ServerIpcMessage::WebSocket(StreamClientMessage::Takeover) = &message {
+            // The web server already stopped the stream on the host
+            let settings = self.busy_settings.lock().await.take();
+            let Some(settings) = settings else {
+                warn!("Received a takeover but the host wasn't busy");
+                return;
+            };
+
+            info!("Retrying to start the stream after a takeover");
+
+            let this = self.clone();
+            spawn(async move {
+                if let Err(err) = this.start_stream(settings).await {
+                    error!("Failed to start stream, stopping: {err}");
+
+                    this.stop().await;
+                }
+            });
+            return;
+        }
+
+        if let ServerIpcMessage::WebSocket(StreamClientMessage::SetAudioRouting { audio_routing }) =
+            &message
+        {
+            let this = self.clone();
+            let audio_routing = (*audio_routing).into();
+            spawn(async move {
+                this.set_audio_routing(audio_routing).await;
+            });
+            return;
+        }
+
+        if let ServerIpcMessage::WebSocket(StreamClientMessage::SetQuality {
+            bitrate,
+            fps,
+            width,
+            height,
+        }) = &message
+        {
+            let quality = StreamQuality {
+                bitrate: *bitrate,
+                fps: *fps,
+                width: *width,
+                height: *height,
+            };
+            request_quality_change(self, quality).await;
+            return;
+        }
+
         if let ServerIpcMessage::WebSocket(StreamClientMessage::SetTransport(transport_type)) =
             &message
         {
@@ -554,6 +1228,7 @@ impl StreamConnection {
 
                     let (sender, events) = match webrtc::new(
                         &self.config.webrtc,
+                        &self.config.media_priority,
                         self.video_frame_queue_size,
                         self.audio_sample_queue_size,
                     )
@@ -570,6 +1245,9 @@ impl StreamConnection {
                 TransportType::WebSocket => {
                     info!("Trying Web Socket transport");
 
+                    // The web socket of the client is the peer
+                    self.peer_watchdog.lock().await.on_peer_connected();
+
                     let (sender, events) = match web_socket::new().await {
                         Ok(value) => value,
                         Err(err) => {
@@ -605,19 +1283,32 @@ impl StreamConnection {
         }
         info!("Starting Moonlight stream with settings: {settings}");
 
+        self.stream_settings.lock().await.replace(settings.clone());
+        self.audio_to_client
+            .store(settings.audio_routing.plays_on_client(), Ordering::Release);
+
         // Send stage
         let mut ipc_sender = self.ipc_sender.clone();
         ipc_sender
             .send(StreamerIpcMessage::WebSocket(
-                StreamServerMessage::DebugLog {
-                    message: "Moonlight Stream".to_string(),
-                    ty: None,
-                },
+                StreamMessageCode::MoonlightStream.debug_log(None),
             ))
             .await;
 
+        let app_id = self.info.app.lock().await.app_id(settings.display_id);
+
         let mut host = self.info.host.lock().await;
 
+        // We'd resume the other app instead of starting ours
+        host.clear_cache();
+        let current_game = host.current_game().await?;
+        if current_game != 0 && current_game != app_id {
+            info!("Host is busy with app {current_game}");
+
+            self.on_host_busy(settings).await;
+            return Ok(());
+        }
+
         let video_decoder = StreamVideoDecoder {
             stream: Arc::downgrade(self),
             supported_formats: settings.video_supported_formats,
@@ -632,16 +1323,20 @@ impl StreamConnection {
             stream: Arc::downgrade(self),
         };
 
+        let encryption_flags =
+            encryption_flags(&self.config.encryption, host.address(), host.http_port()).await;
+        debug!("[Stream]: using the encryption flags {encryption_flags:?}");
+
         let stream = match host
             .start_stream(
                 &self.moonlight,
-                self.info.app_id,
+                app_id,
                 settings.width,
                 settings.height,
                 settings.fps,
                 false,
                 true,
-                settings.play_audio_local,
+                settings.audio_routing,
                 ActiveGamepads::empty(),
                 false,
                 settings.video_colorspace,
@@ -652,7 +1347,7 @@ impl StreamConnection {
                 },
                 settings.bitrate,
                 settings.packet_size,
-                EncryptionFlags::all(),
+                encryption_flags,
                 connection_listener,
                 video_decoder,
                 audio_decoder,
@@ -663,16 +1358,11 @@ impl StreamConnection {
             Err(err) => {
                 warn!("[Stream]: failed to start moonlight stream: {err:?}");
 
-                #[allow(clippy::single_match)]
-                match err {
-                    HostError::Moonlight(MoonlightError::ConnectionAlreadyExists) => {
-                        ipc_sender
-                            .send(StreamerIpcMessage::WebSocket(
-                                StreamServerMessage::DebugLog { message: "Failed to start stream because this streamer is already streaming".to_string(), ty: None },
-                            ))
-                            .await;
-                    }
-                    _ => {}
+                if let HostError::Moonlight(MoonlightError::ConnectionAlreadyExists)
+                | HostError::AppAlreadyRunning = err
+                {
+                    self.on_host_busy(settings).await;
+                    return Ok(());
                 }
 
                 return Err(err.into());
@@ -693,7 +1383,7 @@ impl StreamConnection {
 
             let video = setup.video.unwrap_or_else(|| {
                 warn!("failed to query video setup information. Giving the browser guessed information");
-                VideoSetup { format: VideoFormat::H264, width: settings.width, height: settings.height, redraw_rate: settings.fps, flags: 0 }
+                VideoSetup { format: VideoFormat::H264, width: settings.width, height: settings.height, redraw_rate: settings.fps.get(), flags: 0 }
             });
 
             let audio = setup.audio.clone().unwrap_or(OpusMultistreamConfig::STEREO);
@@ -706,6 +1396,33 @@ impl StreamConnection {
             video_setup.format, video_setup.width, video_setup.height, video_setup.redraw_rate
         );
 
+        let stage_timings = stream
+            .stage_timings()
+            .into_iter()
+            .map(|timing| StatsStageTiming {
+                stage: timing.stage.name().to_string(),
+                duration_ms: timing.duration.as_secs_f64() * 1000.0,
+                error_code: timing.error_code,
+            })
+            .collect::<Vec<_>>();
+        debug!("[Stream]: connecting to the host took {stage_timings:?}");
+
+        start_rtsp_output(self, video_setup.format, &audio_setup).await;
+        start_hls_recording(self).await;
+
+        let this = self.clone();
+        let stats_stage_timings = stage_timings.clone();
+        spawn(async move {
+            this.try_send_packet(
+                OutboundPacket::Stats(StreamerStatsUpdate::StageTimings {
+                    stages: stats_stage_timings,
+                }),
+                "stage timings",
+                false,
+            )
+            .await;
+        });
+
         spawn(async move {
             ipc_sender
                 .send(StreamerIpcMessage::WebSocket(
@@ -714,9 +1431,10 @@ impl StreamConnection {
                         format: video_setup.format as u32,
                         width: video_setup.width,
                         height: video_setup.height,
-                        fps: video_setup.redraw_rate,
+                        fps: Fps(video_setup.redraw_rate),
                         audio_channels: audio_setup.channel_count,
                         audio_sample_rate: audio_setup.sample_rate,
+                        stage_timings,
                     },
                 ))
                 .await;
@@ -725,9 +1443,255 @@ impl StreamConnection {
         let mut stream_guard = self.stream.write().await;
         stream_guard.replace(stream);
 
+        self.video_watchdog
+            .lock()
+            .await
+            .stream_started(Instant::now());
+
+        if self.peer_watchdog.lock().await.on_launched(Instant::now()) {
+            let this = Arc::downgrade(self);
+            let connect_timeout = self.config.peer_watchdog.connect_timeout;
+
+            spawn(async move {
+                sleep(connect_timeout).await;
+
+                let Some(this) = this.upgrade() else {
+                    return;
+                };
+                this.check_peer_watchdog().await;
+            });
+        }
+
         Ok(())
     }
 
+    async fn check_peer_watchdog(&self) {
+        if self.is_terminating.load(Ordering::Acquire)
+            || !self.peer_watchdog.lock().await.check(Instant::now())
+        {
+            return;
+        }
+
+        warn!(
+            "[Stream]: no peer connected within {:?} after launching the app, stopping",
+            self.config.peer_watchdog.connect_timeout
+        );
+
+        // The host only quits the app once our connection is gone
+        let stream = self.stream.write().await.take();
+        if let Some(stream) = stream
+            && let Err(err) = spawn_blocking(move || stream.stop()).await
+        {
+            warn!("Failed to stop the stream: {err}");
+        }
+
+        let mut app_quit = false;
+        if self.config.peer_watchdog.quit_app {
+            match self.info.host.lock().await.cancel().await {
+                Ok(quit) => app_quit = quit,
+                Err(err) => warn!("[Stream]: failed to quit the app on the host: {err:?}"),
+            }
+        }
+
+        self.ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::PeerWatchdog { app_quit })
+            .await;
+
+        self.stop().await;
+    }
+
+    async fn check_video_watchdog(self: &Arc<Self>) {
+        // The stream might be restarting, e.g. to change the audio routing
+        if self.stream.read().await.is_none() {
+            return;
+        }
+
+        let action = self.video_watchdog.lock().await.check(Instant::now());
+
+        match action {
+            None => {}
+            Some(WatchdogAction::RequestIdr) => {
+                warn!("[Stream]: The host didn't send video for a while, requesting an IDR frame");
+
+                self.ipc_sender
+                    .clone()
+                    .send(StreamerIpcMessage::WebSocket(
+                        StreamMessageCode::VideoStalled.debug_log(None),
+                    ))
+                    .await;
+
+                let stream = self.stream.read().await;
+                if let Some(stream) = stream.as_ref()
+                    && let Err(err) = stream.request_idr_frame()
+                {
+                    warn!("[Stream]: Failed to request an IDR frame: {err}");
+                }
+            }
+            Some(WatchdogAction::RestartStream) => {
+                let Some(settings) = self.stream_settings.lock().await.clone() else {
+                    return;
+                };
+
+                warn!("[Stream]: The host still doesn't send video, restarting the stream");
+
+                self.ipc_sender
+                    .clone()
+                    .send(StreamerIpcMessage::WebSocket(
+                        StreamMessageCode::VideoStallRestart.debug_log(None),
+                    ))
+                    .await;
+
+                let stream = self.stream.write().await.take();
+                if let Some(stream) = stream
+                    && let Err(err) = spawn_blocking(move || stream.stop()).await
+                {
+                    warn!("Failed to stop the stream: {err}");
+                }
+
+                if let Err(err) = self.start_stream(settings).await {
+                    error!("Failed to restart stream, stopping: {err}");
+
+                    self.stop().await;
+                }
+            }
+        }
+    }
+
+    async fn set_audio_routing(self: &Arc<Self>, audio_routing: AudioRouting) {
+        let settings = {
+            let mut settings = self.stream_settings.lock().await;
+            let Some(settings) = settings.as_mut() else {
+                warn!("Received an audio routing but the stream wasn't started");
+                return;
+            };
+
+            let previous = settings.audio_routing;
+            settings.audio_routing = audio_routing;
+
+            self.audio_to_client
+                .store(audio_routing.plays_on_client(), Ordering::Release);
+
+            // The host only reads if it should play the audio when launching or resuming
+            if previous.plays_on_host() == audio_routing.plays_on_host() {
+                info!("Changed audio routing to {audio_routing:?}");
+                return;
+            }
+
+            settings.clone()
+        };
+
+        info!("Restarting the stream to change the audio routing to {audio_routing:?}");
+
+        // Only one connection to the host can exist at a time
+        let stream = self.stream.write().await.take();
+        if let Some(stream) = stream
+            && let Err(err) = spawn_blocking(move || stream.stop()).await
+        {
+            warn!("Failed to stop the stream: {err}");
+        }
+
+        if let Err(err) = self.start_stream(settings).await {
+            error!("Failed to restart stream, stopping: {err}");
+
+            self.stop().await;
+        }
+    }
+
+    /// Only the connection to the host is restarted, the transport keeps its tracks and channels
+    async fn switch_app(self: &Arc<Self>, app_id: u32, display_app_ids: HashMap<u32, u32>) {
+        let Some(settings) = self.stream_settings.lock().await.clone() else {
+            warn!("Received an app switch but the stream wasn't started");
+            return;
+        };
+
+        info!("Switching the app to {app_id}");
+
+        self.ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::WebSocket(
+                StreamMessageCode::SwitchingApp.debug_log(None),
+            ))
+            .await;
+
+        // Only one connection to the host can exist at a time
+        let stream = self.stream.write().await.take();
+        if let Some(stream) = stream
+            && let Err(err) = spawn_blocking(move || stream.stop()).await
+        {
+            warn!("Failed to stop the stream: {err}");
+        }
+
+        // The host would resume the running app instead of launching the new one
+        if let Err(err) = self.info.host.lock().await.cancel().await {
+            warn!("[Stream]: failed to quit the app on the host: {err:?}");
+        }
+
+        {
+            let mut app = self.info.app.lock().await;
+            app.app_id = app_id;
+            app.display_app_ids = display_app_ids;
+        }
+
+        if let Err(err) = self.start_stream(settings).await {
+            error!("Failed to switch the app, stopping: {err}");
+
+            self.stop().await;
+        }
+    }
+
+    /// Called by the video decoder at the IDR frame after [request_quality_change]
+    async fn change_quality(self: &Arc<Self>, quality: StreamQuality) {
+        let settings = {
+            let mut settings = self.stream_settings.lock().await;
+            let Some(settings) = settings.as_mut() else {
+                return;
+            };
+
+            settings.bitrate = quality.bitrate;
+            settings.fps = quality.fps;
+            settings.width = quality.width;
+            settings.height = quality.height;
+
+            settings.clone()
+        };
+
+        info!(
+            "Restarting the stream to change the quality to {}x{}x{} with {}",
+            quality.width, quality.height, quality.fps, quality.bitrate
+        );
+
+        // Only one connection to the host can exist at a time
+        let stream = self.stream.write().await.take();
+        if let Some(stream) = stream
+            && let Err(err) = spawn_blocking(move || stream.stop()).await
+        {
+            warn!("Failed to stop the stream: {err}");
+        }
+
+        if let Err(err) = self.start_stream(settings).await {
+            error!("Failed to restart stream, stopping: {err}");
+
+            self.stop().await;
+        }
+    }
+
+    /// Waits for a [StreamClientMessage::Takeover] instead of stopping
+    async fn on_host_busy(&self, settings: StreamSettings) {
+        self.busy_settings.lock().await.replace(settings);
+
+        // The web server fills in the user and if a takeover is allowed
+        self.ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::WebSocket(
+                StreamServerMessage::HostBusy {
+                    current_user: None,
+                    can_takeover: false,
+                },
+            ))
+            .await;
+    }
+
     async fn stop(&self) {
         if self
             .is_terminating
@@ -740,6 +1704,8 @@ impl StreamConnection {
 
         debug!("[Stream]: Stopping...");
 
+        self.info.host_requests.cancel();
+
         {
             let mut stream = self.stream.write().await;
             if let Some(stream) = stream.take() {
@@ -757,6 +1723,19 @@ impl StreamConnection {
             drop(transport);
         }
 
+        {
+            let mut file_transfers = self.file_transfers.lock().await;
+            file_transfers.clear().await;
+        }
+
+        stop_rtsp_output(self).await;
+        stop_hls_output(self).await;
+
+        if let Some(summary) = self.input_stats.lock().await.summary() {
+            info!("Input of the session: {summary}");
+        }
+        log_buffer_pool_stats();
+
         let mut ipc_sender = self.ipc_sender.clone();
         ipc_sender.send(StreamerIpcMessage::Stop).await;
 
@@ -782,10 +1761,10 @@ impl ConnectionListener for StreamConnectionListener {
         stream.runtime.spawn(async move {
             ipc_sender
                 .send(StreamerIpcMessage::WebSocket(
-                    StreamServerMessage::DebugLog {
-                        message: format!("Starting Stage: {}", stage.name()),
-                        ty: None,
-                    },
+                    StreamMessageCode::StageStarting {
+                        stage: stage.name().to_string(),
+                    }
+                    .debug_log(None),
                 ))
                 .await;
         });
@@ -799,10 +1778,10 @@ impl ConnectionListener for StreamConnectionListener {
 
         let mut ipc_sender = stream.ipc_sender.clone();
         ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
-            StreamServerMessage::DebugLog {
-                message: format!("Completed Stage: {}", stage.name()),
-                ty: None,
-            },
+            StreamMessageCode::StageCompleted {
+                stage: stage.name().to_string(),
+            }
+            .debug_log(None),
         ));
     }
 
@@ -814,20 +1793,17 @@ impl ConnectionListener for StreamConnectionListener {
 
         let mut ipc_sender = stream.ipc_sender.clone();
         ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
-            StreamServerMessage::DebugLog {
-                message: format!(
-                    "Failed Stage: {} with error code {}",
-                    stage.name(),
-                    error_code
-                ),
-                ty: Some(LogMessageType::Fatal),
-            },
+            StreamMessageCode::StageFailed {
+                stage: stage.name().to_string(),
+                error_code,
+            }
+            .debug_log(Some(LogMessageType::Fatal)),
         ));
     }
 
     fn connection_started(&mut self) {}
 
-    fn connection_terminated(&mut self, error_code: i32) {
+    fn connection_terminated(&mut self, reason: TerminationReason) {
         let Some(stream) = self.stream.upgrade() else {
             warn!("Failed to get stream because it is already deallocated");
             return;
@@ -835,7 +1811,11 @@ impl ConnectionListener for StreamConnectionListener {
 
         let mut ipc_sender = stream.ipc_sender.clone();
         ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
-            StreamServerMessage::ConnectionTerminated { error_code },
+            StreamServerMessage::ConnectionTerminated {
+                error_code: reason.code(),
+                reason: reason.into(),
+                description: reason.description().to_string(),
+            },
         ));
 
         stream.runtime.clone().block_on(async move {
@@ -882,16 +1862,14 @@ impl ConnectionListener for StreamConnectionListener {
         };
 
         stream.runtime.clone().block_on(async move {
+            let packet = stream.rumble.lock().await.rumble(
+                controller_number as u8,
+                low_frequency_motor,
+                high_frequency_motor,
+            );
+
             stream
-                .try_send_packet(
-                    OutboundPacket::ControllerRumble {
-                        controller_number: controller_number as u8,
-                        low_frequency_motor,
-                        high_frequency_motor,
-                    },
-                    "controller rumble",
-                    true,
-                )
+                .try_send_packet(packet, "controller rumble", true)
                 .await;
         });
     }
@@ -908,17 +1886,23 @@ impl ConnectionListener for StreamConnectionListener {
         };
 
         stream.runtime.clone().block_on(async move {
-            stream
-                .try_send_packet(
-                    OutboundPacket::ControllerTriggerRumble {
-                        controller_number: controller_number as u8,
-                        left_trigger_motor,
-                        right_trigger_motor,
-                    },
-                    "controller rumble triggers",
-                    true,
+            // Controllers without trigger motors might get the rumble on their main motors instead
+            let packets = stream
+                .rumble
+                .lock()
+                .await
+                .trigger_rumble(
+                    controller_number as u8,
+                    left_trigger_motor,
+                    right_trigger_motor,
                 )
-                .await;
+                .collect::<Vec<_>>();
+
+            for packet in packets {
+                stream
+                    .try_send_packet(packet, "controller rumble triggers", true)
+                    .await;
+            }
         });
     }
 
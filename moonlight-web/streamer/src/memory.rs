@@ -0,0 +1,286 @@
+//! Caps the memory of the video and audio samples which wait to be sent.
+//!
+//! If the client stalls the transports keep accepting samples, so without a cap the streamer would grow until it's killed.
+//! Every buffered sample reserves its size in the global [BufferPool], samples which don't fit anymore are dropped.
+
+use std::{
+    mem::take,
+    ops::{Deref, DerefMut},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use common::config::StreamerMemoryConfig;
+use log::{info, warn};
+
+/// Drops are only logged once per interval, a stalled client drops every sample
+const DROP_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+static BUFFER_POOL: OnceLock<BufferPool> = OnceLock::new();
+
+/// Must be called before the first sample is received, else the default config is used
+pub fn init_buffer_pool(config: &StreamerMemoryConfig) {
+    if BUFFER_POOL.set(BufferPool::new(config.clone())).is_err() {
+        warn!("[Memory]: the buffer pool was already initialized");
+    }
+}
+
+pub fn buffer_pool() -> &'static BufferPool {
+    BUFFER_POOL.get_or_init(|| BufferPool::new(StreamerMemoryConfig::default()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Video,
+    Audio,
+}
+
+#[derive(Debug)]
+pub struct BufferPool {
+    config: StreamerMemoryConfig,
+    buffered_bytes: AtomicUsize,
+    free_buffers: Mutex<Vec<Vec<u8>>>,
+    drops: Mutex<DropLog>,
+    dropped_total: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct DropLog {
+    last_log: Option<Instant>,
+    video: u64,
+    audio: u64,
+}
+
+impl BufferPool {
+    pub fn new(config: StreamerMemoryConfig) -> Self {
+        Self {
+            config,
+            buffered_bytes: Default::default(),
+            free_buffers: Default::default(),
+            drops: Default::default(),
+            dropped_total: Default::default(),
+        }
+    }
+
+    /// The bytes of all samples which weren't sent yet
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes.load(Ordering::Acquire)
+    }
+
+    /// The samples which were dropped because of the cap
+    pub fn dropped_total(&self) -> u64 {
+        self.dropped_total.load(Ordering::Acquire)
+    }
+
+    /// Reserves memory for a sample which is buffered elsewhere, None if the sample should be dropped
+    pub fn reserve(&'static self, kind: BufferKind, len: usize) -> Option<MemoryReservation> {
+        let reserved = self
+            .buffered_bytes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |buffered| {
+                buffered
+                    .checked_add(len)
+                    .filter(|new| *new <= self.config.max_buffered_bytes)
+            })
+            .is_ok();
+
+        if !reserved {
+            self.on_drop(kind);
+            return None;
+        }
+
+        Some(MemoryReservation { pool: self, len })
+    }
+
+    /// Returns an empty buffer which can hold len bytes, None if the sample should be dropped
+    pub fn allocate(&'static self, kind: BufferKind, len: usize) -> Option<PooledBuffer> {
+        let reservation = self.reserve(kind, len)?;
+
+        let mut buffer = self
+            .free_buffers
+            .lock()
+            .expect("buffer pool poisoned")
+            .pop()
+            .unwrap_or_default();
+        buffer.reserve(len);
+
+        Some(PooledBuffer {
+            buffer,
+            reservation,
+        })
+    }
+
+    fn release(&self, len: usize) {
+        self.buffered_bytes.fetch_sub(len, Ordering::AcqRel);
+    }
+
+    fn recycle(&self, mut buffer: Vec<u8>) {
+        // Buffers which are a lot larger than the cap would keep the memory forever
+        if buffer.capacity() > self.config.max_buffered_bytes {
+            return;
+        }
+
+        let mut free_buffers = self.free_buffers.lock().expect("buffer pool poisoned");
+        if free_buffers.len() < self.config.max_pooled_buffers {
+            buffer.clear();
+            free_buffers.push(buffer);
+        }
+    }
+
+    fn on_drop(&self, kind: BufferKind) {
+        self.dropped_total.fetch_add(1, Ordering::AcqRel);
+
+        let mut drops = self.drops.lock().expect("buffer pool poisoned");
+        match kind {
+            BufferKind::Video => drops.video += 1,
+            BufferKind::Audio => drops.audio += 1,
+        }
+
+        let now = Instant::now();
+        if drops
+            .last_log
+            .is_some_and(|last_log| now.duration_since(last_log) < DROP_LOG_INTERVAL)
+        {
+            return;
+        }
+        drops.last_log = Some(now);
+
+        let video = take(&mut drops.video);
+        let audio = take(&mut drops.audio);
+        drop(drops);
+
+        warn!(
+            "[Memory]: the buffered samples reached the cap of {} bytes, dropped {video} video and {audio} audio samples",
+            self.config.max_buffered_bytes
+        );
+    }
+}
+
+/// Memory of the [BufferPool] which is freed once this is dropped
+#[derive(Debug)]
+pub struct MemoryReservation {
+    pool: &'static BufferPool,
+    len: usize,
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.pool.release(self.len);
+    }
+}
+
+/// A buffer of the [BufferPool], it's reused once it was dropped
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    reservation: MemoryReservation,
+}
+
+impl PooledBuffer {
+    /// The memory stays reserved until all clones of the bytes are dropped
+    pub fn freeze(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.reservation.pool.recycle(take(&mut self.buffer));
+    }
+}
+
+/// Logs how much memory is buffered, e.g. when the stream ends
+pub fn log_buffer_pool_stats() {
+    let pool = buffer_pool();
+
+    info!(
+        "[Memory]: {} bytes are buffered, dropped {} samples because of the memory cap",
+        pool.buffered_bytes(),
+        pool.dropped_total()
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use common::config::StreamerMemoryConfig;
+
+    use crate::memory::{BufferKind, BufferPool};
+
+    fn pool(max_buffered_bytes: usize) -> &'static BufferPool {
+        Box::leak(Box::new(BufferPool::new(StreamerMemoryConfig {
+            max_buffered_bytes,
+            max_pooled_buffers: 2,
+        })))
+    }
+
+    #[test]
+    fn test_drops_above_cap() {
+        let pool = pool(100);
+
+        let first = pool.allocate(BufferKind::Video, 60).expect("first buffer");
+        assert_eq!(pool.buffered_bytes(), 60);
+
+        assert!(pool.allocate(BufferKind::Video, 60).is_none());
+        assert!(pool.reserve(BufferKind::Audio, 41).is_none());
+        assert_eq!(pool.dropped_total(), 2);
+
+        let second = pool.reserve(BufferKind::Audio, 40).expect("fits exactly");
+        assert_eq!(pool.buffered_bytes(), 100);
+
+        drop(first);
+        drop(second);
+        assert_eq!(pool.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn test_frozen_bytes_keep_reservation() {
+        let pool = pool(100);
+
+        let mut buffer = pool.allocate(BufferKind::Audio, 10).expect("buffer");
+        buffer.extend_from_slice(&[1, 2, 3]);
+
+        let bytes = buffer.freeze();
+        let slice = bytes.slice(1..);
+        drop(bytes);
+        assert_eq!(pool.buffered_bytes(), 10);
+        assert_eq!(&slice[..], &[2, 3]);
+
+        drop(slice);
+        assert_eq!(pool.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = pool(1000);
+
+        let buffer = pool.allocate(BufferKind::Video, 500).expect("buffer");
+        let capacity = buffer.capacity();
+        drop(buffer);
+
+        let buffer = pool.allocate(BufferKind::Video, 10).expect("reused buffer");
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+    }
+}
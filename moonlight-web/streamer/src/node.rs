@@ -0,0 +1,225 @@
+//! Runs the streamers of a web server on this machine, see [StreamerNodeConfig].
+//!
+//! The node keeps a control connection to the web server. Every session spawns another streamer,
+//! whose stdio is forwarded over a connection of its own.
+
+use std::{collections::HashMap, env, path::PathBuf, pin::Pin, process::Stdio, sync::Arc};
+
+use anyhow::{Context, bail};
+use common::{
+    config::StreamerNodeConfig,
+    ipc::{NodeHelloMessage, NodeIpcMessage, ServerNodeMessage, create_process_ipc},
+};
+use log::{LevelFilter, debug, info, warn};
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+use simplelog::{ColorChoice, TermLogger, TerminalMode};
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, copy, split},
+    net::TcpStream,
+    process::Command,
+    select, spawn,
+    sync::{Mutex, oneshot},
+    time::{Instant, interval, sleep},
+};
+use tokio_openssl::SslStream;
+
+/// The web server is considered lost after this many heartbeats without an answer
+const MISSED_HEARTBEATS: u32 = 3;
+
+struct StreamerNode {
+    config: StreamerNodeConfig,
+    connector: SslConnector,
+    /// Sessions are served by this executable
+    streamer_path: PathBuf,
+    /// Stops the streamer of a session
+    sessions: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+}
+
+/// Loads the config and serves the web server until the process is killed
+pub async fn run(config_path: &str) -> Result<(), anyhow::Error> {
+    let config = fs::read_to_string(config_path)
+        .await
+        .with_context(|| format!("failed to read the node config {config_path}"))?;
+    let config = serde_json::from_str::<StreamerNodeConfig>(&config)
+        .context("failed to parse the node config")?;
+
+    TermLogger::init(
+        config.log_level,
+        simplelog::ConfigBuilder::new()
+            .add_filter_ignore_str("webrtc_sctp")
+            .set_time_level(LevelFilter::Off)
+            .build(),
+        TerminalMode::Stderr,
+        ColorChoice::Never,
+    )?;
+
+    let connector = tls_connector(&config).with_context(|| {
+        format!(
+            "failed to load the certificates from {}, {} and {}",
+            config.certificate_path, config.private_key_path, config.ca_certificate_path
+        )
+    })?;
+    let streamer_path = env::current_exe().context("failed to find the streamer executable")?;
+
+    let node = Arc::new(StreamerNode {
+        config,
+        connector,
+        streamer_path,
+        sessions: Default::default(),
+    });
+
+    loop {
+        if let Err(err) = node.run_control_connection().await {
+            warn!("[Node]: lost the connection to the web server: {err:#}");
+        }
+
+        sleep(node.config.reconnect_interval).await;
+    }
+}
+
+impl StreamerNode {
+    async fn connect(
+        &self,
+        hello: &NodeHelloMessage,
+    ) -> Result<SslStream<TcpStream>, anyhow::Error> {
+        let socket = TcpStream::connect(&self.config.server_address)
+            .await
+            .with_context(|| format!("failed to connect to {}", self.config.server_address))?;
+
+        // Verifies that the certificate of the web server belongs to the server name
+        let ssl = self
+            .connector
+            .configure()?
+            .into_ssl(&self.config.server_name)?;
+        let mut stream = SslStream::new(ssl, socket)?;
+        Pin::new(&mut stream)
+            .connect()
+            .await
+            .context("the tls handshake failed")?;
+
+        let mut json = serde_json::to_string(hello)?;
+        json.push('\n');
+        stream.write_all(json.as_bytes()).await?;
+        stream.flush().await?;
+
+        Ok(stream)
+    }
+
+    async fn run_control_connection(self: &Arc<Self>) -> Result<(), anyhow::Error> {
+        let stream = self
+            .connect(&NodeHelloMessage::Register {
+                name: self.config.name.clone(),
+                networks: self.config.networks.clone(),
+                max_sessions: self.config.max_sessions,
+            })
+            .await?;
+        info!(
+            "[Node]: registered as {} at {}",
+            self.config.name, self.config.server_address
+        );
+
+        let (read, write) = split(stream);
+        let (mut ipc_sender, mut ipc_receiver) =
+            create_process_ipc::<ServerNodeMessage, NodeIpcMessage>(read, write).await;
+
+        let heartbeat_timeout = self.config.heartbeat_interval * MISSED_HEARTBEATS;
+        let mut heartbeat = interval(self.config.heartbeat_interval);
+        let mut last_answer = Instant::now();
+
+        loop {
+            select! {
+                _ = heartbeat.tick() => {
+                    if last_answer.elapsed() > heartbeat_timeout {
+                        bail!("the web server didn't answer the heartbeats");
+                    }
+
+                    ipc_sender.send(NodeIpcMessage::Heartbeat).await;
+                }
+                message = ipc_receiver.recv() => match message {
+                    Some(ServerNodeMessage::Heartbeat) => {
+                        last_answer = Instant::now();
+                    }
+                    Some(ServerNodeMessage::StartSession { session_id }) => {
+                        spawn(self.clone().run_session(session_id));
+                    }
+                    Some(ServerNodeMessage::StopSession { session_id }) => {
+                        if let Some(stop) = self.sessions.lock().await.remove(&session_id) {
+                            let _ = stop.send(());
+                        }
+                    }
+                    None => bail!("the web server closed the connection"),
+                },
+            }
+        }
+    }
+
+    async fn run_session(self: Arc<Self>, session_id: u64) {
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        self.sessions.lock().await.insert(session_id, stop_sender);
+
+        match self.serve_session(session_id, stop_receiver).await {
+            Ok(()) => info!("[Node]: session {session_id} ended"),
+            Err(err) => warn!("[Node]: session {session_id} failed: {err:#}"),
+        }
+
+        self.sessions.lock().await.remove(&session_id);
+    }
+
+    async fn serve_session(
+        &self,
+        session_id: u64,
+        stop: oneshot::Receiver<()>,
+    ) -> Result<(), anyhow::Error> {
+        // The streamer logs to the stderr of the node
+        let mut child = Command::new(&self.streamer_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .context("failed to spawn the streamer")?;
+
+        let (Some(mut stdin), Some(mut stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            bail!("the streamer process didn't include a stdin or stdout");
+        };
+
+        let connection = self
+            .connect(&NodeHelloMessage::Session { session_id })
+            .await?;
+        let (mut read, mut write) = split(connection);
+
+        let to_streamer = spawn(async move { copy(&mut read, &mut stdin).await });
+        let from_streamer = spawn(async move {
+            let result = copy(&mut stdout, &mut write).await;
+            let _ = write.shutdown().await;
+            result
+        });
+
+        select! {
+            status = child.wait() => {
+                debug!("[Node]: the streamer of session {session_id} exited with {}", status?);
+            }
+            _ = stop => {
+                debug!("[Node]: the web server stopped session {session_id}");
+                child.kill().await?;
+            }
+        }
+
+        to_streamer.abort();
+        // The last messages of the streamer, e.g. a panic
+        let _ = from_streamer.await;
+
+        Ok(())
+    }
+}
+
+fn tls_connector(config: &StreamerNodeConfig) -> Result<SslConnector, anyhow::Error> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    builder.set_ca_file(&config.ca_certificate_path)?;
+    builder.set_certificate_chain_file(&config.certificate_path)?;
+    builder.set_private_key_file(&config.private_key_path, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+
+    Ok(builder.build())
+}
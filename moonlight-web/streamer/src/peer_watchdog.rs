@@ -0,0 +1,94 @@
+//! Detects streams whose peer never connected, the launched app would otherwise keep running on the host.
+
+use std::time::Instant;
+
+use common::config::PeerWatchdogConfig;
+
+pub struct PeerWatchdog {
+    config: PeerWatchdogConfig,
+    connected: bool,
+    /// None until the app was launched
+    launched: Option<Instant>,
+}
+
+impl PeerWatchdog {
+    pub fn new(config: PeerWatchdogConfig) -> Self {
+        Self {
+            config,
+            connected: false,
+            launched: None,
+        }
+    }
+
+    /// Returns true if the watchdog started waiting for the peer, restarts of the stream don't wait again
+    pub fn on_launched(&mut self, now: Instant) -> bool {
+        if !self.config.enabled || self.connected || self.launched.is_some() {
+            return false;
+        }
+
+        self.launched = Some(now);
+        true
+    }
+
+    pub fn on_peer_connected(&mut self) {
+        self.connected = true;
+    }
+
+    /// Returns true if the stream should be cleaned up
+    pub fn check(&self, now: Instant) -> bool {
+        if !self.config.enabled || self.connected {
+            return false;
+        }
+
+        self.launched.is_some_and(|launched| {
+            now.saturating_duration_since(launched) >= self.config.connect_timeout
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use common::config::PeerWatchdogConfig;
+
+    use crate::peer_watchdog::PeerWatchdog;
+
+    fn watchdog() -> PeerWatchdog {
+        PeerWatchdog::new(PeerWatchdogConfig {
+            enabled: true,
+            connect_timeout: Duration::from_secs(30),
+            quit_app: true,
+        })
+    }
+
+    #[test]
+    fn test_peer_never_connects() {
+        let start = Instant::now();
+        let mut watchdog = watchdog();
+
+        // Nothing was launched yet
+        assert!(!watchdog.check(start + Duration::from_secs(60)));
+
+        assert!(watchdog.on_launched(start));
+        assert!(!watchdog.check(start + Duration::from_secs(29)));
+        assert!(watchdog.check(start + Duration::from_secs(30)));
+
+        // A restart doesn't reset the timeout
+        assert!(!watchdog.on_launched(start + Duration::from_secs(20)));
+        assert!(watchdog.check(start + Duration::from_secs(31)));
+    }
+
+    #[test]
+    fn test_peer_connects() {
+        let start = Instant::now();
+        let mut watchdog = watchdog();
+
+        assert!(watchdog.on_launched(start));
+        watchdog.on_peer_connected();
+        assert!(!watchdog.check(start + Duration::from_secs(60)));
+
+        // Reconnecting to the host doesn't need another peer
+        assert!(!watchdog.on_launched(start + Duration::from_secs(60)));
+    }
+}
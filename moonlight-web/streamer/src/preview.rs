@@ -0,0 +1,254 @@
+//! The low resolution preview track of the stream for [ServerIpcMessage::StartPreview](common::ipc::ServerIpcMessage::StartPreview).
+//!
+//! While the preview runs every frame is copied to a decoder thread, which sends a scaled down jpeg at the configured fps.
+//! Frames which arrive while the decoder is behind are dropped and the decoder waits for the next IDR frame.
+
+use std::{
+    sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use common::{config::SessionPreviewConfig, ipc::StreamerIpcMessage};
+use log::{debug, info, warn};
+use moonlight_common::stream::bindings::{FrameType, VideoDecodeUnit, VideoFormat};
+
+use crate::StreamConnection;
+
+/// The preview decodes with FFmpeg, which is only linked with the `screenshot` feature
+const PREVIEWS_SUPPORTED: bool = cfg!(feature = "screenshot");
+
+/// Frames which wait for the decoder thread
+const PREVIEW_QUEUE_SIZE: usize = 4;
+/// A decoder which can't keep up would otherwise request an IDR frame for every dropped frame
+const IDR_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+pub(crate) struct PreviewTrack {
+    config: SessionPreviewConfig,
+    /// Started with the first IDR frame, the video format is known by then
+    frames: Option<SyncSender<Vec<u8>>>,
+    waiting_for_idr: bool,
+    last_idr_request: Option<Instant>,
+}
+
+pub(crate) async fn start_preview(stream: &StreamConnection, config: SessionPreviewConfig) {
+    if !PREVIEWS_SUPPORTED {
+        warn!("[Preview]: the streamer was built without the screenshot feature");
+
+        stream
+            .ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::Preview { image: None })
+            .await;
+        return;
+    }
+
+    info!(
+        "[Preview]: starting the preview with a width of {}px at {}",
+        config.width, config.fps
+    );
+
+    let mut track = PreviewTrack {
+        config,
+        frames: None,
+        waiting_for_idr: true,
+        last_idr_request: None,
+    };
+    track.request_idr_frame(stream).await;
+
+    *stream.preview.lock().await = Some(track);
+}
+
+pub(crate) async fn stop_preview(stream: &StreamConnection) {
+    if stream.preview.lock().await.take().is_some() {
+        info!("[Preview]: stopped the preview");
+    }
+}
+
+/// Must be called with every frame, copies the frame to the decoder if the preview runs
+pub(crate) async fn push_preview_frame(stream: &StreamConnection, unit: &VideoDecodeUnit<'_>) {
+    let mut preview = stream.preview.lock().await;
+    let Some(track) = preview.as_mut() else {
+        return;
+    };
+
+    if !matches!(unit.frame_type, FrameType::Idr) {
+        if track.waiting_for_idr {
+            track.request_idr_frame(stream).await;
+            return;
+        }
+    } else {
+        track.waiting_for_idr = false;
+    }
+
+    if track.frames.is_none() {
+        let video_format = stream
+            .stream_setup
+            .lock()
+            .await
+            .video
+            .map(|setup| setup.format);
+        let Some(video_format) = video_format else {
+            warn!("[Preview]: received an IDR frame before the video was set up");
+            return;
+        };
+
+        track.frames = Some(spawn_decoder(stream, video_format, track.config.clone()));
+    }
+    let Some(frames) = track.frames.as_ref() else {
+        return;
+    };
+
+    let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+    let mut frame = Vec::with_capacity(frame_len);
+    for buffer in unit.buffers {
+        frame.extend_from_slice(buffer.data);
+    }
+
+    match frames.try_send(frame) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => {
+            debug!("[Preview]: the decoder is behind, dropping frames until the next IDR frame");
+
+            track.waiting_for_idr = true;
+            track.request_idr_frame(stream).await;
+        }
+        Err(TrySendError::Disconnected(_)) => {
+            // The decoder already told the web server that it failed
+            *preview = None;
+        }
+    }
+}
+
+impl PreviewTrack {
+    async fn request_idr_frame(&mut self, stream: &StreamConnection) {
+        let now = Instant::now();
+        if self
+            .last_idr_request
+            .is_some_and(|last_idr_request| now - last_idr_request < IDR_REQUEST_INTERVAL)
+        {
+            return;
+        }
+        self.last_idr_request = Some(now);
+
+        let moonlight_stream = stream.stream.read().await;
+        if let Some(moonlight_stream) = moonlight_stream.as_ref()
+            && let Err(err) = moonlight_stream.request_idr_frame()
+        {
+            warn!("[Preview]: failed to request an IDR frame: {err}");
+        }
+    }
+}
+
+/// The decoder stops once the sender is dropped
+fn spawn_decoder(
+    stream: &StreamConnection,
+    video_format: VideoFormat,
+    config: SessionPreviewConfig,
+) -> SyncSender<Vec<u8>> {
+    let (sender, frames) = sync_channel(PREVIEW_QUEUE_SIZE);
+
+    let runtime = stream.runtime.clone();
+    let ipc_sender = stream.ipc_sender.clone();
+
+    stream.runtime.spawn_blocking(move || {
+        let send_image = |image: Option<Bytes>| {
+            let mut ipc_sender = ipc_sender.clone();
+            runtime.spawn(async move {
+                ipc_sender.send(StreamerIpcMessage::Preview { image }).await;
+            });
+        };
+
+        if let Err(err) = decode_preview(video_format, &config, frames, |image| {
+            send_image(Some(image))
+        }) {
+            warn!("[Preview]: failed to decode the stream, stopping the preview: {err}");
+            send_image(None);
+        }
+    });
+
+    sender
+}
+
+/// The size of the preview frames, frames which are already small enough keep their size
+#[cfg_attr(not(feature = "screenshot"), allow(dead_code))]
+pub(crate) fn preview_size(width: u32, height: u32, max_width: u32) -> (u32, u32) {
+    if width <= max_width || width == 0 {
+        return (width, height);
+    }
+
+    let height = (height as u64 * max_width as u64 / width as u64) as u32;
+    (max_width, height.max(1))
+}
+
+#[cfg(feature = "screenshot")]
+fn decode_preview(
+    video_format: VideoFormat,
+    config: &SessionPreviewConfig,
+    frames: Receiver<Vec<u8>>,
+    mut on_image: impl FnMut(Bytes),
+) -> Result<(), crate::screenshot::decode::DecodeError> {
+    use moonlight_ffmpeg::{
+        ffmpeg::{Error, Packet, error::EAGAIN, frame},
+        video::open_video_decoder,
+    };
+
+    use crate::screenshot::decode::{encode_jpeg, scale_rgb};
+
+    moonlight_ffmpeg::init()?;
+
+    let frame_interval = Duration::from_secs(1) / config.fps.get().max(1);
+
+    let mut decoder = open_video_decoder(video_format)?;
+    let mut decoded = frame::Video::empty();
+    let mut last_image: Option<Instant> = None;
+
+    while let Ok(frame) = frames.recv() {
+        decoder.send_packet(&Packet::copy(&frame))?;
+
+        loop {
+            match decoder.receive_frame(&mut decoded) {
+                Ok(()) => {}
+                Err(Error::Other { errno }) if errno == EAGAIN => break,
+                Err(err) => return Err(err.into()),
+            }
+
+            if last_image.is_some_and(|last_image| last_image.elapsed() < frame_interval) {
+                continue;
+            }
+            last_image = Some(Instant::now());
+
+            let (width, height) = preview_size(decoded.width(), decoded.height(), config.width);
+            let image = scale_rgb(&decoded, width, height)?;
+
+            match encode_jpeg(&image, config.jpeg_quality) {
+                Ok(image) => on_image(image),
+                Err(err) => warn!("[Preview]: failed to encode a frame: {err}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "screenshot"))]
+fn decode_preview(
+    _video_format: VideoFormat,
+    _config: &SessionPreviewConfig,
+    _frames: Receiver<Vec<u8>>,
+    _on_image: impl FnMut(Bytes),
+) -> Result<(), std::convert::Infallible> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::preview::preview_size;
+
+    #[test]
+    fn test_preview_size_keeps_aspect_ratio() {
+        assert_eq!(preview_size(1920, 1080, 320), (320, 180));
+        assert_eq!(preview_size(3440, 1440, 320), (320, 133));
+        assert_eq!(preview_size(256, 144, 320), (256, 144));
+    }
+}
@@ -0,0 +1,100 @@
+//! Allocation counting for the `profiling` feature.
+//!
+//! Allocations are counted per thread, so only the work which happens on the thread itself is measured.
+//! Video decode units are sent while blocking on the moonlight decoder thread,
+//! which makes the count of a frame include everything the transport does until the frame is queued.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+thread_local! {
+    static THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // The thread local might already be destroyed when the thread exits
+    let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+/// The amount of allocations and reallocations on the current thread
+pub fn thread_allocation_count() -> usize {
+    THREAD_ALLOCATIONS.try_with(Cell::get).unwrap_or(0)
+}
+
+pub struct CountingAllocator;
+
+// SAFETY: every call is forwarded to the system allocator with the same arguments
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Logs the allocations per frame every second
+#[derive(Debug, Default)]
+pub(crate) struct FrameAllocations {
+    last_log: Option<Instant>,
+    frame_start: usize,
+    min_allocations: Option<usize>,
+    max_allocations: usize,
+    total_allocations: usize,
+    frame_count: usize,
+}
+
+impl FrameAllocations {
+    pub(crate) fn start_frame(&mut self) {
+        self.frame_start = thread_allocation_count();
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        let allocations = thread_allocation_count() - self.frame_start;
+
+        self.min_allocations = Some(
+            self.min_allocations
+                .map(|min_allocations| min_allocations.min(allocations))
+                .unwrap_or(allocations),
+        );
+        self.max_allocations = self.max_allocations.max(allocations);
+        self.total_allocations += allocations;
+        self.frame_count += 1;
+
+        if self
+            .last_log
+            .is_some_and(|last_log| last_log + Duration::from_secs(1) > Instant::now())
+        {
+            return;
+        }
+
+        info!(
+            "[Profiling]: video allocations per frame min/max/avg: {} / {} / {:.2}",
+            self.min_allocations.unwrap_or(0),
+            self.max_allocations,
+            self.total_allocations as f64 / self.frame_count as f64
+        );
+
+        *self = Self {
+            last_log: Some(Instant::now()),
+            ..Default::default()
+        };
+    }
+}
@@ -0,0 +1,156 @@
+//! Rates the connection quality from 1 (bad) to 5 (excellent), so that every client shows the same rating.
+
+use std::time::Duration;
+
+/// Upper bounds for the ratings 5, 4, 3 and 2, everything above is rated 1
+const RTT_MS_THRESHOLDS: [f64; 4] = [30.0, 60.0, 100.0, 200.0];
+const JITTER_MS_THRESHOLDS: [f64; 4] = [5.0, 10.0, 20.0, 40.0];
+const NACK_RATE_THRESHOLDS: [f64; 4] = [0.005, 0.01, 0.03, 0.1];
+const FRAME_DROP_RATE_THRESHOLDS: [f64; 4] = [0.005, 0.01, 0.03, 0.1];
+
+/// Counters of the video packets since the transport was created
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VideoPacketCounters {
+    pub packets_sent: u64,
+    /// Packets which the client requested again because they were lost
+    pub nack_count: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QualitySample {
+    pub rtt: Duration,
+    pub rtt_variance: Duration,
+    /// None if the transport doesn't lose packets
+    pub packets: Option<VideoPacketCounters>,
+    /// Frames since the last sample
+    pub frames: u32,
+    /// Frames which the host sent but never arrived since the last sample
+    pub dropped_frames: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct QualityMonitor {
+    last_packets: Option<VideoPacketCounters>,
+    last_score: Option<u8>,
+}
+
+impl QualityMonitor {
+    /// Returns the score and if it changed since the last sample
+    pub fn update(&mut self, sample: QualitySample) -> (u8, bool) {
+        let nack_rate = match (sample.packets, self.last_packets) {
+            (Some(packets), Some(last_packets)) => {
+                let sent = packets
+                    .packets_sent
+                    .saturating_sub(last_packets.packets_sent);
+                let nacks = packets.nack_count.saturating_sub(last_packets.nack_count);
+
+                rate(nacks, sent)
+            }
+            _ => 0.0,
+        };
+        self.last_packets = sample.packets;
+
+        let total_frames = sample.frames as u64 + sample.dropped_frames as u64;
+        let frame_drop_rate = rate(sample.dropped_frames as u64, total_frames);
+
+        let score = [
+            rating(sample.rtt.as_secs_f64() * 1000.0, RTT_MS_THRESHOLDS),
+            rating(
+                sample.rtt_variance.as_secs_f64() * 1000.0,
+                JITTER_MS_THRESHOLDS,
+            ),
+            rating(nack_rate, NACK_RATE_THRESHOLDS),
+            rating(frame_drop_rate, FRAME_DROP_RATE_THRESHOLDS),
+        ]
+        .into_iter()
+        .min()
+        .unwrap_or(1);
+
+        let changed = self.last_score != Some(score);
+        self.last_score = Some(score);
+
+        (score, changed)
+    }
+}
+
+fn rate(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+fn rating(value: f64, thresholds: [f64; 4]) -> u8 {
+    let worse_thresholds = thresholds
+        .iter()
+        .filter(|threshold| value >= **threshold)
+        .count();
+
+    5 - worse_thresholds as u8
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::quality::{
+        QualityMonitor, QualitySample, RTT_MS_THRESHOLDS, VideoPacketCounters, rating,
+    };
+
+    fn sample(rtt_ms: u64, packets: Option<VideoPacketCounters>) -> QualitySample {
+        QualitySample {
+            rtt: Duration::from_millis(rtt_ms),
+            rtt_variance: Duration::from_millis(1),
+            packets,
+            frames: 60,
+            dropped_frames: 0,
+        }
+    }
+
+    #[test]
+    fn test_rating() {
+        assert_eq!(rating(10.0, RTT_MS_THRESHOLDS), 5);
+        assert_eq!(rating(30.0, RTT_MS_THRESHOLDS), 4);
+        assert_eq!(rating(80.0, RTT_MS_THRESHOLDS), 3);
+        assert_eq!(rating(150.0, RTT_MS_THRESHOLDS), 2);
+        assert_eq!(rating(500.0, RTT_MS_THRESHOLDS), 1);
+    }
+
+    #[test]
+    fn test_worst_metric_decides() {
+        let mut monitor = QualityMonitor::default();
+
+        assert_eq!(monitor.update(sample(10, None)), (5, true));
+        assert_eq!(monitor.update(sample(10, None)), (5, false));
+
+        let mut dropping = sample(10, None);
+        dropping.frames = 50;
+        dropping.dropped_frames = 10;
+        assert_eq!(monitor.update(dropping), (1, true));
+    }
+
+    #[test]
+    fn test_nack_rate_between_samples() {
+        let mut monitor = QualityMonitor::default();
+
+        // The counters are cumulative, the first sample has nothing to compare with
+        let (score, _) = monitor.update(sample(
+            10,
+            Some(VideoPacketCounters {
+                packets_sent: 1000,
+                nack_count: 500,
+            }),
+        ));
+        assert_eq!(score, 5);
+
+        let (score, _) = monitor.update(sample(
+            10,
+            Some(VideoPacketCounters {
+                packets_sent: 2000,
+                nack_count: 520,
+            }),
+        ));
+        assert_eq!(score, 3);
+    }
+}
@@ -0,0 +1,196 @@
+//! Changes the bitrate, resolution and fps of a running stream, see [StreamClientMessage::SetQuality](common::api_bindings::StreamClientMessage::SetQuality).
+//!
+//! Hosts only apply new encoder settings when the stream is launched, so the stream is restarted with them.
+//! The restart waits for the next IDR frame, so the client decoded a complete picture before the video freezes.
+//! Afterwards frames are dropped until the restarted stream sent its first IDR frame.
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use moonlight_common::units::{Fps, Kbps};
+
+use crate::StreamConnection;
+
+/// The host might not send the requested IDR frame, the stream is switched anyway after this
+const IDR_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamQuality {
+    pub bitrate: Kbps,
+    pub fps: Fps,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SwitchState {
+    #[default]
+    Streaming,
+    /// The old stream is being stopped
+    Restarting,
+    /// The restarted stream is set up, but didn't send an IDR frame yet
+    WaitingForIdr,
+}
+
+pub(crate) enum FrameAction {
+    Forward,
+    Drop,
+    /// Forward this frame and restart the stream with the quality
+    Switch(StreamQuality),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct QualitySwitch {
+    /// The latest requested quality and when it was first requested
+    pending: Option<(StreamQuality, Instant)>,
+    state: SwitchState,
+}
+
+impl QualitySwitch {
+    fn request(&mut self, quality: StreamQuality, now: Instant) {
+        let requested_at = self
+            .pending
+            .map(|(_, requested_at)| requested_at)
+            .unwrap_or(now);
+
+        self.pending = Some((quality, requested_at));
+    }
+
+    /// Must be called with every frame before it's sent anywhere
+    pub fn on_frame(&mut self, keyframe: bool, now: Instant) -> FrameAction {
+        match self.state {
+            SwitchState::Streaming => {}
+            SwitchState::Restarting => return FrameAction::Drop,
+            SwitchState::WaitingForIdr if keyframe => self.state = SwitchState::Streaming,
+            SwitchState::WaitingForIdr => return FrameAction::Drop,
+        }
+
+        if let Some((quality, requested_at)) = self.pending
+            && (keyframe || now - requested_at >= IDR_TIMEOUT)
+        {
+            self.pending = None;
+            self.state = SwitchState::Restarting;
+
+            return FrameAction::Switch(quality);
+        }
+
+        FrameAction::Forward
+    }
+
+    /// Must be called when the video of a stream is set up, the frames after it belong to the restarted stream
+    pub fn on_video_setup(&mut self) {
+        if self.state == SwitchState::Restarting {
+            self.state = SwitchState::WaitingForIdr;
+        }
+    }
+}
+
+/// Switches the quality with the next IDR frame
+pub(crate) async fn request_quality_change(stream: &StreamConnection, quality: StreamQuality) {
+    {
+        let settings = stream.stream_settings.lock().await;
+        let Some(settings) = settings.as_ref() else {
+            warn!("Received a quality change but the stream wasn't started");
+            return;
+        };
+
+        let current = StreamQuality {
+            bitrate: settings.bitrate,
+            fps: settings.fps,
+            width: settings.width,
+            height: settings.height,
+        };
+        if current == quality {
+            return;
+        }
+    }
+
+    info!(
+        "Changing the quality to {}x{}x{} with {} at the next IDR frame",
+        quality.width, quality.height, quality.fps, quality.bitrate
+    );
+
+    stream
+        .quality_switch
+        .lock()
+        .await
+        .request(quality, Instant::now());
+
+    let moonlight_stream = stream.stream.read().await;
+    if let Some(moonlight_stream) = moonlight_stream.as_ref()
+        && let Err(err) = moonlight_stream.request_idr_frame()
+    {
+        warn!("Failed to request an IDR frame for the quality change: {err}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use moonlight_common::units::{Fps, Kbps};
+
+    use crate::quality_switch::{FrameAction, QualitySwitch, StreamQuality};
+
+    const QUALITY: StreamQuality = StreamQuality {
+        bitrate: Kbps(10000),
+        fps: Fps(60),
+        width: 1280,
+        height: 720,
+    };
+
+    #[test]
+    fn test_switches_at_keyframe() {
+        let now = Instant::now();
+        let mut switch = QualitySwitch::default();
+
+        switch.request(QUALITY, now);
+        assert!(matches!(switch.on_frame(false, now), FrameAction::Forward));
+        assert!(matches!(
+            switch.on_frame(true, now),
+            FrameAction::Switch(QUALITY)
+        ));
+
+        // Frames of the old stream and the restarted stream before its first IDR frame
+        assert!(matches!(switch.on_frame(false, now), FrameAction::Drop));
+        switch.on_video_setup();
+        assert!(matches!(switch.on_frame(false, now), FrameAction::Drop));
+        assert!(matches!(switch.on_frame(true, now), FrameAction::Forward));
+        assert!(matches!(switch.on_frame(false, now), FrameAction::Forward));
+    }
+
+    #[test]
+    fn test_switches_without_keyframe_after_timeout() {
+        let now = Instant::now();
+        let mut switch = QualitySwitch::default();
+
+        switch.request(QUALITY, now);
+        assert!(matches!(
+            switch.on_frame(false, now + Duration::from_secs(1)),
+            FrameAction::Forward
+        ));
+        assert!(matches!(
+            switch.on_frame(false, now + Duration::from_secs(3)),
+            FrameAction::Switch(QUALITY)
+        ));
+    }
+
+    #[test]
+    fn test_newest_request_wins() {
+        let now = Instant::now();
+        let mut switch = QualitySwitch::default();
+
+        let lower = StreamQuality {
+            bitrate: Kbps(5000),
+            ..QUALITY
+        };
+        switch.request(QUALITY, now);
+        switch.request(lower, now + Duration::from_secs(1));
+
+        // The timeout counts from the first request
+        assert!(matches!(
+            switch.on_frame(false, now + Duration::from_secs(2)),
+            FrameAction::Switch(quality) if quality == lower
+        ));
+    }
+}
@@ -0,0 +1,547 @@
+//! Republishes the stream as an rtsp server for devices in the local network, see [RtspOutputConfig].
+//!
+//! Every frame is packetized once and all clients receive the same rtp packets interleaved into their rtsp connection.
+//! A client which can't keep up misses packets and an IDR frame is requested, so it recovers with the next keyframe.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, Cursor, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, Weak, atomic::AtomicUsize},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use common::{config::RtspOutputConfig, ipc::StreamerIpcMessage};
+use log::{debug, info, warn};
+use moonlight_common::stream::bindings::{OpusMultistreamConfig, VideoDecodeUnit, VideoFormat};
+use tokio::{net::TcpListener, spawn, sync::broadcast, task::JoinHandle};
+use webrtc::{
+    rtp::{header::Header, packet::Packet},
+    util::marshal::Marshal,
+};
+
+use crate::{
+    StreamConnection,
+    transport::webrtc::video::{
+        annexb::AnnexBSplitter, h264::payloader::H264Payloader, h265::payloader::H265Payloader,
+        packetize, trim_bytes_to_range,
+    },
+};
+
+mod server;
+
+/// Packets which wait for the slowest client
+const PACKET_QUEUE_SIZE: usize = 1024;
+/// Clients on the local network don't need small packets, but some reject packets above the usual mtu
+const RTP_MTU: usize = 1400;
+/// Further streams which run at the same time try the next ports
+const MAX_PORT_ATTEMPTS: u16 = 16;
+/// Lagging clients would otherwise request an IDR frame for every missed packet
+const IDR_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+const VIDEO_CLOCK_RATE: f64 = 90000.0;
+const VIDEO_PAYLOAD_TYPE: u8 = 96;
+const AUDIO_PAYLOAD_TYPE: u8 = 111;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RtspTrack {
+    Video = 0,
+    Audio = 1,
+}
+
+impl RtspTrack {
+    fn control(self) -> &'static str {
+        match self {
+            Self::Video => "track0",
+            Self::Audio => "track1",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RtpPacket {
+    track: RtspTrack,
+    data: Bytes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RtspVideoCodec {
+    H264,
+    H265,
+}
+
+impl RtspVideoCodec {
+    fn from_format(format: VideoFormat) -> Option<Self> {
+        match format {
+            VideoFormat::H264 | VideoFormat::H264High8_444 => Some(Self::H264),
+            VideoFormat::H265
+            | VideoFormat::H265Main10
+            | VideoFormat::H265Rext8_444
+            | VideoFormat::H265Rext10_444 => Some(Self::H265),
+            // Hardly any rtsp client plays av1
+            VideoFormat::Av1Main8
+            | VideoFormat::Av1Main10
+            | VideoFormat::Av1High8_444
+            | VideoFormat::Av1High10_444 => None,
+        }
+    }
+}
+
+/// What the clients get described, known once the stream started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RtspMedia {
+    video: Option<RtspVideoCodec>,
+    /// Only stereo opus has a standard rtp payload format
+    stereo_audio: bool,
+}
+
+/// Shared with the connections of the clients
+struct RtspShared {
+    media: Mutex<Option<RtspMedia>>,
+    packets: broadcast::Sender<RtpPacket>,
+    clients: AtomicUsize,
+    max_clients: usize,
+    last_idr_request: Mutex<Option<Instant>>,
+    stream: Weak<StreamConnection>,
+}
+
+impl RtspShared {
+    fn media(&self) -> Option<RtspMedia> {
+        self.media.lock().ok().and_then(|media| *media)
+    }
+
+    async fn request_idr_frame(&self) {
+        {
+            let Ok(mut last_idr_request) = self.last_idr_request.lock() else {
+                return;
+            };
+
+            let now = Instant::now();
+            if last_idr_request.is_some_and(|last| now - last < IDR_REQUEST_INTERVAL) {
+                return;
+            }
+            *last_idr_request = Some(now);
+        }
+
+        let Some(stream) = self.stream.upgrade() else {
+            return;
+        };
+
+        let moonlight_stream = stream.stream.read().await;
+        if let Some(moonlight_stream) = moonlight_stream.as_ref()
+            && let Err(err) = moonlight_stream.request_idr_frame()
+        {
+            warn!("[RTSP]: failed to request an IDR frame: {err}");
+        }
+    }
+}
+
+/// Numbers the rtp packets of one track
+struct RtpSequence {
+    payload_type: u8,
+    ssrc: u32,
+    sequence_number: u16,
+}
+
+impl RtpSequence {
+    fn new(payload_type: u8) -> Self {
+        Self {
+            payload_type,
+            ssrc: random_u32(),
+            sequence_number: random_u32() as u16,
+        }
+    }
+
+    fn marshal(&mut self, mut packet: Packet) -> Option<Bytes> {
+        packet.header.payload_type = self.payload_type;
+        packet.header.ssrc = self.ssrc;
+        packet.header.sequence_number = self.sequence_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+
+        match packet.marshal() {
+            Ok(data) => Some(data),
+            Err(err) => {
+                warn!("[RTSP]: failed to serialize an rtp packet: {err}");
+                None
+            }
+        }
+    }
+}
+
+enum VideoPayloader {
+    H264(H264Payloader),
+    H265(H265Payloader),
+}
+
+struct VideoPacketizer {
+    annex_b: AnnexBSplitter<Cursor<Vec<u8>>>,
+    payloader: VideoPayloader,
+    sequence: RtpSequence,
+}
+
+impl VideoPacketizer {
+    fn new(codec: RtspVideoCodec) -> Self {
+        Self {
+            annex_b: AnnexBSplitter::new(Cursor::new(Vec::new()), 0),
+            payloader: match codec {
+                RtspVideoCodec::H264 => VideoPayloader::H264(Default::default()),
+                RtspVideoCodec::H265 => VideoPayloader::H265(Default::default()),
+            },
+            sequence: RtpSequence::new(VIDEO_PAYLOAD_TYPE),
+        }
+    }
+
+    fn packetize(&mut self, frame: Vec<u8>, timestamp: u32) -> Vec<Bytes> {
+        self.annex_b.reset(Cursor::new(frame));
+
+        let mut nals = Vec::new();
+        while let Ok(Some(nal)) = self.annex_b.next() {
+            nals.push(trim_bytes_to_range(nal.full, nal.payload_range).freeze());
+        }
+
+        let mut packets = Vec::new();
+        let nal_count = nals.len();
+        for (index, nal) in nals.iter().enumerate() {
+            let last = index + 1 == nal_count;
+
+            let result = match &mut self.payloader {
+                VideoPayloader::H264(payloader) => {
+                    packetize(payloader, RTP_MTU, 0, timestamp, nal, last)
+                }
+                VideoPayloader::H265(payloader) => {
+                    packetize(payloader, RTP_MTU, 0, timestamp, nal, last)
+                }
+            };
+
+            match result {
+                Ok(nal_packets) => packets.extend(
+                    nal_packets
+                        .into_iter()
+                        .filter_map(|packet| self.sequence.marshal(packet)),
+                ),
+                Err(err) => warn!("[RTSP]: failed to packetize a frame: {err}"),
+            }
+        }
+
+        packets
+    }
+}
+
+struct AudioPacketizer {
+    sequence: RtpSequence,
+    timestamp: u32,
+    samples_per_frame: u32,
+}
+
+impl AudioPacketizer {
+    fn new(config: &OpusMultistreamConfig) -> Self {
+        Self {
+            sequence: RtpSequence::new(AUDIO_PAYLOAD_TYPE),
+            timestamp: random_u32(),
+            samples_per_frame: config.samples_per_frame,
+        }
+    }
+
+    fn packetize(&mut self, sample: &[u8]) -> Option<Bytes> {
+        let packet = Packet {
+            header: Header {
+                version: 2,
+                timestamp: self.timestamp,
+                ..Default::default()
+            },
+            payload: Bytes::copy_from_slice(sample),
+        };
+        self.timestamp = self.timestamp.wrapping_add(self.samples_per_frame);
+
+        self.sequence.marshal(packet)
+    }
+}
+
+pub(crate) struct RtspOutput {
+    port: u16,
+    shared: Arc<RtspShared>,
+    video: Option<VideoPacketizer>,
+    audio: Option<AudioPacketizer>,
+    accept_task: JoinHandle<()>,
+}
+
+impl RtspOutput {
+    async fn bind(
+        config: &RtspOutputConfig,
+        stream: Weak<StreamConnection>,
+    ) -> Result<Self, io::Error> {
+        let listener = bind_listener(config.bind_address).await?;
+        let port = listener.local_addr()?.port();
+
+        let (packets, _) = broadcast::channel(PACKET_QUEUE_SIZE);
+        let shared = Arc::new(RtspShared {
+            media: Mutex::new(None),
+            packets,
+            clients: AtomicUsize::new(0),
+            max_clients: config.max_clients,
+            last_idr_request: Mutex::new(None),
+            stream,
+        });
+
+        let accept_task = spawn(server::accept_clients(listener, shared.clone()));
+
+        Ok(Self {
+            port,
+            shared,
+            video: None,
+            audio: None,
+            accept_task,
+        })
+    }
+
+    fn set_media(&mut self, video_format: VideoFormat, audio: &OpusMultistreamConfig) {
+        let video = RtspVideoCodec::from_format(video_format);
+        if video.is_none() {
+            warn!(
+                "[RTSP]: the video format {video_format:?} isn't supported, only the audio is sent"
+            );
+        }
+
+        let stereo_audio = audio.channel_count == 2 && audio.streams == 1;
+        if !stereo_audio {
+            warn!(
+                "[RTSP]: only stereo audio is supported, the stream has {} channels",
+                audio.channel_count
+            );
+        }
+
+        self.video = video.map(VideoPacketizer::new);
+        self.audio = stereo_audio.then(|| AudioPacketizer::new(audio));
+
+        if let Ok(mut media) = self.shared.media.lock() {
+            *media = Some(RtspMedia {
+                video,
+                stereo_audio,
+            });
+        }
+    }
+
+    fn has_clients(&self) -> bool {
+        self.shared.packets.receiver_count() > 0
+    }
+
+    fn send(&self, track: RtspTrack, data: Bytes) {
+        // Only fails without clients
+        let _ = self.shared.packets.send(RtpPacket { track, data });
+    }
+}
+
+impl Drop for RtspOutput {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+async fn bind_listener(address: SocketAddr) -> Result<TcpListener, io::Error> {
+    if address.port() == 0 {
+        return TcpListener::bind(address).await;
+    }
+
+    let mut last_err = None;
+    for offset in 0..MAX_PORT_ATTEMPTS {
+        let Some(port) = address.port().checked_add(offset) else {
+            break;
+        };
+
+        match TcpListener::bind(SocketAddr::new(address.ip(), port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(err) if err.kind() == ErrorKind::AddrInUse => {
+                debug!("[RTSP]: port {port} is already in use, trying the next port");
+                last_err = Some(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| io::Error::from(ErrorKind::AddrInUse)))
+}
+
+/// Starts the rtsp output once the stream started, a restarted stream keeps the server and its clients
+pub(crate) async fn start_rtsp_output(
+    stream: &Arc<StreamConnection>,
+    video_format: VideoFormat,
+    audio: &OpusMultistreamConfig,
+) {
+    let config = &stream.config.rtsp_output;
+    if !config.enabled {
+        return;
+    }
+
+    let mut output = stream.rtsp_output.lock().await;
+    if output.is_none() {
+        match RtspOutput::bind(config, Arc::downgrade(stream)).await {
+            Ok(new_output) => {
+                info!(
+                    "[RTSP]: serving the stream at rtsp://{}/",
+                    SocketAddr::new(config.bind_address.ip(), new_output.port)
+                );
+
+                stream
+                    .ipc_sender
+                    .clone()
+                    .send(StreamerIpcMessage::RtspOutput {
+                        port: new_output.port,
+                    })
+                    .await;
+
+                *output = Some(new_output);
+            }
+            Err(err) => {
+                warn!("[RTSP]: failed to listen on {}: {err}", config.bind_address);
+                return;
+            }
+        }
+    }
+
+    if let Some(output) = output.as_mut() {
+        output.set_media(video_format, audio);
+    }
+}
+
+pub(crate) async fn stop_rtsp_output(stream: &StreamConnection) {
+    if stream.rtsp_output.lock().await.take().is_some() {
+        info!("[RTSP]: stopped the rtsp output");
+    }
+}
+
+/// Must be called with every frame
+pub(crate) async fn push_rtsp_video(stream: &StreamConnection, unit: &VideoDecodeUnit<'_>) {
+    let mut output = stream.rtsp_output.lock().await;
+    let Some(output) = output.as_mut() else {
+        return;
+    };
+    if !output.has_clients() {
+        return;
+    }
+    let Some(video) = output.video.as_mut() else {
+        return;
+    };
+
+    let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+    let mut frame = Vec::with_capacity(frame_len);
+    for buffer in unit.buffers {
+        frame.extend_from_slice(buffer.data);
+    }
+
+    let timestamp = (unit.presentation_time.as_secs_f64() * VIDEO_CLOCK_RATE) as u32;
+    let packets = video.packetize(frame, timestamp);
+
+    for packet in packets {
+        output.send(RtspTrack::Video, packet);
+    }
+}
+
+/// Must be called with every audio sample
+pub(crate) async fn push_rtsp_audio(stream: &StreamConnection, sample: &[u8]) {
+    let mut output = stream.rtsp_output.lock().await;
+    let Some(output) = output.as_mut() else {
+        return;
+    };
+    if !output.has_clients() {
+        return;
+    }
+    let Some(audio) = output.audio.as_mut() else {
+        return;
+    };
+
+    if let Some(packet) = audio.packetize(sample) {
+        output.send(RtspTrack::Audio, packet);
+    }
+}
+
+/// The session description of the stream for DESCRIBE requests
+fn session_description(media: &RtspMedia, address: IpAddr) -> String {
+    let address_type = match address {
+        IpAddr::V4(_) => "IP4",
+        IpAddr::V6(_) => "IP6",
+    };
+
+    let mut sdp = format!(
+        "v=0\r\n\
+        o=- 0 0 IN {address_type} {address}\r\n\
+        s=Moonlight Web\r\n\
+        c=IN {address_type} {address}\r\n\
+        t=0 0\r\n\
+        a=control:*\r\n"
+    );
+
+    if let Some(codec) = media.video {
+        let encoding = match codec {
+            RtspVideoCodec::H264 => "H264",
+            RtspVideoCodec::H265 => "H265",
+        };
+
+        sdp.push_str(&format!(
+            "m=video 0 RTP/AVP {VIDEO_PAYLOAD_TYPE}\r\n\
+            a=rtpmap:{VIDEO_PAYLOAD_TYPE} {encoding}/90000\r\n"
+        ));
+        if codec == RtspVideoCodec::H264 {
+            sdp.push_str(&format!(
+                "a=fmtp:{VIDEO_PAYLOAD_TYPE} packetization-mode=1\r\n"
+            ));
+        }
+        sdp.push_str(&format!("a=control:{}\r\n", RtspTrack::Video.control()));
+    }
+
+    if media.stereo_audio {
+        sdp.push_str(&format!(
+            "m=audio 0 RTP/AVP {AUDIO_PAYLOAD_TYPE}\r\n\
+            a=rtpmap:{AUDIO_PAYLOAD_TYPE} opus/48000/2\r\n\
+            a=control:{}\r\n",
+            RtspTrack::Audio.control()
+        ));
+    }
+
+    sdp
+}
+
+/// The ssrcs and first sequence numbers should be random, the standard library's hasher keys are
+fn random_u32() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::rtsp::{RtspMedia, RtspVideoCodec, session_description};
+
+    #[test]
+    fn test_session_description() {
+        let sdp = session_description(
+            &RtspMedia {
+                video: Some(RtspVideoCodec::H264),
+                stereo_audio: true,
+            },
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+        );
+
+        assert!(sdp.starts_with("v=0\r\n"));
+        assert!(sdp.contains("c=IN IP4 192.168.1.2\r\n"));
+        assert!(sdp.contains("m=video 0 RTP/AVP 96\r\na=rtpmap:96 H264/90000\r\n"));
+        assert!(sdp.contains("a=control:track0\r\n"));
+        assert!(sdp.contains("a=rtpmap:111 opus/48000/2\r\na=control:track1\r\n"));
+    }
+
+    #[test]
+    fn test_session_description_without_audio() {
+        let sdp = session_description(
+            &RtspMedia {
+                video: Some(RtspVideoCodec::H265),
+                stereo_audio: false,
+            },
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+        );
+
+        assert!(sdp.contains("a=rtpmap:96 H265/90000\r\n"));
+        assert!(!sdp.contains("packetization-mode"));
+        assert!(!sdp.contains("m=audio"));
+    }
+}
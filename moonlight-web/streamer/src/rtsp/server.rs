@@ -0,0 +1,505 @@
+//! A minimal rtsp 1.0 server, see RFC 2326.
+//! The rtp packets are always interleaved into the rtsp connection, which every common client supports.
+
+use std::{
+    io::{self, ErrorKind},
+    net::IpAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::{BufMut, BytesMut};
+use log::{debug, info, warn};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, tcp::OwnedWriteHalf},
+    spawn,
+    sync::{Mutex, broadcast::error::RecvError},
+    task::JoinHandle,
+    time::sleep,
+};
+
+use crate::rtsp::{RtspShared, RtspTrack, random_u32, session_description};
+
+/// Requests are only a few headers, anything larger isn't a client we know
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+/// Clients keep the session alive with GET_PARAMETER requests within this
+const SESSION_TIMEOUT_SECS: u32 = 60;
+const PUBLIC_METHODS: &str = "OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER";
+
+/// The client limit is counted by these
+struct ClientSlot {
+    shared: Arc<RtspShared>,
+}
+
+impl ClientSlot {
+    fn acquire(shared: &Arc<RtspShared>) -> Option<Self> {
+        shared
+            .clients
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |clients| {
+                (clients < shared.max_clients).then_some(clients + 1)
+            })
+            .ok()?;
+
+        Some(Self {
+            shared: shared.clone(),
+        })
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.shared.clients.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub(super) async fn accept_clients(listener: TcpListener, shared: Arc<RtspShared>) {
+    loop {
+        let (socket, address) = match listener.accept().await {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("[RTSP]: failed to accept a client: {err}");
+                // e.g. no file descriptors are left
+                sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let Some(slot) = ClientSlot::acquire(&shared) else {
+            info!(
+                "[RTSP]: rejected {address}, already {} clients are watching",
+                shared.max_clients
+            );
+            continue;
+        };
+
+        info!("[RTSP]: {address} connected");
+
+        spawn(async move {
+            if let Err(err) = serve_client(socket, &slot.shared).await {
+                debug!("[RTSP]: the connection to {address} failed: {err}");
+            }
+
+            info!("[RTSP]: {address} disconnected");
+        });
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct RtspRequest {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+}
+
+impl RtspRequest {
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.lines();
+
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let uri = request_line.next()?.to_string();
+        if !request_line.next()?.starts_with("RTSP/1.") {
+            return None;
+        }
+
+        let headers = lines
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect();
+
+        Some(Self {
+            method,
+            uri,
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The track of a SETUP request, clients append the control attribute of the sdp to the uri
+    fn track(&self) -> Option<RtspTrack> {
+        let control = self.uri.trim_end_matches('/').rsplit('/').next()?;
+
+        [RtspTrack::Video, RtspTrack::Audio]
+            .into_iter()
+            .find(|track| track.control() == control)
+    }
+}
+
+/// The interleaved channels of the rtp packets, from the Transport header of a SETUP request.
+/// None if the client only accepts udp.
+fn interleaved_channel(transport: &str, track: RtspTrack) -> Option<u8> {
+    let transport = transport
+        .split(',')
+        .find(|transport| transport.trim().starts_with("RTP/AVP/TCP"))?;
+
+    let requested = transport.split(';').find_map(|parameter| {
+        let (rtp, _rtcp) = parameter
+            .trim()
+            .strip_prefix("interleaved=")?
+            .split_once('-')?;
+        rtp.parse::<u8>().ok()
+    });
+
+    Some(requested.unwrap_or(track as u8 * 2))
+}
+
+struct RtspResponse {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl RtspResponse {
+    fn new(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+    fn ok() -> Self {
+        Self::new(200, "OK")
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn serialize(&self, cseq: Option<&str>) -> String {
+        let mut response = format!("RTSP/1.0 {} {}\r\n", self.status, self.reason);
+        if let Some(cseq) = cseq {
+            response.push_str(&format!("CSeq: {cseq}\r\n"));
+        }
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !self.body.is_empty() {
+            response.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        response.push_str("\r\n");
+        response.push_str(&self.body);
+
+        response
+    }
+}
+
+static NEXT_SESSION_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The state of one rtsp connection
+struct ClientSession {
+    id: String,
+    /// The interleaved channel of every track which was set up
+    channels: [Option<u8>; 2],
+    playing: Option<JoinHandle<()>>,
+}
+
+impl ClientSession {
+    fn new() -> Self {
+        let counter = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            id: format!("{:08X}{counter:04X}", random_u32()),
+            channels: [None; 2],
+            playing: None,
+        }
+    }
+
+    fn session_header(&self) -> String {
+        format!("{};timeout={SESSION_TIMEOUT_SECS}", self.id)
+    }
+
+    fn stop_playing(&mut self) {
+        if let Some(playing) = self.playing.take() {
+            playing.abort();
+        }
+    }
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        self.stop_playing();
+    }
+}
+
+async fn serve_client(socket: TcpStream, shared: &Arc<RtspShared>) -> Result<(), io::Error> {
+    socket.set_nodelay(true)?;
+    let local_address = socket.local_addr()?.ip();
+
+    let (read, write) = socket.into_split();
+    let mut reader = BufReader::new(read);
+    let writer = Arc::new(Mutex::new(write));
+
+    let mut session = ClientSession::new();
+
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        // The receiver reports of the client aren't needed
+        if buffer[0] == b'$' {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header).await?;
+            let len = u16::from_be_bytes([header[2], header[3]]);
+            skip(&mut reader, len as usize).await?;
+            continue;
+        }
+
+        let Some(request) = read_request(&mut reader).await? else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "received an invalid request",
+            ));
+        };
+        debug!("[RTSP]: received {} {}", request.method, request.uri);
+
+        let response = handle_request(&request, &mut session, shared, local_address, &writer);
+
+        writer
+            .lock()
+            .await
+            .write_all(response.serialize(request.header("CSeq")).as_bytes())
+            .await?;
+
+        if request.method == "TEARDOWN" {
+            return Ok(());
+        }
+    }
+}
+
+async fn read_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> Result<Option<RtspRequest>, io::Error> {
+    let mut head = String::new();
+
+    loop {
+        let len = reader.read_line(&mut head).await?;
+        if len == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        if head.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+
+        if head.ends_with("\r\n\r\n") || head.ends_with("\n\n") {
+            break;
+        }
+        // Some clients send empty lines between requests
+        if head.trim().is_empty() {
+            head.clear();
+        }
+    }
+
+    let Some(request) = RtspRequest::parse(&head) else {
+        return Ok(None);
+    };
+
+    // e.g. the parameters of GET_PARAMETER, none of them are supported
+    let content_length = request
+        .header("Content-Length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_REQUEST_SIZE {
+        return Ok(None);
+    }
+    skip(reader, content_length).await?;
+
+    Ok(Some(request))
+}
+
+async fn skip(reader: &mut (impl AsyncBufRead + Unpin), len: usize) -> Result<(), io::Error> {
+    let skipped = tokio::io::copy(&mut reader.take(len as u64), &mut tokio::io::sink()).await?;
+    if skipped < len as u64 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+fn handle_request(
+    request: &RtspRequest,
+    session: &mut ClientSession,
+    shared: &Arc<RtspShared>,
+    local_address: IpAddr,
+    writer: &Arc<Mutex<OwnedWriteHalf>>,
+) -> RtspResponse {
+    match request.method.as_str() {
+        "OPTIONS" => RtspResponse::ok().header("Public", PUBLIC_METHODS),
+        "DESCRIBE" => {
+            let Some(media) = shared.media() else {
+                return RtspResponse::new(503, "Service Unavailable").header("Retry-After", "5");
+            };
+
+            let mut response = RtspResponse::ok()
+                .header("Content-Type", "application/sdp")
+                .header(
+                    "Content-Base",
+                    format!("{}/", request.uri.trim_end_matches('/')),
+                );
+            response.body = session_description(&media, local_address);
+            response
+        }
+        "SETUP" => {
+            let Some(track) = request.track() else {
+                return RtspResponse::new(404, "Not Found");
+            };
+
+            let Some(channel) = request
+                .header("Transport")
+                .and_then(|transport| interleaved_channel(transport, track))
+            else {
+                return RtspResponse::new(461, "Unsupported Transport");
+            };
+
+            session.channels[track as usize] = Some(channel);
+
+            RtspResponse::ok()
+                .header(
+                    "Transport",
+                    format!(
+                        "RTP/AVP/TCP;unicast;interleaved={channel}-{}",
+                        channel.wrapping_add(1)
+                    ),
+                )
+                .header("Session", session.session_header())
+        }
+        "PLAY" => {
+            if session.channels.iter().all(Option::is_none) {
+                return RtspResponse::new(455, "Method Not Valid in This State");
+            }
+
+            if session.playing.is_none() {
+                session.playing = Some(forward_packets(
+                    shared.clone(),
+                    session.channels,
+                    writer.clone(),
+                ));
+            }
+
+            RtspResponse::ok()
+                .header("Range", "npt=0.000-")
+                .header("Session", session.session_header())
+        }
+        "PAUSE" => {
+            session.stop_playing();
+
+            RtspResponse::ok().header("Session", session.session_header())
+        }
+        "TEARDOWN" => {
+            session.stop_playing();
+
+            RtspResponse::ok()
+        }
+        // Keep alive
+        "GET_PARAMETER" | "SET_PARAMETER" => {
+            RtspResponse::ok().header("Session", session.session_header())
+        }
+        _ => RtspResponse::new(501, "Not Implemented").header("Public", PUBLIC_METHODS),
+    }
+}
+
+/// Writes the packets of the set up tracks into the connection until the client stops playing
+fn forward_packets(
+    shared: Arc<RtspShared>,
+    channels: [Option<u8>; 2],
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+) -> JoinHandle<()> {
+    let mut packets = shared.packets.subscribe();
+
+    spawn(async move {
+        // The client can't decode anything before the next keyframe
+        shared.request_idr_frame().await;
+
+        let mut frame = BytesMut::new();
+        loop {
+            let packet = match packets.recv().await {
+                Ok(packet) => packet,
+                Err(RecvError::Lagged(count)) => {
+                    debug!("[RTSP]: a client is behind, it missed {count} packets");
+                    shared.request_idr_frame().await;
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let Some(channel) = channels[packet.track as usize] else {
+                continue;
+            };
+            let Ok(len) = u16::try_from(packet.data.len()) else {
+                continue;
+            };
+
+            frame.clear();
+            frame.put_u8(b'$');
+            frame.put_u8(channel);
+            frame.put_u16(len);
+            frame.extend_from_slice(&packet.data);
+
+            if writer.lock().await.write_all(&frame).await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rtsp::{
+        RtspTrack,
+        server::{RtspRequest, interleaved_channel},
+    };
+
+    #[test]
+    fn test_parse_request() {
+        let request = RtspRequest::parse(
+            "SETUP rtsp://192.168.1.2:8554/track1 RTSP/1.0\r\nCSeq: 3\r\ntransport: RTP/AVP/TCP;unicast;interleaved=2-3\r\n\r\n",
+        )
+        .expect("parse request");
+
+        assert_eq!(request.method, "SETUP");
+        assert_eq!(request.header("CSeq"), Some("3"));
+        assert_eq!(
+            request.header("Transport"),
+            Some("RTP/AVP/TCP;unicast;interleaved=2-3")
+        );
+        assert_eq!(request.track(), Some(RtspTrack::Audio));
+
+        assert!(RtspRequest::parse("GET / HTTP/1.1\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_interleaved_channel() {
+        assert_eq!(
+            interleaved_channel("RTP/AVP/TCP;unicast;interleaved=4-5", RtspTrack::Video),
+            Some(4)
+        );
+        assert_eq!(
+            interleaved_channel(
+                "RTP/AVP;unicast;client_port=5000-5001,RTP/AVP/TCP;unicast",
+                RtspTrack::Audio
+            ),
+            Some(2)
+        );
+        assert_eq!(
+            interleaved_channel("RTP/AVP;unicast;client_port=5000-5001", RtspTrack::Video),
+            None
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Plays the rumble of the trigger motors on the main motors of controllers which don't have trigger motors.
+
+use common::config::{ControllerRumbleConfig, RumbleControllerType, TriggerRumbleRemap};
+use moonlight_common::stream::bindings::{ControllerCapabilities, ControllerType};
+
+use crate::transport::OutboundPacket;
+
+const MAX_CONTROLLERS: usize = 16;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ControllerRumble {
+    /// Play the trigger rumble on the main motors
+    remap: bool,
+    /// Send the trigger rumble to the controller
+    forward_triggers: bool,
+    low_frequency_motor: u16,
+    high_frequency_motor: u16,
+    left_trigger_motor: u16,
+    right_trigger_motor: u16,
+}
+
+impl ControllerRumble {
+    /// The left trigger is mixed into the low frequency motor because both are on the left side
+    fn main_motors(&self) -> (u16, u16) {
+        if self.remap {
+            (
+                self.low_frequency_motor.max(self.left_trigger_motor),
+                self.high_frequency_motor.max(self.right_trigger_motor),
+            )
+        } else {
+            (self.low_frequency_motor, self.high_frequency_motor)
+        }
+    }
+}
+
+pub struct RumbleRemapper {
+    config: ControllerRumbleConfig,
+    controllers: [Option<ControllerRumble>; MAX_CONTROLLERS],
+}
+
+impl RumbleRemapper {
+    pub fn new(config: ControllerRumbleConfig) -> Self {
+        Self {
+            config,
+            controllers: [None; MAX_CONTROLLERS],
+        }
+    }
+
+    pub fn controller_connected(
+        &mut self,
+        id: u8,
+        ty: ControllerType,
+        capabilities: ControllerCapabilities,
+    ) {
+        let Some(controller) = self.controllers.get_mut(id as usize) else {
+            return;
+        };
+
+        let has_trigger_motors = capabilities.contains(ControllerCapabilities::TRIGGER_RUMBLE);
+        let remap = match self.config.trigger_remap(rumble_controller_type(ty)) {
+            TriggerRumbleRemap::Disabled => false,
+            TriggerRumbleRemap::MissingTriggerMotors => !has_trigger_motors,
+            TriggerRumbleRemap::Always => true,
+        };
+
+        *controller = Some(ControllerRumble {
+            remap,
+            forward_triggers: has_trigger_motors || !remap,
+            ..Default::default()
+        });
+    }
+
+    pub fn controller_disconnected(&mut self, id: u8) {
+        if let Some(controller) = self.controllers.get_mut(id as usize) {
+            *controller = None;
+        }
+    }
+
+    pub fn rumble(
+        &mut self,
+        controller_number: u8,
+        low_frequency_motor: u16,
+        high_frequency_motor: u16,
+    ) -> OutboundPacket {
+        let (low_frequency_motor, high_frequency_motor) =
+            match self.controller_mut(controller_number) {
+                Some(controller) => {
+                    controller.low_frequency_motor = low_frequency_motor;
+                    controller.high_frequency_motor = high_frequency_motor;
+
+                    controller.main_motors()
+                }
+                None => (low_frequency_motor, high_frequency_motor),
+            };
+
+        OutboundPacket::ControllerRumble {
+            controller_number,
+            low_frequency_motor,
+            high_frequency_motor,
+        }
+    }
+
+    pub fn trigger_rumble(
+        &mut self,
+        controller_number: u8,
+        left_trigger_motor: u16,
+        right_trigger_motor: u16,
+    ) -> impl Iterator<Item = OutboundPacket> {
+        let trigger_packet = OutboundPacket::ControllerTriggerRumble {
+            controller_number,
+            left_trigger_motor,
+            right_trigger_motor,
+        };
+
+        let Some(controller) = self.controller_mut(controller_number) else {
+            return Some(trigger_packet).into_iter().chain(None);
+        };
+
+        let previous_main_motors = controller.main_motors();
+
+        controller.left_trigger_motor = left_trigger_motor;
+        controller.right_trigger_motor = right_trigger_motor;
+
+        let (low_frequency_motor, high_frequency_motor) = controller.main_motors();
+        let main_packet = (previous_main_motors != (low_frequency_motor, high_frequency_motor))
+            .then_some(OutboundPacket::ControllerRumble {
+                controller_number,
+                low_frequency_motor,
+                high_frequency_motor,
+            });
+
+        controller
+            .forward_triggers
+            .then_some(trigger_packet)
+            .into_iter()
+            .chain(main_packet)
+    }
+
+    fn controller_mut(&mut self, controller_number: u8) -> Option<&mut ControllerRumble> {
+        self.controllers
+            .get_mut(controller_number as usize)
+            .and_then(Option::as_mut)
+    }
+}
+
+fn rumble_controller_type(ty: ControllerType) -> RumbleControllerType {
+    match ty {
+        ControllerType::Unknown => RumbleControllerType::Unknown,
+        ControllerType::Xbox => RumbleControllerType::Xbox,
+        ControllerType::PlayStation => RumbleControllerType::PlayStation,
+        ControllerType::Nintendo => RumbleControllerType::Nintendo,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use common::config::{ControllerRumbleConfig, RumbleControllerType, TriggerRumbleRemap};
+    use moonlight_common::stream::bindings::{ControllerCapabilities, ControllerType};
+
+    use crate::{rumble::RumbleRemapper, transport::OutboundPacket};
+
+    fn main_motors(packets: &[OutboundPacket]) -> Vec<(u16, u16)> {
+        packets
+            .iter()
+            .filter_map(|packet| match packet {
+                OutboundPacket::ControllerRumble {
+                    low_frequency_motor,
+                    high_frequency_motor,
+                    ..
+                } => Some((*low_frequency_motor, *high_frequency_motor)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn trigger_motors(packets: &[OutboundPacket]) -> Vec<(u16, u16)> {
+        packets
+            .iter()
+            .filter_map(|packet| match packet {
+                OutboundPacket::ControllerTriggerRumble {
+                    left_trigger_motor,
+                    right_trigger_motor,
+                    ..
+                } => Some((*left_trigger_motor, *right_trigger_motor)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_remap_missing_trigger_motors() {
+        let mut remapper = RumbleRemapper::new(ControllerRumbleConfig {
+            trigger_remap: TriggerRumbleRemap::MissingTriggerMotors,
+            ..Default::default()
+        });
+        remapper.controller_connected(0, ControllerType::Xbox, ControllerCapabilities::RUMBLE);
+        remapper.controller_connected(
+            1,
+            ControllerType::Xbox,
+            ControllerCapabilities::RUMBLE | ControllerCapabilities::TRIGGER_RUMBLE,
+        );
+
+        // Without trigger motors
+        assert_eq!(main_motors(&[remapper.rumble(0, 100, 0)]), [(100, 0)]);
+        let packets = remapper.trigger_rumble(0, 50, 300).collect::<Vec<_>>();
+        assert!(trigger_motors(&packets).is_empty());
+        assert_eq!(main_motors(&packets), [(100, 300)]);
+        assert_eq!(main_motors(&[remapper.rumble(0, 0, 0)]), [(50, 300)]);
+        let packets = remapper.trigger_rumble(0, 0, 0).collect::<Vec<_>>();
+        assert_eq!(main_motors(&packets), [(0, 0)]);
+
+        // With trigger motors
+        let packets = remapper.trigger_rumble(1, 50, 300).collect::<Vec<_>>();
+        assert_eq!(trigger_motors(&packets), [(50, 300)]);
+        assert!(main_motors(&packets).is_empty());
+        assert_eq!(main_motors(&[remapper.rumble(1, 100, 0)]), [(100, 0)]);
+
+        // Unknown controller
+        let packets = remapper.trigger_rumble(5, 1, 2).collect::<Vec<_>>();
+        assert_eq!(trigger_motors(&packets), [(1, 2)]);
+    }
+
+    #[test]
+    fn test_remap_per_controller_type() {
+        let mut remapper = RumbleRemapper::new(ControllerRumbleConfig {
+            trigger_remap: TriggerRumbleRemap::Disabled,
+            controller_type_trigger_remap: HashMap::from([(
+                RumbleControllerType::PlayStation,
+                TriggerRumbleRemap::Always,
+            )]),
+        });
+        remapper.controller_connected(
+            0,
+            ControllerType::PlayStation,
+            ControllerCapabilities::RUMBLE | ControllerCapabilities::TRIGGER_RUMBLE,
+        );
+        remapper.controller_connected(1, ControllerType::Nintendo, ControllerCapabilities::RUMBLE);
+
+        let packets = remapper.trigger_rumble(0, 10, 20).collect::<Vec<_>>();
+        assert_eq!(trigger_motors(&packets), [(10, 20)]);
+        assert_eq!(main_motors(&packets), [(10, 20)]);
+
+        let packets = remapper.trigger_rumble(1, 10, 20).collect::<Vec<_>>();
+        assert_eq!(trigger_motors(&packets), [(10, 20)]);
+        assert!(main_motors(&packets).is_empty());
+    }
+}
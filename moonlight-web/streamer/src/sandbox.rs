@@ -0,0 +1,136 @@
+//! Restricts the streamer after it received its config from the web server.
+//! Everything after this point might be influenced by untrusted WebRTC input.
+
+use common::ipc::StreamerConfig;
+use log::info;
+#[cfg(target_os = "linux")]
+use log::warn;
+
+#[cfg(target_os = "linux")]
+pub fn apply(config: &StreamerConfig) -> Result<(), anyhow::Error> {
+    if !config.sandbox.enabled {
+        return Ok(());
+    }
+
+    // Landlock only adds to the other restrictions, the stream works without it
+    if config.sandbox.landlock
+        && let Err(err) = linux::apply_landlock(config)
+    {
+        warn!("[Sandbox]: failed to apply landlock, the file system isn't restricted: {err:?}");
+    }
+    if config.sandbox.seccomp {
+        linux::apply_seccomp()?;
+    }
+
+    info!("[Sandbox]: applied sandbox");
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(config: &StreamerConfig) -> Result<(), anyhow::Error> {
+    if config.sandbox.enabled {
+        info!("[Sandbox]: landlock and seccomp are only available on linux");
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{collections::BTreeMap, fs, path::Path};
+
+    use common::ipc::StreamerConfig;
+    use landlock::{
+        ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+        path_beneath_rules,
+    };
+    use log::warn;
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, SeccompRule};
+
+    /// Directories required to resolve dns, load shared libraries and read random numbers.
+    /// `/etc/resolv.conf` is often a link into `/run`, e.g. with systemd-resolved.
+    const READ_ONLY_PATHS: &[&str] = &[
+        "/usr", "/lib", "/lib64", "/etc", "/dev", "/proc", "/sys", "/run",
+    ];
+
+    pub fn apply_landlock(config: &StreamerConfig) -> Result<(), anyhow::Error> {
+        let abi = ABI::V2;
+
+        let read_only = READ_ONLY_PATHS
+            .iter()
+            .copied()
+            .filter(|path| Path::new(path).exists());
+
+        let mut read_write = vec![".".to_string()];
+        if let Some(directory) = config.file_transfer.directory.as_ref() {
+            fs::create_dir_all(directory)?;
+            read_write.push(directory.clone());
+        }
+        if config.hls_output.enabled
+            && let Some(directory) = config.hls_output.recording_directory.as_ref()
+        {
+            fs::create_dir_all(directory)?;
+            read_write.push(directory.clone());
+        }
+
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(read_only, AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(read_write, AccessFs::from_all(abi)))?
+            .restrict_self()?;
+
+        if status.ruleset == RulesetStatus::NotEnforced {
+            warn!("[Sandbox]: landlock is not supported by this kernel");
+        }
+
+        Ok(())
+    }
+
+    /// Syscalls which are denied, the streamer never needs them
+    const DENIED_SYSCALLS: &[i64] = &[
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_bpf,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+    ];
+
+    pub fn apply_seccomp() -> Result<(), anyhow::Error> {
+        let rules = DENIED_SYSCALLS
+            .iter()
+            .map(|syscall| (*syscall, Vec::<SeccompRule>::new()))
+            .collect::<BTreeMap<_, _>>();
+
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            std::env::consts::ARCH.try_into()?,
+        )?;
+        let program: BpfProgram = filter.try_into()?;
+
+        // The tokio runtime already spawned threads
+        seccompiler::apply_filter_all_threads(&program)?;
+
+        Ok(())
+    }
+}
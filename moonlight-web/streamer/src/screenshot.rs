@@ -0,0 +1,209 @@
+//! Screenshots of the stream for [ServerIpcMessage::TakeScreenshot](common::ipc::ServerIpcMessage::TakeScreenshot).
+//!
+//! The next IDR frame is decoded, so every screenshot requests one from the host.
+
+use std::{mem::take, sync::Arc};
+
+use bytes::Bytes;
+use common::{api_bindings::ScreenshotFormat, ipc::StreamerIpcMessage};
+use log::{info, warn};
+use moonlight_common::stream::bindings::{VideoDecodeUnit, VideoFormat};
+use tokio::task::spawn_blocking;
+
+use crate::StreamConnection;
+
+/// Screenshots need FFmpeg, which is only linked with the `screenshot` feature
+const SCREENSHOTS_SUPPORTED: bool = cfg!(feature = "screenshot");
+
+pub(crate) async fn request_screenshot(stream: &Arc<StreamConnection>, format: ScreenshotFormat) {
+    if !SCREENSHOTS_SUPPORTED {
+        warn!("[Screenshot]: the streamer was built without the screenshot feature");
+
+        stream
+            .ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::Screenshot { image: None })
+            .await;
+        return;
+    }
+
+    info!("[Screenshot]: requesting an IDR frame for a {format:?} screenshot");
+    stream.screenshot_requests.lock().await.push(format);
+
+    let moonlight_stream = stream.stream.read().await;
+    if let Some(moonlight_stream) = moonlight_stream.as_ref()
+        && let Err(err) = moonlight_stream.request_idr_frame()
+    {
+        warn!("[Screenshot]: failed to request an IDR frame: {err}");
+    }
+}
+
+/// Must be called with every IDR frame, answers the pending screenshot requests
+pub(crate) async fn capture_screenshots(stream: &StreamConnection, unit: &VideoDecodeUnit<'_>) {
+    let formats = take(&mut *stream.screenshot_requests.lock().await);
+    if formats.is_empty() {
+        return;
+    }
+
+    let video_format = stream
+        .stream_setup
+        .lock()
+        .await
+        .video
+        .map(|setup| setup.format);
+
+    let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+    let mut frame = Vec::with_capacity(frame_len);
+    for buffer in unit.buffers {
+        frame.extend_from_slice(buffer.data);
+    }
+
+    let mut ipc_sender = stream.ipc_sender.clone();
+    stream.runtime.spawn(async move {
+        let images = match video_format {
+            Some(video_format) => {
+                spawn_blocking(move || encode_screenshots(video_format, &frame, &formats))
+                    .await
+                    .unwrap_or_default()
+            }
+            None => {
+                warn!("[Screenshot]: received an IDR frame before the video was set up");
+                vec![None; formats.len()]
+            }
+        };
+
+        for image in images {
+            ipc_sender
+                .send(StreamerIpcMessage::Screenshot { image })
+                .await;
+        }
+    });
+}
+
+/// Decodes the frame once and encodes it in every requested format
+fn encode_screenshots(
+    video_format: VideoFormat,
+    frame: &[u8],
+    formats: &[ScreenshotFormat],
+) -> Vec<Option<Bytes>> {
+    #[cfg(feature = "screenshot")]
+    {
+        let image = match decode::decode_rgb(video_format, frame) {
+            Ok(image) => image,
+            Err(err) => {
+                warn!("[Screenshot]: failed to decode the IDR frame: {err}");
+                return vec![None; formats.len()];
+            }
+        };
+
+        formats
+            .iter()
+            .map(|format| match decode::encode(&image, *format) {
+                Ok(image) => Some(image),
+                Err(err) => {
+                    warn!("[Screenshot]: failed to encode the screenshot as {format:?}: {err}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "screenshot"))]
+    {
+        let _ = (video_format, frame);
+        vec![None; formats.len()]
+    }
+}
+
+#[cfg(feature = "screenshot")]
+pub(crate) mod decode {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+    use common::api_bindings::ScreenshotFormat;
+    use image::{ImageError, ImageFormat, RgbImage, codecs::jpeg::JpegEncoder};
+    use moonlight_common::stream::bindings::VideoFormat;
+    use moonlight_ffmpeg::{
+        ffmpeg::{
+            Error, Packet,
+            format::Pixel,
+            frame,
+            software::scaling::{self, Flags},
+        },
+        video::open_video_decoder,
+    };
+    use thiserror::Error;
+
+    const JPEG_QUALITY: u8 = 90;
+
+    #[derive(Debug, Error)]
+    pub enum DecodeError {
+        #[error("ffmpeg: {0}")]
+        Ffmpeg(#[from] Error),
+        #[error("the frame has an invalid size")]
+        InvalidSize,
+    }
+
+    pub fn decode_rgb(video_format: VideoFormat, frame: &[u8]) -> Result<RgbImage, DecodeError> {
+        moonlight_ffmpeg::init()?;
+
+        let mut decoder = open_video_decoder(video_format)?;
+        decoder.send_packet(&Packet::copy(frame))?;
+        decoder.send_eof()?;
+
+        let mut decoded = frame::Video::empty();
+        decoder.receive_frame(&mut decoded)?;
+
+        scale_rgb(&decoded, decoded.width(), decoded.height())
+    }
+
+    /// Converts a decoded frame into an image of the size
+    pub fn scale_rgb(
+        decoded: &frame::Video,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage, DecodeError> {
+        let mut scaler = scaling::Context::get(
+            decoded.format(),
+            decoded.width(),
+            decoded.height(),
+            Pixel::RGB24,
+            width,
+            height,
+            Flags::BILINEAR,
+        )?;
+        let mut rgb = frame::Video::empty();
+        scaler.run(decoded, &mut rgb)?;
+
+        // The rows of the frame might be padded
+        let stride = rgb.stride(0);
+        let row_len = width as usize * 3;
+        let data = rgb.data(0);
+
+        let mut pixels = Vec::with_capacity(row_len * height as usize);
+        for row in 0..height as usize {
+            pixels.extend_from_slice(&data[row * stride..row * stride + row_len]);
+        }
+
+        RgbImage::from_raw(width, height, pixels).ok_or(DecodeError::InvalidSize)
+    }
+
+    pub fn encode(image: &RgbImage, format: ScreenshotFormat) -> Result<Bytes, ImageError> {
+        match format {
+            ScreenshotFormat::Png => {
+                let mut buffer = Cursor::new(Vec::new());
+                image.write_to(&mut buffer, ImageFormat::Png)?;
+
+                Ok(Bytes::from(buffer.into_inner()))
+            }
+            ScreenshotFormat::Jpeg => encode_jpeg(image, JPEG_QUALITY),
+        }
+    }
+
+    pub fn encode_jpeg(image: &RgbImage, quality: u8) -> Result<Bytes, ImageError> {
+        let mut buffer = Cursor::new(Vec::new());
+        image.write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality))?;
+
+        Ok(Bytes::from(buffer.into_inner()))
+    }
+}
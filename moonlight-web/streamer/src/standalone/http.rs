@@ -0,0 +1,162 @@
+//! A minimal http/1.1 server side, every connection carries a single request.
+
+use std::io::{self, ErrorKind};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Requests are only a few headers, anything larger isn't a client we know
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+/// Session descriptions are the largest messages
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn parse_head(head: &str) -> Option<Self> {
+        let mut lines = head.lines();
+
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        if !request_line.next()?.starts_with("HTTP/1.") {
+            return None;
+        }
+
+        let headers = lines
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect();
+
+        Some(Self {
+            method,
+            path,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    /// None if the connection closed before a request
+    pub async fn read(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Self>, io::Error> {
+        let mut head = String::new();
+        loop {
+            let len = reader.read_line(&mut head).await?;
+            if len == 0 {
+                return Ok(None);
+            }
+            if head.len() > MAX_HEAD_SIZE {
+                return Err(ErrorKind::InvalidData.into());
+            }
+            if head.ends_with("\r\n\r\n") || head.ends_with("\n\n") {
+                break;
+            }
+        }
+
+        let mut request = Self::parse_head(&head).ok_or(ErrorKind::InvalidData)?;
+
+        let content_length = match request.header("Content-Length") {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| io::Error::from(ErrorKind::InvalidData))?,
+            None => 0,
+        };
+        if content_length > MAX_BODY_SIZE {
+            return Err(ErrorKind::InvalidData.into());
+        }
+
+        request.body.resize(content_length, 0);
+        reader.read_exact(&mut request.body).await?;
+
+        Ok(Some(request))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("Authorization")?.strip_prefix("Bearer ")
+    }
+}
+
+pub(super) struct HttpResponse {
+    status: u16,
+    reason: &'static str,
+    body: String,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            body: String::new(),
+        }
+    }
+    pub fn json(body: String) -> Self {
+        Self {
+            status: 200,
+            reason: "OK",
+            body,
+        }
+    }
+
+    pub async fn write(&self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<(), io::Error> {
+        // Browsers embedding the stream in another page send a preflight request first
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\n\
+            Access-Control-Allow-Origin: *\r\n\
+            Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+            Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+            Connection: close\r\n",
+            self.status, self.reason
+        );
+        if !self.body.is_empty() {
+            response.push_str("Content-Type: application/json\r\n");
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n", self.body.len()));
+        response.push_str(&self.body);
+
+        writer.write_all(response.as_bytes()).await?;
+        writer.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::BufReader;
+
+    use crate::standalone::http::HttpRequest;
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let raw = b"POST /stream HTTP/1.1\r\nAuthorization: Bearer secret\r\ncontent-length: 4\r\n\r\n\"Ok\"";
+        let mut reader = BufReader::new(&raw[..]);
+
+        let request = HttpRequest::read(&mut reader).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/stream");
+        assert_eq!(request.bearer_token(), Some("secret"));
+        assert_eq!(request.body, b"\"Ok\"");
+    }
+
+    #[tokio::test]
+    async fn test_reject_invalid_requests() {
+        let mut reader = BufReader::new(&b"GET /stream RTSP/1.0\r\n\r\n"[..]);
+        assert!(HttpRequest::read(&mut reader).await.is_err());
+
+        let mut reader = BufReader::new(&b""[..]);
+        assert!(HttpRequest::read(&mut reader).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,355 @@
+//! Runs the streamer without a web server, see [StandaloneStreamerConfig].
+//!
+//! The web server side of the ipc runs in this process and is bridged to a signaling endpoint:
+//! - `POST /stream`: sends a [StreamClientMessage] to the streamer
+//! - `GET /stream`: waits for the next [StreamServerMessage]s and returns them as a json array
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use common::{
+    api_bindings::{StreamClientMessage, StreamServerMessage},
+    config::{SessionThumbnailConfig, StandaloneStreamerConfig, StreamerLogForwardingConfig},
+    ipc::{
+        InputMacro, IpcReceiver, IpcSender, ServerIpcMessage, StreamerConfig, StreamerIpcMessage,
+        create_child_ipc, create_process_ipc,
+    },
+};
+use log::{debug, warn};
+use tokio::{
+    fs,
+    io::{BufReader, duplex},
+    net::{TcpListener, TcpStream},
+    spawn,
+    sync::{Mutex, Notify},
+    time::{sleep, timeout},
+};
+
+use crate::standalone::http::{HttpRequest, HttpResponse};
+
+mod http;
+
+/// Size of the in-process pipes of the ipc
+const IPC_BUFFER_SIZE: usize = 64 * 1024;
+/// A `GET /stream` returns an empty array after this, so proxies don't close the request
+const POLL_TIMEOUT: Duration = Duration::from_secs(20);
+/// Messages which no client polled are dropped beyond this
+const MAX_QUEUED_MESSAGES: usize = 256;
+
+/// The messages for the client, already serialized
+#[derive(Default)]
+struct ClientQueue {
+    messages: Mutex<VecDeque<String>>,
+    notify: Notify,
+}
+
+impl ClientQueue {
+    async fn push(&self, message: &StreamServerMessage) {
+        let json = match serde_json::to_string(message) {
+            Ok(json) => json,
+            Err(err) => {
+                warn!("[Standalone]: failed to serialize a message: {err}");
+                return;
+            }
+        };
+
+        let mut messages = self.messages.lock().await;
+        if messages.len() >= MAX_QUEUED_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back(json);
+        drop(messages);
+
+        self.notify.notify_waiters();
+    }
+
+    async fn poll(&self) -> String {
+        let _ = timeout(POLL_TIMEOUT, async {
+            loop {
+                let notified = self.notify.notified();
+                if !self.messages.lock().await.is_empty() {
+                    return;
+                }
+                notified.await;
+            }
+        })
+        .await;
+
+        let messages = self.messages.lock().await.drain(..).collect::<Vec<_>>();
+        format!("[{}]", messages.join(","))
+    }
+}
+
+/// Loads the config, starts the signaling endpoint and returns the streamer side of the ipc
+pub async fn create_ipc(
+    config_path: &str,
+) -> Result<(IpcSender<StreamerIpcMessage>, IpcReceiver<ServerIpcMessage>), anyhow::Error> {
+    let config = fs::read_to_string(config_path)
+        .await
+        .with_context(|| format!("failed to read the standalone config {config_path}"))?;
+    let config = serde_json::from_str::<StandaloneStreamerConfig>(&config)
+        .context("failed to parse the standalone config")?;
+
+    if config.access_token.is_empty() {
+        return Err(anyhow!(
+            "the access_token of the standalone config is empty"
+        ));
+    }
+
+    let init = init_message(&config).await?;
+
+    // Bound before the streamer applies its sandbox
+    let listener = TcpListener::bind(config.bind_address)
+        .await
+        .with_context(|| {
+            format!(
+                "failed to bind the signaling endpoint {}",
+                config.bind_address
+            )
+        })?;
+    // The logger is initialized with the Init message
+    eprintln!(
+        "[Standalone]: signaling endpoint listens on http://{}/stream",
+        config.bind_address
+    );
+
+    let (streamer_write, server_read) = duplex(IPC_BUFFER_SIZE);
+    let (server_write, streamer_read) = duplex(IPC_BUFFER_SIZE);
+
+    let (mut server_sender, server_receiver) = create_child_ipc::<
+        ServerIpcMessage,
+        StreamerIpcMessage,
+    >(
+        "[Standalone]", server_write, server_read, None
+    )
+    .await;
+    server_sender.send(init).await;
+
+    let queue = Arc::new(ClientQueue::default());
+
+    spawn(forward_streamer_messages(
+        server_sender.clone(),
+        server_receiver,
+        queue.clone(),
+    ));
+    spawn(accept_clients(
+        listener,
+        Arc::new(config.access_token),
+        server_sender,
+        queue,
+    ));
+
+    Ok(create_process_ipc(streamer_read, streamer_write).await)
+}
+
+async fn init_message(
+    config: &StandaloneStreamerConfig,
+) -> Result<ServerIpcMessage, anyhow::Error> {
+    async fn read_pem(path: &str) -> Result<pem::Pem, anyhow::Error> {
+        let content = fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {path}"))?;
+
+        pem::parse(content).with_context(|| format!("failed to parse {path}"))
+    }
+
+    Ok(ServerIpcMessage::Init {
+        config: StreamerConfig {
+            webrtc: config.webrtc.clone(),
+            file_transfer: Default::default(),
+            controller_rumble: Default::default(),
+            video_watchdog: Default::default(),
+            peer_watchdog: Default::default(),
+            cursor_prediction: Default::default(),
+            media_priority: Default::default(),
+            encryption: Default::default(),
+            // Nobody receives them
+            thumbnail: SessionThumbnailConfig {
+                interval: Duration::ZERO,
+                ..Default::default()
+            },
+            rtsp_output: Default::default(),
+            hls_output: Default::default(),
+            sandbox: config.sandbox.clone(),
+            memory: Default::default(),
+            log_level: config.log_level,
+            log_forwarding: StreamerLogForwardingConfig {
+                enabled: false,
+                ..Default::default()
+            },
+        },
+        host_address: config.host_address.clone(),
+        host_http_port: config.host_http_port,
+        host_proxy: None,
+        host_xml_parse_mode: config.host_xml_parse_mode,
+        client_unique_id: config.client_unique_id.clone(),
+        client_private_key: read_pem(&config.client_private_key_path).await?,
+        client_certificate: read_pem(&config.client_certificate_path).await?,
+        server_certificate: read_pem(&config.server_certificate_path).await?,
+        app_id: config.app_id,
+        display_app_ids: HashMap::new(),
+        video_frame_queue_size: config.video_frame_queue_size,
+        audio_sample_queue_size: config.audio_sample_queue_size,
+        input_macros: Vec::new(),
+    })
+}
+
+/// Answers what the web server would answer, everything for the client is queued
+async fn forward_streamer_messages(
+    mut ipc_sender: IpcSender<ServerIpcMessage>,
+    mut ipc_receiver: IpcReceiver<StreamerIpcMessage>,
+    queue: Arc<ClientQueue>,
+) {
+    let mut next_macro_id = 1;
+
+    while let Some(message) = ipc_receiver.recv().await {
+        match message {
+            StreamerIpcMessage::WebSocket(message) => {
+                queue.push(&message).await;
+            }
+            // Without storage the macros only exist for this session
+            StreamerIpcMessage::SaveInputMacro { name, events } => {
+                ipc_sender
+                    .send(ServerIpcMessage::InputMacroSaved(InputMacro {
+                        id: next_macro_id,
+                        name,
+                        events,
+                    }))
+                    .await;
+                next_macro_id += 1;
+            }
+            // Whoever has the access token may use every app of the host
+            StreamerIpcMessage::SwitchApp { app_id } => {
+                ipc_sender
+                    .send(ServerIpcMessage::SwitchApp {
+                        app_id,
+                        display_app_ids: HashMap::new(),
+                    })
+                    .await;
+            }
+            StreamerIpcMessage::WebSocketTransport(_) => {
+                debug!("[Standalone]: the web socket transport isn't supported, dropping a packet");
+            }
+            StreamerIpcMessage::Stop => break,
+            _ => {}
+        }
+    }
+}
+
+async fn accept_clients(
+    listener: TcpListener,
+    access_token: Arc<String>,
+    ipc_sender: IpcSender<ServerIpcMessage>,
+    queue: Arc<ClientQueue>,
+) {
+    loop {
+        let (socket, address) = match listener.accept().await {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("[Standalone]: failed to accept a client: {err}");
+                // e.g. no file descriptors are left
+                sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let access_token = access_token.clone();
+        let ipc_sender = ipc_sender.clone();
+        let queue = queue.clone();
+        spawn(async move {
+            if let Err(err) = serve_client(socket, &access_token, ipc_sender, &queue).await {
+                debug!("[Standalone]: the request of {address} failed: {err}");
+            }
+        });
+    }
+}
+
+async fn serve_client(
+    socket: TcpStream,
+    access_token: &str,
+    mut ipc_sender: IpcSender<ServerIpcMessage>,
+    queue: &ClientQueue,
+) -> Result<(), std::io::Error> {
+    let (read, mut write) = socket.into_split();
+    let mut reader = BufReader::new(read);
+
+    let Some(request) = HttpRequest::read(&mut reader).await? else {
+        return Ok(());
+    };
+
+    let response = if request.method == "OPTIONS" {
+        HttpResponse::new(204, "No Content")
+    } else if request.path != "/stream" {
+        HttpResponse::new(404, "Not Found")
+    } else if !request
+        .bearer_token()
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), access_token.as_bytes()))
+    {
+        HttpResponse::new(401, "Unauthorized")
+    } else if request.method == "GET" {
+        HttpResponse::json(queue.poll().await)
+    } else if request.method == "POST" {
+        match serde_json::from_slice::<StreamClientMessage>(&request.body) {
+            Ok(message) => {
+                ipc_sender.send(ServerIpcMessage::WebSocket(message)).await;
+                HttpResponse::new(204, "No Content")
+            }
+            Err(err) => {
+                debug!("[Standalone]: received an invalid message: {err}");
+                HttpResponse::new(400, "Bad Request")
+            }
+        }
+    } else {
+        HttpResponse::new(405, "Method Not Allowed")
+    };
+
+    response.write(&mut write).await
+}
+
+/// Doesn't leak how much of the token was correct
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use common::api_bindings::StreamServerMessage;
+
+    use crate::standalone::{ClientQueue, constant_time_eq};
+
+    #[tokio::test]
+    async fn test_queue_returns_json_array() {
+        let queue = ClientQueue::default();
+
+        queue
+            .push(&StreamServerMessage::ResumeToken {
+                token: "a".to_string(),
+            })
+            .await;
+        queue
+            .push(&StreamServerMessage::ResumeToken {
+                token: "b".to_string(),
+            })
+            .await;
+
+        let messages =
+            serde_json::from_str::<Vec<StreamServerMessage>>(&queue.poll().await).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(queue.messages.lock().await.is_empty());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Thumbnails of the stream for the session list of admins, sent as [StreamerIpcMessage::Thumbnail].
+//!
+//! An IDR frame is decoded at most once per interval.
+//! Hosts only send IDR frames on request, so one is requested when the last thumbnail is older than the interval.
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use common::{config::SessionThumbnailConfig, ipc::StreamerIpcMessage};
+use log::{debug, warn};
+use moonlight_common::stream::bindings::{VideoDecodeUnit, VideoFormat};
+use tokio::task::spawn_blocking;
+
+use crate::StreamConnection;
+
+/// Thumbnails need FFmpeg, which is only linked with the `screenshot` feature
+const THUMBNAILS_SUPPORTED: bool = cfg!(feature = "screenshot");
+
+pub(crate) struct Thumbnails {
+    config: SessionThumbnailConfig,
+    last_capture: Option<Instant>,
+}
+
+impl Thumbnails {
+    pub fn new(config: SessionThumbnailConfig) -> Self {
+        Self {
+            config,
+            last_capture: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        THUMBNAILS_SUPPORTED && !self.config.interval.is_zero()
+    }
+    pub fn interval(&self) -> Duration {
+        self.config.interval
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.enabled()
+            && self
+                .last_capture
+                .is_none_or(|last_capture| now - last_capture >= self.config.interval)
+    }
+}
+
+/// Requests an IDR frame if no thumbnail was taken within the interval, called once per interval
+pub(crate) async fn request_thumbnail(stream: &StreamConnection) {
+    if !stream.thumbnails.lock().await.is_due(Instant::now()) {
+        return;
+    }
+
+    let moonlight_stream = stream.stream.read().await;
+    if let Some(moonlight_stream) = moonlight_stream.as_ref()
+        && let Err(err) = moonlight_stream.request_idr_frame()
+    {
+        warn!("[Thumbnail]: failed to request an IDR frame: {err}");
+    }
+}
+
+/// Must be called with every IDR frame, takes a thumbnail if the last one is older than the interval
+pub(crate) async fn capture_thumbnail(stream: &StreamConnection, unit: &VideoDecodeUnit<'_>) {
+    let config = {
+        let mut thumbnails = stream.thumbnails.lock().await;
+
+        let now = Instant::now();
+        if !thumbnails.is_due(now) {
+            return;
+        }
+        thumbnails.last_capture = Some(now);
+
+        thumbnails.config.clone()
+    };
+
+    let Some(video_format) = stream
+        .stream_setup
+        .lock()
+        .await
+        .video
+        .map(|setup| setup.format)
+    else {
+        return;
+    };
+
+    let frame_len = unit.buffers.iter().map(|buffer| buffer.data.len()).sum();
+    let mut frame = Vec::with_capacity(frame_len);
+    for buffer in unit.buffers {
+        frame.extend_from_slice(buffer.data);
+    }
+
+    let mut ipc_sender = stream.ipc_sender.clone();
+    stream.runtime.spawn(async move {
+        let image = spawn_blocking(move || encode_thumbnail(video_format, &frame, &config))
+            .await
+            .ok()
+            .flatten();
+
+        if let Some(image) = image {
+            debug!("[Thumbnail]: took a thumbnail with {} bytes", image.len());
+
+            ipc_sender
+                .send(StreamerIpcMessage::Thumbnail { image })
+                .await;
+        }
+    });
+}
+
+fn encode_thumbnail(
+    video_format: VideoFormat,
+    frame: &[u8],
+    config: &SessionThumbnailConfig,
+) -> Option<Bytes> {
+    #[cfg(feature = "screenshot")]
+    {
+        use crate::{preview::preview_size, screenshot::decode};
+
+        let image = match decode::decode_rgb(video_format, frame) {
+            Ok(image) => image,
+            Err(err) => {
+                warn!("[Thumbnail]: failed to decode the IDR frame: {err}");
+                return None;
+            }
+        };
+
+        let (width, height) = preview_size(image.width(), image.height(), config.width);
+        let image = image::imageops::thumbnail(&image, width, height);
+
+        match decode::encode_jpeg(&image, config.jpeg_quality) {
+            Ok(image) => Some(image),
+            Err(err) => {
+                warn!("[Thumbnail]: failed to encode the thumbnail: {err}");
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "screenshot"))]
+    {
+        let _ = (video_format, frame, config);
+        None
+    }
+}
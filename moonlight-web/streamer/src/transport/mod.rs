@@ -1,10 +1,12 @@
 use std::ops::Range;
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use common::{
     StreamSettings,
     api_bindings::{
-        GeneralClientMessage, GeneralServerMessage, StreamerStatsUpdate, TransportChannelId,
+        GeneralClientMessage, GeneralServerMessage, StatsClientMessage, StreamerStatsUpdate,
+        TransportChannelId,
     },
     ipc::{ServerIpcMessage, StreamerIpcMessage},
 };
@@ -20,7 +22,7 @@ use moonlight_common::stream::{
 use num::FromPrimitive;
 use thiserror::Error;
 
-use crate::buffer::ByteBuffer;
+use crate::{buffer::ByteBuffer, quality::VideoPacketCounters};
 
 pub mod web_socket;
 pub mod webrtc;
@@ -44,6 +46,9 @@ pub enum InboundPacket {
     General {
         message: GeneralClientMessage,
     },
+    Stats {
+        message: StatsClientMessage,
+    },
     MouseMove {
         delta_x: i16,
         delta_y: i16,
@@ -104,6 +109,14 @@ pub enum InboundPacket {
         rotation: Option<u16>,
         event_type: TouchEventType,
     },
+    File(FileTransferPacket),
+}
+
+#[derive(Debug)]
+pub enum FileTransferPacket {
+    Start { id: u32, size: u32, name: String },
+    Chunk { id: u32, offset: u32, data: Vec<u8> },
+    Cancel { id: u32 },
 }
 
 impl InboundPacket {
@@ -133,7 +146,9 @@ impl InboundPacket {
         let mut buffer = ByteBuffer::new(bytes);
 
         match channel {
-            TransportChannel(TransportChannelId::GENERAL) => {
+            TransportChannel(
+                TransportChannelId::GENERAL | TransportChannelId::GENERAL_UNRELIABLE,
+            ) => {
                 if buffer.remaining() < 2 {
                     warn!("[InboudPacket]: failed to read general message");
                     return None;
@@ -158,8 +173,28 @@ impl InboundPacket {
                 Some(Self::General { message })
             }
             TransportChannel(TransportChannelId::STATS) => {
-                warn!("[InboundPacket]: tried to deserialize stats packet, this shouldn't happen");
-                None
+                if buffer.remaining() < 2 {
+                    warn!("[InboudPacket]: failed to read stats message");
+                    return None;
+                }
+
+                let len = buffer.get_u16();
+                let text = match buffer.get_utf8_raw(len as usize) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        warn!("[InboudPacket]: failed to read a stats message: {err}");
+                        return None;
+                    }
+                };
+                let message = match serde_json::from_str(text) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        warn!("[InboudPacket]: failed to deserialize stats message: {err}");
+                        return None;
+                    }
+                };
+
+                Some(Self::Stats { message })
             }
             TransportChannel(TransportChannelId::HOST_VIDEO) => {
                 warn!(
@@ -378,9 +413,16 @@ impl InboundPacket {
                             Self::DEFAULT_CONTROLLER_CAPABILITIES
                         });
 
+                    // Older clients don't send the type
+                    let ty = if buffer.remaining() >= 1 {
+                        controller_type_from_u8(buffer.get_u8())
+                    } else {
+                        ControllerType::Unknown
+                    };
+
                     Some(InboundPacket::ControllerConnected {
                         id,
-                        ty: ControllerType::Unknown,
+                        ty,
                         supported_buttons,
                         capabilities,
                     })
@@ -401,12 +443,14 @@ impl InboundPacket {
                     None
                 }
             }
-            TransportChannel(channel_id)
-                if let Some((gamepad_id, _)) = Self::CONTROLLER_CHANNELS
+            TransportChannel(channel_id) if Self::CONTROLLER_CHANNELS.contains(&channel_id) => {
+                let Some(gamepad_id) = Self::CONTROLLER_CHANNELS
                     .iter()
-                    .enumerate()
-                    .find(|(_, cmp_channel_id)| **cmp_channel_id == channel_id) =>
-            {
+                    .position(|cmp_channel_id| *cmp_channel_id == channel_id)
+                else {
+                    return None;
+                };
+
                 if buffer.remaining() < 1 {
                     warn!(
                         "[InboudPacket]: failed to read controller state message {channel_id}, gamepad: {gamepad_id}"
@@ -452,11 +496,78 @@ impl InboundPacket {
                     None
                 }
             }
+            TransportChannel(TransportChannelId::FILE) => {
+                if buffer.remaining() < 5 {
+                    warn!("[InboudPacket]: failed to read file message");
+                    return None;
+                }
+
+                let ty = buffer.get_u8();
+                let id = buffer.get_u32();
+                if ty == 0 {
+                    // Start
+                    if buffer.remaining() < 6 {
+                        warn!("[InboudPacket]: failed to read file start message");
+                        return None;
+                    }
+
+                    let size = buffer.get_u32();
+                    let len = buffer.get_u16();
+                    let Ok(name) = buffer.get_utf8_raw(len as usize) else {
+                        warn!("[InboundPacket]: received invalid file name");
+                        return None;
+                    };
+
+                    Some(InboundPacket::File(FileTransferPacket::Start {
+                        id,
+                        size,
+                        name: name.to_owned(),
+                    }))
+                } else if ty == 1 {
+                    // Chunk
+                    if buffer.remaining() < 4 {
+                        warn!("[InboudPacket]: failed to read file chunk message");
+                        return None;
+                    }
+
+                    let offset = buffer.get_u32();
+
+                    let mut data = vec![0; buffer.remaining()];
+                    buffer.get_u8_array(&mut data);
+
+                    Some(InboundPacket::File(FileTransferPacket::Chunk {
+                        id,
+                        offset,
+                        data,
+                    }))
+                } else if ty == 2 {
+                    // Cancel
+                    Some(InboundPacket::File(FileTransferPacket::Cancel { id }))
+                } else {
+                    warn!(
+                        "[InboundPacket]: tried to deserialize file packet with type {ty}, this shouldn't happen"
+                    );
+                    None
+                }
+            }
             _ => None,
         }
     }
 }
 
+fn controller_type_from_u8(value: u8) -> ControllerType {
+    const XBOX: u8 = ControllerType::Xbox as u8;
+    const PLAY_STATION: u8 = ControllerType::PlayStation as u8;
+    const NINTENDO: u8 = ControllerType::Nintendo as u8;
+
+    match value {
+        XBOX => ControllerType::Xbox,
+        PLAY_STATION => ControllerType::PlayStation,
+        NINTENDO => ControllerType::Nintendo,
+        _ => ControllerType::Unknown,
+    }
+}
+
 #[derive(Debug)]
 pub enum OutboundPacket {
     General {
@@ -473,9 +584,61 @@ pub enum OutboundPacket {
         left_trigger_motor: u16,
         right_trigger_motor: u16,
     },
+    FileTransfer {
+        id: u32,
+        status: FileTransferStatus,
+    },
+    /// The absolute cursor position which was forwarded to the host
+    CursorPosition {
+        x: i16,
+        y: i16,
+        reference_width: i16,
+        reference_height: i16,
+    },
+}
+
+/// How reliable an [OutboundPacket] must be delivered, transports without a choice deliver everything reliably
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketQos {
+    /// Reliable and ordered, e.g. answers to requests of the client
+    Reliable,
+    /// Unreliable and unordered, e.g. status updates which are outdated by the next one
+    Unreliable,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FileTransferStatus {
+    Progress {
+        received: u32,
+    },
+    Complete,
+    /// Look at FileTransferErrorCode
+    Failed {
+        code: u8,
+    },
 }
 
 impl OutboundPacket {
+    pub fn qos(&self) -> PacketQos {
+        match self {
+            Self::General {
+                message: GeneralServerMessage::ConnectionStatusUpdate { .. },
+            } => PacketQos::Unreliable,
+            Self::General {
+                message: GeneralServerMessage::MacroSaved { .. },
+            } => PacketQos::Reliable,
+            Self::General {
+                message: GeneralServerMessage::QualityScore { .. },
+            } => PacketQos::Unreliable,
+            Self::Stats(_) => PacketQos::Unreliable,
+            Self::ControllerRumble { .. } | Self::ControllerTriggerRumble { .. } => {
+                PacketQos::Unreliable
+            }
+            Self::FileTransfer { .. } => PacketQos::Reliable,
+            Self::CursorPosition { .. } => PacketQos::Unreliable,
+        }
+    }
+
     pub fn serialize(&self, raw_buffer: &mut Vec<u8>) -> Option<(TransportChannel, Range<usize>)> {
         match self {
             Self::General { message } => {
@@ -489,11 +652,13 @@ impl OutboundPacket {
                 buffer.put_u16(text.len() as u16);
                 buffer.put_utf8_raw(&text);
 
+                let channel = match self.qos() {
+                    PacketQos::Reliable => TransportChannelId::GENERAL,
+                    PacketQos::Unreliable => TransportChannelId::GENERAL_UNRELIABLE,
+                };
+
                 buffer.flip();
-                Some((
-                    TransportChannel(TransportChannelId::GENERAL),
-                    buffer.into_raw().1,
-                ))
+                Some((TransportChannel(channel), buffer.into_raw().1))
             }
             Self::Stats(stats) => {
                 let Ok(text) = serde_json::to_string(&stats) else {
@@ -552,15 +717,73 @@ impl OutboundPacket {
                     buffer.into_raw().1,
                 ))
             }
+            Self::FileTransfer { id, status } => {
+                raw_buffer.resize(9, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                // Requires 5 - 9 bytes
+                match status {
+                    FileTransferStatus::Progress { received } => {
+                        buffer.put_u8(0);
+                        buffer.put_u32(*id);
+                        buffer.put_u32(*received);
+                    }
+                    FileTransferStatus::Complete => {
+                        buffer.put_u8(1);
+                        buffer.put_u32(*id);
+                    }
+                    FileTransferStatus::Failed { code } => {
+                        buffer.put_u8(2);
+                        buffer.put_u32(*id);
+                        buffer.put_u8(*code);
+                    }
+                }
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::FILE),
+                    buffer.into_raw().1,
+                ))
+            }
+            Self::CursorPosition {
+                x,
+                y,
+                reference_width,
+                reference_height,
+            } => {
+                raw_buffer.resize(9, 0);
+                let mut buffer = ByteBuffer::new(raw_buffer as &mut [u8]);
+
+                // Requires 9 bytes
+                buffer.put_u8(0);
+                buffer.put_i16(*x);
+                buffer.put_i16(*y);
+                buffer.put_i16(*reference_width);
+                buffer.put_i16(*reference_height);
+
+                buffer.flip();
+                Some((
+                    TransportChannel(TransportChannelId::CURSOR),
+                    buffer.into_raw().1,
+                ))
+            }
         }
     }
 }
 
 #[derive(Debug)]
 pub enum TransportEvent {
-    StartStream { settings: StreamSettings },
-    RecvPacket(InboundPacket),
+    StartStream {
+        settings: StreamSettings,
+    },
+    /// Deserialized with [InboundPacket::deserialize] by the receiver, so it can be recorded
+    RecvPacket {
+        channel: TransportChannel,
+        data: Bytes,
+    },
     SendIpc(StreamerIpcMessage),
+    /// The peer of the client connected for the first time
+    PeerConnected,
     Closed,
 }
 
@@ -585,6 +808,9 @@ pub trait TransportSender {
 
     async fn send(&self, packet: OutboundPacket) -> Result<(), TransportError>;
 
+    /// None if the transport doesn't lose packets
+    async fn video_packet_counters(&self) -> Option<VideoPacketCounters>;
+
     async fn on_ipc_message(&self, message: ServerIpcMessage) -> Result<(), TransportError>;
 
     async fn close(&self) -> Result<(), TransportError>;
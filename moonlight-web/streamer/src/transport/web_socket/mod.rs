@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use common::{
     StreamSettings,
-    api_bindings::{StreamClientMessage, TransportChannelId},
+    api_bindings::{StreamClientMessage, StreamVideoRotation, TransportChannelId},
     ipc::{ServerIpcMessage, StreamerIpcMessage},
 };
 use log::{trace, warn};
@@ -17,9 +17,11 @@ use tokio::sync::mpsc::{Receiver, Sender, channel};
 
 use crate::{
     buffer::ByteBuffer,
+    memory::{BufferKind, buffer_pool},
+    quality::VideoPacketCounters,
     transport::{
-        InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
-        TransportEvents, TransportSender,
+        OutboundPacket, TransportChannel, TransportError, TransportEvent, TransportEvents,
+        TransportSender,
     },
 };
 
@@ -63,7 +65,20 @@ impl TransportSender for WebSocketTransportSender {
         &'a self,
         unit: &'a VideoDecodeUnit<'a>,
     ) -> Result<DecodeResult, TransportError> {
-        let mut new_buffer = vec![0; 5];
+        // Channel id, frame type and presentation time
+        const HEADER_LEN: usize = 6;
+
+        let len = HEADER_LEN
+            + unit
+                .buffers
+                .iter()
+                .map(|buffer| buffer.data.len())
+                .sum::<usize>();
+        let Some(mut new_buffer) = buffer_pool().allocate(BufferKind::Video, len) else {
+            // The following frames can't be decoded without this one
+            return Ok(DecodeResult::NeedIdr);
+        };
+        new_buffer.resize(HEADER_LEN, 0);
 
         let mut byte_buffer = ByteBuffer::new(new_buffer.as_mut_slice());
         byte_buffer.put_u8(TransportChannelId::HOST_VIDEO);
@@ -79,7 +94,7 @@ impl TransportSender for WebSocketTransportSender {
         // TODO: ignore h264/h265 fillerdata?
         self.event_sender
             .send(TransportEvent::SendIpc(
-                StreamerIpcMessage::WebSocketTransport(Bytes::from(new_buffer)),
+                StreamerIpcMessage::WebSocketTransport(new_buffer.freeze()),
             ))
             .await
             .unwrap();
@@ -96,16 +111,16 @@ impl TransportSender for WebSocketTransportSender {
         0
     }
     async fn send_audio_sample(&self, data: &[u8]) -> Result<(), TransportError> {
-        let mut new_buffer = vec![0];
-
-        let mut byte_buffer = ByteBuffer::new(new_buffer.as_mut_slice());
-        byte_buffer.put_u8(TransportChannelId::HOST_AUDIO);
+        let Some(mut new_buffer) = buffer_pool().allocate(BufferKind::Audio, 1 + data.len()) else {
+            return Ok(());
+        };
 
+        new_buffer.push(TransportChannelId::HOST_AUDIO);
         new_buffer.extend_from_slice(data);
 
         self.event_sender
             .send(TransportEvent::SendIpc(
-                StreamerIpcMessage::WebSocketTransport(Bytes::from(new_buffer)),
+                StreamerIpcMessage::WebSocketTransport(new_buffer.freeze()),
             ))
             .await
             .unwrap();
@@ -145,15 +160,11 @@ impl TransportSender for WebSocketTransportSender {
 
                 let channel_id = message[0];
 
-                let Some(packet) =
-                    InboundPacket::deserialize(TransportChannel(channel_id), &message[1..])
-                else {
-                    warn!("Failed to receive packet on channel {channel_id}");
-                    return Ok(());
-                };
-
                 self.event_sender
-                    .send(TransportEvent::RecvPacket(packet))
+                    .send(TransportEvent::RecvPacket {
+                        channel: TransportChannel(channel_id),
+                        data: message.slice(1..),
+                    })
                     .await
                     .unwrap();
             }
@@ -163,16 +174,24 @@ impl TransportSender for WebSocketTransportSender {
                 fps,
                 width,
                 height,
-                play_audio_local,
+                audio_routing,
                 video_supported_formats,
                 video_colorspace,
                 video_color_range_full,
+                video_rotation,
+                display_id,
+                // The web server already replaced the settings with the ones of the preset
+                preset: _,
             }) => {
                 let video_supported_formats = SupportedVideoFormats::from_bits(video_supported_formats).unwrap_or_else(|| {
                     warn!("Failed to deserialize SupportedVideoFormats: {video_supported_formats}, falling back to only H264");
                     SupportedVideoFormats::H264
                 });
 
+                if video_rotation != StreamVideoRotation::Rotate0 {
+                    warn!("Video rotation is only supported by the WebRTC transport, ignoring it");
+                }
+
                 self.event_sender
                     .send(TransportEvent::StartStream {
                         settings: StreamSettings {
@@ -184,7 +203,9 @@ impl TransportSender for WebSocketTransportSender {
                             video_supported_formats,
                             video_color_range_full,
                             video_colorspace: video_colorspace.into(),
-                            play_audio_local,
+                            audio_routing: audio_routing.into(),
+                            video_rotation,
+                            display_id,
                         },
                     })
                     .await
@@ -195,8 +216,73 @@ impl TransportSender for WebSocketTransportSender {
         Ok(())
     }
 
+    async fn video_packet_counters(&self) -> Option<VideoPacketCounters> {
+        // Web sockets retransmit lost packets themselves
+        None
+    }
+
     async fn close(&self) -> Result<(), TransportError> {
         // emtpy
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use common::{api_bindings::TransportChannelId, ipc::StreamerIpcMessage};
+    use moonlight_test_harness::{FakeAudioSource, FakeVideoSource};
+
+    use crate::transport::{
+        TransportEvent, TransportEvents, TransportSender,
+        web_socket::{WebSocketTransportEvents, new},
+    };
+
+    async fn next_message(events: &mut WebSocketTransportEvents) -> Bytes {
+        match events.poll_event().await {
+            Ok(TransportEvent::SendIpc(StreamerIpcMessage::WebSocketTransport(message))) => message,
+            other => panic!("expected a web socket message, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_video_units_are_framed() {
+        let (sender, mut events) = new().await.expect("web socket transport");
+        let mut source = FakeVideoSource::new(1280, 720, 60);
+
+        for expected_frame_type in [1, 0] {
+            let frame = source.next_frame();
+            let buffers = frame.buffers();
+
+            sender
+                .send_video_unit(&frame.unit(&buffers))
+                .await
+                .expect("send video unit");
+
+            let message = next_message(&mut events).await;
+            assert_eq!(message[0], TransportChannelId::HOST_VIDEO);
+            assert_eq!(message[1], expected_frame_type);
+            assert_eq!(
+                u32::from_be_bytes([message[2], message[3], message[4], message[5]]),
+                frame.presentation_time.as_micros() as u32
+            );
+            assert_eq!(&message[6..], frame.data());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audio_samples_are_framed() {
+        let (sender, mut events) = new().await.expect("web socket transport");
+        let mut source = FakeAudioSource::default();
+
+        let sample = source.next_sample();
+        sender
+            .send_audio_sample(&sample)
+            .await
+            .expect("send audio sample");
+
+        let message = next_message(&mut events).await;
+        assert_eq!(message[0], TransportChannelId::HOST_AUDIO);
+        assert_eq!(&message[1..], sample);
+    }
+}
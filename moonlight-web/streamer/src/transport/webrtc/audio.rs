@@ -1,6 +1,15 @@
-use std::{sync::Weak, time::Duration};
+use std::{
+    collections::VecDeque,
+    mem::take,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
+use common::{
+    api_bindings::StreamerStatsUpdate,
+    config::{AudioJitterBufferConfig, OpusConfig},
+};
 use log::{error, warn};
 use moonlight_common::stream::bindings::{AudioConfig, OpusMultistreamConfig};
 use tokio::runtime::Handle;
@@ -12,16 +21,26 @@ use webrtc::{
     track::track_local::track_local_static_sample::TrackLocalStaticSample,
 };
 
-use crate::transport::webrtc::{WebRtcInner, sender::TrackLocalSender};
+use crate::{
+    memory::{BufferKind, buffer_pool},
+    transport::webrtc::{
+        WebRtcInner,
+        priority::{MediaKind, MediaPriority},
+        sender::TrackLocalSender,
+    },
+};
 
-pub fn register_audio_codecs(media_engine: &mut MediaEngine) -> Result<(), webrtc::Error> {
+pub fn register_audio_codecs(
+    media_engine: &mut MediaEngine,
+    opus: &OpusConfig,
+) -> Result<(), webrtc::Error> {
     media_engine.register_codec(
         RTCRtpCodecParameters {
             capability: RTCRtpCodecCapability {
                 mime_type: MIME_TYPE_OPUS.to_owned(),
                 clock_rate: 48000,
                 channels: 2,
-                sdp_fmtp_line: "minptime=10;useinbandfec=1".to_owned(),
+                sdp_fmtp_line: opus.sdp_fmtp_line(),
                 rtcp_feedback: vec![],
             },
             payload_type: 111,
@@ -36,13 +55,29 @@ pub fn register_audio_codecs(media_engine: &mut MediaEngine) -> Result<(), webrt
 pub struct WebRtcAudio {
     sender: TrackLocalSender<TrackLocalStaticSample>,
     config: Option<OpusMultistreamConfig>,
+    jitter_buffer_config: AudioJitterBufferConfig,
+    jitter_buffer: Option<AudioJitterBuffer>,
 }
 
 impl WebRtcAudio {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, channel_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        channel_queue_size: usize,
+        jitter_buffer_config: AudioJitterBufferConfig,
+        priority: Arc<MediaPriority>,
+    ) -> Self {
         Self {
-            sender: TrackLocalSender::new(runtime, peer, channel_queue_size),
+            sender: TrackLocalSender::new(
+                runtime,
+                peer,
+                channel_queue_size,
+                MediaKind::Audio,
+                priority,
+            ),
             config: None,
+            jitter_buffer_config,
+            jitter_buffer: None,
         }
     }
 }
@@ -87,6 +122,12 @@ impl WebRtcAudio {
             return -1;
         };
 
+        if self.jitter_buffer_config.enabled {
+            self.jitter_buffer = Some(AudioJitterBuffer::new(
+                self.jitter_buffer_config.clone(),
+                frame_duration(&stream_config),
+            ));
+        }
         self.config = Some(stream_config);
 
         // Renegotiate
@@ -102,22 +143,255 @@ impl WebRtcAudio {
             return;
         };
 
-        let duration =
-            Duration::from_secs_f64(config.samples_per_frame as f64 / config.sample_rate as f64);
-
-        let data = Bytes::copy_from_slice(data);
+        let duration = frame_duration(config);
 
-        let sample = Sample {
-            data,
-            duration,
-            // Time should be set if you want fine-grained sync
-            ..Default::default()
+        // The memory stays reserved while the sample waits in the jitter buffer or the queue
+        let Some(mut buffer) = buffer_pool().allocate(BufferKind::Audio, data.len()) else {
+            return;
         };
+        buffer.extend_from_slice(data);
+        let data = buffer.freeze();
+
+        if let Some(jitter_buffer) = self.jitter_buffer.as_mut() {
+            jitter_buffer.push(data, Instant::now());
+            return;
+        }
 
-        self.sender.send_samples(vec![sample], false).await;
+        self.sender
+            .send_samples(vec![create_sample(data, duration, 0)], false, None)
+            .await;
+    }
+
+    /// Writes the next sample of the jitter buffer to the track, this must be called once per audio frame.
+    /// Returns None if the jitter buffer is disabled, else the duration until the next call and the stats if they should be sent.
+    pub async fn playout(&mut self) -> Option<(Duration, Option<StreamerStatsUpdate>)> {
+        let jitter_buffer = self.jitter_buffer.as_mut()?;
+
+        let now = Instant::now();
+        if let Some((data, missing)) = jitter_buffer.pop() {
+            self.sender
+                .send_samples(
+                    vec![create_sample(data, jitter_buffer.frame_duration, missing)],
+                    false,
+                    None,
+                )
+                .await;
+        }
+
+        Some((jitter_buffer.frame_duration, jitter_buffer.take_stats(now)))
     }
 
     fn config(&self) -> AudioConfig {
         AudioConfig::STEREO
     }
 }
+
+fn frame_duration(config: &OpusMultistreamConfig) -> Duration {
+    Duration::from_secs_f64(config.samples_per_frame as f64 / config.sample_rate as f64)
+}
+
+fn create_sample(data: Bytes, duration: Duration, missing: u16) -> Sample {
+    Sample {
+        data,
+        duration,
+        // The timestamp skips the missing samples so that the browser conceals them
+        prev_dropped_packets: missing,
+        // Time should be set if you want fine-grained sync
+        ..Default::default()
+    }
+}
+
+/// Holds back audio samples until the target delay is reached and releases them once per frame.
+/// The target delay grows with the jitter of the arrival times.
+/// Samples which are missing when they should be played are left to the packet loss concealment of the browser.
+struct AudioJitterBuffer {
+    config: AudioJitterBufferConfig,
+    frame_duration: Duration,
+    samples: VecDeque<Bytes>,
+    last_arrival: Option<Instant>,
+    /// Smoothed deviation of the arrival intervals from the frame duration, like the interarrival jitter of RFC 3550
+    jitter: Duration,
+    started: bool,
+    buffering: bool,
+    /// Frames which weren't played since the last played sample
+    missing: u16,
+    // Stats
+    last_stats: Option<Instant>,
+    underruns: u32,
+    dropped: u32,
+}
+
+impl AudioJitterBuffer {
+    fn new(config: AudioJitterBufferConfig, frame_duration: Duration) -> Self {
+        Self {
+            config,
+            frame_duration,
+            samples: VecDeque::new(),
+            last_arrival: None,
+            jitter: Duration::ZERO,
+            started: false,
+            buffering: true,
+            missing: 0,
+            last_stats: None,
+            underruns: 0,
+            dropped: 0,
+        }
+    }
+
+    fn target_delay(&self) -> Duration {
+        let max_delay = self.config.max_delay.max(self.config.target_delay);
+
+        (self.jitter * 2).clamp(self.config.target_delay, max_delay)
+    }
+
+    fn frames_in(&self, delay: Duration) -> usize {
+        (delay.as_secs_f64() / self.frame_duration.as_secs_f64()).ceil() as usize
+    }
+
+    fn push(&mut self, data: Bytes, now: Instant) {
+        if let Some(last_arrival) = self.last_arrival {
+            let deviation = (now - last_arrival).abs_diff(self.frame_duration);
+
+            self.jitter = (self.jitter * 15 + deviation) / 16;
+        }
+        self.last_arrival = Some(now);
+
+        self.samples.push_back(data);
+
+        // Catch up after a burst instead of keeping the latency
+        let max_frames = self.frames_in(self.config.max_delay).max(1);
+        while self.samples.len() > max_frames {
+            self.samples.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Returns the next sample and how many frames are missing before it
+    fn pop(&mut self) -> Option<(Bytes, u16)> {
+        if self.buffering && self.samples.len() < self.frames_in(self.target_delay()) {
+            if self.started {
+                self.missing = self.missing.saturating_add(1);
+            }
+            return None;
+        }
+        self.buffering = false;
+
+        let Some(data) = self.samples.pop_front() else {
+            // Fill up to the target delay again
+            self.buffering = true;
+            self.underruns += 1;
+            self.missing = self.missing.saturating_add(1);
+
+            return None;
+        };
+        self.started = true;
+
+        Some((data, take(&mut self.missing)))
+    }
+
+    fn take_stats(&mut self, now: Instant) -> Option<StreamerStatsUpdate> {
+        if self
+            .last_stats
+            .is_some_and(|last_stats| now - last_stats < Duration::from_secs(1))
+        {
+            return None;
+        }
+        self.last_stats = Some(now);
+
+        Some(StreamerStatsUpdate::AudioJitterBuffer {
+            delay_ms: (self.frame_duration * self.samples.len() as u32).as_secs_f64() * 1000.0,
+            target_delay_ms: self.target_delay().as_secs_f64() * 1000.0,
+            jitter_ms: self.jitter.as_secs_f64() * 1000.0,
+            underruns: take(&mut self.underruns),
+            dropped: take(&mut self.dropped),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use common::config::AudioJitterBufferConfig;
+
+    use crate::transport::webrtc::audio::AudioJitterBuffer;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    fn config() -> AudioJitterBufferConfig {
+        AudioJitterBufferConfig {
+            enabled: true,
+            target_delay: Duration::from_millis(20),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+
+    fn sample(value: u8) -> Bytes {
+        Bytes::from(vec![value])
+    }
+
+    #[test]
+    fn test_waits_for_target_delay() {
+        let mut buffer = AudioJitterBuffer::new(config(), FRAME);
+        let start = Instant::now();
+
+        buffer.push(sample(0), start);
+        assert_eq!(buffer.pop(), None);
+
+        buffer.push(sample(1), start + FRAME);
+        assert_eq!(buffer.pop(), Some((sample(0), 0)));
+        assert_eq!(buffer.pop(), Some((sample(1), 0)));
+    }
+
+    #[test]
+    fn test_underrun_reports_missing_frames() {
+        let mut buffer = AudioJitterBuffer::new(config(), FRAME);
+        let start = Instant::now();
+
+        buffer.push(sample(0), start);
+        buffer.push(sample(1), start + FRAME);
+        assert_eq!(buffer.pop(), Some((sample(0), 0)));
+        assert_eq!(buffer.pop(), Some((sample(1), 0)));
+
+        // Nothing arrived in time
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.underruns, 1);
+
+        buffer.push(sample(2), start + FRAME * 3);
+        assert_eq!(buffer.pop(), None);
+        buffer.push(sample(3), start + FRAME * 4);
+        assert_eq!(buffer.pop(), Some((sample(2), 2)));
+    }
+
+    #[test]
+    fn test_drops_above_max_delay() {
+        let mut buffer = AudioJitterBuffer::new(config(), FRAME);
+        let start = Instant::now();
+
+        for i in 0..8 {
+            buffer.push(sample(i), start);
+        }
+
+        assert_eq!(buffer.samples.len(), 5);
+        assert_eq!(buffer.dropped, 3);
+        assert_eq!(buffer.pop(), Some((sample(3), 0)));
+    }
+
+    #[test]
+    fn test_target_delay_follows_jitter() {
+        let mut buffer = AudioJitterBuffer::new(config(), FRAME);
+        let mut now = Instant::now();
+
+        // Samples arrive in bursts of four every 40ms
+        for i in 0..100 {
+            if i % 4 == 0 {
+                now += FRAME * 4;
+            }
+            buffer.push(sample(0), now);
+        }
+
+        assert!(buffer.target_delay() > config().target_delay);
+        assert!(buffer.target_delay() <= config().max_delay);
+    }
+}
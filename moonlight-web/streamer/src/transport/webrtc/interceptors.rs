@@ -0,0 +1,103 @@
+use common::config::WebRtcInterceptorConfig;
+use webrtc::{
+    api::media_engine::MediaEngine,
+    interceptor::{
+        nack::{generator::Generator, responder::Responder},
+        registry::Registry,
+        report::{receiver::ReceiverReport, sender::SenderReport},
+        twcc::receiver::Receiver as TwccReceiver,
+    },
+    rtp_transceiver::{
+        RTCPFeedback, TYPE_RTCP_FB_NACK, TYPE_RTCP_FB_TRANSPORT_CC,
+        rtp_codec::{RTCRtpHeaderExtensionCapability, RTPCodecType},
+    },
+    sdp::extmap::TRANSPORT_CC_URI,
+};
+
+/// The same interceptors as `register_default_interceptors`, but tuned by the config
+pub fn register_interceptors(
+    mut registry: Registry,
+    api_media: &mut MediaEngine,
+    config: &WebRtcInterceptorConfig,
+) -> Result<Registry, webrtc::Error> {
+    // -- NACK
+    api_media.register_feedback(
+        RTCPFeedback {
+            typ: TYPE_RTCP_FB_NACK.to_string(),
+            parameter: String::new(),
+        },
+        RTPCodecType::Video,
+    );
+    api_media.register_feedback(
+        RTCPFeedback {
+            typ: TYPE_RTCP_FB_NACK.to_string(),
+            parameter: "pli".to_string(),
+        },
+        RTPCodecType::Video,
+    );
+
+    registry.add(Box::new(Responder::builder().with_log2_size_minus_6(
+        log2_size_minus_6(config.nack_responder_size),
+    )));
+    registry.add(Box::new(
+        Generator::builder()
+            .with_log2_size_minus_6(log2_size_minus_6(config.nack_generator_size))
+            .with_interval(config.nack_generator_interval),
+    ));
+
+    // -- RTCP Reports
+    registry.add(Box::new(
+        ReceiverReport::builder().with_interval(config.rtcp_report_interval),
+    ));
+    registry.add(Box::new(
+        SenderReport::builder().with_interval(config.rtcp_report_interval),
+    ));
+
+    // -- TWCC
+    if config.twcc {
+        for codec_type in [RTPCodecType::Video, RTPCodecType::Audio] {
+            api_media.register_feedback(
+                RTCPFeedback {
+                    typ: TYPE_RTCP_FB_TRANSPORT_CC.to_string(),
+                    parameter: String::new(),
+                },
+                codec_type,
+            );
+            api_media.register_header_extension(
+                RTCRtpHeaderExtensionCapability {
+                    uri: TRANSPORT_CC_URI.to_string(),
+                },
+                codec_type,
+                None,
+            )?;
+        }
+
+        registry.add(Box::new(
+            TwccReceiver::builder().with_interval(config.twcc_interval),
+        ));
+    }
+
+    Ok(registry)
+}
+
+/// The nack buffers only support powers of two from 64 to 32768
+fn log2_size_minus_6(size: u16) -> u8 {
+    let log2 = u32::from(size).max(1).next_power_of_two().trailing_zeros() as u8;
+
+    log2.clamp(6, 15) - 6
+}
+
+#[cfg(test)]
+mod test {
+    use crate::transport::webrtc::interceptors::log2_size_minus_6;
+
+    #[test]
+    fn test_nack_size_rounding() {
+        assert_eq!(log2_size_minus_6(0), 0);
+        assert_eq!(log2_size_minus_6(64), 0);
+        assert_eq!(log2_size_minus_6(512), 3);
+        assert_eq!(log2_size_minus_6(1000), 4);
+        assert_eq!(log2_size_minus_6(32768), 9);
+        assert_eq!(log2_size_minus_6(u16::MAX), 9);
+    }
+}
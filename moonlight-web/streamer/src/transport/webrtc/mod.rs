@@ -1,10 +1,14 @@
 use std::{
     future::ready,
     pin::Pin,
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
     time::{Duration, Instant},
 };
 
+use anyhow::{anyhow, bail};
 use async_trait::async_trait;
 use bytes::Bytes;
 use common::{
@@ -13,7 +17,9 @@ use common::{
         RtcIceCandidate, RtcSdpType, RtcSessionDescription, StreamClientMessage,
         StreamServerMessage, StreamSignalingMessage, TransportChannelId,
     },
-    config::{PortRange, WebRtcConfig},
+    config::{
+        DataChannelBackpressureConfig, MediaPriorityConfig, PortRange, VideoMtuConfig, WebRtcConfig,
+    },
     ipc::{ServerIpcMessage, StreamerIpcMessage},
 };
 use log::{debug, error, info, trace, warn};
@@ -24,21 +30,25 @@ use moonlight_common::stream::{
     video::VideoSetup,
 };
 use tokio::{
+    pin,
     runtime::Handle,
     spawn,
     sync::{
-        Mutex,
+        Mutex, Notify,
         mpsc::{Receiver, Sender, channel},
     },
-    time::sleep,
+    time::{Instant as TokioInstant, sleep, sleep_until, timeout},
 };
 use webrtc::{
-    api::{
-        APIBuilder, interceptor_registry::register_default_interceptors, media_engine::MediaEngine,
-        setting_engine::SettingEngine,
+    api::{API, APIBuilder, media_engine::MediaEngine, setting_engine::SettingEngine},
+    data_channel::{
+        RTCDataChannel, data_channel_init::RTCDataChannelInit,
+        data_channel_message::DataChannelMessage, data_channel_state::RTCDataChannelState,
+    },
+    ice::{
+        candidate::CandidateType,
+        udp_network::{EphemeralUDP, UDPNetwork},
     },
-    data_channel::{RTCDataChannel, data_channel_message::DataChannelMessage},
-    ice::udp_network::{EphemeralUDP, UDPNetwork},
     ice_transport::{
         ice_candidate::{RTCIceCandidate, RTCIceCandidateInit},
         ice_connection_state::RTCIceConnectionState,
@@ -50,19 +60,23 @@ use webrtc::{
         peer_connection_state::RTCPeerConnectionState,
         sdp::{sdp_type::RTCSdpType, session_description::RTCSessionDescription},
     },
+    stats::StatsReportType,
 };
 
 use crate::{
     convert::{
         from_webrtc_sdp, into_webrtc_ice, into_webrtc_ice_candidate, into_webrtc_network_type,
     },
+    quality::VideoPacketCounters,
     transport::{
-        InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
+        InboundPacket, OutboundPacket, PacketQos, TransportChannel, TransportError, TransportEvent,
         TransportEvents, TransportSender,
         webrtc::{
             audio::{WebRtcAudio, register_audio_codecs},
+            interceptors::register_interceptors,
+            priority::MediaPriority,
             sender::register_header_extensions,
-            video::{WebRtcVideo, register_video_codecs},
+            video::{WebRtcVideo, path_video_mtu, register_video_codecs},
         },
     },
 };
@@ -70,25 +84,212 @@ use crate::{
 pub const TIMEOUT_DURATION: Duration = Duration::from_secs(10);
 
 mod audio;
+mod interceptors;
+mod priority;
 mod sender;
-mod video;
+pub(crate) mod video;
+
+pub use video::payloader_self_test;
+
+/// How often a packet which waits for a data channel checks if the channel closed
+const BACKPRESSURE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
 
 struct WebRtcInner {
     peer: Arc<RTCPeerConnection>,
     event_sender: Sender<TransportEvent>,
-    general_channel: Arc<RTCDataChannel>,
-    stats_channel: Mutex<Option<Arc<RTCDataChannel>>>,
+    /// Indexed by the [TransportChannelId], none for the media tracks
+    data_channels: Vec<Option<Arc<RTCDataChannel>>>,
+    backpressure: DataChannelBackpressureConfig,
+    video_mtu: VideoMtuConfig,
+    /// Notified once any data channel buffers less than the low threshold
+    buffered_amount_low: Arc<Notify>,
     video: Mutex<WebRtcVideo>,
     audio: Mutex<WebRtcAudio>,
+    audio_playout_started: AtomicBool,
     // Timeout / Terminate
     pub timeout_terminate_request: Mutex<Option<Instant>>,
 }
 
 pub async fn new(
     config: &WebRtcConfig,
+    media_priority: &MediaPriorityConfig,
     video_frame_queue_size: usize,
     audio_sample_queue_size: usize,
 ) -> Result<(WebRTCTransportSender, WebRTCTransportEvents), anyhow::Error> {
+    let (api, rtc_config) = create_api(config);
+
+    let (event_sender, event_receiver) = channel::<TransportEvent>(20);
+
+    let peer = Arc::new(api.new_peer_connection(rtc_config).await?);
+
+    let mut data_channels = Vec::new();
+    for options in data_channel_options() {
+        let id = options.id as usize;
+        if data_channels.len() <= id {
+            data_channels.resize(id + 1, None);
+        }
+
+        data_channels[id] = Some(create_negotiated_channel(&peer, &options).await?);
+    }
+
+    let runtime = Handle::current();
+    let priority = Arc::new(MediaPriority::new(media_priority.clone()));
+    let this_owned = Arc::new(WebRtcInner {
+        peer: peer.clone(),
+        event_sender,
+        data_channels,
+        backpressure: config.data_channel_backpressure.clone(),
+        video_mtu: config.video_mtu.clone(),
+        buffered_amount_low: Default::default(),
+        video: Mutex::new(WebRtcVideo::new(
+            runtime.clone(),
+            Arc::downgrade(&peer),
+            video_frame_queue_size,
+            priority.clone(),
+            path_video_mtu(config.video_mtu.mtu, false, false),
+        )),
+        audio: Mutex::new(WebRtcAudio::new(
+            runtime,
+            Arc::downgrade(&peer),
+            audio_sample_queue_size,
+            config.audio_jitter_buffer.clone(),
+            priority,
+        )),
+        audio_playout_started: AtomicBool::new(false),
+        timeout_terminate_request: Mutex::new(None),
+    });
+
+    let this = Arc::downgrade(&this_owned);
+
+    // -- Connection state
+    peer.on_ice_connection_state_change(create_event_handler(
+        this.clone(),
+        |this, state| async move {
+            this.on_ice_connection_state_change(state).await;
+        },
+    ));
+    peer.on_peer_connection_state_change(create_event_handler(
+        this.clone(),
+        |this, state| async move {
+            this.on_peer_connection_state_change(state).await;
+        },
+    ));
+
+    // -- Signaling
+    peer.on_ice_candidate(create_event_handler(
+        this.clone(),
+        |this, candidate| async move {
+            this.on_ice_candidate(candidate).await;
+        },
+    ));
+
+    // -- Data Channels
+    for (id, channel) in this_owned.data_channels.iter().enumerate() {
+        if let Some(channel) = channel {
+            channel.on_message(create_channel_message_handler(
+                this.clone(),
+                TransportChannel(id as u8),
+            ));
+
+            channel
+                .set_buffered_amount_low_threshold(this_owned.backpressure.low_threshold)
+                .await;
+            let buffered_amount_low = this_owned.buffered_amount_low.clone();
+            channel
+                .on_buffered_amount_low(Box::new(move || {
+                    buffered_amount_low.notify_waiters();
+                    Box::pin(ready(()))
+                }))
+                .await;
+        }
+    }
+
+    drop(peer);
+
+    Ok((
+        WebRTCTransportSender {
+            inner: this_owned.clone(),
+        },
+        WebRTCTransportEvents { event_receiver },
+    ))
+}
+
+struct DataChannelOptions {
+    id: u8,
+    label: String,
+    ordered: bool,
+    reliable: bool,
+}
+
+/// The data channels which are created by both peers, this must match `TRANSPORT_CHANNEL_OPTIONS` of the web client
+fn data_channel_options() -> Vec<DataChannelOptions> {
+    let options = |id: u8, label: &str, ordered: bool, reliable: bool| DataChannelOptions {
+        id,
+        label: label.to_string(),
+        ordered,
+        reliable,
+    };
+
+    let mut channels = vec![
+        options(TransportChannelId::GENERAL, "general", true, true),
+        options(
+            TransportChannelId::GENERAL_UNRELIABLE,
+            "general_unreliable",
+            false,
+            false,
+        ),
+        options(TransportChannelId::STATS, "stats", true, true),
+        options(
+            TransportChannelId::MOUSE_RELIABLE,
+            "mouse_reliable",
+            true,
+            true,
+        ),
+        options(
+            TransportChannelId::MOUSE_ABSOLUTE,
+            "mouse_absolute",
+            false,
+            false,
+        ),
+        options(
+            TransportChannelId::MOUSE_RELATIVE,
+            "mouse_relative",
+            false,
+            true,
+        ),
+        options(TransportChannelId::KEYBOARD, "keyboard", true, true),
+        options(TransportChannelId::TOUCH, "touch", true, true),
+        options(TransportChannelId::CONTROLLERS, "controllers", true, true),
+        options(TransportChannelId::FILE, "file", true, true),
+        options(TransportChannelId::CURSOR, "cursor", false, false),
+    ];
+
+    for (number, id) in InboundPacket::CONTROLLER_CHANNELS.into_iter().enumerate() {
+        channels.push(options(id, &format!("controller{number}"), false, false));
+    }
+
+    channels
+}
+
+/// Negotiated channels are created by both peers with the same id instead of being announced,
+/// so they can be used without waiting for the other peer and without matching their labels.
+async fn create_negotiated_channel(
+    peer: &RTCPeerConnection,
+    options: &DataChannelOptions,
+) -> Result<Arc<RTCDataChannel>, webrtc::Error> {
+    peer.create_data_channel(
+        &options.label,
+        Some(RTCDataChannelInit {
+            ordered: Some(options.ordered),
+            max_retransmits: (!options.reliable).then_some(0),
+            negotiated: Some(options.id as u16),
+            ..Default::default()
+        }),
+    )
+    .await
+}
+
+fn create_api(config: &WebRtcConfig) -> (API, RTCConfiguration) {
     // -- Configure WebRTC
     let rtc_config = RTCConfiguration {
         ice_servers: config
@@ -131,16 +332,15 @@ pub async fn new(
     // -- Register media codecs
     // TODO: register them based on the sdp
     let mut api_media = MediaEngine::default();
-    register_audio_codecs(&mut api_media).expect("failed to register audio codecs");
+    register_audio_codecs(&mut api_media, &config.opus).expect("failed to register audio codecs");
     register_video_codecs(&mut api_media).expect("failed to register video codecs");
     register_header_extensions(&mut api_media).expect("failed to register header extensions");
 
     // -- Build Api
     let mut api_registry = Registry::new();
 
-    // Use the default set of Interceptors
-    api_registry = register_default_interceptors(api_registry, &mut api_media)
-        .expect("failed to register webrtc default interceptors");
+    api_registry = register_interceptors(api_registry, &mut api_media, &config.interceptors)
+        .expect("failed to register webrtc interceptors");
 
     let api = APIBuilder::new()
         .with_setting_engine(api_settings)
@@ -148,76 +348,96 @@ pub async fn new(
         .with_interceptor_registry(api_registry)
         .build();
 
-    let (event_sender, event_receiver) = channel::<TransportEvent>(20);
+    (api, rtc_config)
+}
 
-    let peer = Arc::new(api.new_peer_connection(rtc_config).await?);
+/// Connects two local peers with each other and sends a message over a data channel.
+/// Loopback candidates are always included and nat mappings ignored so that the peers can reach each other.
+///
+/// Returns how long it took until the message was received.
+pub async fn loopback_test(config: &WebRtcConfig) -> Result<Duration, anyhow::Error> {
+    let mut config = config.clone();
+    config.include_loopback_candidates = true;
+    config.nat_1to1 = None;
 
-    let general_channel = peer.create_data_channel("general", None).await?;
+    let (api, _) = create_api(&config);
 
-    let runtime = Handle::current();
-    let this_owned = Arc::new(WebRtcInner {
-        peer: peer.clone(),
-        event_sender,
-        general_channel,
-        stats_channel: Mutex::new(None),
-        video: Mutex::new(WebRtcVideo::new(
-            runtime.clone(),
-            Arc::downgrade(&peer),
-            video_frame_queue_size,
-        )),
-        audio: Mutex::new(WebRtcAudio::new(
-            runtime,
-            Arc::downgrade(&peer),
-            audio_sample_queue_size,
-        )),
-        timeout_terminate_request: Mutex::new(None),
-    });
+    let start = Instant::now();
 
-    let this = Arc::downgrade(&this_owned);
+    let offerer = api.new_peer_connection(RTCConfiguration::default()).await?;
+    let answerer = api.new_peer_connection(RTCConfiguration::default()).await?;
 
-    // -- Connection state
-    peer.on_ice_connection_state_change(create_event_handler(
-        this.clone(),
-        async move |this, state| {
-            this.on_ice_connection_state_change(state).await;
-        },
-    ));
-    peer.on_peer_connection_state_change(create_event_handler(
-        this.clone(),
-        async move |this, state| {
-            this.on_peer_connection_state_change(state).await;
-        },
-    ));
+    let data_channel = offerer.create_data_channel("doctor", None).await?;
 
-    // -- Signaling
-    peer.on_ice_candidate(create_event_handler(
-        this.clone(),
-        async move |this, candidate| {
-            this.on_ice_candidate(candidate).await;
-        },
-    ));
+    let opened = Arc::new(Notify::new());
+    data_channel.on_open({
+        let opened = opened.clone();
+        Box::new(move || {
+            opened.notify_one();
+            Box::pin(ready(()))
+        })
+    });
 
-    // -- Data Channels
-    peer.on_data_channel(create_event_handler(
-        this.clone(),
-        async move |this, channel| {
-            this.on_data_channel(channel).await;
-        },
-    ));
+    let (message_sender, mut message_receiver) = channel::<Bytes>(1);
+    answerer.on_data_channel(Box::new(move |channel| {
+        let message_sender = message_sender.clone();
+        channel.on_message(Box::new(move |message| {
+            let message_sender = message_sender.clone();
+            Box::pin(async move {
+                let _ = message_sender.send(message.data).await;
+            })
+        }));
+        Box::pin(ready(()))
+    }));
+
+    // Exchange the descriptions after gathering all candidates so we don't need trickle ice
+    let offer = offerer.create_offer(None).await?;
+    let mut offer_gathered = offerer.gathering_complete_promise().await;
+    offerer.set_local_description(offer).await?;
+    let _ = offer_gathered.recv().await;
+
+    let offer = offerer
+        .local_description()
+        .await
+        .ok_or_else(|| anyhow!("the offer is missing"))?;
+    answerer.set_remote_description(offer).await?;
+
+    let answer = answerer.create_answer(None).await?;
+    let mut answer_gathered = answerer.gathering_complete_promise().await;
+    answerer.set_local_description(answer).await?;
+    let _ = answer_gathered.recv().await;
+
+    let answer = answerer
+        .local_description()
+        .await
+        .ok_or_else(|| anyhow!("the answer is missing"))?;
+    offerer.set_remote_description(answer).await?;
+
+    timeout(TIMEOUT_DURATION, opened.notified())
+        .await
+        .map_err(|_| anyhow!("the data channel didn't open"))?;
+
+    let message = Bytes::from_static(b"doctor");
+    data_channel.send(&message).await?;
+
+    let received = timeout(TIMEOUT_DURATION, message_receiver.recv())
+        .await
+        .map_err(|_| anyhow!("the message didn't arrive"))?;
+    if received.as_ref() != Some(&message) {
+        bail!("received an invalid message: {received:?}");
+    }
 
-    drop(peer);
+    let elapsed = start.elapsed();
 
-    Ok((
-        WebRTCTransportSender {
-            inner: this_owned.clone(),
-        },
-        WebRTCTransportEvents { event_receiver },
-    ))
+    offerer.close().await?;
+    answerer.close().await?;
+
+    Ok(elapsed)
 }
 
 // It compiling...
 #[allow(clippy::complexity)]
-fn create_event_handler<F, Args>(
+fn create_event_handler<F, Fut, Args>(
     inner: Weak<WebRtcInner>,
     f: F,
 ) -> Box<
@@ -225,8 +445,8 @@ fn create_event_handler<F, Args>(
 >
 where
     Args: Send + 'static,
-    F: AsyncFn(Arc<WebRtcInner>, Args) + Send + Sync + Clone + 'static,
-    for<'a> F::CallRefFuture<'a>: Send,
+    F: Fn(Arc<WebRtcInner>, Args) -> Fut + Send + Sync + Clone + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
 {
     Box::new(move |args: Args| {
         let inner = inner.clone();
@@ -257,24 +477,68 @@ fn create_channel_message_handler(
         + Sync
         + 'static,
 > {
-    create_event_handler(inner, async move |inner, message: DataChannelMessage| {
-        let Some(packet) = InboundPacket::deserialize(channel, &message.data) else {
-            return;
-        };
-
-        if let Err(err) = inner
-            .event_sender
-            .send(TransportEvent::RecvPacket(packet))
-            .await
-        {
-            warn!("Failed to dispatch RecvPacket event: {err:?}");
-        };
-    })
+    create_event_handler(
+        inner,
+        move |inner, message: DataChannelMessage| async move {
+            if let Err(err) = inner
+                .event_sender
+                .send(TransportEvent::RecvPacket {
+                    channel,
+                    data: message.data,
+                })
+                .await
+            {
+                warn!("Failed to dispatch RecvPacket event: {err:?}");
+            };
+        },
+    )
 }
 
 impl WebRtcInner {
     // -- Handle Connection State
-    async fn on_ice_connection_state_change(self: &Arc<Self>, _state: RTCIceConnectionState) {}
+    async fn on_ice_connection_state_change(self: &Arc<Self>, state: RTCIceConnectionState) {
+        // The selected path might've changed, e.g. after an ice restart
+        if matches!(
+            state,
+            RTCIceConnectionState::Connected | RTCIceConnectionState::Completed
+        ) {
+            self.update_video_mtu().await;
+        }
+    }
+
+    async fn update_video_mtu(&self) {
+        if !self.video_mtu.auto_adjust {
+            return;
+        }
+
+        let report = self.peer.get_stats().await;
+
+        let Some(pair) = report.reports.values().find_map(|stats| match stats {
+            StatsReportType::CandidatePair(pair) if pair.nominated => Some(pair),
+            _ => None,
+        }) else {
+            debug!("[Stream]: no nominated candidate pair to detect the path mtu");
+            return;
+        };
+        let (
+            Some(StatsReportType::LocalCandidate(local)),
+            Some(StatsReportType::RemoteCandidate(remote)),
+        ) = (
+            report.reports.get(&pair.local_candidate_id),
+            report.reports.get(&pair.remote_candidate_id),
+        )
+        else {
+            debug!("[Stream]: the candidates of the nominated pair are missing in the stats");
+            return;
+        };
+
+        let ipv6 = local.ip.contains(':') || remote.ip.contains(':');
+        let relayed = local.candidate_type == CandidateType::Relay
+            || remote.candidate_type == CandidateType::Relay;
+
+        let mtu = path_video_mtu(self.video_mtu.mtu, ipv6, relayed);
+        self.video.lock().await.set_mtu(mtu);
+    }
     async fn on_peer_connection_state_change(self: Arc<Self>, state: RTCPeerConnectionState) {
         #[allow(clippy::collapsible_if)]
         if matches!(state, RTCPeerConnectionState::Closed) {
@@ -288,6 +552,12 @@ impl WebRtcInner {
         ) {
             self.request_terminate().await;
         } else {
+            if matches!(state, RTCPeerConnectionState::Connected)
+                && let Err(err) = self.event_sender.send(TransportEvent::PeerConnected).await
+            {
+                warn!("Failed to send peer connected event to stream: {err:?}");
+            }
+
             self.clear_terminate_request().await;
         }
     }
@@ -382,10 +652,14 @@ impl WebRtcInner {
                 fps,
                 width,
                 height,
-                play_audio_local,
+                audio_routing,
                 video_supported_formats,
                 video_colorspace,
                 video_color_range_full,
+                video_rotation,
+                display_id,
+                // The web server already replaced the settings with the ones of the preset
+                preset: _,
             } => {
                 let video_supported_formats = SupportedVideoFormats::from_bits(video_supported_formats).unwrap_or_else(|| {
                     warn!("Failed to deserialize SupportedVideoFormats: {video_supported_formats}, falling back to only H264");
@@ -394,6 +668,7 @@ impl WebRtcInner {
                 {
                     let mut video = self.video.lock().await;
                     video.set_codecs(video_supported_formats).await;
+                    video.set_rotation(video_rotation);
                 }
 
                 // TODO: check peer for supported formats via sdp
@@ -411,7 +686,9 @@ impl WebRtcInner {
                             video_supported_formats,
                             video_color_range_full,
                             video_colorspace: video_colorspace.into(),
-                            play_audio_local,
+                            audio_routing: audio_routing.into(),
+                            video_rotation,
+                            display_id,
                         },
                     })
                     .await
@@ -504,78 +781,6 @@ impl WebRtcInner {
         };
     }
 
-    async fn on_data_channel(self: Arc<Self>, channel: Arc<RTCDataChannel>) {
-        let label = channel.label();
-        debug!("adding data channel: \"{label}\"");
-
-        let inner = Arc::downgrade(&self);
-
-        match label {
-            "stats" => {
-                let mut stats = self.stats_channel.lock().await;
-
-                channel.on_close({
-                    let this = Arc::downgrade(&self);
-
-                    Box::new(move ||{
-                        let this = this.clone();
-
-                        Box::pin(async move {
-                            let Some(this) = this.upgrade() else {
-                                warn!("Failed to close stats channel because the main type is already deallocated");
-                                return;
-                            };
-
-                            this.close_stats().await;
-                        })
-                    })
-                });
-
-                *stats = Some(channel);
-            }
-            "mouse_reliable" | "mouse_absolute" | "mouse_relative" => {
-                channel.on_message(create_channel_message_handler(
-                    inner,
-                    TransportChannel(TransportChannelId::MOUSE_ABSOLUTE),
-                ));
-            }
-            "touch" => {
-                channel.on_message(create_channel_message_handler(
-                    inner,
-                    TransportChannel(TransportChannelId::TOUCH),
-                ));
-            }
-            "keyboard" => {
-                channel.on_message(create_channel_message_handler(
-                    inner,
-                    TransportChannel(TransportChannelId::KEYBOARD),
-                ));
-            }
-            "controllers" => {
-                channel.on_message(create_channel_message_handler(
-                    inner,
-                    TransportChannel(TransportChannelId::CONTROLLERS),
-                ));
-            }
-            _ if let Some(number) = label.strip_prefix("controller")
-                && let Ok(id) = number.parse::<usize>()
-                && id < InboundPacket::CONTROLLER_CHANNELS.len() =>
-            {
-                channel.on_message(create_channel_message_handler(
-                    inner,
-                    TransportChannel(InboundPacket::CONTROLLER_CHANNELS[id]),
-                ));
-            }
-            _ => {}
-        };
-    }
-
-    async fn close_stats(&self) {
-        let mut stats = self.stats_channel.lock().await;
-
-        *stats = None;
-    }
-
     // -- Termination
     async fn request_terminate(self: &Arc<Self>) {
         let this = self.clone();
@@ -605,6 +810,114 @@ impl WebRtcInner {
 
         *request = None;
     }
+
+    /// Waits until the data channel buffers less than the high threshold
+    async fn wait_for_buffered_amount_low(
+        &self,
+        data_channel: &RTCDataChannel,
+    ) -> Result<(), TransportError> {
+        debug!(
+            "Waiting for data channel {} to send its buffered data",
+            data_channel.label()
+        );
+
+        loop {
+            let buffered_amount_low = self.buffered_amount_low.notified();
+            pin!(buffered_amount_low);
+            buffered_amount_low.as_mut().enable();
+
+            if data_channel.ready_state() != RTCDataChannelState::Open {
+                return Err(TransportError::ChannelClosed);
+            }
+            if data_channel.buffered_amount().await <= self.backpressure.high_threshold {
+                return Ok(());
+            }
+
+            // The channel might close without ever buffering less
+            let _ = timeout(BACKPRESSURE_CHECK_INTERVAL, buffered_amount_low).await;
+        }
+    }
+
+    async fn send_packet(&self, packet: OutboundPacket) -> Result<(), TransportError> {
+        let mut buffer = Vec::new();
+
+        let Some((channel, range)) = packet.serialize(&mut buffer) else {
+            warn!("Failed to serialize packet: {packet:?}");
+            return Ok(());
+        };
+
+        let bytes = Bytes::from(buffer);
+        let bytes = bytes.slice(range);
+
+        let Some(data_channel) = self
+            .data_channels
+            .get(channel.0 as usize)
+            .and_then(Option::as_ref)
+        else {
+            warn!("Cannot send data on channel {channel:?}");
+            return Err(TransportError::ChannelClosed);
+        };
+
+        if data_channel.buffered_amount().await > self.backpressure.high_threshold {
+            match packet.qos() {
+                // The next packet of this kind replaces it anyways
+                PacketQos::Unreliable => {
+                    trace!("Dropping packet on channel {channel:?} because the client is too slow");
+                    return Ok(());
+                }
+                PacketQos::Reliable => {
+                    self.wait_for_buffered_amount_low(data_channel).await?;
+                }
+            }
+        }
+
+        match data_channel.send(&bytes).await {
+            Ok(_) => {}
+            Err(webrtc::Error::ErrDataChannelNotOpen) => {
+                return Err(TransportError::ChannelClosed);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the samples of the audio jitter buffer in the pace of the audio frames
+async fn audio_playout(inner: Weak<WebRtcInner>) {
+    let mut next_playout = TokioInstant::now();
+
+    loop {
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+
+        let (frame_duration, stats) = {
+            let mut audio = inner.audio.lock().await;
+            match audio.playout().await {
+                Some(value) => value,
+                // The jitter buffer is disabled
+                None => return,
+            }
+        };
+
+        if let Some(stats) = stats
+            && let Err(err) = inner.send_packet(OutboundPacket::Stats(stats)).await
+        {
+            debug!("Failed to send audio jitter buffer stats: {err:?}");
+        }
+        drop(inner);
+
+        next_playout += frame_duration;
+
+        // Don't try to catch up if we're way behind, e.g. because the runtime was busy
+        let now = TokioInstant::now();
+        if next_playout + frame_duration < now {
+            next_playout = now;
+        }
+
+        sleep_until(next_playout).await;
+    }
 }
 
 pub struct WebRTCTransportEvents {
@@ -651,7 +964,18 @@ impl TransportSender for WebRTCTransportSender {
     ) -> i32 {
         let mut audio = self.inner.audio.lock().await;
 
-        audio.setup(&self.inner, audio_config, stream_config).await
+        let result = audio.setup(&self.inner, audio_config, stream_config).await;
+
+        if result == 0
+            && !self
+                .inner
+                .audio_playout_started
+                .swap(true, Ordering::AcqRel)
+        {
+            spawn(audio_playout(Arc::downgrade(&self.inner)));
+        }
+
+        result
     }
     async fn send_audio_sample(&self, data: &[u8]) -> Result<(), TransportError> {
         let mut audio = self.inner.audio.lock().await;
@@ -662,44 +986,23 @@ impl TransportSender for WebRTCTransportSender {
     }
 
     async fn send(&self, packet: OutboundPacket) -> Result<(), TransportError> {
-        let mut buffer = Vec::new();
-
-        let Some((channel, range)) = packet.serialize(&mut buffer) else {
-            warn!("Failed to serialize packet: {packet:?}");
-            return Ok(());
-        };
+        self.inner.send_packet(packet).await
+    }
 
-        let bytes = Bytes::from(buffer);
-        let bytes = bytes.slice(range);
+    async fn video_packet_counters(&self) -> Option<VideoPacketCounters> {
+        let report = self.inner.peer.get_stats().await;
 
-        match channel.0 {
-            TransportChannelId::GENERAL => match self.inner.general_channel.send(&bytes).await {
-                Ok(_) => {}
-                Err(webrtc::Error::ErrDataChannelNotOpen) => {
-                    return Err(TransportError::ChannelClosed);
-                }
-                _ => {}
-            },
-            TransportChannelId::STATS => {
-                let stats = self.inner.stats_channel.lock().await;
-                if let Some(stats) = stats.as_ref() {
-                    match stats.send(&bytes).await {
-                        Ok(_) => {}
-                        Err(webrtc::Error::ErrDataChannelNotOpen) => {
-                            return Err(TransportError::ChannelClosed);
-                        }
-                        _ => {}
-                    }
-                } else {
-                    return Err(TransportError::ChannelClosed);
-                }
-            }
-            _ => {
-                warn!("Cannot send data on channel {channel:?}");
-                return Err(TransportError::ChannelClosed);
+        let mut counters = VideoPacketCounters::default();
+        for stats in report.reports.values() {
+            if let StatsReportType::OutboundRTP(stats) = stats
+                && stats.kind == "video"
+            {
+                counters.packets_sent += stats.packets_sent;
+                counters.nack_count += stats.nack_count;
             }
         }
-        Ok(())
+
+        Some(counters)
     }
 
     async fn on_ipc_message(&self, message: ServerIpcMessage) -> Result<(), TransportError> {
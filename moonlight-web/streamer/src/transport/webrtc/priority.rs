@@ -0,0 +1,128 @@
+//! Audio is more important than video when the bandwidth collapses: a few missing frames are barely noticed, but audio dropouts are.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use common::config::MediaPriorityConfig;
+use log::info;
+use tokio::{pin, sync::Notify, time::timeout};
+
+/// Video frames never wait longer than this for the audio, else a stuck audio track would also stop the video
+const MAX_VIDEO_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Video,
+    Audio,
+}
+
+/// Shared by the audio and video track of a peer
+#[derive(Debug)]
+pub struct MediaPriority {
+    config: MediaPriorityConfig,
+    audio_queued: AtomicUsize,
+    audio_queue_empty: Notify,
+    video_degraded: AtomicBool,
+}
+
+impl MediaPriority {
+    pub fn new(config: MediaPriorityConfig) -> Self {
+        Self {
+            config,
+            audio_queued: Default::default(),
+            audio_queue_empty: Default::default(),
+            video_degraded: Default::default(),
+        }
+    }
+
+    /// Must be called every time the length of the audio queue changes
+    pub fn set_audio_queued(&self, queued: usize) {
+        self.audio_queued.store(queued, Ordering::Release);
+
+        if queued > self.config.audio_backlog_threshold {
+            if !self.video_degraded.swap(true, Ordering::AcqRel) {
+                info!("[Stream]: audio is falling behind, dropping P-frames until it caught up");
+            }
+        } else if queued == 0 {
+            if self.video_degraded.swap(false, Ordering::AcqRel) {
+                info!("[Stream]: audio caught up, sending all video frames again");
+            }
+
+            self.audio_queue_empty.notify_waiters();
+        }
+    }
+
+    /// If frames which aren't IDR frames should be dropped
+    pub fn is_video_degraded(&self) -> bool {
+        self.video_degraded.load(Ordering::Acquire)
+    }
+
+    /// Waits until the queued audio samples are being written
+    pub async fn wait_for_audio(&self) {
+        if !self.config.audio_first {
+            return;
+        }
+
+        let audio_queue_empty = self.audio_queue_empty.notified();
+        pin!(audio_queue_empty);
+        audio_queue_empty.as_mut().enable();
+
+        if self.audio_queued.load(Ordering::Acquire) == 0 {
+            return;
+        }
+
+        let _ = timeout(MAX_VIDEO_DELAY, audio_queue_empty).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use common::config::MediaPriorityConfig;
+    use tokio::time::{Instant, timeout};
+
+    use crate::transport::webrtc::priority::{MAX_VIDEO_DELAY, MediaPriority};
+
+    fn priority() -> MediaPriority {
+        MediaPriority::new(MediaPriorityConfig {
+            audio_first: true,
+            audio_backlog_threshold: 2,
+        })
+    }
+
+    #[test]
+    fn test_video_degraded_until_audio_caught_up() {
+        let priority = priority();
+
+        priority.set_audio_queued(2);
+        assert!(!priority.is_video_degraded());
+
+        priority.set_audio_queued(3);
+        assert!(priority.is_video_degraded());
+
+        // Stays degraded until the audio queue is empty
+        priority.set_audio_queued(1);
+        assert!(priority.is_video_degraded());
+
+        priority.set_audio_queued(0);
+        assert!(!priority.is_video_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_video_waits_for_audio() {
+        let priority = priority();
+
+        // Nothing queued
+        timeout(Duration::from_millis(1), priority.wait_for_audio())
+            .await
+            .expect("video waited without queued audio");
+
+        priority.set_audio_queued(1);
+        let start = Instant::now();
+        priority.wait_for_audio().await;
+        assert!(start.elapsed() >= MAX_VIDEO_DELAY);
+    }
+}
@@ -9,6 +9,7 @@ use log::{debug, warn};
 use tokio::{
     runtime::Handle,
     sync::{Mutex, Notify},
+    task::JoinHandle,
 };
 use webrtc::{
     api::media_engine::MediaEngine,
@@ -20,9 +21,13 @@ use webrtc::{
         extension::{
             HeaderExtension, abs_send_time_extension::AbsSendTimeExtension,
             playout_delay_extension::PlayoutDelayExtension,
+            video_orientation_extension::VideoOrientationExtension,
         },
     },
-    rtp_transceiver::rtp_codec::{RTCRtpHeaderExtensionCapability, RTPCodecType},
+    rtp_transceiver::{
+        rtp_codec::{RTCRtpHeaderExtensionCapability, RTPCodecType},
+        rtp_sender::RTCRtpSender,
+    },
     sdp::extmap::ABS_SEND_TIME_URI,
     track::track_local::{
         TrackLocal, track_local_static_rtp::TrackLocalStaticRTP,
@@ -30,7 +35,13 @@ use webrtc::{
     },
 };
 
+use crate::{
+    memory::MemoryReservation,
+    transport::webrtc::priority::{MediaKind, MediaPriority},
+};
+
 const PLAYOUT_DELAY_URI: &str = "http://www.webrtc.org/experiments/rtp-hdrext/playout-delay";
+const VIDEO_ORIENTATION_URI: &str = "urn:3gpp:video-orientation";
 
 pub fn register_header_extensions(api_media: &mut MediaEngine) -> Result<(), webrtc::Error> {
     api_media.register_header_extension(
@@ -63,6 +74,14 @@ pub fn register_header_extensions(api_media: &mut MediaEngine) -> Result<(), web
         None,
     )?;
 
+    api_media.register_header_extension(
+        RTCRtpHeaderExtensionCapability {
+            uri: VIDEO_ORIENTATION_URI.to_string(),
+        },
+        RTPCodecType::Video,
+        None,
+    )?;
+
     Ok(())
 }
 
@@ -73,8 +92,17 @@ where
     runtime: Handle,
     peer: Weak<RTCPeerConnection>,
     channel_queue_size: usize,
+    kind: MediaKind,
+    priority: Arc<MediaPriority>,
     new_samples_notify: Arc<Notify>,
     queue: Arc<Mutex<VecDeque<FrameSamples<Track>>>>,
+    current: Option<CurrentTrack>,
+    video_orientation: Option<VideoOrientationExtension>,
+}
+
+struct CurrentTrack {
+    rtp_sender: Arc<RTCRtpSender>,
+    sample_sender: JoinHandle<()>,
 }
 
 struct FrameSamples<Track>
@@ -83,22 +111,41 @@ where
 {
     important: bool,
     samples: Vec<Track::Sample>,
+    /// Freed once the samples were written to the track
+    _memory: Option<MemoryReservation>,
 }
 
 impl<Track> TrackLocalSender<Track>
 where
     Track: TrackLike,
 {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, channel_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        channel_queue_size: usize,
+        kind: MediaKind,
+        priority: Arc<MediaPriority>,
+    ) -> Self {
         Self {
             runtime,
             peer,
             channel_queue_size,
+            kind,
+            priority,
             new_samples_notify: Default::default(),
             queue: Default::default(),
+            current: None,
+            video_orientation: None,
         }
     }
 
+    /// Tags the packets of tracks created afterwards so that the browser rotates the video
+    pub fn set_video_orientation(&mut self, video_orientation: Option<VideoOrientationExtension>) {
+        self.video_orientation = video_orientation;
+    }
+
+    /// Adds the track to the peer, a previous track is removed first.
+    /// The peer must be renegotiated afterwards.
     pub async fn create_track(
         &mut self,
         track: Track,
@@ -110,18 +157,50 @@ where
             ));
         };
 
+        if let Some(previous) = self.current.take() {
+            previous.sample_sender.abort();
+
+            // The old track can't be reused because the codec might've changed
+            if let Err(err) = peer.remove_track(&previous.rtp_sender).await {
+                warn!("Failed to remove previous track: {err}");
+            }
+
+            self.clear_queue(true).await;
+        }
+
         let track = Arc::new(track);
 
         let new_samples_notify = self.new_samples_notify.clone();
         let queue = Arc::downgrade(&self.queue);
-        self.runtime.spawn({
+        let video_orientation = self.video_orientation;
+        let kind = self.kind;
+        let priority = self.priority.clone();
+        let sample_sender = self.runtime.spawn({
             let track = track.clone();
             async move {
-                sample_sender(track, &new_samples_notify, queue).await;
+                sample_sender(
+                    track,
+                    &new_samples_notify,
+                    queue,
+                    video_orientation,
+                    kind,
+                    &priority,
+                )
+                .await;
             }
         });
 
-        let track_sender = peer.add_track(track.track()).await?;
+        let track_sender = match peer.add_track(track.track()).await {
+            Ok(value) => value,
+            Err(err) => {
+                sample_sender.abort();
+                return Err(err.into());
+            }
+        };
+        self.current = Some(CurrentTrack {
+            rtp_sender: track_sender.clone(),
+            sample_sender,
+        });
 
         // Read incoming RTCP packets
         // Before these packets are returned they are processed by interceptors. For things
@@ -138,21 +217,39 @@ where
         Ok(())
     }
 
-    /// Returns if the frame will be delivered
-    pub async fn send_samples(&self, samples: Vec<Track::Sample>, important: bool) -> bool {
+    /// Returns if the frame will be delivered.
+    /// The memory of samples which don't own pooled buffers can be reserved with the reservation.
+    pub async fn send_samples(
+        &self,
+        samples: Vec<Track::Sample>,
+        important: bool,
+        memory: Option<MemoryReservation>,
+    ) -> bool {
+        // Dropping the frame requests an IDR frame, which is sent even while the video is degraded
+        if self.kind == MediaKind::Video && !important && self.priority.is_video_degraded() {
+            return false;
+        }
+
         let mut queue = self.queue.lock().await;
 
+        let frame = FrameSamples {
+            important,
+            samples,
+            _memory: memory,
+        };
+
         let result = if important {
-            queue.push_front(FrameSamples { important, samples });
+            queue.push_front(frame);
             true
         } else {
             if queue.len() > self.channel_queue_size {
                 return false;
             }
 
-            queue.push_front(FrameSamples { important, samples });
+            queue.push_front(frame);
             true
         };
+        self.update_priority(&queue);
 
         self.new_samples_notify.notify_waiters();
 
@@ -168,6 +265,13 @@ where
         } else {
             queue.retain(|frame| frame.important);
         }
+        self.update_priority(&queue);
+    }
+
+    fn update_priority(&self, queue: &VecDeque<FrameSamples<Track>>) {
+        if self.kind == MediaKind::Audio {
+            self.priority.set_audio_queued(queue.len());
+        }
     }
 }
 
@@ -175,6 +279,9 @@ async fn sample_sender<Track>(
     track: Arc<Track>,
     new_samples_notify: &Notify,
     queue: Weak<Mutex<VecDeque<FrameSamples<Track>>>>,
+    video_orientation: Option<VideoOrientationExtension>,
+    kind: MediaKind,
+    priority: &MediaPriority,
 ) where
     Track: TrackLike,
 {
@@ -182,7 +289,7 @@ async fn sample_sender<Track>(
         let frame = {
             let Some(queue) = queue.upgrade() else {
                 debug!("no sample queue available: stopping to submit samples");
-                continue;
+                return;
             };
 
             let mut queue = queue.lock().await;
@@ -192,29 +299,35 @@ async fn sample_sender<Track>(
                 new_samples_notify.notified().await;
                 continue;
             };
+            if kind == MediaKind::Audio {
+                priority.set_audio_queued(queue.len());
+            }
 
             new_frame
         };
 
+        if kind == MediaKind::Video {
+            priority.wait_for_audio().await;
+        }
+
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("clock went backwards");
         let now_secs = now.as_secs() as f64 + now.subsec_nanos() as f64 * 1e-9;
         let abs_send_time: u64 = (now_secs * 262_144.0) as u64;
 
+        let mut extensions = vec![
+            HeaderExtension::PlayoutDelay(PlayoutDelayExtension::new(0, 0)),
+            HeaderExtension::AbsSendTime(AbsSendTimeExtension {
+                timestamp: abs_send_time,
+            }),
+        ];
+        if let Some(video_orientation) = video_orientation {
+            extensions.push(HeaderExtension::VideoOrientation(video_orientation));
+        }
+
         for sample in frame.samples {
-            if let Err(err) = track
-                .write_with_extensions(
-                    sample,
-                    &[
-                        HeaderExtension::PlayoutDelay(PlayoutDelayExtension::new(0, 0)),
-                        HeaderExtension::AbsSendTime(AbsSendTimeExtension {
-                            timestamp: abs_send_time,
-                        }),
-                    ],
-                )
-                .await
-            {
+            if let Err(err) = track.write_with_extensions(sample, &extensions).await {
                 warn!("[Stream]: track.write_sample failed: {err}");
             }
         }
@@ -7,10 +7,12 @@ use std::{
     },
 };
 
+use anyhow::bail;
 use bytes::{Bytes, BytesMut};
 use common::{
-    api_bindings::{LogMessageType, StreamServerMessage},
+    api_bindings::{LogMessageType, StreamMessageCode, StreamVideoRotation},
     ipc::StreamerIpcMessage,
+    messages::MessageCatalog,
 };
 use log::{debug, error, info, trace, warn};
 use moonlight_common::stream::{
@@ -27,6 +29,9 @@ use webrtc::{
     },
     rtp::{
         codecs::{av1::Av1Payloader, h265::RTP_OUTBOUND_MTU},
+        extension::video_orientation_extension::{
+            CameraDirection, VideoOrientationExtension, VideoRotation,
+        },
         header::Header,
         packet::Packet,
         packetizer::Payloader,
@@ -38,22 +43,26 @@ use webrtc::{
     track::track_local::track_local_static_rtp::TrackLocalStaticRTP,
 };
 
-use crate::transport::{
-    TransportEvent,
-    webrtc::{
-        WebRtcInner,
-        sender::{SequencedTrackLocalStaticRTP, TrackLocalSender},
-        video::{
-            annexb::AnnexBSplitter,
-            h264::{payloader::H264Payloader, reader::H264Reader},
-            h265::{payloader::H265Payloader, reader::H265Reader},
+use crate::{
+    memory::{BufferKind, MemoryReservation, buffer_pool},
+    transport::{
+        TransportEvent,
+        webrtc::{
+            WebRtcInner,
+            priority::{MediaKind, MediaPriority},
+            sender::{SequencedTrackLocalStaticRTP, TrackLocalSender},
+            video::{
+                annexb::AnnexBSplitter,
+                h264::{payloader::H264Payloader, reader::H264Reader},
+                h265::{payloader::H265Payloader, reader::H265Reader},
+            },
         },
     },
 };
 
-mod annexb;
-mod h264;
-mod h265;
+pub(crate) mod annexb;
+pub(crate) mod h264;
+pub(crate) mod h265;
 
 enum VideoCodec {
     H264 {
@@ -75,26 +84,70 @@ pub struct WebRtcVideo {
     sender: TrackLocalSender<SequencedTrackLocalStaticRTP>,
     needs_idr: Arc<AtomicBool>,
     clock_rate: u32,
+    /// The capability of the current track, the track is only replaced if this changes
+    capability: Option<RTCRtpCodecCapability>,
     codec: Option<VideoCodec>,
     samples: Vec<BytesMut>,
+    /// The size of the rtp packets including their header
+    mtu: usize,
 }
 
 impl WebRtcVideo {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, frame_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        frame_queue_size: usize,
+        priority: Arc<MediaPriority>,
+        mtu: usize,
+    ) -> Self {
         Self {
+            mtu,
             clock_rate: 0,
+            capability: None,
             needs_idr: Default::default(),
-            sender: TrackLocalSender::new(runtime, peer, frame_queue_size),
+            sender: TrackLocalSender::new(
+                runtime,
+                peer,
+                frame_queue_size,
+                MediaKind::Video,
+                priority,
+            ),
             codec: None,
             supported_video_formats: SupportedVideoFormats::empty(),
             samples: Default::default(),
         }
     }
 
+    pub fn set_mtu(&mut self, mtu: usize) {
+        if self.mtu != mtu {
+            info!("[Stream]: the video mtu changed from {} to {mtu}", self.mtu);
+            self.mtu = mtu;
+        }
+    }
+
     pub async fn set_codecs(&mut self, supported_codecs: SupportedVideoFormats) {
         self.supported_video_formats = supported_codecs;
     }
 
+    pub fn set_rotation(&mut self, rotation: StreamVideoRotation) {
+        let rotation = match rotation {
+            StreamVideoRotation::Rotate0 => {
+                self.sender.set_video_orientation(None);
+                return;
+            }
+            StreamVideoRotation::Rotate90 => VideoRotation::Degree90,
+            StreamVideoRotation::Rotate180 => VideoRotation::Degree180,
+            StreamVideoRotation::Rotate270 => VideoRotation::Degree270,
+        };
+
+        self.sender
+            .set_video_orientation(Some(VideoOrientationExtension {
+                direction: CameraDirection::Front,
+                flip: false,
+                rotation,
+            }));
+    }
+
     pub async fn setup(
         &mut self,
         inner: &Arc<WebRtcInner>,
@@ -109,20 +162,16 @@ impl WebRtcVideo {
         info!("[Stream] Stream setup: {width}x{height}x{redraw_rate} and {format:?}");
 
         if !format.contained_in(self.supported_video_formats) {
-            let message = format!(
-                "The host tried to setup a video stream with a non supported video format: {format:?}, supported formats: {}",
-                self.supported_video_formats
-            );
-
-            error!("{}", message);
+            let code = StreamMessageCode::UnsupportedVideoFormat {
+                format: format!("{format:?}"),
+                supported_formats: self.supported_video_formats.to_string(),
+            };
+            error!("{}", MessageCatalog::default().text(&code));
 
             if let Err(err) = inner
                 .event_sender
                 .send(TransportEvent::SendIpc(StreamerIpcMessage::WebSocket(
-                    StreamServerMessage::DebugLog {
-                        message,
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    code.debug_log(Some(LogMessageType::FatalDescription)),
                 )))
                 .await
             {
@@ -138,56 +187,69 @@ impl WebRtcVideo {
             return false;
         };
 
-        let needs_idr = self.needs_idr.clone();
-        if let Err(err) = self
-            .sender
-            .create_track(
-                TrackLocalStaticRTP::new(
-                    codec.capability.clone(),
-                    "video".to_string(),
-                    "moonlight".to_string(),
-                )
-                .into(),
-                {
-                    let needs_idr = needs_idr.clone();
-
-                    move |packet| {
-                        let packet = packet.as_any();
+        // The host calls setup again if the format changes mid-stream, e.g. when toggling hdr
+        let replace_track = self.capability.as_ref() != Some(&codec.capability);
+        if replace_track {
+            if self.capability.is_some() {
+                info!("[Stream] Replacing the video track because the codec changed");
+            }
 
-                        if packet.is::<PictureLossIndication>() {
-                            needs_idr.store(true, Ordering::Release);
+            let needs_idr = self.needs_idr.clone();
+            if let Err(err) = self
+                .sender
+                .create_track(
+                    TrackLocalStaticRTP::new(
+                        codec.capability.clone(),
+                        "video".to_string(),
+                        "moonlight".to_string(),
+                    )
+                    .into(),
+                    {
+                        let needs_idr = needs_idr.clone();
+
+                        move |packet| {
+                            let packet = packet.as_any();
+
+                            if packet.is::<PictureLossIndication>() {
+                                needs_idr.store(true, Ordering::Release);
+                            }
+                            if let Some(_max_bitrate) =
+                                packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>()
+                            {
+                                // Moonlight doesn't support dynamic bitrate changing :(
+                            }
                         }
-                        if let Some(_max_bitrate) =
-                            packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>()
-                        {
-                            // Moonlight doesn't support dynamic bitrate changing :(
-                        }
-                    }
-                },
-            )
-            .await
-        {
-            let message = format!(
-                "Failed to create video track with format {format:?} and codec \"{codec:?}\": {err:?}"
-            );
-            error!("{}", message);
-
-            if let Err(err) = inner
-                .event_sender
-                .send(TransportEvent::SendIpc(StreamerIpcMessage::WebSocket(
-                    StreamServerMessage::DebugLog {
-                        message,
-                        ty: Some(LogMessageType::FatalDescription),
                     },
-                )))
+                )
                 .await
             {
-                warn!("Failed to send error to client: {err}");
+                error!(
+                    "Failed to create video track with format {format:?} and codec \"{codec:?}\": {err:?}"
+                );
+
+                if let Err(err) = inner
+                    .event_sender
+                    .send(TransportEvent::SendIpc(StreamerIpcMessage::WebSocket(
+                        StreamMessageCode::VideoTrackFailed {
+                            format: format!("{format:?}"),
+                        }
+                        .debug_log(Some(LogMessageType::FatalDescription)),
+                    )))
+                    .await
+                {
+                    warn!("Failed to send error to client: {err}");
+                }
+
+                // The previous track was already removed
+                self.capability = None;
+                return false;
             }
-            return false;
+
+            self.capability = Some(codec.capability.clone());
         }
 
         self.clock_rate = codec.capability.clock_rate;
+        self.samples.clear();
 
         self.codec = match format {
             // -- H264
@@ -214,7 +276,7 @@ impl WebRtcVideo {
         };
 
         // Renegotiate
-        if !inner.send_offer().await {
+        if replace_track && !inner.send_offer().await {
             warn!("Failed to renegotiate. Video was added!");
         }
 
@@ -226,7 +288,17 @@ impl WebRtcVideo {
 
         let timestamp = (unit.presentation_time.as_secs_f64() * self.clock_rate as f64) as u32;
 
-        let mut full_frame = Vec::new();
+        let len = unit
+            .buffers
+            .iter()
+            .map(|buffer| buffer.data.len())
+            .sum::<usize>();
+        // The packets of the frame are about as large as the frame itself
+        let Some(memory) = buffer_pool().reserve(BufferKind::Video, len) else {
+            return DecodeResult::NeedIdr;
+        };
+
+        let mut full_frame = Vec::with_capacity(len);
         for buffer in unit.buffers {
             full_frame.extend_from_slice(buffer.data);
         }
@@ -264,8 +336,10 @@ impl WebRtcVideo {
                     &mut self.samples,
                     &mut self.sender,
                     payloader,
+                    self.mtu,
                     timestamp,
                     important,
+                    memory,
                     &self.needs_idr,
                 )
                 .await;
@@ -295,8 +369,10 @@ impl WebRtcVideo {
                     &mut self.samples,
                     &mut self.sender,
                     payloader,
+                    self.mtu,
                     timestamp,
                     important,
+                    memory,
                     &self.needs_idr,
                 )
                 .await;
@@ -316,8 +392,10 @@ impl WebRtcVideo {
                     &mut self.samples,
                     &mut self.sender,
                     payloader,
+                    self.mtu,
                     timestamp,
                     important,
+                    memory,
                     &self.needs_idr,
                 )
                 .await;
@@ -361,8 +439,10 @@ async fn send_single_frame(
     samples: &mut Vec<BytesMut>,
     sender: &mut TrackLocalSender<SequencedTrackLocalStaticRTP>,
     payloader: &mut impl Payloader,
+    mtu: usize,
     timestamp: u32,
     important: bool,
+    memory: MemoryReservation,
     needs_idr: &AtomicBool,
 ) {
     if important {
@@ -375,7 +455,7 @@ async fn send_single_frame(
     while let Some(sample) = peekable.next() {
         let packets = match packetize(
             payloader,
-            RTP_OUTBOUND_MTU,
+            mtu,
             0, // is set in the write fn
             timestamp,
             &sample.freeze(),
@@ -391,7 +471,10 @@ async fn send_single_frame(
         frame_samples.extend(packets);
     }
 
-    if !sender.send_samples(frame_samples, important).await {
+    if !sender
+        .send_samples(frame_samples, important, Some(memory))
+        .await
+    {
         sender.clear_queue(true).await;
 
         // We've dropped a frame (likely due to buffering)
@@ -399,7 +482,27 @@ async fn send_single_frame(
     }
 }
 
-fn packetize(
+/// Additional bytes of an ipv6 header compared to an ipv4 header
+const IPV6_HEADER_OVERHEAD: usize = 20;
+/// The bytes of a TURN send indication around the relayed packet
+const TURN_RELAY_OVERHEAD: usize = 36;
+/// The payloaders need room for some payload next to their headers
+const MIN_VIDEO_MTU: usize = 256;
+
+/// Lowers the configured mtu of a direct ipv4 path for paths with larger headers
+pub fn path_video_mtu(mtu: usize, ipv6: bool, relayed: bool) -> usize {
+    let mut overhead = 0;
+    if ipv6 {
+        overhead += IPV6_HEADER_OVERHEAD;
+    }
+    if relayed {
+        overhead += TURN_RELAY_OVERHEAD;
+    }
+
+    mtu.saturating_sub(overhead).max(MIN_VIDEO_MTU)
+}
+
+pub(crate) fn packetize(
     payloader: &mut impl Payloader,
     mtu: usize,
     sequence_number: u16,
@@ -430,6 +533,96 @@ fn packetize(
     Ok(packets)
 }
 
+/// Packetizes a synthetic frame with every payloader and returns the amount of packets created.
+/// Every frame contains a unit which is larger than the mtu so that fragmentation is also tested.
+pub fn payloader_self_test() -> Vec<(&'static str, Result<usize, anyhow::Error>)> {
+    const LARGE_PAYLOAD_SIZE: usize = RTP_OUTBOUND_MTU * 3;
+
+    let large_unit = |header: &[u8]| {
+        let mut unit = header.to_vec();
+        unit.resize(header.len() + LARGE_PAYLOAD_SIZE, 0xAB);
+        Bytes::from(unit)
+    };
+
+    let h264 = [
+        // Sps, Pps, Idr
+        Bytes::from_static(&[0x67, 0x42, 0xE0, 0x1F, 0x8C, 0x8D]),
+        Bytes::from_static(&[0x68, 0xCE, 0x3C, 0x80]),
+        large_unit(&[0x65, 0x88]),
+    ];
+    let h265 = [
+        // Vps, Sps, Pps, Idr
+        Bytes::from_static(&[0x40, 0x01, 0x0C, 0x01]),
+        Bytes::from_static(&[0x42, 0x01, 0x01, 0x01]),
+        Bytes::from_static(&[0x44, 0x01, 0xC1, 0x72]),
+        large_unit(&[0x26, 0x01, 0xAF]),
+    ];
+
+    // Obu header of a frame with the size field present, followed by the leb128 encoded size
+    let mut av1_frame_header = vec![0x32];
+    let mut size = LARGE_PAYLOAD_SIZE;
+    while size >= 0x80 {
+        av1_frame_header.push((size as u8 & 0x7F) | 0x80);
+        size >>= 7;
+    }
+    av1_frame_header.push(size as u8);
+
+    let av1 = [
+        // Temporal delimiter, Frame
+        Bytes::from_static(&[0x12, 0x00]),
+        large_unit(&av1_frame_header),
+    ];
+
+    vec![
+        (
+            "H264",
+            payloader_self_test_frame(&mut H264Payloader::default(), &h264),
+        ),
+        (
+            "H265",
+            payloader_self_test_frame(&mut H265Payloader::default(), &h265),
+        ),
+        (
+            "AV1",
+            payloader_self_test_frame(&mut Av1Payloader::default(), &av1),
+        ),
+    ]
+}
+
+fn payloader_self_test_frame(
+    payloader: &mut impl Payloader,
+    units: &[Bytes],
+) -> Result<usize, anyhow::Error> {
+    let mut packet_count = 0;
+    for (i, unit) in units.iter().enumerate() {
+        let packets = packetize(
+            payloader,
+            RTP_OUTBOUND_MTU,
+            0,
+            0,
+            unit,
+            i == units.len() - 1,
+        )?;
+
+        for packet in &packets {
+            if packet.payload.is_empty() || packet.payload.len() + 12 > RTP_OUTBOUND_MTU {
+                bail!(
+                    "created a packet with an invalid payload size of {} bytes",
+                    packet.payload.len()
+                );
+            }
+        }
+
+        packet_count += packets.len();
+    }
+
+    if packet_count == 0 {
+        bail!("no packets were created");
+    }
+
+    Ok(packet_count)
+}
+
 fn video_format_to_codec(format: VideoFormat) -> Option<RTCRtpCodecParameters> {
     let rtcp_feedback = vec![
         RTCPFeedback {
@@ -551,7 +744,7 @@ fn video_format_to_codec(format: VideoFormat) -> Option<RTCRtpCodecParameters> {
     }
 }
 
-fn trim_bytes_to_range(mut buf: BytesMut, range: Range<usize>) -> BytesMut {
+pub(crate) fn trim_bytes_to_range(mut buf: BytesMut, range: Range<usize>) -> BytesMut {
     if range.start > 0 {
         let _ = buf.split_to(range.start);
     }
@@ -562,3 +755,16 @@ fn trim_bytes_to_range(mut buf: BytesMut, range: Range<usize>) -> BytesMut {
 
     buf
 }
+
+#[cfg(test)]
+mod test {
+    use crate::transport::webrtc::video::{MIN_VIDEO_MTU, path_video_mtu};
+
+    #[test]
+    fn test_path_video_mtu() {
+        assert_eq!(path_video_mtu(1200, false, false), 1200);
+        assert_eq!(path_video_mtu(1200, true, false), 1180);
+        assert_eq!(path_video_mtu(1200, true, true), 1144);
+        assert_eq!(path_video_mtu(100, false, true), MIN_VIDEO_MTU);
+    }
+}
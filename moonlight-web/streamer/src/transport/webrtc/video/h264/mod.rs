@@ -61,6 +61,23 @@ pub enum NalUnitType {
     Unspecified31 = 31,
 }
 
+impl NalUnitType {
+    /// If the unit contains the coded picture, every access unit ends with one of these
+    pub fn is_vcl(self) -> bool {
+        matches!(
+            self,
+            Self::CodedSliceNonIDR
+                | Self::CodedSliceDataPartitionA
+                | Self::CodedSliceDataPartitionB
+                | Self::CodedSliceDataPartitionC
+                | Self::CodedSliceIDR
+                | Self::CodedSliceAux
+                | Self::CodedSliceExt
+                | Self::CodedSliceExtDepth
+        )
+    }
+}
+
 // https://datatracker.ietf.org/doc/html/rfc3984#section-1.3
 #[allow(unused)]
 #[derive(Debug, Clone, Copy)]
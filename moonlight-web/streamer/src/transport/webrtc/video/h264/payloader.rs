@@ -4,12 +4,17 @@ use webrtc::rtp::{self, packetizer::Payloader};
 
 use crate::transport::webrtc::video::h264::{NalHeader, NalUnitType};
 
+/// Small units are aggregated into STAP-A packets, larger ones are fragmented into FU-A packets
 #[derive(Debug, Clone, Default)]
 pub struct H264Payloader {
-    sps_nalu: Option<Bytes>,
-    pps_nalu: Option<Bytes>,
+    /// Units which aren't part of the coded picture (SPS, PPS, SEI, ...) wait to be aggregated with the following units.
+    /// An access unit always ends with a slice, which is never held back.
+    pending_nalus: Vec<Bytes>,
 }
 
+/// Every unit in a STAP-A packet is prefixed with its size
+const STAP_A_NALU_SIZE_LEN: usize = 2;
+
 #[allow(unused)]
 #[derive(Debug, Clone, Copy)]
 struct FuHeader {
@@ -66,9 +71,43 @@ impl H264Payloader {
         nalu
     }
 
+    fn stap_a_len<'a>(nalus: impl IntoIterator<Item = &'a Bytes>) -> usize {
+        NalHeader::SIZE
+            + nalus
+                .into_iter()
+                .map(|nalu| STAP_A_NALU_SIZE_LEN + nalu.len())
+                .sum::<usize>()
+    }
+
+    /// Sends the pending units, a single unit is sent as is
+    fn flush_pending(&mut self, mtu: usize, packets: &mut Vec<Bytes>) {
+        match self.pending_nalus.len() {
+            0 => {}
+            1 => {
+                let nalu = self.pending_nalus.remove(0);
+                Self::build_packets(nalu, mtu, packets);
+            }
+            _ => {
+                packets.push(Self::build_stap_a_packet(&self.pending_nalus).freeze());
+                self.pending_nalus.clear();
+            }
+        }
+    }
+
+    fn build_packets(nalu: Bytes, mtu: usize, packets: &mut Vec<Bytes>) {
+        if nalu.len() <= mtu {
+            packets.push(Self::build_single_nal(nalu));
+        } else {
+            let nal_header = NalHeader::parse([nalu[0]]);
+            packets.extend(Self::build_fragmented_packets(&nalu, nal_header, mtu));
+        }
+    }
+
     // https://datatracker.ietf.org/doc/html/rfc3984#section-5.7.1
-    fn build_stap_a_packet(nalus: &[&Bytes]) -> BytesMut {
-        let mut stap_len = 1;
+    fn build_stap_a_packet(nalus: &[Bytes]) -> BytesMut {
+        let stap_len = Self::stap_a_len(nalus);
+
+        // The NRI must be the highest NRI of all aggregated units
         let mut nal_ref_idc = 0;
         for nalu in nalus {
             let nal_header = NalHeader::parse([nalu[0]]);
@@ -76,8 +115,6 @@ impl H264Payloader {
             if nal_header.nal_ref_idc > nal_ref_idc {
                 nal_ref_idc = nal_header.nal_ref_idc;
             }
-
-            stap_len += 2 + nalu.len();
         }
 
         let stap_a_header = NalHeader {
@@ -148,40 +185,30 @@ impl Payloader for H264Payloader {
 
         let nal_header = NalHeader::parse([b[0]]);
 
-        match nal_header.nal_unit_type {
-            NalUnitType::AccessUnitDelimiter | NalUnitType::FillerData => {
-                return Ok(vec![]);
-            }
-            NalUnitType::Pps => {
-                self.pps_nalu = Some(b.clone());
-                return Ok(vec![]);
-            }
-            NalUnitType::Sps => {
-                self.sps_nalu = Some(b.clone());
-                return Ok(vec![]);
-            }
-            _ => {}
+        if matches!(
+            nal_header.nal_unit_type,
+            NalUnitType::AccessUnitDelimiter | NalUnitType::FillerData
+        ) {
+            return Ok(vec![]);
         }
 
         let mut packets = vec![];
 
-        if let (Some(pps), Some(sps)) = (self.pps_nalu.as_ref(), self.sps_nalu.as_ref()) {
-            let stap_a = Self::build_stap_a_packet(&[sps, pps]);
-
-            if stap_a.len() >= mtu {
-                return Ok(vec![]);
-            }
-
-            self.pps_nalu.take();
-            self.sps_nalu.take();
+        let fits_aggregated = Self::stap_a_len(self.pending_nalus.iter().chain([b])) <= mtu;
+        if !fits_aggregated {
+            self.flush_pending(mtu, &mut packets);
+        }
 
-            packets.push(stap_a.freeze());
+        if !nal_header.nal_unit_type.is_vcl() {
+            self.pending_nalus.push(b.clone());
+            return Ok(packets);
         }
 
-        if b.len() <= mtu {
-            packets.push(Self::build_single_nal(b.clone()));
+        if self.pending_nalus.is_empty() {
+            Self::build_packets(b.clone(), mtu, &mut packets);
         } else {
-            packets.extend(Self::build_fragmented_packets(b, nal_header, mtu));
+            self.pending_nalus.push(b.clone());
+            self.flush_pending(mtu, &mut packets);
         }
 
         Ok(packets)
@@ -191,3 +218,174 @@ impl Payloader for H264Payloader {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+    use webrtc::rtp::packetizer::Payloader;
+
+    use crate::transport::webrtc::video::h264::{payloader::H264Payloader, reader::H264Reader};
+
+    const SPS: &[u8] = &[0x67, 0x42, 0xE0, 0x1F, 0x8C, 0x8D];
+    const PPS: &[u8] = &[0x68, 0xCE, 0x3C, 0x80];
+
+    fn payload_all(mtu: usize, nalus: &[&'static [u8]]) -> Vec<Vec<Bytes>> {
+        let mut payloader = H264Payloader::default();
+
+        nalus
+            .iter()
+            .map(|nalu| {
+                payloader
+                    .payload(mtu, &Bytes::from_static(nalu))
+                    .expect("payload")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parameter_sets_aggregated_with_slice() {
+        let idr = &[0x65, 0x88, 0x84, 0x00];
+
+        let packets = payload_all(1200, &[SPS, PPS, idr]);
+
+        assert!(packets[0].is_empty());
+        assert!(packets[1].is_empty());
+        assert_eq!(
+            packets[2],
+            [Bytes::from_static(&[
+                // STAP-A with the highest NRI of 3
+                0x78, //
+                0x00, 0x06, 0x67, 0x42, 0xE0, 0x1F, 0x8C, 0x8D, //
+                0x00, 0x04, 0x68, 0xCE, 0x3C, 0x80, //
+                0x00, 0x04, 0x65, 0x88, 0x84, 0x00,
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_sei_aggregated_with_slice() {
+        let sei = &[0x06, 0x05, 0x01, 0xAA];
+        let slice = &[0x41, 0x9A, 0x02];
+
+        let packets = payload_all(1200, &[sei, slice]);
+
+        assert!(packets[0].is_empty());
+        assert_eq!(
+            packets[1],
+            [Bytes::from_static(&[
+                // STAP-A with the NRI of the slice
+                0x58, //
+                0x00, 0x04, 0x06, 0x05, 0x01, 0xAA, //
+                0x00, 0x03, 0x41, 0x9A, 0x02,
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_large_slice_fragmented_after_aggregate() {
+        let mut idr = vec![0x65];
+        idr.extend_from_slice(&[0xAB; 29]);
+        let idr: &'static [u8] = idr.leak();
+
+        let packets = payload_all(20, &[SPS, PPS, idr]);
+        let packets = &packets[2];
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0][0], 0x78);
+        assert_eq!(packets[0].len(), 15);
+
+        // FU-A with the start bit, then with the end bit
+        assert_eq!(&packets[1][..2], &[0x7C, 0x85]);
+        assert_eq!(packets[1].len(), 20);
+        assert_eq!(&packets[2][..2], &[0x7C, 0x45]);
+        assert_eq!(packets[2].len(), 2 + 11);
+    }
+
+    #[test]
+    fn test_single_slice_and_delimiter() {
+        let delimiter = &[0x09, 0xF0];
+        let slice = &[0x41, 0x9A, 0x02];
+
+        let packets = payload_all(1200, &[delimiter, slice]);
+
+        assert!(packets[0].is_empty());
+        assert_eq!(packets[1], [Bytes::from_static(slice)]);
+    }
+
+    /// A complete and decodable 1280x720 constrained baseline access unit.
+    /// Every macroblock of the idr slice is a flat I_16x16 macroblock without residual,
+    /// which is why the slice data repeats the same byte.
+    fn access_unit() -> Vec<u8> {
+        let mut access_unit = vec![0, 0, 0, 1];
+        access_unit.extend_from_slice(&[0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xE4]);
+        access_unit.extend_from_slice(&[0, 0, 0, 1]);
+        access_unit.extend_from_slice(&[0x68, 0xCE, 0x38, 0x80]);
+        access_unit.extend_from_slice(&[0, 0, 0, 1]);
+        access_unit.extend_from_slice(&[0x65, 0x88, 0x84]);
+        access_unit.extend_from_slice(&[0x93; 3600]);
+        access_unit.push(0xC0);
+
+        access_unit
+    }
+
+    /// Splits the access unit like the WebRTC transport and payloads all units
+    fn payload_access_unit(mtu: usize) -> Vec<Bytes> {
+        let mut reader = H264Reader::new(Cursor::new(access_unit()), 0);
+        let mut payloader = H264Payloader::default();
+
+        let mut packets = Vec::new();
+        while let Some(nal) = reader.next_nal().expect("read nal") {
+            let nalu =
+                Bytes::copy_from_slice(&nal.full[nal.header_range.start..nal.payload_range.end]);
+
+            packets.extend(payloader.payload(mtu, &nalu).expect("payload"));
+        }
+
+        packets
+    }
+
+    #[test]
+    fn test_access_unit_fragmented() {
+        let packets = payload_access_unit(1200);
+
+        let mut expected = vec![
+            // STAP-A with the sps and pps
+            [
+                &[0x78, 0x00, 0x09][..],
+                &[0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xE4],
+                &[0x00, 0x04, 0x68, 0xCE, 0x38, 0x80],
+            ]
+            .concat(),
+        ];
+        // FU-As with the idr slice, the first one starts with the remaining slice header
+        expected.push([&[0x7C, 0x85, 0x88, 0x84][..], &[0x93; 1196]].concat());
+        expected.push([&[0x7C, 0x05][..], &[0x93; 1198]].concat());
+        expected.push([&[0x7C, 0x05][..], &[0x93; 1198]].concat());
+        expected.push([&[0x7C, 0x45][..], &[0x93; 8], &[0xC0]].concat());
+
+        assert_eq!(packets, expected);
+    }
+
+    #[test]
+    fn test_access_unit_aggregated() {
+        let packets = payload_access_unit(4000);
+
+        let mut idr = vec![0x65, 0x88, 0x84];
+        idr.extend_from_slice(&[0x93; 3600]);
+        idr.push(0xC0);
+
+        let expected = [
+            &[0x78, 0x00, 0x09][..],
+            &[0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xE4],
+            &[0x00, 0x04, 0x68, 0xCE, 0x38, 0x80],
+            // 3604 bytes
+            &[0x0E, 0x14],
+            &idr,
+        ]
+        .concat();
+
+        assert_eq!(packets, [expected]);
+    }
+}
@@ -3,16 +3,27 @@ use std::{
     time::{Duration, Instant},
 };
 
-use common::api_bindings::{StatsHostProcessingLatency, StreamerStatsUpdate};
-use log::{debug, error, warn};
+use common::{
+    api_bindings::{
+        GeneralServerMessage, LogMessageType, StatsHostProcessingLatency, StreamMessageCode,
+        StreamerStatsUpdate,
+    },
+    ipc::StreamerIpcMessage,
+};
+use log::{debug, error, info, warn};
 use moonlight_common::stream::{
     bindings::{
-        Capabilities, DecodeResult, EstimatedRttInfo, SupportedVideoFormats, VideoDecodeUnit,
+        Capabilities, DecodeResult, EstimatedRttInfo, FrameType, SupportedVideoFormats,
+        VideoDecodeUnit,
     },
     video::{VideoDecoder, VideoSetup},
 };
 
-use crate::{StreamConnection, transport::OutboundPacket};
+use crate::{
+    StreamConnection, hls::push_hls_video, preview::push_preview_frame, quality::QualitySample,
+    quality_switch::FrameAction, rtsp::push_rtsp_video, screenshot::capture_screenshots,
+    thumbnail::capture_thumbnail, transport::OutboundPacket,
+};
 
 pub(crate) struct StreamVideoDecoder {
     pub(crate) stream: Weak<StreamConnection>,
@@ -31,6 +42,7 @@ impl VideoDecoder for StreamVideoDecoder {
             let mut stream_info = stream.stream_setup.blocking_lock();
             stream_info.video = Some(setup);
         }
+        stream.quality_switch.blocking_lock().on_video_setup();
 
         {
             stream.runtime.clone().block_on(async move {
@@ -56,9 +68,38 @@ impl VideoDecoder for StreamVideoDecoder {
         };
 
         stream.runtime.clone().block_on(async {
+            let keyframe = matches!(unit.frame_type, FrameType::Idr);
+            match stream
+                .quality_switch
+                .lock()
+                .await
+                .on_frame(keyframe, Instant::now())
+            {
+                FrameAction::Forward => {}
+                // The frames would reference pictures of the other quality
+                FrameAction::Drop => return DecodeResult::Ok,
+                FrameAction::Switch(quality) => {
+                    let stream = stream.clone();
+                    stream.runtime.clone().spawn(async move {
+                        stream.change_quality(quality).await;
+                    });
+                }
+            }
+
+            if keyframe {
+                capture_screenshots(&stream, &unit).await;
+                capture_thumbnail(&stream, &unit).await;
+            }
+            push_preview_frame(&stream, &unit).await;
+            push_rtsp_video(&stream, &unit).await;
+            push_hls_video(&stream, &unit).await;
+
             let mut sender = stream.transport_sender.lock().await;
 
             if let Some(sender) = sender.as_mut() {
+                #[cfg(feature = "profiling")]
+                self.stats.allocations.start_frame();
+
                 let start = Instant::now();
                 let result = match sender.send_video_unit(&unit).await {
                     Err(err) => {
@@ -68,9 +109,38 @@ impl VideoDecoder for StreamVideoDecoder {
                     Ok(value) => value,
                 };
 
-                let frame_processing_time = Instant::now() - start;
+                let sent_at = Instant::now();
+                let frame_processing_time = sent_at - start;
+
+                #[cfg(feature = "profiling")]
+                self.stats.allocations.end_frame();
+
                 self.stats.analyze(&stream, &unit, frame_processing_time);
 
+                if stream.video_watchdog.lock().await.on_frame(sent_at) {
+                    info!("[Stream]: The host sends video again");
+
+                    let mut ipc_sender = stream.ipc_sender.clone();
+                    stream.runtime.spawn(async move {
+                        ipc_sender
+                            .send(StreamerIpcMessage::WebSocket(
+                                StreamMessageCode::VideoRecovered
+                                    .debug_log(Some(LogMessageType::Recover)),
+                            ))
+                            .await;
+                    });
+                }
+
+                let mut latency_test = stream.latency_test.lock().await;
+                if let Some(latency_test) = latency_test.as_mut() {
+                    latency_test.on_frame_sent(
+                        unit.presentation_time,
+                        sent_at,
+                        unit.frame_processing_latency.unwrap_or(Duration::ZERO)
+                            + frame_processing_time,
+                    );
+                }
+
                 result
             } else {
                 debug!("Dropping video packet because of missing transport");
@@ -92,6 +162,10 @@ impl VideoDecoder for StreamVideoDecoder {
 #[derive(Debug, Default)]
 pub(crate) struct VideoStats {
     last_send: Option<Instant>,
+    last_frame_number: Option<i32>,
+    frame_count: u32,
+    /// Frames which never arrived from the host, detected by gaps in the frame numbers
+    dropped_frame_count: u32,
     min_host_processing_latency: Duration,
     max_host_processing_latency: Duration,
     total_host_processing_latency: Duration,
@@ -100,6 +174,8 @@ pub(crate) struct VideoStats {
     max_streamer_processing_time: Duration,
     total_streamer_processing_time: Duration,
     streamer_processing_time_frame_count: usize,
+    #[cfg(feature = "profiling")]
+    allocations: crate::profiling::FrameAllocations,
 }
 
 impl VideoStats {
@@ -109,6 +185,14 @@ impl VideoStats {
         unit: &VideoDecodeUnit,
         frame_processing_time: Duration,
     ) {
+        if let Some(last_frame_number) = self.last_frame_number
+            && unit.frame_number > last_frame_number + 1
+        {
+            self.dropped_frame_count += (unit.frame_number - last_frame_number - 1) as u32;
+        }
+        self.last_frame_number = Some(unit.frame_number);
+        self.frame_count += 1;
+
         if let Some(host_processing_latency) = unit.frame_processing_latency {
             self.min_host_processing_latency = self
                 .min_host_processing_latency
@@ -149,6 +233,9 @@ impl VideoStats {
                 .checked_div(self.streamer_processing_time_frame_count as u32)
                 .unwrap_or(Duration::ZERO);
 
+            let frames = self.frame_count;
+            let dropped_frames = self.dropped_frame_count;
+
             // Send data
             let runtime = stream.runtime.clone();
 
@@ -203,6 +290,18 @@ impl VideoStats {
                                     false,
                                 )
                                 .await;
+
+                            send_quality_score(
+                                &stream,
+                                QualitySample {
+                                    rtt,
+                                    rtt_variance,
+                                    packets: None,
+                                    frames,
+                                    dropped_frames,
+                                },
+                            )
+                            .await;
                         }
                         Err(err) => {
                             warn!("failed to get estimated rtt info: {err:?}");
@@ -220,8 +319,40 @@ impl VideoStats {
             self.max_streamer_processing_time = Duration::ZERO;
             self.total_streamer_processing_time = Duration::ZERO;
             self.streamer_processing_time_frame_count = 0;
+            self.frame_count = 0;
+            self.dropped_frame_count = 0;
 
             self.last_send = Some(Instant::now());
         }
     }
 }
+
+async fn send_quality_score(stream: &StreamConnection, mut sample: QualitySample) {
+    sample.packets = {
+        let sender = stream.transport_sender.lock().await;
+        match sender.as_ref() {
+            Some(sender) => sender.video_packet_counters().await,
+            None => None,
+        }
+    };
+
+    let (score, changed) = stream.quality.lock().await.update(sample);
+
+    stream
+        .try_send_packet(
+            OutboundPacket::General {
+                message: GeneralServerMessage::QualityScore { score },
+            },
+            "quality score",
+            false,
+        )
+        .await;
+
+    if changed {
+        stream
+            .ipc_sender
+            .clone()
+            .send(StreamerIpcMessage::QualityScore { score })
+            .await;
+    }
+}
@@ -0,0 +1,145 @@
+//! Detects streams which stopped receiving video while the connection to the host is still alive.
+
+use std::time::Instant;
+
+use common::config::VideoWatchdogConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    RequestIdr,
+    RestartStream,
+}
+
+pub struct VideoWatchdog {
+    config: VideoWatchdogConfig,
+    /// None while no stream is running
+    last_frame: Option<Instant>,
+    idr_requested: bool,
+}
+
+impl VideoWatchdog {
+    pub fn new(config: VideoWatchdogConfig) -> Self {
+        Self {
+            config,
+            last_frame: None,
+            idr_requested: false,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// The host needs some time for the first frame, so the stream counts as a frame
+    pub fn stream_started(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+        self.idr_requested = false;
+    }
+
+    /// Returns true if the video was stalled before this frame
+    pub fn on_frame(&mut self, now: Instant) -> bool {
+        let was_stalled = self.idr_requested;
+
+        self.last_frame = Some(now);
+        self.idr_requested = false;
+
+        was_stalled
+    }
+
+    pub fn check(&mut self, now: Instant) -> Option<WatchdogAction> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let stalled_for = now.saturating_duration_since(self.last_frame?);
+
+        if stalled_for >= self.config.restart_timeout {
+            // Waits for the restarted stream
+            self.last_frame = None;
+            self.idr_requested = false;
+
+            Some(WatchdogAction::RestartStream)
+        } else if stalled_for >= self.config.idr_timeout && !self.idr_requested {
+            self.idr_requested = true;
+
+            Some(WatchdogAction::RequestIdr)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use common::config::VideoWatchdogConfig;
+
+    use crate::watchdog::{VideoWatchdog, WatchdogAction};
+
+    fn watchdog() -> VideoWatchdog {
+        VideoWatchdog::new(VideoWatchdogConfig {
+            enabled: true,
+            idr_timeout: Duration::from_secs(5),
+            restart_timeout: Duration::from_secs(15),
+        })
+    }
+
+    #[test]
+    fn test_inactive_without_stream() {
+        let mut watchdog = watchdog();
+        let now = Instant::now();
+
+        assert_eq!(watchdog.check(now + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_stall_escalation() {
+        let mut watchdog = watchdog();
+        let start = Instant::now();
+        watchdog.stream_started(start);
+
+        assert_eq!(watchdog.check(start + Duration::from_secs(4)), None);
+        assert_eq!(
+            watchdog.check(start + Duration::from_secs(5)),
+            Some(WatchdogAction::RequestIdr)
+        );
+        // Only one IDR frame is requested per stall
+        assert_eq!(watchdog.check(start + Duration::from_secs(10)), None);
+        assert_eq!(
+            watchdog.check(start + Duration::from_secs(15)),
+            Some(WatchdogAction::RestartStream)
+        );
+        // Waits until the stream was restarted
+        assert_eq!(watchdog.check(start + Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let mut watchdog = watchdog();
+        let start = Instant::now();
+        watchdog.stream_started(start);
+
+        assert!(!watchdog.on_frame(start + Duration::from_secs(1)));
+        assert_eq!(
+            watchdog.check(start + Duration::from_secs(6)),
+            Some(WatchdogAction::RequestIdr)
+        );
+
+        assert!(watchdog.on_frame(start + Duration::from_secs(7)));
+        assert_eq!(watchdog.check(start + Duration::from_secs(8)), None);
+        assert!(!watchdog.on_frame(start + Duration::from_secs(8)));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut watchdog = VideoWatchdog::new(VideoWatchdogConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        watchdog.stream_started(start);
+
+        assert_eq!(watchdog.check(start + Duration::from_secs(60)), None);
+    }
+}
@@ -0,0 +1,268 @@
+//! Administration commands which are executed from the command line instead of the web interface.
+
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, anyhow};
+use common::config::Config;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        AppError, create_key_store,
+        host::HostId,
+        password::StoragePassword,
+        storage::{
+            Storage, StorageHost, StorageHostModify, StorageUserAdd, StorageUserModify,
+            create_storage, migrate::migrate_storage,
+        },
+        user::Role,
+    },
+    cli::AdminCommand,
+    pair_import::{ClientIdentity, MoonlightQtConfig, parse_certificate},
+};
+
+pub async fn run_admin_command(
+    config: &Config,
+    command: AdminCommand,
+) -> Result<(), anyhow::Error> {
+    let storage_config = match &command {
+        AdminCommand::MigrateStorage {
+            from: Some(from), ..
+        } => from.clone(),
+        _ => config.data_storage.clone(),
+    };
+    let storage = create_storage(storage_config)
+        .await
+        .context("failed to load storage")?;
+
+    match command {
+        AdminCommand::AddUser {
+            name,
+            password,
+            admin,
+        } => {
+            let password = password_or_prompt(password)?;
+
+            let user = storage
+                .add_user(StorageUserAdd {
+                    name: name.clone(),
+                    password: Some(StoragePassword::new(&password)?),
+                    role: if admin { Role::Admin } else { Role::User },
+                    client_unique_id: name,
+                })
+                .await?;
+
+            println!("Added user \"{}\" with id {}", user.name, user.id.0);
+        }
+        AdminCommand::ResetPassword { name, password } => {
+            let (user_id, _) = storage.get_user_by_name(&name).await?;
+
+            let password = password_or_prompt(password)?;
+
+            storage
+                .modify_user(
+                    user_id,
+                    StorageUserModify {
+                        password: Some(Some(StoragePassword::new(&password)?)),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            storage.remove_all_user_session_tokens(user_id).await?;
+
+            println!("Changed the password of user \"{name}\"");
+        }
+        AdminCommand::ListHosts => {
+            for (host_id, host) in storage.list_hosts().await? {
+                let host = match host {
+                    Some(host) => host,
+                    None => storage.get_host(host_id).await?,
+                };
+
+                print_host(&*storage, &host).await?;
+            }
+        }
+        AdminCommand::Unpair { host_id } => {
+            let host_id = HostId(host_id);
+
+            let host = storage.get_host(host_id).await?;
+            let Some(pair_info) = host.pair_info else {
+                return Err(anyhow!(AppError::HostNotPaired));
+            };
+
+            storage
+                .modify_host(
+                    host_id,
+                    StorageHostModify {
+                        pair_info: Some(None),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            create_key_store(&config.client_key_store)?
+                .remove(&pair_info.client_private_key)
+                .context("failed to remove the client private key")?;
+
+            println!(
+                "Unpaired host \"{}\" with id {}",
+                host.cache.name, host_id.0
+            );
+        }
+        AdminCommand::ImportPairing {
+            host_id,
+            moonlight_qt_config,
+            certificate,
+            private_key,
+            server_certificate,
+        } => {
+            let host_id = HostId(host_id);
+            let host = storage.get_host(host_id).await?;
+
+            let server_certificate = match server_certificate {
+                Some(path) => Some(
+                    parse_certificate(&fs::read(&path).await?)
+                        .with_context(|| format!("invalid server certificate {path:?}"))?,
+                ),
+                None => None,
+            };
+
+            let (identity, server_certificate) = match (
+                moonlight_qt_config,
+                certificate,
+                private_key,
+            ) {
+                (Some(path), _, _) => {
+                    let config = MoonlightQtConfig::parse(&fs::read_to_string(&path).await?)
+                        .with_context(|| format!("failed to read Moonlight Qt config {path:?}"))?;
+
+                    let identity = ClientIdentity::new(&config.certificate, &config.private_key)?;
+
+                    let server_certificate = match server_certificate {
+                        Some(server_certificate) => server_certificate,
+                        None => {
+                            let qt_host = config.host_by_address(&host.address).ok_or_else(|| {
+                                anyhow!(
+                                    "no host with the address {} found in the Moonlight Qt config, specify the certificate of the host with --server-certificate",
+                                    host.address
+                                )
+                            })?;
+                            let certificate =
+                                qt_host.server_certificate.as_ref().ok_or_else(|| {
+                                    anyhow!(
+                                        "the host \"{}\" isn't paired in Moonlight Qt",
+                                        qt_host.name
+                                    )
+                                })?;
+
+                            parse_certificate(certificate)
+                                .context("invalid server certificate in the Moonlight Qt config")?
+                        }
+                    };
+
+                    (identity, server_certificate)
+                }
+                (None, Some(certificate), Some(private_key)) => {
+                    let identity = ClientIdentity::new(
+                        &fs::read(&certificate).await?,
+                        &fs::read(&private_key).await?,
+                    )?;
+
+                    (
+                        identity,
+                        server_certificate.context("the certificate of the host is required")?,
+                    )
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "specify either --moonlight-qt-config or --certificate and --private-key"
+                    ));
+                }
+            };
+
+            let mut pair_info = identity.into_pair_info(server_certificate);
+            pair_info.client_private_key = create_key_store(&config.client_key_store)?
+                .store(&Uuid::new_v4().to_string(), &pair_info.client_private_key)
+                .context("failed to store the client private key")?;
+
+            storage
+                .modify_host(
+                    host_id,
+                    StorageHostModify {
+                        pair_info: Some(Some(pair_info)),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            println!(
+                "Imported the pairing of host \"{}\" with id {}",
+                host.cache.name, host_id.0
+            );
+        }
+        AdminCommand::MigrateStorage { from: _, to: None } => {
+            // Loading the storage already migrated the data, we only need to store it
+            println!("Migrated storage to the newest format");
+        }
+        AdminCommand::MigrateStorage {
+            from: _,
+            to: Some(to),
+        } => {
+            let target = create_storage(to)
+                .await
+                .context("failed to load target storage")?;
+
+            let migration = migrate_storage(&*storage, &*target).await?;
+
+            println!(
+                "Copied {} users and {} hosts into the new storage, change `data_storage` in the config to use it",
+                migration.users, migration.hosts
+            );
+            println!("Sessions aren't copied, every user has to log in again");
+        }
+    }
+
+    storage.flush().await?;
+
+    Ok(())
+}
+
+async fn print_host(
+    storage: &(dyn Storage + Send + Sync),
+    host: &StorageHost,
+) -> Result<(), AppError> {
+    let owner = match host.owner {
+        None => "everyone".to_string(),
+        Some(user_id) => match storage.get_user(user_id).await {
+            Ok(user) => user.name,
+            Err(AppError::UserNotFound) => format!("unknown user {}", user_id.0),
+            Err(err) => return Err(err),
+        },
+    };
+
+    println!(
+        "{}: \"{}\" at {}:{}, owner: {owner}, paired: {}",
+        host.id.0,
+        host.cache.name,
+        host.address,
+        host.http_port,
+        host.pair_info.is_some()
+    );
+
+    Ok(())
+}
+
+fn password_or_prompt(password: Option<String>) -> Result<String, anyhow::Error> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+
+    eprint!("Password: ");
+    io::stderr().flush()?;
+
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
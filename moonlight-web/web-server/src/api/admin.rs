@@ -1,18 +1,24 @@
 use actix_web::{
     HttpResponse, delete, get, patch, post,
-    web::{Data, Json},
+    web::{Data, Json, Query},
 };
 use common::api_bindings::{
-    DeleteUserRequest, DetailedUser, GetUsersResponse, PatchUserRequest, PostUserRequest,
+    DeleteUserRequest, DetailedUser, GetHostClientsResponse, GetUsersQuery, GetUsersResponse,
+    PatchUserRequest, PostUserRequest,
 };
 use futures::future::join_all;
 use log::warn;
 
-use crate::app::{
-    App, AppError,
-    password::StoragePassword,
-    storage::{StorageUserAdd, StorageUserModify},
-    user::{Admin, AuthenticatedUser, Role, UserId},
+use crate::{
+    api::pagination,
+    app::{
+        App, AppError,
+        password::StoragePassword,
+        storage::{
+            StorageUserAdd, StorageUserModify, StorageUserStreamLimits, query::StorageUserFilter,
+        },
+        user::{Admin, AuthenticatedUser, Role, UserId},
+    },
 };
 
 #[post("/user")]
@@ -63,6 +69,8 @@ pub async fn patch_user(
                         password: Some(new_password),
                         role: request.role.map(Role::from),
                         client_unique_id: request.client_unique_id,
+                        stream_limits: request.stream_limits.map(StorageUserStreamLimits::from),
+                        privacy_mode: request.privacy_mode,
                     },
                 )
                 .await?;
@@ -72,14 +80,16 @@ pub async fn patch_user(
                 return Err(AppError::Forbidden);
             }
 
-            // Only allow changing the password
+            // Only allow changing the password and the privacy mode
             let PatchUserRequest {
                 id: _,
                 password: _,
                 role,
                 client_unique_id,
+                stream_limits,
+                privacy_mode: _,
             } = &request;
-            if role.is_some() || client_unique_id.is_some() {
+            if role.is_some() || client_unique_id.is_some() || stream_limits.is_some() {
                 return Err(AppError::Forbidden);
             }
 
@@ -87,6 +97,9 @@ pub async fn patch_user(
                 user.set_password(StoragePassword::new(&new_password)?)
                     .await?;
             }
+            if let Some(privacy_mode) = request.privacy_mode {
+                user.set_privacy_mode(privacy_mode).await?;
+            }
         }
     }
 
@@ -109,8 +122,18 @@ pub async fn delete_user(
 }
 
 #[get("/users")]
-pub async fn list_users(app: Data<App>, admin: Admin) -> Result<Json<GetUsersResponse>, AppError> {
-    let mut users = app.all_users(admin).await?;
+pub async fn list_users(
+    app: Data<App>,
+    admin: Admin,
+    Query(query): Query<GetUsersQuery>,
+) -> Result<Json<GetUsersResponse>, AppError> {
+    let pagination = pagination(query.cursor, query.limit, query.sort_by, query.descending)?;
+    let filter = StorageUserFilter {
+        role: query.role.map(Role::from),
+        name_contains: query.name,
+    };
+
+    let (mut users, next_cursor) = app.query_users(admin, filter, pagination).await?;
 
     let user_results = join_all(users.iter_mut().map(|user| user.detailed_user_no_auth())).await;
 
@@ -126,5 +149,18 @@ pub async fn list_users(app: Data<App>, admin: Admin) -> Result<Json<GetUsersRes
         }
     }
 
-    Ok(Json(GetUsersResponse { users: out_users }))
+    Ok(Json(GetUsersResponse {
+        users: out_users,
+        next_cursor: next_cursor.map(|cursor| cursor.to_string()),
+    }))
+}
+
+#[get("/host-clients")]
+pub async fn list_host_clients(
+    app: Data<App>,
+    admin: Admin,
+) -> Result<Json<GetHostClientsResponse>, AppError> {
+    let clients = app.host_client_stats(&admin).await;
+
+    Ok(Json(GetHostClientsResponse { clients }))
 }
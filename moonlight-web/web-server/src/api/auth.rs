@@ -10,12 +10,16 @@ use actix_web::{
 };
 use common::api_bindings::PostLoginRequest;
 use futures::future::{Ready, ready};
-use std::{pin::Pin, time::Duration};
-
-use crate::app::{
-    App, AppError,
-    auth::{SessionToken, UserAuth},
-    user::{Admin, AuthenticatedUser},
+use log::debug;
+use std::{net::IpAddr, pin::Pin, time::Duration};
+
+use crate::{
+    api::client_ip::client_ip,
+    app::{
+        App, AppError,
+        auth::{SessionToken, UserAuth},
+        user::{Admin, AuthenticatedUser},
+    },
 };
 
 pub const COOKIE_SESSION_TOKEN_NAME: &str = "mlSession";
@@ -82,17 +86,42 @@ impl FromRequest for AuthenticatedUser {
 
         let auth_future = UserAuth::from_request(req, payload);
 
+        let client_ip = client_ip(req);
+        let path = req.path().to_string();
+
         let app = app.clone();
         Box::pin(async move {
             let auth = auth_future.await?;
 
+            if let UserAuth::None = auth
+                && !guest_ip_allowed(&app, client_ip)
+            {
+                return Err(AppError::Unauthorized);
+            }
+
             let user = app.user_by_auth(auth).await?;
 
+            if user.is_guest() {
+                debug!("[Guest]: {path} requested by {client_ip:?}");
+            }
+
             Ok(user)
         })
     }
 }
 
+fn guest_ip_allowed(app: &App, client_ip: Option<IpAddr>) -> bool {
+    let Some(allowed_networks) = &app.config().web_server.default_user.allowed_networks else {
+        return true;
+    };
+
+    client_ip.is_some_and(|client_ip| {
+        allowed_networks
+            .iter()
+            .any(|network| network.contains(&client_ip))
+    })
+}
+
 impl FromRequest for Admin {
     type Error = AppError;
 
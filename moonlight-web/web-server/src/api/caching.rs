@@ -0,0 +1,191 @@
+//! Responses which the browser caches, but validates with the server before using them again.
+
+use std::time::SystemTime;
+
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse,
+    http::header::{
+        ACCEPT_RANGES, ContentRange, ContentRangeSpec, ETag, EntityTag, HttpDate, IfModifiedSince,
+        IfNoneMatch, IfRange, LastModified, Range,
+    },
+    web::Bytes,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum RequestedRange {
+    Full,
+    /// Start and end are inclusive
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Answers conditional requests with 304 and single byte ranges with 206.
+pub fn validated_response(
+    req: &HttpRequest,
+    content: Bytes,
+    etag: &str,
+    last_modified: SystemTime,
+) -> HttpResponse {
+    let etag = EntityTag::new_strong(etag.to_string());
+    // Http dates only have a precision of seconds
+    let last_modified = HttpDate::from(last_modified);
+
+    let length = content.len() as u64;
+
+    let (mut response, body) = if is_not_modified(req, &etag, last_modified) {
+        (HttpResponse::NotModified(), None)
+    } else {
+        match requested_range(req, &etag, last_modified, length) {
+            RequestedRange::Full => (HttpResponse::Ok(), Some(content)),
+            RequestedRange::Partial(start, end) => {
+                let mut response = HttpResponse::PartialContent();
+                response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)),
+                    instance_length: Some(length),
+                }));
+
+                (response, Some(content.slice(start as usize..=end as usize)))
+            }
+            RequestedRange::Unsatisfiable => {
+                let mut response = HttpResponse::RangeNotSatisfiable();
+                response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: None,
+                    instance_length: Some(length),
+                }));
+
+                (response, None)
+            }
+        }
+    };
+
+    response
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header((ACCEPT_RANGES, "bytes"));
+
+    match body {
+        Some(body) => response.body(body),
+        None => response.finish(),
+    }
+}
+
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+
+    if let Some(IfModifiedSince(since)) = req.get_header::<IfModifiedSince>() {
+        return SystemTime::from(last_modified) <= SystemTime::from(since);
+    }
+
+    false
+}
+
+fn requested_range(
+    req: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: HttpDate,
+    length: u64,
+) -> RequestedRange {
+    let Some(Range::Bytes(ranges)) = req.get_header::<Range>() else {
+        return RequestedRange::Full;
+    };
+
+    // The range of a different version would corrupt the data which the client already has
+    if let Some(if_range) = req.get_header::<IfRange>() {
+        let unchanged = match if_range {
+            IfRange::EntityTag(tag) => tag.strong_eq(etag),
+            IfRange::Date(date) => SystemTime::from(last_modified) <= SystemTime::from(date),
+        };
+
+        if !unchanged {
+            return RequestedRange::Full;
+        }
+    }
+
+    // Multiple ranges require a multipart response
+    let [range] = ranges.as_slice() else {
+        return RequestedRange::Full;
+    };
+
+    match range.to_satisfiable_range(length) {
+        Some((start, end)) => RequestedRange::Partial(start, end),
+        None => RequestedRange::Unsatisfiable,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use actix_web::{
+        http::header::{EntityTag, HttpDate},
+        test::TestRequest,
+    };
+
+    use crate::api::caching::{RequestedRange, is_not_modified, requested_range};
+
+    fn etag() -> EntityTag {
+        EntityTag::new_strong("abc".to_string())
+    }
+
+    fn last_modified() -> HttpDate {
+        HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let req = TestRequest::default()
+            .insert_header(("If-None-Match", "\"abc\""))
+            .to_http_request();
+        assert!(is_not_modified(&req, &etag(), last_modified()));
+
+        let req = TestRequest::default()
+            .insert_header(("If-None-Match", "\"other\""))
+            .insert_header(("If-Modified-Since", "Wed, 15 Nov 2023 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!is_not_modified(&req, &etag(), last_modified()));
+
+        let req = TestRequest::default()
+            .insert_header(("If-Modified-Since", "Wed, 15 Nov 2023 00:00:00 GMT"))
+            .to_http_request();
+        assert!(is_not_modified(&req, &etag(), last_modified()));
+
+        let req = TestRequest::default()
+            .insert_header(("If-Modified-Since", "Mon, 13 Nov 2023 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!is_not_modified(&req, &etag(), last_modified()));
+
+        let req = TestRequest::default().to_http_request();
+        assert!(!is_not_modified(&req, &etag(), last_modified()));
+    }
+
+    #[test]
+    fn test_requested_range() {
+        let range = |value: &'static str| {
+            let req = TestRequest::default()
+                .insert_header(("Range", value))
+                .to_http_request();
+            requested_range(&req, &etag(), last_modified(), 100)
+        };
+
+        assert_eq!(range("bytes=0-9"), RequestedRange::Partial(0, 9));
+        assert_eq!(range("bytes=90-"), RequestedRange::Partial(90, 99));
+        assert_eq!(range("bytes=-10"), RequestedRange::Partial(90, 99));
+        assert_eq!(range("bytes=200-300"), RequestedRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,5-6"), RequestedRange::Full);
+
+        let req = TestRequest::default()
+            .insert_header(("Range", "bytes=0-9"))
+            .insert_header(("If-Range", "\"other\""))
+            .to_http_request();
+        assert_eq!(
+            requested_range(&req, &etag(), last_modified(), 100),
+            RequestedRange::Full
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Finds the ip of the client, even if the request was forwarded by trusted proxies.
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{HttpRequest, http::header::HeaderMap, web::Data};
+use common::config::{TrustedProxyHeader, WebServerConfig};
+
+use crate::app::App;
+
+/// The ip of the client which sent the request.
+///
+/// The `trusted_proxy_header` is only used if the request comes from one of the `trusted_proxies`.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer_ip = req.peer_addr()?.ip();
+
+    let Some(app) = req.app_data::<Data<App>>() else {
+        return Some(peer_ip);
+    };
+
+    let config = &app.config().web_server;
+
+    Some(resolve_client_ip(
+        config,
+        peer_ip,
+        &forwarded_chain(req.headers(), config.trusted_proxy_header),
+    ))
+}
+
+/// Walks the chain from the closest to the furthest hop until an untrusted ip or the hop limit is reached.
+fn resolve_client_ip(
+    config: &WebServerConfig,
+    peer_ip: IpAddr,
+    chain: &[Option<IpAddr>],
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| {
+        config
+            .trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    };
+
+    let mut client_ip = peer_ip;
+    for (hop, forwarded_ip) in chain.iter().rev().enumerate() {
+        if hop >= config.trusted_proxy_hops || !is_trusted(&client_ip) {
+            break;
+        }
+
+        // An obfuscated or invalid ip hides everything behind it
+        let Some(forwarded_ip) = forwarded_ip else {
+            break;
+        };
+        client_ip = *forwarded_ip;
+    }
+
+    client_ip
+}
+
+/// Every ip the request was forwarded for, the client comes first.
+/// Only the configured header is read, a client could add the other one itself.
+fn forwarded_chain(headers: &HeaderMap, header: TrustedProxyHeader) -> Vec<Option<IpAddr>> {
+    match header {
+        TrustedProxyHeader::Forwarded => headers
+            .get_all("Forwarded")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect(),
+        TrustedProxyHeader::XForwardedFor => headers
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(parse_node)
+            .collect(),
+    }
+}
+
+/// Parses ips like `192.0.2.60`, `"192.0.2.60:4711"` or `"[2001:db8:cafe::17]:4711"`
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(address) = value.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+
+    value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use common::config::{TrustedProxyHeader, WebServerConfig};
+
+    use crate::api::client_ip::{forwarded_chain, parse_node, resolve_client_ip};
+
+    #[allow(clippy::unwrap_used)]
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[allow(clippy::unwrap_used)]
+    fn config(trusted_proxies: &[&str], trusted_proxy_hops: usize) -> WebServerConfig {
+        WebServerConfig {
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|network| network.parse().unwrap())
+                .collect(),
+            trusted_proxy_hops,
+            ..Default::default()
+        }
+    }
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_parse_node() {
+        assert_eq!(parse_node("192.0.2.60"), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node(" \"192.0.2.60:4711\""), Some(ip("192.0.2.60")));
+        assert_eq!(
+            parse_node("\"[2001:db8:cafe::17]:4711\""),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(
+            parse_node("[2001:db8:cafe::17]"),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(parse_node("_hidden"), None);
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let chain = forwarded_chain(
+            &headers(
+                "forwarded",
+                "for=192.0.2.60;proto=http;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\"",
+            ),
+            TrustedProxyHeader::Forwarded,
+        );
+
+        assert_eq!(
+            chain,
+            vec![Some(ip("192.0.2.60")), Some(ip("2001:db8:cafe::17"))]
+        );
+    }
+
+    #[test]
+    fn test_x_forwarded_for_header() {
+        let chain = forwarded_chain(
+            &headers("x-forwarded-for", "203.0.113.1, unknown, 10.0.0.2"),
+            TrustedProxyHeader::XForwardedFor,
+        );
+
+        assert_eq!(
+            chain,
+            vec![Some(ip("203.0.113.1")), None, Some(ip("10.0.0.2"))]
+        );
+    }
+
+    /// A client can't spoof its ip with the header which the proxy doesn't write
+    #[test]
+    fn test_only_configured_header() {
+        let mut headers = headers("x-forwarded-for", "203.0.113.1");
+        headers.insert(
+            HeaderName::from_static("forwarded"),
+            HeaderValue::from_static("for=10.0.0.5"),
+        );
+
+        assert_eq!(
+            forwarded_chain(&headers, TrustedProxyHeader::XForwardedFor),
+            vec![Some(ip("203.0.113.1"))]
+        );
+        assert_eq!(
+            forwarded_chain(&headers, TrustedProxyHeader::Forwarded),
+            vec![Some(ip("10.0.0.5"))]
+        );
+    }
+
+    #[test]
+    fn test_untrusted_peer() {
+        let config = config(&["10.0.0.0/8"], 1);
+
+        let client = resolve_client_ip(&config, ip("203.0.113.7"), &[Some(ip("1.1.1.1"))]);
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_trusted_hops() {
+        let chain = [Some(ip("203.0.113.1")), Some(ip("10.0.0.2"))];
+
+        let client = resolve_client_ip(&config(&["10.0.0.0/8"], 1), ip("10.0.0.1"), &chain);
+        assert_eq!(client, ip("10.0.0.2"));
+
+        let client = resolve_client_ip(&config(&["10.0.0.0/8"], 2), ip("10.0.0.1"), &chain);
+        assert_eq!(client, ip("203.0.113.1"));
+    }
+
+    #[test]
+    fn test_obfuscated_hop() {
+        let chain = [Some(ip("203.0.113.1")), None];
+
+        let client = resolve_client_ip(&config(&["10.0.0.0/8"], 2), ip("10.0.0.1"), &chain);
+        assert_eq!(client, ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,48 @@
+use actix_web::{
+    HttpResponse, delete, get,
+    web::{Json, Query},
+};
+use common::api_bindings::{DeleteInputMacroQuery, GetInputMacrosResponse, InputMacroInfo};
+
+use crate::app::{AppError, user::AuthenticatedUser};
+
+#[get("/user/macros")]
+pub async fn get_input_macros(
+    user: AuthenticatedUser,
+) -> Result<Json<GetInputMacrosResponse>, AppError> {
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
+    let input_macros = user.input_macros().await?;
+
+    Ok(Json(GetInputMacrosResponse {
+        macros: input_macros
+            .into_iter()
+            .map(|input_macro| InputMacroInfo {
+                id: input_macro.id,
+                name: input_macro.name,
+                event_count: input_macro.events.len() as u32,
+                duration_ms: input_macro
+                    .events
+                    .iter()
+                    .map(|event| u64::from(event.delay_ms))
+                    .sum(),
+            })
+            .collect(),
+    }))
+}
+
+#[delete("/user/macro")]
+pub async fn delete_input_macro(
+    user: AuthenticatedUser,
+    Query(query): Query<DeleteInputMacroQuery>,
+) -> Result<HttpResponse, AppError> {
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
+    user.remove_input_macro(query.id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
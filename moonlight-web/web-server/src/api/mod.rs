@@ -1,42 +1,103 @@
 use actix_web::{
-    HttpResponse, delete,
+    HttpRequest, HttpResponse, delete,
     dev::HttpServiceFactory,
     get,
     middleware::from_fn,
     patch, post, services,
-    web::{self, Bytes, Data, Json, Query},
+    web::{self, Data, Json, Query},
+};
+use futures::{StreamExt, future::try_join_all};
+use log::{debug, warn};
+use moonlight_common::{
+    PairPin, PinPolicy,
+    network::{launch::AudioRouting, request_client::CancellationToken},
 };
-use futures::future::try_join_all;
-use log::warn;
-use moonlight_common::PairPin;
 use tokio::spawn;
 
 use crate::{
     api::{
-        admin::{add_user, delete_user, list_users, patch_user},
+        admin::{add_user, delete_user, list_host_clients, list_users, patch_user},
         auth::auth_middleware,
+        caching::validated_response,
+        input_macro::{delete_input_macro, get_input_macros},
         response_streaming::StreamedResponse,
+        stream::quit_host_app,
+        sunshine::{
+            get_sunshine_apps, get_sunshine_encoder, get_sunshine_logs, patch_sunshine_encoder,
+            post_sunshine_app, put_sunshine_credentials,
+        },
+        tailscale::get_tailscale_peers,
+        web_push::{delete_push_subscription, get_web_push_key, post_push_subscription},
     },
     app::{
         App, AppError,
         host::{AppId, HostId},
-        storage::StorageHostModify,
-        user::{AuthenticatedUser, Role, UserId},
+        storage::{
+            StorageHostModify,
+            query::{StorageHostFilter, StoragePagination, StorageSortBy},
+        },
+        user::{Admin, AuthenticatedUser, Role, UserId},
     },
 };
 use common::api_bindings::{
-    self, DeleteHostQuery, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse,
-    GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, PatchHostRequest,
-    PostHostRequest, PostHostResponse, PostPairRequest, PostPairResponse1, PostPairResponse2,
-    PostWakeUpRequest, UndetailedHost,
+    self, BuildInfo, DeleteHostQuery, DetailedUser, GetAppImageQuery, GetAppStreamDefaultsQuery,
+    GetAppStreamDefaultsResponse, GetAppsQuery, GetAppsResponse, GetCapabilitiesResponse,
+    GetHostCertificatesResponse, GetHostDisplaysQuery, GetHostDisplaysResponse, GetHostQuery,
+    GetHostResponse, GetHostsQuery, GetHostsResponse, GetUserQuery, GetVersionResponse,
+    HostDisplay, HostOwner, ListSortBy, PairStatus, PatchHostRequest, PostAppLaunchRequest,
+    PostAppLaunchResponse, PostAppQuitRequest, PostAppQuitResponse,
+    PostHostCertificateRotateRequest, PostHostCertificateRotateResponse, PostHostRequest,
+    PostHostResponse, PostPairRequest, PostPairResponse1, PostPairResponse2, PostWakeUpRequest,
+    PushNotification, PushNotificationKind, StreamDefaults, UndetailedHost,
 };
 
 pub mod admin;
 pub mod auth;
+pub mod caching;
+pub mod client_ip;
+pub mod input_macro;
 pub mod stream;
+pub mod sunshine;
+pub mod tailscale;
+pub mod web_push;
 
 pub mod response_streaming;
 
+/// The cargo features this web server was built with
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "keyring")]
+    "keyring",
+];
+
+#[get("/version")]
+async fn get_version(app: Data<App>, _user: AuthenticatedUser) -> Json<GetVersionResponse> {
+    Json(GetVersionResponse::new(
+        BuildInfo::current(FEATURES),
+        app.streamer_build_info().await,
+    ))
+}
+
+#[get("/capabilities")]
+async fn get_capabilities(
+    app: Data<App>,
+    _user: AuthenticatedUser,
+) -> Result<Json<GetCapabilitiesResponse>, AppError> {
+    let streamer = app.streamer_capabilities().await?;
+    let config = app.config();
+
+    Ok(Json(GetCapabilitiesResponse {
+        streamer,
+        screenshots: streamer.video_decoding,
+        session_preview: streamer.video_decoding,
+        session_thumbnails: streamer.video_decoding && !config.session_thumbnail.interval.is_zero(),
+        rtsp_output: streamer.rtsp_output && config.rtsp_output.enabled,
+        hls_output: streamer.hls_output && config.hls_output.enabled,
+        hls_recording: streamer.hls_recording
+            && config.hls_output.enabled
+            && config.hls_output.recording_directory.is_some(),
+    }))
+}
+
 #[get("/user")]
 async fn get_user(
     app: Data<App>,
@@ -69,14 +130,56 @@ async fn get_user(
     }
 }
 
+fn pagination(
+    cursor: Option<String>,
+    limit: Option<u32>,
+    sort_by: Option<ListSortBy>,
+    descending: Option<bool>,
+) -> Result<StoragePagination, AppError> {
+    let cursor = match cursor {
+        Some(cursor) => Some(cursor.parse().map_err(|_| AppError::BadRequest)?),
+        None => None,
+    };
+    // An empty page wouldn't have a cursor to continue with
+    if limit == Some(0) {
+        return Err(AppError::BadRequest);
+    }
+
+    Ok(StoragePagination {
+        cursor,
+        limit: limit.map(|limit| limit as usize),
+        sort_by: match sort_by.unwrap_or_default() {
+            ListSortBy::Id => StorageSortBy::Id,
+            ListSortBy::Name => StorageSortBy::Name,
+        },
+        descending: descending.unwrap_or(false),
+    })
+}
+
 #[get("/hosts")]
 async fn list_hosts(
     mut user: AuthenticatedUser,
+    Query(query): Query<GetHostsQuery>,
 ) -> Result<StreamedResponse<GetHostsResponse, UndetailedHost>, AppError> {
-    let (mut stream_response, stream_sender) =
-        StreamedResponse::new(GetHostsResponse { hosts: Vec::new() });
+    let (mut stream_response, stream_sender) = StreamedResponse::new(GetHostsResponse {
+        hosts: Vec::new(),
+        next_cursor: None,
+    });
 
-    let hosts = user.hosts().await?;
+    let pagination = pagination(query.cursor, query.limit, query.sort_by, query.descending)?;
+    let filter = StorageHostFilter {
+        owner: query.owner.map(|owner| match owner {
+            HostOwner::ThisUser => Some(user.id()),
+            HostOwner::Global => None,
+        }),
+        paired: query
+            .paired
+            .map(|paired| matches!(paired, PairStatus::Paired)),
+        name_contains: query.name,
+        ..Default::default()
+    };
+
+    let (hosts, next_cursor) = user.query_hosts(filter, pagination).await?;
 
     // Try join all because storage should always work, the actual host info will be send using response streaming
     let undetailed_hosts = try_join_all(hosts.into_iter().map(move |mut host| {
@@ -113,6 +216,7 @@ async fn list_hosts(
 
     stream_response.set_initial(GetHostsResponse {
         hosts: undetailed_hosts,
+        next_cursor: next_cursor.map(|cursor| cursor.to_string()),
     });
 
     Ok(stream_response)
@@ -152,11 +256,21 @@ async fn post_host(
     }))
 }
 
+const MAX_HOST_NOTES_LENGTH: usize = 4096;
+const MAX_HOST_LABELS: usize = 32;
+/// Applies to the key and the value of a label
+const MAX_HOST_LABEL_LENGTH: usize = 128;
+
 #[patch("/host")]
 async fn patch_host(
     mut user: AuthenticatedUser,
     Json(request): Json<PatchHostRequest>,
 ) -> Result<HttpResponse, AppError> {
+    // Guests act as the default user, but they may not change its hosts
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
     let host_id = HostId(request.host_id);
 
     let mut host = user.host(host_id).await?;
@@ -175,6 +289,32 @@ async fn patch_host(
         }
     }
 
+    if request.notes.is_some() || request.labels.is_some() {
+        // Global hosts are shared, so only admins can write notes for them
+        if role != Role::Admin && host.owner().await? != Some(user.id()) {
+            return Err(AppError::Forbidden);
+        }
+
+        if let Some(notes) = &request.notes
+            && notes.chars().count() > MAX_HOST_NOTES_LENGTH
+        {
+            return Err(AppError::BadRequest);
+        }
+        if let Some(labels) = &request.labels
+            && (labels.len() > MAX_HOST_LABELS
+                || labels.iter().any(|(key, value)| {
+                    key.trim().is_empty()
+                        || key.chars().count() > MAX_HOST_LABEL_LENGTH
+                        || value.chars().count() > MAX_HOST_LABEL_LENGTH
+                }))
+        {
+            return Err(AppError::BadRequest);
+        }
+
+        modify.notes = request.notes;
+        modify.labels = request.labels;
+    }
+
     host.modify(&mut user, modify).await?;
 
     Ok(HttpResponse::Ok().finish())
@@ -194,6 +334,7 @@ async fn delete_host(
 
 #[post("/pair")]
 async fn pair_host(
+    app: Data<App>,
     mut user: AuthenticatedUser,
     Json(request): Json<PostPairRequest>,
 ) -> Result<StreamedResponse<PostPairResponse1, PostPairResponse2>, AppError> {
@@ -201,10 +342,16 @@ async fn pair_host(
 
     let mut host = user.host(host_id).await?;
 
-    let pin = PairPin::generate()?;
+    let pin = PairPin::generate_with(PinPolicy::EXTENDED, app.config().moonlight.pair_pin_length)?;
+    let pin_info = host.pair_pin_info(&mut user, pin).await?;
 
-    let (stream_response, stream_sender) =
-        StreamedResponse::new(PostPairResponse1::Pin(pin.to_string()));
+    let (mut stream_response, stream_sender) =
+        StreamedResponse::new(PostPairResponse1::Pin(pin_info));
+
+    // Nobody enters the pin anymore once the client is gone
+    let cancellation_token = CancellationToken::new();
+    host.set_cancellation_token(cancellation_token.clone());
+    stream_response.cancel_on_drop(cancellation_token);
 
     spawn(async move {
         let result = host.pair(&mut user, pin).await;
@@ -237,18 +384,118 @@ async fn pair_host(
 
 #[post("/host/wake")]
 async fn wake_host(
+    app: Data<App>,
     mut user: AuthenticatedUser,
     Json(request): Json<PostWakeUpRequest>,
 ) -> Result<HttpResponse, AppError> {
     let host_id = HostId(request.host_id);
 
-    let host = user.host(host_id).await?;
+    let mut host = user.host(host_id).await?;
 
     host.wake(&mut user).await?;
 
+    if app.config().web_push.enabled {
+        let timeout = app.config().web_push.host_online_timeout;
+
+        // The host takes a while to boot, the browser might be closed by then
+        spawn(async move {
+            if let Err(err) = host.wait_online(&mut user, timeout).await {
+                debug!("Host {host_id:?} didn't come online after waking it: {err}");
+                return;
+            }
+
+            let name = host
+                .name()
+                .await
+                .unwrap_or_else(|_| format!("host {}", host_id.0));
+            app.notify_user(
+                user.id(),
+                PushNotification {
+                    kind: PushNotificationKind::HostOnline,
+                    title: format!("{name} is online"),
+                    body: format!("{name} woke up and is ready to stream."),
+                    host_id: Some(host_id.0),
+                },
+            );
+        });
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
+#[get("/host/certificates")]
+async fn get_host_certificates(
+    app: Data<App>,
+    admin: Admin,
+) -> Result<Json<GetHostCertificatesResponse>, AppError> {
+    let hosts = app.paired_hosts(&admin).await?;
+
+    let mut user = AuthenticatedUser::clone(&admin);
+    let mut certificates = Vec::with_capacity(hosts.len());
+    for host in hosts {
+        match host.client_certificate(&mut user).await {
+            Ok(Some(certificate)) => certificates.push(certificate),
+            Ok(None) => {}
+            Err(err) => {
+                warn!("Failed to read the client certificate of host {host:?}: {err}");
+            }
+        }
+    }
+
+    Ok(Json(GetHostCertificatesResponse { certificates }))
+}
+
+#[post("/host/certificate/rotate")]
+async fn rotate_host_certificate(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostHostCertificateRotateRequest>,
+) -> Result<Json<PostHostCertificateRotateResponse>, AppError> {
+    let mut host = user.host(HostId(request.host_id)).await?;
+
+    let rotated = host
+        .rotate_client_certificate(&mut user, request.force)
+        .await?;
+
+    Ok(Json(PostHostCertificateRotateResponse { rotated }))
+}
+
+#[get("/host/displays")]
+async fn get_host_displays(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+    Query(query): Query<GetHostDisplaysQuery>,
+) -> Result<Json<GetHostDisplaysResponse>, AppError> {
+    let host_id = HostId(query.host_id);
+
+    // Only users who can access the host should see its displays
+    user.host(host_id).await?;
+
+    let mut displays = Vec::new();
+    for (display_id, display) in web_app
+        .config()
+        .moonlight
+        .host_displays(host_id.0)
+        .iter()
+        .enumerate()
+    {
+        let mut app_ids = Vec::new();
+        for app_id in display.app_variants.keys() {
+            if user.can_use_app(AppId(*app_id))? {
+                app_ids.push(*app_id);
+            }
+        }
+        app_ids.sort();
+
+        displays.push(HostDisplay {
+            display_id: display_id as u32,
+            name: display.name.clone(),
+            app_ids,
+        });
+    }
+
+    Ok(Json(GetHostDisplaysResponse { displays }))
+}
+
 #[get("/apps")]
 async fn get_apps(
     mut user: AuthenticatedUser,
@@ -274,9 +521,10 @@ async fn get_apps(
 
 #[get("/app/image")]
 async fn get_app_image(
+    req: HttpRequest,
     mut user: AuthenticatedUser,
     Query(query): Query<GetAppImageQuery>,
-) -> Result<Bytes, AppError> {
+) -> Result<HttpResponse, AppError> {
     let host_id = HostId(query.host_id);
     let app_id = AppId(query.app_id);
 
@@ -286,7 +534,156 @@ async fn get_app_image(
         .app_image(&mut user, app_id, query.force_refresh)
         .await?;
 
-    Ok(image)
+    Ok(validated_response(
+        &req,
+        image.data,
+        &image.etag,
+        image.fetched_at,
+    ))
+}
+
+/// How many images are fetched from the host at the same time
+const APP_IMAGE_PREFETCH_CONCURRENCY: usize = 4;
+
+#[post("/apps/images/prefetch")]
+async fn prefetch_app_images(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostAppImagesPrefetchRequest>,
+) -> Result<
+    StreamedResponse<PostAppImagesPrefetchResponse1, PostAppImagesPrefetchResponse2>,
+    AppError,
+> {
+    let host_id = HostId(request.host_id);
+
+    let mut host = user.host(host_id).await?;
+
+    let mut app_ids = Vec::new();
+    for app in host.list_apps(&mut user).await? {
+        if user.can_use_app(app.id)? {
+            app_ids.push(app.id);
+        }
+    }
+
+    let total = app_ids.len() as u32;
+    let force_refresh = request.force_refresh;
+    let (stream_response, stream_sender) =
+        StreamedResponse::new(PostAppImagesPrefetchResponse1 { total });
+
+    spawn(async move {
+        let mut results = futures::stream::iter(app_ids)
+            .map(|app_id| {
+                // The host info is already cached by the app list, the clones don't request it again
+                let mut host = host.clone();
+                let mut user = user.clone();
+
+                async move {
+                    let result = host.app_image(&mut user, app_id, force_refresh).await;
+
+                    if let Err(err) = &result {
+                        warn!(
+                            "Failed to prefetch the image of app {app_id:?} of host {host:?}: {err}"
+                        );
+                    }
+
+                    (app_id, result.is_ok())
+                }
+            })
+            .buffer_unordered(APP_IMAGE_PREFETCH_CONCURRENCY);
+
+        let mut finished = 0;
+        while let Some((app_id, success)) = results.next().await {
+            finished += 1;
+
+            if let Err(err) = stream_sender
+                .send(PostAppImagesPrefetchResponse2 {
+                    app_id: app_id.0,
+                    success,
+                    finished,
+                    total,
+                })
+                .await
+            {
+                // The client stopped listening, so there's no reason to fetch the other images
+                warn!("Failed to send app image prefetch progress: {err}");
+                return;
+            }
+        }
+    });
+
+    Ok(stream_response)
+}
+
+#[post("/app/launch")]
+async fn launch_app(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostAppLaunchRequest>,
+) -> Result<Json<PostAppLaunchResponse>, AppError> {
+    let host_id = HostId(request.host_id);
+    let app_id = AppId(request.app_id);
+
+    let mut host = user.host(host_id).await?;
+
+    let launched = host
+        .launch_app(
+            &mut user,
+            app_id,
+            request.width,
+            request.height,
+            request.fps,
+            request.hdr,
+            // Without a stream there's no client which could play the audio
+            if request.play_audio_local {
+                AudioRouting::Host
+            } else {
+                AudioRouting::Client
+            },
+        )
+        .await?;
+
+    Ok(Json(PostAppLaunchResponse { launched }))
+}
+
+#[get("/app/stream-defaults")]
+async fn get_app_stream_defaults(
+    mut user: AuthenticatedUser,
+    Query(query): Query<GetAppStreamDefaultsQuery>,
+) -> Result<Json<GetAppStreamDefaultsResponse>, AppError> {
+    let host_id = HostId(query.host_id);
+    let app_id = AppId(query.app_id);
+
+    // Only users who can access the host should see the settings
+    user.host(host_id).await?;
+    if !user.can_use_app(app_id)? {
+        return Err(AppError::Forbidden);
+    }
+
+    let defaults = user.stream_defaults(host_id, app_id).await?;
+
+    Ok(Json(GetAppStreamDefaultsResponse {
+        defaults: defaults.map(|defaults| StreamDefaults {
+            bitrate: defaults.bitrate,
+            fps: defaults.fps,
+            width: defaults.width,
+            height: defaults.height,
+            video_format: defaults.video_format,
+        }),
+    }))
+}
+
+/// Like `POST /api/host/cancel`, the session of another user can only be stopped by admins
+#[post("/app/quit")]
+async fn quit_app(
+    app: Data<App>,
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostAppQuitRequest>,
+) -> Result<Json<PostAppQuitResponse>, AppError> {
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
+    let (success, _) = quit_host_app(&app, &mut user, HostId(request.host_id)).await?;
+
+    Ok(Json(PostAppQuitResponse { success }))
 }
 
 pub fn api_service() -> impl HttpServiceFactory {
@@ -298,29 +695,77 @@ pub fn api_service() -> impl HttpServiceFactory {
             auth::logout,
             auth::authenticate
         ])
+        .service(services![
+            // -- Server
+            get_version,
+            get_capabilities,
+        ])
         .service(services![
             // -- Host
             get_user,
             list_hosts,
             get_host,
+            get_host_displays,
             post_host,
             patch_host,
             wake_host,
             delete_host,
             pair_host,
+            get_host_certificates,
+            rotate_host_certificate,
+        ])
+        .service(services![
+            // -- App
             get_apps,
             get_app_image,
+            prefetch_app_images,
+            get_app_stream_defaults,
+            launch_app,
+            quit_app,
         ])
         .service(services![
             // -- Stream
             stream::start_host,
             stream::cancel_host,
+            stream::get_stream_presets,
+            stream::get_sessions,
+            stream::get_session_diagnostics,
+            stream::post_session_screenshot,
+            stream::get_session_preview,
+            stream::get_session_thumbnail,
+            stream::post_session_hls,
+            stream::get_session_hls_file,
+        ])
+        .service(services![
+            // -- Input Macros
+            get_input_macros,
+            delete_input_macro,
+        ])
+        .service(services![
+            // -- Sunshine
+            put_sunshine_credentials,
+            get_sunshine_apps,
+            post_sunshine_app,
+            get_sunshine_encoder,
+            patch_sunshine_encoder,
+            get_sunshine_logs,
+        ])
+        .service(services![
+            // -- Tailscale
+            get_tailscale_peers,
+        ])
+        .service(services![
+            // -- Web Push
+            get_web_push_key,
+            post_push_subscription,
+            delete_push_subscription,
         ])
         .service(services![
             // -- Admin
             add_user,
             patch_user,
             delete_user,
-            list_users
+            list_users,
+            list_host_clients
         ])
 }
@@ -6,6 +6,7 @@ use std::{
 
 use actix_web::{HttpRequest, HttpResponse, Responder, body::BoxBody, web::Bytes};
 use futures::Stream;
+use moonlight_common::network::request_client::CancellationToken;
 use serde::Serialize;
 use thiserror::Error;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
@@ -13,6 +14,7 @@ use tokio::sync::mpsc::{Receiver, Sender, channel};
 pub struct StreamedResponse<Initial, Other> {
     receiver: Receiver<Other>,
     initial: Initial,
+    cancel_on_drop: Option<CancellationToken>,
 }
 
 impl<Initial, Other> StreamedResponse<Initial, Other> {
@@ -21,12 +23,24 @@ impl<Initial, Other> StreamedResponse<Initial, Other> {
 
         let stream_sender = StreamedResponseSender { sender };
 
-        (Self { initial, receiver }, stream_sender)
+        (
+            Self {
+                initial,
+                receiver,
+                cancel_on_drop: None,
+            },
+            stream_sender,
+        )
     }
 
     pub fn set_initial(&mut self, initial: Initial) {
         self.initial = initial;
     }
+
+    /// Cancels the token once the response isn't streamed anymore, e.g. because the client disconnected
+    pub fn cancel_on_drop(&mut self, cancellation_token: CancellationToken) {
+        self.cancel_on_drop = Some(cancellation_token);
+    }
 }
 
 impl<Initial, Other> Responder for StreamedResponse<Initial, Other>
@@ -40,6 +54,7 @@ where
         let stream = StreamedResponseReceiver {
             initial: Some(self.initial),
             receiver: self.receiver,
+            cancel_on_drop: self.cancel_on_drop,
         };
 
         HttpResponse::Ok()
@@ -51,6 +66,15 @@ where
 struct StreamedResponseReceiver<Initial, Other> {
     initial: Option<Initial>,
     receiver: Receiver<Other>,
+    cancel_on_drop: Option<CancellationToken>,
+}
+
+impl<Initial, Other> Drop for StreamedResponseReceiver<Initial, Other> {
+    fn drop(&mut self) {
+        if let Some(cancellation_token) = self.cancel_on_drop.take() {
+            cancellation_token.cancel();
+        }
+    }
 }
 
 impl<Initial, Other> Stream for StreamedResponseReceiver<Initial, Other>
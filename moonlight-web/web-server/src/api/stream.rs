@@ -1,37 +1,81 @@
-use std::process::Stdio;
-
 use actix_web::{
-    Error, HttpRequest, HttpResponse, get, post, rt as actix_rt,
-    web::{Data, Json, Payload},
+    Error, HttpRequest, HttpResponse, get,
+    http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_DISPOSITION},
+    post, rt as actix_rt,
+    web::{Data, Json, Path, Payload, Query},
 };
-use actix_ws::{Closed, Message, Session};
+use actix_ws::{Closed, Message, MessageStream, Session};
+use bytes::Bytes;
 use common::{
     api_bindings::{
-        LogMessageType, PostCancelRequest, PostCancelResponse, StreamClientMessage,
-        StreamServerMessage,
+        GetHostStreamQuery, GetSessionHlsQuery, GetSessionsResponse, GetStreamPresetsResponse,
+        LogMessageType, PostCancelRequest, PostCancelResponse, PostScreenshotQuery,
+        PostSessionHlsResponse, PushNotification, PushNotificationKind, ScreenshotFormat,
+        StreamClientMessage, StreamMessageCode, StreamServerMessage, StreamSession,
     },
-    ipc::{ServerIpcMessage, StreamerConfig, StreamerIpcMessage, create_child_ipc},
+    config::SessionThumbnailConfig,
+    ipc::{InputMacro, ServerIpcMessage, StreamerConfig, StreamerIpcMessage},
     serialize_json,
 };
-use log::{debug, error, info, warn};
-use tokio::{process::Command, spawn};
+use log::{Level, debug, error, info, log, warn};
+use moonlight_common::{formats::SupportedVideoFormats, units::Kbps};
+use openssl::rand::rand_bytes;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    mem::take,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    spawn,
+    sync::{Mutex, oneshot},
+    time::timeout,
+};
 
-use crate::app::{
-    App, AppError,
-    host::{AppId, HostId},
-    user::AuthenticatedUser,
+use crate::{
+    api::client_ip::client_ip,
+    app::{
+        App, AppError, ResumableStream, ScreenshotWaiters, SessionPreview,
+        diagnostics::{SessionId, SignalingSide},
+        host::{self, AppId, Host, HostId},
+        storage::{StorageInputMacro, StorageStreamDefaults},
+        stream_limits::{apply_user_stream_limits, check_stream_settings},
+        user::{Admin, AuthenticatedUser, Role, UserId},
+    },
+    error_reporting::{ErrorContext, ErrorKind, ErrorProcess, ErrorReport, log_unreported, report},
 };
 
+const RESUME_TOKEN_SIZE: usize = 32;
+/// Messages for a disconnected client beyond this are dropped
+const MAX_PENDING_CLIENT_MESSAGES: usize = 256;
+/// Separates the jpeg frames of the session preview
+const PREVIEW_BOUNDARY: &str = "preview-frame";
+
 #[get("/host/stream")]
 pub async fn start_host(
     web_app: Data<App>,
     mut user: AuthenticatedUser,
     request: HttpRequest,
     payload: Payload,
+    Query(query): Query<GetHostStreamQuery>,
 ) -> Result<HttpResponse, Error> {
     let (response, mut session, mut stream) = actix_ws::handle(&request, payload)?;
 
+    if let Some(token) = query.resume {
+        let web_app = web_app.clone();
+        actix_rt::spawn(async move {
+            resume_stream(&web_app, &user, &token, session, stream).await;
+        });
+
+        return Ok(response);
+    }
+
     let client_unique_id = user.host_unique_id().await?;
+    let client_ip = client_ip(&request);
 
     let web_app = web_app.clone();
     actix_rt::spawn(async move {
@@ -77,17 +121,41 @@ pub async fn start_host(
         let host_id = HostId(host_id);
         let app_id = AppId(app_id);
 
+        let resume_token = match new_resume_token() {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("[Stream]: failed to create a resume token: {err}");
+
+                let _ = send_ws_message(
+                    &mut session,
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::ServerError,
+                        Some(LogMessageType::FatalDescription),
+                    ),
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+        };
+        let _ = send_ws_message(
+            &mut session,
+            StreamServerMessage::ResumeToken {
+                token: resume_token.clone(),
+            },
+        )
+        .await;
+
         // -- Collect host data
         let mut host = match user.host(host_id).await {
             Ok(host) => host,
             Err(AppError::HostNotFound) => {
                 let _ = send_ws_message(
                     &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because the host was not found"
-                            .to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::HostNotFound,
+                        Some(LogMessageType::FatalDescription),
+                    ),
                 )
                 .await;
                 let _ = session.close(None).await;
@@ -98,10 +166,10 @@ pub async fn start_host(
 
                 let _ = send_ws_message(
                     &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because of a server error".to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::ServerError,
+                        Some(LogMessageType::FatalDescription),
+                    ),
                 )
                 .await;
                 let _ = session.close(None).await;
@@ -109,6 +177,36 @@ pub async fn start_host(
             }
         };
 
+        match wake_host(&web_app, &mut session, &mut host, &mut user).await {
+            Ok(()) => {}
+            Err(AppError::HostOffline) => {
+                let _ = send_ws_message(
+                    &mut session,
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::HostOffline,
+                        Some(LogMessageType::FatalDescription),
+                    ),
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+            Err(err) => {
+                warn!("failed to start stream for host {host_id:?} (at wake): {err}");
+
+                let _ = send_ws_message(
+                    &mut session,
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::ServerError,
+                        Some(LogMessageType::FatalDescription),
+                    ),
+                )
+                .await;
+                let _ = session.close(None).await;
+                return;
+            }
+        }
+
         let apps = match host.list_apps(&mut user).await {
             Ok(apps) => apps,
             Err(err) => {
@@ -116,10 +214,10 @@ pub async fn start_host(
 
                 let _ = send_ws_message(
                     &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because of a server error".to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::ServerError,
+                        Some(LogMessageType::FatalDescription),
+                    ),
                 )
                 .await;
                 let _ = session.close(None).await;
@@ -132,10 +230,10 @@ pub async fn start_host(
 
             let _ = send_ws_message(
                 &mut session,
-                StreamServerMessage::DebugLog {
-                    message: "Failed to start stream because the app was not found".to_string(),
-                    ty: Some(LogMessageType::FatalDescription),
-                },
+                web_app.config().messages.debug_log(
+                    StreamMessageCode::AppNotFound,
+                    Some(LogMessageType::FatalDescription),
+                ),
             )
             .await;
             let _ = session.close(None).await;
@@ -149,10 +247,10 @@ pub async fn start_host(
 
                 let _ = send_ws_message(
                     &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because of a server error".to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::ServerError,
+                        Some(LogMessageType::FatalDescription),
+                    ),
                 )
                 .await;
                 let _ = session.close(None).await;
@@ -167,11 +265,10 @@ pub async fn start_host(
 
                 let _ = send_ws_message(
                     &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because the host is not paired"
-                            .to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
+                    web_app.config().messages.debug_log(
+                        StreamMessageCode::HostNotPaired,
+                        Some(LogMessageType::FatalDescription),
+                    ),
                 )
                 .await;
                 let _ = session.close(None).await;
@@ -179,6 +276,13 @@ pub async fn start_host(
             }
         };
 
+        if user.is_guest() {
+            info!(
+                "[Guest]: starting stream of app {} on host {host_id:?} from {:?}",
+                app.id.0, client_ip
+            );
+        }
+
         // -- Send App info
         let _ = send_ws_message(
             &mut session,
@@ -189,90 +293,324 @@ pub async fn start_host(
         // -- Starting stage: launch streamer
         let _ = send_ws_message(
             &mut session,
-            StreamServerMessage::DebugLog {
-                message: "Launching streamer".to_string(),
-                ty: None,
-            },
+            web_app
+                .config()
+                .messages
+                .debug_log(StreamMessageCode::LaunchStreamer, None),
         )
         .await;
 
-        // Spawn child
-        let (mut child, stdin, stdout) = match Command::new(&web_app.config().streamer_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .kill_on_drop(true)
-            .spawn()
-        {
-            Ok(mut child) => {
-                if let Some(stdin) = child.stdin.take()
-                    && let Some(stdout) = child.stdout.take()
-                {
-                    (child, stdin, stdout)
-                } else {
-                    error!("[Stream]: streamer process didn't include a stdin or stdout");
+        let privacy_mode = match user.privacy_mode().await {
+            Ok(privacy_mode) => privacy_mode,
+            Err(err) => {
+                // Rather keep too little than record a private session
+                warn!("[Stream]: failed to get the privacy mode of the user: {err}");
+                true
+            }
+        };
+        let diagnostics = web_app
+            .new_session_diagnostics(host_id, user.id(), privacy_mode)
+            .await;
+
+        // Spawn child, on the streamer node closest to the host if there's one
+        let host_address = web_app.config().moonlight.resolve_host_address(&address);
+        let (mut child, mut ipc_sender, mut ipc_receiver, capabilities) =
+            match web_app.take_streamer(&host_address).await {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("[Stream]: failed to spawn streamer process: {err}");
 
                     let _ = send_ws_message(
                         &mut session,
-                        StreamServerMessage::DebugLog {
-                            message: "Failed to start stream because of a server error".to_string(),
-                            ty: Some(LogMessageType::FatalDescription),
-                        },
+                        web_app.config().messages.debug_log(
+                            StreamMessageCode::ServerError,
+                            Some(LogMessageType::FatalDescription),
+                        ),
                     )
                     .await;
                     let _ = session.close(None).await;
-
-                    if let Err(err) = child.kill().await {
-                        warn!("[Stream]: failed to kill child: {err}");
-                    }
-
                     return;
                 }
-            }
-            Err(err) => {
-                error!("[Stream]: failed to spawn streamer process: {err}");
-
-                let _ = send_ws_message(
-                    &mut session,
-                    StreamServerMessage::DebugLog {
-                        message: "Failed to start stream because of a server error".to_string(),
-                        ty: Some(LogMessageType::FatalDescription),
-                    },
-                )
-                .await;
-                let _ = session.close(None).await;
-                return;
-            }
-        };
+            };
 
-        // Create ipc
-        let (mut ipc_sender, mut ipc_receiver) = create_child_ipc::<
-            ServerIpcMessage,
-            StreamerIpcMessage,
-        >(
-            "Streamer", stdin, stdout, child.stderr.take()
-        )
-        .await;
+        // The bitrate is only known from the client, everything else from the streamer
+        let requested_bitrate = Arc::new(AtomicU32::new(0));
+        let stream_bitrate = requested_bitrate.clone();
+        let mut stream_user = user.clone();
+        let stream_app = web_app.clone();
+        let client_socket = ClientSocket::new(session);
+        let stream_client_socket = client_socket.clone();
+        let stream_resume_token = resume_token.clone();
+        let mut stream_ipc_sender = ipc_sender.clone();
+        let stream_diagnostics = diagnostics.clone();
+        let screenshots = ScreenshotWaiters::default();
+        let preview = SessionPreview::default();
+        // The streamer might start the rtsp output before the connection completed
+        let mut rtsp_port = None;
 
         // Redirect ipc message into ws
         spawn(async move {
+            // Changed by an app switch
+            let mut app_id = app_id;
+
             while let Some(message) = ipc_receiver.recv().await {
                 match message {
-                    StreamerIpcMessage::WebSocket(message) => {
-                        if let Err(Closed) = send_ws_message(&mut session, message).await {
-                            warn!(
-                                "[Ipc]: Tried to send a ws message (text) but the socket is already closed"
-                            );
+                    StreamerIpcMessage::WebSocket(mut message) => {
+                        // The streamer only knows the english texts
+                        stream_app.config().messages.localize(&mut message);
+
+                        if let StreamServerMessage::HostBusy {
+                            current_user,
+                            can_takeover,
+                        } = &mut message
+                        {
+                            // The streamer doesn't know about the users of this web server
+                            match host_busy_info(&stream_app, &mut stream_user, host_id).await {
+                                Ok((user_name, allowed)) => {
+                                    *current_user = user_name;
+                                    *can_takeover = allowed;
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to query the user of the active stream: {err}"
+                                    );
+                                    *can_takeover = false;
+                                }
+                            }
+
+                            stream_app
+                                .notify_when_host_available(host_id, stream_user.id())
+                                .await;
                         }
+
+                        if let StreamServerMessage::WebRtc(signaling) = &message {
+                            stream_diagnostics
+                                .signaling(SignalingSide::Streamer, signaling)
+                                .await;
+                        }
+
+                        if let StreamServerMessage::ConnectionComplete {
+                            format,
+                            width,
+                            height,
+                            fps,
+                            ..
+                        } = &message
+                        {
+                            let defaults = StorageStreamDefaults {
+                                bitrate: Kbps(stream_bitrate.load(Ordering::Acquire)),
+                                fps: *fps,
+                                width: *width,
+                                height: *height,
+                                video_format: *format,
+                            };
+
+                            if let Err(err) = stream_user
+                                .set_stream_defaults(host_id, app_id, defaults)
+                                .await
+                            {
+                                warn!("[Stream]: failed to store stream defaults: {err}");
+                            }
+
+                            stream_app
+                                .set_active_stream(
+                                    host_id,
+                                    stream_user.id(),
+                                    stream_diagnostics.id(),
+                                    stream_ipc_sender.clone(),
+                                    screenshots.clone(),
+                                    preview.clone(),
+                                )
+                                .await;
+                            if let Some(rtsp_port) = rtsp_port {
+                                stream_app
+                                    .set_active_stream_rtsp_port(
+                                        host_id,
+                                        stream_diagnostics.id(),
+                                        rtsp_port,
+                                    )
+                                    .await;
+                            }
+                            stream_diagnostics.connection(&message).await;
+                        }
+
+                        stream_client_socket.send(message).await;
                     }
                     StreamerIpcMessage::WebSocketTransport(data) => {
-                        if let Err(Closed) = session.binary(data).await {
+                        if let Err(Closed) = stream_client_socket.binary(data).await {
                             warn!(
                                 "[Ipc]: Tried to send a ws message (binary) but the socket is already closed"
                             );
                             break;
                         }
                     }
+                    StreamerIpcMessage::SaveInputMacro { name, events } => {
+                        // Guests share the default user
+                        if stream_user.is_guest() {
+                            debug!("[Stream]: not saving the input macro of a guest");
+                            continue;
+                        }
+
+                        match stream_user.add_input_macro(name, events).await {
+                            Ok(input_macro) => {
+                                stream_ipc_sender
+                                    .send(ServerIpcMessage::InputMacroSaved(input_macro_to_ipc(
+                                        input_macro,
+                                    )))
+                                    .await;
+                            }
+                            Err(err) => {
+                                warn!("[Stream]: failed to save input macro: {err}");
+                            }
+                        }
+                    }
+                    StreamerIpcMessage::QualityScore { score } => {
+                        stream_diagnostics.quality_score(score).await;
+                        stream_app
+                            .set_active_stream_quality(host_id, stream_diagnostics.id(), score)
+                            .await;
+                    }
+                    StreamerIpcMessage::Screenshot { image } => {
+                        screenshots.answer(image).await;
+                    }
+                    StreamerIpcMessage::Thumbnail { image } => {
+                        stream_app
+                            .set_active_stream_thumbnail(host_id, stream_diagnostics.id(), image)
+                            .await;
+                    }
+                    StreamerIpcMessage::RtspOutput { port } => {
+                        info!("[Stream]: the session is available over rtsp on port {port}");
+                        rtsp_port = Some(port);
+                        stream_app
+                            .set_active_stream_rtsp_port(host_id, stream_diagnostics.id(), port)
+                            .await;
+                    }
+                    StreamerIpcMessage::HlsFile { request_id, file } => {
+                        stream_app
+                            .answer_hls_request(host_id, stream_diagnostics.id(), request_id, file)
+                            .await;
+                    }
+                    StreamerIpcMessage::Preview { image } => {
+                        if image.is_none() {
+                            info!("[Stream]: the streamer stopped the preview of the session");
+                        }
+                        preview.publish(image);
+                    }
+                    StreamerIpcMessage::Log {
+                        level,
+                        target,
+                        message,
+                    } => {
+                        if level == Level::Error {
+                            log_unreported(|| {
+                                log!(target: &target, level, "[Streamer]: {message}");
+                            });
+                            report(ErrorReport {
+                                kind: ErrorKind::Error,
+                                process: ErrorProcess::Streamer,
+                                target: target.clone(),
+                                message: message.clone(),
+                                context: Some(ErrorContext::session(&stream_diagnostics)),
+                            });
+                        } else {
+                            log!(target: &target, level, "[Streamer]: {message}");
+                        }
+                        stream_diagnostics.log(level, &target, &message).await;
+                    }
+                    StreamerIpcMessage::SwitchApp { app_id: new_app_id } => {
+                        let new_app_id = AppId(new_app_id);
+
+                        let app = match find_app(&mut stream_user, host_id, new_app_id).await {
+                            Ok(app) => app,
+                            Err(err) => {
+                                warn!("[Stream]: failed to list the apps for an app switch: {err}");
+                                None
+                            }
+                        };
+                        let Some(app) = app else {
+                            info!(
+                                "[Stream]: denied the switch to app {} on host {host_id:?}",
+                                new_app_id.0
+                            );
+                            stream_client_socket
+                                .send(
+                                    stream_app
+                                        .config()
+                                        .messages
+                                        .debug_log(StreamMessageCode::SwitchAppDenied, None),
+                                )
+                                .await;
+                            continue;
+                        };
+
+                        info!(
+                            "[Stream]: switching from app {} to app {} on host {host_id:?}",
+                            app_id.0, new_app_id.0
+                        );
+                        stream_diagnostics
+                            .log(
+                                Level::Info,
+                                "stream",
+                                &format!("switching to app {}", new_app_id.0),
+                            )
+                            .await;
+
+                        app_id = new_app_id;
+                        stream_ipc_sender
+                            .send(ServerIpcMessage::SwitchApp {
+                                app_id: app_id.0,
+                                display_app_ids: display_app_ids(
+                                    &stream_app,
+                                    &stream_user,
+                                    host_id,
+                                    app_id,
+                                ),
+                            })
+                            .await;
+                        stream_client_socket
+                            .send(StreamServerMessage::UpdateApp { app: app.into() })
+                            .await;
+                    }
+                    StreamerIpcMessage::PeerWatchdog { app_quit } => {
+                        let message = if app_quit {
+                            "no peer connected after launching the app, quit the app"
+                        } else {
+                            "no peer connected after launching the app, the app keeps running"
+                        };
+
+                        info!("[Stream]: {message}");
+                        stream_diagnostics
+                            .log(Level::Warn, "peer_watchdog", message)
+                            .await;
+                    }
+                    StreamerIpcMessage::Panic { message } => {
+                        log_unreported(|| {
+                            error!("[Streamer]: the streamer panicked: {message}");
+                        });
+                        report(ErrorReport {
+                            kind: ErrorKind::Panic,
+                            process: ErrorProcess::Streamer,
+                            target: "streamer".to_string(),
+                            message: message.clone(),
+                            context: Some(ErrorContext::session(&stream_diagnostics)),
+                        });
+                        stream_diagnostics
+                            .log(Level::Error, "streamer", &format!("panicked: {message}"))
+                            .await;
+                        // The message might contain details of the session, it's in the logs
+                        stream_app.notify_admins(PushNotification {
+                            kind: PushNotificationKind::AdminAttention,
+                            title: "The streamer crashed".to_string(),
+                            body: format!(
+                                "The session {} crashed, its diagnostics contain the details.",
+                                stream_diagnostics.id().0
+                            ),
+                            host_id: Some(host_id.0),
+                        });
+                    }
+                    StreamerIpcMessage::Capabilities(_) => {
+                        debug!("[Ipc]: the streamer sent its capabilities again");
+                    }
                     StreamerIpcMessage::Stop => {
                         debug!("[Ipc]: ipc receiver stopped by streamer");
                         break;
@@ -281,10 +619,15 @@ pub async fn start_host(
             }
             info!("[Ipc]: ipc receiver is closed");
 
+            stream_app
+                .remove_active_stream(host_id, stream_diagnostics.id())
+                .await;
+
             // close the websocket when the streamer crashed / disconnected / whatever
-            if let Err(err) = session.close(None).await {
-                warn!("failed to close streamer web socket: {err}");
-            }
+            stream_client_socket.close().await;
+            stream_app
+                .remove_resumable_stream(&stream_resume_token)
+                .await;
 
             // kill the streamer
             if let Err(err) = child.kill().await {
@@ -292,49 +635,672 @@ pub async fn start_host(
             }
         });
 
+        // The streamer might run in another working directory
+        let mut file_transfer = web_app.config().file_transfer.clone();
+        if let Some(directory) = file_transfer.directory.as_mut()
+            && let Ok(absolute) = std::path::absolute(&directory)
+        {
+            *directory = absolute.to_string_lossy().to_string();
+        }
+        let mut hls_output = web_app.config().hls_output.clone();
+        if let Some(directory) = hls_output.recording_directory.as_mut()
+            && let Ok(absolute) = std::path::absolute(&directory)
+        {
+            *directory = absolute.to_string_lossy().to_string();
+        }
+        // Private sessions aren't recorded
+        if privacy_mode || !capabilities.hls_recording {
+            hls_output.recording_directory = None;
+        }
+        // Subsystems which the streamer wasn't built with stay disabled
+        hls_output.enabled &= capabilities.hls_output;
+        let mut rtsp_output = web_app.config().rtsp_output.clone();
+        rtsp_output.enabled &= capabilities.rtsp_output;
+        // The rtsp output doesn't authenticate its clients, so private sessions aren't republished
+        rtsp_output.enabled &= !privacy_mode;
+
+        let display_app_ids = display_app_ids(&web_app, &user, host_id, app_id);
+
+        let input_macros = match user.input_macros().await {
+            Ok(input_macros) => input_macros.into_iter().map(input_macro_to_ipc).collect(),
+            Err(err) => {
+                warn!("[Stream]: failed to load input macros: {err}");
+                Vec::new()
+            }
+        };
+
         // Send init into ipc
         ipc_sender
             .send(ServerIpcMessage::Init {
                 config: StreamerConfig {
                     webrtc: web_app.config().webrtc.clone(),
+                    file_transfer,
+                    controller_rumble: web_app.config().controller_rumble.clone(),
+                    video_watchdog: web_app.config().video_watchdog.clone(),
+                    peer_watchdog: web_app.config().peer_watchdog.clone(),
+                    cursor_prediction: web_app.config().cursor_prediction.clone(),
+                    media_priority: web_app.config().media_priority.clone(),
+                    encryption: web_app.config().stream_encryption.clone(),
+                    thumbnail: if privacy_mode || !capabilities.video_decoding {
+                        SessionThumbnailConfig {
+                            interval: Duration::ZERO,
+                            ..web_app.config().session_thumbnail.clone()
+                        }
+                    } else {
+                        web_app.config().session_thumbnail.clone()
+                    },
+                    rtsp_output,
+                    hls_output,
+                    sandbox: web_app.config().streamer_sandbox.clone(),
+                    memory: web_app.config().streamer_memory.clone(),
                     log_level: web_app.config().log.level_filter,
+                    log_forwarding: web_app.config().log.streamer_forwarding,
                 },
-                host_address: address,
+                // moonlight-common-c connects with the system resolver, so it gets the address
+                host_address: web_app.config().moonlight.resolve_host_address(&address),
                 host_http_port: http_port,
+                host_proxy: web_app
+                    .config()
+                    .moonlight
+                    .request_client_options(Some(host_id.0))
+                    .proxy,
+                host_xml_parse_mode: web_app.config().moonlight.xml_parse_mode(Some(host_id.0)),
                 client_unique_id: Some(client_unique_id),
                 client_private_key: pair_info.client_private_key,
                 client_certificate: pair_info.client_certificate,
                 server_certificate: pair_info.server_certificate,
                 app_id: app_id.0,
+                display_app_ids,
                 video_frame_queue_size,
                 audio_sample_queue_size,
+                input_macros,
             })
             .await;
 
         // Redirect ws message into ipc
-        while let Some(Ok(message)) = stream.recv().await {
-            match message {
-                Message::Text(text) => {
-                    let Ok(message) = serde_json::from_str::<StreamClientMessage>(&text) else {
-                        warn!("[Stream]: failed to deserialize from json");
-                        return;
-                    };
-
-                    ipc_sender.send(ServerIpcMessage::WebSocket(message)).await;
-                }
-                Message::Binary(binary) => {
-                    ipc_sender
-                        .send(ServerIpcMessage::WebSocketTransport(binary))
-                        .await;
+        loop {
+            while let Some(Ok(message)) = stream.recv().await {
+                match message {
+                    Message::Text(text) => {
+                        let Ok(mut message) = serde_json::from_str::<StreamClientMessage>(&text)
+                        else {
+                            warn!("[Stream]: failed to deserialize from json");
+                            return;
+                        };
+
+                        if let StreamClientMessage::StartStream {
+                            bitrate,
+                            packet_size,
+                            fps,
+                            width,
+                            height,
+                            video_supported_formats,
+                            video_color_range_full,
+                            preset,
+                            ..
+                        } = &mut message
+                        {
+                            if let Some(name) = preset.take() {
+                                let Some(stream_preset) =
+                                    web_app.config().moonlight.stream_preset(&name)
+                                else {
+                                    info!(
+                                        "[Stream]: the client requested the unknown preset {name}"
+                                    );
+
+                                    client_socket
+                                        .send(web_app.config().messages.debug_log(
+                                            StreamMessageCode::UnknownStreamPreset { name },
+                                            Some(LogMessageType::FatalDescription),
+                                        ))
+                                        .await;
+                                    client_socket.close().await;
+
+                                    ipc_sender.send(ServerIpcMessage::Stop).await;
+                                    return;
+                                };
+
+                                *width = stream_preset.width;
+                                *height = stream_preset.height;
+                                *fps = stream_preset.fps;
+                                *bitrate = stream_preset.bitrate;
+                                if let Some(preset_packet_size) = stream_preset.packet_size {
+                                    *packet_size = preset_packet_size;
+                                }
+                                if let Some(color_range_full) = stream_preset.video_color_range_full
+                                {
+                                    *video_color_range_full = color_range_full;
+                                }
+                            }
+
+                            match user.stream_limits().await {
+                                Ok(user_limits) => {
+                                    if apply_user_stream_limits(
+                                        &user_limits,
+                                        width,
+                                        height,
+                                        bitrate,
+                                    ) {
+                                        info!(
+                                            "[Stream]: lowered the stream settings of user {:?} to {width}x{height} with {bitrate}",
+                                            user.id()
+                                        );
+                                    }
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to get the stream limits of the user: {err}"
+                                    );
+
+                                    client_socket
+                                        .send(web_app.config().messages.debug_log(
+                                            StreamMessageCode::ServerError,
+                                            Some(LogMessageType::FatalDescription),
+                                        ))
+                                        .await;
+                                    client_socket.close().await;
+
+                                    ipc_sender.send(ServerIpcMessage::Stop).await;
+                                    return;
+                                }
+                            }
+
+                            let limits = web_app.config().moonlight.stream_limits(host_id.0);
+                            if let Err((field, allowed_range)) =
+                                check_stream_settings(&limits, *width, *height, *fps, *bitrate)
+                            {
+                                info!(
+                                    "[Stream]: rejected the stream settings because {field:?} isn't in {allowed_range:?}"
+                                );
+
+                                client_socket
+                                    .send(StreamServerMessage::SettingsRejected {
+                                        field,
+                                        allowed_range,
+                                    })
+                                    .await;
+                                client_socket.close().await;
+
+                                ipc_sender.send(ServerIpcMessage::Stop).await;
+                                return;
+                            }
+
+                            requested_bitrate.store(bitrate.get(), Ordering::Release);
+
+                            // The client only tells us what it can decode, the codec is picked here
+                            let client_formats =
+                                SupportedVideoFormats::from_bits_retain(*video_supported_formats);
+                            match host
+                                .negotiate_video_formats(&mut user, client_formats)
+                                .await
+                            {
+                                Ok(formats) if formats.is_empty() => {
+                                    client_socket
+                                        .send(web_app.config().messages.debug_log(
+                                            StreamMessageCode::NoCommonVideoCodec,
+                                            Some(LogMessageType::FatalDescription),
+                                        ))
+                                        .await;
+                                    client_socket.close().await;
+
+                                    ipc_sender.send(ServerIpcMessage::Stop).await;
+                                    return;
+                                }
+                                Ok(formats) => {
+                                    debug!(
+                                        "[Stream]: negotiated video formats {formats} from the client formats {client_formats}"
+                                    );
+                                    *video_supported_formats = formats.bits();
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to negotiate the video codec, using the formats of the client: {err}"
+                                    );
+                                }
+                            }
+                        }
+                        if let StreamClientMessage::SetQuality {
+                            bitrate,
+                            fps,
+                            width,
+                            height,
+                        } = &mut message
+                        {
+                            match user.stream_limits().await {
+                                Ok(user_limits) => {
+                                    if apply_user_stream_limits(
+                                        &user_limits,
+                                        width,
+                                        height,
+                                        bitrate,
+                                    ) {
+                                        info!(
+                                            "[Stream]: lowered the quality change of user {:?} to {width}x{height} with {bitrate}",
+                                            user.id()
+                                        );
+                                    }
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to get the stream limits of the user, ignoring the quality change: {err}"
+                                    );
+                                    continue;
+                                }
+                            }
+
+                            let limits = web_app.config().moonlight.stream_limits(host_id.0);
+                            if let Err((field, allowed_range)) =
+                                check_stream_settings(&limits, *width, *height, *fps, *bitrate)
+                            {
+                                info!(
+                                    "[Stream]: rejected the quality change because {field:?} isn't in {allowed_range:?}"
+                                );
+
+                                client_socket
+                                    .send(StreamServerMessage::QualityRejected {
+                                        field,
+                                        allowed_range,
+                                    })
+                                    .await;
+                                continue;
+                            }
+
+                            requested_bitrate.store(bitrate.get(), Ordering::Release);
+                        }
+
+                        match &message {
+                            StreamClientMessage::WebRtc(signaling) => {
+                                diagnostics
+                                    .signaling(SignalingSide::Client, signaling)
+                                    .await;
+                            }
+                            StreamClientMessage::SetTransport(transport) => {
+                                diagnostics.transport(transport).await;
+                            }
+                            StreamClientMessage::StartStream { .. } => {
+                                diagnostics.stream_settings(&message).await;
+                            }
+                            _ => {}
+                        }
+
+                        if let StreamClientMessage::Takeover = &message {
+                            match host_busy_info(&web_app, &mut user, host_id).await {
+                                Ok((_, true)) => {}
+                                Ok((_, false)) => {
+                                    warn!(
+                                        "[Stream]: user {:?} isn't allowed to take over host {host_id:?}",
+                                        user.id()
+                                    );
+                                    continue;
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to query the user of the active stream: {err}"
+                                    );
+                                    continue;
+                                }
+                            }
+
+                            info!(
+                                "[Stream]: user {:?} takes over the stream of host {host_id:?}",
+                                user.id()
+                            );
+
+                            match host.cancel_app(&mut user).await {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    client_socket
+                                        .send(
+                                            web_app
+                                                .config()
+                                                .messages
+                                                .debug_log(StreamMessageCode::TakeoverFailed, None),
+                                        )
+                                        .await;
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "[Stream]: failed to cancel the app for a takeover: {err}"
+                                    );
+                                }
+                            }
+                        }
+
+                        ipc_sender.send(ServerIpcMessage::WebSocket(message)).await;
+                    }
+                    Message::Binary(binary) => {
+                        ipc_sender
+                            .send(ServerIpcMessage::WebSocketTransport(binary))
+                            .await;
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
+
+            // The streamer keeps running, the client might reconnect the web socket
+            let Some(resumed) =
+                wait_for_resume(&web_app, &client_socket, &resume_token, user.id()).await
+            else {
+                break;
+            };
+            stream = resumed;
         }
     });
 
     Ok(response)
 }
 
+/// The variants of the app which stream another display by display id, only the variants which the user may use
+fn display_app_ids(
+    web_app: &App,
+    user: &AuthenticatedUser,
+    host_id: HostId,
+    app_id: AppId,
+) -> HashMap<u32, u32> {
+    web_app
+        .config()
+        .moonlight
+        .host_displays(host_id.0)
+        .iter()
+        .enumerate()
+        .filter_map(|(display_id, display)| {
+            let variant = display.app_variants.get(&app_id.0)?;
+            matches!(user.can_use_app(AppId(*variant)), Ok(true))
+                .then_some((display_id as u32, *variant))
+        })
+        .collect()
+}
+
+/// None if the app doesn't exist or the user may not use it
+async fn find_app(
+    user: &mut AuthenticatedUser,
+    host_id: HostId,
+    app_id: AppId,
+) -> Result<Option<host::App>, AppError> {
+    let mut host = user.host(host_id).await?;
+    let apps = host.list_apps(user).await?;
+
+    Ok(apps.into_iter().find(|app| app.id == app_id))
+}
+
+/// Returns the name of the user which is streaming from the host and if the user is allowed to stop that stream.
+/// Guests can never take over and only admins can take over the streams of other users.
+async fn host_busy_info(
+    web_app: &App,
+    user: &mut AuthenticatedUser,
+    host_id: HostId,
+) -> Result<(Option<String>, bool), AppError> {
+    if user.is_guest() {
+        return Ok((None, false));
+    }
+
+    let Some(current_user_id) = web_app.active_stream_user(host_id).await else {
+        // Somebody streams without this web server, e.g. using a native Moonlight client
+        return Ok((None, true));
+    };
+
+    let mut current_user = web_app.user_by_id(current_user_id).await?;
+    let current_user_name = current_user.detailed_user_no_auth().await?.name;
+
+    let can_takeover = can_control_session(user, current_user_id).await?;
+
+    Ok((Some(current_user_name), can_takeover))
+}
+
+/// Users can stop and take over their own sessions, admins every session and guests none.
+async fn can_control_session(
+    user: &mut AuthenticatedUser,
+    session_user_id: UserId,
+) -> Result<bool, AppError> {
+    if user.is_guest() {
+        return Ok(false);
+    }
+
+    Ok(session_user_id == user.id() || user.role().await? == Role::Admin)
+}
+
+/// Wakes the host if it's offline and waits until it booted, the client is told about the progress
+async fn wake_host(
+    web_app: &App,
+    session: &mut Session,
+    host: &mut Host,
+    user: &mut AuthenticatedUser,
+) -> Result<(), AppError> {
+    let boot_timeout = web_app.config().moonlight.host_boot_timeout;
+
+    // Only checks once without a timeout
+    match host.wait_online(user, Duration::ZERO).await {
+        Err(AppError::HostOffline) if !boot_timeout.is_zero() => {}
+        result => return result,
+    }
+
+    info!(
+        "[Stream]: host {:?} is offline, waking it and waiting {boot_timeout:?} for it to boot",
+        host.id()
+    );
+
+    let _ = send_ws_message(
+        session,
+        web_app
+            .config()
+            .messages
+            .debug_log(StreamMessageCode::WakingHost, None),
+    )
+    .await;
+
+    match host.wake(user).await {
+        Ok(()) => {}
+        // The mac address is unknown until the host was online once
+        Err(AppError::HostNotFound) => return Err(AppError::HostOffline),
+        Err(err) => return Err(err),
+    }
+
+    let _ = send_ws_message(
+        session,
+        web_app
+            .config()
+            .messages
+            .debug_log(StreamMessageCode::WaitingForHostBoot, None),
+    )
+    .await;
+
+    host.wait_online(user, boot_timeout).await?;
+
+    let _ = send_ws_message(
+        session,
+        web_app
+            .config()
+            .messages
+            .debug_log(StreamMessageCode::HostBooted, None),
+    )
+    .await;
+
+    Ok(())
+}
+
+fn new_resume_token() -> Result<String, AppError> {
+    let mut bytes = [0; RESUME_TOKEN_SIZE];
+    rand_bytes(&mut bytes)?;
+
+    Ok(hex::encode(bytes))
+}
+
+/// Waits until the client reconnects the dropped web socket, none if the stream can't be resumed anymore
+async fn wait_for_resume(
+    web_app: &App,
+    client_socket: &ClientSocket,
+    token: &str,
+    user_id: UserId,
+) -> Option<MessageStream> {
+    client_socket.disconnected().await;
+
+    let resume_timeout = web_app.config().web_server.stream_resume_timeout;
+    if resume_timeout.is_zero() || client_socket.is_ended().await {
+        return None;
+    }
+
+    let (resume, resumed) = oneshot::channel();
+    web_app
+        .add_resumable_stream(token.to_string(), ResumableStream { user_id, resume })
+        .await;
+
+    info!(
+        "[Stream]: the web socket of the client dropped, waiting {resume_timeout:?} for it to resume"
+    );
+
+    // The sender is dropped once the streamer stops
+    let Ok(Ok((session, stream))) = timeout(resume_timeout, resumed).await else {
+        web_app.remove_resumable_stream(token).await;
+
+        info!("[Stream]: the client didn't resume the web socket");
+        return None;
+    };
+
+    info!("[Stream]: the client resumed the web socket");
+    client_socket.resume(session).await;
+
+    Some(stream)
+}
+
+/// Hands the web socket over to the stream of the token
+async fn resume_stream(
+    web_app: &App,
+    user: &AuthenticatedUser,
+    token: &str,
+    session: Session,
+    stream: MessageStream,
+) {
+    let socket = match web_app.take_resumable_stream(token, user.id()).await {
+        Some(resumable) => match resumable.resume.send((session, stream)) {
+            Ok(()) => return,
+            Err(socket) => socket,
+        },
+        None => (session, stream),
+    };
+    let (mut session, _) = socket;
+
+    warn!("[Stream]: failed to resume a stream, it already ended or belongs to another user");
+
+    let _ = send_ws_message(
+        &mut session,
+        web_app.config().messages.debug_log(
+            StreamMessageCode::ResumeFailed,
+            Some(LogMessageType::FatalDescription),
+        ),
+    )
+    .await;
+    let _ = session.close(None).await;
+}
+
+/// The web socket of the client, it's replaced when the client resumes the stream.
+/// Text messages are kept while the client is disconnected, binary messages are only used by the web socket transport which can't resume.
+#[derive(Clone)]
+struct ClientSocket {
+    inner: Arc<Mutex<ClientSocketInner>>,
+}
+
+struct ClientSocketInner {
+    session: Session,
+    connected: bool,
+    /// The stream stopped, it can't be resumed anymore
+    ended: bool,
+    pending: Vec<String>,
+}
+
+impl ClientSocket {
+    fn new(session: Session) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ClientSocketInner {
+                session,
+                connected: true,
+                ended: false,
+                pending: Vec::new(),
+            })),
+        }
+    }
+
+    async fn send(&self, message: StreamServerMessage) {
+        let Some(json) = serialize_json(&message) else {
+            return;
+        };
+
+        let mut inner = self.inner.lock().await;
+
+        if inner.connected {
+            match inner.session.text(json.clone()).await {
+                Ok(()) => return,
+                Err(Closed) => {
+                    debug!("[Stream]: the web socket of the client is closed, keeping the message");
+                    inner.connected = false;
+                }
+            }
+        }
+
+        if !inner.ended && inner.pending.len() < MAX_PENDING_CLIENT_MESSAGES {
+            inner.pending.push(json);
+        }
+    }
+
+    async fn binary(&self, data: Bytes) -> Result<(), Closed> {
+        let mut inner = self.inner.lock().await;
+        if !inner.connected {
+            return Err(Closed);
+        }
+
+        let result = inner.session.binary(data).await;
+        if result.is_err() {
+            inner.connected = false;
+        }
+
+        result
+    }
+
+    async fn disconnected(&self) {
+        self.inner.lock().await.connected = false;
+    }
+
+    async fn is_ended(&self) -> bool {
+        self.inner.lock().await.ended
+    }
+
+    async fn resume(&self, session: Session) {
+        let mut inner = self.inner.lock().await;
+
+        inner.session = session;
+        inner.connected = true;
+
+        let mut pending = take(&mut inner.pending).into_iter();
+        while let Some(json) = pending.next() {
+            if let Err(Closed) = inner.session.text(json.clone()).await {
+                // Kept for the next time the client resumes
+                inner.connected = false;
+                inner.pending = [json].into_iter().chain(pending).collect();
+                break;
+            }
+        }
+    }
+
+    async fn close(&self) {
+        let mut inner = self.inner.lock().await;
+
+        inner.connected = false;
+        inner.ended = true;
+        inner.pending.clear();
+
+        if let Err(err) = inner.session.clone().close(None).await {
+            warn!("failed to close streamer web socket: {err}");
+        }
+    }
+}
+
+fn input_macro_to_ipc(input_macro: StorageInputMacro) -> InputMacro {
+    InputMacro {
+        id: input_macro.id,
+        name: input_macro.name,
+        events: input_macro.events,
+    }
+}
+
 async fn send_ws_message(sender: &mut Session, message: StreamServerMessage) -> Result<(), Closed> {
     let Some(json) = serialize_json(&message) else {
         return Ok(());
@@ -343,16 +1309,245 @@ async fn send_ws_message(sender: &mut Session, message: StreamServerMessage) ->
     sender.text(json).await
 }
 
+/// The presets which can be selected when starting a stream
+#[get("/stream/presets")]
+pub async fn get_stream_presets(
+    web_app: Data<App>,
+    _user: AuthenticatedUser,
+) -> Json<GetStreamPresetsResponse> {
+    Json(GetStreamPresetsResponse {
+        presets: web_app.config().moonlight.stream_presets.clone(),
+    })
+}
+
 #[post("/host/cancel")]
 pub async fn cancel_host(
+    web_app: Data<App>,
     mut user: AuthenticatedUser,
     Json(request): Json<PostCancelRequest>,
 ) -> Result<Json<PostCancelResponse>, AppError> {
     let host_id = HostId(request.host_id);
 
+    let (success, streamer_terminated) = quit_host_app(&web_app, &mut user, host_id).await?;
+
+    Ok(Json(PostCancelResponse {
+        success,
+        streamer_terminated,
+    }))
+}
+
+/// Quits the app on the host, a session of this web server may only be stopped by the users who can control it.
+/// Returns if the app was quit and if the streamer of a session was terminated.
+pub(super) async fn quit_host_app(
+    web_app: &App,
+    user: &mut AuthenticatedUser,
+    host_id: HostId,
+) -> Result<(bool, bool), AppError> {
     let mut host = user.host(host_id).await?;
 
-    host.cancel_app(&mut user).await?;
+    // A session of this web server is stopped through its streamer, so the client gets notified
+    let mut streamer_terminated = false;
+    if let Some(active_stream) = web_app.active_stream(host_id).await {
+        if !can_control_session(user, active_stream.user_id).await? {
+            return Err(AppError::Forbidden);
+        }
+
+        streamer_terminated = web_app
+            .stop_active_stream(host_id, active_stream.session_id)
+            .await;
+        if streamer_terminated {
+            info!(
+                "[Stream]: user {:?} stopped the session {:?} of host {host_id:?}",
+                user.id(),
+                active_stream.session_id
+            );
+        }
+    }
+
+    let success = host.cancel_app(user).await?;
+
+    Ok((success, streamer_terminated))
+}
+
+#[get("/sessions")]
+pub async fn get_sessions(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+) -> Result<Json<GetSessionsResponse>, AppError> {
+    let is_admin = !user.is_guest() && matches!(user.role().await?, Role::Admin);
+
+    let mut sessions = Vec::new();
+    for (host_id, active_stream) in web_app.active_streams().await {
+        if !is_admin && active_stream.user_id != user.id() {
+            continue;
+        }
+
+        let mut stream_user = web_app.user_by_id(active_stream.user_id).await?;
+        let user_name = stream_user.detailed_user_no_auth().await?.name;
+
+        sessions.push(StreamSession {
+            session_id: active_stream.session_id.0,
+            host_id: host_id.0,
+            user_id: active_stream.user_id.0,
+            user_name,
+            quality_score: active_stream.quality_score,
+            rtsp_port: active_stream.rtsp_port,
+        });
+    }
+
+    Ok(Json(GetSessionsResponse { sessions }))
+}
+
+/// Packages everything which was recorded about the session for bug reports
+#[get("/session/{id}/diagnostics")]
+pub async fn get_session_diagnostics(
+    web_app: Data<App>,
+    _admin: Admin,
+    path: Path<u32>,
+) -> Result<HttpResponse, AppError> {
+    let session_id = SessionId(path.into_inner());
+
+    let diagnostics = web_app.session_diagnostics(session_id).await?;
+    let bundle = diagnostics
+        .bundle(web_app.config().log.anonymize_ips)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"session-{}-diagnostics.zip\"",
+                session_id.0
+            ),
+        ))
+        .body(bundle))
+}
+
+/// Decodes the next keyframe of the session, admins can take screenshots of every session
+#[post("/session/{id}/screenshot")]
+pub async fn post_session_screenshot(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+    path: Path<u32>,
+    Query(query): Query<PostScreenshotQuery>,
+) -> Result<HttpResponse, AppError> {
+    let session_id = SessionId(path.into_inner());
+    let format = query.format.unwrap_or_default();
+
+    let is_admin = !user.is_guest() && matches!(user.role().await?, Role::Admin);
+    let user_id = (!is_admin).then(|| user.id());
+
+    let image = web_app.take_screenshot(session_id, user_id, format).await?;
+
+    let (content_type, extension) = match format {
+        ScreenshotFormat::Png => ("image/png", "png"),
+        ScreenshotFormat::Jpeg => ("image/jpeg", "jpg"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!(
+                "inline; filename=\"session-{}-screenshot.{extension}\"",
+                session_id.0
+            ),
+        ))
+        .body(image))
+}
+
+/// The low resolution preview of a session as motion jpeg, which browsers can show in an img element
+#[get("/session/{id}/preview")]
+pub async fn get_session_preview(
+    web_app: Data<App>,
+    _admin: Admin,
+    path: Path<u32>,
+) -> Result<HttpResponse, AppError> {
+    let session_id = SessionId(path.into_inner());
+
+    let watcher = web_app.watch_preview(session_id).await?;
+
+    // The watcher is dropped once the admin stops watching, which stops the preview
+    let frames = futures::stream::unfold(watcher, |mut watcher| async move {
+        let image = watcher.next_frame().await?;
+
+        let mut part = format!(
+            "--{PREVIEW_BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            image.len()
+        )
+        .into_bytes();
+        part.extend_from_slice(&image);
+        part.extend_from_slice(b"\r\n");
+
+        Some((Ok::<_, Infallible>(Bytes::from(part)), watcher))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format!(
+            "multipart/x-mixed-replace; boundary={PREVIEW_BOUNDARY}"
+        ))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .streaming(frames))
+}
+
+/// The latest thumbnail of an active session for the session list
+#[get("/admin/sessions/{id}/thumb")]
+pub async fn get_session_thumbnail(
+    web_app: Data<App>,
+    _admin: Admin,
+    path: Path<u32>,
+) -> Result<HttpResponse, AppError> {
+    let session_id = SessionId(path.into_inner());
+
+    let image = web_app.session_thumbnail(session_id).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/jpeg")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .body(image))
+}
+
+/// Allows casting a session with hls, the returned playlist doesn't need credentials
+#[post("/session/{id}/hls")]
+pub async fn post_session_hls(
+    web_app: Data<App>,
+    mut user: AuthenticatedUser,
+    path: Path<u32>,
+) -> Result<Json<PostSessionHlsResponse>, AppError> {
+    let session_id = SessionId(path.into_inner());
+
+    let is_admin = !user.is_guest() && matches!(user.role().await?, Role::Admin);
+    let token = web_app
+        .session_hls_token(session_id, user.id(), is_admin)
+        .await?;
+
+    Ok(Json(PostSessionHlsResponse {
+        playlist_path: format!(
+            "{}/api/session/{}/hls/{token}/index.m3u8",
+            web_app.config().web_server.url_path_prefix,
+            session_id.0
+        ),
+    }))
+}
+
+/// The playlist and segments of the hls output, authorized by the token in the path
+#[get("/session/{id}/hls/{token}/{file}")]
+pub async fn get_session_hls_file(
+    web_app: Data<App>,
+    path: Path<(u32, String, String)>,
+    Query(query): Query<GetSessionHlsQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (session_id, token, file) = path.into_inner();
+
+    let file = web_app
+        .session_hls_file(SessionId(session_id), &token, file, query.msn, query.part)
+        .await?;
 
-    Ok(Json(PostCancelResponse { success: true }))
+    Ok(HttpResponse::Ok()
+        .content_type(file.content_type)
+        .insert_header((CACHE_CONTROL, "no-store"))
+        // Cast receivers load the playlist from another origin
+        .insert_header((ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+        .body(file.data))
 }
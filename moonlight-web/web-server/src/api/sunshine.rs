@@ -0,0 +1,145 @@
+use actix_web::{
+    HttpResponse, get, patch, post, put,
+    web::{Json, Query},
+};
+use common::api_bindings::{
+    GetSunshineAppsResponse, GetSunshineEncoderResponse, GetSunshineQuery,
+    PatchSunshineEncoderRequest, PostSunshineAppRequest, PutSunshineCredentialsRequest,
+    SunshineApp,
+};
+
+use crate::app::{
+    AppError,
+    host::HostId,
+    storage::{StorageHostModify, StorageSunshineCredentials},
+    sunshine_api::{self, ENCODER_SETTINGS},
+    user::{AuthenticatedUser, Role},
+};
+
+#[put("/host/sunshine/credentials")]
+pub async fn put_sunshine_credentials(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PutSunshineCredentialsRequest>,
+) -> Result<HttpResponse, AppError> {
+    if user.is_guest() || !matches!(user.role().await?, Role::Admin) {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut host = user.host(HostId(request.host_id)).await?;
+
+    host.modify(
+        &mut user,
+        StorageHostModify {
+            sunshine_credentials: Some(request.credentials.map(|credentials| {
+                StorageSunshineCredentials {
+                    username: credentials.username,
+                    password: credentials.password,
+                    web_ui_port: credentials.web_ui_port,
+                }
+            })),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/host/sunshine/apps")]
+pub async fn get_sunshine_apps(
+    mut user: AuthenticatedUser,
+    Query(query): Query<GetSunshineQuery>,
+) -> Result<Json<GetSunshineAppsResponse>, AppError> {
+    let mut host = user.host(HostId(query.host_id)).await?;
+    let api = host.sunshine_api(&mut user).await?;
+
+    let apps = api.apps().await?;
+
+    Ok(Json(GetSunshineAppsResponse {
+        apps: apps
+            .into_iter()
+            .map(|app| SunshineApp {
+                name: app.name,
+                cmd: app.cmd,
+                working_dir: app.working_dir,
+                image_path: app.image_path,
+                detached: app.detached,
+            })
+            .collect(),
+    }))
+}
+
+#[post("/host/sunshine/apps")]
+pub async fn post_sunshine_app(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PostSunshineAppRequest>,
+) -> Result<HttpResponse, AppError> {
+    if request.app.name.trim().is_empty() {
+        return Err(AppError::NameEmpty);
+    }
+
+    let mut host = user.host(HostId(request.host_id)).await?;
+    let api = host.sunshine_api(&mut user).await?;
+
+    api.add_app(&sunshine_api::SunshineApp {
+        name: request.app.name,
+        cmd: request.app.cmd,
+        working_dir: request.app.working_dir,
+        image_path: request.app.image_path,
+        detached: request.app.detached,
+        other: Default::default(),
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/host/sunshine/encoder")]
+pub async fn get_sunshine_encoder(
+    mut user: AuthenticatedUser,
+    Query(query): Query<GetSunshineQuery>,
+) -> Result<Json<GetSunshineEncoderResponse>, AppError> {
+    let mut host = user.host(HostId(query.host_id)).await?;
+    let api = host.sunshine_api(&mut user).await?;
+
+    Ok(Json(GetSunshineEncoderResponse {
+        settings: api.encoder_settings().await?,
+    }))
+}
+
+#[patch("/host/sunshine/encoder")]
+pub async fn patch_sunshine_encoder(
+    mut user: AuthenticatedUser,
+    Json(request): Json<PatchSunshineEncoderRequest>,
+) -> Result<HttpResponse, AppError> {
+    // Other settings could make the host unreachable
+    if request
+        .settings
+        .keys()
+        .any(|key| !ENCODER_SETTINGS.contains(&key.as_str()))
+    {
+        return Err(AppError::BadRequest);
+    }
+
+    let mut host = user.host(HostId(request.host_id)).await?;
+    let api = host.sunshine_api(&mut user).await?;
+
+    api.set_encoder_settings(request.settings).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/host/sunshine/logs")]
+pub async fn get_sunshine_logs(
+    mut user: AuthenticatedUser,
+    Query(query): Query<GetSunshineQuery>,
+) -> Result<HttpResponse, AppError> {
+    let mut host = user.host(HostId(query.host_id)).await?;
+    let api = host.sunshine_api(&mut user).await?;
+
+    let logs = api.logs().await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(logs))
+}
@@ -0,0 +1,13 @@
+use actix_web::{get, web::Json};
+use common::api_bindings::GetTailscalePeersResponse;
+
+use crate::app::{AppError, user::AuthenticatedUser};
+
+#[get("/tailscale/peers")]
+pub async fn get_tailscale_peers(
+    mut user: AuthenticatedUser,
+) -> Result<Json<GetTailscalePeersResponse>, AppError> {
+    let peers = user.tailscale_peers().await?;
+
+    Ok(Json(GetTailscalePeersResponse { peers }))
+}
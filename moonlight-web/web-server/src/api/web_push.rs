@@ -0,0 +1,52 @@
+use actix_web::{
+    HttpResponse, delete, get, post,
+    web::{Data, Json, Query},
+};
+use common::api_bindings::{
+    DeletePushSubscriptionQuery, GetWebPushKeyResponse, PostPushSubscriptionRequest,
+};
+
+use crate::app::{App, AppError, storage::StoragePushSubscription, user::AuthenticatedUser};
+
+#[get("/push/key")]
+pub async fn get_web_push_key(
+    app: Data<App>,
+    _user: AuthenticatedUser,
+) -> Result<Json<GetWebPushKeyResponse>, AppError> {
+    Ok(Json(GetWebPushKeyResponse {
+        public_key: app.web_push_public_key()?,
+    }))
+}
+
+#[post("/push/subscription")]
+pub async fn post_push_subscription(
+    user: AuthenticatedUser,
+    Json(request): Json<PostPushSubscriptionRequest>,
+) -> Result<HttpResponse, AppError> {
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
+    user.add_push_subscription(StoragePushSubscription {
+        endpoint: request.endpoint,
+        p256dh: request.keys.p256dh,
+        auth: request.keys.auth,
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/push/subscription")]
+pub async fn delete_push_subscription(
+    user: AuthenticatedUser,
+    Query(query): Query<DeletePushSubscriptionQuery>,
+) -> Result<HttpResponse, AppError> {
+    if user.is_guest() {
+        return Err(AppError::Forbidden);
+    }
+
+    user.remove_push_subscription(&query.endpoint).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,130 @@
+//! Rotates the client certificates of paired hosts once [ClientCertificateConfig::rotate_days_before_expiry] is reached.
+//!
+//! [ClientCertificateConfig::rotate_days_before_expiry]: common::config::ClientCertificateConfig::rotate_days_before_expiry
+
+use std::time::Duration;
+
+use common::api_bindings::{PushNotification, PushNotificationKind};
+use log::{info, warn};
+use moonlight_common::network::request_client::CancellationToken;
+use tokio::time::interval;
+
+use crate::app::{
+    AppError, AppRef,
+    host::Host,
+    storage::query::{StorageHostFilter, StoragePagination, StorageUserFilter},
+    user::{AuthenticatedUser, Role, User},
+    web_push::PushRecipients,
+};
+
+/// The certificates are checked this often, rotating is due days before they expire
+const ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Runs until the app is destroyed
+pub(super) async fn run_certificate_rotation(app: AppRef) {
+    let mut check_interval = interval(ROTATION_CHECK_INTERVAL);
+
+    loop {
+        check_interval.tick().await;
+
+        match rotate_due_certificates(&app).await {
+            Ok(()) => {}
+            Err(AppError::AppDestroyed) => return,
+            Err(err) => {
+                warn!("[Certificate Rotation]: failed to check the client certificates: {err}")
+            }
+        }
+    }
+}
+
+async fn rotate_due_certificates(app: &AppRef) -> Result<(), AppError> {
+    let inner = app.access()?;
+
+    // Rotating is restricted to admins, so the first admin rotates the certificates of all hosts
+    let Some(admin) = inner
+        .storage
+        .query_users(
+            StorageUserFilter {
+                role: Some(Role::Admin),
+                ..Default::default()
+            },
+            StoragePagination {
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await?
+        .entries
+        .into_iter()
+        .next()
+    else {
+        return Ok(());
+    };
+
+    let hosts = inner
+        .storage
+        .query_hosts(
+            StorageHostFilter {
+                paired: Some(true),
+                ..Default::default()
+            },
+            StoragePagination::default(),
+        )
+        .await?
+        .entries;
+
+    drop(inner);
+
+    let mut user = AuthenticatedUser {
+        inner: User {
+            app: app.clone(),
+            id: admin.id,
+            cache_storage: Some(admin),
+        },
+        is_guest: false,
+    };
+
+    for storage_host in hosts {
+        let host_id = storage_host.id;
+        let name = storage_host.cache.name.clone();
+
+        let mut host = Host {
+            app: app.clone(),
+            id: host_id,
+            cache_storage: Some(storage_host),
+            cache_host_info: None,
+            cancellation_token: CancellationToken::new(),
+        };
+
+        match host.rotate_client_certificate(&mut user, false).await {
+            // The rotation logs itself
+            Ok(_) => {}
+            Err(AppError::AppDestroyed) => return Err(AppError::AppDestroyed),
+            // Tried again at the next check
+            Err(AppError::HostOffline) => {
+                info!(
+                    "[Certificate Rotation]: host {host_id:?} is offline, its client certificate is rotated later"
+                )
+            }
+            Err(err) => {
+                warn!(
+                    "[Certificate Rotation]: failed to rotate the client certificate of host {host_id:?}: {err}"
+                );
+
+                app.push(
+                    PushRecipients::Admins,
+                    PushNotification {
+                        kind: PushNotificationKind::AdminAttention,
+                        title: "A client certificate couldn't be rotated".to_string(),
+                        body: format!(
+                            "The client certificate of {name} expires soon and couldn't be rotated: {err}"
+                        ),
+                        host_id: Some(host_id.0),
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
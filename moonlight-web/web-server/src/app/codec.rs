@@ -0,0 +1,88 @@
+//! Picks the video codec of a stream from the formats of the browser, the host and the configured policy.
+
+use common::config::{VideoCodec, VideoCodecPolicy};
+use moonlight_common::formats::{ServerCodeModeSupport, SupportedVideoFormats};
+
+/// Returns the formats of the most preferred codec which is supported by the client and the host.
+/// The result is empty if there's no such codec.
+pub fn negotiate_video_formats(
+    client: SupportedVideoFormats,
+    host: ServerCodeModeSupport,
+    policy: &VideoCodecPolicy,
+) -> SupportedVideoFormats {
+    let mut available = client & SupportedVideoFormats::from_server_codec_mode_support(host);
+
+    if !policy.allow_hdr {
+        available.remove(SupportedVideoFormats::MASK_10BIT);
+    }
+    if !policy.allow_yuv444 {
+        available.remove(SupportedVideoFormats::MASK_YUV444);
+    }
+
+    policy
+        .preference
+        .iter()
+        .map(|codec| available & codec_mask(*codec))
+        .find(|formats| !formats.is_empty())
+        .unwrap_or_else(SupportedVideoFormats::empty)
+}
+
+fn codec_mask(codec: VideoCodec) -> SupportedVideoFormats {
+    match codec {
+        VideoCodec::H264 => SupportedVideoFormats::MASK_H264,
+        VideoCodec::H265 => SupportedVideoFormats::MASK_H265,
+        VideoCodec::Av1 => SupportedVideoFormats::MASK_AV1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use common::config::{VideoCodec, VideoCodecPolicy};
+    use moonlight_common::formats::{ServerCodeModeSupport, SupportedVideoFormats};
+
+    use crate::app::codec::negotiate_video_formats;
+
+    #[test]
+    fn test_prefers_first_common_codec() {
+        let client = SupportedVideoFormats::H264
+            | SupportedVideoFormats::H265
+            | SupportedVideoFormats::AV1_MAIN8;
+        let host = ServerCodeModeSupport::H264 | ServerCodeModeSupport::AV1_MAIN8;
+
+        let formats = negotiate_video_formats(client, host, &VideoCodecPolicy::default());
+
+        assert_eq!(formats.bits(), SupportedVideoFormats::AV1_MAIN8.bits());
+    }
+
+    #[test]
+    fn test_policy_removes_hdr_and_codecs() {
+        let client = SupportedVideoFormats::H264
+            | SupportedVideoFormats::H265
+            | SupportedVideoFormats::H265_MAIN10;
+        let host = ServerCodeModeSupport::HEVC | ServerCodeModeSupport::HEVC_MAIN10;
+
+        let policy = VideoCodecPolicy {
+            allow_hdr: false,
+            ..Default::default()
+        };
+        let formats = negotiate_video_formats(client, host, &policy);
+        assert_eq!(formats.bits(), SupportedVideoFormats::H265.bits());
+
+        let policy = VideoCodecPolicy {
+            preference: vec![VideoCodec::H264],
+            ..Default::default()
+        };
+        let formats = negotiate_video_formats(client, host, &policy);
+        assert_eq!(formats.bits(), SupportedVideoFormats::H264.bits());
+    }
+
+    #[test]
+    fn test_no_common_codec() {
+        let client = SupportedVideoFormats::AV1_MAIN8;
+        let host = ServerCodeModeSupport::HEVC;
+
+        let formats = negotiate_video_formats(client, host, &VideoCodecPolicy::default());
+
+        assert!(formats.is_empty());
+    }
+}
@@ -0,0 +1,503 @@
+//! Records what happened during a stream, so that admins can download it for bug reports.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, Write},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use common::{
+    api_bindings::{StreamSignalingMessage, TransportType},
+    config::LogIpAnonymization,
+};
+use flate2::{Compression, Crc, write::DeflateEncoder};
+use log::{Level, warn};
+use serde::Serialize;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{
+    app::{host::HostId, user::UserId},
+    logging::anonymize_ips,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(pub u32);
+
+/// Diagnostics of older sessions are dropped once this many newer sessions started
+pub(super) const MAX_SESSION_DIAGNOSTICS: usize = 16;
+/// Older log messages of a session are dropped once it logged this many
+const MAX_LOG_LINES: usize = 10_000;
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, Copy)]
+pub enum SignalingSide {
+    Client,
+    Streamer,
+}
+
+impl SignalingSide {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Streamer => "streamer",
+        }
+    }
+}
+
+pub struct SessionDiagnostics {
+    id: SessionId,
+    host_id: HostId,
+    user_id: UserId,
+    started_at: SystemTime,
+    start: Instant,
+    /// Sessions of users in privacy mode record nothing
+    private: bool,
+    data: Mutex<DiagnosticsData>,
+}
+
+#[derive(Default)]
+struct DiagnosticsData {
+    logs: VecDeque<String>,
+    descriptions: Vec<String>,
+    ice_candidates: Vec<String>,
+    transport: Option<String>,
+    stream_settings: Option<String>,
+    connection: Option<String>,
+    /// Milliseconds since the start of the session and the score
+    quality_scores: Vec<(u128, u8)>,
+}
+
+#[derive(Serialize)]
+struct SessionInfo<'a> {
+    session_id: u32,
+    host_id: u32,
+    user_id: u32,
+    started_at_unix_secs: u64,
+    duration_secs: u64,
+    transport: Option<&'a str>,
+}
+
+impl SessionDiagnostics {
+    pub(super) fn new(id: SessionId, host_id: HostId, user_id: UserId, private: bool) -> Self {
+        Self {
+            id,
+            host_id,
+            user_id,
+            started_at: SystemTime::now(),
+            start: Instant::now(),
+            private,
+            data: Default::default(),
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+    pub fn host_id(&self) -> HostId {
+        self.host_id
+    }
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// The data to record into, none if the session is private
+    async fn recording(&self) -> Option<MutexGuard<'_, DiagnosticsData>> {
+        if self.private {
+            return None;
+        }
+
+        Some(self.data.lock().await)
+    }
+
+    fn elapsed_ms(&self) -> u128 {
+        self.start.elapsed().as_millis()
+    }
+
+    pub async fn log(&self, level: Level, target: &str, message: &str) {
+        let line = format!("{:>10} [{level}] {target}: {message}", self.elapsed_ms());
+
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        if data.logs.len() >= MAX_LOG_LINES {
+            data.logs.pop_front();
+        }
+        data.logs.push_back(line);
+    }
+
+    pub async fn signaling(&self, side: SignalingSide, message: &StreamSignalingMessage) {
+        let elapsed_ms = self.elapsed_ms();
+
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        match message {
+            StreamSignalingMessage::Description(description) => {
+                data.descriptions.push(format!(
+                    "-- {elapsed_ms} ms: {:?} from the {}\n{}",
+                    description.ty,
+                    side.name(),
+                    description.sdp
+                ));
+            }
+            StreamSignalingMessage::AddIceCandidate(candidate) => {
+                data.ice_candidates.push(format!(
+                    "{elapsed_ms:>10} {}: {}",
+                    side.name(),
+                    candidate.candidate
+                ));
+            }
+        }
+    }
+
+    pub async fn transport(&self, transport: &TransportType) {
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        data.transport = Some(format!("{transport:?}"));
+    }
+
+    /// The settings which the client requested
+    pub async fn stream_settings(&self, settings: &impl Serialize) {
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        data.stream_settings = to_json(settings);
+    }
+
+    /// The settings which the host and the streamer agreed on
+    pub async fn connection(&self, connection: &impl Serialize) {
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        data.connection = to_json(connection);
+    }
+
+    pub async fn quality_score(&self, score: u8) {
+        let elapsed_ms = self.elapsed_ms();
+
+        let Some(mut data) = self.recording().await else {
+            return;
+        };
+        data.quality_scores.push((elapsed_ms, score));
+    }
+
+    /// Packages everything into a zip file.
+    /// Credentials of the connection are always removed, ip addresses like configured for the log.
+    pub async fn bundle(&self, anonymization: LogIpAnonymization) -> Result<Vec<u8>, io::Error> {
+        let data = self.data.lock().await;
+
+        let redact = |text: &str| anonymize_ips(&redact_secrets(text), anonymization).into_owned();
+
+        let info = SessionInfo {
+            session_id: self.id.0,
+            host_id: self.host_id.0,
+            user_id: self.user_id.0,
+            started_at_unix_secs: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            duration_secs: self.start.elapsed().as_secs(),
+            transport: data.transport.as_deref(),
+        };
+
+        let mut stats = String::from("elapsed_ms,quality_score\n");
+        for (elapsed_ms, score) in &data.quality_scores {
+            let _ = writeln!(stats, "{elapsed_ms},{score}");
+        }
+
+        let mut zip = ZipWriter::default();
+        zip.add_file(
+            "session.json",
+            to_json(&info).unwrap_or_default().as_bytes(),
+        )?;
+        zip.add_file(
+            "stream_settings.json",
+            data.stream_settings.as_deref().unwrap_or("null").as_bytes(),
+        )?;
+        zip.add_file(
+            "connection.json",
+            data.connection.as_deref().unwrap_or("null").as_bytes(),
+        )?;
+        zip.add_file("stats.csv", stats.as_bytes())?;
+        zip.add_file("sdp.txt", redact(&data.descriptions.join("\n")).as_bytes())?;
+        zip.add_file(
+            "ice_candidates.txt",
+            redact(&data.ice_candidates.join("\n")).as_bytes(),
+        )?;
+        zip.add_file(
+            "streamer.log",
+            redact(
+                &data
+                    .logs
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .as_bytes(),
+        )?;
+
+        Ok(zip.finish())
+    }
+}
+
+fn to_json(value: &impl Serialize) -> Option<String> {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => Some(json),
+        Err(err) => {
+            warn!("failed to serialize session diagnostics: {err}");
+            None
+        }
+    }
+}
+
+/// Removes the ice credentials and certificate fingerprints of sdp and ice candidates.
+/// The lines are always separated by `\n` afterwards.
+fn redact_secrets(text: &str) -> String {
+    const SECRET_ATTRIBUTES: [&str; 3] = ["a=ice-pwd:", "a=ice-ufrag:", "a=fingerprint:"];
+
+    let mut out = String::with_capacity(text.len());
+
+    for (index, line) in text.lines().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+
+        if let Some(attribute) = SECRET_ATTRIBUTES
+            .iter()
+            .find(|attribute| line.trim_start().starts_with(**attribute))
+        {
+            out.push_str(attribute);
+            out.push_str(REDACTED);
+            continue;
+        }
+
+        // Candidates contain the username fragment after "ufrag"
+        let mut words = line.split(' ');
+        let mut first = true;
+        while let Some(word) = words.next() {
+            if !first {
+                out.push(' ');
+            }
+            first = false;
+
+            out.push_str(word);
+            if word == "ufrag" && words.next().is_some() {
+                out.push(' ');
+                out.push_str(REDACTED);
+            }
+        }
+    }
+
+    out
+}
+
+// -- Zip
+
+/// The entries don't have a modification time, zip uses the dos epoch 1980-01-01 for them
+const DOS_DATE_EPOCH: u16 = (1 << 5) | 1;
+/// The names are utf-8
+const ZIP_FLAGS: u16 = 1 << 11;
+const ZIP_METHOD_DEFLATE: u16 = 8;
+const ZIP_VERSION: u16 = 20;
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a zip file in memory, the diagnostics are small enough for that
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipWriter {
+    fn add_file(&mut self, name: &str, content: &[u8]) -> Result<(), io::Error> {
+        let mut crc = Crc::new();
+        crc.update(content);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content)?;
+        let compressed = encoder.finish()?;
+
+        let too_big = || io::Error::other("zip entry is too big");
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc.sum(),
+            compressed_size: u32::try_from(compressed.len()).map_err(|_| too_big())?,
+            size: u32::try_from(content.len()).map_err(|_| too_big())?,
+            offset: u32::try_from(self.data.len()).map_err(|_| too_big())?,
+        };
+
+        // Local file header
+        self.put_u32(0x04034b50);
+        self.put_u16(ZIP_VERSION);
+        self.put_entry_fields(&entry);
+        self.put_u16(0);
+        self.data.extend_from_slice(entry.name.as_bytes());
+        self.data.extend_from_slice(&compressed);
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let central_directory_offset = self.data.len() as u32;
+
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.put_u32(0x02014b50);
+            // Version made by and version needed to extract
+            self.put_u16(ZIP_VERSION);
+            self.put_u16(ZIP_VERSION);
+            self.put_entry_fields(entry);
+            // Extra field, comment, disk number, internal and external attributes
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u16(0);
+            self.put_u32(0);
+            self.put_u32(entry.offset);
+            self.data.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let central_directory_size = self.data.len() as u32 - central_directory_offset;
+
+        // End of central directory
+        self.put_u32(0x06054b50);
+        self.put_u16(0);
+        self.put_u16(0);
+        self.put_u16(entries.len() as u16);
+        self.put_u16(entries.len() as u16);
+        self.put_u32(central_directory_size);
+        self.put_u32(central_directory_offset);
+        self.put_u16(0);
+
+        self.data
+    }
+
+    /// The fields from the flags to the file name length, which both headers share
+    fn put_entry_fields(&mut self, entry: &ZipEntry) {
+        self.put_u16(ZIP_FLAGS);
+        self.put_u16(ZIP_METHOD_DEFLATE);
+        self.put_u16(0);
+        self.put_u16(DOS_DATE_EPOCH);
+        self.put_u32(entry.crc);
+        self.put_u32(entry.compressed_size);
+        self.put_u32(entry.size);
+        self.put_u16(entry.name.len() as u16);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    fn put_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use flate2::read::DeflateDecoder;
+
+    use log::Level;
+
+    use crate::app::{
+        diagnostics::{SessionDiagnostics, SessionId, ZipWriter, redact_secrets},
+        host::HostId,
+        user::UserId,
+    };
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn test_redact_sdp() {
+        let sdp = "v=0\r\na=ice-ufrag:abcd\r\na=ice-pwd:secretpassword\r\na=fingerprint:sha-256 AB:CD\r\na=mid:0";
+
+        assert_eq!(
+            redact_secrets(sdp),
+            "v=0\na=ice-ufrag:[redacted]\na=ice-pwd:[redacted]\na=fingerprint:[redacted]\na=mid:0"
+        );
+    }
+
+    #[test]
+    fn test_redact_candidate() {
+        assert_eq!(
+            redact_secrets(
+                "candidate:1 1 udp 2130706431 192.168.1.2 50000 typ host ufrag abcd network-id 1"
+            ),
+            "candidate:1 1 udp 2130706431 192.168.1.2 50000 typ host ufrag [redacted] network-id 1"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_private_session_records_nothing() {
+        let diagnostics = SessionDiagnostics::new(SessionId(1), HostId(1), UserId(1), true);
+
+        diagnostics
+            .log(Level::Info, "streamer", "pressed a key")
+            .await;
+        diagnostics.quality_score(5).await;
+
+        let data = diagnostics.data.lock().await;
+        assert!(data.logs.is_empty());
+        assert!(data.quality_scores.is_empty());
+    }
+
+    #[test]
+    fn test_zip_layout() {
+        let content = b"hello hello hello hello";
+
+        let mut zip = ZipWriter::default();
+        zip.add_file("a.txt", content).expect("add file");
+        zip.add_file("b.txt", b"").expect("add file");
+        let data = zip.finish();
+
+        // Local file header of the first entry
+        assert_eq!(u32_at(&data, 0), 0x04034b50);
+        let compressed_size = u32_at(&data, 18) as usize;
+        assert_eq!(u32_at(&data, 22) as usize, content.len());
+        assert_eq!(&data[30..35], b"a.txt");
+
+        let mut decompressed = Vec::new();
+        DeflateDecoder::new(&data[35..35 + compressed_size])
+            .read_to_end(&mut decompressed)
+            .expect("decompress");
+        assert_eq!(decompressed, content);
+
+        // End of central directory
+        let end = data.len() - 22;
+        assert_eq!(u32_at(&data, end), 0x06054b50);
+        assert_eq!(u16_at(&data, end + 10), 2);
+
+        let central_directory_offset = u32_at(&data, end + 16) as usize;
+        assert_eq!(u32_at(&data, central_directory_offset), 0x02014b50);
+        // The offset of the first local header
+        assert_eq!(u32_at(&data, central_directory_offset + 42), 0);
+    }
+}
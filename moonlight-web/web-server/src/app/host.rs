@@ -1,37 +1,61 @@
 use std::{
     fmt::{Debug, Formatter},
     str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use actix_web::web::Bytes;
-use common::api_bindings::{self, DetailedHost, HostOwner, HostState, PairStatus, UndetailedHost};
-use log::warn;
+use common::{
+    api_bindings::{
+        self, DetailedHost, HostCapabilities, HostCertificate, HostOwner, HostSession, HostState,
+        HostType, PairPinInfo, PairStatus, UndetailedHost,
+    },
+    config::{Config, SunshineCredentials},
+};
+use log::{info, warn};
 use moonlight_common::{
     PairPin, ServerState,
-    high::broadcast_magic_packet,
+    formats::{ServerCodeModeSupport, SupportedVideoFormats},
+    high::{AppListChange, broadcast_magic_packet},
     network::{
         self, ApiError, ClientAppBoxArtRequest, ClientInfo, HostInfo, host_app_box_art,
         host_app_list, host_cancel, host_info,
-        request_client::{RequestClient, RequestError},
+        launch::{AudioRouting, ClientStreamRequest, DEFAULT_LAUNCH_QUERY_PARAMETERS, host_launch},
+        request_client::{CancellationToken, RequestClient, RequestError},
     },
-    pair::{PairSuccess, generate_new_client, host_pair},
+    pair::{CertificateValidity, PairSuccess, generate_new_client_valid_for, host_pair},
+    units::Fps,
+};
+use openssl::sha::sha256;
+use pem::Pem;
+use tokio::{
+    spawn,
+    time::{Instant, sleep},
 };
 use uuid::Uuid;
 
 use crate::app::{
     AppError, AppInner, AppRef, MoonlightClient,
+    codec::negotiate_video_formats,
     storage::{StorageHost, StorageHostModify, StorageHostPairInfo},
+    sunshine_api::{SunshineApi, web_ui_port},
+    tailscale::is_tailscale_address,
     user::{AuthenticatedUser, Role, UserId},
 };
 
+/// How often [Host::wait_online] asks the host if it's online
+const HOST_ONLINE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct HostId(pub u32);
 
+#[derive(Clone)]
 pub struct Host {
     pub(super) app: AppRef,
     pub(super) id: HostId,
     pub(super) cache_storage: Option<StorageHost>,
     pub(super) cache_host_info: Option<(UserId, HostInfo)>,
+    pub(super) cancellation_token: CancellationToken,
 }
 
 impl Debug for Host {
@@ -43,6 +67,27 @@ impl Debug for Host {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AppId(pub u32);
 
+#[derive(Clone)]
+pub struct AppImage {
+    pub data: Bytes,
+    /// A hash of the data, so it stays the same when the image is fetched again
+    pub etag: String,
+    /// When the image was fetched from the host
+    pub fetched_at: SystemTime,
+}
+
+impl AppImage {
+    fn new(data: Bytes) -> Self {
+        let hash = sha256(&data);
+
+        Self {
+            etag: hex::encode(&hash[..16]),
+            data,
+            fetched_at: SystemTime::now(),
+        }
+    }
+}
+
 pub struct App {
     pub id: AppId,
     pub title: String,
@@ -73,9 +118,20 @@ impl Host {
         self.id
     }
 
+    /// Aborts the requests to the host once cancelled, e.g. when the client which waits for them disconnected
+    pub fn set_cancellation_token(&mut self, cancellation_token: CancellationToken) {
+        self.cancellation_token = cancellation_token;
+    }
+
     async fn can_use(&self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
+        if !user.can_use_host(self.id)? {
+            return Err(AppError::Forbidden);
+        }
+
         let owner = self.owner().await?;
-        if owner.is_none() || owner == Some(user.id()) || matches!(user.role().await?, Role::Admin)
+        if owner.is_none()
+            || owner == Some(user.id())
+            || (!user.is_guest() && matches!(user.role().await?, Role::Admin))
         {
             Ok(())
         } else {
@@ -99,6 +155,14 @@ impl Host {
         Ok(())
     }
 
+    pub async fn name(&self) -> Result<String, AppError> {
+        let app = self.app.access()?;
+
+        let host = self.storage_host(&app).await?;
+
+        Ok(host.cache.name)
+    }
+
     pub async fn owner(&self) -> Result<Option<UserId>, AppError> {
         let app = self.app.access()?;
 
@@ -147,43 +211,66 @@ impl Host {
         app: &AppInner,
         user: &mut AuthenticatedUser,
         pairing: bool,
-        // app, https_capable, client, host, port, client_info
-        f: impl AsyncFnOnce(&mut Self, bool, &mut MoonlightClient, &str, u16, ClientInfo) -> R,
+        // app, https_capable, client, cancellation_token, host, port, client_info
+        f: impl AsyncFnOnce(
+            &mut Self,
+            bool,
+            &mut MoonlightClient,
+            &CancellationToken,
+            &str,
+            u16,
+            ClientInfo,
+        ) -> R,
     ) -> Result<R, AppError> {
         let user_unique_id = user.host_unique_id().await?;
         let host_data = self.storage_host(app).await?;
+        let options = app.config.moonlight.request_client_options(Some(self.id.0));
 
         let (mut client, https_capable) = if pairing {
             (
-                MoonlightClient::with_defaults_long_timeout().map_err(ApiError::RequestClient)?,
+                MoonlightClient::with_defaults_long_timeout(&options)
+                    .map_err(ApiError::RequestClient)?,
                 false,
             )
         } else if let Some(pair_info) = host_data.pair_info {
-            (
-                MoonlightClient::with_certificates(
-                    &pair_info.client_private_key,
-                    &pair_info.client_certificate,
-                    &pair_info.server_certificate,
-                )
-                .map_err(ApiError::RequestClient)?,
-                true,
-            )
+            let certificates = (&pair_info.client_certificate, &pair_info.server_certificate);
+            let client = app
+                .host_clients
+                .client(self.id, Some(certificates), || {
+                    Ok(MoonlightClient::with_certificates(
+                        &options,
+                        &app.client_private_key(&pair_info.client_private_key)?,
+                        &pair_info.client_certificate,
+                        &pair_info.server_certificate,
+                    )
+                    .map_err(ApiError::RequestClient)?)
+                })
+                .await?;
+
+            (client, true)
         } else {
-            (
-                MoonlightClient::with_defaults().map_err(ApiError::RequestClient)?,
-                false,
-            )
+            let client =
+                app.host_clients
+                    .client(self.id, None, || {
+                        Ok(MoonlightClient::with_defaults(&options)
+                            .map_err(ApiError::RequestClient)?)
+                    })
+                    .await?;
+
+            (client, false)
         };
 
         let info = ClientInfo {
             unique_id: &user_unique_id,
             uuid: Uuid::new_v4(),
         };
+        let cancellation_token = self.cancellation_token.clone();
 
         Ok(f(
             self,
             https_capable,
             &mut client,
+            &cancellation_token,
             &host_data.address,
             host_data.http_port,
             info,
@@ -215,6 +302,7 @@ impl Host {
         Ok((host.address, host.http_port))
     }
 
+    /// Contains the private key itself, not what the key store returned
     pub async fn pair_info(
         &self,
         user: &mut AuthenticatedUser,
@@ -225,7 +313,10 @@ impl Host {
 
         let host = app.storage.get_host(self.id).await?;
 
-        host.pair_info.ok_or(AppError::HostNotPaired)
+        let mut pair_info = host.pair_info.ok_or(AppError::HostNotPaired)?;
+        pair_info.client_private_key = app.client_private_key(&pair_info.client_private_key)?;
+
+        Ok(pair_info)
     }
 
     fn is_offline<T>(
@@ -256,13 +347,15 @@ impl Host {
             app,
             user,
             false,
-            async |this, https_capable, client, host, port, client_info| {
+            async |this, https_capable, client, cancel, host, port, client_info| {
                 let mut info = match this.is_offline(
                     host_info(
                         client,
+                        cancel,
                         false,
                         &Self::build_hostport(host, port),
                         Some(client_info),
+                        app.config.moonlight.xml_parse_mode(Some(this.id.0)),
                     )
                     .await,
                 ) {
@@ -273,9 +366,11 @@ impl Host {
                 if https_capable {
                     match host_info(
                         client,
+                        cancel,
                         true,
                         &Self::build_hostport(host, info.https_port),
                         Some(client_info),
+                        app.config.moonlight.xml_parse_mode(Some(this.id.0)),
                     )
                     .await
                     {
@@ -368,6 +463,7 @@ impl Host {
         let storage = self.storage_host(&app).await?;
 
         let owner = self.owner_info(user, &storage).await?;
+        let tunnel_reachable = is_tailscale_address(&storage.address);
 
         match self.host_info(&app, user).await {
             Ok(Some(info)) => {
@@ -383,6 +479,15 @@ impl Host {
                     }
                 };
 
+                let current_session = if info.current_game != 0 {
+                    Some(HostSession {
+                        app_id: info.current_game,
+                        app_title: self.app_title(user, AppId(info.current_game)).await,
+                    })
+                } else {
+                    None
+                };
+
                 Ok(DetailedHost {
                     host_id: self.id.0,
                     owner,
@@ -399,8 +504,18 @@ impl Host {
                     mac: info.mac.map(|mac| mac.to_string()),
                     local_ip: info.local_ip,
                     current_game: info.current_game,
+                    current_session,
                     max_luma_pixels_hevc: info.max_luma_pixels_hevc,
                     server_codec_mode_support: info.server_codec_mode_support,
+                    host_type: info.host_type.into(),
+                    capabilities: HostCapabilities {
+                        hdr: info.supports_hdr(),
+                        extended_gamepads: info.host_type.supports_extended_gamepads(),
+                        pen_touch: info.host_type.supports_pen_touch(),
+                    },
+                    notes: storage.notes,
+                    labels: storage.labels,
+                    tunnel_reachable,
                 })
             }
             Ok(None) => {
@@ -426,14 +541,56 @@ impl Host {
                     mac: storage.cache.mac.map(|mac| mac.to_string()),
                     local_ip: "Offline".to_string(),
                     current_game: 0,
+                    current_session: None,
                     max_luma_pixels_hevc: 0,
                     server_codec_mode_support: 0,
+                    host_type: HostType::Unknown,
+                    capabilities: HostCapabilities::default(),
+                    notes: storage.notes,
+                    labels: storage.labels,
+                    tunnel_reachable,
                 })
             }
             Err(err) => Err(err),
         }
     }
 
+    /// Resolves the title of an app with the last app list of the host.
+    /// The list is only requested if the app isn't in it, this requires the host to be paired.
+    async fn app_title(&mut self, user: &mut AuthenticatedUser, app_id: AppId) -> Option<String> {
+        if !user.can_use_app(app_id).unwrap_or(false) {
+            return None;
+        }
+
+        if let Ok(app) = self.app.access() {
+            let app_lists = app.app_lists.read().await;
+
+            let cached_title = app_lists
+                .get(&self.id)
+                .and_then(|app_list| app_list.apps())
+                .and_then(|apps| apps.iter().find(|cached| cached.id == app_id.0))
+                .map(|cached| cached.title.clone());
+            if cached_title.is_some() {
+                return cached_title;
+            }
+        }
+
+        match self.list_apps(user).await {
+            Ok(apps) => apps
+                .into_iter()
+                .find(|app| app.id == app_id)
+                .map(|app| app.title),
+            Err(AppError::HostNotPaired) => None,
+            Err(err) => {
+                warn!(
+                    "failed to get the app list of host {self:?} to resolve the current app: {err}"
+                );
+
+                None
+            }
+        }
+    }
+
     pub async fn is_paired(
         &mut self,
         user: &mut AuthenticatedUser,
@@ -448,6 +605,62 @@ impl Host {
         }
     }
 
+    /// The pin and the representations of it which are shown to the user while pairing
+    pub async fn pair_pin_info(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        pin: PairPin,
+    ) -> Result<PairPinInfo, AppError> {
+        self.can_use(user).await?;
+
+        let app = self.app.access()?;
+
+        let host = self.storage_host(&app).await?;
+        let credentials = sunshine_credentials(&app.config, &host);
+
+        Ok(PairPinInfo {
+            pin: pin.to_string(),
+            spoken_pin: pin
+                .digits()
+                .iter()
+                .map(|number| number.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            otp_url: format!(
+                "https://{}:{}/pin#{pin}",
+                host.address,
+                web_ui_port(host.http_port, credentials.as_ref())
+            ),
+            auto_submit: auto_submit_credentials(&app.config, &host).is_some(),
+        })
+    }
+
+    /// Only admins can use the Sunshine api because it uses the admin credentials of the host
+    pub async fn sunshine_api(
+        &mut self,
+        user: &mut AuthenticatedUser,
+    ) -> Result<SunshineApi, AppError> {
+        self.can_use(user).await?;
+
+        if user.is_guest() || !matches!(user.role().await?, Role::Admin) {
+            return Err(AppError::Forbidden);
+        }
+
+        let app = self.app.access()?;
+
+        let host = self.storage_host(&app).await?;
+        let credentials =
+            sunshine_credentials(&app.config, &host).ok_or(AppError::SunshineCredentialsMissing)?;
+        let pair_info = host.pair_info.as_ref().ok_or(AppError::HostNotPaired)?;
+
+        Ok(SunshineApi::new(
+            &app.config.moonlight.resolve_host_address(&host.address),
+            host.http_port,
+            credentials,
+            &pair_info.server_certificate,
+        )?)
+    }
+
     pub async fn pair(
         &mut self,
         user: &mut AuthenticatedUser,
@@ -455,7 +668,6 @@ impl Host {
     ) -> Result<(), AppError> {
         self.can_use(user).await?;
 
-        let user_id = user.id();
         let app = self.app.access()?;
 
         let info = self
@@ -467,18 +679,60 @@ impl Host {
             return Err(AppError::HostPaired);
         }
 
+        let submit_credentials =
+            auto_submit_credentials(&app.config, &self.storage_host(&app).await?);
+
         let modify = self
+            .pair_new_client(&app, user, &info, pin, submit_credentials)
+            .await?;
+
+        self.modify(user, modify).await
+    }
+
+    /// Generates a new client certificate and pairs it, the pin is submitted to Sunshine if there are credentials
+    async fn pair_new_client(
+        &mut self,
+        app: &AppInner,
+        user: &mut AuthenticatedUser,
+        info: &HostInfo,
+        pin: PairPin,
+        submit_credentials: Option<(SunshineCredentials, Pem)>,
+    ) -> Result<StorageHostModify, AppError> {
+        let user_id = user.id();
+
+        self
             .use_client(
-                &app,
+                app,
                 user,
                 true,
-                async |this,_https_capable, client, host, port, client_info| {
-                    let auth = generate_new_client()?;
+                async |this, _https_capable, client, cancel, host, port, client_info| {
+                    let auth = generate_new_client_valid_for(
+                        app.config.moonlight.client_certificate.validity_days,
+                    )?;
 
                     let https_address = Self::build_hostport(host, info.https_port);
 
-                    let PairSuccess { server_certificate, mut client } =host_pair(
+                    let submit_task = match submit_credentials {
+                        Some((credentials, server_certificate)) => {
+                            let api = SunshineApi::new(
+                                &app.config.moonlight.resolve_host_address(host),
+                                port,
+                                credentials,
+                                &server_certificate,
+                            )?;
+                            let device_name = app.config.moonlight.pair_device_name.clone();
+
+                            Some(spawn(async move {
+                                api.submit_pin_while_pairing(pin, &device_name).await;
+                            }))
+                        }
+                        None => None,
+                    };
+
+                    let result = host_pair(
                         client,
+                        &app.config.moonlight.request_client_options(Some(this.id.0)),
+                        cancel,
                         &Self::build_hostport(host, port),
                         &https_address,
                         client_info,
@@ -488,15 +742,23 @@ impl Host {
                         info.app_version,
                         pin,
                     )
-                    .await?;
+                    .await;
+
+                    if let Some(submit_task) = submit_task {
+                        submit_task.abort();
+                    }
+
+                    let PairSuccess { server_certificate, mut client } = result?;
 
 
                     // Store pair info
                     let (name, mac) = match host_info(
                         &mut client,
+                        cancel,
                         true,
                         &Self::build_hostport(host, info.https_port),
                         Some(client_info),
+                        app.config.moonlight.xml_parse_mode(Some(this.id.0)),
                     )
                     .await
                     {
@@ -511,9 +773,13 @@ impl Host {
                         },
                     };
 
+                    let client_private_key = app
+                        .key_store
+                        .store(&Uuid::new_v4().to_string(), &auth.private_key)?;
+
                     Ok::<_, AppError>(StorageHostModify {
                         pair_info: Some(Some(StorageHostPairInfo {
-                            client_private_key: auth.private_key,
+                            client_private_key,
                             client_certificate: auth.certificate,
                             server_certificate,
                         })),
@@ -523,9 +789,97 @@ impl Host {
                     })
                 },
             )
-            .await??;
+            .await?
+    }
 
-        self.modify(user, modify).await
+    /// None if the host isn't paired
+    pub async fn client_certificate(
+        &self,
+        user: &mut AuthenticatedUser,
+    ) -> Result<Option<HostCertificate>, AppError> {
+        self.can_use(user).await?;
+
+        let app = self.app.access()?;
+
+        let storage = self.storage_host(&app).await?;
+        let Some(pair_info) = storage.pair_info else {
+            return Ok(None);
+        };
+
+        let validity = CertificateValidity::read(&pair_info.client_certificate)?;
+        let now = SystemTime::now();
+
+        Ok(Some(HostCertificate {
+            host_id: self.id.0,
+            name: storage.cache.name,
+            not_before: unix_secs(validity.not_before),
+            not_after: unix_secs(validity.not_after),
+            age_days: days(validity.age(now)),
+            expires_in_days: days(validity.remaining(now)),
+            rotation_due: is_rotation_due(&app.config, &validity, now),
+        }))
+    }
+
+    /// Pairs a new client certificate when the current one expires soon.
+    /// Returns if the certificate was replaced.
+    ///
+    /// The pin is submitted with the Sunshine credentials, so this only works for hosts which have them.
+    pub async fn rotate_client_certificate(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        force: bool,
+    ) -> Result<bool, AppError> {
+        self.can_use(user).await?;
+
+        if user.is_guest() || !matches!(user.role().await?, Role::Admin) {
+            return Err(AppError::Forbidden);
+        }
+
+        let app = self.app.access()?;
+
+        let storage = self.storage_host(&app).await?;
+        let pair_info = storage.pair_info.as_ref().ok_or(AppError::HostNotPaired)?;
+
+        let validity = CertificateValidity::read(&pair_info.client_certificate)?;
+        if !force && !is_rotation_due(&app.config, &validity, SystemTime::now()) {
+            return Ok(false);
+        }
+
+        let credentials = sunshine_credentials(&app.config, &storage)
+            .ok_or(AppError::SunshineCredentialsMissing)?;
+
+        // The host info is requested with the current certificate, so this also checks that it is still paired
+        let info = self
+            .host_info(&app, user)
+            .await?
+            .ok_or(AppError::HostOffline)?;
+        if !matches!(info.pair_status.into(), PairStatus::Paired) {
+            return Err(AppError::HostNotPaired);
+        }
+
+        let pin = PairPin::generate()?;
+        let modify = self
+            .pair_new_client(
+                &app,
+                user,
+                &info,
+                pin,
+                Some((credentials, pair_info.server_certificate.clone())),
+            )
+            .await?;
+
+        self.modify(user, modify).await?;
+
+        if let Err(err) = app.key_store.remove(&pair_info.client_private_key) {
+            warn!("Failed to remove the old client private key of host {self:?}: {err}");
+        }
+
+        info!(
+            "Rotated the client certificate of host {self:?}, the old one expires in {} days",
+            days(validity.remaining(SystemTime::now()))
+        );
+
+        Ok(true)
     }
 
     pub async fn unpair(&self, user: &mut AuthenticatedUser) -> Result<Host, AppError> {
@@ -549,6 +903,36 @@ impl Host {
         }
     }
 
+    /// Polls the host until it answers, e.g. while it boots after [Self::wake]
+    pub async fn wait_online(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        timeout: Duration,
+    ) -> Result<(), AppError> {
+        self.can_use(user).await?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.cache_host_info = None;
+
+            let app = self.app.access()?;
+            match self.host_info(&app, user).await {
+                Ok(Some(_)) => return Ok(()),
+                Ok(None) => {}
+                // Booting hosts often accept the connection before they answer
+                Err(AppError::MoonlightApi(ApiError::RequestClient(err)))
+                    if !err.is_cancelled() => {}
+                Err(err) => return Err(err),
+            }
+            drop(app);
+
+            if Instant::now() + HOST_ONLINE_POLL_INTERVAL > deadline {
+                return Err(AppError::HostOffline);
+            }
+            sleep(HOST_ONLINE_POLL_INTERVAL).await;
+        }
+    }
+
     pub async fn list_apps(&mut self, user: &mut AuthenticatedUser) -> Result<Vec<App>, AppError> {
         self.can_use(user).await?;
 
@@ -558,37 +942,90 @@ impl Host {
             .host_info(&app, user)
             .await?
             .ok_or(AppError::HostOffline)?;
+        let xml_parse_mode = app.config.moonlight.xml_parse_mode(Some(self.id.0));
 
         self.use_client(
             &app,
             user,
             false,
-            async |_this, https_capable, client, host, _port, client_info| {
+            async |_this, https_capable, client, cancel, host, _port, client_info| {
                 if !https_capable {
                     return Err(AppError::HostNotPaired);
                 }
 
                 let apps = host_app_list(
                     client,
+                    cancel,
                     &Self::build_hostport(host, info.https_port),
                     client_info,
+                    xml_parse_mode,
                 )
                 .await?;
 
-                let apps = apps.apps.into_iter().map(App::from).collect::<Vec<_>>();
-
-                Ok(apps)
+                Ok(apps.apps)
             },
         )
-        .await?
+        .await??;
+
+        self.apply_app_list_changes(&app, &apps).await;
+
+        let apps = apps.into_iter().map(App::from).collect::<Vec<_>>();
+
+        let mut allowed_apps = Vec::with_capacity(apps.len());
+        for app in apps {
+            if user.can_use_app(app.id)? {
+                allowed_apps.push(app);
+            }
+        }
+
+        Ok(allowed_apps)
+    }
+    /// Removes the cached images of apps which were removed or changed on the host
+    async fn apply_app_list_changes(&self, app: &AppInner, apps: &[network::App]) {
+        let changes = {
+            let mut app_lists = app.app_lists.write().await;
+            app_lists.entry(self.id).or_default().update(apps)
+        };
+
+        if changes.is_empty() {
+            return;
+        }
+
+        let mut stale_apps = Vec::new();
+        for change in &changes {
+            match change {
+                AppListChange::Added(added) => {
+                    info!("App {} was added to host {:?}", added.title, self.id);
+                }
+                AppListChange::Removed(removed) => {
+                    info!("App {} was removed from host {:?}", removed.title, self.id);
+                    stale_apps.push(AppId(removed.id));
+                }
+                AppListChange::Changed { old, new } => {
+                    info!(
+                        "App {} changed on host {:?}: {old:?} -> {new:?}",
+                        new.title, self.id
+                    );
+                    stale_apps.push(AppId(new.id));
+                }
+            }
+        }
+
+        let mut app_images = app.app_image_cache.write().await;
+        app_images
+            .retain(|(_, host_id, app_id), _| *host_id != self.id || !stale_apps.contains(app_id));
     }
+
     pub async fn app_image(
         &mut self,
         user: &mut AuthenticatedUser,
         app_id: AppId,
         force_refresh: bool,
-    ) -> Result<Bytes, AppError> {
+    ) -> Result<AppImage, AppError> {
         self.can_use(user).await?;
+        if !user.can_use_app(app_id)? {
+            return Err(AppError::Forbidden);
+        }
 
         let app = self.app.access()?;
 
@@ -612,13 +1049,14 @@ impl Host {
                 &app,
                 user,
                 false,
-                async |_this, https_capable, client, host, _port, client_info| {
+                async |_this, https_capable, client, cancel, host, _port, client_info| {
                     if !https_capable {
                         return Err(AppError::HostNotPaired);
                     }
 
                     let image = host_app_box_art(
                         client,
+                        cancel,
                         &Self::build_hostport(host, info.https_port),
                         client_info,
                         ClientAppBoxArtRequest { app_id: app_id.0 },
@@ -629,7 +1067,7 @@ impl Host {
                 },
             )
             .await??;
-        let app_image = Bytes::from_owner(app_image);
+        let app_image = AppImage::new(Bytes::from_owner(app_image));
 
         {
             let mut app_images = app.app_image_cache.write().await;
@@ -639,6 +1077,118 @@ impl Host {
         Ok(app_image)
     }
 
+    /// Reduces the formats of the client to the best codec which the host can encode and the policy allows.
+    pub async fn negotiate_video_formats(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        client_formats: SupportedVideoFormats,
+    ) -> Result<SupportedVideoFormats, AppError> {
+        self.can_use(user).await?;
+
+        let app = self.app.access()?;
+
+        let info = self
+            .host_info(&app, user)
+            .await?
+            .ok_or(AppError::HostOffline)?;
+
+        let mut host_support =
+            ServerCodeModeSupport::from_bits_retain(info.server_codec_mode_support);
+        // Older hosts report 10 bit codecs which they can't stream as hdr
+        if !info.supports_hdr() {
+            host_support.remove(
+                ServerCodeModeSupport::HEVC_MAIN10
+                    | ServerCodeModeSupport::AV1_MAIN10
+                    | ServerCodeModeSupport::HEVC_REXT10_444
+                    | ServerCodeModeSupport::AV1_HIGH10_444,
+            );
+        }
+
+        let policy = app.config.moonlight.video_codec_policy(self.id.0);
+
+        Ok(negotiate_video_formats(
+            client_formats,
+            host_support,
+            policy,
+        ))
+    }
+
+    /// Launches the app without streaming it. A stream started later on resumes the app.
+    ///
+    /// Returns false if the app was already running.
+    pub async fn launch_app(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        app_id: AppId,
+        width: u32,
+        height: u32,
+        fps: Fps,
+        hdr: bool,
+        audio_routing: AudioRouting,
+    ) -> Result<bool, AppError> {
+        self.can_use(user).await?;
+        if !user.can_use_app(app_id)? {
+            return Err(AppError::Forbidden);
+        }
+
+        let app = self.app.access()?;
+
+        // The current game must be up to date
+        self.cache_host_info = None;
+        let info = self
+            .host_info(&app, user)
+            .await?
+            .ok_or(AppError::HostOffline)?;
+
+        if info.current_game == app_id.0 {
+            return Ok(false);
+        } else if info.current_game != 0 {
+            return Err(AppError::HostBusy);
+        }
+
+        if hdr && !info.supports_hdr() {
+            info!("Host {self:?} doesn't support hdr, launching app {app_id:?} without it");
+        }
+        let hdr = hdr && info.supports_hdr();
+
+        let request = ClientStreamRequest::launch_only(
+            app_id.0,
+            width,
+            height,
+            fps,
+            hdr,
+            true,
+            audio_routing,
+        )?;
+
+        self.use_client(
+            &app,
+            user,
+            false,
+            async |this, https_capable, client, cancel, host, _port, client_info| {
+                if !https_capable {
+                    return Err(AppError::HostNotPaired);
+                }
+
+                host_launch(
+                    DEFAULT_LAUNCH_QUERY_PARAMETERS,
+                    client,
+                    cancel,
+                    &Self::build_hostport(host, info.https_port),
+                    client_info,
+                    request,
+                )
+                .await?;
+
+                // Now there's an active app
+                this.cache_host_info = None;
+
+                Ok(true)
+            },
+        )
+        .await?
+    }
+
     pub async fn cancel_app(&mut self, user: &mut AuthenticatedUser) -> Result<bool, AppError> {
         self.can_use(user).await?;
 
@@ -653,13 +1203,14 @@ impl Host {
             &app,
             user,
             false,
-            async |_this, https_capable, client, host, _port, client_info| {
+            async |_this, https_capable, client, cancel, host, _port, client_info| {
                 if !https_capable {
                     return Err(AppError::Forbidden);
                 }
 
                 let success = host_cancel(
                     client,
+                    cancel,
                     &Self::build_hostport(host, info.https_port),
                     client_info,
                 )
@@ -672,6 +1223,11 @@ impl Host {
     }
 
     pub async fn delete(self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
+        // Guests act as the default user, but they may not delete its hosts
+        if user.is_guest() {
+            return Err(AppError::Forbidden);
+        }
+
         let app = self.app.access()?;
 
         let host = app.storage.get_host(self.id).await?;
@@ -681,6 +1237,11 @@ impl Host {
                 let mut app_images = app.app_image_cache.write().await;
                 app_images.retain(|(_, host_id, _), _| *host_id != self.id);
             }
+            {
+                let mut app_lists = app.app_lists.write().await;
+                app_lists.remove(&self.id);
+            }
+            app.host_clients.remove(self.id).await;
 
             drop(app);
             self.delete_no_auth().await
@@ -691,8 +1252,68 @@ impl Host {
     pub async fn delete_no_auth(self) -> Result<(), AppError> {
         let app = self.app.access()?;
 
+        let host = app.storage.get_host(self.id).await?;
+
         app.storage.remove_host(self.id).await?;
 
+        if let Some(pair_info) = host.pair_info
+            && let Err(err) = app.key_store.remove(&pair_info.client_private_key)
+        {
+            warn!("Failed to remove the client private key of host {self:?}: {err}");
+        }
+
         Ok(())
     }
 }
+
+/// The credentials stored for the host are preferred over the ones in the config
+fn sunshine_credentials(config: &Config, host: &StorageHost) -> Option<SunshineCredentials> {
+    match &host.sunshine_credentials {
+        Some(credentials) => Some(SunshineCredentials {
+            username: credentials.username.clone(),
+            password: credentials.password.clone(),
+            web_ui_port: credentials.web_ui_port,
+        }),
+        None => config.moonlight.sunshine_credentials(host.id.0).cloned(),
+    }
+}
+
+fn is_rotation_due(config: &Config, validity: &CertificateValidity, now: SystemTime) -> bool {
+    let rotate_before = Duration::from_secs(
+        config
+            .moonlight
+            .client_certificate
+            .rotate_days_before_expiry as u64
+            * SECS_PER_DAY,
+    );
+
+    validity.remaining(now) <= rotate_before
+}
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+fn days(duration: Duration) -> u32 {
+    (duration.as_secs() / SECS_PER_DAY) as u32
+}
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Only hosts which were paired before, the web ui is verified with their stored server certificate
+fn auto_submit_credentials(
+    config: &Config,
+    host: &StorageHost,
+) -> Option<(SunshineCredentials, Pem)> {
+    if !config.moonlight.auto_submit_pair_pin {
+        return None;
+    }
+
+    let pair_info = host.pair_info.as_ref()?;
+
+    Some((
+        sunshine_credentials(config, host)?,
+        pair_info.server_certificate.clone(),
+    ))
+}
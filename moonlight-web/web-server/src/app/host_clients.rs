@@ -0,0 +1,119 @@
+//! Keeps one [MoonlightClient] per host, so the requests to a host reuse the pooled connections of its client.
+
+use std::collections::HashMap;
+
+use common::api_bindings::HostClientStats;
+use pem::Pem;
+use tokio::sync::Mutex;
+
+use crate::app::{AppError, MoonlightClient, host::HostId};
+
+#[derive(Default)]
+pub struct HostClients {
+    clients: Mutex<HashMap<HostId, HostClient>>,
+}
+
+struct HostClient {
+    /// The client and server certificate of a paired host, the client must be recreated when they change
+    certificates: Option<(Pem, Pem)>,
+    client: MoonlightClient,
+    stats: HostClientStats,
+}
+
+impl HostClients {
+    /// Returns the kept client of the host or creates it if the certificates changed
+    pub async fn client(
+        &self,
+        host_id: HostId,
+        certificates: Option<(&Pem, &Pem)>,
+        create: impl FnOnce() -> Result<MoonlightClient, AppError>,
+    ) -> Result<MoonlightClient, AppError> {
+        let certificates = certificates.map(|(client, server)| (client.clone(), server.clone()));
+
+        let mut clients = self.clients.lock().await;
+
+        let stats = match clients.get_mut(&host_id) {
+            Some(host_client) if host_client.certificates == certificates => {
+                host_client.stats.requests += 1;
+                return Ok(host_client.client.clone());
+            }
+            Some(host_client) => host_client.stats.clone(),
+            None => HostClientStats {
+                host_id: host_id.0,
+                ..Default::default()
+            },
+        };
+
+        let client = create()?;
+        clients.insert(
+            host_id,
+            HostClient {
+                certificates,
+                client: client.clone(),
+                stats: HostClientStats {
+                    clients_created: stats.clients_created + 1,
+                    requests: stats.requests + 1,
+                    ..stats
+                },
+            },
+        );
+
+        Ok(client)
+    }
+
+    pub async fn remove(&self, host_id: HostId) {
+        self.clients.lock().await.remove(&host_id);
+    }
+
+    pub async fn stats(&self) -> Vec<HostClientStats> {
+        let clients = self.clients.lock().await;
+
+        let mut stats = clients
+            .values()
+            .map(|host_client| host_client.stats.clone())
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|stats| stats.host_id);
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use moonlight_common::network::request_client::{RequestClient, RequestClientOptions};
+
+    use crate::app::{MoonlightClient, host::HostId, host_clients::HostClients};
+
+    #[actix_web::test]
+    async fn test_reuses_client() {
+        let clients = HostClients::default();
+
+        for _ in 0..3 {
+            clients
+                .client(HostId(1), None, || {
+                    Ok(
+                        MoonlightClient::with_defaults(&RequestClientOptions::default())
+                            .expect("client"),
+                    )
+                })
+                .await
+                .expect("client");
+        }
+        clients
+            .client(HostId(2), None, || {
+                Ok(
+                    MoonlightClient::with_defaults(&RequestClientOptions::default())
+                        .expect("client"),
+                )
+            })
+            .await
+            .expect("client");
+
+        let stats = clients.stats().await;
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].host_id, 1);
+        assert_eq!(stats[0].clients_created, 1);
+        assert_eq!(stats[0].requests, 3);
+        assert_eq!(stats[1].clients_created, 1);
+    }
+}
@@ -1,35 +1,82 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     io,
     ops::Deref,
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use actix_web::{ResponseError, http::StatusCode, web::Bytes};
-use common::config::Config;
+use actix_web::{ResponseError, http::StatusCode};
+use actix_ws::{MessageStream, Session};
+use bytes::Bytes;
+use common::{
+    api_bindings::{
+        BuildInfo, HostClientStats, PushNotification, PushNotificationKind, ScreenshotFormat,
+        StreamerCapabilities,
+    },
+    config::{Config, KeyStoreConfig},
+    ipc::{HlsFile, IpcSender, ServerIpcMessage},
+};
 use hex::FromHexError;
 use log::{error, warn};
 use moonlight_common::{
-    network::{ApiError, backend::reqwest::ReqwestClient, request_client::RequestClient},
+    high::AppListWatcher,
+    key_store::{FileKeyStore, KeyStore, KeyStoreError, PemKeyStore},
+    network::{
+        ApiError,
+        backend::reqwest::ReqwestClient,
+        request_client::{CancellationToken, RequestClient},
+    },
     pair::PairError,
 };
-use openssl::error::ErrorStack;
+use openssl::{error::ErrorStack, memcmp, rand::rand_bytes};
+use pem::Pem;
 use thiserror::Error;
-use tokio::sync::RwLock;
-
-use crate::app::{
-    auth::{SessionToken, UserAuth},
-    host::{AppId, HostId},
-    password::StoragePassword,
-    storage::{Either, Storage, StorageHostModify, StorageUserAdd, create_storage},
-    user::{Admin, AuthenticatedUser, Role, User, UserId},
+use tokio::{
+    spawn,
+    sync::{Mutex, OnceCell, RwLock, oneshot, watch},
+    time::timeout,
+};
+
+use crate::{
+    app::{
+        auth::{SessionToken, UserAuth},
+        certificate_rotation::run_certificate_rotation,
+        diagnostics::{MAX_SESSION_DIAGNOSTICS, SessionDiagnostics, SessionId},
+        host::{AppId, AppImage, Host, HostId},
+        host_clients::HostClients,
+        password::StoragePassword,
+        schedule::run_wake_schedules,
+        storage::{
+            Storage, StorageHostModify, StorageUserAdd, create_storage,
+            query::{StorageCursor, StorageHostFilter, StoragePagination, StorageUserFilter},
+        },
+        sunshine_api::SunshineApiError,
+        tailscale::TailscaleError,
+        user::{Admin, AuthenticatedUser, Role, User, UserId},
+        web_push::{PushRecipients, WebPush, WebPushError, host_name},
+    },
+    remote_streamer::RemoteStreamers,
+    streamer::{SpawnedStreamer, StreamerPool, query_streamer_build_info},
 };
 
 pub mod auth;
+mod certificate_rotation;
+pub mod codec;
+pub mod diagnostics;
 pub mod host;
+pub mod host_clients;
 pub mod password;
+mod schedule;
 pub mod storage;
+pub mod stream_limits;
+pub mod sunshine_api;
+pub mod tailscale;
 pub mod user;
+pub mod web_push;
 
 #[derive(Debug, Error)]
 pub enum AppError {
@@ -45,12 +92,44 @@ pub enum AppError {
     UserAlreadyExists,
     #[error("the host was not found")]
     HostNotFound,
+    #[error("the host already exists")]
+    HostAlreadyExists,
     #[error("the host was already paired")]
     HostPaired,
     #[error("the host must be paired for this action")]
     HostNotPaired,
     #[error("the host was offline, but the action requires that the host is online")]
     HostOffline,
+    #[error("another app is already running on the host")]
+    HostBusy,
+    #[error("no sunshine credentials are configured for the host")]
+    SunshineCredentialsMissing,
+    #[error("the input macro was not found")]
+    InputMacroNotFound,
+    #[error("the user has too many input macros")]
+    InputMacroLimitReached,
+    #[error("the stream session was not found")]
+    StreamSessionNotFound,
+    #[error("the streamer failed to take a screenshot")]
+    ScreenshotFailed,
+    #[error("the streamer didn't receive an IDR frame in time for the screenshot")]
+    ScreenshotTimeout,
+    #[error("the streamer didn't send a thumbnail of the session yet")]
+    ThumbnailNotFound,
+    #[error("the hls output is disabled")]
+    HlsOutputDisabled,
+    #[error("the file of the hls output was not found")]
+    HlsFileNotFound,
+    #[error("the streamer didn't answer the hls request in time")]
+    HlsTimeout,
+    #[error("the installed streamer was built without support for this")]
+    NotSupportedByStreamer,
+    #[error("web push notifications are disabled")]
+    WebPushDisabled,
+    #[error("the push subscription was not found")]
+    PushSubscriptionNotFound,
+    #[error("the user has too many push subscriptions")]
+    PushSubscriptionLimitReached,
     // -- Unauthorized
     #[error("the credentials don't exists")]
     CredentialsWrong,
@@ -87,6 +166,14 @@ pub enum AppError {
     MoonlightApi(#[from] ApiError<<MoonlightClient as RequestClient>::Error>),
     #[error("pairing error: {0}")]
     Pairing(#[from] PairError<<MoonlightClient as RequestClient>::Error>),
+    #[error("sunshine api error: {0}")]
+    SunshineApi(#[from] SunshineApiError),
+    #[error("tailscale error: {0}")]
+    Tailscale(#[from] TailscaleError),
+    #[error("key store error: {0}")]
+    KeyStore(#[from] KeyStoreError),
+    #[error("web push error: {0}")]
+    WebPush(#[from] WebPushError),
 }
 
 impl ResponseError for AppError {
@@ -99,8 +186,24 @@ impl ResponseError for AppError {
             Self::HostNotPaired => StatusCode::FORBIDDEN,
             Self::HostPaired => StatusCode::NOT_MODIFIED,
             Self::HostOffline => StatusCode::GATEWAY_TIMEOUT,
+            Self::HostBusy => StatusCode::CONFLICT,
+            Self::SunshineCredentialsMissing => StatusCode::PRECONDITION_FAILED,
             Self::UserNotFound => StatusCode::NOT_FOUND,
+            Self::InputMacroNotFound => StatusCode::NOT_FOUND,
+            Self::InputMacroLimitReached => StatusCode::CONFLICT,
+            Self::StreamSessionNotFound => StatusCode::NOT_FOUND,
+            Self::ScreenshotFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ScreenshotTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::ThumbnailNotFound => StatusCode::NOT_FOUND,
+            Self::HlsOutputDisabled => StatusCode::PRECONDITION_FAILED,
+            Self::HlsFileNotFound => StatusCode::NOT_FOUND,
+            Self::HlsTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::NotSupportedByStreamer => StatusCode::NOT_IMPLEMENTED,
+            Self::WebPushDisabled => StatusCode::PRECONDITION_FAILED,
+            Self::PushSubscriptionNotFound => StatusCode::NOT_FOUND,
+            Self::PushSubscriptionLimitReached => StatusCode::CONFLICT,
             Self::UserAlreadyExists => StatusCode::CONFLICT,
+            Self::HostAlreadyExists => StatusCode::CONFLICT,
             Self::CredentialsWrong => StatusCode::UNAUTHORIZED,
             Self::SessionTokenNotFound => StatusCode::UNAUTHORIZED,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
@@ -117,6 +220,16 @@ impl ResponseError for AppError {
             Self::MoonlightApi(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Pairing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::SunshineApi(_) => StatusCode::BAD_GATEWAY,
+            Self::Tailscale(TailscaleError::Disabled | TailscaleError::Unsupported) => {
+                StatusCode::PRECONDITION_FAILED
+            }
+            Self::Tailscale(_) => StatusCode::BAD_GATEWAY,
+            Self::KeyStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::WebPush(
+                WebPushError::InvalidSubscription | WebPushError::PushServiceNotAllowed,
+            ) => StatusCode::BAD_REQUEST,
+            Self::WebPush(_) => StatusCode::BAD_GATEWAY,
         }
     }
 }
@@ -135,26 +248,288 @@ impl AppRef {
 struct AppInner {
     config: Config,
     storage: Arc<dyn Storage + Send + Sync>,
-    app_image_cache: RwLock<HashMap<(UserId, HostId, AppId), Bytes>>,
+    key_store: Arc<dyn KeyStore>,
+    app_image_cache: RwLock<HashMap<(UserId, HostId, AppId), AppImage>>,
+    /// The last app list of every host, to notice apps which changed on the host
+    app_lists: RwLock<HashMap<HostId, AppListWatcher>>,
+    host_clients: HostClients,
+    /// The users which are currently streaming from a host through this web server
+    active_streams: RwLock<HashMap<HostId, ActiveStream>>,
+    /// Streams whose client web socket dropped, by their resume token
+    resumable_streams: Mutex<HashMap<String, ResumableStream>>,
+    /// The newest sessions, including finished ones
+    session_diagnostics: RwLock<VecDeque<Arc<SessionDiagnostics>>>,
+    next_session_id: AtomicU32,
+    streamer_pool: Arc<StreamerPool>,
+    /// Shared with the tenants, none if remote streamers are disabled
+    remote_streamers: Option<Arc<RemoteStreamers>>,
+    /// Queried once, none if the streamer couldn't be started
+    streamer_build_info: OnceCell<Option<BuildInfo>>,
+    /// None if web push is disabled
+    web_push: Option<WebPush>,
+    /// The users which got told that a host is busy, notified once its stream ends
+    host_available_waiters: Mutex<HashMap<HostId, HashSet<UserId>>>,
+}
+
+impl AppInner {
+    /// The storage only contains what the key store returned, this loads the actual key
+    fn client_private_key(&self, stored: &Pem) -> Result<Pem, AppError> {
+        Ok(self.key_store.load(stored)?)
+    }
+}
+
+pub fn create_key_store(config: &KeyStoreConfig) -> Result<Arc<dyn KeyStore>, anyhow::Error> {
+    match config {
+        KeyStoreConfig::Pem => Ok(Arc::new(PemKeyStore)),
+        KeyStoreConfig::File { directory } => Ok(Arc::new(FileKeyStore::new(directory)?)),
+        #[cfg(feature = "keyring")]
+        KeyStoreConfig::Keyring { service } => Ok(Arc::new(
+            moonlight_common::key_store::KeyringKeyStore::new(service),
+        )),
+        #[cfg(not(feature = "keyring"))]
+        KeyStoreConfig::Keyring { .. } => Err(anyhow::anyhow!(
+            "the keyring key store requires the web server to be built with the \"keyring\" feature"
+        )),
+    }
 }
 
 pub type MoonlightClient = ReqwestClient;
 
+/// The host needs to encode an IDR frame and the streamer needs to decode it
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The streamer holds back blocking playlist reloads for up to 10 seconds
+const HLS_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const HLS_TOKEN_SIZE: usize = 24;
+
+#[derive(Debug, Clone)]
+pub struct ActiveStream {
+    pub user_id: UserId,
+    pub session_id: SessionId,
+    /// Controls the streamer process of the session
+    pub ipc_sender: IpcSender<ServerIpcMessage>,
+    /// Rated by the streamer from 1 to 5
+    pub quality_score: Option<u8>,
+    pub screenshots: ScreenshotWaiters,
+    pub preview: SessionPreview,
+    /// The latest jpeg thumbnail of the stream
+    pub thumbnail: Option<Bytes>,
+    /// The port of the rtsp output of the streamer
+    pub rtsp_port: Option<u16>,
+    pub hls: HlsPassthrough,
+}
+
+/// A stream which waits for its client to reconnect the web socket
+pub struct ResumableStream {
+    pub user_id: UserId,
+    /// Receives the new web socket
+    pub resume: oneshot::Sender<(Session, MessageStream)>,
+}
+
+/// Requests which wait for a [StreamerIpcMessage::Screenshot](common::ipc::StreamerIpcMessage::Screenshot), the streamer answers them in order
+#[derive(Debug, Clone, Default)]
+pub struct ScreenshotWaiters {
+    waiters: Arc<Mutex<VecDeque<oneshot::Sender<Option<Bytes>>>>>,
+}
+
+impl ScreenshotWaiters {
+    async fn wait(&self) -> oneshot::Receiver<Option<Bytes>> {
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.lock().await.push_back(sender);
+
+        receiver
+    }
+
+    pub async fn answer(&self, image: Option<Bytes>) {
+        let waiter = self.waiters.lock().await.pop_front();
+
+        // The request might've already timed out
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(image);
+        }
+    }
+}
+
+/// Forwards requests for the hls output of a session to its streamer, see [HlsOutputConfig](common::config::HlsOutputConfig)
+#[derive(Debug, Clone, Default)]
+pub struct HlsPassthrough {
+    inner: Arc<HlsPassthroughInner>,
+}
+
+#[derive(Debug, Default)]
+struct HlsPassthroughInner {
+    /// Cast receivers can't authenticate, so the urls of the hls output contain this token
+    token: Mutex<Option<String>>,
+    /// Requests which wait for their [StreamerIpcMessage::HlsFile](common::ipc::StreamerIpcMessage::HlsFile)
+    waiters: Mutex<HashMap<u32, oneshot::Sender<Option<HlsFile>>>>,
+    next_request_id: AtomicU32,
+}
+
+impl HlsPassthrough {
+    async fn token(&self) -> Result<String, AppError> {
+        let mut token = self.inner.token.lock().await;
+
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+
+        let mut bytes = [0; HLS_TOKEN_SIZE];
+        rand_bytes(&mut bytes)?;
+
+        Ok(token.insert(hex::encode(bytes)).clone())
+    }
+
+    async fn is_token(&self, token: &str) -> bool {
+        self.inner
+            .token
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|expected| {
+                expected.len() == token.len() && memcmp::eq(expected.as_bytes(), token.as_bytes())
+            })
+    }
+
+    async fn wait(&self) -> (u32, oneshot::Receiver<Option<HlsFile>>) {
+        let request_id = self.inner.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.inner.waiters.lock().await.insert(request_id, sender);
+
+        (request_id, receiver)
+    }
+
+    async fn answer(&self, request_id: u32, file: Option<HlsFile>) {
+        let waiter = self.inner.waiters.lock().await.remove(&request_id);
+
+        // The request might've already timed out
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(file);
+        }
+    }
+}
+
+/// The latest frame of the preview track of a session, see [StreamerIpcMessage::Preview](common::ipc::StreamerIpcMessage::Preview)
+#[derive(Debug, Clone, Default)]
+pub struct SessionPreview {
+    inner: Arc<SessionPreviewInner>,
+}
+
+#[derive(Debug)]
+struct SessionPreviewInner {
+    frames: watch::Sender<PreviewFrame>,
+    /// The streamer only decodes the preview while this isn't zero
+    watchers: AtomicUsize,
+}
+
+impl Default for SessionPreviewInner {
+    fn default() -> Self {
+        Self {
+            frames: watch::Sender::new(PreviewFrame::Waiting),
+            watchers: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PreviewFrame {
+    Waiting,
+    Image(Bytes),
+    /// The streamer stopped the preview because it can't decode the stream
+    Failed,
+}
+
+impl SessionPreview {
+    pub fn publish(&self, image: Option<Bytes>) {
+        self.inner.frames.send_replace(match image {
+            Some(image) => PreviewFrame::Image(image),
+            None => PreviewFrame::Failed,
+        });
+    }
+}
+
+/// Receives the frames of a [SessionPreview], the streamer stops the preview once the last watcher is dropped
+pub struct PreviewWatcher {
+    frames: watch::Receiver<PreviewFrame>,
+    // Not an Arc, so the frames end together with the session
+    preview: Weak<SessionPreviewInner>,
+    ipc_sender: IpcSender<ServerIpcMessage>,
+}
+
+impl PreviewWatcher {
+    /// None once the session or the preview ended
+    pub async fn next_frame(&mut self) -> Option<Bytes> {
+        loop {
+            self.frames.changed().await.ok()?;
+
+            match &*self.frames.borrow_and_update() {
+                PreviewFrame::Waiting => {}
+                PreviewFrame::Image(image) => return Some(image.clone()),
+                PreviewFrame::Failed => return None,
+            }
+        }
+    }
+}
+
+impl Drop for PreviewWatcher {
+    fn drop(&mut self) {
+        let Some(preview) = self.preview.upgrade() else {
+            return;
+        };
+
+        if preview.watchers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let mut ipc_sender = self.ipc_sender.clone();
+            spawn(async move {
+                ipc_sender.send(ServerIpcMessage::StopPreview).await;
+            });
+        }
+    }
+}
+
 pub struct App {
     inner: Arc<AppInner>,
 }
 
 impl App {
-    pub async fn new(config: Config) -> Result<Self, anyhow::Error> {
+    pub async fn new(
+        config: Config,
+        streamer_pool: Arc<StreamerPool>,
+        remote_streamers: Option<Arc<RemoteStreamers>>,
+    ) -> Result<Self, anyhow::Error> {
+        let web_push = if config.web_push.enabled {
+            Some(WebPush::load(config.web_push.clone()).await?)
+        } else {
+            None
+        };
+
         let app = AppInner {
             storage: create_storage(config.data_storage.clone()).await?,
+            key_store: create_key_store(&config.client_key_store)?,
+            streamer_pool,
+            remote_streamers,
             config,
             app_image_cache: Default::default(),
+            app_lists: Default::default(),
+            host_clients: Default::default(),
+            active_streams: Default::default(),
+            resumable_streams: Default::default(),
+            session_diagnostics: Default::default(),
+            next_session_id: AtomicU32::new(1),
+            streamer_build_info: OnceCell::new(),
+            web_push,
+            host_available_waiters: Default::default(),
         };
 
-        Ok(Self {
+        let app = Self {
             inner: Arc::new(app),
-        })
+        };
+
+        if !app.config().moonlight.host_wake_schedules.is_empty() {
+            spawn(run_wake_schedules(app.new_ref()));
+        }
+        if app.config().moonlight.client_certificate.auto_rotate {
+            spawn(run_certificate_rotation(app.new_ref()));
+        }
+
+        Ok(app)
     }
 
     fn new_ref(&self) -> AppRef {
@@ -231,6 +606,11 @@ impl App {
         self.add_user_no_auth(user).await
     }
 
+    /// How often the kept clients of the hosts were used
+    pub async fn host_client_stats(&self, _: &Admin) -> Vec<HostClientStats> {
+        self.inner.host_clients.stats().await
+    }
+
     async fn add_user_no_auth(&self, user: StorageUserAdd) -> Result<AuthenticatedUser, AppError> {
         if user.name.is_empty() {
             return Err(AppError::NameEmpty);
@@ -244,6 +624,7 @@ impl App {
                 id: user.id,
                 cache_storage: Some(user),
             },
+            is_guest: false,
         })
     }
 
@@ -343,35 +724,530 @@ impl App {
                 id: user_id,
                 cache_storage: user,
             },
+            is_guest: false,
         })
     }
 
-    pub async fn all_users(&self, _: Admin) -> Result<Vec<User>, AppError> {
-        let users = self.inner.storage.list_users().await?;
-
-        let users = match users {
-            Either::Left(user_ids) => user_ids
-                .into_iter()
-                .map(|id| User {
-                    app: self.new_ref(),
-                    id,
-                    cache_storage: None,
-                })
-                .collect::<Vec<_>>(),
-            Either::Right(users) => users
-                .into_iter()
-                .map(|user| User {
-                    app: self.new_ref(),
-                    id: user.id,
-                    cache_storage: Some(user),
-                })
-                .collect::<Vec<_>>(),
-        };
+    /// Returns the cursor of the next page if there are more users
+    pub async fn query_users(
+        &self,
+        _: Admin,
+        filter: StorageUserFilter,
+        pagination: StoragePagination,
+    ) -> Result<(Vec<User>, Option<StorageCursor>), AppError> {
+        let page = self.inner.storage.query_users(filter, pagination).await?;
 
-        Ok(users)
+        let users = page
+            .entries
+            .into_iter()
+            .map(|user| User {
+                app: self.new_ref(),
+                id: user.id,
+                cache_storage: Some(user),
+            })
+            .collect();
+
+        Ok((users, page.next_cursor))
+    }
+
+    /// All paired hosts, including the ones owned by other users
+    pub async fn paired_hosts(&self, _: &Admin) -> Result<Vec<Host>, AppError> {
+        let page = self
+            .inner
+            .storage
+            .query_hosts(
+                StorageHostFilter {
+                    paired: Some(true),
+                    ..Default::default()
+                },
+                StoragePagination::default(),
+            )
+            .await?;
+
+        Ok(page
+            .entries
+            .into_iter()
+            .map(|host| Host {
+                app: self.new_ref(),
+                id: host.id,
+                cache_storage: Some(host),
+                cache_host_info: None,
+                cancellation_token: CancellationToken::new(),
+            })
+            .collect())
     }
 
     pub async fn delete_session(&self, session: SessionToken) -> Result<(), AppError> {
         self.inner.storage.remove_session_token(session).await
     }
+
+    /// Starts a streamer on the streamer node closest to the host,
+    /// otherwise returns an idle streamer from the pool or spawns a new one
+    pub async fn take_streamer(&self, host_address: &str) -> Result<SpawnedStreamer, io::Error> {
+        if let Some(remote_streamers) = &self.inner.remote_streamers {
+            match remote_streamers.take(host_address).await {
+                Ok(Some(streamer)) => return Ok(streamer),
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("[Remote Streamer]: failed to start a streamer on a node: {err}");
+                }
+            }
+
+            if !self.config().remote_streamers.local_fallback {
+                return Err(io::Error::other(
+                    "no streamer node can stream from the host",
+                ));
+            }
+        }
+
+        self.inner.streamer_pool.take().await
+    }
+
+    /// The optional subsystems of the installed streamer
+    pub async fn streamer_capabilities(&self) -> Result<StreamerCapabilities, io::Error> {
+        self.inner.streamer_pool.capabilities().await
+    }
+
+    pub async fn streamer_build_info(&self) -> Option<BuildInfo> {
+        self.inner
+            .streamer_build_info
+            .get_or_init(|| async {
+                query_streamer_build_info(self.config())
+                    .await
+                    .inspect_err(|err| {
+                        warn!("[Server]: failed to query the streamer version: {err}")
+                    })
+                    .ok()
+            })
+            .await
+            .clone()
+    }
+
+    pub async fn active_stream_user(&self, host_id: HostId) -> Option<UserId> {
+        let active_streams = self.inner.active_streams.read().await;
+
+        active_streams
+            .get(&host_id)
+            .map(|active_stream| active_stream.user_id)
+    }
+    pub async fn active_stream(&self, host_id: HostId) -> Option<ActiveStream> {
+        let active_streams = self.inner.active_streams.read().await;
+
+        active_streams.get(&host_id).cloned()
+    }
+    pub async fn active_streams(&self) -> Vec<(HostId, ActiveStream)> {
+        let active_streams = self.inner.active_streams.read().await;
+
+        active_streams
+            .iter()
+            .map(|(host_id, active_stream)| (*host_id, active_stream.clone()))
+            .collect()
+    }
+    pub async fn set_active_stream(
+        &self,
+        host_id: HostId,
+        user_id: UserId,
+        session_id: SessionId,
+        ipc_sender: IpcSender<ServerIpcMessage>,
+        screenshots: ScreenshotWaiters,
+        preview: SessionPreview,
+    ) {
+        let mut active_streams = self.inner.active_streams.write().await;
+
+        // A restarted stream keeps its hls token, so cast receivers keep playing
+        let hls = active_streams
+            .get(&host_id)
+            .filter(|active_stream| active_stream.session_id == session_id)
+            .map(|active_stream| active_stream.hls.clone())
+            .unwrap_or_default();
+
+        active_streams.insert(
+            host_id,
+            ActiveStream {
+                user_id,
+                session_id,
+                ipc_sender,
+                quality_score: None,
+                screenshots,
+                preview,
+                thumbnail: None,
+                rtsp_port: None,
+                hls,
+            },
+        );
+    }
+    pub async fn set_active_stream_quality(
+        &self,
+        host_id: HostId,
+        session_id: SessionId,
+        score: u8,
+    ) {
+        let mut active_streams = self.inner.active_streams.write().await;
+
+        if let Some(active_stream) = active_streams.get_mut(&host_id)
+            && active_stream.session_id == session_id
+        {
+            active_stream.quality_score = Some(score);
+        }
+    }
+    pub async fn set_active_stream_thumbnail(
+        &self,
+        host_id: HostId,
+        session_id: SessionId,
+        image: Bytes,
+    ) {
+        let mut active_streams = self.inner.active_streams.write().await;
+
+        if let Some(active_stream) = active_streams.get_mut(&host_id)
+            && active_stream.session_id == session_id
+        {
+            active_stream.thumbnail = Some(image);
+        }
+    }
+    pub async fn set_active_stream_rtsp_port(
+        &self,
+        host_id: HostId,
+        session_id: SessionId,
+        port: u16,
+    ) {
+        let mut active_streams = self.inner.active_streams.write().await;
+
+        if let Some(active_stream) = active_streams.get_mut(&host_id)
+            && active_stream.session_id == session_id
+        {
+            active_stream.rtsp_port = Some(port);
+        }
+    }
+    pub async fn session_thumbnail(&self, session_id: SessionId) -> Result<Bytes, AppError> {
+        let active_stream = self
+            .session_active_stream(session_id)
+            .await
+            .ok_or(AppError::StreamSessionNotFound)?;
+
+        active_stream.thumbnail.ok_or(AppError::ThumbnailNotFound)
+    }
+    /// Starts recording the diagnostics of a new session, the oldest session is dropped if there are too many
+    pub async fn new_session_diagnostics(
+        &self,
+        host_id: HostId,
+        user_id: UserId,
+        privacy_mode: bool,
+    ) -> Arc<SessionDiagnostics> {
+        let id = SessionId(self.inner.next_session_id.fetch_add(1, Ordering::Relaxed));
+        let diagnostics = Arc::new(SessionDiagnostics::new(id, host_id, user_id, privacy_mode));
+
+        // Nothing is kept of private sessions once they ended
+        if diagnostics.is_private() {
+            return diagnostics;
+        }
+
+        let mut session_diagnostics = self.inner.session_diagnostics.write().await;
+        if session_diagnostics.len() >= MAX_SESSION_DIAGNOSTICS {
+            session_diagnostics.pop_front();
+        }
+        session_diagnostics.push_back(diagnostics.clone());
+
+        diagnostics
+    }
+    pub async fn session_diagnostics(
+        &self,
+        session_id: SessionId,
+    ) -> Result<Arc<SessionDiagnostics>, AppError> {
+        let session_diagnostics = self.inner.session_diagnostics.read().await;
+
+        session_diagnostics
+            .iter()
+            .find(|diagnostics| diagnostics.id() == session_id)
+            .cloned()
+            .ok_or(AppError::StreamSessionNotFound)
+    }
+    pub async fn add_resumable_stream(&self, token: String, stream: ResumableStream) {
+        self.inner
+            .resumable_streams
+            .lock()
+            .await
+            .insert(token, stream);
+    }
+    /// Only the user of the stream can resume it
+    pub async fn take_resumable_stream(
+        &self,
+        token: &str,
+        user_id: UserId,
+    ) -> Option<ResumableStream> {
+        let mut resumable_streams = self.inner.resumable_streams.lock().await;
+
+        if resumable_streams
+            .get(token)
+            .is_some_and(|stream| stream.user_id == user_id)
+        {
+            resumable_streams.remove(token)
+        } else {
+            None
+        }
+    }
+    pub async fn remove_resumable_stream(&self, token: &str) {
+        self.inner.resumable_streams.lock().await.remove(token);
+    }
+    /// Decodes the next IDR frame of an active session, only the user of the session can see it if a user id is given
+    pub async fn take_screenshot(
+        &self,
+        session_id: SessionId,
+        user_id: Option<UserId>,
+        format: ScreenshotFormat,
+    ) -> Result<Bytes, AppError> {
+        if !self.streamer_capabilities().await?.video_decoding {
+            return Err(AppError::NotSupportedByStreamer);
+        }
+
+        let mut active_stream = self
+            .session_active_stream(session_id)
+            .await
+            .filter(|active_stream| user_id.is_none_or(|user_id| active_stream.user_id == user_id))
+            .ok_or(AppError::StreamSessionNotFound)?;
+
+        let screenshot = active_stream.screenshots.wait().await;
+        active_stream
+            .ipc_sender
+            .send(ServerIpcMessage::TakeScreenshot { format })
+            .await;
+
+        match timeout(SCREENSHOT_TIMEOUT, screenshot).await {
+            Ok(Ok(Some(image))) => Ok(image),
+            Ok(_) => Err(AppError::ScreenshotFailed),
+            Err(_) => Err(AppError::ScreenshotTimeout),
+        }
+    }
+    /// Starts the preview of an active session if nobody watches it yet
+    pub async fn watch_preview(&self, session_id: SessionId) -> Result<PreviewWatcher, AppError> {
+        if !self.streamer_capabilities().await?.video_decoding {
+            return Err(AppError::NotSupportedByStreamer);
+        }
+
+        let mut active_stream = self
+            .session_active_stream(session_id)
+            .await
+            .ok_or(AppError::StreamSessionNotFound)?;
+
+        if self
+            .user_by_id(active_stream.user_id)
+            .await?
+            .privacy_mode()
+            .await?
+        {
+            return Err(AppError::Forbidden);
+        }
+
+        let preview = &active_stream.preview.inner;
+        let watcher = PreviewWatcher {
+            frames: preview.frames.subscribe(),
+            preview: Arc::downgrade(preview),
+            ipc_sender: active_stream.ipc_sender.clone(),
+        };
+
+        if preview.watchers.fetch_add(1, Ordering::AcqRel) == 0 {
+            // A previous preview might've failed
+            preview.frames.send_replace(PreviewFrame::Waiting);
+
+            active_stream
+                .ipc_sender
+                .send(ServerIpcMessage::StartPreview(
+                    self.config().session_preview.clone(),
+                ))
+                .await;
+        }
+
+        Ok(watcher)
+    }
+    /// The token for the hls urls of a session, admins can also cast the sessions of other users which aren't private
+    pub async fn session_hls_token(
+        &self,
+        session_id: SessionId,
+        user_id: UserId,
+        is_admin: bool,
+    ) -> Result<String, AppError> {
+        if !self.config().hls_output.enabled {
+            return Err(AppError::HlsOutputDisabled);
+        }
+        if !self.streamer_capabilities().await?.hls_output {
+            return Err(AppError::NotSupportedByStreamer);
+        }
+
+        let active_stream = self
+            .session_active_stream(session_id)
+            .await
+            .ok_or(AppError::StreamSessionNotFound)?;
+
+        if active_stream.user_id != user_id {
+            if !is_admin {
+                return Err(AppError::StreamSessionNotFound);
+            }
+            if self
+                .user_by_id(active_stream.user_id)
+                .await?
+                .privacy_mode()
+                .await?
+            {
+                return Err(AppError::Forbidden);
+            }
+        }
+
+        active_stream.hls.token().await
+    }
+    /// Requests a playlist or segment of the hls output from the streamer of the session
+    pub async fn session_hls_file(
+        &self,
+        session_id: SessionId,
+        token: &str,
+        path: String,
+        msn: Option<u64>,
+        part: Option<u32>,
+    ) -> Result<HlsFile, AppError> {
+        let mut active_stream = self
+            .session_active_stream(session_id)
+            .await
+            .ok_or(AppError::StreamSessionNotFound)?;
+
+        // Don't tell apart sessions which don't exist and wrong tokens
+        if !active_stream.hls.is_token(token).await {
+            return Err(AppError::StreamSessionNotFound);
+        }
+
+        let (request_id, file) = active_stream.hls.wait().await;
+        active_stream
+            .ipc_sender
+            .send(ServerIpcMessage::HlsRequest {
+                request_id,
+                path,
+                msn,
+                part,
+            })
+            .await;
+
+        match timeout(HLS_REQUEST_TIMEOUT, file).await {
+            Ok(Ok(Some(file))) => Ok(file),
+            Ok(_) => Err(AppError::HlsFileNotFound),
+            Err(_) => {
+                // Nobody answers this request anymore
+                active_stream.hls.answer(request_id, None).await;
+                Err(AppError::HlsTimeout)
+            }
+        }
+    }
+    pub async fn answer_hls_request(
+        &self,
+        host_id: HostId,
+        session_id: SessionId,
+        request_id: u32,
+        file: Option<HlsFile>,
+    ) {
+        let hls = {
+            let active_streams = self.inner.active_streams.read().await;
+
+            active_streams
+                .get(&host_id)
+                .filter(|active_stream| active_stream.session_id == session_id)
+                .map(|active_stream| active_stream.hls.clone())
+        };
+
+        if let Some(hls) = hls {
+            hls.answer(request_id, file).await;
+        }
+    }
+    async fn session_active_stream(&self, session_id: SessionId) -> Option<ActiveStream> {
+        let active_streams = self.inner.active_streams.read().await;
+
+        active_streams
+            .values()
+            .find(|active_stream| active_stream.session_id == session_id)
+            .cloned()
+    }
+    /// Tells the streamer of the session to stop, returns false if the session already ended
+    pub async fn stop_active_stream(&self, host_id: HostId, session_id: SessionId) -> bool {
+        let active_stream = {
+            let mut active_streams = self.inner.active_streams.write().await;
+
+            match active_streams.get(&host_id) {
+                Some(active_stream) if active_stream.session_id == session_id => {
+                    active_streams.remove(&host_id)
+                }
+                _ => None,
+            }
+        };
+
+        let Some(mut active_stream) = active_stream else {
+            return false;
+        };
+        active_stream.ipc_sender.send(ServerIpcMessage::Stop).await;
+
+        true
+    }
+    /// Only removes the stream if it's still this session, it might've been taken over
+    pub async fn remove_active_stream(&self, host_id: HostId, session_id: SessionId) {
+        let mut active_streams = self.inner.active_streams.write().await;
+
+        if active_streams
+            .get(&host_id)
+            .is_some_and(|active_stream| active_stream.session_id == session_id)
+        {
+            active_streams.remove(&host_id);
+            drop(active_streams);
+
+            self.notify_host_available(host_id).await;
+        }
+    }
+
+    /// The key with which browsers subscribe to push notifications
+    pub fn web_push_public_key(&self) -> Result<String, AppError> {
+        let web_push = self
+            .inner
+            .web_push
+            .as_ref()
+            .ok_or(AppError::WebPushDisabled)?;
+
+        Ok(web_push.public_key())
+    }
+
+    pub fn notify_user(&self, user_id: UserId, notification: PushNotification) {
+        self.new_ref()
+            .push(PushRecipients::User(user_id), notification);
+    }
+
+    pub fn notify_admins(&self, notification: PushNotification) {
+        self.new_ref().push(PushRecipients::Admins, notification);
+    }
+
+    /// The user will be notified once the stream which currently occupies the host ends
+    pub async fn notify_when_host_available(&self, host_id: HostId, user_id: UserId) {
+        if self.inner.web_push.is_none() {
+            return;
+        }
+
+        let mut waiters = self.inner.host_available_waiters.lock().await;
+        waiters.entry(host_id).or_default().insert(user_id);
+    }
+
+    async fn notify_host_available(&self, host_id: HostId) {
+        let Some(user_ids) = self
+            .inner
+            .host_available_waiters
+            .lock()
+            .await
+            .remove(&host_id)
+        else {
+            return;
+        };
+
+        let name = host_name(&self.inner, host_id).await;
+        for user_id in user_ids {
+            self.notify_user(
+                user_id,
+                PushNotification {
+                    kind: PushNotificationKind::HostAvailable,
+                    title: format!("{name} is available"),
+                    body: format!("The stream on {name} ended, you can start yours now."),
+                    host_id: Some(host_id.0),
+                },
+            );
+        }
+    }
 }
@@ -0,0 +1,194 @@
+//! Wakes hosts at the times of their [HostWakeSchedule] and launches the scheduled app.
+
+use std::time::Duration;
+
+use chrono::{Datelike, Days, Local, NaiveDateTime};
+use common::{
+    api_bindings::{PushNotification, PushNotificationKind},
+    config::{HostWakeSchedule, ScheduledAppLaunch},
+};
+use log::{info, warn};
+use moonlight_common::network::launch::AudioRouting;
+use tokio::{spawn, time::interval};
+
+use crate::app::{
+    AppError, AppRef,
+    host::{AppId, HostId},
+    user::{AuthenticatedUser, User, UserId},
+    web_push::{PushRecipients, host_name},
+};
+
+/// The schedules are checked this often, so they might run this much later
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Runs until the app is destroyed
+pub(super) async fn run_wake_schedules(app: AppRef) {
+    let mut check_interval = interval(SCHEDULE_CHECK_INTERVAL);
+    let mut last_check = Local::now().naive_local();
+
+    loop {
+        check_interval.tick().await;
+        let now = Local::now().naive_local();
+
+        let Ok(inner) = app.access() else {
+            return;
+        };
+
+        for (host_id, schedules) in &inner.config.moonlight.host_wake_schedules {
+            for schedule in schedules {
+                if next_occurrence(schedule, last_check).is_some_and(|next| next <= now) {
+                    spawn(run_schedule(
+                        app.clone(),
+                        HostId(*host_id),
+                        schedule.clone(),
+                    ));
+                }
+            }
+        }
+        drop(inner);
+
+        last_check = now;
+    }
+}
+
+/// The first time after `after` at which the schedule runs
+fn next_occurrence(schedule: &HostWakeSchedule, after: NaiveDateTime) -> Option<NaiveDateTime> {
+    (0..=7)
+        .filter_map(|days| after.date().checked_add_days(Days::new(days)))
+        .filter(|date| schedule.weekdays.is_empty() || schedule.weekdays.contains(&date.weekday()))
+        .map(|date| date.and_time(schedule.time))
+        .find(|time| *time > after)
+}
+
+async fn run_schedule(app: AppRef, host_id: HostId, schedule: HostWakeSchedule) {
+    info!(
+        "[Schedule]: waking host {host_id:?} for the schedule at {}",
+        schedule.time
+    );
+
+    if let Err(err) = wake_and_launch(&app, host_id, &schedule).await {
+        warn!("[Schedule]: failed to run the schedule of host {host_id:?}: {err}");
+
+        let name = match app.access() {
+            Ok(inner) => host_name(&inner, host_id).await,
+            Err(_) => return,
+        };
+        app.push(
+            PushRecipients::Admins,
+            PushNotification {
+                kind: PushNotificationKind::AdminAttention,
+                title: "A wake schedule failed".to_string(),
+                body: format!("The schedule at {} of {name} failed: {err}", schedule.time),
+                host_id: Some(host_id.0),
+            },
+        );
+    }
+}
+
+async fn wake_and_launch(
+    app: &AppRef,
+    host_id: HostId,
+    schedule: &HostWakeSchedule,
+) -> Result<(), AppError> {
+    let user_id = UserId(schedule.user_id);
+    let storage_user = app.access()?.storage.get_user(user_id).await?;
+
+    // The schedule was configured by an admin, so it acts as the user without credentials
+    let mut user = AuthenticatedUser {
+        inner: User {
+            app: app.clone(),
+            id: user_id,
+            cache_storage: Some(storage_user),
+        },
+        is_guest: false,
+    };
+    let mut host = user.host(host_id).await?;
+
+    host.wake(&mut user).await?;
+
+    let Some(ScheduledAppLaunch {
+        app_id,
+        width,
+        height,
+        fps,
+        hdr,
+        boot_timeout,
+    }) = schedule.launch_app.clone()
+    else {
+        return Ok(());
+    };
+
+    host.wait_online(&mut user, boot_timeout).await?;
+
+    let launched = host
+        .launch_app(
+            &mut user,
+            AppId(app_id),
+            width,
+            height,
+            fps,
+            hdr,
+            AudioRouting::Host,
+        )
+        .await?;
+
+    if launched {
+        info!("[Schedule]: launched app {app_id} on host {host_id:?}");
+    } else {
+        info!("[Schedule]: app {app_id} was already running on host {host_id:?}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+    use common::config::HostWakeSchedule;
+
+    use crate::app::schedule::next_occurrence;
+
+    fn schedule(time: &str, weekdays: Vec<Weekday>) -> HostWakeSchedule {
+        HostWakeSchedule {
+            time: time.parse().unwrap(),
+            weekdays,
+            user_id: 1,
+            launch_app: None,
+        }
+    }
+
+    fn date_time(year: i32, month: u32, day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_time(time.parse::<NaiveTime>().unwrap())
+    }
+
+    #[test]
+    fn test_every_day() {
+        let schedule = schedule("18:30", vec![]);
+
+        assert_eq!(
+            next_occurrence(&schedule, date_time(2025, 3, 5, "12:00")),
+            Some(date_time(2025, 3, 5, "18:30"))
+        );
+        assert_eq!(
+            next_occurrence(&schedule, date_time(2025, 3, 5, "18:30")),
+            Some(date_time(2025, 3, 6, "18:30"))
+        );
+    }
+
+    #[test]
+    fn test_weekdays() {
+        // 2025-03-05 is a Wednesday
+        let schedule = schedule("09:00", vec![Weekday::Sat, Weekday::Sun]);
+
+        assert_eq!(
+            next_occurrence(&schedule, date_time(2025, 3, 5, "12:00")),
+            Some(date_time(2025, 3, 8, "09:00"))
+        );
+        assert_eq!(
+            next_occurrence(&schedule, date_time(2025, 3, 9, "10:00")),
+            Some(date_time(2025, 3, 15, "09:00"))
+        );
+    }
+}
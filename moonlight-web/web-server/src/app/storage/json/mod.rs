@@ -1,20 +1,23 @@
 use std::{
-    collections::HashMap,
-    io::ErrorKind,
+    collections::{BTreeMap, HashMap},
+    io::{self, ErrorKind},
     path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
 
+use actix_web::web::Bytes;
 use anyhow::anyhow;
 use async_trait::async_trait;
+use common::ipc::InputMacroEvent;
 use futures::future::join_all;
 use log::{debug, error};
+use moonlight_common::units::{Fps, Kbps};
 use openssl::rand::rand_bytes;
 use tokio::{
     fs, spawn,
     sync::{
-        RwLock,
+        Mutex, RwLock,
         mpsc::{self, Receiver, Sender, error::TrySendError},
         oneshot,
     },
@@ -25,14 +28,22 @@ use tokio::{
 use crate::app::{
     AppError,
     auth::SessionToken,
-    host::HostId,
+    host::{AppId, HostId},
     password::StoragePassword,
     storage::{
-        Either, Storage, StorageHost, StorageHostAdd, StorageHostCache, StorageHostModify,
-        StorageHostPairInfo, StorageQueryHosts, StorageUser, StorageUserAdd, StorageUserModify,
+        Either, Storage, StorageAppStreamDefaults, StorageHost, StorageHostAdd, StorageHostCache,
+        StorageHostModify, StorageHostPairInfo, StorageInputMacro, StorageInputMacroAdd,
+        StoragePushSubscription, StorageQueryHosts, StorageStreamDefaults,
+        StorageSunshineCredentials, StorageUser, StorageUserAdd, StorageUserData,
+        StorageUserModify, StorageUserStreamLimits,
         json::versions::{
-            Json, V2, V2Host, V2HostCache, V2HostPairInfo, V2User, V2UserPassword,
-            migrate_to_latest,
+            Json, V2, V2Host, V2HostCache, V2HostPairInfo, V2HostSunshineCredentials, V2InputMacro,
+            V2InputMacroEvent, V2PushSubscription, V2StreamDefaults, V2User, V2UserPassword,
+            V2UserStreamLimits, migrate_to_latest,
+        },
+        query::{
+            StorageHostFilter, StoragePage, StoragePagination, StorageUserFilter, host_cursor,
+            user_cursor,
         },
     },
     user::UserId,
@@ -44,6 +55,7 @@ mod versions;
 pub struct JsonStorage {
     file: PathBuf,
     store_sender: Sender<()>,
+    write_lock: Mutex<()>,
     session_expiration_checker: JoinHandle<()>,
     users: RwLock<HashMap<u32, RwLock<V2User>>>,
     hosts: RwLock<HashMap<u32, RwLock<V2Host>>>,
@@ -100,6 +112,7 @@ impl JsonStorage {
         let this = Self {
             file,
             store_sender,
+            write_lock: Default::default(),
             session_expiration_checker,
             hosts: Default::default(),
             users: Default::default(),
@@ -168,7 +181,7 @@ impl JsonStorage {
 
         Ok(())
     }
-    async fn store(&self) {
+    async fn store(&self) -> Result<(), io::Error> {
         let json = {
             let users = self.users.read().await;
             let hosts = self.hosts.read().await;
@@ -193,17 +206,11 @@ impl JsonStorage {
             })
         };
 
-        let text = match serde_json::to_string_pretty(&json) {
-            Ok(text) => text,
-            Err(err) => {
-                error!("Failed to serialize data to json: {err:?}");
-                return;
-            }
-        };
+        let text = serde_json::to_string_pretty(&json).map_err(io::Error::other)?;
 
-        if let Err(err) = fs::write(&self.file, text).await {
-            error!("Failed to write data to file: {err:?}");
-        }
+        // The file writer and flush might store at the same time
+        let _write_guard = self.write_lock.lock().await;
+        fs::write(&self.file, text).await
     }
 }
 
@@ -213,7 +220,9 @@ async fn file_writer(mut store_receiver: Receiver<()>, json: Arc<JsonStorage>) {
             return;
         }
 
-        json.store().await;
+        if let Err(err) = json.store().await {
+            error!("Failed to write data to file: {err:?}");
+        }
     }
 }
 
@@ -227,6 +236,45 @@ fn user_from_json(user_id: UserId, user: &V2User) -> StorageUser {
         }),
         role: user.role,
         client_unique_id: user.client_unique_id.clone(),
+        stream_limits: StorageUserStreamLimits {
+            max_bitrate: user.stream_limits.max_bitrate.map(Kbps),
+            max_width: user.stream_limits.max_width,
+            max_height: user.stream_limits.max_height,
+        },
+        privacy_mode: user.privacy_mode,
+    }
+}
+
+fn stream_limits_to_json(limits: StorageUserStreamLimits) -> V2UserStreamLimits {
+    V2UserStreamLimits {
+        max_bitrate: limits.max_bitrate.map(Kbps::get),
+        max_width: limits.max_width,
+        max_height: limits.max_height,
+    }
+}
+
+fn stream_defaults_from_json(defaults: &V2StreamDefaults) -> StorageStreamDefaults {
+    StorageStreamDefaults {
+        bitrate: Kbps(defaults.bitrate),
+        fps: Fps(defaults.fps),
+        width: defaults.width,
+        height: defaults.height,
+        video_format: defaults.video_format,
+    }
+}
+fn stream_defaults_to_json(
+    host_id: HostId,
+    app_id: AppId,
+    defaults: StorageStreamDefaults,
+) -> V2StreamDefaults {
+    V2StreamDefaults {
+        host_id: host_id.0,
+        app_id: app_id.0,
+        bitrate: defaults.bitrate.get(),
+        fps: defaults.fps.get(),
+        width: defaults.width,
+        height: defaults.height,
+        video_format: defaults.video_format,
     }
 }
 
@@ -245,6 +293,55 @@ fn host_from_json(host_id: HostId, host: &V2Host) -> StorageHost {
             name: host.cache.name.clone(),
             mac: host.cache.mac,
         },
+        notes: host.notes.clone(),
+        labels: host.labels.clone(),
+        sunshine_credentials: host.sunshine_credentials.clone().map(|credentials| {
+            StorageSunshineCredentials {
+                username: credentials.username,
+                password: credentials.password,
+                web_ui_port: credentials.web_ui_port,
+            }
+        }),
+    }
+}
+
+fn sunshine_credentials_to_json(
+    credentials: StorageSunshineCredentials,
+) -> V2HostSunshineCredentials {
+    V2HostSunshineCredentials {
+        username: credentials.username,
+        password: credentials.password,
+        web_ui_port: credentials.web_ui_port,
+    }
+}
+
+fn input_macro_from_json(input_macro: &V2InputMacro) -> StorageInputMacro {
+    StorageInputMacro {
+        id: input_macro.id,
+        name: input_macro.name.clone(),
+        events: input_macro
+            .events
+            .iter()
+            .map(|event| InputMacroEvent {
+                delay_ms: event.delay_ms,
+                channel: event.channel,
+                data: Bytes::copy_from_slice(&event.data),
+            })
+            .collect(),
+    }
+}
+fn input_macro_to_json(id: u32, name: String, events: Vec<InputMacroEvent>) -> V2InputMacro {
+    V2InputMacro {
+        id,
+        name,
+        events: events
+            .into_iter()
+            .map(|event| V2InputMacroEvent {
+                delay_ms: event.delay_ms,
+                channel: event.channel,
+                data: event.data.to_vec(),
+            })
+            .collect(),
     }
 }
 
@@ -259,6 +356,11 @@ impl Storage for JsonStorage {
                 hash: password.hash,
             }),
             client_unique_id: user.client_unique_id,
+            stream_defaults: Vec::new(),
+            input_macros: Vec::new(),
+            stream_limits: Default::default(),
+            privacy_mode: false,
+            push_subscriptions: Vec::new(),
         };
 
         {
@@ -298,6 +400,8 @@ impl Storage for JsonStorage {
             }),
             role: user.role,
             client_unique_id: user.client_unique_id,
+            stream_limits: Default::default(),
+            privacy_mode: user.privacy_mode,
         })
     }
     async fn modify_user(
@@ -322,6 +426,12 @@ impl Storage for JsonStorage {
         if let Some(client_unique_id) = modify.client_unique_id {
             user.client_unique_id = client_unique_id;
         }
+        if let Some(stream_limits) = modify.stream_limits {
+            user.stream_limits = stream_limits_to_json(stream_limits);
+        }
+        if let Some(privacy_mode) = modify.privacy_mode {
+            user.privacy_mode = privacy_mode;
+        }
 
         drop(user);
         drop(users);
@@ -372,6 +482,61 @@ impl Storage for JsonStorage {
 
         result
     }
+    async fn import_user(&self, user: StorageUser, data: StorageUserData) -> Result<(), AppError> {
+        let mut users = self.users.write().await;
+
+        if users.contains_key(&user.id.0) {
+            return Err(AppError::UserAlreadyExists);
+        }
+
+        users.insert(
+            user.id.0,
+            RwLock::new(V2User {
+                role: user.role,
+                name: user.name,
+                password: user.password.map(|password| V2UserPassword {
+                    salt: password.salt,
+                    hash: password.hash,
+                }),
+                client_unique_id: user.client_unique_id,
+                stream_defaults: data
+                    .stream_defaults
+                    .into_iter()
+                    .map(|defaults| {
+                        stream_defaults_to_json(
+                            defaults.host_id,
+                            defaults.app_id,
+                            defaults.defaults,
+                        )
+                    })
+                    .collect(),
+                input_macros: data
+                    .input_macros
+                    .into_iter()
+                    .map(|input_macro| {
+                        input_macro_to_json(input_macro.id, input_macro.name, input_macro.events)
+                    })
+                    .collect(),
+                stream_limits: stream_limits_to_json(user.stream_limits),
+                privacy_mode: user.privacy_mode,
+                push_subscriptions: data
+                    .push_subscriptions
+                    .into_iter()
+                    .map(|subscription| V2PushSubscription {
+                        endpoint: subscription.endpoint,
+                        p256dh: subscription.p256dh,
+                        auth: subscription.auth,
+                    })
+                    .collect(),
+            }),
+        );
+
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
     async fn list_users(&self) -> Result<Either<Vec<UserId>, Vec<StorageUser>>, AppError> {
         let users = self.users.read().await;
 
@@ -386,6 +551,24 @@ impl Storage for JsonStorage {
         let out = join_all(futures).await;
         Ok(Either::Right(out))
     }
+    async fn query_users(
+        &self,
+        filter: StorageUserFilter,
+        pagination: StoragePagination,
+    ) -> Result<StoragePage<StorageUser>, AppError> {
+        let users = self.users.read().await;
+
+        let mut matching_users = Vec::new();
+        for (user_id, user) in &*users {
+            let user = user_from_json(UserId(*user_id), &user.read().await);
+
+            if filter.matches(&user) {
+                matching_users.push(user);
+            }
+        }
+
+        Ok(pagination.apply(matching_users, user_cursor))
+    }
     async fn any_user_exists(&self) -> Result<bool, AppError> {
         let users = self.users.read().await;
 
@@ -462,6 +645,9 @@ impl Storage for JsonStorage {
                 name: host.cache.name,
                 mac: host.cache.mac,
             },
+            notes: String::new(),
+            labels: BTreeMap::new(),
+            sunshine_credentials: None,
         };
 
         let mut hosts = self.hosts.write().await;
@@ -494,6 +680,9 @@ impl Storage for JsonStorage {
                 name: host.cache.name,
                 mac: host.cache.mac,
             },
+            notes: host.notes,
+            labels: host.labels,
+            sunshine_credentials: None,
         })
     }
     async fn modify_host(
@@ -528,6 +717,15 @@ impl Storage for JsonStorage {
         if let Some(new_cache_mac) = modify.cache_mac {
             host.cache.mac = new_cache_mac;
         }
+        if let Some(new_notes) = modify.notes {
+            host.notes = new_notes;
+        }
+        if let Some(new_labels) = modify.labels {
+            host.labels = new_labels;
+        }
+        if let Some(new_sunshine_credentials) = modify.sunshine_credentials {
+            host.sunshine_credentials = new_sunshine_credentials.map(sunshine_credentials_to_json);
+        }
 
         self.force_write();
 
@@ -547,6 +745,49 @@ impl Storage for JsonStorage {
         if hosts.remove(&host_id.0).is_none() {
             return Err(AppError::HostNotFound);
         }
+        drop(hosts);
+
+        let users = self.users.read().await;
+        for user in users.values() {
+            let mut user = user.write().await;
+            user.stream_defaults
+                .retain(|defaults| defaults.host_id != host_id.0);
+        }
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn import_host(&self, host: StorageHost) -> Result<(), AppError> {
+        let mut hosts = self.hosts.write().await;
+
+        if hosts.contains_key(&host.id.0) {
+            return Err(AppError::HostAlreadyExists);
+        }
+
+        hosts.insert(
+            host.id.0,
+            RwLock::new(V2Host {
+                owner: host.owner.map(|user_id| user_id.0),
+                address: host.address,
+                http_port: host.http_port,
+                pair_info: host.pair_info.map(|pair_info| V2HostPairInfo {
+                    client_private_key: pair_info.client_private_key,
+                    client_certificate: pair_info.client_certificate,
+                    server_certificate: pair_info.server_certificate,
+                }),
+                cache: V2HostCache {
+                    name: host.cache.name,
+                    mac: host.cache.mac,
+                },
+                notes: host.notes,
+                labels: host.labels,
+                sunshine_credentials: host.sunshine_credentials.map(sunshine_credentials_to_json),
+            }),
+        );
+
+        drop(hosts);
 
         self.force_write();
 
@@ -571,4 +812,233 @@ impl Storage for JsonStorage {
 
         Ok(user_hosts)
     }
+
+    async fn get_stream_defaults(
+        &self,
+        user_id: UserId,
+        host_id: HostId,
+        app_id: AppId,
+    ) -> Result<Option<StorageStreamDefaults>, AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let user = user.read().await;
+
+        Ok(user
+            .stream_defaults
+            .iter()
+            .find(|defaults| defaults.host_id == host_id.0 && defaults.app_id == app_id.0)
+            .map(stream_defaults_from_json))
+    }
+    async fn set_stream_defaults(
+        &self,
+        user_id: UserId,
+        host_id: HostId,
+        app_id: AppId,
+        defaults: StorageStreamDefaults,
+    ) -> Result<(), AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user.write().await;
+
+        user.stream_defaults
+            .retain(|defaults| defaults.host_id != host_id.0 || defaults.app_id != app_id.0);
+        user.stream_defaults
+            .push(stream_defaults_to_json(host_id, app_id, defaults));
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn list_stream_defaults(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageAppStreamDefaults>, AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let user = user.read().await;
+
+        Ok(user
+            .stream_defaults
+            .iter()
+            .map(|defaults| StorageAppStreamDefaults {
+                host_id: HostId(defaults.host_id),
+                app_id: AppId(defaults.app_id),
+                defaults: stream_defaults_from_json(defaults),
+            })
+            .collect())
+    }
+
+    async fn list_input_macros(&self, user_id: UserId) -> Result<Vec<StorageInputMacro>, AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let user = user.read().await;
+
+        Ok(user
+            .input_macros
+            .iter()
+            .map(input_macro_from_json)
+            .collect())
+    }
+    async fn add_input_macro(
+        &self,
+        user_id: UserId,
+        input_macro: StorageInputMacroAdd,
+    ) -> Result<StorageInputMacro, AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user.write().await;
+
+        let id = user
+            .input_macros
+            .iter()
+            .map(|input_macro| input_macro.id + 1)
+            .max()
+            .unwrap_or(0);
+
+        let input_macro = input_macro_to_json(id, input_macro.name, input_macro.events);
+        let storage_input_macro = input_macro_from_json(&input_macro);
+        user.input_macros.push(input_macro);
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
+
+        Ok(storage_input_macro)
+    }
+    async fn remove_input_macro(&self, user_id: UserId, macro_id: u32) -> Result<(), AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user.write().await;
+
+        let previous_len = user.input_macros.len();
+        user.input_macros
+            .retain(|input_macro| input_macro.id != macro_id);
+        if user.input_macros.len() == previous_len {
+            return Err(AppError::InputMacroNotFound);
+        }
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
+
+    async fn list_push_subscriptions(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StoragePushSubscription>, AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let user = user.read().await;
+
+        Ok(user
+            .push_subscriptions
+            .iter()
+            .map(|subscription| StoragePushSubscription {
+                endpoint: subscription.endpoint.clone(),
+                p256dh: subscription.p256dh.clone(),
+                auth: subscription.auth.clone(),
+            })
+            .collect())
+    }
+    async fn add_push_subscription(
+        &self,
+        user_id: UserId,
+        subscription: StoragePushSubscription,
+    ) -> Result<(), AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user.write().await;
+
+        user.push_subscriptions
+            .retain(|existing| existing.endpoint != subscription.endpoint);
+        user.push_subscriptions.push(V2PushSubscription {
+            endpoint: subscription.endpoint,
+            p256dh: subscription.p256dh,
+            auth: subscription.auth,
+        });
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
+    async fn remove_push_subscription(
+        &self,
+        user_id: UserId,
+        endpoint: &str,
+    ) -> Result<(), AppError> {
+        let users = self.users.read().await;
+
+        let user = users.get(&user_id.0).ok_or(AppError::UserNotFound)?;
+        let mut user = user.write().await;
+
+        let previous_len = user.push_subscriptions.len();
+        user.push_subscriptions
+            .retain(|subscription| subscription.endpoint != endpoint);
+        if user.push_subscriptions.len() == previous_len {
+            return Err(AppError::PushSubscriptionNotFound);
+        }
+
+        drop(user);
+        drop(users);
+
+        self.force_write();
+
+        Ok(())
+    }
+
+    async fn list_hosts(&self) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError> {
+        let hosts = self.hosts.read().await;
+
+        let mut all_hosts = Vec::with_capacity(hosts.len());
+        for (host_id, host) in &*hosts {
+            let host_id = HostId(*host_id);
+            let host = host.read().await;
+
+            all_hosts.push((host_id, Some(host_from_json(host_id, &host))));
+        }
+
+        Ok(all_hosts)
+    }
+    async fn query_hosts(
+        &self,
+        filter: StorageHostFilter,
+        pagination: StoragePagination,
+    ) -> Result<StoragePage<StorageHost>, AppError> {
+        let hosts = self.hosts.read().await;
+
+        let mut matching_hosts = Vec::new();
+        for (host_id, host) in &*hosts {
+            let host = host_from_json(HostId(*host_id), &host.read().await);
+
+            if filter.matches(&host) {
+                matching_hosts.push(host);
+            }
+        }
+
+        Ok(pagination.apply(matching_hosts, host_cursor))
+    }
+
+    async fn flush(&self) -> Result<(), AppError> {
+        self.store().await?;
+
+        Ok(())
+    }
 }
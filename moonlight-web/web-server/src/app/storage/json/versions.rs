@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use log::error;
 use moonlight_common::mac::MacAddress;
@@ -74,6 +74,9 @@ pub fn migrate_v1_to_v2(old: V1) -> V2 {
                 name: old_host.cache.name.unwrap_or_else(|| "Unknown".to_string()),
                 mac: old_host.cache.mac,
             },
+            notes: String::new(),
+            labels: BTreeMap::new(),
+            sunshine_credentials: None,
         };
 
         v2_hosts.insert(id as u32, v2_host);
@@ -87,7 +90,7 @@ pub fn migrate_v1_to_v2(old: V1) -> V2 {
 
 // -- V2
 
-use crate::app::storage::json::serde_helpers::{de_int_key, hex_array};
+use crate::app::storage::json::serde_helpers::{de_int_key, hex_array, hex_bytes};
 
 #[derive(Serialize, Deserialize)]
 pub struct V2 {
@@ -103,6 +106,59 @@ pub struct V2User {
     pub name: String,
     pub password: Option<V2UserPassword>,
     pub client_unique_id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stream_defaults: Vec<V2StreamDefaults>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_macros: Vec<V2InputMacro>,
+    #[serde(default, skip_serializing_if = "V2UserStreamLimits::is_unlimited")]
+    pub stream_limits: V2UserStreamLimits,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub privacy_mode: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub push_subscriptions: Vec<V2PushSubscription>,
+}
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct V2UserStreamLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bitrate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<u32>,
+}
+impl V2UserStreamLimits {
+    fn is_unlimited(&self) -> bool {
+        self.max_bitrate.is_none() && self.max_width.is_none() && self.max_height.is_none()
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2StreamDefaults {
+    pub host_id: u32,
+    pub app_id: u32,
+    pub bitrate: u32,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    pub video_format: u32,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2InputMacro {
+    pub id: u32,
+    pub name: String,
+    pub events: Vec<V2InputMacroEvent>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2PushSubscription {
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2InputMacroEvent {
+    pub delay_ms: u32,
+    pub channel: u8,
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct V2UserPassword {
@@ -119,6 +175,12 @@ pub struct V2Host {
     pub http_port: u16,
     pub pair_info: Option<V2HostPairInfo>,
     pub cache: V2HostCache,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunshine_credentials: Option<V2HostSunshineCredentials>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -128,6 +190,14 @@ pub struct V2HostPairInfo {
     pub server_certificate: Pem,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct V2HostSunshineCredentials {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub web_ui_port: Option<u16>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct V2HostCache {
     pub name: String,
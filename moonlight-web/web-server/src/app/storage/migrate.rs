@@ -0,0 +1,360 @@
+//! Copies all data from one storage into another, e.g. when switching the storage type.
+//!
+//! Sessions are not copied, every user has to log in again after switching the storage.
+
+use anyhow::{Context, bail};
+
+use crate::app::{
+    storage::{Either, Storage, StorageHost, StorageInputMacro, StorageUser, StorageUserData},
+    user::UserId,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StorageMigration {
+    pub users: usize,
+    pub hosts: usize,
+}
+
+/// Copies all users with their data and all hosts with their pair info from `from` into `to`,
+/// keeping their ids, and checks that they arrived unchanged.
+///
+/// The target storage must not contain any users.
+pub async fn migrate_storage(
+    from: &(dyn Storage + Send + Sync),
+    to: &(dyn Storage + Send + Sync),
+) -> Result<StorageMigration, anyhow::Error> {
+    if to.any_user_exists().await? {
+        bail!("the target storage already contains users");
+    }
+
+    let users = match from.list_users().await? {
+        Either::Left(user_ids) => {
+            let mut users = Vec::with_capacity(user_ids.len());
+            for user_id in user_ids {
+                users.push(from.get_user(user_id).await?);
+            }
+            users
+        }
+        Either::Right(users) => users,
+    };
+    let mut users_data = Vec::with_capacity(users.len());
+    for user in users {
+        let data = user_data(from, user.id).await?;
+        users_data.push((user, data));
+    }
+    let users = users_data;
+
+    let mut hosts = Vec::new();
+    for (host_id, host) in from.list_hosts().await? {
+        hosts.push(match host {
+            Some(host) => host,
+            None => from.get_host(host_id).await?,
+        });
+    }
+
+    for (user, data) in &users {
+        to.import_user(user.clone(), data.clone())
+            .await
+            .with_context(|| format!("failed to copy user {}", user.id.0))?;
+    }
+    for host in &hosts {
+        to.import_host(host.clone())
+            .await
+            .with_context(|| format!("failed to copy host {}", host.id.0))?;
+    }
+
+    to.flush().await?;
+
+    // Verify
+    for (user, data) in &users {
+        let copied = to.get_user(user.id).await?;
+        let copied_data = user_data(to, user.id).await?;
+        if !user_equals((user, data), (&copied, &copied_data)) {
+            bail!("user {} differs after copying", user.id.0);
+        }
+    }
+    for host in &hosts {
+        let copied = to.get_host(host.id).await?;
+        if !host_equals(host, &copied) {
+            bail!("host {} differs after copying", host.id.0);
+        }
+    }
+
+    Ok(StorageMigration {
+        users: users.len(),
+        hosts: hosts.len(),
+    })
+}
+
+async fn user_data(
+    storage: &(dyn Storage + Send + Sync),
+    user_id: UserId,
+) -> Result<StorageUserData, anyhow::Error> {
+    Ok(StorageUserData {
+        stream_defaults: storage.list_stream_defaults(user_id).await?,
+        input_macros: storage.list_input_macros(user_id).await?,
+        push_subscriptions: storage.list_push_subscriptions(user_id).await?,
+    })
+}
+
+fn user_equals(
+    (a, a_data): (&StorageUser, &StorageUserData),
+    (b, b_data): (&StorageUser, &StorageUserData),
+) -> bool {
+    let password_equals = match (&a.password, &b.password) {
+        (Some(a), Some(b)) => a.salt == b.salt && a.hash == b.hash,
+        (None, None) => true,
+        _ => false,
+    };
+
+    a.id == b.id
+        && a.name == b.name
+        && password_equals
+        && a.role == b.role
+        && a.client_unique_id == b.client_unique_id
+        && a.stream_limits == b.stream_limits
+        && a.privacy_mode == b.privacy_mode
+        && unordered_equals(&a_data.stream_defaults, &b_data.stream_defaults)
+        && a_data.input_macros.len() == b_data.input_macros.len()
+        && a_data
+            .input_macros
+            .iter()
+            .all(|a| b_data.input_macros.iter().any(|b| input_macro_equals(a, b)))
+        && unordered_equals(&a_data.push_subscriptions, &b_data.push_subscriptions)
+}
+
+fn input_macro_equals(a: &StorageInputMacro, b: &StorageInputMacro) -> bool {
+    a.id == b.id
+        && a.name == b.name
+        && a.events.len() == b.events.len()
+        && a.events
+            .iter()
+            .zip(&b.events)
+            .all(|(a, b)| a.delay_ms == b.delay_ms && a.channel == b.channel && a.data == b.data)
+}
+
+/// The storages might list the entries in another order
+fn unordered_equals<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.len() == b.len() && a.iter().all(|value| b.contains(value))
+}
+
+fn host_equals(a: &StorageHost, b: &StorageHost) -> bool {
+    let pair_info_equals = match (&a.pair_info, &b.pair_info) {
+        (Some(a), Some(b)) => {
+            a.client_private_key == b.client_private_key
+                && a.client_certificate == b.client_certificate
+                && a.server_certificate == b.server_certificate
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    let sunshine_credentials_equals = match (&a.sunshine_credentials, &b.sunshine_credentials) {
+        (Some(a), Some(b)) => {
+            a.username == b.username && a.password == b.password && a.web_ui_port == b.web_ui_port
+        }
+        (None, None) => true,
+        _ => false,
+    };
+
+    a.id == b.id
+        && a.owner == b.owner
+        && a.address == b.address
+        && a.http_port == b.http_port
+        && pair_info_equals
+        && a.cache.name == b.cache.name
+        && a.cache.mac == b.cache.mac
+        && a.notes == b.notes
+        && a.labels == b.labels
+        && sunshine_credentials_equals
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, env, path::PathBuf, sync::Arc, time::Duration};
+
+    use bytes::Bytes;
+    use common::ipc::InputMacroEvent;
+    use moonlight_common::units::{Fps, Kbps};
+    use uuid::Uuid;
+
+    use crate::app::{
+        host::{AppId, HostId},
+        storage::{
+            Storage, StorageAppStreamDefaults, StorageHostAdd, StorageHostCache, StorageHostModify,
+            StorageInputMacroAdd, StoragePushSubscription, StorageStreamDefaults,
+            StorageSunshineCredentials, StorageUserAdd, StorageUserModify, StorageUserStreamLimits,
+            json::JsonStorage, migrate::migrate_storage,
+        },
+        user::{Role, UserId},
+    };
+
+    fn temp_file() -> PathBuf {
+        env::temp_dir().join(format!("moonlight-web-migrate-{}.json", Uuid::new_v4()))
+    }
+
+    async fn json_storage(file: PathBuf) -> Arc<JsonStorage> {
+        JsonStorage::load(file, Duration::from_secs(60 * 60))
+            .await
+            .unwrap()
+    }
+
+    /// Fills the storage with a user and a host which have every optional field set
+    async fn fill(storage: &JsonStorage) -> (UserId, HostId) {
+        let user = storage
+            .add_user(StorageUserAdd {
+                role: Role::User,
+                name: "user".to_string(),
+                password: None,
+                client_unique_id: "client".to_string(),
+            })
+            .await
+            .unwrap();
+        storage
+            .modify_user(
+                user.id,
+                StorageUserModify {
+                    stream_limits: Some(StorageUserStreamLimits {
+                        max_bitrate: Some(Kbps(20_000)),
+                        max_width: Some(1920),
+                        max_height: None,
+                    }),
+                    privacy_mode: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let host = storage
+            .add_host(StorageHostAdd {
+                owner: Some(user.id),
+                address: "192.168.1.20".to_string(),
+                http_port: 47989,
+                pair_info: None,
+                cache: StorageHostCache {
+                    name: "Host".to_string(),
+                    mac: None,
+                },
+            })
+            .await
+            .unwrap();
+        storage
+            .modify_host(
+                host.id,
+                StorageHostModify {
+                    notes: Some("living room".to_string()),
+                    labels: Some(BTreeMap::from([("room".to_string(), "1".to_string())])),
+                    sunshine_credentials: Some(Some(StorageSunshineCredentials {
+                        username: "admin".to_string(),
+                        password: "password".to_string(),
+                        web_ui_port: Some(47990),
+                    })),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        storage
+            .set_stream_defaults(user.id, host.id, AppId(1), stream_defaults())
+            .await
+            .unwrap();
+
+        // The first macro is removed, so the ids don't start at zero
+        let removed = storage
+            .add_input_macro(user.id, input_macro("removed"))
+            .await
+            .unwrap();
+        storage
+            .add_input_macro(user.id, input_macro("jump"))
+            .await
+            .unwrap();
+        storage
+            .remove_input_macro(user.id, removed.id)
+            .await
+            .unwrap();
+
+        storage
+            .add_push_subscription(user.id, push_subscription())
+            .await
+            .unwrap();
+
+        (user.id, host.id)
+    }
+
+    fn input_macro(name: &str) -> StorageInputMacroAdd {
+        StorageInputMacroAdd {
+            name: name.to_string(),
+            events: vec![InputMacroEvent {
+                delay_ms: 100,
+                channel: 2,
+                data: Bytes::from_static(&[1, 2, 3]),
+            }],
+        }
+    }
+
+    fn push_subscription() -> StoragePushSubscription {
+        StoragePushSubscription {
+            endpoint: "https://fcm.googleapis.com/fcm/send/abc".to_string(),
+            p256dh: "p256dh".to_string(),
+            auth: "auth".to_string(),
+        }
+    }
+
+    fn stream_defaults() -> StorageStreamDefaults {
+        StorageStreamDefaults {
+            bitrate: Kbps(10_000),
+            fps: Fps(60),
+            width: 1920,
+            height: 1080,
+            video_format: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_every_field() {
+        let (from_file, to_file) = (temp_file(), temp_file());
+        let from = json_storage(from_file.clone()).await;
+        let to = json_storage(to_file.clone()).await;
+
+        let (user_id, host_id) = fill(&from).await;
+
+        let migration = migrate_storage(&*from, &*to).await.unwrap();
+        assert_eq!(migration.users, 1);
+        assert_eq!(migration.hosts, 1);
+
+        let user = to.get_user(user_id).await.unwrap();
+        assert!(user.privacy_mode);
+        assert_eq!(user.stream_limits.max_width, Some(1920));
+        assert_eq!(
+            to.list_stream_defaults(user_id).await.unwrap(),
+            vec![StorageAppStreamDefaults {
+                host_id,
+                app_id: AppId(1),
+                defaults: stream_defaults(),
+            }]
+        );
+
+        let input_macros = to.list_input_macros(user_id).await.unwrap();
+        assert_eq!(input_macros.len(), 1);
+        assert_eq!(input_macros[0].id, 1);
+        assert_eq!(input_macros[0].name, "jump");
+        assert_eq!(&input_macros[0].events[0].data[..], &[1, 2, 3]);
+
+        assert_eq!(
+            to.list_push_subscriptions(user_id).await.unwrap(),
+            vec![push_subscription()]
+        );
+
+        let host = to.get_host(host_id).await.unwrap();
+        assert_eq!(host.notes, "living room");
+        assert_eq!(host.labels.len(), 1);
+        assert!(host.sunshine_credentials.is_some());
+
+        // Nothing may be copied into a storage with users
+        assert!(migrate_storage(&*from, &*to).await.is_err());
+
+        let _ = std::fs::remove_file(from_file);
+        let _ = std::fs::remove_file(to_file);
+    }
+}
@@ -1,20 +1,28 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use common::config::StorageConfig;
-use moonlight_common::mac::MacAddress;
+use common::{config::StorageConfig, ipc::InputMacroEvent};
+use moonlight_common::{
+    mac::MacAddress,
+    units::{Fps, Kbps},
+};
 use pem::Pem;
 
 use crate::app::{
     AppError,
     auth::SessionToken,
-    host::HostId,
+    host::{AppId, HostId},
     password::StoragePassword,
-    storage::json::JsonStorage,
+    storage::{
+        json::JsonStorage,
+        query::{StorageHostFilter, StoragePage, StoragePagination, StorageUserFilter},
+    },
     user::{Role, UserId},
 };
 
 pub mod json;
+pub mod migrate;
+pub mod query;
 
 pub async fn create_storage(
     config: StorageConfig,
@@ -41,6 +49,16 @@ pub struct StorageUser {
     pub password: Option<StoragePassword>,
     pub role: Role,
     pub client_unique_id: String,
+    pub stream_limits: StorageUserStreamLimits,
+    /// No thumbnails, input statistics or diagnostics are kept of the streams of this user
+    pub privacy_mode: bool,
+}
+/// The highest settings the user can stream with, unset values aren't limited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StorageUserStreamLimits {
+    pub max_bitrate: Option<Kbps>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
 }
 #[derive(Clone)]
 pub struct StorageUserAdd {
@@ -54,6 +72,8 @@ pub struct StorageUserModify {
     pub role: Option<Role>,
     pub password: Option<Option<StoragePassword>>,
     pub client_unique_id: Option<String>,
+    pub stream_limits: Option<StorageUserStreamLimits>,
+    pub privacy_mode: Option<bool>,
 }
 
 #[derive(Clone)]
@@ -65,6 +85,9 @@ pub struct StorageHost {
     pub http_port: u16,
     pub pair_info: Option<StorageHostPairInfo>,
     pub cache: StorageHostCache,
+    pub notes: String,
+    pub labels: BTreeMap<String, String>,
+    pub sunshine_credentials: Option<StorageSunshineCredentials>,
 }
 #[derive(Clone)]
 pub struct StorageHostAdd {
@@ -85,6 +108,13 @@ pub struct StorageHostPairInfo {
     pub client_certificate: Pem,
     pub server_certificate: Pem,
 }
+/// Admin credentials of the Sunshine web ui, stored in plaintext
+#[derive(Clone)]
+pub struct StorageSunshineCredentials {
+    pub username: String,
+    pub password: String,
+    pub web_ui_port: Option<u16>,
+}
 #[derive(Default, Clone)]
 pub struct StorageHostModify {
     pub owner: Option<Option<UserId>>,
@@ -93,6 +123,57 @@ pub struct StorageHostModify {
     pub pair_info: Option<Option<StorageHostPairInfo>>,
     pub cache_name: Option<String>,
     pub cache_mac: Option<Option<MacAddress>>,
+    pub notes: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+    pub sunshine_credentials: Option<Option<StorageSunshineCredentials>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageStreamDefaults {
+    pub bitrate: Kbps,
+    pub fps: Fps,
+    pub width: u32,
+    pub height: u32,
+    pub video_format: u32,
+}
+
+/// The [StorageStreamDefaults] of one app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageAppStreamDefaults {
+    pub host_id: HostId,
+    pub app_id: AppId,
+    pub defaults: StorageStreamDefaults,
+}
+
+/// Everything of a user which isn't part of [StorageUser], copied when migrating between storages
+#[derive(Debug, Default, Clone)]
+pub struct StorageUserData {
+    pub stream_defaults: Vec<StorageAppStreamDefaults>,
+    /// Imported with their ids
+    pub input_macros: Vec<StorageInputMacro>,
+    pub push_subscriptions: Vec<StoragePushSubscription>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageInputMacro {
+    pub id: u32,
+    pub name: String,
+    pub events: Vec<InputMacroEvent>,
+}
+#[derive(Debug, Clone)]
+pub struct StorageInputMacroAdd {
+    pub name: String,
+    pub events: Vec<InputMacroEvent>,
+}
+
+/// A browser which receives web push messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoragePushSubscription {
+    pub endpoint: String,
+    /// Base64url encoded
+    pub p256dh: String,
+    /// Base64url encoded
+    pub auth: String,
 }
 
 #[derive(Clone)]
@@ -115,8 +196,15 @@ pub trait Storage {
     async fn get_user_by_name(&self, name: &str)
     -> Result<(UserId, Option<StorageUser>), AppError>;
     async fn remove_user(&self, user_id: UserId) -> Result<(), AppError>;
+    /// Inserts the user with the same id and its data, used when migrating between storages
+    async fn import_user(&self, user: StorageUser, data: StorageUserData) -> Result<(), AppError>;
     /// The returned tuple can contain a Vec<UserId> or Vec<StorageUser> if the Storage thinks it's more efficient to query all data directly
     async fn list_users(&self) -> Result<Either<Vec<UserId>, Vec<StorageUser>>, AppError>;
+    async fn query_users(
+        &self,
+        filter: StorageUserFilter,
+        pagination: StoragePagination,
+    ) -> Result<StoragePage<StorageUser>, AppError>;
     async fn any_user_exists(&self) -> Result<bool, AppError>;
 
     async fn create_session_token(
@@ -136,6 +224,8 @@ pub trait Storage {
     async fn modify_host(&self, host_id: HostId, host: StorageHostModify) -> Result<(), AppError>;
     async fn get_host(&self, host_id: HostId) -> Result<StorageHost, AppError>;
     async fn remove_host(&self, host_id: HostId) -> Result<(), AppError>;
+    /// Inserts the host with the same id, used when migrating between storages
+    async fn import_host(&self, host: StorageHost) -> Result<(), AppError>;
 
     /// Returns all hosts that either have no owner (global) or have the specified user_id as an owner
     ///
@@ -144,4 +234,62 @@ pub trait Storage {
         &self,
         query: StorageQueryHosts,
     ) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError>;
+    /// The settings of the last successful stream of this app by the user
+    async fn get_stream_defaults(
+        &self,
+        user_id: UserId,
+        host_id: HostId,
+        app_id: AppId,
+    ) -> Result<Option<StorageStreamDefaults>, AppError>;
+    async fn set_stream_defaults(
+        &self,
+        user_id: UserId,
+        host_id: HostId,
+        app_id: AppId,
+        defaults: StorageStreamDefaults,
+    ) -> Result<(), AppError>;
+    /// The stream defaults of every app of the user
+    async fn list_stream_defaults(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StorageAppStreamDefaults>, AppError>;
+
+    /// The input macros the user recorded while streaming
+    async fn list_input_macros(&self, user_id: UserId) -> Result<Vec<StorageInputMacro>, AppError>;
+    async fn add_input_macro(
+        &self,
+        user_id: UserId,
+        input_macro: StorageInputMacroAdd,
+    ) -> Result<StorageInputMacro, AppError>;
+    async fn remove_input_macro(&self, user_id: UserId, macro_id: u32) -> Result<(), AppError>;
+
+    /// The browsers of the user which receive web push messages
+    async fn list_push_subscriptions(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<StoragePushSubscription>, AppError>;
+    /// Replaces the subscription with the same endpoint
+    async fn add_push_subscription(
+        &self,
+        user_id: UserId,
+        subscription: StoragePushSubscription,
+    ) -> Result<(), AppError>;
+    async fn remove_push_subscription(
+        &self,
+        user_id: UserId,
+        endpoint: &str,
+    ) -> Result<(), AppError>;
+
+    /// Returns all hosts regardless of their owner
+    ///
+    /// The returned tuple in the Vec can contain a StorageHost if the Storage thinks it's more efficient to query all data directly
+    async fn list_hosts(&self) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError>;
+    async fn query_hosts(
+        &self,
+        filter: StorageHostFilter,
+        pagination: StoragePagination,
+    ) -> Result<StoragePage<StorageHost>, AppError>;
+
+    /// Waits until all previous changes are persisted
+    async fn flush(&self) -> Result<(), AppError>;
 }
@@ -0,0 +1,247 @@
+//! Filtering, sorting and pagination of storage listings.
+//!
+//! Storages which can't filter or sort in their own queries can use [StorageUserFilter::matches],
+//! [StorageHostFilter::matches] and [StoragePagination::apply] on all entries.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::app::{
+    host::HostId,
+    storage::{StorageHost, StorageUser},
+    user::{Role, UserId},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageSortBy {
+    #[default]
+    Id,
+    Name,
+}
+
+/// The position of the last entry of a page.
+/// It contains the sort keys instead of an offset so that pages stay stable while entries are added or removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageCursor {
+    pub id: u32,
+    pub name: String,
+}
+
+impl fmt::Display for StorageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.id, self.name)
+    }
+}
+
+impl FromStr for StorageCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, name) = s.split_once(':').ok_or(())?;
+
+        Ok(Self {
+            id: id.parse().map_err(|_| ())?,
+            name: name.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StoragePagination {
+    /// Only return entries after this cursor
+    pub cursor: Option<StorageCursor>,
+    /// If none all remaining entries are returned
+    pub limit: Option<usize>,
+    pub sort_by: StorageSortBy,
+    pub descending: bool,
+}
+
+pub struct StoragePage<T> {
+    pub entries: Vec<T>,
+    /// Some if there are more entries after this page
+    pub next_cursor: Option<StorageCursor>,
+}
+
+impl StoragePagination {
+    fn compare(&self, a: &StorageCursor, b: &StorageCursor) -> Ordering {
+        let ordering = match self.sort_by {
+            StorageSortBy::Id => a.id.cmp(&b.id),
+            StorageSortBy::Name => a
+                .name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then(a.id.cmp(&b.id)),
+        };
+
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// Sorts the entries and returns the page after the cursor
+    pub fn apply<T>(&self, entries: Vec<T>, key: impl Fn(&T) -> StorageCursor) -> StoragePage<T> {
+        let mut entries = entries
+            .into_iter()
+            .map(|entry| (key(&entry), entry))
+            .filter(|(entry_key, _)| {
+                self.cursor
+                    .as_ref()
+                    .is_none_or(|cursor| self.compare(entry_key, cursor) == Ordering::Greater)
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|(a, _), (b, _)| self.compare(a, b));
+
+        let mut next_cursor = None;
+        if let Some(limit) = self.limit
+            && entries.len() > limit
+        {
+            entries.truncate(limit);
+            next_cursor = entries.last().map(|(entry_key, _)| entry_key.clone());
+        }
+
+        StoragePage {
+            entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+            next_cursor,
+        }
+    }
+}
+
+fn name_contains(name: &str, pattern: &Option<String>) -> bool {
+    pattern
+        .as_ref()
+        .is_none_or(|pattern| name.to_lowercase().contains(&pattern.to_lowercase()))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageUserFilter {
+    pub role: Option<Role>,
+    /// Case insensitive
+    pub name_contains: Option<String>,
+}
+
+impl StorageUserFilter {
+    pub fn matches(&self, user: &StorageUser) -> bool {
+        self.role.is_none_or(|role| user.role == role)
+            && name_contains(&user.name, &self.name_contains)
+    }
+}
+
+pub fn user_cursor(user: &StorageUser) -> StorageCursor {
+    StorageCursor {
+        id: user.id.0,
+        name: user.name.clone(),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageHostFilter {
+    /// Only hosts which have no owner (global) or are owned by this user
+    pub accessible_by: Option<UserId>,
+    /// `Some(None)` only matches global hosts
+    pub owner: Option<Option<UserId>>,
+    pub paired: Option<bool>,
+    /// Case insensitive, matches the cached name
+    pub name_contains: Option<String>,
+    /// Only these hosts, e.g. the allowed hosts of the default user
+    pub host_ids: Option<Vec<HostId>>,
+}
+
+impl StorageHostFilter {
+    pub fn matches(&self, host: &StorageHost) -> bool {
+        self.accessible_by
+            .is_none_or(|user_id| host.owner.is_none() || host.owner == Some(user_id))
+            && self.owner.is_none_or(|owner| host.owner == owner)
+            && self
+                .paired
+                .is_none_or(|paired| host.pair_info.is_some() == paired)
+            && name_contains(&host.cache.name, &self.name_contains)
+            && self
+                .host_ids
+                .as_ref()
+                .is_none_or(|host_ids| host_ids.contains(&host.id))
+    }
+}
+
+pub fn host_cursor(host: &StorageHost) -> StorageCursor {
+    StorageCursor {
+        id: host.id.0,
+        name: host.cache.name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::app::storage::query::{StorageCursor, StoragePagination, StorageSortBy};
+
+    fn cursor(id: u32, name: &str) -> StorageCursor {
+        StorageCursor {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    fn entries() -> Vec<StorageCursor> {
+        vec![
+            cursor(3, "beta"),
+            cursor(1, "Gamma"),
+            cursor(4, "alpha"),
+            cursor(2, "alpha"),
+        ]
+    }
+
+    fn ids(entries: &[StorageCursor]) -> Vec<u32> {
+        entries.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn test_pages_follow_the_cursor() {
+        let mut pagination = StoragePagination {
+            limit: Some(3),
+            ..Default::default()
+        };
+
+        let page = pagination.apply(entries(), Clone::clone);
+        assert_eq!(ids(&page.entries), [1, 2, 3]);
+        assert_eq!(page.next_cursor, Some(cursor(3, "beta")));
+
+        pagination.cursor = page.next_cursor;
+        let page = pagination.apply(entries(), Clone::clone);
+        assert_eq!(ids(&page.entries), [4]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_sort_by_name() {
+        let mut pagination = StoragePagination {
+            limit: Some(2),
+            sort_by: StorageSortBy::Name,
+            ..Default::default()
+        };
+
+        let page = pagination.apply(entries(), Clone::clone);
+        assert_eq!(ids(&page.entries), [2, 4]);
+
+        pagination.cursor = page.next_cursor;
+        let page = pagination.apply(entries(), Clone::clone);
+        assert_eq!(ids(&page.entries), [3, 1]);
+        assert_eq!(page.next_cursor, None);
+
+        let pagination = StoragePagination {
+            sort_by: StorageSortBy::Name,
+            descending: true,
+            ..Default::default()
+        };
+        let page = pagination.apply(entries(), Clone::clone);
+        assert_eq!(ids(&page.entries), [1, 3, 4, 2]);
+    }
+
+    #[test]
+    fn test_cursor_string() {
+        let value = cursor(12, "name: with colon");
+
+        assert_eq!(value.to_string().parse::<StorageCursor>(), Ok(value));
+        assert!("no id".parse::<StorageCursor>().is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Rejects stream settings outside of the configured [StreamLimits] before the host is asked to stream them,
+//! settings above the limits of the user are lowered instead.
+
+use common::{
+    api_bindings::{StreamSettingRange, StreamSettingsField},
+    config::StreamLimits,
+};
+use moonlight_common::units::{Fps, Kbps};
+
+use crate::app::storage::StorageUserStreamLimits;
+
+/// Returns the first setting which isn't allowed together with its allowed range
+pub fn check_stream_settings(
+    limits: &StreamLimits,
+    width: u32,
+    height: u32,
+    fps: Fps,
+    bitrate: Kbps,
+) -> Result<(), (StreamSettingsField, StreamSettingRange)> {
+    let settings = [
+        (StreamSettingsField::Width, width, limits.width),
+        (StreamSettingsField::Height, height, limits.height),
+        (StreamSettingsField::Fps, fps.get(), limits.fps),
+        (StreamSettingsField::Bitrate, bitrate.get(), limits.bitrate),
+    ];
+
+    for (field, value, range) in settings {
+        if !range.contains(value) {
+            return Err((field, range));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lowers the settings to the limits of the user, the resolution keeps its aspect ratio.
+/// Returns if a setting was lowered.
+pub fn apply_user_stream_limits(
+    limits: &StorageUserStreamLimits,
+    width: &mut u32,
+    height: &mut u32,
+    bitrate: &mut Kbps,
+) -> bool {
+    let mut lowered = false;
+
+    if let Some(max_bitrate) = limits.max_bitrate
+        && *bitrate > max_bitrate
+    {
+        *bitrate = max_bitrate;
+        lowered = true;
+    }
+
+    let (mut new_width, mut new_height) = (*width as u64, *height as u64);
+    if let Some(max_width) = limits.max_width.map(u64::from)
+        && new_width > max_width
+    {
+        new_height = new_height * max_width / new_width;
+        new_width = max_width;
+    }
+    if let Some(max_height) = limits.max_height.map(u64::from)
+        && new_height > max_height
+    {
+        new_width = new_width * max_height / new_height;
+        new_height = max_height;
+    }
+
+    if (new_width, new_height) != (*width as u64, *height as u64) {
+        // Encoders expect an even resolution
+        *width = (new_width as u32 & !1).max(2);
+        *height = (new_height as u32 & !1).max(2);
+        lowered = true;
+    }
+
+    lowered
+}
+
+#[cfg(test)]
+mod test {
+    use common::{
+        api_bindings::{StreamSettingRange, StreamSettingsField},
+        config::StreamLimits,
+    };
+    use moonlight_common::units::{Fps, Kbps};
+
+    use crate::app::{
+        storage::StorageUserStreamLimits,
+        stream_limits::{apply_user_stream_limits, check_stream_settings},
+    };
+
+    #[test]
+    fn test_default_limits_allow_common_settings() {
+        let limits = StreamLimits::default();
+
+        assert!(check_stream_settings(&limits, 1920, 1080, Fps(60), Kbps(10000)).is_ok());
+        assert!(check_stream_settings(&limits, 3840, 2160, Fps(120), Kbps(80000)).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_first_invalid_field() {
+        let limits = StreamLimits {
+            fps: StreamSettingRange { min: 30, max: 60 },
+            bitrate: StreamSettingRange {
+                min: 1000,
+                max: 20000,
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            check_stream_settings(&limits, 1920, 1080, Fps(120), Kbps(50000)),
+            Err((StreamSettingsField::Fps, limits.fps))
+        );
+        assert_eq!(
+            check_stream_settings(&limits, 1920, 1080, Fps(60), Kbps(50000)),
+            Err((StreamSettingsField::Bitrate, limits.bitrate))
+        );
+        assert_eq!(
+            check_stream_settings(&limits, 0, 1080, Fps(60), Kbps(10000)),
+            Err((StreamSettingsField::Width, limits.width))
+        );
+    }
+
+    #[test]
+    fn test_user_limits_keep_aspect_ratio() {
+        let limits = StorageUserStreamLimits {
+            max_bitrate: Some(Kbps(10000)),
+            max_width: Some(1920),
+            max_height: Some(1080),
+        };
+
+        let (mut width, mut height, mut bitrate) = (3840, 2160, Kbps(50000));
+        assert!(apply_user_stream_limits(
+            &limits,
+            &mut width,
+            &mut height,
+            &mut bitrate
+        ));
+        assert_eq!((width, height, bitrate), (1920, 1080, Kbps(10000)));
+
+        // Ultrawide streams are limited by their width
+        let (mut width, mut height, mut bitrate) = (3440, 1440, Kbps(5000));
+        assert!(apply_user_stream_limits(
+            &limits,
+            &mut width,
+            &mut height,
+            &mut bitrate
+        ));
+        assert_eq!((width, height, bitrate), (1920, 802, Kbps(5000)));
+
+        let (mut width, mut height, mut bitrate) = (1280, 720, Kbps(5000));
+        assert!(!apply_user_stream_limits(
+            &limits,
+            &mut width,
+            &mut height,
+            &mut bitrate
+        ));
+        assert_eq!((width, height, bitrate), (1280, 720, Kbps(5000)));
+    }
+}
@@ -0,0 +1,311 @@
+//! Client for the config api of Sunshine which is served by its web ui.
+//!
+//! Sunshine serves the web ui with the same self signed certificate as the https api of the host,
+//! so the server certificate which was stored when pairing is the only trusted root.
+//! The admin credentials are never sent to hosts which weren't paired.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use common::config::SunshineCredentials;
+use log::{debug, info, warn};
+use moonlight_common::PairPin;
+use pem::Pem;
+use reqwest::Certificate;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tokio::time::sleep;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sunshine only accepts the pin after the pair request reached it
+const PIN_SUBMIT_ATTEMPTS: usize = 10;
+const PIN_SUBMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum SunshineApiError {
+    #[error("request to the sunshine api failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("sunshine rejected the request")]
+    Rejected,
+}
+
+/// The keys of the Sunshine config which can be changed with [SunshineApi::set_encoder_settings]
+pub const ENCODER_SETTINGS: &[&str] = &[
+    "encoder",
+    "adapter_name",
+    "capture",
+    "hevc_mode",
+    "av1_mode",
+    "min_threads",
+    "qp",
+    "nvenc_preset",
+    "nvenc_twopass",
+    "nvenc_spatial_aq",
+    "qsv_preset",
+    "qsv_coder",
+    "amd_usage",
+    "amd_rc",
+    "amd_quality",
+    "vt_coder",
+    "vt_software",
+    "sw_preset",
+    "sw_tune",
+];
+
+/// Sent with the config but not part of it
+const CONFIG_STATUS_KEYS: &[&str] = &["status", "platform", "version"];
+
+/// The web ui runs on the http port + 1 by default
+pub fn web_ui_port(http_port: u16, credentials: Option<&SunshineCredentials>) -> u16 {
+    credentials
+        .and_then(|credentials| credentials.web_ui_port)
+        .unwrap_or(http_port.wrapping_add(1))
+}
+
+pub struct SunshineApi {
+    client: reqwest::Client,
+    base_url: String,
+    credentials: SunshineCredentials,
+}
+
+#[derive(Serialize)]
+struct PinRequest<'a> {
+    pin: String,
+    name: &'a str,
+}
+
+/// An app like Sunshine stores it in its apps.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SunshineApp {
+    pub name: String,
+    #[serde(default)]
+    pub cmd: String,
+    #[serde(
+        default,
+        rename = "working-dir",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub working_dir: String,
+    #[serde(
+        default,
+        rename = "image-path",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub image_path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detached: Vec<String>,
+    /// Fields which we don't know, e.g. prep commands
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct AppsResponse {
+    apps: Vec<SunshineApp>,
+}
+
+#[derive(Serialize)]
+struct AddAppRequest<'a> {
+    #[serde(flatten)]
+    app: &'a SunshineApp,
+    /// -1 adds a new app
+    index: i32,
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    // Older versions of sunshine send it as a string
+    #[serde(deserialize_with = "de_status")]
+    status: bool,
+}
+
+fn de_status<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Status {
+        Bool(bool),
+        String(String),
+    }
+
+    Ok(match Status::deserialize(deserializer)? {
+        Status::Bool(value) => value,
+        Status::String(value) => value == "true",
+    })
+}
+
+impl SunshineApi {
+    /// The `server_certificate` is the one of the pair info of the host
+    pub fn new(
+        address: &str,
+        http_port: u16,
+        credentials: SunshineCredentials,
+        server_certificate: &Pem,
+    ) -> Result<Self, SunshineApiError> {
+        let server_certificate = Certificate::from_pem(server_certificate.to_string().as_bytes())?;
+
+        // The certificate doesn't contain the address of the host
+        let client = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(server_certificate)
+            .danger_accept_invalid_hostnames(true)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: format!(
+                "https://{address}:{}",
+                web_ui_port(http_port, Some(&credentials))
+            ),
+            credentials,
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{path}", self.base_url))
+            .basic_auth(&self.credentials.username, Some(&self.credentials.password))
+    }
+
+    async fn send_for_status(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<(), SunshineApiError> {
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<StatusResponse>()
+            .await?;
+
+        if response.status {
+            Ok(())
+        } else {
+            Err(SunshineApiError::Rejected)
+        }
+    }
+
+    pub async fn submit_pin(&self, pin: PairPin, name: &str) -> Result<(), SunshineApiError> {
+        self.send_for_status(
+            self.request(reqwest::Method::POST, "/api/pin")
+                .json(&PinRequest {
+                    pin: pin.to_string(),
+                    name,
+                }),
+        )
+        .await
+    }
+
+    /// Retries until the pending pair request reached Sunshine
+    pub async fn submit_pin_while_pairing(&self, pin: PairPin, name: &str) {
+        for attempt in 0..PIN_SUBMIT_ATTEMPTS {
+            sleep(PIN_SUBMIT_INTERVAL).await;
+
+            match self.submit_pin(pin, name).await {
+                Ok(()) => {
+                    info!("Submitted the pair pin to sunshine at {}", self.base_url);
+                    return;
+                }
+                Err(SunshineApiError::Rejected) => {
+                    debug!("Sunshine rejected the pair pin, attempt {attempt}");
+                }
+                Err(err) => {
+                    warn!("Failed to submit the pair pin to sunshine: {err}");
+                    return;
+                }
+            }
+        }
+
+        warn!(
+            "Sunshine at {} didn't accept the pair pin, it has to be entered manually",
+            self.base_url
+        );
+    }
+
+    pub async fn apps(&self) -> Result<Vec<SunshineApp>, SunshineApiError> {
+        let response = self
+            .request(reqwest::Method::GET, "/api/apps")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AppsResponse>()
+            .await?;
+
+        Ok(response.apps)
+    }
+
+    pub async fn add_app(&self, app: &SunshineApp) -> Result<(), SunshineApiError> {
+        self.send_for_status(
+            self.request(reqwest::Method::POST, "/api/apps")
+                .json(&AddAppRequest { app, index: -1 }),
+        )
+        .await
+    }
+
+    async fn config(&self) -> Result<Map<String, Value>, SunshineApiError> {
+        let mut config = self
+            .request(reqwest::Method::GET, "/api/config")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Map<String, Value>>()
+            .await?;
+
+        for key in CONFIG_STATUS_KEYS {
+            config.remove(*key);
+        }
+
+        Ok(config)
+    }
+
+    /// Only returns the settings which aren't the default of Sunshine
+    pub async fn encoder_settings(&self) -> Result<BTreeMap<String, String>, SunshineApiError> {
+        let config = self.config().await?;
+
+        Ok(config
+            .into_iter()
+            .filter(|(key, _)| ENCODER_SETTINGS.contains(&key.as_str()))
+            .map(|(key, value)| match value {
+                Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            })
+            .collect())
+    }
+
+    /// Sunshine replaces the whole config, so the current config is fetched and changed.
+    /// An empty value resets the setting to the default.
+    pub async fn set_encoder_settings(
+        &self,
+        settings: BTreeMap<String, String>,
+    ) -> Result<(), SunshineApiError> {
+        let mut config = self.config().await?;
+
+        for (key, value) in settings {
+            if value.is_empty() {
+                config.remove(&key);
+            } else {
+                config.insert(key, Value::String(value));
+            }
+        }
+
+        self.send_for_status(
+            self.request(reqwest::Method::POST, "/api/config")
+                .json(&config),
+        )
+        .await
+    }
+
+    pub async fn logs(&self) -> Result<String, SunshineApiError> {
+        Ok(self
+            .request(reqwest::Method::GET, "/api/logs")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+}
@@ -0,0 +1,227 @@
+//! Lists the peers of the Tailscale network of the web server through the local api of tailscaled.
+//!
+//! Hosts in the same Tailscale network are reachable without port forwarding,
+//! so their tailscale address is suggested when adding them.
+
+use std::{collections::HashMap, io, net::IpAddr};
+
+use common::config::TailscaleConfig;
+use serde::Deserialize;
+use thiserror::Error;
+
+#[cfg(unix)]
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// tailscaled rejects local api requests with another host header
+#[cfg(unix)]
+const LOCAL_API_HOST: &str = "local-tailscaled.sock";
+
+#[derive(Debug, Error)]
+pub enum TailscaleError {
+    #[error("tailscale is not enabled in the config")]
+    Disabled,
+    #[error("the tailscale local api is only supported on unix")]
+    Unsupported,
+    #[error("failed to reach the tailscale local api: {0}")]
+    Io(#[from] io::Error),
+    #[error("the tailscale local api sent an invalid http response")]
+    InvalidResponse,
+    #[error("the tailscale local api responded with \"{0}\"")]
+    Status(String),
+    #[error("failed to parse the tailscale status: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Status {
+    /// Null if there are no peers
+    #[serde(default)]
+    peer: Option<HashMap<String, PeerStatus>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PeerStatus {
+    pub host_name: String,
+    #[serde(rename = "DNSName", default)]
+    pub dns_name: String,
+    #[serde(rename = "OS", default)]
+    pub os: String,
+    #[serde(rename = "TailscaleIPs", default)]
+    pub tailscale_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub online: bool,
+}
+
+impl PeerStatus {
+    /// The ipv4 is preferred because the network of the web server might not route ipv6
+    pub fn address(&self) -> Option<IpAddr> {
+        self.tailscale_ips
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or(self.tailscale_ips.first())
+            .copied()
+    }
+
+    /// The MagicDNS name without the trailing dot
+    pub fn dns_name(&self) -> &str {
+        self.dns_name.trim_end_matches('.')
+    }
+}
+
+/// Tailscale assigns addresses of 100.64.0.0/10 and fd7a:115c:a1e0::/48, MagicDNS names end with ts.net.
+/// 100.64.0.0/10 is the carrier grade nat range, so other tunnels using it are also detected.
+pub fn is_tailscale_address(address: &str) -> bool {
+    match address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(IpAddr::V4(ip)) => {
+            let [first, second, ..] = ip.octets();
+            first == 100 && (64..128).contains(&second)
+        }
+        Ok(IpAddr::V6(ip)) => ip.segments()[0..3] == [0xfd7a, 0x115c, 0xa1e0],
+        Err(_) => address.trim_end_matches('.').ends_with(".ts.net"),
+    }
+}
+
+/// The peers sorted by their host name
+pub async fn peers(config: &TailscaleConfig) -> Result<Vec<PeerStatus>, TailscaleError> {
+    if !config.enabled {
+        return Err(TailscaleError::Disabled);
+    }
+
+    let body = local_api_get(&config.socket, "/localapi/v0/status").await?;
+
+    parse_peers(&body)
+}
+
+fn parse_peers(body: &[u8]) -> Result<Vec<PeerStatus>, TailscaleError> {
+    let status = serde_json::from_slice::<Status>(body)?;
+
+    let mut peers = status
+        .peer
+        .unwrap_or_default()
+        .into_values()
+        .collect::<Vec<_>>();
+    peers.sort_by(|a, b| a.host_name.cmp(&b.host_name));
+
+    Ok(peers)
+}
+
+#[cfg(unix)]
+async fn local_api_get(socket: &str, path: &str) -> Result<Vec<u8>, TailscaleError> {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+        time::timeout,
+    };
+
+    let request = async {
+        let mut stream = UnixStream::connect(socket).await?;
+
+        // Http 1.0 closes the connection after the response, so the body isn't chunked
+        stream
+            .write_all(format!("GET {path} HTTP/1.0\r\nHost: {LOCAL_API_HOST}\r\n\r\n").as_bytes())
+            .await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+
+        Ok::<_, io::Error>(response)
+    };
+
+    let response = timeout(REQUEST_TIMEOUT, request)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    http_response_body(response)
+}
+
+#[cfg(not(unix))]
+async fn local_api_get(_socket: &str, _path: &str) -> Result<Vec<u8>, TailscaleError> {
+    Err(TailscaleError::Unsupported)
+}
+
+#[cfg_attr(not(unix), allow(unused))]
+fn http_response_body(mut response: Vec<u8>) -> Result<Vec<u8>, TailscaleError> {
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or(TailscaleError::InvalidResponse)?;
+
+    let header = String::from_utf8_lossy(&response[..header_end]);
+    let status_line = header.lines().next().unwrap_or_default();
+
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => {}
+        Some(_) => return Err(TailscaleError::Status(status_line.to_string())),
+        None => return Err(TailscaleError::InvalidResponse),
+    }
+
+    Ok(response.split_off(header_end + 4))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::app::tailscale::{
+        TailscaleError, http_response_body, is_tailscale_address, parse_peers,
+    };
+
+    #[test]
+    fn test_tailscale_addresses() {
+        assert!(is_tailscale_address("100.101.102.103"));
+        assert!(is_tailscale_address("[fd7a:115c:a1e0::1]"));
+        assert!(is_tailscale_address("gamingpc.tail1234.ts.net"));
+
+        assert!(!is_tailscale_address("100.128.0.1"));
+        assert!(!is_tailscale_address("192.168.1.20"));
+        assert!(!is_tailscale_address("gamingpc.lan"));
+    }
+
+    #[test]
+    fn test_parse_peers() {
+        let status = br#"{
+            "Self": { "HostName": "server", "TailscaleIPs": ["100.64.0.1"] },
+            "Peer": {
+                "nodekey:b": {
+                    "HostName": "laptop",
+                    "DNSName": "laptop.tail1234.ts.net.",
+                    "OS": "linux",
+                    "TailscaleIPs": ["fd7a:115c:a1e0::2", "100.64.0.3"],
+                    "Online": false
+                },
+                "nodekey:a": {
+                    "HostName": "gamingpc",
+                    "DNSName": "gamingpc.tail1234.ts.net.",
+                    "OS": "windows",
+                    "TailscaleIPs": ["100.64.0.2"],
+                    "Online": true
+                }
+            }
+        }"#;
+
+        let peers = parse_peers(status).unwrap();
+
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].host_name, "gamingpc");
+        assert_eq!(peers[0].dns_name(), "gamingpc.tail1234.ts.net");
+        assert_eq!(peers[1].address().unwrap().to_string(), "100.64.0.3");
+
+        assert!(parse_peers(br#"{ "Peer": null }"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_http_response_body() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}".to_vec();
+        assert_eq!(http_response_body(response).unwrap(), b"{}");
+
+        let response = b"HTTP/1.0 403 Forbidden\r\n\r\naccess denied".to_vec();
+        assert!(matches!(
+            http_response_body(response),
+            Err(TailscaleError::Status(_))
+        ));
+    }
+}
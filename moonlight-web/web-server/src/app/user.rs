@@ -4,10 +4,13 @@ use std::{
     time::Duration,
 };
 
-use common::api_bindings::{self, DetailedUser};
+use common::{
+    api_bindings::{self, DetailedUser, TailscalePeer},
+    ipc::InputMacroEvent,
+};
 use moonlight_common::network::{
     ApiError, ClientInfo, host_info,
-    request_client::{RequestClient, RequestError},
+    request_client::{CancellationToken, RequestClient, RequestError},
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -15,13 +18,22 @@ use uuid::Uuid;
 use crate::app::{
     AppError, AppRef, MoonlightClient,
     auth::{SessionToken, UserAuth},
-    host::{Host, HostId},
+    host::{AppId, Host, HostId},
     password::StoragePassword,
     storage::{
-        StorageHostAdd, StorageHostCache, StorageQueryHosts, StorageUser, StorageUserModify,
+        StorageHostAdd, StorageHostCache, StorageInputMacro, StorageInputMacroAdd,
+        StoragePushSubscription, StorageQueryHosts, StorageStreamDefaults, StorageUser,
+        StorageUserModify, StorageUserStreamLimits,
+        query::{StorageCursor, StorageHostFilter, StoragePagination},
     },
+    tailscale::{self, is_tailscale_address},
 };
 
+/// Every macro is sent to the streamer when a stream starts
+const MAX_INPUT_MACROS: usize = 64;
+/// Every subscription is notified, one per browser of the user
+const MAX_PUSH_SUBSCRIPTIONS: usize = 10;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Role {
     User,
@@ -48,6 +60,26 @@ impl From<api_bindings::UserRole> for Role {
     }
 }
 
+impl From<StorageUserStreamLimits> for api_bindings::UserStreamLimits {
+    fn from(value: StorageUserStreamLimits) -> Self {
+        Self {
+            max_bitrate: value.max_bitrate,
+            max_width: value.max_width,
+            max_height: value.max_height,
+        }
+    }
+}
+
+impl From<api_bindings::UserStreamLimits> for StorageUserStreamLimits {
+    fn from(value: api_bindings::UserStreamLimits) -> Self {
+        Self {
+            max_bitrate: value.max_bitrate,
+            max_width: value.max_width,
+            max_height: value.max_height,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct UserId(pub u32);
 
@@ -94,7 +126,9 @@ impl User {
         &mut self,
         requesting_user: &mut AuthenticatedUser,
     ) -> Result<DetailedUser, AppError> {
-        if requesting_user.role().await? == Role::Admin || self.id() == requesting_user.id() {
+        let is_admin = !requesting_user.is_guest() && requesting_user.role().await? == Role::Admin;
+
+        if is_admin || self.id() == requesting_user.id() {
             self.detailed_user_no_auth().await
         } else {
             Err(AppError::Forbidden)
@@ -109,9 +143,19 @@ impl User {
             name: storage.name,
             role: storage.role.into(),
             client_unique_id: storage.client_unique_id,
+            stream_limits: storage.stream_limits.into(),
+            privacy_mode: storage.privacy_mode,
         })
     }
 
+    pub async fn stream_limits(&mut self) -> Result<StorageUserStreamLimits, AppError> {
+        Ok(self.storage_user().await?.stream_limits)
+    }
+
+    pub async fn privacy_mode(&mut self) -> Result<bool, AppError> {
+        Ok(self.storage_user().await?.privacy_mode)
+    }
+
     pub async fn modify(&mut self, _: &Admin, modify: StorageUserModify) -> Result<(), AppError> {
         let app = self.app.access()?;
 
@@ -131,9 +175,10 @@ impl User {
 
     pub async fn authenticate(mut self, auth: &UserAuth) -> Result<AuthenticatedUser, AppError> {
         match auth {
-            UserAuth::None if self.is_default_user().await? => {
-                Ok(AuthenticatedUser { inner: self })
-            }
+            UserAuth::None if self.is_default_user().await? => Ok(AuthenticatedUser {
+                inner: self,
+                is_guest: true,
+            }),
             UserAuth::UserPassword { username, password } => {
                 let storage = self.storage_user().await?;
 
@@ -145,7 +190,10 @@ impl User {
                 if let Some(storage_password) = storage.password
                     && storage_password.verify(password)?
                 {
-                    Ok(AuthenticatedUser { inner: self })
+                    Ok(AuthenticatedUser {
+                        inner: self,
+                        is_guest: false,
+                    })
                 } else {
                     Err(AppError::CredentialsWrong)
                 }
@@ -161,7 +209,10 @@ impl User {
 
                 self.cache_storage = self.cache_storage.or(user);
 
-                Ok(AuthenticatedUser { inner: self })
+                Ok(AuthenticatedUser {
+                    inner: self,
+                    is_guest: false,
+                })
             }
             UserAuth::ForwardedHeaders { username } => {
                 let app = self.app.access()?;
@@ -172,7 +223,10 @@ impl User {
 
                 let storage = self.storage_user().await?;
                 if storage.name.as_str() == username.as_str() {
-                    Ok(AuthenticatedUser { inner: self })
+                    Ok(AuthenticatedUser {
+                        inner: self,
+                        is_guest: false,
+                    })
                 } else {
                     Err(AppError::Forbidden)
                 }
@@ -185,6 +239,8 @@ impl User {
 #[derive(Clone)]
 pub struct AuthenticatedUser {
     pub(super) inner: User,
+    /// Logged in as the default user without any credentials
+    pub(super) is_guest: bool,
 }
 
 impl Deref for AuthenticatedUser {
@@ -205,6 +261,36 @@ impl AuthenticatedUser {
         self.detailed_user_no_auth().await
     }
 
+    pub fn is_guest(&self) -> bool {
+        self.is_guest
+    }
+    /// Checks the `default_user.allowed_hosts` if this is a guest
+    pub fn can_use_host(&self, host_id: HostId) -> Result<bool, AppError> {
+        if !self.is_guest {
+            return Ok(true);
+        }
+
+        let app = self.app.access()?;
+
+        Ok(match &app.config.web_server.default_user.allowed_hosts {
+            None => true,
+            Some(allowed_hosts) => allowed_hosts.contains(&host_id.0),
+        })
+    }
+    /// Checks the `default_user.allowed_apps` if this is a guest
+    pub fn can_use_app(&self, app_id: AppId) -> Result<bool, AppError> {
+        if !self.is_guest {
+            return Ok(true);
+        }
+
+        let app = self.app.access()?;
+
+        Ok(match &app.config.web_server.default_user.allowed_apps {
+            None => true,
+            Some(allowed_apps) => allowed_apps.contains(&app_id.0),
+        })
+    }
+
     pub async fn role(&mut self) -> Result<Role, AppError> {
         let storage = self.storage_user().await?;
 
@@ -229,6 +315,24 @@ impl AuthenticatedUser {
         Ok(())
     }
 
+    pub async fn set_privacy_mode(&mut self, privacy_mode: bool) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        self.cache_storage = None;
+
+        app.storage
+            .modify_user(
+                self.id,
+                StorageUserModify {
+                    privacy_mode: Some(privacy_mode),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn new_session(&self, expiration: Duration) -> Result<SessionToken, AppError> {
         let app = self.app.access()?;
 
@@ -240,6 +344,89 @@ impl AuthenticatedUser {
         Ok(token)
     }
 
+    pub async fn stream_defaults(
+        &self,
+        host_id: HostId,
+        app_id: AppId,
+    ) -> Result<Option<StorageStreamDefaults>, AppError> {
+        let app = self.app.access()?;
+
+        app.storage
+            .get_stream_defaults(self.id, host_id, app_id)
+            .await
+    }
+    pub async fn set_stream_defaults(
+        &self,
+        host_id: HostId,
+        app_id: AppId,
+        defaults: StorageStreamDefaults,
+    ) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage
+            .set_stream_defaults(self.id, host_id, app_id, defaults)
+            .await
+    }
+
+    pub async fn input_macros(&self) -> Result<Vec<StorageInputMacro>, AppError> {
+        let app = self.app.access()?;
+
+        app.storage.list_input_macros(self.id).await
+    }
+    pub async fn add_input_macro(
+        &self,
+        name: String,
+        events: Vec<InputMacroEvent>,
+    ) -> Result<StorageInputMacro, AppError> {
+        let app = self.app.access()?;
+
+        if app.storage.list_input_macros(self.id).await?.len() >= MAX_INPUT_MACROS {
+            return Err(AppError::InputMacroLimitReached);
+        }
+
+        app.storage
+            .add_input_macro(self.id, StorageInputMacroAdd { name, events })
+            .await
+    }
+    pub async fn remove_input_macro(&self, macro_id: u32) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage.remove_input_macro(self.id, macro_id).await
+    }
+
+    pub async fn add_push_subscription(
+        &self,
+        subscription: StoragePushSubscription,
+    ) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        let Some(web_push) = &app.web_push else {
+            return Err(AppError::WebPushDisabled);
+        };
+        web_push.validate_subscription(&subscription)?;
+
+        // Replacing the subscription of a browser doesn't count towards the limit
+        let subscriptions = app.storage.list_push_subscriptions(self.id).await?;
+        if subscriptions.len() >= MAX_PUSH_SUBSCRIPTIONS
+            && !subscriptions
+                .iter()
+                .any(|existing| existing.endpoint == subscription.endpoint)
+        {
+            return Err(AppError::PushSubscriptionLimitReached);
+        }
+
+        app.storage
+            .add_push_subscription(self.id, subscription)
+            .await
+    }
+    pub async fn remove_push_subscription(&self, endpoint: &str) -> Result<(), AppError> {
+        let app = self.app.access()?;
+
+        app.storage
+            .remove_push_subscription(self.id, endpoint)
+            .await
+    }
+
     pub async fn host_unique_id(&mut self) -> Result<String, AppError> {
         let user = self.storage_user().await?;
 
@@ -249,25 +436,69 @@ impl AuthenticatedUser {
     pub async fn hosts(&mut self) -> Result<Vec<Host>, AppError> {
         let app = self.app.access()?;
 
-        let hosts = app
+        let mut hosts = app
             .storage
             .list_user_hosts(StorageQueryHosts { user_id: self.id })
-            .await?
+            .await?;
+        if self.is_guest
+            && let Some(allowed_hosts) = &app.config.web_server.default_user.allowed_hosts
+        {
+            hosts.retain(|(host_id, _)| allowed_hosts.contains(&host_id.0));
+        }
+
+        let hosts = hosts
             .into_iter()
             .map(|(host_id, host)| Host {
                 app: self.app.clone(),
                 id: host_id,
                 cache_storage: host,
                 cache_host_info: None,
+                cancellation_token: CancellationToken::new(),
             })
             .collect();
 
         Ok(hosts)
     }
 
+    /// Like [Self::hosts] but filtered and paginated, returns the cursor of the next page
+    pub async fn query_hosts(
+        &mut self,
+        mut filter: StorageHostFilter,
+        pagination: StoragePagination,
+    ) -> Result<(Vec<Host>, Option<StorageCursor>), AppError> {
+        let app = self.app.access()?;
+
+        filter.accessible_by = Some(self.id);
+        if self.is_guest
+            && let Some(allowed_hosts) = &app.config.web_server.default_user.allowed_hosts
+        {
+            filter.host_ids = Some(allowed_hosts.iter().copied().map(HostId).collect());
+        }
+
+        let page = app.storage.query_hosts(filter, pagination).await?;
+
+        let hosts = page
+            .entries
+            .into_iter()
+            .map(|host| Host {
+                app: self.app.clone(),
+                id: host.id,
+                cache_storage: Some(host),
+                cache_host_info: None,
+                cancellation_token: CancellationToken::new(),
+            })
+            .collect();
+
+        Ok((hosts, page.next_cursor))
+    }
+
     pub async fn host(&mut self, host_id: HostId) -> Result<Host, AppError> {
         let app = self.app.access()?;
 
+        if !self.can_use_host(host_id)? {
+            return Err(AppError::Forbidden);
+        }
+
         let host = app.storage.get_host(host_id).await?;
 
         if host.owner.is_none() || host.owner == Some(self.id) {
@@ -276,27 +507,86 @@ impl AuthenticatedUser {
                 id: host.id,
                 cache_storage: Some(host),
                 cache_host_info: None,
+                cancellation_token: CancellationToken::new(),
             })
         } else {
             Err(AppError::Forbidden)
         }
     }
 
+    /// The peers of the Tailscale network, each with the host of this user which is likely the same machine
+    pub async fn tailscale_peers(&mut self) -> Result<Vec<TailscalePeer>, AppError> {
+        if self.is_guest {
+            return Err(AppError::Forbidden);
+        }
+
+        let app = self.app.access()?;
+
+        let peers = tailscale::peers(&app.config.tailscale).await?;
+
+        let mut hosts = Vec::new();
+        for (host_id, host) in app
+            .storage
+            .list_user_hosts(StorageQueryHosts { user_id: self.id })
+            .await?
+        {
+            let host = match host {
+                Some(host) => host,
+                None => app.storage.get_host(host_id).await?,
+            };
+            hosts.push(host);
+        }
+
+        Ok(peers
+            .into_iter()
+            .map(|peer| {
+                let suggested_host_id = hosts
+                    .iter()
+                    .find(|host| {
+                        host.cache.name.eq_ignore_ascii_case(&peer.host_name)
+                            && !is_tailscale_address(&host.address)
+                    })
+                    .map(|host| host.id.0);
+
+                TailscalePeer {
+                    address: peer
+                        .address()
+                        .map(|address| address.to_string())
+                        .unwrap_or_else(|| peer.dns_name().to_string()),
+                    addresses: peer
+                        .tailscale_ips
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect(),
+                    dns_name: peer.dns_name().to_string(),
+                    host_name: peer.host_name,
+                    os: peer.os,
+                    online: peer.online,
+                    suggested_host_id,
+                }
+            })
+            .collect())
+    }
+
     pub async fn host_add(&mut self, address: String, http_port: u16) -> Result<Host, AppError> {
         let app = self.app.access()?;
 
         let unique_id = self.host_unique_id().await?;
 
-        let mut client = MoonlightClient::with_defaults().map_err(ApiError::RequestClient)?;
+        let options = app.config.moonlight.request_client_options(None);
+        let mut client =
+            MoonlightClient::with_defaults(&options).map_err(ApiError::RequestClient)?;
 
         let info = match host_info(
             &mut client,
+            &CancellationToken::new(),
             false,
             &format!("{}:{}", address, http_port),
             Some(ClientInfo {
                 uuid: Uuid::new_v4(),
                 unique_id: &unique_id,
             }),
+            app.config.moonlight.xml_parse_mode(None),
         )
         .await
         {
@@ -326,6 +616,7 @@ impl AuthenticatedUser {
             id: host.id,
             cache_storage: Some(host),
             cache_host_info: None,
+            cancellation_token: CancellationToken::new(),
         })
     }
 
@@ -352,6 +643,11 @@ impl Admin {
     pub async fn try_from(
         mut user: AuthenticatedUser,
     ) -> Result<Result<Admin, AuthenticatedUser>, AppError> {
+        // Guests are never admins, even if the default user is one
+        if user.is_guest {
+            return Ok(Err(user));
+        }
+
         match user.role().await? {
             Role::Admin => Ok(Ok(Self(user))),
             _ => Ok(Err(user)),
@@ -0,0 +1,533 @@
+//! Sends web push messages to the browsers of users, see [RFC 8030](https://www.rfc-editor.org/rfc/rfc8030).
+//!
+//! The payload is encrypted for the browser with aes128gcm ([RFC 8291](https://www.rfc-editor.org/rfc/rfc8291))
+//! and the web server identifies itself to the push service with vapid ([RFC 8292](https://www.rfc-editor.org/rfc/rfc8292)).
+
+use std::{
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::{api_bindings::PushNotification, config::WebPushConfig};
+use log::{debug, warn};
+use openssl::{
+    base64,
+    bn::BigNumContext,
+    derive::Deriver,
+    ec::{EcGroup, EcKey, EcPoint, PointConversionForm},
+    ecdsa::EcdsaSig,
+    error::ErrorStack,
+    hash::{MessageDigest, hash},
+    nid::Nid,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    sign::Signer,
+    symm::{Cipher, encrypt_aead},
+};
+use reqwest::{Client, StatusCode, Url};
+use serde_json::json;
+use thiserror::Error;
+use tokio::{fs, spawn};
+
+use crate::app::{
+    AppError, AppInner, AppRef,
+    host::HostId,
+    storage::{Either, StoragePushSubscription},
+    user::{Role, UserId},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Push services reject tokens which expire in more than 24 hours
+const VAPID_TOKEN_EXPIRATION: Duration = Duration::from_secs(12 * 60 * 60);
+/// The payload always fits into one record
+const RECORD_SIZE: u32 = 4096;
+
+const PUBLIC_KEY_LEN: usize = 65;
+const AUTH_SECRET_LEN: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum WebPushError {
+    #[error("openssl error: {0}")]
+    OpenSSL(#[from] ErrorStack),
+    #[error("failed to load the vapid key: {0}")]
+    Io(#[from] io::Error),
+    #[error("the keys of the push subscription are invalid")]
+    InvalidSubscription,
+    #[error("the endpoint of the push subscription isn't an allowed push service")]
+    PushServiceNotAllowed,
+    #[error("the push service is unreachable: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The browser unsubscribed, the subscription should be removed
+    #[error("the push subscription expired")]
+    Expired,
+    #[error("the push service responded with {0}")]
+    Status(StatusCode),
+}
+
+pub struct WebPush {
+    config: WebPushConfig,
+    key: EcKey<Private>,
+    /// The uncompressed point of the vapid public key
+    public_key: Vec<u8>,
+    client: Client,
+}
+
+impl WebPush {
+    /// Loads the vapid key or generates it on the first start
+    pub async fn load(config: WebPushConfig) -> Result<Self, WebPushError> {
+        let path = Path::new(&config.vapid_key_path);
+
+        let key = match fs::read(path).await {
+            Ok(pem) => EcKey::private_key_from_pem(&pem)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let key = EcKey::generate(&p256()?)?;
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(path, key.private_key_to_pem()?).await?;
+
+                key
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let public_key = public_key_bytes(&key)?;
+
+        Ok(Self {
+            config,
+            key,
+            public_key,
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+        })
+    }
+
+    /// The `applicationServerKey` with which browsers subscribe
+    pub fn public_key(&self) -> String {
+        base64_url_encode(&self.public_key)
+    }
+
+    /// Checked before a subscription is stored
+    pub fn validate_subscription(
+        &self,
+        subscription: &StoragePushSubscription,
+    ) -> Result<(), WebPushError> {
+        validate_endpoint(&subscription.endpoint, &self.config.push_services)?;
+        subscription_keys(subscription)?;
+
+        Ok(())
+    }
+
+    pub async fn send(
+        &self,
+        subscription: &StoragePushSubscription,
+        notification: &PushNotification,
+    ) -> Result<(), WebPushError> {
+        // Subscriptions which were stored before the push service was removed from the config
+        let endpoint = validate_endpoint(&subscription.endpoint, &self.config.push_services)?;
+
+        let payload = serde_json::to_vec(notification).map_err(io::Error::other)?;
+        let body = encrypt(subscription, &payload)?;
+        let token = self.vapid_token(&endpoint)?;
+
+        let response = self
+            .client
+            .post(endpoint)
+            .header("TTL", self.config.ttl.as_secs().to_string())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header(
+                "Authorization",
+                format!("vapid t={token}, k={}", self.public_key()),
+            )
+            .body(body)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND | StatusCode::GONE => Err(WebPushError::Expired),
+            status => Err(WebPushError::Status(status)),
+        }
+    }
+
+    /// A jwt signed with ES256 for the origin of the push service
+    fn vapid_token(&self, endpoint: &Url) -> Result<String, WebPushError> {
+        let expiration = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            + VAPID_TOKEN_EXPIRATION;
+
+        let header = json!({ "typ": "JWT", "alg": "ES256" });
+        let claims = json!({
+            "aud": endpoint.origin().ascii_serialization(),
+            "exp": expiration.as_secs(),
+            "sub": self.config.subject,
+        });
+        let unsigned = format!(
+            "{}.{}",
+            base64_url_encode(header.to_string().as_bytes()),
+            base64_url_encode(claims.to_string().as_bytes())
+        );
+
+        let digest = hash(MessageDigest::sha256(), unsigned.as_bytes())?;
+        let signature = EcdsaSig::sign(&digest, &self.key)?;
+
+        // JWS wants the raw r and s instead of der
+        let mut raw_signature = signature.r().to_vec_padded(32)?;
+        raw_signature.extend(signature.s().to_vec_padded(32)?);
+
+        Ok(format!("{unsigned}.{}", base64_url_encode(&raw_signature)))
+    }
+}
+
+/// Who receives a push notification
+pub enum PushRecipients {
+    User(UserId),
+    Admins,
+}
+
+impl AppRef {
+    /// Sends the notification to every browser of the recipients in the background, expired subscriptions are removed
+    pub(super) fn push(&self, recipients: PushRecipients, notification: PushNotification) {
+        let app = self.clone();
+
+        spawn(async move {
+            let Ok(inner) = app.access() else {
+                return;
+            };
+            let Some(web_push) = inner.web_push.as_ref() else {
+                return;
+            };
+
+            let user_ids = match recipients {
+                PushRecipients::User(user_id) => vec![user_id],
+                PushRecipients::Admins => match admin_ids(&inner).await {
+                    Ok(user_ids) => user_ids,
+                    Err(err) => {
+                        warn!("[Web Push]: failed to list the admins: {err}");
+                        return;
+                    }
+                },
+            };
+
+            for user_id in user_ids {
+                let subscriptions = match inner.storage.list_push_subscriptions(user_id).await {
+                    Ok(subscriptions) => subscriptions,
+                    Err(err) => {
+                        warn!("[Web Push]: failed to list the subscriptions of {user_id:?}: {err}");
+                        continue;
+                    }
+                };
+
+                for subscription in subscriptions {
+                    match web_push.send(&subscription, &notification).await {
+                        Ok(()) => {}
+                        Err(WebPushError::Expired) => {
+                            debug!("[Web Push]: removing an expired subscription of {user_id:?}");
+
+                            if let Err(err) = inner
+                                .storage
+                                .remove_push_subscription(user_id, &subscription.endpoint)
+                                .await
+                            {
+                                warn!(
+                                    "[Web Push]: failed to remove an expired subscription: {err}"
+                                );
+                            }
+                        }
+                        Err(err) => {
+                            warn!("[Web Push]: failed to notify {user_id:?}: {err}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn admin_ids(inner: &AppInner) -> Result<Vec<UserId>, AppError> {
+    match inner.storage.list_users().await? {
+        Either::Left(user_ids) => {
+            let mut admin_ids = Vec::new();
+            for user_id in user_ids {
+                if inner.storage.get_user(user_id).await?.role == Role::Admin {
+                    admin_ids.push(user_id);
+                }
+            }
+
+            Ok(admin_ids)
+        }
+        Either::Right(users) => Ok(users
+            .into_iter()
+            .filter(|user| user.role == Role::Admin)
+            .map(|user| user.id)
+            .collect()),
+    }
+}
+
+/// The name for notifications, the id if the host can't be loaded
+pub(super) async fn host_name(inner: &AppInner, host_id: HostId) -> String {
+    match inner.storage.get_host(host_id).await {
+        Ok(host) => host.cache.name,
+        Err(_) => format!("host {}", host_id.0),
+    }
+}
+
+fn p256() -> Result<EcGroup, ErrorStack> {
+    EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+}
+
+fn public_key_bytes(key: &EcKey<impl openssl::pkey::HasPublic>) -> Result<Vec<u8>, ErrorStack> {
+    let mut context = BigNumContext::new()?;
+
+    key.public_key()
+        .to_bytes(&p256()?, PointConversionForm::UNCOMPRESSED, &mut context)
+}
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    for data in data {
+        signer.update(data)?;
+    }
+
+    signer.sign_to_vec()
+}
+
+/// Push services only accept https and must be one of the configured domains or a subdomain of them.
+/// Ip addresses are never accepted, so subscriptions can't make the web server send requests into its own network.
+fn validate_endpoint(endpoint: &str, push_services: &[String]) -> Result<Url, WebPushError> {
+    let endpoint = Url::parse(endpoint).map_err(|_| WebPushError::InvalidSubscription)?;
+    if endpoint.scheme() != "https" {
+        return Err(WebPushError::InvalidSubscription);
+    }
+
+    // None for ip addresses
+    let Some(domain) = endpoint.domain() else {
+        return Err(WebPushError::PushServiceNotAllowed);
+    };
+    let allowed = push_services.iter().any(|service| {
+        domain == service
+            || domain
+                .strip_suffix(service.as_str())
+                .is_some_and(|subdomain| subdomain.ends_with('.'))
+    });
+    if !allowed {
+        return Err(WebPushError::PushServiceNotAllowed);
+    }
+
+    Ok(endpoint)
+}
+
+/// The public key and the auth secret of the browser
+fn subscription_keys(
+    subscription: &StoragePushSubscription,
+) -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+    let public_key = base64_url_decode(&subscription.p256dh)
+        .filter(|key| key.len() == PUBLIC_KEY_LEN)
+        .ok_or(WebPushError::InvalidSubscription)?;
+    let auth_secret = base64_url_decode(&subscription.auth)
+        .filter(|auth| auth.len() == AUTH_SECRET_LEN)
+        .ok_or(WebPushError::InvalidSubscription)?;
+
+    Ok((public_key, auth_secret))
+}
+
+/// The aes128gcm content encoding with one record and a new key for every message
+fn encrypt(
+    subscription: &StoragePushSubscription,
+    payload: &[u8],
+) -> Result<Vec<u8>, WebPushError> {
+    let (user_agent_public_key, auth_secret) = subscription_keys(subscription)?;
+
+    let group = p256()?;
+    let mut context = BigNumContext::new()?;
+    let user_agent_point = EcPoint::from_bytes(&group, &user_agent_public_key, &mut context)
+        .map_err(|_| WebPushError::InvalidSubscription)?;
+    let user_agent_key = PKey::from_ec_key(EcKey::from_public_key(&group, &user_agent_point)?)?;
+
+    let server_key = EcKey::generate(&group)?;
+    let server_public_key = public_key_bytes(&server_key)?;
+    let server_key = PKey::from_ec_key(server_key)?;
+
+    let mut deriver = Deriver::new(&server_key)?;
+    deriver.set_peer(&user_agent_key)?;
+    let shared_secret = deriver.derive_to_vec()?;
+
+    let mut salt = [0u8; 16];
+    rand_bytes(&mut salt)?;
+
+    // The HKDF expands only need one block, so they're a single hmac
+    let key_prk = hmac_sha256(&auth_secret, &[&shared_secret])?;
+    let ikm = hmac_sha256(
+        &key_prk,
+        &[
+            b"WebPush: info\0",
+            &user_agent_public_key,
+            &server_public_key,
+            &[1],
+        ],
+    )?;
+    let prk = hmac_sha256(&salt, &[&ikm])?;
+    let content_encryption_key = hmac_sha256(&prk, &[b"Content-Encoding: aes128gcm\0", &[1]])?;
+    let nonce = hmac_sha256(&prk, &[b"Content-Encoding: nonce\0", &[1]])?;
+
+    // The delimiter of the last record
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_128_gcm(),
+        &content_encryption_key[..16],
+        Some(&nonce[..12]),
+        &[],
+        &plaintext,
+        &mut tag,
+    )?;
+
+    let mut body =
+        Vec::with_capacity(salt.len() + 5 + server_public_key.len() + ciphertext.len() + tag.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(server_public_key.len() as u8);
+    body.extend_from_slice(&server_public_key);
+    body.extend_from_slice(&ciphertext);
+    body.extend_from_slice(&tag);
+
+    Ok(body)
+}
+
+fn base64_url_encode(data: &[u8]) -> String {
+    base64::encode_block(data)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Browsers encode without padding, but some add it anyway
+pub fn base64_url_decode(text: &str) -> Option<Vec<u8>> {
+    let mut text = text
+        .trim_end_matches('=')
+        .replace('-', "+")
+        .replace('_', "/");
+    while !text.len().is_multiple_of(4) {
+        text.push('=');
+    }
+
+    base64::decode_block(&text).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use openssl::{
+        bn::BigNumContext,
+        derive::Deriver,
+        ec::{EcGroup, EcKey, EcPoint},
+        nid::Nid,
+        pkey::PKey,
+        symm::{Cipher, decrypt_aead},
+    };
+
+    use crate::app::{
+        storage::StoragePushSubscription,
+        web_push::{
+            WebPushError, base64_url_decode, base64_url_encode, encrypt, hmac_sha256,
+            public_key_bytes, validate_endpoint,
+        },
+    };
+
+    #[test]
+    fn test_validate_endpoint() {
+        let push_services = [
+            "fcm.googleapis.com".to_string(),
+            "push.apple.com".to_string(),
+        ];
+        let allowed = |endpoint: &str| validate_endpoint(endpoint, &push_services).is_ok();
+
+        assert!(allowed("https://fcm.googleapis.com/fcm/send/abc"));
+        assert!(allowed("https://web.push.apple.com/abc"));
+
+        assert!(!allowed("http://fcm.googleapis.com/fcm/send/abc"));
+        assert!(!allowed("https://evilfcm.googleapis.com.example.com/"));
+        assert!(!allowed("https://evilpush.apple.com/"));
+        assert!(!allowed("https://localhost/"));
+        assert!(!allowed("https://internal.lan/"));
+        assert!(matches!(
+            validate_endpoint("https://127.0.0.1/", &push_services),
+            Err(WebPushError::PushServiceNotAllowed)
+        ));
+        assert!(!allowed("https://192.168.1.1/"));
+        assert!(!allowed("https://169.254.169.254/latest/meta-data"));
+        assert!(!allowed("https://[::1]/"));
+        assert!(!allowed("not a url"));
+    }
+
+    #[test]
+    fn test_base64_url() {
+        let data = [0xfb, 0xff, 0x01, 0x02];
+
+        let encoded = base64_url_encode(&data);
+        assert_eq!(encoded, "-_8BAg");
+        assert_eq!(base64_url_decode(&encoded).unwrap(), data);
+        assert_eq!(base64_url_decode("-_8BAg==").unwrap(), data);
+    }
+
+    /// Decrypts the message like a browser would
+    #[test]
+    fn test_encrypt() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let browser_key = EcKey::generate(&group).unwrap();
+        let browser_public_key = public_key_bytes(&browser_key).unwrap();
+        let auth_secret = [7u8; 16];
+
+        let subscription = StoragePushSubscription {
+            endpoint: "https://push.example.com/send/abc".to_string(),
+            p256dh: base64_url_encode(&browser_public_key),
+            auth: base64_url_encode(&auth_secret),
+        };
+        let body = encrypt(&subscription, b"hello").unwrap();
+
+        let salt = &body[..16];
+        assert_eq!(&body[16..20], &4096u32.to_be_bytes());
+        assert_eq!(body[20], 65);
+        let server_public_key = &body[21..86];
+        let (ciphertext, tag) = body[86..].split_at(body.len() - 86 - 16);
+
+        let mut context = BigNumContext::new().unwrap();
+        let server_point = EcPoint::from_bytes(&group, server_public_key, &mut context).unwrap();
+        let server_key =
+            PKey::from_ec_key(EcKey::from_public_key(&group, &server_point).unwrap()).unwrap();
+        let browser_key = PKey::from_ec_key(browser_key).unwrap();
+        let mut deriver = Deriver::new(&browser_key).unwrap();
+        deriver.set_peer(&server_key).unwrap();
+        let shared_secret = deriver.derive_to_vec().unwrap();
+
+        let key_prk = hmac_sha256(&auth_secret, &[&shared_secret]).unwrap();
+        let ikm = hmac_sha256(
+            &key_prk,
+            &[
+                b"WebPush: info\0",
+                &browser_public_key,
+                server_public_key,
+                &[1],
+            ],
+        )
+        .unwrap();
+        let prk = hmac_sha256(salt, &[&ikm]).unwrap();
+        let key = hmac_sha256(&prk, &[b"Content-Encoding: aes128gcm\0", &[1]]).unwrap();
+        let nonce = hmac_sha256(&prk, &[b"Content-Encoding: nonce\0", &[1]]).unwrap();
+
+        let plaintext = decrypt_aead(
+            Cipher::aes_128_gcm(),
+            &key[..16],
+            Some(&nonce[..12]),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .unwrap();
+        assert_eq!(plaintext, b"hello\x02");
+    }
+}
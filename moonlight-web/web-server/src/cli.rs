@@ -1,14 +1,15 @@
 use std::{
     env,
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
 };
 
 use clap::{Args, Parser, Subcommand};
 use common::{
     api_bindings::RtcIceServer,
     config::{
-        Config, ConfigSsl, ForwardedHeaders, PortRange, WebRtcNat1To1IceCandidateType,
-        WebRtcNat1To1Mapping, WebRtcNetworkType,
+        Config, ConfigSsl, ForwardedHeaders, PortRange, StorageConfig,
+        WebRtcNat1To1IceCandidateType, WebRtcNat1To1Mapping, WebRtcNetworkType,
     },
 };
 use log::LevelFilter;
@@ -65,6 +66,61 @@ pub enum Command {
     Run,
     /// Prints the config into stdout in json format
     PrintConfig,
+    #[command(flatten)]
+    Admin(AdminCommand),
+}
+
+/// These operate directly on the storage and can be used to regain access without the web interface.
+/// The server shouldn't be running at the same time because it'll overwrite the changes.
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// Adds a new user, the password is read from stdin if not specified
+    #[command(name = "adduser")]
+    AddUser {
+        name: String,
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long, default_value_t = false)]
+        admin: bool,
+    },
+    /// Changes the password of a user and removes all sessions of that user, the password is read from stdin if not specified
+    #[command(name = "resetpassword")]
+    ResetPassword {
+        name: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Lists the hosts of all users
+    #[command(name = "listhosts")]
+    ListHosts,
+    /// Removes the pairing of a host so that it can be paired again
+    Unpair { host_id: u32 },
+    /// Uses the pairing of an existing Moonlight client for a host, so it doesn't need to be paired again
+    ImportPairing {
+        host_id: u32,
+        /// The Moonlight.conf of Moonlight Qt, the certificate of the host is taken from the host with the same address
+        #[arg(long, conflicts_with_all = ["certificate", "private_key"], required_unless_present = "certificate")]
+        moonlight_qt_config: Option<PathBuf>,
+        /// The client certificate in the pem or der format, e.g. client.crt of moonlight-android
+        #[arg(long, requires_all = ["private_key", "server_certificate"])]
+        certificate: Option<PathBuf>,
+        /// The client private key in the pem or der format, e.g. client.key of moonlight-android
+        #[arg(long, requires = "certificate")]
+        private_key: Option<PathBuf>,
+        /// The certificate of the host in the pem or der format, overwrites the one found in the Moonlight Qt config
+        #[arg(long)]
+        server_certificate: Option<PathBuf>,
+    },
+    /// Copies all users and hosts into another storage, e.g. "json:server/new_data.json".
+    /// Without `--to` the storage is written back in the newest format.
+    MigrateStorage {
+        /// The storage to copy from, defaults to the `data_storage` in the config
+        #[arg(long)]
+        from: Option<StorageConfig>,
+        /// The storage to copy into, it must not contain any users
+        #[arg(long)]
+        to: Option<StorageConfig>,
+    },
 }
 
 #[derive(Args)]
@@ -102,8 +158,14 @@ pub struct CliConfig {
     /// Overwrites `log.log_file_path`.
     #[arg(long, env = "LOG_FILE")]
     pub log_file: Option<String>,
+    /// Overwrites `file_transfer.directory`.
+    #[arg(long, env = "FILE_TRANSFER_DIRECTORY")]
+    pub file_transfer_directory: Option<String>,
     #[arg(long, env = "STREAMER_PATH")]
     pub streamer_path: Option<String>,
+    /// Overwrites `streamer_sandbox.enabled`.
+    #[arg(long, env = "STREAMER_SANDBOX")]
+    pub streamer_sandbox: Option<bool>,
     /// Disables the STUN ice server which are bundled by default.
     /// This only disables the generation of them in the first config.
     /// After the config.json has been generated the ice servers in the config will be used regardless if this is set.
@@ -169,9 +231,15 @@ impl CliConfig {
         if let Some(log_file) = self.log_file {
             config.log.file_path = Some(log_file);
         }
+        if let Some(file_transfer_directory) = self.file_transfer_directory {
+            config.file_transfer.directory = Some(file_transfer_directory);
+        }
         if let Some(streamer_path) = self.streamer_path {
             config.streamer_path = streamer_path;
         }
+        if let Some(streamer_sandbox) = self.streamer_sandbox {
+            config.streamer_sandbox.enabled = streamer_sandbox;
+        }
         if self.disable_default_webrtc_ice_servers {
             config
                 .webrtc
@@ -0,0 +1,435 @@
+//! Reports panics and error logs to Sentry or a webhook, see [ErrorReportingConfig].
+//!
+//! Reports are queued from the logger and the panic hook without blocking and sent by a background task.
+
+use std::{
+    cell::Cell,
+    panic,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use common::config::{ErrorReportingConfig, ErrorReportingTarget, LogIpAnonymization};
+use log::{Level, LevelFilter, Log, Metadata, Record, warn};
+use openssl::rand::rand_bytes;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use serde_json::json;
+use simplelog::{Config, SharedLogger};
+use thiserror::Error;
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+
+use crate::{
+    app::{diagnostics::SessionDiagnostics, host::HostId, user::UserId},
+    logging::anonymize_ips,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Reports above this are dropped while the background task is still sending
+const QUEUE_SIZE: usize = 64;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The message of errors of sessions in privacy mode
+const PRIVATE_MESSAGE: &str = "[hidden by privacy mode]";
+
+static REPORTS: OnceLock<Sender<ErrorReport>> = OnceLock::new();
+
+thread_local! {
+    /// Set while logging an error which is reported separately with more context
+    static LOGGING_UNREPORTED: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Panic,
+    Error,
+}
+
+impl ErrorKind {
+    fn level(&self) -> &'static str {
+        match self {
+            Self::Panic => "fatal",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorProcess {
+    WebServer,
+    Streamer,
+}
+
+impl ErrorProcess {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::WebServer => "web-server",
+            Self::Streamer => "streamer",
+        }
+    }
+}
+
+/// The session in which the error happened
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorContext {
+    pub session_id: u32,
+    pub host_id: HostId,
+    pub user_id: UserId,
+    pub private: bool,
+}
+
+impl ErrorContext {
+    pub fn session(diagnostics: &SessionDiagnostics) -> Self {
+        Self {
+            session_id: diagnostics.id().0,
+            host_id: diagnostics.host_id(),
+            user_id: diagnostics.user_id(),
+            private: diagnostics.is_private(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    pub process: ErrorProcess,
+    pub target: String,
+    pub message: String,
+    pub context: Option<ErrorContext>,
+}
+
+/// Starts sending reports if a target is configured, must be called inside of the runtime after the logger is initialized
+pub fn init_error_reporting(config: &ErrorReportingConfig, anonymization: LogIpAnonymization) {
+    let Some(target) = config.target.clone() else {
+        return;
+    };
+
+    let target = match ReportTarget::new(target) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("[Error Reporting]: not reporting errors because the target is invalid: {err}");
+            return;
+        }
+    };
+
+    let client = match Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("[Error Reporting]: failed to create the http client: {err}");
+            return;
+        }
+    };
+
+    let (sender, receiver) = channel(QUEUE_SIZE);
+    if REPORTS.set(sender).is_err() {
+        warn!("[Error Reporting]: error reporting is already initialized");
+        return;
+    }
+
+    tokio::spawn(send_reports(
+        SendContext {
+            client,
+            target,
+            environment: config.environment.clone(),
+            anonymization,
+            max_reports_per_minute: config.max_reports_per_minute,
+        },
+        receiver,
+    ));
+
+    let default_panic = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report(ErrorReport {
+            kind: ErrorKind::Panic,
+            process: ErrorProcess::WebServer,
+            target: std::thread::current()
+                .name()
+                .unwrap_or("unnamed thread")
+                .to_string(),
+            message: info.to_string(),
+            context: None,
+        });
+
+        default_panic(info);
+    }));
+}
+
+/// Queues the report, it's dropped if reporting is disabled or too many reports are queued
+pub fn report(report: ErrorReport) {
+    if let Some(sender) = REPORTS.get() {
+        let _ = sender.try_send(report);
+    }
+}
+
+/// Logs without reporting errors, used if the error is reported with [report] to include its context
+pub fn log_unreported(log: impl FnOnce()) {
+    LOGGING_UNREPORTED.set(true);
+    log();
+    LOGGING_UNREPORTED.set(false);
+}
+
+// -- Logger
+
+/// Reports all error logs of the web server
+pub struct ErrorReportingLogger;
+
+impl Log for ErrorReportingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Error
+            // The requests of this module must never report their own errors
+            && !metadata.target().starts_with(module_path!())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) || LOGGING_UNREPORTED.get() {
+            return;
+        }
+
+        report(ErrorReport {
+            kind: ErrorKind::Error,
+            process: ErrorProcess::WebServer,
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            context: None,
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for ErrorReportingLogger {
+    fn level(&self) -> LevelFilter {
+        LevelFilter::Error
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+// -- Sending
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum InvalidTargetError {
+    #[error("the url is invalid")]
+    Url,
+    #[error("the dsn doesn't contain a public key")]
+    MissingKey,
+    #[error("the dsn doesn't contain a project id")]
+    MissingProject,
+}
+
+#[derive(Debug)]
+enum ReportTarget {
+    Sentry {
+        envelope_url: Url,
+        public_key: String,
+    },
+    Webhook {
+        url: Url,
+    },
+}
+
+impl ReportTarget {
+    fn new(target: ErrorReportingTarget) -> Result<Self, InvalidTargetError> {
+        match target {
+            ErrorReportingTarget::Sentry { dsn } => {
+                let (envelope_url, public_key) = parse_sentry_dsn(&dsn)?;
+                Ok(Self::Sentry {
+                    envelope_url,
+                    public_key,
+                })
+            }
+            ErrorReportingTarget::Webhook { url } => Ok(Self::Webhook {
+                url: Url::parse(&url).map_err(|_| InvalidTargetError::Url)?,
+            }),
+        }
+    }
+}
+
+/// Returns the envelope endpoint and the public key of a dsn like `https://KEY@HOST/PATH/PROJECT_ID`
+fn parse_sentry_dsn(dsn: &str) -> Result<(Url, String), InvalidTargetError> {
+    let mut url = Url::parse(dsn).map_err(|_| InvalidTargetError::Url)?;
+
+    let public_key = url.username().to_string();
+    if public_key.is_empty() {
+        return Err(InvalidTargetError::MissingKey);
+    }
+
+    let path = url.path().trim_end_matches('/').to_string();
+    let (prefix, project_id) = path.rsplit_once('/').unwrap_or(("", path.as_str()));
+    if project_id.is_empty() {
+        return Err(InvalidTargetError::MissingProject);
+    }
+
+    url.set_path(&format!("{prefix}/api/{project_id}/envelope/"));
+    url.set_username("")
+        .and_then(|_| url.set_password(None))
+        .map_err(|_| InvalidTargetError::Url)?;
+
+    Ok((url, public_key))
+}
+
+struct SendContext {
+    client: Client,
+    target: ReportTarget,
+    environment: Option<String>,
+    anonymization: LogIpAnonymization,
+    max_reports_per_minute: u32,
+}
+
+async fn send_reports(context: SendContext, mut receiver: Receiver<ErrorReport>) {
+    let mut window_start = Instant::now();
+    let mut window_reports = 0;
+
+    while let Some(report) = receiver.recv().await {
+        if window_start.elapsed() >= Duration::from_mins(1) {
+            window_start = Instant::now();
+            window_reports = 0;
+        }
+        if window_reports >= context.max_reports_per_minute {
+            continue;
+        }
+        window_reports += 1;
+
+        if let Err(err) = send_report(&context, report).await {
+            warn!("[Error Reporting]: failed to send a report: {err}");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookReport<'a> {
+    level: &'a str,
+    process: &'a str,
+    target: &'a str,
+    message: &'a str,
+    release: &'a str,
+    environment: Option<&'a str>,
+    timestamp: String,
+    session_id: Option<u32>,
+    host_id: Option<u32>,
+    user_id: Option<u32>,
+}
+
+async fn send_report(context: &SendContext, report: ErrorReport) -> Result<(), reqwest::Error> {
+    let message = if report.context.is_some_and(|context| context.private) {
+        PRIVATE_MESSAGE.to_string()
+    } else {
+        anonymize_ips(&report.message, context.anonymization).into_owned()
+    };
+    let timestamp = Utc::now().to_rfc3339();
+
+    let request = match &context.target {
+        ReportTarget::Webhook { url } => context.client.post(url.clone()).json(&WebhookReport {
+            level: report.kind.level(),
+            process: report.process.name(),
+            target: &report.target,
+            message: &message,
+            release: VERSION,
+            environment: context.environment.as_deref(),
+            timestamp,
+            session_id: report.context.map(|context| context.session_id),
+            host_id: report.context.map(|context| context.host_id.0),
+            user_id: report.context.map(|context| context.user_id.0),
+        }),
+        ReportTarget::Sentry {
+            envelope_url,
+            public_key,
+        } => {
+            let mut event_id = [0u8; 16];
+            if rand_bytes(&mut event_id).is_err() {
+                warn!("[Error Reporting]: failed to generate an event id");
+            }
+            let event_id = hex::encode(event_id);
+
+            let mut tags = json!({ "process": report.process.name() });
+            if let Some(error_context) = report.context {
+                tags["session_id"] = error_context.session_id.to_string().into();
+                tags["host_id"] = error_context.host_id.0.to_string().into();
+                tags["user_id"] = error_context.user_id.0.to_string().into();
+            }
+
+            let event = json!({
+                "event_id": event_id,
+                "timestamp": timestamp,
+                "platform": "other",
+                "level": report.kind.level(),
+                "logger": report.target,
+                "release": format!("moonlight-web@{VERSION}"),
+                "environment": context.environment,
+                "message": { "formatted": message },
+                "tags": tags,
+            });
+
+            // https://develop.sentry.dev/sdk/data-model/envelopes/
+            let envelope = format!(
+                "{}\n{}\n{}\n",
+                json!({ "event_id": event_id, "sent_at": timestamp }),
+                json!({ "type": "event" }),
+                event
+            );
+
+            context
+                .client
+                .post(envelope_url.clone())
+                .header("Content-Type", "application/x-sentry-envelope")
+                .header(
+                    "X-Sentry-Auth",
+                    format!(
+                        "Sentry sentry_version=7, sentry_client=moonlight-web/{VERSION}, sentry_key={public_key}"
+                    ),
+                )
+                .body(envelope)
+        }
+    };
+
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error_reporting::{InvalidTargetError, parse_sentry_dsn};
+
+    #[test]
+    fn test_sentry_dsn() {
+        let (url, key) = parse_sentry_dsn("https://abc123@o42.ingest.sentry.io/4711").unwrap();
+
+        assert_eq!(
+            url.as_str(),
+            "https://o42.ingest.sentry.io/api/4711/envelope/"
+        );
+        assert_eq!(key, "abc123");
+    }
+
+    #[test]
+    fn test_sentry_dsn_with_path() {
+        let (url, key) = parse_sentry_dsn("http://public@glitchtip.local:8000/errors/3/").unwrap();
+
+        assert_eq!(
+            url.as_str(),
+            "http://glitchtip.local:8000/errors/api/3/envelope/"
+        );
+        assert_eq!(key, "public");
+    }
+
+    #[test]
+    fn test_invalid_sentry_dsn() {
+        assert_eq!(
+            parse_sentry_dsn("https://o42.ingest.sentry.io/4711").unwrap_err(),
+            InvalidTargetError::MissingKey
+        );
+        assert_eq!(
+            parse_sentry_dsn("https://abc123@o42.ingest.sentry.io/").unwrap_err(),
+            InvalidTargetError::MissingProject
+        );
+    }
+}
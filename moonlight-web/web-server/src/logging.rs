@@ -0,0 +1,410 @@
+//! Logger setup: ip anonymization of all log messages, rotation of the log file and error reporting.
+
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+use common::config::{LogConfig, LogFileRotationConfig, LogIpAnonymization};
+use flate2::{Compression, write::GzEncoder};
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{
+    ColorChoice, CombinedLogger, Config, SharedLogger, TermLogger, TerminalMode, WriteLogger,
+};
+
+use crate::error_reporting::ErrorReportingLogger;
+
+pub fn init_logger(config: &LogConfig) {
+    let log_config = simplelog::ConfigBuilder::new()
+        .add_filter_ignore_str("actix_http::h1")
+        .build();
+
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
+        config.level_filter,
+        log_config.clone(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    )];
+
+    if let Some(file_path) = &config.file_path {
+        let file = RotatingFile::open(file_path.into(), config.file_rotation.clone())
+            .expect("failed to open log file");
+
+        loggers.push(WriteLogger::new(config.level_filter, log_config, file));
+    }
+
+    if config.anonymize_ips != LogIpAnonymization::None {
+        loggers = loggers
+            .into_iter()
+            .map(|inner| {
+                Box::new(AnonymizingLogger {
+                    inner,
+                    anonymization: config.anonymize_ips,
+                }) as Box<dyn SharedLogger>
+            })
+            .collect();
+    }
+
+    // Reports are anonymized when they're sent
+    if config.error_reporting.target.is_some() {
+        loggers.push(Box::new(ErrorReportingLogger));
+    }
+
+    CombinedLogger::init(loggers).expect("failed to init combined logger");
+}
+
+// -- Ip Anonymization
+
+struct AnonymizingLogger {
+    inner: Box<dyn SharedLogger>,
+    anonymization: LogIpAnonymization,
+}
+
+impl Log for AnonymizingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        let message = anonymize_ips(&message, self.anonymization);
+
+        self.inner.log(
+            &Record::builder()
+                .metadata(record.metadata().clone())
+                .args(format_args!("{message}"))
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+impl SharedLogger for AnonymizingLogger {
+    fn level(&self) -> LevelFilter {
+        self.inner.level()
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.inner.config()
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+const HIDDEN_IP: &str = "[hidden ip]";
+
+/// Replaces all ipv4 and ipv6 addresses in the text, ports are kept
+pub fn anonymize_ips(text: &str, anonymization: LogIpAnonymization) -> Cow<'_, str> {
+    if anonymization == LogIpAnonymization::None {
+        return Cow::Borrowed(text);
+    }
+
+    let is_ip_char = |c: char| c.is_ascii_hexdigit() || c == '.' || c == ':';
+
+    let mut out = String::new();
+    let mut copied_until = 0;
+
+    let mut rest = text;
+    while let Some(start) = rest.find(is_ip_char) {
+        let offset = text.len() - rest.len() + start;
+        let token = &rest[start..];
+        let len = token.find(|c: char| !is_ip_char(c)).unwrap_or(token.len());
+
+        // Trailing dots or colons are most likely punctuation
+        let token = token[..len].trim_end_matches(['.', ':']);
+
+        // Parts of a word like `std::fs` or `Error::Api` aren't ips
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let is_word = text[..offset].chars().next_back().is_some_and(is_word_char)
+            || text[offset + len..]
+                .chars()
+                .next()
+                .is_some_and(is_word_char);
+
+        if !is_word && let Some((ip, ip_len)) = parse_ip_prefix(token) {
+            out.push_str(&text[copied_until..offset]);
+            out.push_str(&anonymize_ip(ip, anonymization));
+            copied_until = offset + ip_len;
+        }
+
+        rest = &rest[start + len..];
+    }
+
+    if copied_until == 0 {
+        return Cow::Borrowed(text);
+    }
+
+    out.push_str(&text[copied_until..]);
+    Cow::Owned(out)
+}
+
+/// Returns the ip and its length in the token, the token might contain a port after an ipv4 address
+fn parse_ip_prefix(token: &str) -> Option<(IpAddr, usize)> {
+    if let Ok(ip) = token.parse::<Ipv4Addr>() {
+        return Some((IpAddr::V4(ip), token.len()));
+    }
+    // Short tokens like `d::f` are more likely to be something else
+    if let Ok(ip) = token.parse::<Ipv6Addr>()
+        && token.split(':').filter(|group| !group.is_empty()).count() >= 2
+    {
+        return Some((IpAddr::V6(ip), token.len()));
+    }
+
+    let (ip, port) = token.rsplit_once(':')?;
+    if port.is_empty() || !port.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let ip = ip.parse::<Ipv4Addr>().ok()?;
+    Some((IpAddr::V4(ip), token.len() - port.len() - 1))
+}
+
+fn anonymize_ip(ip: IpAddr, anonymization: LogIpAnonymization) -> String {
+    match (anonymization, ip) {
+        (LogIpAnonymization::None, ip) => ip.to_string(),
+        (LogIpAnonymization::Full, _) => HIDDEN_IP.to_string(),
+        (LogIpAnonymization::Subnet, IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+
+            Ipv4Addr::new(a, b, c, 0).to_string()
+        }
+        (LogIpAnonymization::Subnet, IpAddr::V6(ip)) => {
+            let [a, b, c, ..] = ip.segments();
+
+            Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0).to_string()
+        }
+    }
+}
+
+// -- Log File Rotation
+
+/// A log file which is moved to `FILE.1` once it's too big, older files are moved to `FILE.2` and so on.
+struct RotatingFile {
+    path: PathBuf,
+    config: LogFileRotationConfig,
+    file: Option<File>,
+    size: u64,
+    at_line_start: bool,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, config: LogFileRotationConfig) -> Result<Self, io::Error> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = File::options().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        let this = Self {
+            path,
+            config,
+            file: Some(file),
+            size,
+            at_line_start: true,
+        };
+        this.remove_expired();
+
+        Ok(this)
+    }
+
+    fn rotated_path(&self, index: usize, compressed: bool) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        if compressed {
+            path.push(".gz");
+        }
+
+        path.into()
+    }
+
+    fn rotate(&mut self) -> Result<(), io::Error> {
+        // The file must be closed before renaming it on windows
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        let max_files = self.config.max_files;
+        if max_files > 0 {
+            for compressed in [false, true] {
+                remove_if_exists(&self.rotated_path(max_files, compressed))?;
+            }
+            for index in (1..max_files).rev() {
+                for compressed in [false, true] {
+                    let from = self.rotated_path(index, compressed);
+                    if from.exists() {
+                        fs::rename(&from, self.rotated_path(index + 1, compressed))?;
+                    }
+                }
+            }
+
+            let rotated = self.rotated_path(1, false);
+            fs::rename(&self.path, &rotated)?;
+
+            if self.config.compress {
+                compress_file(&rotated, &self.rotated_path(1, true))?;
+                fs::remove_file(&rotated)?;
+            }
+        }
+
+        self.file = Some(
+            File::options()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&self.path)?,
+        );
+        self.size = 0;
+
+        self.remove_expired();
+
+        Ok(())
+    }
+
+    fn remove_expired(&self) {
+        let Some(max_age) = self.config.max_age else {
+            return;
+        };
+
+        for index in 1..=self.config.max_files {
+            for compressed in [false, true] {
+                let path = self.rotated_path(index, compressed);
+
+                let expired = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_some_and(|age| age > max_age);
+
+                if expired && let Err(err) = fs::remove_file(&path) {
+                    eprintln!("failed to remove expired log file {path:?}: {err}");
+                }
+            }
+        }
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only rotate between lines so that a message isn't split into two files
+        if self.size >= self.config.max_file_size
+            && self.at_line_start
+            && let Err(err) = self.rotate()
+        {
+            eprintln!("failed to rotate log file: {err}");
+        }
+
+        if self.file.is_none() {
+            self.file = Some(File::options().create(true).append(true).open(&self.path)?);
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Err(io::Error::other("log file is not open"));
+        };
+
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        self.at_line_start = buf[..written].ends_with(b"\n");
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn compress_file(from: &Path, to: &Path) -> Result<(), io::Error> {
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    io::copy(&mut File::open(from)?, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use common::config::LogIpAnonymization;
+
+    use crate::logging::anonymize_ips;
+
+    #[test]
+    fn test_no_anonymization() {
+        let text = "connection from 192.168.1.42";
+
+        assert_eq!(anonymize_ips(text, LogIpAnonymization::None), text);
+    }
+
+    #[test]
+    fn test_subnet_ipv4() {
+        assert_eq!(
+            anonymize_ips(
+                "connection from 192.168.1.42:40512.",
+                LogIpAnonymization::Subnet
+            ),
+            "connection from 192.168.1.0:40512."
+        );
+    }
+
+    #[test]
+    fn test_subnet_ipv6() {
+        assert_eq!(
+            anonymize_ips(
+                "peer [2001:db8:cafe:1::17]:4711",
+                LogIpAnonymization::Subnet
+            ),
+            "peer [2001:db8:cafe::]:4711"
+        );
+    }
+
+    #[test]
+    fn test_full() {
+        assert_eq!(
+            anonymize_ips(
+                "Some(10.0.0.2) and 2001:db8::1 requested /api/host",
+                LogIpAnonymization::Full
+            ),
+            "Some([hidden ip]) and [hidden ip] requested /api/host"
+        );
+    }
+
+    #[test]
+    fn test_ignore_non_ips() {
+        let text = "took 12 ms at 12:30:01, mac aa:bb:cc:dd:ee:ff, host deadbeef";
+
+        assert_eq!(anonymize_ips(text, LogIpAnonymization::Full), text);
+    }
+
+    #[test]
+    fn test_ignore_rust_paths() {
+        let text = "failed at std::fs::read: Error::Api(err), ::1 and fe::";
+
+        assert_eq!(anonymize_ips(text, LogIpAnonymization::Full), text);
+    }
+}
@@ -1,30 +1,42 @@
 use common::config::Config;
 use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
-use std::{io::ErrorKind, path::PathBuf, str::FromStr};
-use tokio::fs::{self, File};
+use std::{io::ErrorKind, path::PathBuf, process::exit, str::FromStr};
+use tokio::fs;
 
 use actix_web::{
     App as ActixApp, HttpServer,
-    middleware::{self, Logger},
+    dev::HttpServiceFactory,
+    guard::{self, Guard},
+    middleware::{Logger, from_fn},
     web::{Data, scope},
 };
 use log::{Level, error, info};
-use simplelog::{ColorChoice, CombinedLogger, SharedLogger, TermLogger, TerminalMode, WriteLogger};
 
 use crate::{
-    api::api_service,
+    admin::run_admin_command,
+    api::{api_service, client_ip::client_ip},
     app::App,
     cli::{Cli, Command},
+    error_reporting::init_error_reporting,
     human_json::preprocess_human_json,
-    web::{web_config_js_service, web_service},
+    logging::init_logger,
+    remote_streamer::RemoteStreamers,
+    streamer::StreamerPool,
+    web::{cache_policy::cache_policy_middleware, web_config_js_service, web_service},
 };
 
 mod api;
 mod app;
 mod web;
 
+mod admin;
 mod cli;
+mod error_reporting;
 mod human_json;
+mod logging;
+mod pair_import;
+mod remote_streamer;
+mod streamer;
 
 #[actix_web::main]
 async fn main() {
@@ -68,39 +80,22 @@ async fn main() {
             println!("{json}");
             return;
         }
+        Some(Command::Admin(command)) => {
+            if let Err(err) = run_admin_command(&config, command).await {
+                eprintln!("{err:#}");
+                exit(1);
+            }
+            return;
+        }
         None | Some(Command::Run) => {
             // Fallthrough
         }
     }
 
-    // TODO: log config: anonymize ips when enabled in file
     // TODO: https://www.reddit.com/r/csharp/comments/166xgcl/comment/jynybpe/
 
-    let log_config = simplelog::ConfigBuilder::new()
-        .add_filter_ignore_str("actix_http::h1")
-        .build();
-
-    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
-        config.log.level_filter,
-        log_config.clone(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )];
-
-    if let Some(file_path) = &config.log.file_path {
-        let file = File::create(file_path)
-            .await
-            .expect("failed to open log file");
-
-        loggers.push(WriteLogger::new(
-            config.log.level_filter,
-            log_config,
-            file.try_into_std()
-                .expect("failed to cast tokio file into std file"),
-        ));
-    }
-
-    CombinedLogger::init(loggers).expect("failed to init combined logger");
+    init_logger(&config.log);
+    init_error_reporting(&config.log.error_reporting, config.log.anonymize_ips);
 
     if let Err(err) = start(config).await {
         error!("{err:?}");
@@ -108,37 +103,62 @@ async fn main() {
 }
 
 async fn start(config: Config) -> Result<(), anyhow::Error> {
-    let app = App::new(config.clone()).await?;
+    // A single endpoint for the nodes, which stream for every tenant
+    let remote_streamers = if config.remote_streamers.enabled {
+        Some(RemoteStreamers::start(config.remote_streamers.clone()).await?)
+    } else {
+        None
+    };
+
+    // The idle streamers don't belong to a tenant, so they're shared like the nodes
+    let streamer_pool = StreamerPool::new(config.clone());
+
+    let app = App::new(
+        config.clone(),
+        streamer_pool.clone(),
+        remote_streamers.clone(),
+    )
+    .await?;
     let app = Data::new(app);
 
+    let mut tenants = Vec::with_capacity(config.tenants.len());
+    for tenant in &config.tenants {
+        info!(
+            "[Server]: Serving a tenant for the hosts {:?}",
+            tenant.hosts
+        );
+
+        let tenant_app = App::new(
+            config.for_tenant(tenant),
+            streamer_pool.clone(),
+            remote_streamers.clone(),
+        )
+        .await?;
+        tenants.push((tenant.hosts.clone(), Data::new(tenant_app)));
+    }
+
     let bind_address = app.config().web_server.bind_address;
     let server = HttpServer::new({
         let url_path_prefix = config.web_server.url_path_prefix.clone();
         let app = app.clone();
 
         move || {
-            ActixApp::new().service(
-                scope(&url_path_prefix)
-                    .app_data(app.clone())
-                    .wrap(
-                        Logger::new("%r took %D ms")
-                            .log_target("http_server")
-                            .log_level(Level::Debug),
-                    )
-                    .wrap(
-                        // TODO: maybe only re cache when required?
-                        middleware::DefaultHeaders::new()
-                            .add((
-                                "Cache-Control",
-                                "no-store, no-cache, must-revalidate, private",
-                            ))
-                            .add(("Pragma", "no-cache"))
-                            .add(("Expires", "0")),
-                    )
-                    .service(api_service())
-                    .service(web_config_js_service())
-                    .service(web_service()),
-            )
+            let mut actix_app = ActixApp::new();
+
+            // The app data must be available in the logger middleware to find the client ip,
+            // so every tenant gets an outer scope which only provides its app
+            for (hosts, tenant_app) in &tenants {
+                actix_app = actix_app.service(
+                    scope("")
+                        .guard(tenant_guard(hosts))
+                        .app_data(tenant_app.clone())
+                        .service(instance_service(&url_path_prefix)),
+                );
+            }
+
+            actix_app
+                .app_data(app.clone())
+                .service(instance_service(&url_path_prefix))
         }
     });
 
@@ -161,3 +181,30 @@ async fn start(config: Config) -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+fn instance_service(url_path_prefix: &str) -> impl HttpServiceFactory + 'static {
+    scope(url_path_prefix)
+        .wrap(
+            Logger::new("%{client_ip}xi %r took %D ms")
+                .custom_request_replace("client_ip", |req| {
+                    client_ip(req.request())
+                        .map(|ip| ip.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                })
+                .log_target("http_server")
+                .log_level(Level::Debug),
+        )
+        .wrap(from_fn(cache_policy_middleware))
+        .service(api_service())
+        .service(web_config_js_service())
+        .service(web_service())
+}
+
+/// Matches requests whose host header is one of the hosts, the port is ignored
+fn tenant_guard(hosts: &[String]) -> impl Guard + 'static {
+    hosts
+        .iter()
+        .fold(guard::Any(guard::fn_guard(|_| false)), |any, host| {
+            any.or(guard::Host(host.clone()))
+        })
+}
@@ -0,0 +1,247 @@
+//! Imports the pairing of existing Moonlight clients so hosts don't need to be paired again.
+//!
+//! Moonlight Qt stores everything in its `Moonlight.conf`, which is written by QSettings in the ini format.
+//! moonlight-android stores the client certificate and key as files (`client.crt`, `client.key`),
+//! the certificate of the host has to be exported separately.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, bail};
+use openssl::{pkey::PKey, x509::X509};
+use pem::Pem;
+
+use crate::app::storage::StorageHostPairInfo;
+
+/// The client identity of a Moonlight installation
+pub struct ClientIdentity {
+    pub certificate: Pem,
+    pub private_key: Pem,
+}
+
+impl ClientIdentity {
+    pub fn new(certificate: &[u8], private_key: &[u8]) -> Result<Self, anyhow::Error> {
+        let certificate = parse_certificate(certificate).context("invalid client certificate")?;
+
+        let private_key = PKey::private_key_from_pem(private_key)
+            .or_else(|_| PKey::private_key_from_der(private_key))
+            .context("invalid client private key")?;
+
+        let x509 = X509::from_der(certificate.contents())?;
+        if !x509.public_key()?.public_eq(&private_key) {
+            bail!("the client private key doesn't belong to the client certificate");
+        }
+
+        Ok(Self {
+            certificate,
+            private_key: pem::parse(private_key.private_key_to_pem_pkcs8()?)?,
+        })
+    }
+
+    pub fn into_pair_info(self, server_certificate: Pem) -> StorageHostPairInfo {
+        StorageHostPairInfo {
+            client_private_key: self.private_key,
+            client_certificate: self.certificate,
+            server_certificate,
+        }
+    }
+}
+
+/// Accepts pem and der encoded certificates
+pub fn parse_certificate(data: &[u8]) -> Result<Pem, anyhow::Error> {
+    let x509 = X509::from_pem(data).or_else(|_| X509::from_der(data))?;
+
+    Ok(Pem::new("CERTIFICATE", x509.to_der()?))
+}
+
+#[derive(Debug, Default)]
+pub struct MoonlightQtHost {
+    pub name: String,
+    pub addresses: Vec<String>,
+    pub server_certificate: Option<Vec<u8>>,
+}
+
+pub struct MoonlightQtConfig {
+    pub certificate: Vec<u8>,
+    pub private_key: Vec<u8>,
+    pub hosts: Vec<MoonlightQtHost>,
+}
+
+impl MoonlightQtConfig {
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut general = HashMap::new();
+        let mut hosts = HashMap::<u32, MoonlightQtHost>::new();
+
+        let mut section = "General".to_string();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = name.to_string();
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = unescape_value(value.trim());
+
+            match section.as_str() {
+                "General" => {
+                    general.insert(key.trim().to_string(), value);
+                }
+                "hosts" => {
+                    // Hosts are stored as an array: "1\name", "1\srvcert", ...
+                    let Some((index, key)) = key.trim().split_once('\\') else {
+                        continue;
+                    };
+                    let Ok(index) = index.parse() else {
+                        continue;
+                    };
+
+                    let host = hosts.entry(index).or_default();
+                    match key {
+                        "hostname" => host.name = String::from_utf8_lossy(&value).to_string(),
+                        "srvcert" if !value.is_empty() => host.server_certificate = Some(value),
+                        "localaddress" | "remoteaddress" | "manualaddress" | "ipv6address"
+                            if !value.is_empty() =>
+                        {
+                            host.addresses
+                                .push(String::from_utf8_lossy(&value).to_string());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let certificate = general
+            .remove("certificate")
+            .context("the config doesn't contain a client certificate")?;
+        let private_key = general
+            .remove("key")
+            .context("the config doesn't contain a client private key")?;
+
+        let mut hosts = hosts.into_iter().collect::<Vec<_>>();
+        hosts.sort_by_key(|(index, _)| *index);
+
+        Ok(Self {
+            certificate,
+            private_key,
+            hosts: hosts.into_iter().map(|(_, host)| host).collect(),
+        })
+    }
+
+    /// Finds the host which is reachable at this address
+    pub fn host_by_address(&self, address: &str) -> Option<&MoonlightQtHost> {
+        self.hosts.iter().find(|host| {
+            host.addresses
+                .iter()
+                .any(|host_address| host_address.eq_ignore_ascii_case(address))
+        })
+    }
+}
+
+/// Reverses the escaping of QSettings, including `@ByteArray(...)` values
+fn unescape_value(value: &str) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    let mut chars = value.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => {}
+            '\\' => match chars.next() {
+                Some('n') => output.push(b'\n'),
+                Some('r') => output.push(b'\r'),
+                Some('t') => output.push(b'\t'),
+                Some('0') => output.push(0),
+                Some('x') => {
+                    let mut code = 0u32;
+                    while let Some(digit) = chars.peek().and_then(|char| char.to_digit(16)) {
+                        code = code * 16 + digit;
+                        chars.next();
+                    }
+                    if let Some(char) = char::from_u32(code) {
+                        let mut buffer = [0; 4];
+                        output.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+                    }
+                }
+                Some(char) => {
+                    let mut buffer = [0; 4];
+                    output.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+                }
+                None => {}
+            },
+            char => {
+                let mut buffer = [0; 4];
+                output.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+
+    match output
+        .strip_prefix(b"@ByteArray(")
+        .and_then(|value| value.strip_suffix(b")"))
+    {
+        Some(value) => value.to_vec(),
+        None => output,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pair_import::{MoonlightQtConfig, unescape_value};
+
+    #[test]
+    fn test_unescape_byte_array() {
+        assert_eq!(
+            unescape_value("@ByteArray(-----BEGIN\\nline\\\\end)"),
+            b"-----BEGIN\nline\\end"
+        );
+        assert_eq!(unescape_value("\"quoted, value\""), b"quoted, value");
+        assert_eq!(unescape_value("\\x41\\0"), b"A\0");
+    }
+
+    #[test]
+    fn test_parse_moonlight_qt_config() {
+        let config = MoonlightQtConfig::parse(
+            "[General]\n\
+             certificate=@ByteArray(CERT\\n)\n\
+             key=@ByteArray(KEY\\n)\n\
+             \n\
+             [hosts]\n\
+             1\\hostname=Office\n\
+             1\\localaddress=192.168.1.20\n\
+             1\\srvcert=@ByteArray(SERVER\\n)\n\
+             2\\hostname=Laptop\n\
+             2\\manualaddress=laptop.lan\n\
+             2\\srvcert=@ByteArray()\n\
+             size=2\n",
+        )
+        .expect("failed to parse config");
+
+        assert_eq!(config.certificate, b"CERT\n");
+        assert_eq!(config.private_key, b"KEY\n");
+        assert_eq!(config.hosts.len(), 2);
+
+        let host = config
+            .host_by_address("192.168.1.20")
+            .expect("missing host");
+        assert_eq!(host.name, "Office");
+        assert_eq!(
+            host.server_certificate.as_deref(),
+            Some(b"SERVER\n".as_slice())
+        );
+
+        let host = config.host_by_address("LAPTOP.lan").expect("missing host");
+        assert_eq!(host.server_certificate, None);
+
+        assert!(config.host_by_address("10.0.0.1").is_none());
+    }
+}
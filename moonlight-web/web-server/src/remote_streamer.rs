@@ -0,0 +1,480 @@
+//! Streamers on streamer nodes, which connect to the web server over mutual tls, see [RemoteStreamersConfig].
+//!
+//! Every connection of a node starts with a [NodeHelloMessage]. The control connection carries the heartbeats
+//! and starts sessions, the node answers every session with a new connection for the ipc of its streamer.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, hash_map::Entry},
+    io,
+    net::IpAddr,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use common::{
+    config::RemoteStreamersConfig,
+    ipc::{
+        IpcSender, NodeHelloMessage, NodeIpcMessage, ServerIpcMessage, ServerNodeMessage,
+        StreamerIpcMessage, create_child_ipc,
+    },
+};
+use ipnet::IpNet;
+use log::{debug, info, warn};
+use openssl::{
+    hash::MessageDigest,
+    ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod, SslRef, SslVerifyMode},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader, split},
+    net::{TcpListener, TcpStream, lookup_host},
+    spawn,
+    sync::{Mutex, RwLock, oneshot},
+    time::{sleep, timeout},
+};
+use tokio_openssl::SslStream;
+
+use crate::streamer::{SpawnedStreamer, StreamerProcess, receive_capabilities};
+
+/// The tls handshake and the hello of a connection must finish within this
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// The hello is a single json line
+const MAX_HELLO_SIZE: u64 = 16 * 1024;
+/// The node spawns the streamer before connecting back
+const SESSION_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type NodeConnection = BufReader<SslStream<TcpStream>>;
+
+struct StreamerNode {
+    name: String,
+    /// Digest of the client certificate, session connections must use the same certificate
+    certificate_digest: Vec<u8>,
+    networks: Vec<IpNet>,
+    max_sessions: usize,
+    sessions: AtomicUsize,
+    ipc_sender: IpcSender<ServerNodeMessage>,
+}
+
+impl StreamerNode {
+    /// Returns false if the node is full
+    fn reserve_session(&self) -> bool {
+        self.sessions
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |sessions| {
+                (sessions < self.max_sessions).then_some(sessions + 1)
+            })
+            .is_ok()
+    }
+}
+
+/// A session on a node, the streamer is stopped once this is dropped
+pub struct RemoteSession {
+    node: Arc<StreamerNode>,
+    session_id: u64,
+    stopped: bool,
+}
+
+impl RemoteSession {
+    pub fn stop(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.stopped = true;
+
+        self.node.sessions.fetch_sub(1, Ordering::AcqRel);
+        if !self
+            .node
+            .ipc_sender
+            .try_send(ServerNodeMessage::StopSession {
+                session_id: self.session_id,
+            })
+        {
+            warn!(
+                "[Remote Streamer]: failed to stop session {} on node {}",
+                self.session_id, self.node.name
+            );
+        }
+    }
+}
+
+impl Drop for RemoteSession {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct PendingSession {
+    /// The certificate of the node which was asked to start the session
+    certificate_digest: Vec<u8>,
+    sender: oneshot::Sender<NodeConnection>,
+}
+
+pub struct RemoteStreamers {
+    config: RemoteStreamersConfig,
+    nodes: RwLock<HashMap<u64, Arc<StreamerNode>>>,
+    /// The sessions which wait for the node to connect back
+    pending_sessions: Mutex<HashMap<u64, PendingSession>>,
+    /// Used for the ids of nodes and sessions
+    next_id: AtomicU64,
+}
+
+impl RemoteStreamers {
+    /// Starts accepting streamer nodes
+    pub async fn start(config: RemoteStreamersConfig) -> Result<Arc<Self>, anyhow::Error> {
+        let acceptor = tls_acceptor(&config).with_context(|| {
+            format!(
+                "failed to load the certificates of the remote streamers from {}, {} and {}",
+                config.certificate_path, config.private_key_path, config.ca_certificate_path
+            )
+        })?;
+
+        let listener = TcpListener::bind(config.bind_address)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to bind the remote streamers endpoint {}",
+                    config.bind_address
+                )
+            })?;
+        info!(
+            "[Remote Streamer]: streamer nodes can connect to {}",
+            config.bind_address
+        );
+
+        let remote_streamers = Arc::new(Self {
+            config,
+            nodes: Default::default(),
+            pending_sessions: Default::default(),
+            next_id: AtomicU64::new(1),
+        });
+        spawn(remote_streamers.clone().accept_nodes(listener, acceptor));
+
+        Ok(remote_streamers)
+    }
+
+    /// Starts a session on the node closest to the host, none if no node can stream from it
+    pub async fn take(&self, host_address: &str) -> Result<Option<SpawnedStreamer>, io::Error> {
+        let Some(host_ip) = resolve_host(host_address).await else {
+            return Ok(None);
+        };
+        let Some(node) = self.reserve_node(host_ip).await else {
+            return Ok(None);
+        };
+
+        let session_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        // Releases the reserved session on every error
+        let session = RemoteSession {
+            node: node.clone(),
+            session_id,
+            stopped: false,
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending_sessions.lock().await.insert(
+            session_id,
+            PendingSession {
+                certificate_digest: node.certificate_digest.clone(),
+                sender,
+            },
+        );
+
+        let mut node_sender = node.ipc_sender.clone();
+        node_sender
+            .send(ServerNodeMessage::StartSession { session_id })
+            .await;
+
+        let connection = timeout(SESSION_CONNECT_TIMEOUT, receiver).await;
+        self.pending_sessions.lock().await.remove(&session_id);
+        let Ok(Ok(connection)) = connection else {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("the streamer node {} didn't start a streamer", node.name),
+            ));
+        };
+
+        let (read, write) = split(connection);
+        let (ipc_sender, mut ipc_receiver) =
+            create_child_ipc::<ServerIpcMessage, StreamerIpcMessage>(
+                "Remote Streamer",
+                write,
+                read,
+                None,
+            )
+            .await;
+
+        let Some(capabilities) = receive_capabilities(&mut ipc_receiver).await else {
+            return Err(io::Error::other(format!(
+                "the streamer on node {} didn't start",
+                node.name
+            )));
+        };
+        debug!(
+            "[Remote Streamer]: started session {session_id} on node {} with {capabilities:?}",
+            node.name
+        );
+
+        Ok(Some((
+            StreamerProcess::Remote(session),
+            ipc_sender,
+            ipc_receiver,
+            capabilities,
+        )))
+    }
+
+    async fn reserve_node(&self, host_ip: IpAddr) -> Option<Arc<StreamerNode>> {
+        let nodes = self.nodes.read().await;
+
+        let mut candidates = nodes
+            .values()
+            .filter_map(|node| {
+                let rank = node_rank(
+                    &node.networks,
+                    node.max_sessions,
+                    node.sessions.load(Ordering::Acquire),
+                    host_ip,
+                )?;
+                Some((rank, node))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(rank, _)| Reverse(*rank));
+
+        // Another stream might've filled a node in the meantime
+        candidates
+            .into_iter()
+            .map(|(_, node)| node)
+            .find(|node| node.reserve_session())
+            .cloned()
+    }
+
+    async fn accept_nodes(self: Arc<Self>, listener: TcpListener, acceptor: SslAcceptor) {
+        loop {
+            let (socket, address) = match listener.accept().await {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!("[Remote Streamer]: failed to accept a connection: {err}");
+                    sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let this = self.clone();
+            let ssl = Ssl::new(acceptor.context());
+            spawn(async move {
+                let result = match ssl {
+                    Ok(ssl) => this.handle_connection(socket, ssl).await,
+                    Err(err) => Err(err.into()),
+                };
+
+                if let Err(err) = result {
+                    warn!("[Remote Streamer]: the connection of {address} failed: {err:#}");
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, socket: TcpStream, ssl: Ssl) -> Result<(), anyhow::Error> {
+        let mut stream = SslStream::new(ssl, socket)?;
+        timeout(HELLO_TIMEOUT, Pin::new(&mut stream).accept())
+            .await
+            .context("the tls handshake timed out")?
+            .context("the tls handshake failed")?;
+        let certificate_digest = peer_certificate_digest(stream.ssl())?;
+
+        let mut connection = BufReader::new(stream);
+        let hello = timeout(HELLO_TIMEOUT, read_hello(&mut connection))
+            .await
+            .context("didn't receive a hello")??;
+
+        match hello {
+            NodeHelloMessage::Register {
+                name,
+                networks,
+                max_sessions,
+            } => {
+                self.run_node(connection, certificate_digest, name, networks, max_sessions)
+                    .await;
+                Ok(())
+            }
+            NodeHelloMessage::Session { session_id } => {
+                let pending_session = match self.pending_sessions.lock().await.entry(session_id) {
+                    // Another node must not take over the session
+                    Entry::Occupied(entry)
+                        if entry.get().certificate_digest == certificate_digest =>
+                    {
+                        entry.remove()
+                    }
+                    Entry::Occupied(_) => {
+                        return Err(anyhow!(
+                            "the session {session_id} was started on another node"
+                        ));
+                    }
+                    Entry::Vacant(_) => {
+                        return Err(anyhow!("the session {session_id} wasn't started"));
+                    }
+                };
+
+                if pending_session.sender.send(connection).is_err() {
+                    debug!("[Remote Streamer]: the session {session_id} connected too late");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn run_node(
+        &self,
+        connection: NodeConnection,
+        certificate_digest: Vec<u8>,
+        name: String,
+        networks: Vec<IpNet>,
+        max_sessions: usize,
+    ) {
+        let (read, write) = split(connection);
+        let (mut ipc_sender, mut ipc_receiver) = create_child_ipc::<
+            ServerNodeMessage,
+            NodeIpcMessage,
+        >("Streamer Node", write, read, None)
+        .await;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        info!("[Remote Streamer]: node {name} registered for {networks:?}");
+        self.nodes.write().await.insert(
+            id,
+            Arc::new(StreamerNode {
+                name: name.clone(),
+                certificate_digest,
+                networks,
+                max_sessions,
+                sessions: AtomicUsize::new(0),
+                ipc_sender: ipc_sender.clone(),
+            }),
+        );
+
+        loop {
+            match timeout(self.config.heartbeat_timeout, ipc_receiver.recv()).await {
+                Ok(Some(NodeIpcMessage::Heartbeat)) => {
+                    ipc_sender.send(ServerNodeMessage::Heartbeat).await;
+                }
+                Ok(None) => {
+                    info!("[Remote Streamer]: node {name} disconnected");
+                    break;
+                }
+                Err(_) => {
+                    warn!("[Remote Streamer]: node {name} missed its heartbeats");
+                    break;
+                }
+            }
+        }
+
+        // Running sessions keep their own connections
+        self.nodes.write().await.remove(&id);
+    }
+}
+
+fn tls_acceptor(config: &RemoteStreamersConfig) -> Result<SslAcceptor, anyhow::Error> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    builder.set_private_key_file(&config.private_key_path, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(&config.certificate_path)?;
+    builder.check_private_key()?;
+
+    // Only nodes with a certificate of the authority can connect
+    builder.set_ca_file(&config.ca_certificate_path)?;
+    builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+
+    Ok(builder.build())
+}
+
+/// The acceptor requires a client certificate, so every accepted connection has one
+fn peer_certificate_digest(ssl: &SslRef) -> Result<Vec<u8>, anyhow::Error> {
+    let certificate = ssl
+        .peer_certificate()
+        .context("the node didn't send a certificate")?;
+
+    Ok(certificate.digest(MessageDigest::sha256())?.to_vec())
+}
+
+async fn read_hello(
+    connection: &mut (impl AsyncBufRead + Unpin),
+) -> Result<NodeHelloMessage, anyhow::Error> {
+    let mut line = String::new();
+    connection.take(MAX_HELLO_SIZE).read_line(&mut line).await?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+async fn resolve_host(address: &str) -> Option<IpAddr> {
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = address.parse() {
+        return Some(ip);
+    }
+
+    match lookup_host((address, 0)).await {
+        Ok(mut addresses) => addresses.next().map(|address| address.ip()),
+        Err(err) => {
+            warn!("[Remote Streamer]: failed to resolve the host {address}: {err}");
+            None
+        }
+    }
+}
+
+/// None if the node can't stream from the host, otherwise higher is closer.
+/// The most specific network of the host wins, nodes with less sessions are preferred on a tie.
+fn node_rank(
+    networks: &[IpNet],
+    max_sessions: usize,
+    sessions: usize,
+    host_ip: IpAddr,
+) -> Option<(u8, Reverse<usize>)> {
+    if sessions >= max_sessions {
+        return None;
+    }
+
+    let prefix_len = networks
+        .iter()
+        .filter(|network| network.contains(&host_ip))
+        .map(|network| network.prefix_len())
+        .max()?;
+
+    Some((prefix_len, Reverse(sessions)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use ipnet::IpNet;
+
+    use crate::remote_streamer::node_rank;
+
+    fn networks(networks: &[&str]) -> Vec<IpNet> {
+        networks
+            .iter()
+            .map(|network| network.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_most_specific_network_wins() {
+        let host = "192.168.1.20".parse::<IpAddr>().unwrap();
+
+        let everywhere = node_rank(&networks(&["0.0.0.0/0"]), 4, 0, host).unwrap();
+        let home = node_rank(&networks(&["10.0.0.0/8", "192.168.1.0/24"]), 4, 3, host).unwrap();
+        assert!(home > everywhere);
+
+        assert_eq!(node_rank(&networks(&["10.0.0.0/8"]), 4, 0, host), None);
+    }
+
+    #[test]
+    fn test_less_sessions_win_on_a_tie() {
+        let host = "192.168.1.20".parse::<IpAddr>().unwrap();
+        let home = networks(&["192.168.1.0/24"]);
+
+        assert!(node_rank(&home, 4, 1, host) > node_rank(&home, 4, 2, host));
+        // Full nodes aren't used at all
+        assert_eq!(node_rank(&home, 4, 4, host), None);
+    }
+}
@@ -0,0 +1,488 @@
+//! Spawning of the streamer child process.
+
+use std::{
+    env, io,
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use common::{
+    api_bindings::{BuildInfo, StreamerCapabilities},
+    config::{Config, StreamerIpcMethod},
+    ipc::{IpcReceiver, IpcSender, ServerIpcMessage, StreamerIpcMessage, create_child_ipc},
+};
+use log::{debug, warn};
+use tokio::{
+    fs,
+    process::{Child, Command},
+    spawn,
+    sync::Mutex,
+    time::timeout,
+};
+
+use crate::remote_streamer::RemoteSession;
+
+pub type SpawnedStreamer = (
+    StreamerProcess,
+    IpcSender<ServerIpcMessage>,
+    IpcReceiver<StreamerIpcMessage>,
+    StreamerCapabilities,
+);
+
+/// The streamer sends its capabilities right after starting
+const CAPABILITIES_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A streamer runs as a child of the web server or on a streamer node
+pub enum StreamerProcess {
+    Local(Child),
+    Remote(RemoteSession),
+}
+
+impl StreamerProcess {
+    fn has_exited(&mut self) -> bool {
+        match self {
+            Self::Local(child) => !matches!(child.try_wait(), Ok(None)),
+            Self::Remote(_) => false,
+        }
+    }
+
+    pub async fn kill(&mut self) -> Result<(), io::Error> {
+        match self {
+            Self::Local(child) => child.kill().await,
+            Self::Remote(session) => {
+                session.stop();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Idle streamers which are spawned ahead of time.
+/// They only receive their `Init` once a stream starts.
+pub struct StreamerPool {
+    config: Config,
+    idle: Mutex<Vec<SpawnedStreamer>>,
+    refilling: AtomicBool,
+    /// The capabilities of the last spawned streamer
+    capabilities: Mutex<Option<StreamerCapabilities>>,
+}
+
+impl StreamerPool {
+    pub fn new(config: Config) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            config,
+            idle: Default::default(),
+            refilling: AtomicBool::new(false),
+            capabilities: Default::default(),
+        });
+
+        pool.refill();
+
+        pool
+    }
+
+    /// Returns an idle streamer or spawns a new one if there's none
+    pub async fn take(self: &Arc<Self>) -> Result<SpawnedStreamer, io::Error> {
+        let streamer = {
+            let mut idle = self.idle.lock().await;
+
+            let mut streamer = None;
+            while let Some(mut candidate) = idle.pop() {
+                // Idle streamers might've exited in the meantime
+                if !candidate.0.has_exited() {
+                    streamer = Some(candidate);
+                    break;
+                }
+
+                warn!("[Stream]: dropping an idle streamer which already exited");
+            }
+
+            streamer
+        };
+
+        self.refill();
+
+        match streamer {
+            Some(streamer) => {
+                debug!("[Stream]: using an idle streamer from the pool");
+                Ok(streamer)
+            }
+            None => self.spawn().await,
+        }
+    }
+
+    /// The capabilities of the installed streamer, an idle streamer is spawned to find them out if there's none yet
+    pub async fn capabilities(&self) -> Result<StreamerCapabilities, io::Error> {
+        if let Some(capabilities) = *self.capabilities.lock().await {
+            return Ok(capabilities);
+        }
+
+        let streamer = self.spawn().await?;
+        let capabilities = streamer.3;
+
+        if self.config.streamer_pool.size > 0 {
+            self.idle.lock().await.push(streamer);
+        }
+
+        Ok(capabilities)
+    }
+
+    async fn spawn(&self) -> Result<SpawnedStreamer, io::Error> {
+        let streamer = spawn_streamer(&self.config).await?;
+        *self.capabilities.lock().await = Some(streamer.3);
+
+        Ok(streamer)
+    }
+
+    fn refill(self: &Arc<Self>) {
+        let size = self.config.streamer_pool.size;
+        if size == 0 || self.refilling.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let this = self.clone();
+        spawn(async move {
+            while this.idle.lock().await.len() < size {
+                match this.spawn().await {
+                    Ok(streamer) => this.idle.lock().await.push(streamer),
+                    Err(err) => {
+                        warn!("[Stream]: failed to spawn an idle streamer: {err}");
+                        break;
+                    }
+                }
+            }
+
+            this.refilling.store(false, Ordering::Release);
+        });
+    }
+}
+
+pub async fn spawn_streamer(config: &Config) -> Result<SpawnedStreamer, io::Error> {
+    let (mut child, ipc_sender, mut ipc_receiver) = spawn_streamer_process(config).await?;
+
+    let Some(capabilities) = receive_capabilities(&mut ipc_receiver).await else {
+        if let Err(err) = child.kill().await {
+            warn!("[Stream]: failed to kill child: {err}");
+        }
+
+        return Err(io::Error::other("the streamer didn't start"));
+    };
+    debug!("[Stream]: spawned a streamer with {capabilities:?}");
+
+    Ok((
+        StreamerProcess::Local(child),
+        ipc_sender,
+        ipc_receiver,
+        capabilities,
+    ))
+}
+
+/// None if the streamer didn't start
+pub async fn receive_capabilities(
+    ipc_receiver: &mut IpcReceiver<StreamerIpcMessage>,
+) -> Option<StreamerCapabilities> {
+    match timeout(CAPABILITIES_TIMEOUT, ipc_receiver.recv()).await {
+        Ok(Some(StreamerIpcMessage::Capabilities(capabilities))) => Some(capabilities),
+        Ok(Some(_)) => {
+            warn!("[Stream]: the streamer didn't send its capabilities, it's probably outdated");
+            Some(StreamerCapabilities::default())
+        }
+        Ok(None) | Err(_) => None,
+    }
+}
+
+async fn spawn_streamer_process(
+    config: &Config,
+) -> Result<
+    (
+        Child,
+        IpcSender<ServerIpcMessage>,
+        IpcReceiver<StreamerIpcMessage>,
+    ),
+    io::Error,
+> {
+    let mut command = streamer_command(config).await?;
+
+    match config.streamer_ipc {
+        StreamerIpcMethod::Stdio => {
+            command.stdin(Stdio::piped()).stdout(Stdio::piped());
+
+            let mut child = command.spawn()?;
+            #[cfg(windows)]
+            windows::assign_to_job(&child);
+
+            let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                if let Err(err) = child.kill().await {
+                    warn!("[Stream]: failed to kill child: {err}");
+                }
+
+                return Err(io::Error::other(
+                    "streamer process didn't include a stdin or stdout",
+                ));
+            };
+
+            let (ipc_sender, ipc_receiver) =
+                create_child_ipc("Streamer", stdin, stdout, child.stderr.take()).await;
+
+            Ok((child, ipc_sender, ipc_receiver))
+        }
+        #[cfg(windows)]
+        StreamerIpcMethod::NamedPipe => {
+            let (child, ipc_sender, ipc_receiver) = windows::spawn_with_pipe(command).await?;
+
+            Ok((child, ipc_sender, ipc_receiver))
+        }
+        #[cfg(not(windows))]
+        StreamerIpcMethod::NamedPipe => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "named pipe ipc is only supported on windows",
+        )),
+    }
+}
+
+/// Older streamers don't know `--build-info` and wait for their ipc instead
+const BUILD_INFO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs the streamer with `--build-info`
+pub async fn query_streamer_build_info(config: &Config) -> Result<BuildInfo, io::Error> {
+    let mut command = streamer_command(config).await?;
+    command.arg("--build-info").stdout(Stdio::piped());
+
+    let output = timeout(BUILD_INFO_TIMEOUT, command.output())
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the streamer didn't answer"))??;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "the streamer exited with {}",
+            output.status
+        )));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(io::Error::other)
+}
+
+async fn streamer_command(config: &Config) -> Result<Command, io::Error> {
+    let streamer_path = resolve_streamer_path(&config.streamer_path);
+
+    let mut command = Command::new(&streamer_path);
+
+    if config.streamer_sandbox.enabled {
+        // Resolve the path before changing the working directory of the child
+        let streamer_path = fs::canonicalize(&streamer_path).await?;
+
+        let working_directory = &config.streamer_sandbox.working_directory;
+        fs::create_dir_all(working_directory).await?;
+
+        command = Command::new(streamer_path);
+        command.current_dir(working_directory).env_clear();
+
+        #[cfg(target_os = "linux")]
+        // SAFETY: restrict_privileges only calls prctl which is async signal safe
+        unsafe {
+            command.pre_exec(restrict_privileges);
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        // Don't open a new console window for every streamer
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    Ok(command)
+}
+
+/// Searches the streamer at the configured path and next to the web server executable.
+/// On windows the `.exe` extension can be omitted.
+fn resolve_streamer_path(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+
+    let mut candidates = vec![path.clone()];
+    if let Ok(executable) = env::current_exe()
+        && let Some(directory) = executable.parent()
+        && let Some(file_name) = path.file_name()
+    {
+        candidates.push(directory.join(file_name));
+    }
+
+    if cfg!(windows) {
+        let with_extension = candidates
+            .iter()
+            .filter(|candidate| candidate.extension().is_none())
+            .map(|candidate| candidate.with_extension("exe"))
+            .collect::<Vec<_>>();
+
+        candidates.extend(with_extension);
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .unwrap_or(path)
+}
+
+/// Runs in the forked child before exec.
+/// The streamer applies landlock and seccomp itself once it received its config.
+#[cfg(target_os = "linux")]
+fn restrict_privileges() -> Result<(), io::Error> {
+    // SAFETY: prctl with these options doesn't access any memory
+    unsafe {
+        // The streamer and everything it might execute can never gain privileges again
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_CLEAR_ALL,
+            0,
+            0,
+            0,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{io, ptr, sync::OnceLock, time::Duration};
+
+    use common::ipc::{
+        IpcReceiver, IpcSender, ServerIpcMessage, StreamerIpcMessage, create_child_ipc,
+    };
+    use log::warn;
+    use tokio::{
+        io::split,
+        net::windows::named_pipe::ServerOptions,
+        process::{Child, Command},
+        time::timeout,
+    };
+    use uuid::Uuid;
+    use windows_sys::Win32::{
+        Foundation::HANDLE,
+        System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+            SetInformationJobObject,
+        },
+    };
+
+    const PIPE_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub async fn spawn_with_pipe(
+        mut command: Command,
+    ) -> Result<
+        (
+            Child,
+            IpcSender<ServerIpcMessage>,
+            IpcReceiver<StreamerIpcMessage>,
+        ),
+        io::Error,
+    > {
+        let pipe_name = format!(r"\\.\pipe\moonlight-web-streamer-{}", Uuid::new_v4());
+
+        let pipe = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&pipe_name)?;
+
+        command.arg("--ipc-pipe").arg(&pipe_name);
+
+        let mut child = command.spawn()?;
+        assign_to_job(&child);
+
+        match timeout(PIPE_CONNECT_TIMEOUT, pipe.connect()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                let _ = child.kill().await;
+                return Err(err);
+            }
+            Err(_) => {
+                let _ = child.kill().await;
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "streamer didn't connect to the ipc pipe",
+                ));
+            }
+        }
+
+        let (read, write) = split(pipe);
+        let (ipc_sender, ipc_receiver) =
+            create_child_ipc("Streamer", write, read, child.stderr.take()).await;
+
+        Ok((child, ipc_sender, ipc_receiver))
+    }
+
+    struct JobHandle(HANDLE);
+
+    // SAFETY: job object handles can be used from any thread
+    unsafe impl Send for JobHandle {}
+    unsafe impl Sync for JobHandle {}
+
+    static JOB: OnceLock<Option<JobHandle>> = OnceLock::new();
+
+    /// All streamers are assigned to a job object which kills them when the web server exits,
+    /// even if it crashes.
+    pub fn assign_to_job(child: &Child) {
+        let job = JOB.get_or_init(|| {
+            // SAFETY: all pointers passed are either null or valid for the duration of the call
+            unsafe {
+                let job = CreateJobObjectW(ptr::null(), ptr::null());
+                if job.is_null() {
+                    warn!(
+                        "[Stream]: failed to create job object: {}",
+                        io::Error::last_os_error()
+                    );
+                    return None;
+                }
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+
+                if SetInformationJobObject(
+                    job,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                ) == 0
+                {
+                    warn!(
+                        "[Stream]: failed to configure job object: {}",
+                        io::Error::last_os_error()
+                    );
+                    return None;
+                }
+
+                Some(JobHandle(job))
+            }
+        });
+
+        let (Some(job), Some(process)) = (job, child.raw_handle()) else {
+            return;
+        };
+
+        // SAFETY: both handles are valid while the child is alive
+        if unsafe { AssignProcessToJobObject(job.0, process as HANDLE) } == 0 {
+            warn!(
+                "[Stream]: failed to assign streamer to job object: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Decides how long the browser may cache a response based on where it was served from.
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{CACHE_CONTROL, HeaderValue},
+    middleware::Next,
+    web::Data,
+};
+use common::config::CacheControlConfig;
+use log::warn;
+
+use crate::app::{App, AppError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CachePolicy {
+    Api,
+    Image,
+    StaticFile,
+    FingerprintedFile,
+}
+
+impl CachePolicy {
+    /// The path must not contain the url path prefix
+    fn from_path(path: &str) -> Self {
+        if path.starts_with("/api/app/image") {
+            Self::Image
+        } else if path == "/api" || path.starts_with("/api/") {
+            Self::Api
+        } else if is_fingerprinted(path) {
+            Self::FingerprintedFile
+        } else {
+            Self::StaticFile
+        }
+    }
+
+    fn header_value(self, config: &CacheControlConfig) -> &str {
+        match self {
+            Self::Api => &config.api,
+            Self::Image => &config.images,
+            Self::StaticFile => &config.static_files,
+            Self::FingerprintedFile => &config.fingerprinted_files,
+        }
+    }
+}
+
+/// A file name like `stream.3f2a9c1b.js` where one part is a hash of the content.
+/// The content of such a file never changes, a new version gets a new name.
+fn is_fingerprinted(path: &str) -> bool {
+    const MIN_HASH_LENGTH: usize = 8;
+
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+
+    let mut parts = file_name.split('.').collect::<Vec<_>>();
+    // The extension and the name can't be the hash
+    if parts.len() < 3 {
+        return false;
+    }
+    parts.pop();
+
+    parts[1..].iter().any(|part| {
+        part.len() >= MIN_HASH_LENGTH && part.chars().all(|char| char.is_ascii_hexdigit())
+    })
+}
+
+/// Sets the `Cache-Control` header if the handler didn't set it
+pub async fn cache_policy_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(app) = req.app_data::<Data<App>>().cloned() else {
+        return Err(AppError::AppDestroyed.into());
+    };
+
+    let path = req
+        .path()
+        .strip_prefix(&app.config().web_server.url_path_prefix)
+        .unwrap_or(req.path());
+    let policy = CachePolicy::from_path(path);
+
+    let mut response = next.call(req).await?;
+
+    let headers = response.headers_mut();
+    if !headers.contains_key(CACHE_CONTROL) {
+        match HeaderValue::from_str(policy.header_value(&app.config().web_server.cache_control)) {
+            Ok(value) => {
+                headers.insert(CACHE_CONTROL, value);
+            }
+            Err(err) => {
+                warn!("invalid Cache-Control value for {policy:?} in the config: {err}");
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::web::cache_policy::{CachePolicy, is_fingerprinted};
+
+    #[test]
+    fn test_fingerprinted() {
+        assert!(is_fingerprinted("/stream.3f2a9c1b.js"));
+        assert!(is_fingerprinted("/resources/icon.0123456789abcdef.svg"));
+        assert!(is_fingerprinted("/resources/icon.3f2a9c1b.min.css"));
+
+        assert!(!is_fingerprinted("/stream.js"));
+        assert!(!is_fingerprinted("/3f2a9c1b.js"));
+        assert!(!is_fingerprinted("/stream.3f2a.js"));
+        assert!(!is_fingerprinted("/stream.settings.js"));
+        assert!(!is_fingerprinted("/"));
+    }
+
+    #[test]
+    fn test_policy_from_path() {
+        assert_eq!(CachePolicy::from_path("/api/hosts"), CachePolicy::Api);
+        assert_eq!(CachePolicy::from_path("/api"), CachePolicy::Api);
+        assert_eq!(CachePolicy::from_path("/api/app/image"), CachePolicy::Image);
+        assert_eq!(
+            CachePolicy::from_path("/apis.html"),
+            CachePolicy::StaticFile
+        );
+        assert_eq!(
+            CachePolicy::from_path("/index.html"),
+            CachePolicy::StaticFile
+        );
+        assert_eq!(
+            CachePolicy::from_path("/config.js"),
+            CachePolicy::StaticFile
+        );
+        assert_eq!(
+            CachePolicy::from_path("/stream.3f2a9c1b.js"),
+            CachePolicy::FingerprintedFile
+        );
+    }
+}
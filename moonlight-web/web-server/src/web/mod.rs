@@ -5,6 +5,8 @@ use log::warn;
 
 use crate::app::App;
 
+pub mod cache_policy;
+
 pub fn web_service() -> impl HttpServiceFactory {
     #[cfg(debug_assertions)]
     let files = Files::new("/", "dist").index_file("index.html");
@@ -1,6 +1,6 @@
 import "./polyfill/index.js"
 import "./styles/index.js"
-import { Api, apiGetUser, apiLogout, apiPostUser, FetchError, getApi } from "./api.js";
+import { Api, apiGetSessions, apiGetUser, apiLogout, apiPostUser, FetchError, getApi } from "./api.js";
 import { Component, ComponentEvent } from "./component/index.js";
 import { showErrorPopup } from "./component/error.js";
 import { setTouchContextMenuEnabled } from "./polyfill/ios_right_click.js";
@@ -64,6 +64,7 @@ class AdminApp implements Component {
     // User Panel
     private userPanel = document.createElement("div")
     private addUserButton = document.createElement("button")
+    private sessionsButton = document.createElement("button")
     private userSearch = document.createElement("input")
     private userList: UserList
 
@@ -131,6 +132,10 @@ class AdminApp implements Component {
         })
         this.userPanel.appendChild(this.addUserButton)
 
+        this.sessionsButton.innerText = "Active Sessions"
+        this.sessionsButton.addEventListener("click", this.showSessions.bind(this))
+        this.userPanel.appendChild(this.sessionsButton)
+
         this.userSearch.placeholder = "Search User"
         this.userSearch.type = "text"
         this.userSearch.addEventListener("input", this.onUserSearchChange.bind(this))
@@ -146,6 +151,20 @@ class AdminApp implements Component {
         await this.userList.forceFetch()
     }
 
+    private async showSessions() {
+        const sessions = await apiGetSessions(this.api)
+        if (sessions.length == 0) {
+            await showMessage("There are no active sessions")
+            return
+        }
+
+        await showMessage(sessions.map(session =>
+            `Session ${session.session_id}: host ${session.host_id}, user ${session.user_name}\n` +
+            `Quality: ${session.quality_score ?? "not rated yet"}` +
+            (session.rtsp_port != null ? `, RTSP Port: ${session.rtsp_port}` : "")
+        ).join("\n\n"))
+    }
+
     private onUserSearchChange() {
         this.userList.setFilter(this.userSearch.value)
     }
@@ -1,4 +1,4 @@
-import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest } from "./api_bindings.js";
+import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, PostAppImagesPrefetchRequest, PostAppImagesPrefetchResponse1, PostAppImagesPrefetchResponse2, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsQuery, GetHostsResponse, GetUserQuery, GetUsersQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest, PostAppLaunchRequest, PostAppLaunchResponse, PostAppQuitRequest, PostAppQuitResponse, GetAppStreamDefaultsQuery, GetAppStreamDefaultsResponse, StreamDefaults, GetHostDisplaysQuery, GetHostDisplaysResponse, HostDisplay, GetSunshineQuery, GetSunshineAppsResponse, SunshineApp, PostSunshineAppRequest, GetSunshineEncoderResponse, PatchSunshineEncoderRequest, PutSunshineCredentialsRequest, GetInputMacrosResponse, InputMacroInfo, DeleteInputMacroQuery, GetSessionsResponse, StreamSession } from "./api_bindings.js";
 import { showErrorPopup } from "./component/error.js";
 import { showMessage, showModal } from "./component/modal/index.js";
 import { ApiUserPasswordPrompt } from "./component/modal/login.js";
@@ -302,8 +302,8 @@ export async function apiGetUser(api: Api, query?: GetUserQuery): Promise<Detail
 
     return response as DetailedUser
 }
-export async function apiGetUsers(api: Api): Promise<GetUsersResponse> {
-    const response = await fetchApi(api, "/users", GET)
+export async function apiGetUsers(api: Api, query?: GetUsersQuery): Promise<GetUsersResponse> {
+    const response = await fetchApi(api, "/users", GET, { query })
 
     return response as GetUsersResponse
 }
@@ -325,8 +325,8 @@ export async function apiDeleteUser(api: Api, data: DeleteUserRequest): Promise<
     })
 }
 
-export async function apiGetHosts(api: Api): Promise<StreamedJsonResponse<GetHostsResponse, UndetailedHost>> {
-    return await fetchApi<GetHostsResponse, UndetailedHost>(api, "/hosts", GET, { response: "jsonStreaming" })
+export async function apiGetHosts(api: Api, query?: GetHostsQuery): Promise<StreamedJsonResponse<GetHostsResponse, UndetailedHost>> {
+    return await fetchApi<GetHostsResponse, UndetailedHost>(api, "/hosts", GET, { query, response: "jsonStreaming" })
 }
 export async function apiGetHost(api: Api, query: GetHostQuery): Promise<DetailedHost> {
     const response = await fetchApi(api, "/host", GET, { query })
@@ -363,6 +363,44 @@ export async function apiWakeUp(api: Api, request: PostWakeUpRequest): Promise<v
     })
 }
 
+export async function apiGetHostDisplays(api: Api, query: GetHostDisplaysQuery): Promise<Array<HostDisplay>> {
+    const response = await fetchApi(api, "/host/displays", GET, { query }) as GetHostDisplaysResponse
+
+    return response.displays
+}
+
+export async function apiPutSunshineCredentials(api: Api, request: PutSunshineCredentialsRequest): Promise<void> {
+    await fetchApi(api, "/host/sunshine/credentials", "put", {
+        json: request,
+        response: "ignore"
+    })
+}
+export async function apiGetSunshineApps(api: Api, query: GetSunshineQuery): Promise<Array<SunshineApp>> {
+    const response = await fetchApi(api, "/host/sunshine/apps", GET, { query }) as GetSunshineAppsResponse
+
+    return response.apps
+}
+export async function apiPostSunshineApp(api: Api, request: PostSunshineAppRequest): Promise<void> {
+    await fetchApi(api, "/host/sunshine/apps", "post", {
+        json: request,
+        response: "ignore"
+    })
+}
+export async function apiGetSunshineEncoder(api: Api, query: GetSunshineQuery): Promise<GetSunshineEncoderResponse> {
+    return await fetchApi(api, "/host/sunshine/encoder", GET, { query }) as GetSunshineEncoderResponse
+}
+export async function apiPatchSunshineEncoder(api: Api, request: PatchSunshineEncoderRequest): Promise<void> {
+    await fetchApi(api, "/host/sunshine/encoder", "patch", {
+        json: request,
+        response: "ignore"
+    })
+}
+export async function apiGetSunshineLogs(api: Api, query: GetSunshineQuery): Promise<string> {
+    const response = await fetchApi(api, "/host/sunshine/logs", GET, { query, response: "ignore" })
+
+    return await response.text()
+}
+
 export async function apiGetApps(api: Api, query: GetAppsQuery): Promise<Array<App>> {
     const response = await fetchApi(api, "/apps", GET, { query }) as GetAppsResponse
 
@@ -378,10 +416,54 @@ export async function apiGetAppImage(api: Api, query: GetAppImageQuery): Promise
     return await response.blob()
 }
 
+export async function apiPrefetchAppImages(api: Api, request: PostAppImagesPrefetchRequest): Promise<StreamedJsonResponse<PostAppImagesPrefetchResponse1, PostAppImagesPrefetchResponse2>> {
+    return await fetchApi(api, "/apps/images/prefetch", POST, {
+        json: request,
+        response: "jsonStreaming",
+        noTimeout: true
+    })
+}
+
+export async function apiGetAppStreamDefaults(api: Api, query: GetAppStreamDefaultsQuery): Promise<StreamDefaults | null> {
+    const response = await fetchApi(api, "/app/stream-defaults", GET, { query }) as GetAppStreamDefaultsResponse
+
+    return response.defaults
+}
+
+export async function apiAppLaunch(api: Api, request: PostAppLaunchRequest): Promise<PostAppLaunchResponse> {
+    const response = await fetchApi(api, "/app/launch", POST, {
+        json: request
+    })
+
+    return response as PostAppLaunchResponse
+}
+
+export async function apiAppQuit(api: Api, request: PostAppQuitRequest): Promise<PostAppQuitResponse> {
+    const response = await fetchApi(api, "/app/quit", POST, {
+        json: request
+    })
+
+    return response as PostAppQuitResponse
+}
+
 export async function apiHostCancel(api: Api, request: PostCancelRequest): Promise<PostCancelResponse> {
     const response = await fetchApi(api, "/host/cancel", POST, {
         json: request
     })
 
     return response as PostCancelResponse
-}
\ No newline at end of file
+}
+export async function apiGetSessions(api: Api): Promise<Array<StreamSession>> {
+    const response = await fetchApi(api, "/sessions", GET) as GetSessionsResponse
+
+    return response.sessions
+}
+
+export async function apiGetInputMacros(api: Api): Promise<Array<InputMacroInfo>> {
+    const response = await fetchApi(api, "/user/macros", GET) as GetInputMacrosResponse
+
+    return response.macros
+}
+export async function apiDeleteInputMacro(api: Api, query: DeleteInputMacroQuery): Promise<void> {
+    await fetchApi(api, "/user/macro", "delete", { query, response: "ignore" })
+}
@@ -1,10 +1,13 @@
 import { Component, ComponentEvent } from "../index.js";
-import { Api, apiGetAppImage, apiHostCancel } from "../../api.js";
+import { Api, apiAppLaunch, apiAppQuit, apiGetAppImage, apiGetAppStreamDefaults, apiGetHostDisplays, apiHostCancel } from "../../api.js";
 import { App } from "../../api_bindings.js";
 import { setContextMenu } from "../context_menu.js";
-import { showMessage } from "../modal/index.js";
+import { showMessage, showModal } from "../modal/index.js";
 import { APP_NO_IMAGE } from "../../resources/index.js";
 import { buildUrl } from "../../config_.js";
+import { defaultStreamSettings, getLocalStreamSettings } from "../settings_menu.js";
+import { getStreamerSize } from "../../stream/index.js";
+import { AppStreamSettings, AppStreamSettingsModal } from "./settings_modal.js";
 
 export type GameCache = App & { activeApp: number | null }
 
@@ -132,11 +135,21 @@ export class Game implements Component {
             this.divElement.dispatchEvent(event)
         }
     }
-    private startStream() {
+    private startStream(displayId?: number, settings?: AppStreamSettings) {
         let query = new URLSearchParams({
             hostId: this.getHostId(),
             appId: this.getAppId(),
         } as any)
+        if (displayId != undefined) {
+            query.set("displayId", displayId.toString())
+        }
+        // Only used for this stream, the local settings stay the same
+        if (settings != undefined) {
+            query.set("bitrate", settings.bitrate.toString())
+            query.set("fps", settings.fps.toString())
+            query.set("width", settings.width.toString())
+            query.set("height", settings.height.toString())
+        }
 
         if (window.matchMedia('(display-mode: standalone)').matches) {
             // If we're in a pwa: open in the current tab
@@ -147,7 +160,11 @@ export class Game implements Component {
         }
     }
 
-    private onContextMenu(event: MouseEvent) {
+    private async onContextMenu(event: MouseEvent) {
+        // The displays are loaded before the menu is shown
+        event.preventDefault()
+        event.stopPropagation()
+
         const elements = []
 
         elements.push({
@@ -155,11 +172,93 @@ export class Game implements Component {
             callback: this.showDetails.bind(this),
         })
 
+        if (this.isActive()) {
+            elements.push({
+                name: "Quit App",
+                callback: this.quit.bind(this),
+            })
+        }
+
+        // Another display or other settings can only be selected when starting a new session
+        if (this.cache.activeApp == null) {
+            elements.push({
+                name: "Stream with Settings",
+                callback: this.startStreamWithSettings.bind(this),
+            })
+            elements.push({
+                name: "Launch without Streaming",
+                callback: this.launch.bind(this),
+            })
+
+            const displays = await apiGetHostDisplays(this.api, { host_id: this.hostId })
+            for (const display of displays) {
+                if (!display.app_ids.includes(this.appId)) {
+                    continue
+                }
+
+                elements.push({
+                    name: `Stream on ${display.name}`,
+                    callback: () => this.startStream(display.display_id),
+                })
+            }
+        }
+
         setContextMenu(event, {
             elements
         })
     }
 
+    // Launches the app with the local settings, the first stream resumes it
+    private async launch() {
+        const settings = getLocalStreamSettings() ?? defaultStreamSettings()
+        const [width, height] = getStreamerSize(settings, getBrowserSize())
+
+        const response = await apiAppLaunch(this.api, {
+            host_id: this.hostId,
+            app_id: this.appId,
+            width,
+            height,
+            fps: settings.fps,
+            hdr: false,
+            play_audio_local: settings.audioRouting != "client",
+        })
+        if (!response.launched) {
+            await showMessage("The app is already running!")
+        }
+
+        const event = new ComponentEvent("ml-gamereload", this)
+        this.divElement.dispatchEvent(event)
+    }
+    private async quit() {
+        const response = await apiAppQuit(this.api, { host_id: this.hostId })
+        if (!response.success) {
+            await showMessage("Failed to close app!")
+        }
+
+        const event = new ComponentEvent("ml-gamereload", this)
+        this.divElement.dispatchEvent(event)
+    }
+
+    private async startStreamWithSettings() {
+        const settings = getLocalStreamSettings() ?? defaultStreamSettings()
+        const [width, height] = getStreamerSize(settings, getBrowserSize())
+
+        const defaults = await apiGetAppStreamDefaults(this.api, { host_id: this.hostId, app_id: this.appId })
+
+        const modal = new AppStreamSettingsModal(this.cache.title, {
+            bitrate: settings.bitrate,
+            fps: settings.fps,
+            width,
+            height,
+        }, defaults)
+        const streamSettings = await showModal(modal)
+        if (streamSettings == null) {
+            return
+        }
+
+        this.startStream(undefined, streamSettings)
+    }
+
     private async showDetails() {
         const app = this.cache
 
@@ -201,4 +300,11 @@ export class Game implements Component {
         this.mounted--
         this.updateImage()
     }
-}
\ No newline at end of file
+}
+
+function getBrowserSize(): [number, number] {
+    return [
+        Math.max(document.documentElement.clientWidth || 0, window.innerWidth || 0),
+        Math.max(document.documentElement.clientHeight || 0, window.innerHeight || 0)
+    ]
+}
@@ -0,0 +1,72 @@
+import { StreamDefaults } from "../../api_bindings.js"
+import { InputComponent } from "../input.js"
+import { FormModal } from "../modal/form.js"
+
+export type AppStreamSettings = {
+    bitrate: number
+    fps: number
+    width: number
+    height: number
+}
+
+// The settings of a single stream, pre-filled with the settings of the last successful stream of the app
+export class AppStreamSettingsModal extends FormModal<AppStreamSettings> {
+
+    private header: HTMLElement = document.createElement("h2")
+
+    private initial: AppStreamSettings
+
+    private bitrate: InputComponent
+    private fps: InputComponent
+    private width: InputComponent
+    private height: InputComponent
+
+    constructor(title: string, settings: AppStreamSettings, defaults: StreamDefaults | null) {
+        super()
+
+        this.header.innerText = defaults ? `${title} (last stream)` : title
+
+        this.initial = defaults ?? settings
+
+        this.bitrate = new InputComponent("appBitrate", "number", "Bitrate", {
+            step: "100",
+            formRequired: true
+        })
+        this.fps = new InputComponent("appFps", "number", "Fps", {
+            formRequired: true
+        })
+        this.width = new InputComponent("appVideoWidth", "number", "Video Width", {
+            formRequired: true
+        })
+        this.height = new InputComponent("appVideoHeight", "number", "Video Height", {
+            formRequired: true
+        })
+    }
+
+    reset(): void {
+        this.bitrate.setValue(this.initial.bitrate.toString())
+        this.fps.setValue(this.initial.fps.toString())
+        this.width.setValue(this.initial.width.toString())
+        this.height.setValue(this.initial.height.toString())
+    }
+    submit(): AppStreamSettings | null {
+        const bitrate = parseInt(this.bitrate.getValue())
+        const fps = parseInt(this.fps.getValue())
+        const width = parseInt(this.width.getValue())
+        const height = parseInt(this.height.getValue())
+
+        if ([bitrate, fps, width, height].some(value => isNaN(value) || value <= 0)) {
+            return null
+        }
+
+        return { bitrate, fps, width, height }
+    }
+
+    mountForm(form: HTMLFormElement): void {
+        form.appendChild(this.header)
+        this.bitrate.mount(form)
+        this.fps.mount(form)
+        this.width.mount(form)
+        this.height.mount(form)
+    }
+}
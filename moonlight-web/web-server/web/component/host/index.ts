@@ -1,10 +1,11 @@
-import { DetailedHost, DetailedUser, PatchHostRequest, UndetailedHost } from "../../api_bindings.js"
-import { Api, apiDeleteHost, apiGetHost, isDetailedHost, apiPostPair, apiWakeUp, apiGetUser, apiPatchHost } from "../../api.js"
+import { DetailedHost, DetailedUser, HostCapabilities, PatchHostRequest, UndetailedHost } from "../../api_bindings.js"
+import { Api, apiDeleteHost, apiGetHost, isDetailedHost, apiPostPair, apiWakeUp, apiGetUser, apiPatchHost, apiPrefetchAppImages } from "../../api.js"
 import { Component, ComponentEvent } from "../index.js"
 import { setContextMenu } from "../context_menu.js"
 import { showErrorPopup } from "../error.js"
-import { showMessage } from "../modal/index.js"
+import { showMessage, showPrompt } from "../modal/index.js"
 import { HOST_IMAGE, HOST_OVERLAY_LOCK, HOST_OVERLAY_NONE, HOST_OVERLAY_OFFLINE } from "../../resources/index.js"
+import { addSunshineApp, downloadSunshineLogs, editSunshineCredentials, editSunshineEncoder, showSunshineApps } from "./sunshine.js"
 
 export type HostEventListener = (event: ComponentEvent<Host>) => void
 
@@ -112,6 +113,13 @@ export class Host implements Component {
             })
         }
 
+        if (this.cache?.server_state != null && this.cache?.paired == "Paired") {
+            elements.push({
+                name: "Refresh App Images",
+                callback: this.refreshAppImages.bind(this)
+            })
+        }
+
         // Make private / global
         if (this.userCache?.role == "Admin") {
             if (this.cache?.owner == "Global") {
@@ -129,7 +137,35 @@ export class Host implements Component {
             }
         }
 
+        // The Sunshine web ui is only used for paired hosts
+        if (this.userCache?.role == "Admin" && this.cache?.server_state != null && this.cache?.paired == "Paired") {
+            elements.push({
+                name: "Sunshine Credentials",
+                callback: () => editSunshineCredentials(this.api, this.hostId)
+            })
+            elements.push({
+                name: "Sunshine Apps",
+                callback: () => showSunshineApps(this.api, this.hostId)
+            })
+            elements.push({
+                name: "Add Sunshine App",
+                callback: () => addSunshineApp(this.api, this.hostId)
+            })
+            elements.push({
+                name: "Sunshine Encoder Settings",
+                callback: () => editSunshineEncoder(this.api, this.hostId)
+            })
+            elements.push({
+                name: "Download Sunshine Log",
+                callback: () => downloadSunshineLogs(this.api, this.hostId)
+            })
+        }
+
         if (this.cache?.owner == "ThisUser" || this.userCache?.role == "Admin") {
+            elements.push({
+                name: "Edit Notes",
+                callback: this.editNotes.bind(this)
+            })
             elements.push({
                 name: "Remove Host",
                 callback: this.remove.bind(this)
@@ -165,15 +201,62 @@ export class Host implements Component {
             `External Port: ${host.external_port}\n` +
             `Version: ${host.version}\n` +
             `Gfe Version: ${host.gfe_version}\n` +
+            `Host Type: ${host.host_type}\n` +
             `Unique ID: ${host.unique_id}\n` +
             `MAC: ${host.mac}\n` +
             `Local IP: ${host.local_ip}\n` +
-            `Current Game: ${host.current_game}\n` +
+            `Current Game: ${host.current_session?.app_title ?? host.current_game}\n` +
             `Max Luma Pixels Hevc: ${host.max_luma_pixels_hevc}\n` +
-            `Server Codec Mode Support: ${host.server_codec_mode_support}`
+            `Server Codec Mode Support: ${host.server_codec_mode_support}\n` +
+            `Supports: ${formatCapabilities(host.capabilities)}` +
+            formatLabels(host.labels) +
+            (host.notes ? `\n\nNotes:\n${host.notes}` : "")
         )
     }
 
+    private async editNotes() {
+        let host = this.cache
+        if (!host || !isDetailedHost(host)) {
+            host = await apiGetHost(this.api, {
+                host_id: this.hostId,
+            })
+        }
+
+        const notes = await showPrompt("Notes", {
+            defaultValue: isDetailedHost(host) ? host.notes : "",
+        })
+        if (notes == null) {
+            return
+        }
+
+        const labelsText = await showPrompt("Labels (key=value, separated by \";\")", {
+            defaultValue: isDetailedHost(host) ? Object.entries(host.labels).map(([key, value]) => `${key}=${value}`).join("; ") : "",
+        })
+        if (labelsText == null) {
+            return
+        }
+
+        const labels: { [key: string]: string } = {}
+        for (const label of labelsText.split(";")) {
+            if (label.trim() == "") {
+                continue
+            }
+
+            const [key, ...value] = label.split("=")
+            labels[key.trim()] = value.join("=").trim()
+        }
+
+        await apiPatchHost(this.api, {
+            host_id: this.hostId,
+            change_owner: false,
+            owner: null,
+            notes,
+            labels,
+        })
+
+        await this.forceFetch()
+    }
+
     addHostRemoveListener(listener: HostEventListener, options?: EventListenerOptions) {
         this.divElement.addEventListener("ml-hostremove", listener as any, options)
     }
@@ -193,6 +276,8 @@ export class Host implements Component {
             host_id: this.hostId,
             change_owner: true,
             owner: null,
+            notes: null,
+            labels: null,
         })
 
         if (this.cache) {
@@ -206,6 +291,8 @@ export class Host implements Component {
             host_id: this.hostId,
             change_owner: true,
             owner: user.id,
+            notes: null,
+            labels: null,
         })
 
         if (this.cache) {
@@ -220,6 +307,33 @@ export class Host implements Component {
 
         this.divElement.dispatchEvent(new ComponentEvent("ml-hostremove", this))
     }
+    private async refreshAppImages() {
+        const responseStream = await apiPrefetchAppImages(this.api, {
+            host_id: this.hostId,
+            force_refresh: true,
+        })
+        const total = responseStream.response.total
+
+        const messageAbort = new AbortController()
+        showMessage(`Fetching ${total} app images from ${this.cache?.name}...`, {
+            signal: messageAbort.signal
+        })
+
+        let failed = 0
+        let progress
+        while ((progress = await responseStream.next()) != null) {
+            if (!progress.success) {
+                failed++
+            }
+        }
+        messageAbort.abort()
+
+        if (failed > 0) {
+            await showMessage(`Failed to fetch ${failed} of ${total} app images.`)
+        } else {
+            await showMessage(`Fetched ${total} app images.`)
+        }
+    }
     private async wakeUp() {
         await apiWakeUp(this.api, {
             host_id: this.getHostId()
@@ -245,8 +359,21 @@ export class Host implements Component {
             throw `failed to pair (stage 1): ${responseStream.response}`
         }
 
+        const pin = responseStream.response.Pin
+        const hostName = this.getCache()?.name
+
         const messageAbort = new AbortController()
-        showMessage(`Please pair your host ${this.getCache()?.name} with this pin:\nPin: ${responseStream.response.Pin}`, { signal: messageAbort.signal })
+        if (pin.auto_submit) {
+            showMessage(`Pairing with your host ${hostName}, the pin ${pin.pin} is entered automatically.`, {
+                signal: messageAbort.signal,
+                ariaLabel: `Pairing with your host ${hostName}, the pin ${pin.spoken_pin} is entered automatically.`,
+            })
+        } else {
+            showMessage(`Please pair your host ${hostName} with this pin:\nPin: ${pin.pin}\n\nSunshine Web UI: ${pin.otp_url}`, {
+                signal: messageAbort.signal,
+                ariaLabel: `Please pair your host ${hostName} with this pin: ${pin.spoken_pin}`,
+            })
+        }
 
         const resultResponse = await responseStream.next()
         messageAbort.abort()
@@ -308,4 +435,28 @@ export class Host implements Component {
     unmount(parent: HTMLElement): void {
         parent.removeChild(this.divElement)
     }
-}
\ No newline at end of file
+}
+
+function formatLabels(labels: { [key in string]?: string }): string {
+    const entries = Object.entries(labels)
+    if (entries.length == 0) {
+        return ""
+    }
+
+    return "\nLabels: " + entries.map(([key, value]) => `${key}=${value}`).join(", ")
+}
+
+function formatCapabilities(capabilities: HostCapabilities): string {
+    const supported = []
+    if (capabilities.hdr) {
+        supported.push("HDR")
+    }
+    if (capabilities.extended_gamepads) {
+        supported.push("Extended Gamepads")
+    }
+    if (capabilities.pen_touch) {
+        supported.push("Pen / Touch")
+    }
+
+    return supported.length > 0 ? supported.join(", ") : "None"
+}
@@ -0,0 +1,241 @@
+import { Api, apiGetSunshineApps, apiGetSunshineEncoder, apiGetSunshineLogs, apiPatchSunshineEncoder, apiPostSunshineApp, apiPutSunshineCredentials } from "../../api.js"
+import { SunshineApp, SunshineCredentials } from "../../api_bindings.js"
+import { InputComponent } from "../input.js"
+import { FormModal } from "../modal/form.js"
+import { showMessage, showModal } from "../modal/index.js"
+
+// -- Admin actions which use the Sunshine web ui of a host
+
+export async function editSunshineCredentials(api: Api, hostId: number) {
+    const output = await showModal(new SunshineCredentialsModal())
+    if (output == null) {
+        return
+    }
+
+    await apiPutSunshineCredentials(api, {
+        host_id: hostId,
+        credentials: output.credentials,
+    })
+}
+
+export async function showSunshineApps(api: Api, hostId: number) {
+    const apps = await apiGetSunshineApps(api, { host_id: hostId })
+
+    if (apps.length == 0) {
+        await showMessage("Sunshine has no apps")
+        return
+    }
+
+    await showMessage(apps.map(app => `${app.name}: ${app.cmd || "-"}`).join("\n"))
+}
+export async function addSunshineApp(api: Api, hostId: number) {
+    const app = await showModal(new SunshineAppModal())
+    if (app == null) {
+        return
+    }
+
+    await apiPostSunshineApp(api, {
+        host_id: hostId,
+        app,
+    })
+}
+
+export async function editSunshineEncoder(api: Api, hostId: number) {
+    const { settings } = await apiGetSunshineEncoder(api, { host_id: hostId })
+
+    const newSettings = await showModal(new SunshineEncoderModal(settings))
+    if (newSettings == null) {
+        return
+    }
+
+    // Removed settings are reset to the Sunshine default with an empty value
+    const changes: { [key in string]?: string } = {}
+    for (const key in settings) {
+        if (newSettings[key] == null) {
+            changes[key] = ""
+        }
+    }
+    for (const [key, value] of Object.entries(newSettings)) {
+        if (settings[key] != value) {
+            changes[key] = value
+        }
+    }
+
+    if (Object.keys(changes).length == 0) {
+        return
+    }
+
+    await apiPatchSunshineEncoder(api, {
+        host_id: hostId,
+        settings: changes,
+    })
+}
+
+export async function downloadSunshineLogs(api: Api, hostId: number) {
+    const logs = await apiGetSunshineLogs(api, { host_id: hostId })
+
+    const url = URL.createObjectURL(new Blob([logs], { type: "text/plain" }))
+
+    const link = document.createElement("a")
+    link.href = url
+    link.download = `sunshine-${hostId}.log`
+    link.click()
+
+    // The download has to start before the url is revoked
+    window.setTimeout(() => URL.revokeObjectURL(url), 1000)
+}
+
+// Null credentials remove the stored ones
+type SunshineCredentialsOutput = {
+    credentials: SunshineCredentials | null
+}
+
+class SunshineCredentialsModal extends FormModal<SunshineCredentialsOutput> {
+
+    private header: HTMLElement = document.createElement("h2")
+    private hint: HTMLElement = document.createElement("p")
+
+    private username: InputComponent
+    private password: InputComponent
+    private webUiPort: InputComponent
+
+    constructor() {
+        super()
+
+        this.header.innerText = "Sunshine Credentials"
+        this.hint.innerText = "Leave the username empty to remove the stored credentials."
+
+        this.username = new InputComponent("sunshineUsername", "text", "Username")
+        this.password = new InputComponent("sunshinePassword", "password", "Password")
+        this.webUiPort = new InputComponent("sunshineWebUiPort", "text", "Web UI Port", {
+            inputMode: "numeric",
+            placeholer: "Http Port + 1",
+        })
+    }
+
+    reset(): void {
+        this.username.reset()
+        this.password.reset()
+        this.webUiPort.reset()
+    }
+    submit(): SunshineCredentialsOutput | null {
+        const username = this.username.getValue()
+        if (username == "") {
+            return { credentials: null }
+        }
+
+        const webUiPort = parseInt(this.webUiPort.getValue())
+
+        return {
+            credentials: {
+                username,
+                password: this.password.getValue(),
+                web_ui_port: isNaN(webUiPort) ? null : webUiPort,
+            }
+        }
+    }
+
+    mountForm(form: HTMLFormElement): void {
+        form.appendChild(this.header)
+        form.appendChild(this.hint)
+        this.username.mount(form)
+        this.password.mount(form)
+        this.webUiPort.mount(form)
+    }
+}
+
+class SunshineAppModal extends FormModal<SunshineApp> {
+
+    private header: HTMLElement = document.createElement("h2")
+
+    private name: InputComponent
+    private cmd: InputComponent
+    private workingDir: InputComponent
+    private imagePath: InputComponent
+    private detached: InputComponent
+
+    constructor() {
+        super()
+
+        this.header.innerText = "Sunshine App"
+
+        this.name = new InputComponent("sunshineAppName", "text", "Name", {
+            formRequired: true
+        })
+        this.cmd = new InputComponent("sunshineAppCmd", "text", "Command")
+        this.workingDir = new InputComponent("sunshineAppWorkingDir", "text", "Working Directory")
+        this.imagePath = new InputComponent("sunshineAppImagePath", "text", "Image Path")
+        this.detached = new InputComponent("sunshineAppDetached", "text", "Detached Commands (separated by \";\")")
+    }
+
+    reset(): void {
+        this.name.reset()
+        this.cmd.reset()
+        this.workingDir.reset()
+        this.imagePath.reset()
+        this.detached.reset()
+    }
+    submit(): SunshineApp | null {
+        return {
+            name: this.name.getValue(),
+            cmd: this.cmd.getValue(),
+            working_dir: this.workingDir.getValue(),
+            image_path: this.imagePath.getValue(),
+            detached: this.detached.getValue()
+                .split(";")
+                .map(command => command.trim())
+                .filter(command => command != ""),
+        }
+    }
+
+    mountForm(form: HTMLFormElement): void {
+        form.appendChild(this.header)
+        this.name.mount(form)
+        this.cmd.mount(form)
+        this.workingDir.mount(form)
+        this.imagePath.mount(form)
+        this.detached.mount(form)
+    }
+}
+
+class SunshineEncoderModal extends FormModal<{ [key in string]?: string }> {
+
+    private header: HTMLElement = document.createElement("h2")
+    private hint: HTMLElement = document.createElement("p")
+
+    private settingsText: string
+    private settings: InputComponent
+
+    constructor(settings: { [key in string]?: string }) {
+        super()
+
+        this.header.innerText = "Sunshine Encoder Settings"
+        this.hint.innerText = "Only the changed settings are listed, removing one resets it to the Sunshine default."
+
+        this.settingsText = Object.entries(settings).map(([key, value]) => `${key}=${value}`).join("; ")
+        this.settings = new InputComponent("sunshineEncoderSettings", "text", "Settings (key=value, separated by \";\")")
+    }
+
+    reset(): void {
+        this.settings.setValue(this.settingsText)
+    }
+    submit(): { [key in string]?: string } | null {
+        const settings: { [key in string]?: string } = {}
+        for (const setting of this.settings.getValue().split(";")) {
+            if (setting.trim() == "") {
+                continue
+            }
+
+            const [key, ...value] = setting.split("=")
+            settings[key.trim()] = value.join("=").trim()
+        }
+
+        return settings
+    }
+
+    mountForm(form: HTMLFormElement): void {
+        form.appendChild(this.header)
+        form.appendChild(this.hint)
+        this.settings.mount(form)
+    }
+}
@@ -108,6 +108,8 @@ class PromptModal extends FormModal<string> {
 
 type MessageInit = {
     signal?: AbortSignal
+    // Read by screen readers instead of the message
+    ariaLabel?: string
 }
 
 export async function showMessage(message: string, init?: MessageInit) {
@@ -124,6 +126,9 @@ class MessageModal implements Component, Modal<void> {
 
     constructor(message: string, init?: MessageInit) {
         this.textElement.innerText = message
+        if (init?.ariaLabel) {
+            this.textElement.setAttribute("aria-label", init.ariaLabel)
+        }
 
         this.okButton.innerText = "Ok"
 
@@ -16,9 +16,10 @@ export type StreamSettings = {
         height: number
     },
     fps: number
+    videoRotation: VideoRotation
     videoCodec: StreamCodec,
     canvasRenderer: boolean
-    playAudioLocal: boolean
+    audioRouting: StreamAudioRouting
     audioSampleQueueSize: number
     mouseScrollMode: MouseScrollMode
     controllerConfig: ControllerConfig
@@ -29,6 +30,9 @@ export type StreamSettings = {
 
 export type StreamCodec = "h264" | "auto" | "h265" | "av1"
 export type TransportType = "auto" | "webrtc" | "websocket"
+export type StreamAudioRouting = "client" | "host" | "both"
+// Clockwise in degrees
+export type VideoRotation = 0 | 90 | 180 | 270
 
 // TODO: rename this into a more generalized settings, this not only affects streaming
 export function defaultStreamSettings(): StreamSettings {
@@ -43,9 +47,10 @@ export function defaultStreamSettings(): StreamSettings {
             width: 1920,
             height: 1080,
         },
+        videoRotation: 0,
         videoCodec: "h264",
         canvasRenderer: false,
-        playAudioLocal: false,
+        audioRouting: "client",
         audioSampleQueueSize: 20,
         mouseScrollMode: "highres",
         controllerConfig: {
@@ -69,6 +74,12 @@ export function getLocalStreamSettings(): StreamSettings | null {
 
         const settingsLoaded = JSON.parse(settingsLoadedJson)
 
+        // Play Audio Local was replaced by the audio routing, the browser also played the audio
+        if (settingsLoaded.playAudioLocal === true && settingsLoaded.audioRouting === undefined) {
+            settingsLoaded.audioRouting = "both"
+        }
+        delete settingsLoaded.playAudioLocal
+
         settings = defaultStreamSettings()
         Object.assign(settings, settingsLoaded)
     } catch (e) {
@@ -100,10 +111,12 @@ export class StreamSettingsComponent implements Component {
     private videoSizeWidth: InputComponent
     private videoSizeHeight: InputComponent
 
+    private videoRotation: SelectComponent
+
     private videoSampleQueueSize: InputComponent
 
     private audioHeader: HTMLHeadingElement = document.createElement("h2")
-    private playAudioLocal: InputComponent
+    private audioRouting: SelectComponent
     private audioSampleQueueSize: InputComponent
 
     private mouseHeader: HTMLHeadingElement = document.createElement("h2")
@@ -211,6 +224,22 @@ export class StreamSettingsComponent implements Component {
         this.videoSizeHeight.addChangeListener(this.onSettingsChange.bind(this))
         this.videoSizeHeight.mount(this.divElement)
 
+        // Video Rotation
+        this.videoRotation = new SelectComponent("videoRotation",
+            [
+                { value: "0", name: "None" },
+                { value: "90", name: "90° (Portrait)" },
+                { value: "180", name: "180°" },
+                { value: "270", name: "270° (Portrait)" }
+            ],
+            {
+                displayName: "Video Rotation",
+                preSelectedOption: (settings?.videoRotation ?? defaultSettings.videoRotation).toString()
+            }
+        )
+        this.videoRotation.addChangeListener(this.onSettingsChange.bind(this))
+        this.videoRotation.mount(this.divElement)
+
         // Video Sample Queue Size
         this.videoSampleQueueSize = new InputComponent("videoFrameQueueSize", "number", "Video Frame Queue Size", {
             defaultValue: defaultSettings.videoFrameQueueSize.toString(),
@@ -244,11 +273,19 @@ export class StreamSettingsComponent implements Component {
         this.audioHeader.innerText = "Audio"
         this.divElement.appendChild(this.audioHeader)
 
-        this.playAudioLocal = new InputComponent("playAudioLocal", "checkbox", "Play Audio Local", {
-            checked: settings?.playAudioLocal
-        })
-        this.playAudioLocal.addChangeListener(this.onSettingsChange.bind(this))
-        this.playAudioLocal.mount(this.divElement)
+        this.audioRouting = new SelectComponent("audioRouting",
+            [
+                { value: "client", name: "Browser" },
+                { value: "host", name: "Host" },
+                { value: "both", name: "Browser and Host" }
+            ],
+            {
+                displayName: "Play Audio On",
+                preSelectedOption: settings?.audioRouting || defaultSettings.audioRouting
+            }
+        )
+        this.audioRouting.addChangeListener(this.onSettingsChange.bind(this))
+        this.audioRouting.mount(this.divElement)
 
         // Audio Sample Queue Size
         this.audioSampleQueueSize = new InputComponent("audioSampleQueueSize", "number", "Audio Sample Queue Size", {
@@ -379,11 +416,12 @@ export class StreamSettingsComponent implements Component {
             width: parseInt(this.videoSizeWidth.getValue()),
             height: parseInt(this.videoSizeHeight.getValue())
         }
+        settings.videoRotation = parseInt(this.videoRotation.getValue()) as VideoRotation
         settings.videoFrameQueueSize = parseInt(this.videoSampleQueueSize.getValue())
         settings.videoCodec = this.videoCodec.getValue() as any
         settings.canvasRenderer = this.canvasRenderer.isChecked()
 
-        settings.playAudioLocal = this.playAudioLocal.isChecked()
+        settings.audioRouting = this.audioRouting.getValue() as any
         settings.audioSampleQueueSize = parseInt(this.audioSampleQueueSize.getValue())
 
         settings.mouseScrollMode = this.mouseScrollMode.getValue() as any
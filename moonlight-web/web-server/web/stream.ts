@@ -1,14 +1,14 @@
 import "./polyfill/index.js"
-import { Api, getApi } from "./api.js";
+import { Api, apiDeleteInputMacro, apiGetInputMacros, getApi } from "./api.js";
 import { Component } from "./component/index.js";
 import { showErrorPopup } from "./component/error.js";
 import { InfoEvent, Stream } from "./stream/index.js"
-import { getModalBackground, Modal, showMessage, showModal } from "./component/modal/index.js";
+import { getModalBackground, Modal, showMessage, showModal, showPrompt } from "./component/modal/index.js";
 import { getSidebarRoot, setSidebar, setSidebarExtended, setSidebarStyle, Sidebar } from "./component/sidebar/index.js";
 import { defaultStreamInputConfig, MouseMode, ScreenKeyboardSetVisibleEvent, StreamInputConfig } from "./stream/input.js";
 import { defaultStreamSettings, getLocalStreamSettings, StreamSettings } from "./component/settings_menu.js";
 import { SelectComponent } from "./component/input.js";
-import { LogMessageType, StreamCapabilities, StreamKeys } from "./api_bindings.js";
+import { InputMacroInfo, LogMessageType, StreamCapabilities, StreamKeys } from "./api_bindings.js";
 import { ScreenKeyboard, TextEvent } from "./screen_keyboard.js";
 import { FormModal } from "./component/modal/form.js";
 import { streamStatsToText } from "./stream/stats.js";
@@ -36,6 +36,26 @@ async function startApp() {
     const hostId = Number.parseInt(hostIdStr)
     const appId = Number.parseInt(appIdStr)
 
+    const displayIdStr = queryParams.get("displayId")
+    const displayId = displayIdStr == null ? null : Number.parseInt(displayIdStr)
+
+    // The app can be started with other settings than the local ones
+    const settings = getLocalStreamSettings() ?? defaultStreamSettings()
+    const bitrate = Number.parseInt(queryParams.get("bitrate") ?? "")
+    if (!isNaN(bitrate)) {
+        settings.bitrate = bitrate
+    }
+    const fps = Number.parseInt(queryParams.get("fps") ?? "")
+    if (!isNaN(fps)) {
+        settings.fps = fps
+    }
+    const width = Number.parseInt(queryParams.get("width") ?? "")
+    const height = Number.parseInt(queryParams.get("height") ?? "")
+    if (!isNaN(width) && !isNaN(height)) {
+        settings.videoSize = "custom"
+        settings.videoSizeCustom = { width, height }
+    }
+
     // event propagation on overlays
     const sidebarRoot = getSidebarRoot()
     if (sidebarRoot) {
@@ -48,7 +68,7 @@ async function startApp() {
     }
 
     // Start and Mount App
-    const app = new ViewerApp(api, hostId, appId)
+    const app = new ViewerApp(api, hostId, appId, displayId, settings)
     app.mount(rootElement)
 }
 
@@ -80,7 +100,7 @@ class ViewerApp implements Component {
     private toggleFullscreenWithKeybind: boolean
     private hasShownFullscreenEscapeWarning = false
 
-    constructor(api: Api, hostId: number, appId: number) {
+    constructor(api: Api, hostId: number, appId: number, displayId: number | null, settings: StreamSettings) {
         this.api = api
 
         // Configure sidebar
@@ -106,14 +126,12 @@ class ViewerApp implements Component {
         this.div.appendChild(this.statsDiv)
 
         // Configure stream
-        const settings = getLocalStreamSettings() ?? defaultStreamSettings()
-
         let browserWidth = Math.max(document.documentElement.clientWidth || 0, window.innerWidth || 0)
         let browserHeight = Math.max(document.documentElement.clientHeight || 0, window.innerHeight || 0)
 
         this.previousMouseMode = this.inputConfig.mouseMode
         this.toggleFullscreenWithKeybind = settings.toggleFullscreenWithKeybind
-        this.startStream(hostId, appId, settings, [browserWidth, browserHeight])
+        this.startStream(hostId, appId, displayId, settings, [browserWidth, browserHeight])
 
         this.settings = settings
 
@@ -158,18 +176,18 @@ class ViewerApp implements Component {
         element.addEventListener("touchmove", this.onTouchMove.bind(this), { passive: false })
     }
 
-    private async startStream(hostId: number, appId: number, settings: StreamSettings, browserSize: [number, number]) {
+    private async startStream(hostId: number, appId: number, displayId: number | null, settings: StreamSettings, browserSize: [number, number]) {
         setSidebarStyle({
             edge: settings.sidebarEdge,
         })
 
-        this.stream = new Stream(this.api, hostId, appId, settings, browserSize)
+        this.stream = new Stream(this.api, hostId, appId, displayId, settings, browserSize)
 
         // Add app info listener
         this.stream.addInfoListener(this.onInfo.bind(this))
 
         // Create connection info modal
-        const connectionInfo = new ConnectionInfoModal()
+        const connectionInfo = new ConnectionInfoModal(() => this.stream?.takeover())
         this.stream.addInfoListener(connectionInfo.onInfo.bind(connectionInfo))
         showModal(connectionInfo)
 
@@ -512,6 +530,9 @@ class ViewerApp implements Component {
     getStream(): Stream | null {
         return this.stream
     }
+    getApi(): Api {
+        return this.api
+    }
 }
 
 class ConnectionInfoModal implements Modal<void> {
@@ -527,12 +548,24 @@ class ConnectionInfoModal implements Modal<void> {
     private debugDetail = "" // We store this seperate because line breaks don't work when the element is not mounted on the dom
     private debugDetailDisplay = document.createElement("div")
 
-    constructor() {
+    private takeoverButton = document.createElement("button")
+
+    constructor(onTakeover: () => void) {
         this.root.classList.add("modal-video-connect")
 
         this.text.innerText = "Connecting"
         this.root.appendChild(this.text)
 
+        this.takeoverButton.innerText = "Stop Current Stream and Connect"
+        this.takeoverButton.addEventListener("click", () => {
+            this.root.removeChild(this.takeoverButton)
+
+            this.text.innerText = "Connecting"
+            this.textTy = null
+
+            onTakeover()
+        })
+
         this.debugDetailButton.innerText = "Show Logs"
         this.debugDetailButton.addEventListener("click", this.onDebugDetailClick.bind(this))
         this.root.appendChild(this.debugDetailButton)
@@ -588,6 +621,10 @@ class ConnectionInfoModal implements Modal<void> {
             } else if (data.additional?.type == "recover") {
                 showModal(null)
             }
+        } else if (data.type == "hostBusy") {
+            if (data.canTakeover && !this.root.contains(this.takeoverButton)) {
+                this.root.insertBefore(this.takeoverButton, this.debugDetailButton)
+            }
         } else if (data.type == "serverMessage") {
             const text = `Server: ${data.message}`
             this.text.innerText = text
@@ -625,9 +662,14 @@ class ViewerSidebar implements Component, Sidebar {
     private fullscreenButton = document.createElement("button")
 
     private statsButton = document.createElement("button")
+    private latencyTestButton = document.createElement("button")
+
+    private recordMacroButton = document.createElement("button")
+    private macrosButton = document.createElement("button")
 
     private mouseMode: SelectComponent
     private touchMode: SelectComponent
+    private audioRouting: SelectComponent
 
     constructor(app: ViewerApp) {
         this.app = app
@@ -694,6 +736,25 @@ class ViewerSidebar implements Component, Sidebar {
         })
         this.buttonDiv.appendChild(this.statsButton)
 
+        // Latency Test
+        this.latencyTestButton.innerText = "Latency Test"
+        this.latencyTestButton.addEventListener("click", () => {
+            const stats = this.app.getStream()?.getStats()
+            if (stats) {
+                stats.toggleLatencyTest()
+            }
+        })
+        this.buttonDiv.appendChild(this.latencyTestButton)
+
+        // Input Macros
+        this.recordMacroButton.innerText = "Record Macro"
+        this.recordMacroButton.addEventListener("click", this.onRecordMacro.bind(this))
+        this.buttonDiv.appendChild(this.recordMacroButton)
+
+        this.macrosButton.innerText = "Macros"
+        this.macrosButton.addEventListener("click", this.onMacros.bind(this))
+        this.buttonDiv.appendChild(this.macrosButton)
+
         // Select Mouse Mode
         this.mouseMode = new SelectComponent("mouseMode", [
             { value: "relative", name: "Relative" },
@@ -717,6 +778,19 @@ class ViewerSidebar implements Component, Sidebar {
         })
         this.touchMode.addChangeListener(this.onTouchModeChange.bind(this))
         this.touchMode.mount(this.div)
+
+        // Select Audio Routing
+        const settings = getLocalStreamSettings() ?? defaultStreamSettings()
+        this.audioRouting = new SelectComponent("audioRouting", [
+            { value: "client", name: "Browser" },
+            { value: "host", name: "Host" },
+            { value: "both", name: "Browser and Host" }
+        ], {
+            displayName: "Play Audio On",
+            preSelectedOption: settings.audioRouting
+        })
+        this.audioRouting.addChangeListener(this.onAudioRoutingChange.bind(this))
+        this.audioRouting.mount(this.div)
     }
 
     onCapabilitiesChange(capabilities: StreamCapabilities) {
@@ -738,6 +812,48 @@ class ViewerSidebar implements Component, Sidebar {
         this.app.getStream()?.getInput().onKeyUp(event)
     }
 
+    // -- Input Macros
+    private async onRecordMacro() {
+        const macros = this.app.getStream()?.getInputMacros()
+        if (!macros) {
+            return
+        }
+
+        if (!macros.isRecording()) {
+            setSidebarExtended(false)
+
+            macros.startRecording()
+            this.recordMacroButton.innerText = "Stop Recording"
+            return
+        }
+
+        this.recordMacroButton.innerText = "Record Macro"
+
+        // No name discards the recording
+        const name = await showPrompt("Macro Name")
+        macros.stopRecording(name ?? "")
+    }
+    private async onMacros() {
+        const macros = await apiGetInputMacros(this.app.getApi())
+        if (macros.length == 0) {
+            await showMessage("There are no recorded macros")
+            return
+        }
+
+        const action = await showModal(new InputMacroModal(macros))
+        if (action == null) {
+            return
+        }
+
+        if (action.type == "run") {
+            setSidebarExtended(false)
+
+            this.app.getStream()?.getInputMacros().run(action.id)
+        } else {
+            await apiDeleteInputMacro(this.app.getApi(), { id: action.id })
+        }
+    }
+
     // -- Mouse Mode
     private onMouseModeChange() {
         const config = this.app.getInputConfig()
@@ -752,6 +868,11 @@ class ViewerSidebar implements Component, Sidebar {
         this.app.setInputConfig(config)
     }
 
+    // -- Audio Routing
+    private onAudioRoutingChange() {
+        this.app.getStream()?.setAudioRouting(this.audioRouting.getValue() as any)
+    }
+
     extended(): void {
 
     }
@@ -817,6 +938,54 @@ class SendKeycodeModal extends FormModal<number> {
     }
 }
 
+type InputMacroAction = { type: "run" | "delete", id: number }
+
+class InputMacroModal extends FormModal<InputMacroAction> {
+
+    private macro: SelectComponent
+    private action: SelectComponent
+
+    constructor(macros: Array<InputMacroInfo>) {
+        super()
+
+        this.macro = new SelectComponent("inputMacro", macros.map(macro => ({
+            value: macro.id.toString(),
+            name: `${macro.name} (${(macro.duration_ms / 1000).toFixed(1)}s)`
+        })), {
+            displayName: "Macro",
+            preSelectedOption: macros[0]?.id.toString()
+        })
+
+        this.action = new SelectComponent("inputMacroAction", [
+            { value: "run", name: "Run" },
+            { value: "delete", name: "Delete" },
+        ], {
+            displayName: "Action",
+            preSelectedOption: "run"
+        })
+    }
+
+    mountForm(form: HTMLFormElement): void {
+        this.macro.mount(form)
+        this.action.mount(form)
+    }
+
+    reset(): void {
+        this.macro.reset()
+        this.action.reset()
+    }
+
+    submit(): InputMacroAction | null {
+        const id = this.macro.getValue()
+        const type = this.action.getValue()
+        if (id == null || type == null) {
+            return null
+        }
+
+        return { type: type as InputMacroAction["type"], id: parseInt(id) }
+    }
+}
+
 // Stop propagation so the stream doesn't get it
 function stopPropagationOn(element: HTMLElement) {
     element.addEventListener("keydown", onStopPropagation)
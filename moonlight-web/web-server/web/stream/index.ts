@@ -1,8 +1,8 @@
 import { Api } from "../api.js"
-import { App, ConnectionStatus, StreamCapabilities, StreamClientMessage, StreamServerMessage, TransportChannelId } from "../api_bindings.js"
+import { App, ConnectionStatus, StreamAudioRouting, StreamCapabilities, StreamClientMessage, StreamServerMessage, StreamVideoRotation, TransportChannelId } from "../api_bindings.js"
 import { showErrorPopup } from "../component/error.js"
 import { Component } from "../component/index.js"
-import { StreamSettings } from "../component/settings_menu.js"
+import { StreamAudioRouting as SettingsAudioRouting, StreamSettings, VideoRotation } from "../component/settings_menu.js"
 import { AudioPlayer } from "./audio/index.js"
 import { buildAudioPipeline } from "./audio/pipeline.js"
 import { BIG_BUFFER } from "./buffer.js"
@@ -10,6 +10,7 @@ import { defaultStreamInputConfig, StreamInput } from "./input.js"
 import { Logger, LogMessageInfo } from "./log.js"
 import { gatherPipeInfo, getPipe } from "./pipeline/index.js"
 import { StreamStats } from "./stats.js"
+import { StreamInputMacros } from "./macros.js"
 import { Transport, TransportShutdown } from "./transport/index.js"
 import { WebSocketTransport } from "./transport/web_socket.js"
 import { WebRTCTransport } from "./transport/webrtc.js"
@@ -27,6 +28,7 @@ export type InfoEvent = CustomEvent<
     { type: "serverMessage", message: string } |
     { type: "connectionComplete", capabilities: StreamCapabilities } |
     { type: "connectionStatus", status: ConnectionStatus } |
+    { type: "hostBusy", currentUser: string | null, canTakeover: boolean } |
     { type: "addDebugLine", line: string, additional?: LogMessageInfo }
 >
 export type InfoEventListener = (event: InfoEvent) => void
@@ -51,6 +53,11 @@ export function getStreamerSize(settings: StreamSettings, viewerScreenSize: [num
     } else { // native
         width = viewerScreenSize[0]
         height = viewerScreenSize[1]
+
+        // The browser rotates the video of the host to fill the screen
+        if (settings.videoRotation == 90 || settings.videoRotation == 270) {
+            [width, height] = [height, width]
+        }
     }
     return [width, height]
 }
@@ -77,6 +84,10 @@ function getVideoCodecHint(settings: StreamSettings): VideoCodecSupport {
     return videoCodecHint
 }
 
+// The web server keeps the stream for a while after the web socket dropped
+const WS_RESUME_MAX_ATTEMPTS = 5
+const WS_RESUME_DELAY_MS = 1000
+
 export class Stream implements Component {
     private logger: Logger = new Logger()
 
@@ -84,6 +95,7 @@ export class Stream implements Component {
 
     private hostId: number
     private appId: number
+    private displayId: number | null
 
     private settings: StreamSettings
 
@@ -91,6 +103,8 @@ export class Stream implements Component {
     private eventTarget = new EventTarget()
 
     private ws: WebSocket
+    private wsResumeToken: string | null = null
+    private wsResumeAttempts = 0
     private iceServers: Array<RTCIceServer> | null = null
 
     private videoRenderer: VideoRenderer | null = null
@@ -98,10 +112,11 @@ export class Stream implements Component {
 
     private input: StreamInput
     private stats: StreamStats
+    private inputMacros: StreamInputMacros
 
     private streamerSize: [number, number]
 
-    constructor(api: Api, hostId: number, appId: number, settings: StreamSettings, viewerScreenSize: [number, number]) {
+    constructor(api: Api, hostId: number, appId: number, displayId: number | null, settings: StreamSettings, viewerScreenSize: [number, number]) {
         this.logger.addInfoListener((info, type) => {
             this.debugLog(info, { type: type ?? undefined })
         })
@@ -110,19 +125,15 @@ export class Stream implements Component {
 
         this.hostId = hostId
         this.appId = appId
+        this.displayId = displayId
 
         this.settings = settings
 
         this.streamerSize = getStreamerSize(settings, viewerScreenSize)
 
         // Configure web socket
-        const wsApiHost = api.host_url.replace(/^http(s)?:/, "ws$1:")
         // TODO: firstly try out WebTransport
-        this.ws = new WebSocket(`${wsApiHost}/host/stream`)
-        this.ws.addEventListener("error", this.onError.bind(this))
-        this.ws.addEventListener("open", this.onWsOpen.bind(this))
-        this.ws.addEventListener("close", this.onWsClose.bind(this))
-        this.ws.addEventListener("message", this.onRawWsMessage.bind(this))
+        this.ws = this.connectWs("")
 
         this.sendWsMessage({
             Init: {
@@ -143,6 +154,9 @@ export class Stream implements Component {
 
         // Stream Stats
         this.stats = new StreamStats()
+
+        // Input Macros
+        this.inputMacros = new StreamInputMacros(this.logger)
     }
 
     private debugLog(message: string, additional?: LogMessageInfo) {
@@ -156,9 +170,15 @@ export class Stream implements Component {
     }
 
     private async onMessage(message: StreamServerMessage) {
-        if ("DebugLog" in message) {
+        if ("ResumeToken" in message) {
+            this.wsResumeToken = message.ResumeToken.token
+        } else if ("DebugLog" in message) {
             const debugLog = message.DebugLog
 
+            if (debugLog.code == "ResumeFailed") {
+                this.wsResumeToken = null
+            }
+
             this.debugLog(debugLog.message, {
                 type: debugLog.ty ?? undefined
             })
@@ -190,7 +210,9 @@ export class Stream implements Component {
 
             this.eventTarget.dispatchEvent(event)
 
-            this.input.onStreamStart(capabilities, [width, height])
+            // Only WebRTC can tell the browser to rotate the video
+            const rotation = this.transport instanceof WebRTCTransport ? this.settings.videoRotation : 0
+            this.input.onStreamStart(capabilities, [width, height], rotation)
 
             this.stats.setVideoInfo(format ?? "Unknown", width, height, fps)
 
@@ -212,9 +234,24 @@ export class Stream implements Component {
                 })
             ])
         } else if ("ConnectionTerminated" in message) {
-            const code = message.ConnectionTerminated.error_code
+            const { error_code: code, reason, description } = message.ConnectionTerminated
+
+            this.debugLog(`${description} (${reason}, code ${code})`, { type: "fatalDescription" })
+        } else if ("HostBusy" in message) {
+            const currentUser = message.HostBusy.current_user
+            const canTakeover = message.HostBusy.can_takeover
+
+            this.debugLog(`Failed to start stream because the host is already streaming${currentUser ? ` to ${currentUser}` : ""}`, { type: "fatalDescription" })
+
+            const event: InfoEvent = new CustomEvent("stream-info", {
+                detail: { type: "hostBusy", currentUser, canTakeover }
+            })
 
-            this.debugLog(`ConnectionTerminated with code ${code}`, { type: "fatalDescription" })
+            this.eventTarget.dispatchEvent(event)
+        } else if ("SettingsRejected" in message) {
+            const { field, allowed_range: range } = message.SettingsRejected
+
+            this.debugLog(`Failed to start stream because the ${field.toLowerCase()} must be between ${range.min} and ${range.max}`, { type: "fatalDescription" })
         }
         // -- WebRTC Config
         else if ("Setup" in message) {
@@ -239,6 +276,29 @@ export class Stream implements Component {
         }
     }
 
+    takeover() {
+        this.debugLog("Stopping the current stream of the host")
+
+        this.sendWsMessage("Takeover")
+    }
+
+    getAudioRouting(): SettingsAudioRouting {
+        return this.settings.audioRouting
+    }
+    setAudioRouting(audioRouting: SettingsAudioRouting) {
+        if (this.settings.audioRouting == audioRouting) {
+            return
+        }
+        this.debugLog(`Changing audio routing to ${audioRouting}`)
+
+        this.settings.audioRouting = audioRouting
+        this.sendWsMessage({
+            SetAudioRouting: {
+                audio_routing: toStreamAudioRouting(audioRouting)
+            }
+        })
+    }
+
     async startConnection() {
         this.debugLog(`Using transport: ${this.settings.dataTransport}`)
 
@@ -269,6 +329,7 @@ export class Stream implements Component {
 
         this.input.setTransport(this.transport)
         this.stats.setTransport(this.transport)
+        this.inputMacros.setTransport(this.transport)
     }
 
     private async tryWebRTCTransport(): Promise<TransportShutdown> {
@@ -415,6 +476,7 @@ export class Stream implements Component {
             video.addTrackListener((track) => {
                 videoRenderer.setTrack(track)
             })
+            videoRenderer.setFrameDisplayedListener?.(this.stats.onFrameDisplayed.bind(this.stats))
 
             this.videoRenderer = videoRenderer
         } else if (video.type == "data") {
@@ -430,6 +492,7 @@ export class Stream implements Component {
             video.addReceiveListener((data) => {
                 videoRenderer.submitPacket(data)
             })
+            videoRenderer.setFrameDisplayedListener?.(this.stats.onFrameDisplayed.bind(this.stats))
 
             this.videoRenderer = videoRenderer
         } else {
@@ -503,10 +566,12 @@ export class Stream implements Component {
                 fps: this.settings.fps,
                 width: this.streamerSize[0],
                 height: this.streamerSize[1],
-                play_audio_local: this.settings.playAudioLocal,
+                audio_routing: toStreamAudioRouting(this.settings.audioRouting),
                 video_supported_formats: createSupportedVideoFormatsBits(videoCodecSupport),
                 video_colorspace: "Rec709",
                 video_color_range_full: false,
+                video_rotation: toStreamVideoRotation(this.settings.videoRotation),
+                display_id: this.displayId,
             }
         }
         this.debugLog(`Starting stream with info: ${JSON.stringify(message)}`)
@@ -532,15 +597,45 @@ export class Stream implements Component {
     // -- Raw Web Socket stuff
     private wsSendBuffer: Array<string> = []
 
+    private connectWs(query: string): WebSocket {
+        const wsApiHost = this.api.host_url.replace(/^http(s)?:/, "ws$1:")
+
+        const ws = new WebSocket(`${wsApiHost}/host/stream${query}`)
+        ws.addEventListener("error", this.onError.bind(this))
+        ws.addEventListener("open", this.onWsOpen.bind(this))
+        ws.addEventListener("close", this.onWsClose.bind(this))
+        ws.addEventListener("message", this.onRawWsMessage.bind(this))
+
+        return ws
+    }
     private onWsOpen() {
         this.debugLog(`Web Socket Open`)
 
+        this.wsResumeAttempts = 0
+
         for (const raw of this.wsSendBuffer.splice(0)) {
             this.ws.send(raw)
         }
     }
-    private onWsClose() {
+    private onWsClose(event: CloseEvent) {
         this.debugLog(`Web Socket Closed`)
+
+        // The web socket transport sends the stream itself, it can't continue on a new web socket
+        if (event.wasClean || this.wsResumeToken == null || this.transport instanceof WebSocketTransport) {
+            return
+        }
+        if (this.wsResumeAttempts >= WS_RESUME_MAX_ATTEMPTS) {
+            this.debugLog("Failed to reconnect the Web Socket")
+            return
+        }
+        this.wsResumeAttempts += 1
+
+        const token = this.wsResumeToken
+        this.debugLog(`Reconnecting the Web Socket (attempt ${this.wsResumeAttempts} of ${WS_RESUME_MAX_ATTEMPTS})`)
+
+        setTimeout(() => {
+            this.ws = this.connectWs(`?resume=${encodeURIComponent(token)}`)
+        }, WS_RESUME_DELAY_MS)
     }
     private onError(event: Event) {
         this.debugLog(`Web Socket or WebRtcPeer Error`)
@@ -579,6 +674,9 @@ export class Stream implements Component {
     getStats(): StreamStats {
         return this.stats
     }
+    getInputMacros(): StreamInputMacros {
+        return this.inputMacros
+    }
 
     getStreamerSize(): [number, number] {
         return this.streamerSize
@@ -599,4 +697,28 @@ function createPrettyList(list: Array<string>): string {
     text += "]"
 
     return text
-}
\ No newline at end of file
+}
+
+function toStreamAudioRouting(audioRouting: SettingsAudioRouting): StreamAudioRouting {
+    switch (audioRouting) {
+        case "client":
+            return "Client"
+        case "host":
+            return "Host"
+        case "both":
+            return "Both"
+    }
+}
+
+function toStreamVideoRotation(rotation: VideoRotation): StreamVideoRotation {
+    switch (rotation) {
+        case 0:
+            return "Rotate0"
+        case 90:
+            return "Rotate90"
+        case 180:
+            return "Rotate180"
+        case 270:
+            return "Rotate270"
+    }
+}
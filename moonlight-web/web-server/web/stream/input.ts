@@ -1,9 +1,10 @@
-import { StreamCapabilities, StreamControllerCapabilities, StreamMouseButton, TransportChannelId } from "../api_bindings.js"
+import { StreamCapabilities, StreamControllerCapabilities, StreamControllerType, StreamMouseButton, TransportChannelId } from "../api_bindings.js"
 import { ByteBuffer, I16_MAX, U16_MAX, U8_MAX } from "./buffer.js"
 import { ControllerConfig, emptyGamepadState, extractGamepadState, GamepadState, SUPPORTED_BUTTONS } from "./gamepad.js"
 import { convertToKey, convertToModifiers } from "./keyboard.js"
 import { convertToButton } from "./mouse.js"
 import { DataTransportChannel, Transport, TransportChannelIdKey, TransportChannelIdValue } from "./transport/index.js"
+import { VideoRotation } from "../component/settings_menu.js"
 
 // Smooth scrolling multiplier
 const TOUCH_HIGH_RES_SCROLL_MULTIPLIER = 10
@@ -20,6 +21,21 @@ const TOUCHES_AS_KEYBOARD_DISTANCE = 100
 
 const CONTROLLER_RUMBLE_INTERVAL_MS = 60
 
+// Chrome: "... (STANDARD GAMEPAD Vendor: 054c Product: 09cc)", Firefox: "054c-09cc-Wireless Controller"
+function detectControllerType(gamepad: Gamepad): number {
+    const id = gamepad.id.toLowerCase()
+
+    const vendor = /vendor: ([0-9a-f]{4})/.exec(id)?.[1] ?? /^([0-9a-f]{4})-[0-9a-f]{4}-/.exec(id)?.[1]
+    if (vendor == "045e" || id.includes("xbox") || id.includes("xinput")) {
+        return StreamControllerType.XBOX
+    } else if (vendor == "054c" || id.includes("dualshock") || id.includes("dualsense")) {
+        return StreamControllerType.PLAYSTATION
+    } else if (vendor == "057e" || id.includes("nintendo")) {
+        return StreamControllerType.NINTENDO
+    }
+    return StreamControllerType.UNKNOWN
+}
+
 function trySendChannel(channel: DataTransportChannel | null, buffer: ByteBuffer) {
     if (!channel) {
         return
@@ -70,6 +86,8 @@ export class StreamInput {
     private capabilities: StreamCapabilities = { touch: true }
     // Size of the streamer device
     private streamerSize: [number, number] = [0, 0]
+    // Clockwise rotation of the displayed video
+    private rotation: VideoRotation = 0
 
     private keyboard: DataTransportChannel | null = null
     private mouseReliable: DataTransportChannel | null = null
@@ -143,11 +161,12 @@ export class StreamInput {
     }
 
     // -- On Stream Start
-    onStreamStart(capabilities: StreamCapabilities, streamerSize: [number, number]) {
+    onStreamStart(capabilities: StreamCapabilities, streamerSize: [number, number], rotation: VideoRotation) {
         this.connected = true
 
         this.capabilities = capabilities
         this.streamerSize = streamerSize
+        this.rotation = rotation
         this.registerBufferedControllers()
     }
 
@@ -277,8 +296,9 @@ export class StreamInput {
         trySendChannel(this.mouseRelative, this.buffer)
     }
     sendMouseMoveClientCoordinates(movementX: number, movementY: number, rect: DOMRect) {
-        const scaledMovementX = movementX / rect.width * this.streamerSize[0];
-        const scaledMovementY = movementY / rect.height * this.streamerSize[1];
+        const [normalizedX, normalizedY] = this.unrotate(movementX / rect.width, movementY / rect.height, false)
+        const scaledMovementX = normalizedX * this.streamerSize[0];
+        const scaledMovementY = normalizedY * this.streamerSize[1];
 
         this.sendMouseMove(scaledMovementX, scaledMovementY)
     }
@@ -577,7 +597,23 @@ export class StreamInput {
             // invalid touch
             return null
         }
-        return [x, y]
+        return this.unrotate(x, y, true)
+    }
+    // Converts normalized coordinates of the rotated video into coordinates of the streamer
+    private unrotate(x: number, y: number, isPosition: boolean): [number, number] {
+        // Positions are relative to the top left corner which moves when rotating
+        const offset = isPosition ? 1 : 0
+
+        switch (this.rotation) {
+            case 90:
+                return [y, offset - x]
+            case 180:
+                return [offset - x, offset - y]
+            case 270:
+                return [offset - y, x]
+            default:
+                return [x, y]
+        }
     }
     private sendTouch(type: number, touch: Touch, rect: DOMRect) {
         this.buffer.reset()
@@ -695,7 +731,7 @@ export class StreamInput {
             }
         }
 
-        this.sendControllerAdd(this.gamepads.length - 1, SUPPORTED_BUTTONS, capabilities)
+        this.sendControllerAdd(this.gamepads.length - 1, SUPPORTED_BUTTONS, capabilities, detectControllerType(gamepad))
 
         if (gamepad.mapping != "standard") {
             console.warn(`[Gamepad]: Unable to read values of gamepad with mapping ${gamepad.mapping}`)
@@ -870,13 +906,14 @@ export class StreamInput {
     }
 
     // -- Controller Sending
-    sendControllerAdd(id: number, supportedButtons: number, capabilities: number) {
+    sendControllerAdd(id: number, supportedButtons: number, capabilities: number, type: number) {
         this.buffer.reset()
 
         this.buffer.putU8(0)
         this.buffer.putU8(id)
         this.buffer.putU32(supportedButtons)
         this.buffer.putU16(capabilities)
+        this.buffer.putU8(type)
 
         trySendChannel(this.controllers, this.buffer)
     }
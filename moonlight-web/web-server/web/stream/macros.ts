@@ -0,0 +1,104 @@
+import { GeneralClientMessage, GeneralServerMessage, TransportChannelId } from "../api_bindings.js"
+import { ByteBuffer } from "./buffer.js"
+import { Logger } from "./log.js"
+import { DataTransportChannel, Transport } from "./transport/index.js"
+
+export type MacroSavedListener = (id: number, name: string) => void
+
+/// The streamer records the input itself, so the recording also contains input which isn't sent by this class
+export class StreamInputMacros {
+
+    private logger: Logger | null = null
+
+    private generalChannel: DataTransportChannel | null = null
+    private recording: boolean = false
+
+    private savedListeners: Array<MacroSavedListener> = []
+
+    constructor(logger?: Logger) {
+        if (logger) {
+            this.logger = logger
+        }
+    }
+
+    setTransport(transport: Transport) {
+        if (this.generalChannel) {
+            this.generalChannel.removeReceiveListener(this.onRawDataListener)
+            this.generalChannel = null
+        }
+
+        const channel = transport.getChannel(TransportChannelId.GENERAL)
+        if (channel.type != "data") {
+            this.logger?.debug(`Failed initialize general transport channel because type is "${channel.type}" and not "data"`)
+            return
+        }
+        channel.addReceiveListener(this.onRawDataListener)
+        this.generalChannel = channel
+    }
+
+    isRecording(): boolean {
+        return this.recording
+    }
+    startRecording() {
+        this.recording = true
+        this.sendMessage("StartMacroRecording")
+    }
+    /// An empty name discards the recording
+    stopRecording(name: string) {
+        this.recording = false
+        this.sendMessage({ StopMacroRecording: { name } })
+    }
+
+    run(id: number) {
+        this.sendMessage({ RunMacro: { id } })
+    }
+    cancel() {
+        this.sendMessage("CancelMacro")
+    }
+
+    addSavedListener(listener: MacroSavedListener) {
+        this.savedListeners.push(listener)
+    }
+    removeSavedListener(listener: MacroSavedListener) {
+        const index = this.savedListeners.indexOf(listener)
+        if (index != -1) {
+            this.savedListeners.splice(index, 1)
+        }
+    }
+
+    private sendBuffer: ByteBuffer = new ByteBuffer(1000)
+    private sendMessage(message: GeneralClientMessage) {
+        if (!this.generalChannel) {
+            this.logger?.debug("Cannot send general message without general channel")
+            return
+        }
+
+        const text = JSON.stringify(message)
+
+        this.sendBuffer.reset()
+        this.sendBuffer.putU16(text.length)
+        this.sendBuffer.putUtf8Raw(text)
+
+        this.sendBuffer.flip()
+        this.generalChannel.send(this.sendBuffer.getRemainingBuffer().buffer)
+    }
+
+    private buffer: ByteBuffer = new ByteBuffer(1000)
+    private onRawDataListener = this.onRawData.bind(this)
+    private onRawData(data: ArrayBuffer) {
+        this.buffer.reset()
+        this.buffer.putU8Array(new Uint8Array(data))
+
+        this.buffer.flip()
+
+        const textLength = this.buffer.getU16()
+        const text = this.buffer.getUtf8Raw(textLength)
+
+        const message: GeneralServerMessage = JSON.parse(text)
+        if ("MacroSaved" in message) {
+            for (const listener of this.savedListeners) {
+                listener(message.MacroSaved.id, message.MacroSaved.name)
+            }
+        }
+    }
+}
@@ -6,12 +6,15 @@ export interface DataPipe extends Pipe {
 
 export function addPipePassthrough(pipe: Pipe, overwrite?: Array<string>) {
     const pipeAny = pipe as any
-    const passthrough = (name: string, force: boolean) => {
+    const passthrough = (name: string, force: boolean, optional?: boolean) => {
         if (name in pipeAny && !force) {
             return
         }
         pipeAny[name] = function () {
             const base = pipe.getBase() as any
+            if (optional && typeof base?.[name] != "function") {
+                return
+            }
             if (base) {
                 return base[name].apply(base, arguments)
             }
@@ -24,6 +27,7 @@ export function addPipePassthrough(pipe: Pipe, overwrite?: Array<string>) {
     passthrough("onUserInteraction", false)
     passthrough("mount", false)
     passthrough("unmount", false)
+    passthrough("setFrameDisplayedListener", false, true)
 
     if (overwrite) {
         for (const overwriteFn of overwrite) {
@@ -1,4 +1,4 @@
-import { StreamerStatsUpdate, TransportChannelId } from "../api_bindings.js"
+import { StatsClientMessage, StreamerStatsUpdate, TransportChannelId } from "../api_bindings.js"
 import { BIG_BUFFER, ByteBuffer } from "./buffer.js"
 import { Logger } from "./log.js"
 import { DataTransportChannel, Transport } from "./transport/index.js"
@@ -18,6 +18,15 @@ export type StreamStatsData = {
     minStreamerProcessingTimeMs: number | null
     maxStreamerProcessingTimeMs: number | null
     avgStreamerProcessingTimeMs: number | null
+    audioJitterBufferDelayMs: number | null
+    audioJitterBufferTargetDelayMs: number | null
+    audioJitterMs: number | null
+    audioJitterBufferUnderruns: number | null
+    audioJitterBufferDropped: number | null
+    minEstimatedLatencyMs: number | null
+    maxEstimatedLatencyMs: number | null
+    avgEstimatedLatencyMs: number | null
+    avgDisplayRoundTripMs: number | null
     transport: Record<string, string>
 }
 
@@ -38,6 +47,14 @@ streamer round trip time: ${num(statsData.streamerRttMs, "ms")} (variance: ${num
 host processing latency min/max/avg: ${num(statsData.minHostProcessingLatencyMs, "ms")} / ${num(statsData.maxHostProcessingLatencyMs, "ms")} / ${num(statsData.avgHostProcessingLatencyMs, "ms")}
 streamer processing latency min/max/avg: ${num(statsData.minStreamerProcessingTimeMs, "ms")} / ${num(statsData.maxStreamerProcessingTimeMs, "ms")} / ${num(statsData.avgStreamerProcessingTimeMs, "ms")}
 `
+    if (statsData.audioJitterBufferDelayMs != null) {
+        text += `audio jitter buffer delay/target: ${num(statsData.audioJitterBufferDelayMs, "ms")} / ${num(statsData.audioJitterBufferTargetDelayMs, "ms")} (jitter: ${num(statsData.audioJitterMs, "ms")}, underruns: ${statsData.audioJitterBufferUnderruns}, dropped: ${statsData.audioJitterBufferDropped})
+`
+    }
+    if (statsData.avgEstimatedLatencyMs != null) {
+        text += `estimated glass to glass latency min/max/avg: ${num(statsData.minEstimatedLatencyMs, "ms")} / ${num(statsData.maxEstimatedLatencyMs, "ms")} / ${num(statsData.avgEstimatedLatencyMs, "ms")} (display round trip: ${num(statsData.avgDisplayRoundTripMs, "ms")})
+`
+    }
     for (const key in statsData.transport) {
         const value = statsData.transport[key]
         let valuePretty = value
@@ -57,6 +74,7 @@ export class StreamStats {
     private logger: Logger | null = null
 
     private enabled: boolean = false
+    private latencyTest: boolean = false
     private transport: Transport | null = null
     private statsChannel: DataTransportChannel | null = null
     private updateIntervalId: number | null = null
@@ -76,6 +94,15 @@ export class StreamStats {
         minStreamerProcessingTimeMs: null,
         maxStreamerProcessingTimeMs: null,
         avgStreamerProcessingTimeMs: null,
+        audioJitterBufferDelayMs: null,
+        audioJitterBufferTargetDelayMs: null,
+        audioJitterMs: null,
+        audioJitterBufferUnderruns: null,
+        audioJitterBufferDropped: null,
+        minEstimatedLatencyMs: null,
+        maxEstimatedLatencyMs: null,
+        avgEstimatedLatencyMs: null,
+        avgDisplayRoundTripMs: null,
         transport: {}
     }
 
@@ -93,7 +120,7 @@ export class StreamStats {
     private checkEnabled() {
         if (this.enabled) {
             if (this.statsChannel) {
-                this.statsChannel.removeReceiveListener(this.onRawData.bind(this))
+                this.statsChannel.removeReceiveListener(this.onRawDataListener)
                 this.statsChannel = null
             }
 
@@ -103,7 +130,7 @@ export class StreamStats {
                     this.logger?.debug(`Failed initialize debug transport channel because type is "${channel.type}" and not "data"`)
                     return
                 }
-                channel.addReceiveListener(this.onRawData.bind(this))
+                channel.addReceiveListener(this.onRawDataListener)
                 this.statsChannel = channel
             }
             if (this.updateIntervalId == null) {
@@ -129,7 +156,54 @@ export class StreamStats {
         this.setEnabled(!this.isEnabled())
     }
 
+    /// The streamer matches the echoed frames with the time it sent them
+    setLatencyTest(enabled: boolean) {
+        if (enabled) {
+            this.setEnabled(true)
+        } else {
+            this.statsData.minEstimatedLatencyMs = null
+            this.statsData.maxEstimatedLatencyMs = null
+            this.statsData.avgEstimatedLatencyMs = null
+            this.statsData.avgDisplayRoundTripMs = null
+        }
+
+        this.latencyTest = enabled
+        this.sendMessage({ SetLatencyTest: { enabled } })
+    }
+    isLatencyTestEnabled(): boolean {
+        return this.latencyTest
+    }
+    toggleLatencyTest() {
+        this.setLatencyTest(!this.isLatencyTestEnabled())
+    }
+
+    onFrameDisplayed(timestampMicroseconds: number) {
+        if (!this.latencyTest) {
+            return
+        }
+
+        this.sendMessage({ LatencyEcho: { presentation_time_us: timestampMicroseconds } })
+    }
+
+    private sendBuffer: ByteBuffer = new ByteBuffer(1000)
+    private sendMessage(message: StatsClientMessage) {
+        if (!this.statsChannel) {
+            this.logger?.debug("Cannot send stats message without stats channel")
+            return
+        }
+
+        const text = JSON.stringify(message)
+
+        this.sendBuffer.reset()
+        this.sendBuffer.putU16(text.length)
+        this.sendBuffer.putUtf8Raw(text)
+
+        this.sendBuffer.flip()
+        this.statsChannel.send(this.sendBuffer.getRemainingBuffer().buffer)
+    }
+
     private buffer: ByteBuffer = BIG_BUFFER
+    private onRawDataListener = this.onRawData.bind(this)
     private onRawData(data: ArrayBuffer) {
         this.buffer.reset()
         this.buffer.putU8Array(new Uint8Array(data))
@@ -160,6 +234,17 @@ export class StreamStats {
             this.statsData.minStreamerProcessingTimeMs = msg.Video.min_streamer_processing_time_ms
             this.statsData.maxStreamerProcessingTimeMs = msg.Video.max_streamer_processing_time_ms
             this.statsData.avgStreamerProcessingTimeMs = msg.Video.avg_streamer_processing_time_ms
+        } else if ("AudioJitterBuffer" in msg) {
+            this.statsData.audioJitterBufferDelayMs = msg.AudioJitterBuffer.delay_ms
+            this.statsData.audioJitterBufferTargetDelayMs = msg.AudioJitterBuffer.target_delay_ms
+            this.statsData.audioJitterMs = msg.AudioJitterBuffer.jitter_ms
+            this.statsData.audioJitterBufferUnderruns = msg.AudioJitterBuffer.underruns
+            this.statsData.audioJitterBufferDropped = msg.AudioJitterBuffer.dropped
+        } else if ("Latency" in msg) {
+            this.statsData.minEstimatedLatencyMs = msg.Latency.min_estimated_latency_ms
+            this.statsData.maxEstimatedLatencyMs = msg.Latency.max_estimated_latency_ms
+            this.statsData.avgEstimatedLatencyMs = msg.Latency.avg_estimated_latency_ms
+            this.statsData.avgDisplayRoundTripMs = msg.Latency.avg_display_round_trip_ms
         }
     }
 
@@ -23,12 +23,14 @@ export type TransportAudioSetup = {
 }
 
 // TOOD: common transport channel types: e.g. reliable / unreliable, ordered usw
+// The streamer creates the same data channels, see data_channel_options in transport/webrtc of the streamer
 export type TransportChannelOption = {
     ordered: boolean
     reliable: boolean
 }
 export const TRANSPORT_CHANNEL_OPTIONS: Record<keyof typeof TransportChannelId, TransportChannelOption> = {
     GENERAL: { reliable: true, ordered: true },
+    GENERAL_UNRELIABLE: { reliable: false, ordered: false },
     STATS: { reliable: true, ordered: true },
     HOST_VIDEO: { reliable: false, ordered: true },
     HOST_AUDIO: { reliable: false, ordered: true },
@@ -54,6 +56,7 @@ export const TRANSPORT_CHANNEL_OPTIONS: Record<keyof typeof TransportChannelId,
     CONTROLLER13: { reliable: false, ordered: false },
     CONTROLLER14: { reliable: false, ordered: false },
     CONTROLLER15: { reliable: false, ordered: false },
+    FILE: { reliable: true, ordered: true },
 }
 
 // failednoconnect => a connection failed without firstly being established
@@ -288,10 +288,13 @@ export class WebRTCTransport implements Transport {
                 continue
             }
 
+            // Both peers create the channel with the channel id, so we don't need to wait for the streamer
             const id = TransportChannelId[channel]
             const dataChannel = this.peer.createDataChannel(channel.toLowerCase(), {
                 ordered: options.ordered,
-                maxRetransmits: options.reliable ? undefined : 0
+                maxRetransmits: options.reliable ? undefined : 0,
+                negotiated: true,
+                id
             })
 
             this.channels[id] = new WebRTCDataTransportChannel(channel, dataChannel)
@@ -1,6 +1,6 @@
 import { globalObject, Pipe, PipeInfo } from "../pipeline/index.js"
 import { allVideoCodecs } from "../video.js"
-import { FrameVideoRenderer, getStreamRectCorrected, VideoRenderer, VideoRendererSetup } from "./index.js"
+import { FrameDisplayedListener, FrameVideoRenderer, getStreamRectCorrected, VideoRenderer, VideoRendererSetup } from "./index.js"
 
 export abstract class BaseCanvasVideoRenderer implements VideoRenderer {
 
@@ -62,11 +62,17 @@ export class CanvasVideoRenderer extends BaseCanvasVideoRenderer implements Fram
     private animationFrameRequest: number | null = null
 
     private currentFrame: VideoFrame | null = null
+    private lastDrawnFrame: VideoFrame | null = null
+    private frameDisplayedListener: FrameDisplayedListener | null = null
 
     constructor() {
         super("canvas")
     }
 
+    setFrameDisplayedListener(listener: FrameDisplayedListener | null): void {
+        this.frameDisplayedListener = listener
+    }
+
     async setup(setup: VideoRendererSetup): Promise<void> {
         await super.setup(setup)
 
@@ -115,6 +121,11 @@ export class CanvasVideoRenderer extends BaseCanvasVideoRenderer implements Fram
             // Clear the canvas before drawing the new frame to prevent artifacts
             this.context.clearRect(0, 0, this.canvas.width, this.canvas.height)
             this.context.drawImage(frame, 0, 0, this.canvas.width, this.canvas.height)
+
+            if (frame != this.lastDrawnFrame) {
+                this.lastDrawnFrame = frame
+                this.frameDisplayedListener?.(frame.timestamp % 2 ** 32)
+            }
         }
 
         this.animationFrameRequest = requestAnimationFrame(this.onAnimationFrame.bind(this))
@@ -9,6 +9,9 @@ export type VideoRendererSetup = {
     fps: number
 }
 
+/// The timestamp is the presentation time of the frame, wrapping around at 2^32
+export type FrameDisplayedListener = (timestampMicroseconds: number) => void
+
 export interface VideoRenderer extends Component, Pipe {
     readonly implementationName: string
 
@@ -25,6 +28,9 @@ export interface VideoRenderer extends Component, Pipe {
     mount(parent: HTMLElement): void
     /// Don't work inside a worker
     unmount(parent: HTMLElement): void
+
+    /// Used by the latency test, not every renderer knows when a frame is displayed
+    setFrameDisplayedListener?(listener: FrameDisplayedListener | null): void
 }
 
 export function getStreamRectCorrected(boundingRect: DOMRect, videoSize: [number, number]): DOMRect {
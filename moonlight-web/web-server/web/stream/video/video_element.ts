@@ -1,6 +1,6 @@
 import { globalObject, Pipe, PipeInfo } from "../pipeline/index.js";
 import { emptyVideoCodecs, maybeVideoCodecs, VIDEO_DECODER_CODECS, VideoCodecSupport } from "../video.js";
-import { getStreamRectCorrected, TrackVideoRenderer, VideoRendererSetup } from "./index.js";
+import { FrameDisplayedListener, getStreamRectCorrected, TrackVideoRenderer, VideoRendererSetup } from "./index.js";
 
 function detectCodecs(): VideoCodecSupport {
     if (!("canPlayType" in HTMLVideoElement.prototype)) {
@@ -47,6 +47,9 @@ export class VideoElementRenderer implements TrackVideoRenderer {
 
     private size: [number, number] | null = null
 
+    private frameDisplayedListener: FrameDisplayedListener | null = null
+    private videoFrameCallback: number | null = null
+
     constructor() {
         this.videoElement.classList.add("video-stream")
         this.videoElement.preload = "none"
@@ -89,6 +92,32 @@ export class VideoElementRenderer implements TrackVideoRenderer {
         this.oldTrack = track
     }
 
+    setFrameDisplayedListener(listener: FrameDisplayedListener | null): void {
+        this.frameDisplayedListener = listener
+
+        if (!("requestVideoFrameCallback" in this.videoElement)) {
+            return
+        }
+
+        if (listener && this.videoFrameCallback == null) {
+            this.videoFrameCallback = this.videoElement.requestVideoFrameCallback(this.onVideoFrame.bind(this))
+        } else if (!listener && this.videoFrameCallback != null) {
+            this.videoElement.cancelVideoFrameCallback(this.videoFrameCallback)
+            this.videoFrameCallback = null
+        }
+    }
+    private onVideoFrame(_now: DOMHighResTimeStamp, metadata: VideoFrameCallbackMetadata) {
+        if (metadata.rtpTimestamp != null) {
+            // WebRTC: the rtp timestamp uses a 90kHz clock
+            this.frameDisplayedListener?.(Math.round(metadata.rtpTimestamp * 1000 / 90) % 2 ** 32)
+        } else {
+            // Generated tracks use the timestamp of the VideoFrame
+            this.frameDisplayedListener?.(Math.round(metadata.mediaTime * 1000000) % 2 ** 32)
+        }
+
+        this.videoFrameCallback = this.videoElement.requestVideoFrameCallback(this.onVideoFrame.bind(this))
+    }
+
     mount(parent: HTMLElement): void {
         parent.appendChild(this.videoElement)
     }